@@ -0,0 +1,11 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // gRPC compiles the service, which imports the message schema
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/hub.proto")?;
+    #[cfg(all(feature = "protobuf", not(feature = "grpc")))]
+    tonic_build::configure()
+        .build_client(false)
+        .build_server(false)
+        .compile_protos(&["proto/hub_messages.proto"], &["proto"])?;
+    Ok(())
+}
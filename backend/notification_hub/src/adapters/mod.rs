@@ -1,3 +1,30 @@
 pub mod notification_hub;
 
-pub use notification_hub::{serial, websocket};
+#[cfg(all(feature = "can", target_os = "linux"))]
+pub use notification_hub::can;
+#[cfg(feature = "dbus")]
+pub use notification_hub::dbus;
+#[cfg(feature = "grpc")]
+pub use notification_hub::grpc;
+#[cfg(all(feature = "i2c", target_os = "linux"))]
+pub use notification_hub::i2c;
+pub(crate) use notification_hub::lease;
+#[cfg(feature = "nats")]
+pub use notification_hub::nats;
+#[cfg(feature = "quic")]
+pub use notification_hub::quic;
+#[cfg(feature = "ros2")]
+pub use notification_hub::ros2;
+#[cfg(feature = "shm")]
+pub use notification_hub::shm;
+#[cfg(all(feature = "spi", target_os = "linux"))]
+pub use notification_hub::spi;
+#[cfg(feature = "sse")]
+pub use notification_hub::sse;
+#[cfg(feature = "zenoh")]
+pub use notification_hub::zenoh;
+#[cfg(feature = "zmq")]
+pub use notification_hub::zmq;
+pub use notification_hub::{
+    batch, lora, mavlink, memory, reconnect, sample, serial, stdio, udp, websocket, xbee,
+};
@@ -0,0 +1,113 @@
+use std::future::Future;
+use tokio::sync::mpsc;
+use tokio::time::{Duration, Instant};
+
+use crate::models::hub::HubMessage;
+
+/// Module implements optional message batching for hub nodes.
+///
+/// Messages are collected until either `max_messages` are buffered or `max_delay`
+/// has elapsed since the first message of the batch was queued. The batch is then
+/// handed to the node, which sends it as a single transport unit (WS frame, serial block...).
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BatchOptions {
+    max_messages: usize,
+    max_delay: Duration,
+}
+
+impl BatchOptions {
+    pub fn new(max_messages: usize, max_delay_millis: u64) -> Self {
+        Self {
+            max_messages: max_messages.max(1),
+            max_delay: Duration::from_millis(max_delay_millis),
+        }
+    }
+
+    pub fn max_messages(&self) -> usize {
+        self.max_messages
+    }
+
+    pub fn max_delay(&self) -> Duration {
+        self.max_delay
+    }
+}
+
+/// Spawns a batching task. Messages sent through the returned channel are grouped
+/// according to `options` and passed to `flush`.
+pub(crate) fn spawn_batcher<F, Fut>(
+    options: BatchOptions,
+    mut flush: F,
+) -> mpsc::UnboundedSender<HubMessage>
+where
+    F: FnMut(Vec<HubMessage>) -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send,
+{
+    let (sender, mut receiver) = mpsc::unbounded_channel::<HubMessage>();
+    tokio::spawn(async move {
+        while let Some(first) = receiver.recv().await {
+            let mut batch = vec![first];
+            let deadline = Instant::now() + options.max_delay;
+            while batch.len() < options.max_messages {
+                match tokio::time::timeout_at(deadline, receiver.recv()).await {
+                    Ok(Some(message)) => batch.push(message),
+                    // deadline reached or channel closed
+                    _ => break,
+                }
+            }
+            flush(batch).await;
+        }
+    });
+    sender
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(data: &str) -> HubMessage {
+        HubMessage::try_from_str("test_channel", data).unwrap()
+    }
+
+    #[test]
+    fn test_batch_options_min_messages() {
+        let options = BatchOptions::new(0, 10);
+        assert_eq!(options.max_messages(), 1);
+        assert_eq!(options.max_delay(), Duration::from_millis(10));
+    }
+
+    #[tokio::test]
+    async fn test_batch_flushed_when_full() {
+        let (flushed_tx, mut flushed_rx) = mpsc::unbounded_channel();
+        let batcher = spawn_batcher(BatchOptions::new(2, 1000), move |batch| {
+            let flushed_tx = flushed_tx.clone();
+            async move {
+                let _ = flushed_tx.send(batch);
+            }
+        });
+
+        batcher.send(message("1")).unwrap();
+        batcher.send(message("2")).unwrap();
+
+        let batch = flushed_rx.recv().await.unwrap();
+        assert_eq!(batch.len(), 2);
+        assert_eq!(batch[0].data.as_str(), "1");
+        assert_eq!(batch[1].data.as_str(), "2");
+    }
+
+    #[tokio::test]
+    async fn test_batch_flushed_after_delay() {
+        let (flushed_tx, mut flushed_rx) = mpsc::unbounded_channel();
+        let batcher = spawn_batcher(BatchOptions::new(10, 20), move |batch| {
+            let flushed_tx = flushed_tx.clone();
+            async move {
+                let _ = flushed_tx.send(batch);
+            }
+        });
+
+        batcher.send(message("1")).unwrap();
+
+        let batch = flushed_rx.recv().await.unwrap();
+        assert_eq!(batch.len(), 1);
+    }
+}
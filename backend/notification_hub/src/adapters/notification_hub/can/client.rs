@@ -0,0 +1,201 @@
+use async_trait::async_trait;
+use log::{debug, error, info, warn};
+use socketcan::tokio::CanSocket;
+use socketcan::{CanFrame, EmbeddedFrame, ExtendedId, Id, StandardId};
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::sync::{broadcast, RwLock};
+
+use super::mapping::{CanId, CanMapping};
+use crate::models::hub::{HubChannelName, HubMessage};
+use crate::ports::{NodeTasks, NotificationHub};
+
+fn can_id(id: Id) -> CanId {
+    match id {
+        Id::Standard(id) => CanId::Standard(id.as_raw() as u32),
+        Id::Extended(id) => CanId::Extended(id.as_raw()),
+    }
+}
+
+fn can_frame(id: CanId, data: &[u8]) -> Result<CanFrame, std::io::Error> {
+    let invalid =
+        |msg: &str| std::io::Error::new(std::io::ErrorKind::InvalidInput, msg.to_string());
+    let id: Id = match id {
+        CanId::Standard(raw) => u16::try_from(raw)
+            .ok()
+            .and_then(StandardId::new)
+            .ok_or_else(|| invalid("Invalid standard CAN id"))?
+            .into(),
+        CanId::Extended(raw) => ExtendedId::new(raw)
+            .ok_or_else(|| invalid("Invalid extended CAN id"))?
+            .into(),
+    };
+    CanFrame::new(id, data).ok_or_else(|| invalid("Invalid CAN frame payload"))
+}
+
+/// `CanClient` is a hub node reading and writing frames of a SocketCAN interface (e.g.
+/// `can0`), to integrate motor controllers and battery management systems speaking CAN.
+///
+/// Frames are mapped to hub channels by their CAN identifier, and their payload is converted
+/// from and to hub data as configured in the `CanMapping`. Frames with unmapped identifiers
+/// are ignored, as are messages from unmapped channels.
+///
+/// # Fields
+/// - `interface`: SocketCAN interface name.
+/// - `socket`: CAN socket, shared with the receive loop.
+/// - `mapping`: Mapping between CAN identifiers and hub channels.
+/// - `channels`: Channels received so far.
+pub struct CanClient {
+    interface: String,
+    socket: Arc<CanSocket>,
+    mapping: Arc<CanMapping>,
+    channels: Arc<RwLock<HashSet<HubChannelName>>>,
+}
+
+impl std::fmt::Debug for CanClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CanClient")
+            .field("interface", &self.interface)
+            .field("mapping", &self.mapping)
+            .finish_non_exhaustive()
+    }
+}
+
+impl CanClient {
+    /// Opens SocketCAN `interface`
+    pub fn new(interface: &str, mapping: CanMapping) -> Result<Self, std::io::Error> {
+        let socket = CanSocket::open(interface)?;
+        info!("CAN client opened interface {}", interface);
+        Ok(Self {
+            interface: interface.to_string(),
+            socket: Arc::new(socket),
+            mapping: Arc::new(mapping),
+            channels: Arc::new(RwLock::new(HashSet::new())),
+        })
+    }
+
+    pub fn interface(&self) -> &str {
+        &self.interface
+    }
+}
+
+#[async_trait]
+impl NotificationHub for CanClient {
+    /// Write message as a frame with the CAN identifier of its channel
+    async fn send(&self, data: HubMessage) -> Result<(), std::io::Error> {
+        let Some(mapping) = self.mapping.by_channel(&data.channel) else {
+            return Ok(());
+        };
+        let payload = mapping
+            .encode(&data.data)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        self.socket
+            .write_frame(can_frame(mapping.id, &payload)?)
+            .await
+    }
+
+    /// List channels received so far
+    async fn list_channels(&self) -> Result<Vec<HubChannelName>, std::io::Error> {
+        Ok(self.channels.read().await.iter().cloned().collect())
+    }
+
+    /// Start receive loop
+    async fn start(
+        &self,
+        sender: Option<broadcast::Sender<HubMessage>>,
+    ) -> Result<NodeTasks, std::io::Error> {
+        let mut tasks = NodeTasks::new();
+        if let Some(sender) = sender {
+            let socket = Arc::clone(&self.socket);
+            let mapping = Arc::clone(&self.mapping);
+            let channels = Arc::clone(&self.channels);
+            let interface = self.interface.clone();
+            let task = tokio::spawn(async move {
+                loop {
+                    let frame = match socket.read_frame().await {
+                        Ok(CanFrame::Data(frame)) => frame,
+                        // remote and error frames carry no data
+                        Ok(frame) => {
+                            debug!("CAN frame {:?} ignored", frame);
+                            continue;
+                        }
+                        Err(e) => {
+                            error!("CAN interface {} read error {:?}", interface, e);
+                            break;
+                        }
+                    };
+                    let Some(frame_mapping) = mapping.by_id(&can_id(frame.id())) else {
+                        continue;
+                    };
+                    match frame_mapping.decode(frame.data()) {
+                        Ok(data) => {
+                            let channel = frame_mapping.channel.clone();
+                            channels.write().await.insert(channel.clone());
+                            let _ = sender.send(HubMessage::new(channel, data));
+                        }
+                        Err(e) => warn!("Invalid CAN frame {:?}: {}", frame_mapping.id, e),
+                    }
+                }
+            });
+            tasks.push(task);
+        }
+        Ok(tasks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::can::{CanMappingBuilder, CanSignal};
+    use tokio::time::{timeout, Duration};
+
+    #[test]
+    fn test_frame_ids() {
+        let frame = can_frame(CanId::Standard(0x101), &[1, 2]).unwrap();
+        assert_eq!(can_id(frame.id()), CanId::Standard(0x101));
+        let frame = can_frame(CanId::Extended(0x18ff_50e5), &[]).unwrap();
+        assert_eq!(can_id(frame.id()), CanId::Extended(0x18ff_50e5));
+
+        assert!(can_frame(CanId::Standard(0x800), &[]).is_err());
+        assert!(can_frame(CanId::Standard(0x101), &[0; 9]).is_err());
+    }
+
+    // Requires a virtual CAN interface:
+    // sudo modprobe vcan && sudo ip link add dev vcan0 type vcan && sudo ip link set up vcan0
+    #[tokio::test]
+    #[ignore]
+    async fn test_vcan() {
+        let mapping = || {
+            CanMappingBuilder::new()
+                .frame(
+                    0x101,
+                    "motor/status",
+                    vec![
+                        CanSignal::new("speed", 0, 16).scaled(0.1, 0.0),
+                        CanSignal::new("current", 16, 12).signed(),
+                    ],
+                )
+                .build()
+                .unwrap()
+        };
+        let node1 = CanClient::new("vcan0", mapping()).unwrap();
+        let node2 = CanClient::new("vcan0", mapping()).unwrap();
+        let (sender, mut receiver) = broadcast::channel(10);
+        node2.start(Some(sender)).await.unwrap();
+
+        node1
+            .send(HubMessage::try_from_str("motor/status", "123.4,-200").unwrap())
+            .await
+            .unwrap();
+        node1
+            .send(HubMessage::try_from_str("unmapped", "1").unwrap())
+            .await
+            .unwrap();
+        let received = timeout(Duration::from_secs(1), receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(received.channel.as_str(), "motor/status");
+        assert_eq!(received.data.as_str(), "123.4,-200");
+    }
+}
@@ -0,0 +1,431 @@
+use std::collections::HashMap;
+
+use crate::models::hub::{HubChannelName, HubData};
+
+/// Maximum payload length in bytes of a classic CAN frame
+pub const MAX_CAN_DATA_LEN: usize = 8;
+/// Largest standard (11 bit) CAN identifier
+pub const MAX_STANDARD_ID: u32 = 0x7FF;
+/// Largest extended (29 bit) CAN identifier
+pub const MAX_EXTENDED_ID: u32 = 0x1FFF_FFFF;
+
+/// Byte order of a CAN signal, as defined in DBC files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ByteOrder {
+    /// Intel byte order. `start_bit` is the least significant bit of the signal.
+    #[default]
+    LittleEndian,
+    /// Motorola byte order. `start_bit` is the most significant bit of the signal.
+    BigEndian,
+}
+
+/// Signal packed in the payload of a CAN frame.
+///
+/// Bits are numbered as in DBC files: bit `n` is bit `n % 8` of byte `n / 8`, bit 0 being the
+/// least significant bit of the byte. The physical value of the signal is
+/// `raw * scale + offset`.
+///
+/// # Fields
+/// - `name`: Signal name, for error messages.
+/// - `start_bit`: Position of the signal in the payload (see `ByteOrder`).
+/// - `length`: Length of the signal in bits (1 to 64).
+/// - `byte_order`: Byte order of the signal.
+/// - `signed`: The raw value is a two's complement integer.
+/// - `scale`: Scale of the physical value.
+/// - `offset`: Offset of the physical value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CanSignal {
+    pub name: String,
+    pub start_bit: u16,
+    pub length: u8,
+    pub byte_order: ByteOrder,
+    pub signed: bool,
+    pub scale: f64,
+    pub offset: f64,
+}
+
+impl CanSignal {
+    /// Unsigned little endian signal, with the raw value as physical value
+    pub fn new(name: &str, start_bit: u16, length: u8) -> Self {
+        Self {
+            name: name.to_string(),
+            start_bit,
+            length,
+            byte_order: ByteOrder::LittleEndian,
+            signed: false,
+            scale: 1.0,
+            offset: 0.0,
+        }
+    }
+
+    pub fn big_endian(mut self) -> Self {
+        self.byte_order = ByteOrder::BigEndian;
+        self
+    }
+
+    pub fn signed(mut self) -> Self {
+        self.signed = true;
+        self
+    }
+
+    pub fn scaled(mut self, scale: f64, offset: f64) -> Self {
+        self.scale = scale;
+        self.offset = offset;
+        self
+    }
+
+    // Payload bit positions of the signal, from the most significant bit
+    fn bits(&self) -> Result<Vec<usize>, String> {
+        let mut bits = Vec::with_capacity(self.length as usize);
+        let mut bit = self.start_bit as usize;
+        for _ in 0..self.length {
+            if bit >= MAX_CAN_DATA_LEN * 8 {
+                return Err(format!("Signal {} doesn't fit in a CAN frame", self.name));
+            }
+            bits.push(bit);
+            bit = match self.byte_order {
+                ByteOrder::LittleEndian => bit + 1,
+                // Motorola signals continue in the most significant bit of the next byte
+                ByteOrder::BigEndian if bit % 8 == 0 => bit + 15,
+                ByteOrder::BigEndian => bit - 1,
+            };
+        }
+        if self.byte_order == ByteOrder::LittleEndian {
+            bits.reverse();
+        }
+        Ok(bits)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.length == 0 || self.length > 64 {
+            return Err(format!(
+                "Signal {} length must be between 1 and 64 bits",
+                self.name
+            ));
+        }
+        if self.scale == 0.0 || !self.scale.is_finite() || !self.offset.is_finite() {
+            return Err(format!("Signal {} has an invalid scale", self.name));
+        }
+        self.bits().map(|_| ())
+    }
+
+    /// Unpacks physical value of the signal from frame payload
+    pub fn decode(&self, data: &[u8]) -> Result<f64, String> {
+        let mut raw: u64 = 0;
+        for bit in self.bits()? {
+            let byte = data
+                .get(bit / 8)
+                .ok_or_else(|| format!("Frame too short for signal {}", self.name))?;
+            raw = (raw << 1) | ((byte >> (bit % 8)) & 1) as u64;
+        }
+        let value = if self.signed && self.length < 64 && (raw >> (self.length - 1)) & 1 == 1 {
+            // sign extension
+            (raw | (u64::MAX << self.length)) as i64 as f64
+        } else if self.signed {
+            raw as i64 as f64
+        } else {
+            raw as f64
+        };
+        Ok(value * self.scale + self.offset)
+    }
+
+    /// Packs physical value of the signal into frame payload
+    pub fn encode(&self, value: f64, data: &mut [u8]) -> Result<(), String> {
+        let raw = ((value - self.offset) / self.scale).round();
+        let (min, max) = if self.signed {
+            let half = 2f64.powi(self.length as i32 - 1);
+            (-half, half - 1.0)
+        } else {
+            (0.0, 2f64.powi(self.length as i32) - 1.0)
+        };
+        if !(min..=max).contains(&raw) {
+            return Err(format!(
+                "Value {} out of range of signal {}",
+                value, self.name
+            ));
+        }
+        let raw = if self.signed {
+            raw as i64 as u64
+        } else {
+            raw as u64
+        };
+        let bits = self.bits()?;
+        for (i, bit) in bits.iter().enumerate() {
+            let byte = data
+                .get_mut(bit / 8)
+                .ok_or_else(|| format!("Frame too short for signal {}", self.name))?;
+            let shift = bits.len() - 1 - i;
+            if (raw >> shift) & 1 == 1 {
+                *byte |= 1 << (bit % 8);
+            } else {
+                *byte &= !(1 << (bit % 8));
+            }
+        }
+        Ok(())
+    }
+
+    // Number of payload bytes needed to hold the signal
+    fn data_len(&self) -> usize {
+        self.bits()
+            .map(|bits| bits.iter().max().map_or(0, |bit| bit / 8 + 1))
+            .unwrap_or(MAX_CAN_DATA_LEN)
+    }
+}
+
+/// Identifier of a CAN frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CanId {
+    Standard(u32),
+    Extended(u32),
+}
+
+/// Mapping of the frames of a CAN identifier to a hub channel.
+///
+/// Frames without signals carry their payload as hex string (e.g. `0a1bff`). Frames with
+/// signals carry the physical values of the signals as comma separated list, in the order of
+/// the signals.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CanFrameMapping {
+    pub id: CanId,
+    pub channel: HubChannelName,
+    pub signals: Vec<CanSignal>,
+}
+
+impl CanFrameMapping {
+    /// Converts frame payload into hub data
+    pub fn decode(&self, data: &[u8]) -> Result<HubData, String> {
+        let data = if self.signals.is_empty() {
+            data.iter().map(|byte| format!("{:02x}", byte)).collect()
+        } else {
+            self.signals
+                .iter()
+                .map(|signal| signal.decode(data).map(|value| value.to_string()))
+                .collect::<Result<Vec<_>, _>>()?
+                .join(",")
+        };
+        HubData::try_from(data)
+    }
+
+    /// Converts hub data into frame payload
+    pub fn encode(&self, data: &HubData) -> Result<Vec<u8>, String> {
+        let data = data.as_str().trim();
+        if self.signals.is_empty() {
+            if !data.is_ascii() || data.len() % 2 != 0 || data.len() > MAX_CAN_DATA_LEN * 2 {
+                return Err(format!("Invalid CAN payload {}", data));
+            }
+            return (0..data.len())
+                .step_by(2)
+                .map(|i| {
+                    u8::from_str_radix(&data[i..i + 2], 16)
+                        .map_err(|e| format!("Invalid CAN payload {}: {}", data, e))
+                })
+                .collect();
+        }
+        let values: Vec<&str> = data.split(',').collect();
+        if values.len() != self.signals.len() {
+            return Err(format!(
+                "Expected {} signal values, received {}",
+                self.signals.len(),
+                values.len()
+            ));
+        }
+        let len = self
+            .signals
+            .iter()
+            .map(CanSignal::data_len)
+            .max()
+            .unwrap_or(0);
+        let mut payload = vec![0; len];
+        for (signal, value) in self.signals.iter().zip(values) {
+            let value = value
+                .trim()
+                .parse::<f64>()
+                .map_err(|e| format!("Invalid value {} of signal {}: {}", value, signal.name, e))?;
+            signal.encode(value, &mut payload)?;
+        }
+        Ok(payload)
+    }
+}
+
+/// Mapping between CAN identifiers and hub channels
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CanMapping {
+    frames: HashMap<CanId, CanFrameMapping>,
+    channels: HashMap<HubChannelName, CanId>,
+}
+
+impl CanMapping {
+    pub fn by_id(&self, id: &CanId) -> Option<&CanFrameMapping> {
+        self.frames.get(id)
+    }
+
+    pub fn by_channel(&self, channel: &HubChannelName) -> Option<&CanFrameMapping> {
+        self.channels
+            .get(channel)
+            .and_then(|id| self.frames.get(id))
+    }
+
+    pub fn channels(&self) -> Vec<HubChannelName> {
+        self.channels.keys().cloned().collect()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CanMappingBuilder {
+    frames: Vec<(CanId, String, Vec<CanSignal>)>,
+}
+
+impl CanMappingBuilder {
+    pub fn new() -> Self {
+        Self { frames: Vec::new() }
+    }
+
+    /// Maps frames with standard identifier `id` to `channel`
+    pub fn frame(&self, id: u32, channel: &str, signals: Vec<CanSignal>) -> Self {
+        let mut new = self.clone();
+        new.frames
+            .push((CanId::Standard(id), channel.to_string(), signals));
+        new
+    }
+
+    /// Maps frames with extended identifier `id` to `channel`
+    pub fn extended_frame(&self, id: u32, channel: &str, signals: Vec<CanSignal>) -> Self {
+        let mut new = self.clone();
+        new.frames
+            .push((CanId::Extended(id), channel.to_string(), signals));
+        new
+    }
+
+    pub fn build(self) -> Result<CanMapping, String> {
+        let mut mapping = CanMapping::default();
+        for (id, channel, signals) in self.frames {
+            match id {
+                CanId::Standard(raw) if raw > MAX_STANDARD_ID => {
+                    return Err(format!("Invalid standard CAN id {:#x}", raw))
+                }
+                CanId::Extended(raw) if raw > MAX_EXTENDED_ID => {
+                    return Err(format!("Invalid extended CAN id {:#x}", raw))
+                }
+                _ => {}
+            }
+            let channel = HubChannelName::try_from(channel)?;
+            for signal in &signals {
+                signal.validate()?;
+            }
+            if mapping.frames.contains_key(&id) {
+                return Err(format!("CAN id {:?} mapped more than once", id));
+            }
+            if mapping.channels.contains_key(&channel) {
+                return Err(format!(
+                    "Channel {} mapped to more than one CAN id",
+                    channel.as_str()
+                ));
+            }
+            mapping.channels.insert(channel.clone(), id);
+            mapping.frames.insert(
+                id,
+                CanFrameMapping {
+                    id,
+                    channel,
+                    signals,
+                },
+            );
+        }
+        Ok(mapping)
+    }
+}
+
+impl Default for CanMappingBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_little_endian_signals() {
+        // 16 bit unsigned at bit 0, 12 bit signed at bit 16
+        let speed = CanSignal::new("speed", 0, 16).scaled(0.1, 0.0);
+        let current = CanSignal::new("current", 16, 12).signed();
+        let data = [0xd2, 0x04, 0x38, 0x0f];
+        assert_eq!(speed.decode(&data).unwrap(), 123.4);
+        assert_eq!(current.decode(&data).unwrap(), -200.0);
+
+        let mut payload = [0; 4];
+        speed.encode(123.4, &mut payload).unwrap();
+        current.encode(-200.0, &mut payload).unwrap();
+        assert_eq!(payload, [0xd2, 0x04, 0x38, 0x0f]);
+        assert!(current.encode(4096.0, &mut payload).is_err());
+    }
+
+    #[test]
+    fn test_big_endian_signals() {
+        // 16 bit signal with its most significant bit at bit 7 of byte 0
+        let voltage = CanSignal::new("voltage", 7, 16)
+            .big_endian()
+            .scaled(0.01, 0.0);
+        let data = [0x04, 0xd2];
+        assert!((voltage.decode(&data).unwrap() - 12.34).abs() < 1e-9);
+
+        let mut payload = [0; 2];
+        voltage.encode(12.34, &mut payload).unwrap();
+        assert_eq!(payload, data);
+
+        let overflow = CanSignal::new("overflow", 63, 16).big_endian();
+        assert!(overflow.validate().is_err());
+    }
+
+    #[test]
+    fn test_frame_mapping() {
+        let mapping = CanMappingBuilder::new()
+            .frame(
+                0x101,
+                "motor/status",
+                vec![
+                    CanSignal::new("speed", 0, 16).scaled(0.1, 0.0),
+                    CanSignal::new("current", 16, 12).signed(),
+                ],
+            )
+            .extended_frame(0x18ff_50e5, "bms/raw", vec![])
+            .build()
+            .unwrap();
+
+        let motor = mapping.by_id(&CanId::Standard(0x101)).unwrap();
+        let data = motor.decode(&[0xd2, 0x04, 0x38, 0x0f]).unwrap();
+        assert_eq!(data.as_str(), "123.4,-200");
+        assert_eq!(motor.encode(&data).unwrap(), vec![0xd2, 0x04, 0x38, 0x0f]);
+
+        let channel = HubChannelName::try_from("bms/raw").unwrap();
+        let bms = mapping.by_channel(&channel).unwrap();
+        assert_eq!(bms.id, CanId::Extended(0x18ff_50e5));
+        let data = bms.decode(&[0x0a, 0x1b, 0xff]).unwrap();
+        assert_eq!(data.as_str(), "0a1bff");
+        assert_eq!(bms.encode(&data).unwrap(), vec![0x0a, 0x1b, 0xff]);
+        assert!(bms.encode(&"0a1".parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_invalid_mapping() {
+        assert!(CanMappingBuilder::new()
+            .frame(0x800, "motor", vec![])
+            .build()
+            .is_err());
+        assert!(CanMappingBuilder::new()
+            .frame(0x101, "motor", vec![])
+            .frame(0x101, "motor2", vec![])
+            .build()
+            .is_err());
+        assert!(CanMappingBuilder::new()
+            .frame(0x101, "motor", vec![])
+            .frame(0x102, "motor", vec![])
+            .build()
+            .is_err());
+        assert!(CanMappingBuilder::new()
+            .frame(0x101, "motor", vec![CanSignal::new("speed", 60, 8)])
+            .build()
+            .is_err());
+    }
+}
@@ -0,0 +1,6 @@
+/// SocketCAN transport for the notification hub, with signal unpacking of CAN frames.
+pub mod client;
+pub mod mapping;
+
+pub use client::CanClient;
+pub use mapping::{ByteOrder, CanId, CanMapping, CanMappingBuilder, CanSignal};
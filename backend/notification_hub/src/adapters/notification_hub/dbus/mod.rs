@@ -0,0 +1,4 @@
+/// D-Bus service exposing the hub to system services.
+pub mod server;
+
+pub use server::{DbusBus, DbusServer, DBUS_NAME, DBUS_PATH};
@@ -0,0 +1,336 @@
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use log::{error, info, warn};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::{broadcast, RwLock};
+use zbus::message::Header;
+use zbus::object_server::SignalContext;
+use zbus::{fdo, interface, Connection};
+
+use crate::adapters::lease::LeaseSlot;
+use crate::models::hub::{HubChannelName, HubMessage};
+use crate::ports::{NodeTasks, NotificationHub};
+
+/// Object path of the hub interface
+pub const DBUS_PATH: &str = "/org/robopilot/Hub";
+/// Default well-known name of the hub service
+pub const DBUS_NAME: &str = "org.robopilot.Hub";
+
+fn dbus_error(e: impl std::fmt::Display) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Other, e.to_string())
+}
+
+/// Message bus the `DbusServer` connects to. The system bus requires a policy allowing the
+/// server to own its name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DbusBus {
+    Session,
+    System,
+}
+
+/// # Fields
+/// - `hub_sender`: Sender of messages published by peers, set when the node is started.
+/// - `channels`: Channels published by peers so far.
+/// - `subscriptions`: Unique bus names of the peers subscribed to each channel.
+#[derive(Debug, Clone, Default)]
+struct DbusState {
+    hub_sender: Arc<RwLock<Option<broadcast::Sender<HubMessage>>>>,
+    channels: Arc<RwLock<HashSet<HubChannelName>>>,
+    subscriptions: Arc<RwLock<HashMap<HubChannelName, HashSet<String>>>>,
+}
+
+impl DbusState {
+    // Removes subscriptions of `peer`
+    async fn remove_peer(&self, peer: &str) {
+        let mut subscriptions = self.subscriptions.write().await;
+        subscriptions.retain(|_, peers| {
+            peers.remove(peer);
+            !peers.is_empty()
+        });
+    }
+}
+
+fn sender_name(header: &Header<'_>) -> fdo::Result<String> {
+    header
+        .sender()
+        .map(|sender| sender.to_string())
+        .ok_or_else(|| fdo::Error::Failed("Unknown sender".to_string()))
+}
+
+fn channel_name(channel: &str) -> fdo::Result<HubChannelName> {
+    HubChannelName::try_from(channel).map_err(fdo::Error::InvalidArgs)
+}
+
+/// `org.robopilot.Hub1` interface
+struct HubInterface {
+    state: DbusState,
+}
+
+#[interface(name = "org.robopilot.Hub1")]
+impl HubInterface {
+    /// Publishes `data` on `channel`
+    async fn publish(&self, channel: &str, data: &str) -> fdo::Result<()> {
+        let message = HubMessage::try_from_str(channel, data).map_err(fdo::Error::InvalidArgs)?;
+        self.state
+            .channels
+            .write()
+            .await
+            .insert(message.channel.clone());
+        let hub_sender = self.state.hub_sender.read().await;
+        let hub_sender = hub_sender
+            .as_ref()
+            .ok_or_else(|| fdo::Error::Failed("Hub does not accept messages".to_string()))?;
+        let _ = hub_sender.send(message);
+        Ok(())
+    }
+
+    /// Emits `Message` signals for the messages sent to the hub on `channel`
+    async fn subscribe(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        channel: &str,
+    ) -> fdo::Result<()> {
+        let channel = channel_name(channel)?;
+        let peer = sender_name(&header)?;
+        self.state
+            .subscriptions
+            .write()
+            .await
+            .entry(channel)
+            .or_default()
+            .insert(peer);
+        Ok(())
+    }
+
+    /// Stops `Message` signals of `channel` requested by the caller
+    async fn unsubscribe(
+        &self,
+        #[zbus(header)] header: Header<'_>,
+        channel: &str,
+    ) -> fdo::Result<()> {
+        let channel = channel_name(channel)?;
+        let peer = sender_name(&header)?;
+        let mut subscriptions = self.state.subscriptions.write().await;
+        if let Some(peers) = subscriptions.get_mut(&channel) {
+            peers.remove(&peer);
+            if peers.is_empty() {
+                subscriptions.remove(&channel);
+            }
+        }
+        Ok(())
+    }
+
+    /// Channels published by peers so far
+    async fn list_channels(&self) -> Vec<String> {
+        self.state
+            .channels
+            .read()
+            .await
+            .iter()
+            .map(|channel| channel.as_str().to_string())
+            .collect()
+    }
+
+    /// Message sent to the hub on a subscribed channel
+    #[zbus(signal)]
+    async fn message(
+        ctxt: &SignalContext<'_>,
+        channel: &str,
+        data: &str,
+        timestamp: f64,
+    ) -> zbus::Result<()>;
+}
+
+/// `DbusServer` is a hub node exposing the hub on D-Bus, so that system services (power
+/// management, network status...) feed events into the hub and receive commands.
+///
+/// The `org.robopilot.Hub1` interface is served at `/org/robopilot/Hub`:
+/// - `Publish(channel, data)` publishes a message to the hub.
+/// - `Subscribe(channel)` and `Unsubscribe(channel)` start and stop the `Message(channel,
+///   data, timestamp)` signal for the messages sent to the hub on the channel. Subscriptions
+///   of peers leaving the bus are dropped.
+/// - `ListChannels()` lists the channels published by peers so far.
+///
+/// # Fields
+/// - `connection`: Bus connection serving the interface.
+/// - `state`: State shared with the interface.
+/// - `running`: Leased by the node task while the node is started.
+#[derive(Debug)]
+pub struct DbusServer {
+    connection: Connection,
+    state: DbusState,
+    running: LeaseSlot<()>,
+}
+
+impl DbusServer {
+    /// Connects to `bus`, and serves the hub interface under well-known `name` (e.g.
+    /// `DBUS_NAME`)
+    pub async fn new(bus: DbusBus, name: &str) -> Result<Self, std::io::Error> {
+        let state = DbusState::default();
+        let builder = match bus {
+            DbusBus::Session => zbus::connection::Builder::session(),
+            DbusBus::System => zbus::connection::Builder::system(),
+        }
+        .map_err(dbus_error)?;
+        let connection = builder
+            .name(name.to_string())
+            .map_err(dbus_error)?
+            .serve_at(
+                DBUS_PATH,
+                HubInterface {
+                    state: state.clone(),
+                },
+            )
+            .map_err(dbus_error)?
+            .build()
+            .await
+            .map_err(dbus_error)?;
+        info!("D-Bus server serving {} at {}", name, DBUS_PATH);
+        Ok(Self {
+            connection,
+            state,
+            running: LeaseSlot::new(()),
+        })
+    }
+
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+}
+
+#[async_trait]
+impl NotificationHub for DbusServer {
+    /// Emit message signal if its channel is subscribed
+    async fn send(&self, data: HubMessage) -> Result<(), std::io::Error> {
+        if !self
+            .state
+            .subscriptions
+            .read()
+            .await
+            .contains_key(&data.channel)
+        {
+            return Ok(());
+        }
+        let ctxt = SignalContext::new(&self.connection, DBUS_PATH).map_err(dbus_error)?;
+        HubInterface::message(
+            &ctxt,
+            data.channel.as_str(),
+            data.data.as_str(),
+            data.timestamp.wall,
+        )
+        .await
+        .map_err(dbus_error)
+    }
+
+    /// List channels published by peers so far
+    async fn list_channels(&self) -> Result<Vec<HubChannelName>, std::io::Error> {
+        Ok(self.state.channels.read().await.iter().cloned().collect())
+    }
+
+    /// Accept published messages, and drop subscriptions of peers leaving the bus
+    async fn start(
+        &self,
+        sender: Option<broadcast::Sender<HubMessage>>,
+    ) -> Result<NodeTasks, std::io::Error> {
+        let running = self.running.lease("D-Bus server")?;
+        *self.state.hub_sender.write().await = sender;
+
+        let proxy = fdo::DBusProxy::new(&self.connection)
+            .await
+            .map_err(dbus_error)?;
+        let mut owner_changes = proxy
+            .receive_name_owner_changed()
+            .await
+            .map_err(dbus_error)?;
+        let state = self.state.clone();
+        let task = tokio::spawn(async move {
+            let _running = running;
+            while let Some(signal) = owner_changes.next().await {
+                match signal.args() {
+                    Ok(args) if args.new_owner().is_none() => {
+                        state.remove_peer(args.name().as_str()).await
+                    }
+                    Ok(_) => {}
+                    Err(e) => warn!("Invalid D-Bus NameOwnerChanged signal: {}", e),
+                }
+            }
+            error!("D-Bus NameOwnerChanged stream closed");
+        });
+        let mut tasks = NodeTasks::new();
+        tasks.push(task);
+        Ok(tasks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time::{timeout, Duration};
+
+    #[tokio::test]
+    async fn test_remove_peer() {
+        let state = DbusState::default();
+        let pose = HubChannelName::try_from("pose").unwrap();
+        let battery = HubChannelName::try_from("battery").unwrap();
+        {
+            let mut subscriptions = state.subscriptions.write().await;
+            subscriptions.insert(
+                pose.clone(),
+                HashSet::from([":1.1".to_string(), ":1.2".to_string()]),
+            );
+            subscriptions.insert(battery.clone(), HashSet::from([":1.1".to_string()]));
+        }
+        state.remove_peer(":1.1").await;
+        let subscriptions = state.subscriptions.read().await;
+        assert_eq!(subscriptions.len(), 1);
+        assert_eq!(subscriptions[&pose], HashSet::from([":1.2".to_string()]));
+    }
+
+    // Requires a D-Bus session bus
+    #[tokio::test]
+    #[ignore]
+    async fn test_dbus_server() {
+        let name = "org.robopilot.HubTest";
+        let server = DbusServer::new(DbusBus::Session, name).await.unwrap();
+        let (sender, mut receiver) = broadcast::channel(10);
+        server.start(Some(sender)).await.unwrap();
+
+        let client = Connection::session().await.unwrap();
+        let proxy = zbus::Proxy::new(&client, name, DBUS_PATH, "org.robopilot.Hub1")
+            .await
+            .unwrap();
+        proxy
+            .call_method("Publish", &("power/state", "on_battery"))
+            .await
+            .unwrap();
+        let message = timeout(Duration::from_secs(1), receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(message.channel.as_str(), "power/state");
+        assert_eq!(message.data.as_str(), "on_battery");
+        assert!(proxy
+            .call_method("Publish", &("Power State", "on"))
+            .await
+            .is_err());
+
+        let mut signals = proxy.receive_signal("Message").await.unwrap();
+        proxy.call_method("Subscribe", &("cmd",)).await.unwrap();
+        server
+            .send(HubMessage::try_from_str("other", "0").unwrap())
+            .await
+            .unwrap();
+        server
+            .send(HubMessage::try_from_str("cmd", "shutdown").unwrap())
+            .await
+            .unwrap();
+        let signal = timeout(Duration::from_secs(1), signals.next())
+            .await
+            .unwrap()
+            .unwrap();
+        let (channel, data, _): (String, String, f64) = signal.body().deserialize().unwrap();
+        assert_eq!(channel, "cmd");
+        assert_eq!(data, "shutdown");
+    }
+}
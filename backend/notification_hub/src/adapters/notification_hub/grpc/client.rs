@@ -0,0 +1,221 @@
+use async_trait::async_trait;
+use log::{error, info};
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::wrappers::{ReceiverStream, UnboundedReceiverStream};
+use tonic::transport::Channel;
+
+use super::proto::{self, hub_client::HubClient, ListChannelsRequest, SubscriptionRequest};
+use crate::adapters::lease::LeaseSlot;
+use crate::models::hub::{HubChannelName, HubMessage};
+use crate::ports::{NodeTasks, NotificationHub};
+
+const CHANNEL_CAPACITY: usize = 100;
+
+fn grpc_error(e: impl std::fmt::Display) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Other, e.to_string())
+}
+
+fn stopped() -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::BrokenPipe, "gRPC client stopped")
+}
+
+/// `GrpcClient` is a hub node connected to a remote `GrpcServer`, e.g. to bridge the hubs of
+/// two robots.
+///
+/// Messages sent to the node are published on a single `Publish` stream, opened on connection.
+/// Channel subscriptions are sent on the `Subscribe` stream, opened when the node is started,
+/// and the messages received on it are forwarded to the hub. A new stream is opened when the
+/// node is started again.
+///
+/// # Fields
+/// - `client`: gRPC client.
+/// - `publisher`: Messages streamed to the server by the `Publish` call.
+/// - `subscriptions`: Subscription requests streamed to the server by the `Subscribe` call.
+/// - `subscription_requests`: Receiving half of `subscriptions`, leased by the node task
+///   forwarding the requests to the `Subscribe` stream.
+#[derive(Debug)]
+pub struct GrpcClient {
+    client: HubClient<Channel>,
+    publisher: mpsc::Sender<proto::HubMessage>,
+    subscriptions: mpsc::UnboundedSender<SubscriptionRequest>,
+    subscription_requests: LeaseSlot<mpsc::UnboundedReceiver<SubscriptionRequest>>,
+}
+
+impl GrpcClient {
+    /// Connects to gRPC server at `url` (e.g. `http://127.0.0.1:50051`)
+    pub async fn new(url: &str) -> Result<Self, std::io::Error> {
+        let client = HubClient::connect(url.to_string())
+            .await
+            .map_err(grpc_error)?;
+        info!("gRPC client connected to {}", url);
+
+        let (publisher, messages) = mpsc::channel(CHANNEL_CAPACITY);
+        let mut publish_client = client.clone();
+        // Publish stream ends when the client is dropped
+        tokio::spawn(async move {
+            if let Err(e) = publish_client.publish(ReceiverStream::new(messages)).await {
+                error!("gRPC publish stream error: {}", e);
+            }
+        });
+
+        let (subscriptions, subscription_requests) = mpsc::unbounded_channel();
+        Ok(Self {
+            client,
+            publisher,
+            subscriptions,
+            subscription_requests: LeaseSlot::new(subscription_requests),
+        })
+    }
+}
+
+#[async_trait]
+impl NotificationHub for GrpcClient {
+    /// Publish message to the server
+    async fn send(&self, data: HubMessage) -> Result<(), std::io::Error> {
+        self.publisher
+            .send(data.into())
+            .await
+            .map_err(|_| stopped())
+    }
+
+    /// List channels published to the server
+    async fn list_channels(&self) -> Result<Vec<HubChannelName>, std::io::Error> {
+        let channels = self
+            .client
+            .clone()
+            .list_channels(ListChannelsRequest {})
+            .await
+            .map_err(grpc_error)?
+            .into_inner()
+            .channels;
+        channels
+            .into_iter()
+            .map(|channel| HubChannelName::try_from(channel).map_err(grpc_error))
+            .collect()
+    }
+
+    /// Open subscribe stream and forward received messages to the hub
+    async fn start(
+        &self,
+        sender: Option<broadcast::Sender<HubMessage>>,
+    ) -> Result<NodeTasks, std::io::Error> {
+        let mut tasks = NodeTasks::new();
+        if let Some(sender) = sender {
+            let mut requests = self.subscription_requests.lease("gRPC client")?;
+            let (stream_requests, stream) = mpsc::unbounded_channel();
+            let mut messages = self
+                .client
+                .clone()
+                .subscribe(UnboundedReceiverStream::new(stream))
+                .await
+                .map_err(grpc_error)?
+                .into_inner();
+            let task = tokio::spawn(async move {
+                loop {
+                    tokio::select! {
+                        request = requests.recv() => match request {
+                            Some(request) => {
+                                let _ = stream_requests.send(request);
+                            }
+                            None => break,
+                        },
+                        message = messages.message() => match message {
+                            Ok(Some(message)) => match HubMessage::try_from(message) {
+                                Ok(message) => {
+                                    let _ = sender.send(message);
+                                }
+                                Err(e) => error!("Invalid gRPC message: {}", e),
+                            },
+                            Ok(None) => break,
+                            Err(e) => {
+                                error!("gRPC subscribe stream error: {}", e);
+                                break;
+                            }
+                        },
+                    }
+                }
+            });
+            tasks.push(task);
+        }
+        Ok(tasks)
+    }
+
+    async fn subscribe(&self, channel: HubChannelName) -> Result<(), std::io::Error> {
+        self.subscriptions
+            .send(SubscriptionRequest::subscribe(&channel))
+            .map_err(|_| stopped())
+    }
+
+    async fn unsubscribe(&self, channel: HubChannelName) -> Result<(), std::io::Error> {
+        self.subscriptions
+            .send(SubscriptionRequest::unsubscribe(&channel))
+            .map_err(|_| stopped())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::grpc::GrpcServer;
+    use tokio::time::{timeout, Duration};
+
+    #[tokio::test]
+    async fn test_client_server() {
+        let server = GrpcServer::new("127.0.0.1:0").await.unwrap();
+        let (server_sender, mut server_receiver) = broadcast::channel(10);
+        server.start(Some(server_sender)).await.unwrap();
+
+        let client = GrpcClient::new(&format!("http://{}", server.local_addr()))
+            .await
+            .unwrap();
+        let (client_sender, mut client_receiver) = broadcast::channel(10);
+        client.start(Some(client_sender)).await.unwrap();
+
+        // client to server
+        let message = HubMessage::try_from_str("imu", "1,2,3").unwrap();
+        client.send(message.clone()).await.unwrap();
+        let received = timeout(Duration::from_secs(5), server_receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(received.channel, message.channel);
+        assert_eq!(received.data.as_str(), "1,2,3");
+        assert_eq!(client.list_channels().await.unwrap(), vec![message.channel]);
+
+        // server to client. Subscription takes a while to reach the server
+        let channel = HubChannelName::try_from("cmd").unwrap();
+        client.subscribe(channel.clone()).await.unwrap();
+        let received = timeout(Duration::from_secs(5), async {
+            loop {
+                let message = HubMessage::try_from_str("cmd", "forward").unwrap();
+                server.send(message).await.unwrap();
+                if let Ok(Ok(message)) =
+                    timeout(Duration::from_millis(50), client_receiver.recv()).await
+                {
+                    return message;
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(received.channel, channel);
+
+        // messages of other channels are not delivered
+        client.unsubscribe(channel).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        server
+            .send(HubMessage::try_from_str("cmd", "stop").unwrap())
+            .await
+            .unwrap();
+        server
+            .send(HubMessage::try_from_str("other", "stop").unwrap())
+            .await
+            .unwrap();
+        while let Ok(message) = client_receiver.try_recv() {
+            assert_eq!(message.data.as_str(), "forward");
+        }
+        assert!(timeout(Duration::from_millis(200), client_receiver.recv())
+            .await
+            .is_err());
+    }
+}
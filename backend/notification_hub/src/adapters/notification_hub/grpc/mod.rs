@@ -0,0 +1,7 @@
+/// gRPC transport for the notification hub. The service is defined in `proto/hub.proto`.
+pub mod client;
+pub mod proto;
+pub mod server;
+
+pub use client::GrpcClient;
+pub use server::GrpcServer;
@@ -0,0 +1,21 @@
+use crate::models::hub::HubChannelName;
+
+pub use crate::models::hub::hub_proto::*;
+
+impl SubscriptionRequest {
+    pub fn subscribe(channel: &HubChannelName) -> Self {
+        Self {
+            request: Some(subscription_request::Request::Subscribe(
+                channel.as_str().to_string(),
+            )),
+        }
+    }
+
+    pub fn unsubscribe(channel: &HubChannelName) -> Self {
+        Self {
+            request: Some(subscription_request::Request::Unsubscribe(
+                channel.as_str().to_string(),
+            )),
+        }
+    }
+}
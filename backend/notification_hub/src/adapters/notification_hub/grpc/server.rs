@@ -0,0 +1,225 @@
+use async_trait::async_trait;
+use futures_util::Stream;
+use log::{error, info};
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast, mpsc, RwLock};
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::{Request, Response, Status, Streaming};
+
+use super::proto::{
+    self, hub_server, subscription_request, ChannelList, ListChannelsRequest, PublishReply,
+    SubscriptionRequest,
+};
+use crate::adapters::lease::LeaseSlot;
+use crate::models::hub::{HubChannelName, HubMessage};
+use crate::ports::{NodeTasks, NotificationHub};
+
+const CHANNEL_CAPACITY: usize = 100;
+
+type SubscribeStream = Pin<Box<dyn Stream<Item = Result<proto::HubMessage, Status>> + Send>>;
+
+/// `GrpcServer` is a hub node serving the `robopilot.hub.Hub` gRPC service, so that clients in
+/// any language with gRPC support exchange messages with the hub.
+///
+/// - `Subscribe` is a bidirectional stream: clients send subscription requests and receive the
+///   messages sent to the hub on the subscribed channels.
+/// - `Publish` is a client stream of messages forwarded to the hub.
+/// - `ListChannels` returns the channels published by clients so far.
+///
+/// # Fields
+/// - `address`: Address the server is listening to.
+/// - `listener`: TCP listener bound by `new`, consumed by the server task when the node is first
+///   started. Its lease marks the server as running, and the address is bound again when the
+///   node is started again.
+/// - `service`: State shared with the gRPC service.
+#[derive(Debug)]
+pub struct GrpcServer {
+    address: SocketAddr,
+    listener: LeaseSlot<Option<TcpListener>>,
+    service: HubService,
+}
+
+/// # Fields
+/// - `hub_sender`: Sender of messages published by clients, set when the node is started.
+/// - `outgoing`: Messages sent to the node, fanned out to every subscribe stream.
+/// - `channels`: Channels published by clients so far.
+#[derive(Debug, Clone)]
+struct HubService {
+    hub_sender: Arc<RwLock<Option<broadcast::Sender<HubMessage>>>>,
+    outgoing: broadcast::Sender<HubMessage>,
+    channels: Arc<RwLock<HashSet<HubChannelName>>>,
+}
+
+impl GrpcServer {
+    /// Binds gRPC server to `address` (e.g. `127.0.0.1:50051`)
+    pub async fn new(address: &str) -> Result<Self, std::io::Error> {
+        let listener = TcpListener::bind(address).await?;
+        let address = listener.local_addr()?;
+        info!("gRPC server listening on {}", address);
+        let (outgoing, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Ok(Self {
+            address,
+            listener: LeaseSlot::new(Some(listener)),
+            service: HubService {
+                hub_sender: Arc::new(RwLock::new(None)),
+                outgoing,
+                channels: Arc::new(RwLock::new(HashSet::new())),
+            },
+        })
+    }
+
+    /// Address the server is listening to. Resolves the port chosen when binding to port 0
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+}
+
+#[async_trait]
+impl NotificationHub for GrpcServer {
+    /// Send message to clients subscribed to its channel
+    async fn send(&self, data: HubMessage) -> Result<(), std::io::Error> {
+        // no subscribe stream open
+        let _ = self.service.outgoing.send(data);
+        Ok(())
+    }
+
+    /// List channels published by clients so far
+    async fn list_channels(&self) -> Result<Vec<HubChannelName>, std::io::Error> {
+        Ok(self.service.channels.read().await.iter().cloned().collect())
+    }
+
+    /// Start serving gRPC requests
+    async fn start(
+        &self,
+        sender: Option<broadcast::Sender<HubMessage>>,
+    ) -> Result<NodeTasks, std::io::Error> {
+        let mut running = self.listener.lease("gRPC server")?;
+        let listener = match running.take() {
+            Some(listener) => listener,
+            None => TcpListener::bind(self.address).await?,
+        };
+        *self.service.hub_sender.write().await = sender;
+
+        let service = hub_server::HubServer::new(self.service.clone());
+        let task = tokio::spawn(async move {
+            let _running = running;
+            if let Err(e) = tonic::transport::Server::builder()
+                .add_service(service)
+                .serve_with_incoming(TcpListenerStream::new(listener))
+                .await
+            {
+                error!("gRPC server error: {}", e);
+            }
+        });
+        let mut tasks = NodeTasks::new();
+        tasks.push(task);
+        Ok(tasks)
+    }
+}
+
+#[tonic::async_trait]
+impl hub_server::Hub for HubService {
+    type SubscribeStream = SubscribeStream;
+
+    async fn subscribe(
+        &self,
+        request: Request<Streaming<SubscriptionRequest>>,
+    ) -> Result<Response<Self::SubscribeStream>, Status> {
+        let mut requests = request.into_inner();
+        let mut outgoing = self.outgoing.subscribe();
+        let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+
+        tokio::spawn(async move {
+            let mut subscribed = HashSet::new();
+            // clients may close their side of the stream and keep receiving messages
+            let mut requests_open = true;
+            loop {
+                tokio::select! {
+                    request = requests.message(), if requests_open => {
+                        match request {
+                            Ok(Some(request)) => {
+                                if let Err(e) = apply_request(&mut subscribed, request) {
+                                    let _ = sender.send(Err(e)).await;
+                                    break;
+                                }
+                            }
+                            Ok(None) => requests_open = false,
+                            Err(_) => break,
+                        }
+                    }
+                    message = outgoing.recv() => {
+                        let message = match message {
+                            Ok(message) => message,
+                            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                                error!("gRPC subscriber lagged, {} messages skipped", skipped);
+                                continue;
+                            }
+                            Err(broadcast::error::RecvError::Closed) => break,
+                        };
+                        if subscribed.contains(&message.channel)
+                            && sender.send(Ok(message.into())).await.is_err()
+                        {
+                            // client disconnected
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(ReceiverStream::new(receiver))))
+    }
+
+    async fn publish(
+        &self,
+        request: Request<Streaming<proto::HubMessage>>,
+    ) -> Result<Response<PublishReply>, Status> {
+        let mut messages = request.into_inner();
+        let mut published = 0;
+        while let Some(message) = messages.message().await? {
+            let message = HubMessage::try_from(message).map_err(Status::invalid_argument)?;
+            self.channels.write().await.insert(message.channel.clone());
+            let hub_sender = self.hub_sender.read().await;
+            let hub_sender = hub_sender
+                .as_ref()
+                .ok_or_else(|| Status::unavailable("Hub does not accept messages"))?;
+            let _ = hub_sender.send(message);
+            published += 1;
+        }
+        Ok(Response::new(PublishReply { published }))
+    }
+
+    async fn list_channels(
+        &self,
+        _request: Request<ListChannelsRequest>,
+    ) -> Result<Response<ChannelList>, Status> {
+        let channels = self
+            .channels
+            .read()
+            .await
+            .iter()
+            .map(|channel| channel.as_str().to_string())
+            .collect();
+        Ok(Response::new(ChannelList { channels }))
+    }
+}
+
+fn apply_request(
+    subscribed: &mut HashSet<HubChannelName>,
+    request: SubscriptionRequest,
+) -> Result<(), Status> {
+    match request.request {
+        Some(subscription_request::Request::Subscribe(channel)) => {
+            subscribed.insert(HubChannelName::try_from(channel).map_err(Status::invalid_argument)?);
+        }
+        Some(subscription_request::Request::Unsubscribe(channel)) => {
+            subscribed
+                .remove(&HubChannelName::try_from(channel).map_err(Status::invalid_argument)?);
+        }
+        None => return Err(Status::invalid_argument("Empty subscription request")),
+    }
+    Ok(())
+}
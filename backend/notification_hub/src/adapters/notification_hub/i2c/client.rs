@@ -0,0 +1,210 @@
+use async_trait::async_trait;
+use i2cdev::core::I2CDevice;
+use i2cdev::linux::LinuxI2CDevice;
+use log::{error, info, warn};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{broadcast, RwLock};
+use tokio::time::MissedTickBehavior;
+
+use super::options::{I2cRead, I2cSensorOptions};
+use crate::models::hub::{HubChannelName, HubMessage};
+use crate::ports::{NodeTasks, NotificationHub};
+
+type Devices = HashMap<u16, LinuxI2CDevice>;
+
+fn i2c_error(e: impl std::fmt::Display) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Other, e.to_string())
+}
+
+// Reads the register blocks of every configured device. Runs on a blocking thread, as
+// i2c-dev transfers are synchronous.
+fn read_all(devices: &Mutex<Devices>, reads: &[I2cRead]) -> Vec<Result<Vec<u8>, String>> {
+    let mut devices = match devices.lock() {
+        Ok(devices) => devices,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    reads
+        .iter()
+        .map(|read| {
+            let device = devices
+                .get_mut(&read.address)
+                .ok_or_else(|| format!("Device {:#04x} not opened", read.address))?;
+            device
+                .smbus_read_i2c_block_data(read.register, read.layout.len() as u8)
+                .map_err(|e| e.to_string())
+        })
+        .collect()
+}
+
+/// `I2cSensorClient` is a hub node polling I2C sensors (IMUs, barometers, ADCs...) through the
+/// Linux i2c-dev interface, and publishing their readings on hub channels.
+///
+/// On every poll, each configured register block is read and decoded with its `SampleLayout`
+/// into comma separated values. Sensors are read only, so messages sent to the node are
+/// ignored.
+///
+/// # Fields
+/// - `options`: Bus, poll rate and registers to read.
+/// - `devices`: Open devices by address, shared with the poll loop.
+/// - `channels`: Channels published so far.
+pub struct I2cSensorClient {
+    options: Arc<I2cSensorOptions>,
+    devices: Arc<Mutex<Devices>>,
+    channels: Arc<RwLock<HashSet<HubChannelName>>>,
+}
+
+impl std::fmt::Debug for I2cSensorClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("I2cSensorClient")
+            .field("options", &self.options)
+            .finish_non_exhaustive()
+    }
+}
+
+impl I2cSensorClient {
+    /// Opens the devices of `options`, and writes their init registers
+    pub fn new(options: I2cSensorOptions) -> Result<Self, std::io::Error> {
+        let mut devices = Devices::new();
+        let addresses = options
+            .reads()
+            .iter()
+            .map(|read| read.address)
+            .chain(options.init().iter().map(|write| write.address));
+        for address in addresses {
+            if !devices.contains_key(&address) {
+                let device = LinuxI2CDevice::new(options.bus(), address).map_err(i2c_error)?;
+                devices.insert(address, device);
+            }
+        }
+        for write in options.init() {
+            if let Some(device) = devices.get_mut(&write.address) {
+                device
+                    .smbus_write_byte_data(write.register, write.value)
+                    .map_err(i2c_error)?;
+            }
+        }
+        info!(
+            "I2C sensor client opened {} devices on {}",
+            devices.len(),
+            options.bus()
+        );
+        Ok(Self {
+            options: Arc::new(options),
+            devices: Arc::new(Mutex::new(devices)),
+            channels: Arc::new(RwLock::new(HashSet::new())),
+        })
+    }
+
+    pub fn options(&self) -> &I2cSensorOptions {
+        &self.options
+    }
+}
+
+#[async_trait]
+impl NotificationHub for I2cSensorClient {
+    /// Sensors are read only, messages are ignored
+    async fn send(&self, _data: HubMessage) -> Result<(), std::io::Error> {
+        Ok(())
+    }
+
+    /// List channels published so far
+    async fn list_channels(&self) -> Result<Vec<HubChannelName>, std::io::Error> {
+        Ok(self.channels.read().await.iter().cloned().collect())
+    }
+
+    /// Start poll loop
+    async fn start(
+        &self,
+        sender: Option<broadcast::Sender<HubMessage>>,
+    ) -> Result<NodeTasks, std::io::Error> {
+        let mut tasks = NodeTasks::new();
+        if let Some(sender) = sender {
+            let options = Arc::clone(&self.options);
+            let devices = Arc::clone(&self.devices);
+            let channels = Arc::clone(&self.channels);
+            let task = tokio::spawn(async move {
+                let mut interval = tokio::time::interval(options.period());
+                interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+                loop {
+                    interval.tick().await;
+                    let samples = {
+                        let options = Arc::clone(&options);
+                        let devices = Arc::clone(&devices);
+                        tokio::task::spawn_blocking(move || read_all(&devices, options.reads()))
+                            .await
+                    };
+                    let samples = match samples {
+                        Ok(samples) => samples,
+                        Err(e) => {
+                            error!("I2C poll task failed: {:?}", e);
+                            break;
+                        }
+                    };
+                    for (read, sample) in options.reads().iter().zip(samples) {
+                        match sample.and_then(|sample| read.layout.decode(&sample)) {
+                            Ok(data) => {
+                                if !channels.read().await.contains(&read.channel) {
+                                    channels.write().await.insert(read.channel.clone());
+                                }
+                                let _ = sender.send(HubMessage::new(read.channel.clone(), data));
+                            }
+                            Err(e) => warn!(
+                                "I2C read of {:#04x} register {:#04x} failed: {}",
+                                read.address, read.register, e
+                            ),
+                        }
+                    }
+                }
+            });
+            tasks.push(task);
+        }
+        Ok(tasks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::i2c::I2cSensorOptionsBuilder;
+    use crate::adapters::sample::{FieldKind, SampleField, SampleLayout};
+    use tokio::time::{timeout, Duration};
+
+    // Requires an MPU-6050 IMU at address 0x68 of /dev/i2c-1
+    #[tokio::test]
+    #[ignore]
+    async fn test_mpu6050() {
+        let accel = |name: &str, offset: usize| {
+            SampleField::new(name, offset, FieldKind::I16Be).scaled(9.80665 / 16384.0, 0.0)
+        };
+        let options = I2cSensorOptionsBuilder::new()
+            .rate(100.0)
+            // wake up from sleep mode
+            .init_write(0x68, 0x6b, 0x00)
+            .read(
+                0x68,
+                0x3b,
+                "imu/accel",
+                SampleLayout::new(vec![accel("x", 0), accel("y", 2), accel("z", 4)]).unwrap(),
+            )
+            .build()
+            .unwrap();
+        let node = I2cSensorClient::new(options).unwrap();
+        let (sender, mut receiver) = broadcast::channel(10);
+        node.start(Some(sender)).await.unwrap();
+
+        let received = timeout(Duration::from_secs(1), receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(received.channel.as_str(), "imu/accel");
+        let values: Vec<f64> = received
+            .data
+            .as_str()
+            .split(',')
+            .map(|value| value.parse().unwrap())
+            .collect();
+        assert_eq!(values.len(), 3);
+        assert_eq!(node.list_channels().await.unwrap().len(), 1);
+    }
+}
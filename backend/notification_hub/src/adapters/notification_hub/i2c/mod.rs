@@ -0,0 +1,6 @@
+/// I2C sensor transport for the notification hub, polling device registers over Linux i2c-dev.
+pub mod client;
+pub mod options;
+
+pub use client::I2cSensorClient;
+pub use options::{I2cRead, I2cSensorOptions, I2cSensorOptionsBuilder, I2cWrite};
@@ -0,0 +1,230 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use crate::adapters::sample::SampleLayout;
+use crate::models::hub::HubChannelName;
+
+const DEFAULT_BUS: &str = "/dev/i2c-1";
+const DEFAULT_RATE_HZ: f64 = 10.0;
+const MAX_RATE_HZ: f64 = 1000.0;
+// 7 bit addressing
+const MAX_ADDRESS: u16 = 0x7f;
+// SMBus block reads are limited to 32 bytes
+const MAX_READ_LEN: usize = 32;
+
+/// Block of registers read from a device on every poll, and published on a hub channel.
+///
+/// # Fields
+/// - `address`: 7 bit address of the device.
+/// - `register`: First register of the block.
+/// - `channel`: Hub channel where readings are published.
+/// - `layout`: Layout of the block, decoded into the published data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct I2cRead {
+    pub address: u16,
+    pub register: u8,
+    pub channel: HubChannelName,
+    pub layout: SampleLayout,
+}
+
+/// Register written once when the client is created, e.g. to wake up a sensor or set its
+/// range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct I2cWrite {
+    pub address: u16,
+    pub register: u8,
+    pub value: u8,
+}
+
+/// `I2cSensorOptions` configures an `I2cSensorClient`.
+///
+/// # Fields
+/// - `bus`: Path of the i2c-dev bus. Defaults to `/dev/i2c-1`.
+/// - `period`: Poll period. Defaults to 10 Hz.
+/// - `reads`: Register blocks read on every poll.
+/// - `init`: Registers written before polling starts, in order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct I2cSensorOptions {
+    bus: String,
+    period: Duration,
+    reads: Vec<I2cRead>,
+    init: Vec<I2cWrite>,
+}
+
+impl I2cSensorOptions {
+    pub fn bus(&self) -> &str {
+        &self.bus
+    }
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+    pub fn reads(&self) -> &[I2cRead] {
+        &self.reads
+    }
+    pub fn init(&self) -> &[I2cWrite] {
+        &self.init
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct I2cSensorOptionsBuilder {
+    bus: Option<String>,
+    rate: Option<f64>,
+    reads: Vec<(u16, u8, String, SampleLayout)>,
+    init: Vec<I2cWrite>,
+}
+
+impl I2cSensorOptionsBuilder {
+    pub fn new() -> Self {
+        Self {
+            bus: None,
+            rate: None,
+            reads: Vec::new(),
+            init: Vec::new(),
+        }
+    }
+
+    pub fn bus(&self, bus: &str) -> Self {
+        let mut new = self.clone();
+        new.bus = Some(bus.to_string());
+        new
+    }
+    /// Poll rate in Hz
+    pub fn rate(&self, rate: f64) -> Self {
+        let mut new = self.clone();
+        new.rate = Some(rate);
+        new
+    }
+    /// Reads `layout.len()` bytes from `register` of device `address`, and publishes them on
+    /// `channel`
+    pub fn read(&self, address: u16, register: u8, channel: &str, layout: SampleLayout) -> Self {
+        let mut new = self.clone();
+        new.reads
+            .push((address, register, channel.to_string(), layout));
+        new
+    }
+    /// Writes `value` to `register` of device `address` before polling starts
+    pub fn init_write(&self, address: u16, register: u8, value: u8) -> Self {
+        let mut new = self.clone();
+        new.init.push(I2cWrite {
+            address,
+            register,
+            value,
+        });
+        new
+    }
+    pub fn build(self) -> Result<I2cSensorOptions, String> {
+        let rate = self.rate.unwrap_or(DEFAULT_RATE_HZ);
+        if !rate.is_finite() || rate <= 0.0 || rate > MAX_RATE_HZ {
+            return Err(format!(
+                "Poll rate must be positive and at most {} Hz",
+                MAX_RATE_HZ
+            ));
+        }
+        if self.reads.is_empty() {
+            return Err("No I2C registers to read".to_string());
+        }
+        let check_address = |address: u16| {
+            if address > MAX_ADDRESS {
+                Err(format!("Invalid 7 bit I2C address {:#04x}", address))
+            } else {
+                Ok(())
+            }
+        };
+        let mut channels = HashSet::new();
+        let mut reads = Vec::with_capacity(self.reads.len());
+        for (address, register, channel, layout) in self.reads {
+            check_address(address)?;
+            if layout.len() > MAX_READ_LEN {
+                return Err(format!(
+                    "Reads of channel {} exceed {} bytes",
+                    channel, MAX_READ_LEN
+                ));
+            }
+            let channel = HubChannelName::try_from(channel.as_str())?;
+            if !channels.insert(channel.clone()) {
+                return Err(format!(
+                    "Channel {} is read more than once",
+                    channel.as_str()
+                ));
+            }
+            reads.push(I2cRead {
+                address,
+                register,
+                channel,
+                layout,
+            });
+        }
+        for write in &self.init {
+            check_address(write.address)?;
+        }
+        Ok(I2cSensorOptions {
+            bus: self.bus.unwrap_or(DEFAULT_BUS.to_string()),
+            period: Duration::from_secs_f64(1.0 / rate),
+            reads,
+            init: self.init,
+        })
+    }
+}
+
+impl Default for I2cSensorOptionsBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::sample::{FieldKind, SampleField};
+
+    fn layout(len: usize) -> SampleLayout {
+        SampleLayout::new(vec![SampleField::new("x", len - 1, FieldKind::U8)]).unwrap()
+    }
+
+    #[test]
+    fn test_options() {
+        let options = I2cSensorOptionsBuilder::new()
+            .rate(50.0)
+            .init_write(0x68, 0x6b, 0x00)
+            .read(0x68, 0x3b, "imu/accel", layout(6))
+            .build()
+            .unwrap();
+        assert_eq!(options.bus(), "/dev/i2c-1");
+        assert_eq!(options.period(), Duration::from_millis(20));
+        assert_eq!(options.reads().len(), 1);
+        assert_eq!(options.reads()[0].channel.as_str(), "imu/accel");
+        assert_eq!(
+            options.init(),
+            &[I2cWrite {
+                address: 0x68,
+                register: 0x6b,
+                value: 0
+            }]
+        );
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        let builder = I2cSensorOptionsBuilder::new();
+        assert!(builder.clone().build().is_err());
+        assert!(builder.read(0x80, 0, "sensor", layout(1)).build().is_err());
+        assert!(builder.read(0x48, 0, "sensor", layout(33)).build().is_err());
+        assert!(builder.read(0x48, 0, "Sensor", layout(1)).build().is_err());
+        assert!(builder
+            .read(0x48, 0, "sensor", layout(1))
+            .read(0x49, 0, "sensor", layout(1))
+            .build()
+            .is_err());
+        assert!(builder
+            .read(0x48, 0, "sensor", layout(1))
+            .rate(0.0)
+            .build()
+            .is_err());
+        assert!(builder
+            .read(0x48, 0, "sensor", layout(1))
+            .init_write(0x100, 0, 0)
+            .build()
+            .is_err());
+    }
+}
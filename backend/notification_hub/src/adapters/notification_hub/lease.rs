@@ -0,0 +1,98 @@
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// `LeaseSlot` holds a resource of a hub node taken by the tasks of the node when it is
+/// started (e.g. the read half of a port, or a listener). The resource is leased: it is put
+/// back in the slot when the task ends or is aborted by `HubManager::stop`, so the node can be
+/// started again. Leasing fails while the resource is leased, meaning the node is running.
+#[derive(Debug)]
+pub(crate) struct LeaseSlot<T> {
+    slot: Arc<Mutex<Option<T>>>,
+}
+
+/// Resource leased from a `LeaseSlot`, put back in the slot when dropped
+#[derive(Debug)]
+pub(crate) struct Lease<T> {
+    // only taken when the lease is dropped
+    value: Option<T>,
+    slot: Arc<Mutex<Option<T>>>,
+}
+
+fn lock<T>(slot: &Mutex<Option<T>>) -> MutexGuard<'_, Option<T>> {
+    slot.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl<T> LeaseSlot<T> {
+    pub(crate) fn new(value: T) -> Self {
+        Self {
+            slot: Arc::new(Mutex::new(Some(value))),
+        }
+    }
+
+    /// Leases the resource. Fails with `AlreadyExists` if already leased, reporting `node`
+    /// as already started
+    pub(crate) fn lease(&self, node: &str) -> Result<Lease<T>, std::io::Error> {
+        let value = lock(&self.slot).take().ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("{} already started", node),
+            )
+        })?;
+        Ok(Lease {
+            value: Some(value),
+            slot: Arc::clone(&self.slot),
+        })
+    }
+}
+
+impl<T> Deref for Lease<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value.as_ref().expect("leased value")
+    }
+}
+
+impl<T> DerefMut for Lease<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.value.as_mut().expect("leased value")
+    }
+}
+
+impl<T> Drop for Lease<T> {
+    fn drop(&mut self) {
+        *lock(&self.slot) = self.value.take();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_lease_is_returned_by_aborted_task() {
+        let slot = LeaseSlot::new(1);
+        let lease = slot.lease("node").unwrap();
+        assert_eq!(*lease, 1);
+        let err = slot.lease("node").unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+        assert_eq!(err.to_string(), "node already started");
+
+        let task = tokio::spawn(async move {
+            let _lease = lease;
+            std::future::pending::<()>().await
+        });
+        task.abort();
+        assert!(task.await.unwrap_err().is_cancelled());
+        assert_eq!(*slot.lease("node").unwrap(), 1);
+    }
+
+    #[test]
+    fn test_lease_keeps_changes() {
+        let slot = LeaseSlot::new(1);
+        let mut lease = slot.lease("node").unwrap();
+        *lease = 2;
+        drop(lease);
+        assert_eq!(*slot.lease("node").unwrap(), 2);
+    }
+}
@@ -0,0 +1,207 @@
+use async_trait::async_trait;
+use log::{error, info, warn};
+use serialport::SerialPort;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf};
+use tokio::sync::{broadcast, Mutex, RwLock};
+use tokio::time::Instant;
+use tokio_serial::{DataBits, Parity, SerialPortBuilderExt, SerialStream, StopBits};
+
+use super::codec::{encode_frame, FrameParser, FRAME_OVERHEAD};
+use super::options::LoraOptions;
+use crate::adapters::lease::LeaseSlot;
+use crate::models::hub::{HubChannelName, HubMessage};
+use crate::ports::{NodeTasks, NotificationHub};
+
+/// `LoraClient` is a hub node sending telemetry over a long-range, low-bandwidth LoRa link,
+/// through a UART LoRa modem in transparent mode.
+///
+/// Only whitelisted channels are sent, each at most once per its minimum interval. Frames
+/// carry the position of the channel in the whitelist instead of its name, and the data
+/// compacted as configured for the channel. Received messages are timestamped on arrival.
+///
+/// # Fields
+/// - `options`: Whitelisted channels and max packet size.
+/// - `reader`: Read half of the serial port. It is leased by the read loop when the client is
+///   started.
+/// - `writer`: Write half of the serial port.
+/// - `last_sent`: Time the last message of each channel id was sent.
+/// - `channels`: Channels received so far.
+#[derive(Debug)]
+pub struct LoraClient {
+    options: Arc<LoraOptions>,
+    reader: LeaseSlot<ReadHalf<SerialStream>>,
+    writer: Mutex<WriteHalf<SerialStream>>,
+    last_sent: Mutex<HashMap<u8, Instant>>,
+    channels: Arc<RwLock<HashSet<HubChannelName>>>,
+}
+
+impl LoraClient {
+    pub fn new(port: &str, baud_rate: u32, options: LoraOptions) -> Result<Self, std::io::Error> {
+        info!(
+            "Opening LoRa modem at {} with params {}...",
+            port, baud_rate
+        );
+        let mut port = tokio_serial::new(port, baud_rate)
+            .open_native_async()
+            .inspect_err(|_| {
+                error!("LoRa modem at {} not ready", port);
+            })?;
+        port.set_parity(Parity::None)?;
+        port.set_stop_bits(StopBits::One)?;
+        port.set_data_bits(DataBits::Eight)?;
+        let (reader, writer) = tokio::io::split(port);
+        Ok(Self {
+            options: Arc::new(options),
+            reader: LeaseSlot::new(reader),
+            writer: Mutex::new(writer),
+            last_sent: Mutex::new(HashMap::new()),
+            channels: Arc::new(RwLock::new(HashSet::new())),
+        })
+    }
+
+    pub fn options(&self) -> &LoraOptions {
+        &self.options
+    }
+}
+
+#[async_trait]
+impl NotificationHub for LoraClient {
+    /// Send message if its channel is whitelisted and its min interval has elapsed
+    async fn send(&self, data: HubMessage) -> Result<(), std::io::Error> {
+        let Some((id, lora_channel)) = self.options.by_channel(&data.channel) else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        let mut last_sent = self.last_sent.lock().await;
+        if let Some(last) = last_sent.get(&id) {
+            if now.duration_since(*last) < lora_channel.min_interval {
+                return Ok(());
+            }
+        }
+        let payload = lora_channel
+            .encoding
+            .encode(&data.data)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        if payload.len() + FRAME_OVERHEAD > self.options.max_packet_size() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "Message of channel {} exceeds LoRa max packet size",
+                    data.channel.as_str()
+                ),
+            ));
+        }
+        let frame = encode_frame(id, &payload)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+        self.writer.lock().await.write_all(&frame).await?;
+        last_sent.insert(id, now);
+        Ok(())
+    }
+
+    /// List channels received so far
+    async fn list_channels(&self) -> Result<Vec<HubChannelName>, std::io::Error> {
+        Ok(self.channels.read().await.iter().cloned().collect())
+    }
+
+    /// Start receive loop
+    async fn start(
+        &self,
+        sender: Option<broadcast::Sender<HubMessage>>,
+    ) -> Result<NodeTasks, std::io::Error> {
+        let mut tasks = NodeTasks::new();
+        if let Some(sender) = sender {
+            let mut reader = self.reader.lease("LoRa modem")?;
+            let options = Arc::clone(&self.options);
+            let channels = Arc::clone(&self.channels);
+            let task = tokio::spawn(async move {
+                let mut parser = FrameParser::new();
+                let mut buffer = [0u8; 256];
+                loop {
+                    match reader.read(&mut buffer).await {
+                        Ok(n) if n > 0 => parser.push(&buffer[..n]),
+                        Ok(_) => {
+                            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+                            continue;
+                        }
+                        Err(e) => {
+                            error!("LoRa modem error {:?}", e);
+                            break;
+                        }
+                    }
+                    while let Some(frame) = parser.next_frame() {
+                        let (id, payload) = match frame {
+                            Ok(frame) => frame,
+                            Err(e) => {
+                                warn!("{}", e);
+                                continue;
+                            }
+                        };
+                        let Some(lora_channel) = options.by_id(id) else {
+                            warn!("LoRa frame of unknown channel id {}", id);
+                            continue;
+                        };
+                        match lora_channel.encoding.decode(&payload) {
+                            Ok(data) => {
+                                let channel = lora_channel.channel.clone();
+                                channels.write().await.insert(channel.clone());
+                                let _ = sender.send(HubMessage::new(channel, data));
+                            }
+                            Err(e) => warn!(
+                                "Invalid LoRa payload of channel {}: {}",
+                                lora_channel.channel.as_str(),
+                                e
+                            ),
+                        }
+                    }
+                }
+            });
+            tasks.push(task);
+        }
+        Ok(tasks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::lora::{LoraEncoding, LoraOptionsBuilder};
+    use tokio::time::{timeout, Duration};
+
+    // Requires two LoRa modems in transparent mode on the same frequency
+    #[tokio::test]
+    #[ignore]
+    async fn test_lora_link() {
+        let options = || {
+            LoraOptionsBuilder::new()
+                .channel("gps/position", LoraEncoding::F32, Duration::from_secs(1))
+                .channel(
+                    "battery",
+                    LoraEncoding::Quantized { scale: 0.01 },
+                    Duration::ZERO,
+                )
+                .build()
+                .unwrap()
+        };
+        let node1 = LoraClient::new("/dev/ttyUSB0", 9600, options()).unwrap();
+        let node2 = LoraClient::new("/dev/ttyUSB1", 9600, options()).unwrap();
+        let (sender, mut receiver) = broadcast::channel(10);
+        node2.start(Some(sender)).await.unwrap();
+
+        node1
+            .send(HubMessage::try_from_str("camera", "frame").unwrap())
+            .await
+            .unwrap();
+        node1
+            .send(HubMessage::try_from_str("battery", "12.6").unwrap())
+            .await
+            .unwrap();
+        let received = timeout(Duration::from_secs(5), receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(received.channel.as_str(), "battery");
+        assert_eq!(received.data.as_str(), "12.60");
+    }
+}
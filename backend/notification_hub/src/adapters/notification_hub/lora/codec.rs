@@ -0,0 +1,209 @@
+use crate::models::hub::HubData;
+
+/// Start of frame marker
+pub const SYNC: u8 = 0xa5;
+/// Bytes added to the payload by the frame: sync, channel id, length and checksum
+pub const FRAME_OVERHEAD: usize = 4;
+
+/// Compaction of the data of a whitelisted channel.
+///
+/// - `Text`: Data is sent as is. For channels whose data isn't numeric.
+/// - `F32`: Data is a comma separated list of numbers, each sent as a 4 byte float.
+/// - `Quantized`: Data is a comma separated list of numbers, each sent as a 2 byte integer
+///   multiple of `scale` (e.g. a scale of 0.01 sends a battery voltage of 12.34 as 1234).
+///   Numbers out of the range of the integer are rejected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoraEncoding {
+    Text,
+    F32,
+    Quantized { scale: f64 },
+}
+
+// Decimals needed to print multiples of scale
+fn decimals(scale: f64) -> usize {
+    // tolerance avoids an extra decimal when log10 is not exact
+    (-scale.abs().log10() - 1e-9).ceil().max(0.0) as usize
+}
+
+impl LoraEncoding {
+    /// Compacts `data` into a payload
+    pub fn encode(&self, data: &HubData) -> Result<Vec<u8>, String> {
+        match self {
+            Self::Text => Ok(data.as_str().as_bytes().to_vec()),
+            Self::F32 => Ok(data
+                .as_f64_vec()?
+                .into_iter()
+                .flat_map(|value| (value as f32).to_be_bytes())
+                .collect()),
+            Self::Quantized { scale } => {
+                let mut payload = Vec::new();
+                for value in data.as_f64_vec()? {
+                    let quantized = (value / scale).round();
+                    if quantized < i16::MIN as f64 || quantized > i16::MAX as f64 {
+                        return Err(format!("Value {} out of quantized range", value));
+                    }
+                    payload.extend_from_slice(&(quantized as i16).to_be_bytes());
+                }
+                Ok(payload)
+            }
+        }
+    }
+
+    /// Expands `payload` into hub data
+    pub fn decode(&self, payload: &[u8]) -> Result<HubData, String> {
+        let data = match self {
+            Self::Text => String::from_utf8(payload.to_vec()).map_err(|e| e.to_string())?,
+            Self::F32 => {
+                if payload.len() % 4 != 0 {
+                    return Err("Invalid F32 payload length".to_string());
+                }
+                payload
+                    .chunks(4)
+                    .map(|bytes| {
+                        f32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]).to_string()
+                    })
+                    .collect::<Vec<_>>()
+                    .join(",")
+            }
+            Self::Quantized { scale } => {
+                if payload.len() % 2 != 0 {
+                    return Err("Invalid quantized payload length".to_string());
+                }
+                let decimals = decimals(*scale);
+                payload
+                    .chunks(2)
+                    .map(|bytes| {
+                        let value = i16::from_be_bytes([bytes[0], bytes[1]]) as f64 * scale;
+                        format!("{:.*}", decimals, value)
+                    })
+                    .collect::<Vec<_>>()
+                    .join(",")
+            }
+        };
+        HubData::try_from(data)
+    }
+}
+
+// CRC-8 with polynomial 0x07
+fn crc8(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |crc, byte| {
+        (0..8).fold(crc ^ byte, |crc, _| {
+            if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+/// Frames `payload` of channel `id`. Payloads are at most 255 bytes long.
+pub fn encode_frame(id: u8, payload: &[u8]) -> Result<Vec<u8>, String> {
+    let len = u8::try_from(payload.len()).map_err(|_| "LoRa payload too long".to_string())?;
+    let mut frame = Vec::with_capacity(payload.len() + FRAME_OVERHEAD);
+    frame.extend_from_slice(&[SYNC, id, len]);
+    frame.extend_from_slice(payload);
+    frame.push(crc8(&frame[1..]));
+    Ok(frame)
+}
+
+/// Extracts frames from the byte stream received from the modem. Bytes preceding a sync
+/// marker are discarded, so that the parser recovers from truncated or corrupted frames.
+#[derive(Debug, Default)]
+pub struct FrameParser {
+    buffer: Vec<u8>,
+}
+
+impl FrameParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Next channel id and payload, or `None` until a full frame is buffered
+    pub fn next_frame(&mut self) -> Option<Result<(u8, Vec<u8>), String>> {
+        let start = match self.buffer.iter().position(|byte| *byte == SYNC) {
+            Some(start) => start,
+            None => {
+                self.buffer.clear();
+                return None;
+            }
+        };
+        self.buffer.drain(..start);
+        if self.buffer.len() < 3 {
+            return None;
+        }
+        let len = self.buffer[2] as usize + FRAME_OVERHEAD;
+        if self.buffer.len() < len {
+            return None;
+        }
+        if crc8(&self.buffer[1..len - 1]) != self.buffer[len - 1] {
+            // drop sync marker, and look for the next one
+            self.buffer.drain(..1);
+            return Some(Err("Invalid LoRa frame checksum".to_string()));
+        }
+        let frame: Vec<u8> = self.buffer.drain(..len).collect();
+        Some(Ok((frame[1], frame[3..len - 1].to_vec())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(encoding: LoraEncoding, data: &str) -> Result<(usize, String), String> {
+        let payload = encoding.encode(&data.parse::<HubData>()?)?;
+        let decoded = encoding.decode(&payload)?;
+        Ok((payload.len(), decoded.as_str().to_string()))
+    }
+
+    #[test]
+    fn test_encodings() {
+        assert_eq!(
+            round_trip(LoraEncoding::Text, "armed").unwrap(),
+            (5, "armed".to_string())
+        );
+        assert_eq!(
+            round_trip(LoraEncoding::F32, "40.5,-3.25").unwrap(),
+            (8, "40.5,-3.25".to_string())
+        );
+        assert_eq!(
+            round_trip(LoraEncoding::Quantized { scale: 0.01 }, "12.34,87").unwrap(),
+            (4, "12.34,87.00".to_string())
+        );
+        assert_eq!(
+            round_trip(LoraEncoding::Quantized { scale: 10.0 }, "1234").unwrap(),
+            (2, "1230".to_string())
+        );
+        assert!(round_trip(LoraEncoding::Quantized { scale: 0.01 }, "400").is_err());
+        assert!(round_trip(LoraEncoding::F32, "north").is_err());
+        assert!(LoraEncoding::F32.decode(&[0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn test_frames() {
+        let frame = encode_frame(3, &[1, 2, 3]).unwrap();
+        assert_eq!(frame.len(), 3 + FRAME_OVERHEAD);
+        assert!(encode_frame(0, &[0; 256]).is_err());
+
+        let mut parser = FrameParser::new();
+        // noise, then a frame split across reads
+        parser.push(&[0x00, 0x42]);
+        parser.push(&frame[..4]);
+        assert!(parser.next_frame().is_none());
+        parser.push(&frame[4..]);
+        assert_eq!(parser.next_frame().unwrap().unwrap(), (3, vec![1, 2, 3]));
+        assert!(parser.next_frame().is_none());
+
+        // corrupted frame followed by a valid one
+        let mut corrupted = frame.clone();
+        corrupted[4] ^= 0xff;
+        parser.push(&corrupted);
+        parser.push(&encode_frame(1, &[]).unwrap());
+        assert!(parser.next_frame().unwrap().is_err());
+        assert_eq!(parser.next_frame().unwrap().unwrap(), (1, vec![]));
+    }
+}
@@ -0,0 +1,9 @@
+/// LoRa transport for the notification hub, for low-bandwidth long-range telemetry through
+/// UART LoRa modems.
+pub mod client;
+pub mod codec;
+pub mod options;
+
+pub use client::LoraClient;
+pub use codec::LoraEncoding;
+pub use options::{LoraChannel, LoraOptions, LoraOptionsBuilder};
@@ -0,0 +1,188 @@
+use std::time::Duration;
+
+use super::codec::{LoraEncoding, FRAME_OVERHEAD};
+use crate::models::hub::HubChannelName;
+
+// Serial buffer of common UART LoRa modules (e.g. Ebyte E32). Larger packets are split by the
+// modem, and may be lost partially.
+const DEFAULT_MAX_PACKET_SIZE: usize = 58;
+const MAX_PACKET_SIZE: usize = u8::MAX as usize + FRAME_OVERHEAD;
+
+/// Channel allowed on the LoRa link.
+///
+/// # Fields
+/// - `channel`: Hub channel name. It isn't sent over the air, the position of the channel in
+///   the whitelist is sent instead.
+/// - `encoding`: Compaction of the channel data.
+/// - `min_interval`: Minimum time between two messages of the channel. Messages sent sooner
+///   are dropped, so that high-rate channels don't saturate the link.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoraChannel {
+    pub channel: HubChannelName,
+    pub encoding: LoraEncoding,
+    pub min_interval: Duration,
+}
+
+/// `LoraOptions` configures a `LoraClient`. Both ends of the link must be configured with the
+/// same whitelist, in the same order.
+///
+/// # Fields
+/// - `channels`: Whitelisted channels. Messages of other channels aren't sent.
+/// - `max_packet_size`: Maximum frame size in bytes. Defaults to 58 bytes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoraOptions {
+    channels: Vec<LoraChannel>,
+    max_packet_size: usize,
+}
+
+impl LoraOptions {
+    pub fn channels(&self) -> &[LoraChannel] {
+        &self.channels
+    }
+    pub fn max_packet_size(&self) -> usize {
+        self.max_packet_size
+    }
+
+    /// Id and configuration of whitelisted `channel`
+    pub fn by_channel(&self, channel: &HubChannelName) -> Option<(u8, &LoraChannel)> {
+        self.channels
+            .iter()
+            .position(|lora_channel| &lora_channel.channel == channel)
+            .map(|id| (id as u8, &self.channels[id]))
+    }
+
+    /// Configuration of the whitelisted channel with `id`
+    pub fn by_id(&self, id: u8) -> Option<&LoraChannel> {
+        self.channels.get(id as usize)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LoraOptionsBuilder {
+    channels: Vec<(String, LoraEncoding, Duration)>,
+    max_packet_size: Option<usize>,
+}
+
+impl LoraOptionsBuilder {
+    pub fn new() -> Self {
+        Self {
+            channels: Vec::new(),
+            max_packet_size: None,
+        }
+    }
+
+    /// Whitelists `channel`, sending at most one message every `min_interval`
+    pub fn channel(&self, channel: &str, encoding: LoraEncoding, min_interval: Duration) -> Self {
+        let mut new = self.clone();
+        new.channels
+            .push((channel.to_string(), encoding, min_interval));
+        new
+    }
+    pub fn max_packet_size(&self, max_packet_size: usize) -> Self {
+        let mut new = self.clone();
+        new.max_packet_size = Some(max_packet_size);
+        new
+    }
+    pub fn build(self) -> Result<LoraOptions, String> {
+        if self.channels.is_empty() {
+            return Err("No whitelisted LoRa channels".to_string());
+        }
+        if self.channels.len() > u8::MAX as usize + 1 {
+            return Err(format!(
+                "At most {} LoRa channels can be whitelisted",
+                u8::MAX as usize + 1
+            ));
+        }
+        let max_packet_size = self.max_packet_size.unwrap_or(DEFAULT_MAX_PACKET_SIZE);
+        if max_packet_size <= FRAME_OVERHEAD || max_packet_size > MAX_PACKET_SIZE {
+            return Err(format!(
+                "Max packet size must be between {} and {} bytes",
+                FRAME_OVERHEAD + 1,
+                MAX_PACKET_SIZE
+            ));
+        }
+        let mut channels: Vec<LoraChannel> = Vec::with_capacity(self.channels.len());
+        for (channel, encoding, min_interval) in self.channels {
+            let channel = HubChannelName::try_from(channel.as_str())?;
+            if channels.iter().any(|other| other.channel == channel) {
+                return Err(format!(
+                    "Channel {} is whitelisted more than once",
+                    channel.as_str()
+                ));
+            }
+            if let LoraEncoding::Quantized { scale } = encoding {
+                if !scale.is_finite() || scale <= 0.0 {
+                    return Err(format!(
+                        "Channel {} has an invalid quantization scale",
+                        channel.as_str()
+                    ));
+                }
+            }
+            channels.push(LoraChannel {
+                channel,
+                encoding,
+                min_interval,
+            });
+        }
+        Ok(LoraOptions {
+            channels,
+            max_packet_size,
+        })
+    }
+}
+
+impl Default for LoraOptionsBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_options() {
+        let options = LoraOptionsBuilder::new()
+            .channel("gps/position", LoraEncoding::F32, Duration::from_secs(5))
+            .channel(
+                "battery",
+                LoraEncoding::Quantized { scale: 0.01 },
+                Duration::from_secs(30),
+            )
+            .build()
+            .unwrap();
+        assert_eq!(options.max_packet_size(), 58);
+        let battery = HubChannelName::try_from("battery").unwrap();
+        let (id, channel) = options.by_channel(&battery).unwrap();
+        assert_eq!(id, 1);
+        assert_eq!(channel.min_interval, Duration::from_secs(30));
+        assert_eq!(options.by_id(0).unwrap().channel.as_str(), "gps/position");
+        assert!(options.by_id(2).is_none());
+        assert!(options
+            .by_channel(&HubChannelName::try_from("camera").unwrap())
+            .is_none());
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        let builder = LoraOptionsBuilder::new();
+        assert!(builder.clone().build().is_err());
+        let builder = builder.channel("battery", LoraEncoding::F32, Duration::ZERO);
+        assert!(builder.max_packet_size(FRAME_OVERHEAD).build().is_err());
+        assert!(builder.max_packet_size(1000).build().is_err());
+        assert!(builder
+            .channel("battery", LoraEncoding::Text, Duration::ZERO)
+            .build()
+            .is_err());
+        assert!(builder
+            .channel(
+                "rssi",
+                LoraEncoding::Quantized { scale: 0.0 },
+                Duration::ZERO
+            )
+            .build()
+            .is_err());
+        assert!(builder.build().is_ok());
+    }
+}
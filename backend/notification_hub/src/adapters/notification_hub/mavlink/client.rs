@@ -0,0 +1,585 @@
+use async_trait::async_trait;
+use log::{debug, error, info, warn};
+use serialport::SerialPort;
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf};
+use tokio::net::UdpSocket;
+use tokio::sync::{broadcast, Mutex, RwLock};
+use tokio_serial::{DataBits, Parity, SerialPortBuilderExt, SerialStream, StopBits};
+
+use super::frame::{FrameParser, MavFrame};
+use super::messages::{MavMessage, MAV_AUTOPILOT_INVALID};
+use super::options::MavlinkOptions;
+use crate::adapters::lease::LeaseSlot;
+use crate::models::hub::{HubChannelName, HubData, HubMessage};
+use crate::ports::{NodeTasks, NotificationHub};
+
+const MAX_COMMAND_PARAMS: usize = 7;
+// Larger than the largest MAVLink 2 frame
+const READ_BUFFER_SIZE: usize = 1024;
+
+// Connection to the autopilot. Over UDP, the remote address is learned from the first
+// datagram received unless configured.
+#[derive(Debug)]
+enum Link {
+    Serial {
+        reader: LeaseSlot<ReadHalf<SerialStream>>,
+        writer: Mutex<WriteHalf<SerialStream>>,
+    },
+    Udp {
+        socket: Arc<UdpSocket>,
+        remote: Arc<RwLock<Option<SocketAddr>>>,
+    },
+}
+
+impl Link {
+    async fn write(&self, frame: &[u8]) -> Result<(), std::io::Error> {
+        match self {
+            Self::Serial { writer, .. } => writer.lock().await.write_all(frame).await,
+            Self::Udp { socket, remote } => {
+                let remote = remote.read().await.ok_or_else(|| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::NotConnected,
+                        "No MAVLink datagram received yet",
+                    )
+                })?;
+                socket.send_to(frame, remote).await.map(|_| ())
+            }
+        }
+    }
+}
+
+/// `MavlinkClient` is a hub node bridging the hub with ArduPilot and PX4 autopilots over
+/// MAVLink, through a serial telemetry port or UDP.
+///
+/// Attitude (`ATTITUDE`), global position (`GLOBAL_POSITION_INT`) and battery status
+/// (`SYS_STATUS`) messages of the autopilot are published on the telemetry channels set in
+/// `MavlinkOptions`, with comma separated data:
+/// - attitude: `roll,pitch,yaw,rollspeed,pitchspeed,yawspeed` in rad and rad/s.
+/// - gps: `lat,lon,alt,relative_alt` in degrees and meters.
+/// - battery: `voltage,current,remaining` in V, A and %, -1 if unknown.
+///
+/// Messages of the command channels are sent to the autopilot as `COMMAND_LONG`, with the
+/// message data as comma separated parameters. The hub announces itself as a ground control
+/// station with periodic heartbeats, which autopilots expect before streaming telemetry.
+///
+/// # Fields
+/// - `options`: Ids, channels and commands.
+/// - `link`: Serial port or UDP socket.
+/// - `sequence`: Sequence number of the next frame sent.
+/// - `target`: System and component ids of the autopilot receiving commands.
+/// - `channels`: Telemetry channels received so far.
+/// - `running`: Leased by the tasks of the client while it is started.
+#[derive(Debug)]
+pub struct MavlinkClient {
+    options: Arc<MavlinkOptions>,
+    link: Arc<Link>,
+    sequence: Arc<AtomicU8>,
+    target: Arc<RwLock<Option<(u8, u8)>>>,
+    channels: Arc<RwLock<HashSet<HubChannelName>>>,
+    running: LeaseSlot<()>,
+}
+
+impl MavlinkClient {
+    /// Client connected to an autopilot telemetry port
+    pub fn serial(
+        port: &str,
+        baud_rate: u32,
+        options: MavlinkOptions,
+    ) -> Result<Self, std::io::Error> {
+        info!("Opening MAVLink port {} with params {}...", port, baud_rate);
+        let mut port = tokio_serial::new(port, baud_rate)
+            .open_native_async()
+            .inspect_err(|_| {
+                error!("MAVLink port {} not ready", port);
+            })?;
+        port.set_parity(Parity::None)?;
+        port.set_stop_bits(StopBits::One)?;
+        port.set_data_bits(DataBits::Eight)?;
+        let (reader, writer) = tokio::io::split(port);
+        let link = Link::Serial {
+            reader: LeaseSlot::new(reader),
+            writer: Mutex::new(writer),
+        };
+        Ok(Self::with_link(link, options))
+    }
+
+    /// Client bound to `local` UDP address, exchanging datagrams with `remote`. Without a
+    /// remote address, the client replies to the sender of the first datagram received, as
+    /// ground control stations listening on port 14550 do.
+    pub fn udp(
+        local: SocketAddr,
+        remote: Option<SocketAddr>,
+        options: MavlinkOptions,
+    ) -> Result<Self, std::io::Error> {
+        let socket = std::net::UdpSocket::bind(local)?;
+        socket.set_nonblocking(true)?;
+        let socket = UdpSocket::from_std(socket)?;
+        info!("MAVLink bound to {}", socket.local_addr()?);
+        let link = Link::Udp {
+            socket: Arc::new(socket),
+            remote: Arc::new(RwLock::new(remote)),
+        };
+        Ok(Self::with_link(link, options))
+    }
+
+    fn with_link(link: Link, options: MavlinkOptions) -> Self {
+        let target = options.target();
+        Self {
+            options: Arc::new(options),
+            link: Arc::new(link),
+            sequence: Arc::new(AtomicU8::new(0)),
+            target: Arc::new(RwLock::new(target)),
+            channels: Arc::new(RwLock::new(HashSet::new())),
+            running: LeaseSlot::new(()),
+        }
+    }
+
+    pub fn options(&self) -> &MavlinkOptions {
+        &self.options
+    }
+
+    /// Local address of the UDP socket. `None` over a serial port.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        match self.link.as_ref() {
+            Link::Serial { .. } => None,
+            Link::Udp { socket, .. } => socket.local_addr().ok(),
+        }
+    }
+
+    /// System and component ids of the autopilot receiving commands, once known
+    pub async fn target(&self) -> Option<(u8, u8)> {
+        *self.target.read().await
+    }
+}
+
+async fn write_message(
+    link: &Link,
+    sequence: &AtomicU8,
+    options: &MavlinkOptions,
+    message: &MavMessage,
+) -> Result<(), std::io::Error> {
+    let frame = MavFrame {
+        sequence: sequence.fetch_add(1, Ordering::Relaxed),
+        system_id: options.system_id(),
+        component_id: options.component_id(),
+        message_id: message.id(),
+        payload: message.encode(),
+    }
+    .encode()
+    .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    link.write(&frame).await
+}
+
+fn command_params(data: &str) -> Result<[f32; MAX_COMMAND_PARAMS], String> {
+    let mut params = [0.0; MAX_COMMAND_PARAMS];
+    if data.is_empty() {
+        return Ok(params);
+    }
+    let values: Vec<&str> = data.split(',').collect();
+    if values.len() > MAX_COMMAND_PARAMS {
+        return Err(format!(
+            "MAVLink commands take at most {} parameters",
+            MAX_COMMAND_PARAMS
+        ));
+    }
+    for (param, value) in params.iter_mut().zip(values) {
+        *param = value
+            .trim()
+            .parse()
+            .map_err(|_| format!("Invalid MAVLink command parameter {}", value))?;
+    }
+    Ok(params)
+}
+
+// Telemetry channel and data of a message received from the autopilot
+fn telemetry(options: &MavlinkOptions, message: &MavMessage) -> Option<(HubChannelName, String)> {
+    match *message {
+        MavMessage::Attitude {
+            roll,
+            pitch,
+            yaw,
+            rollspeed,
+            pitchspeed,
+            yawspeed,
+            ..
+        } => Some((
+            options.attitude_channel()?.clone(),
+            format!(
+                "{},{},{},{},{},{}",
+                roll, pitch, yaw, rollspeed, pitchspeed, yawspeed
+            ),
+        )),
+        MavMessage::GlobalPositionInt {
+            lat,
+            lon,
+            alt,
+            relative_alt,
+            ..
+        } => Some((
+            options.gps_channel()?.clone(),
+            format!(
+                "{:.7},{:.7},{:.3},{:.3}",
+                lat as f64 / 1e7,
+                lon as f64 / 1e7,
+                alt as f64 / 1000.0,
+                relative_alt as f64 / 1000.0
+            ),
+        )),
+        MavMessage::SysStatus {
+            voltage_battery,
+            current_battery,
+            battery_remaining,
+        } => {
+            let current = if current_battery == -1 {
+                -1.0
+            } else {
+                current_battery as f64 / 100.0
+            };
+            Some((
+                options.battery_channel()?.clone(),
+                format!(
+                    "{:.3},{:.2},{}",
+                    voltage_battery as f64 / 1000.0,
+                    current,
+                    battery_remaining
+                ),
+            ))
+        }
+        _ => None,
+    }
+}
+
+// Handles the frames received from the autopilot
+#[derive(Debug, Clone)]
+struct Inbound {
+    options: Arc<MavlinkOptions>,
+    target: Arc<RwLock<Option<(u8, u8)>>>,
+    channels: Arc<RwLock<HashSet<HubChannelName>>>,
+    sender: broadcast::Sender<HubMessage>,
+}
+
+impl Inbound {
+    async fn handle(&self, parser: &mut FrameParser) {
+        while let Some(frame) = parser.next_frame() {
+            let frame = match frame {
+                Ok(frame) => frame,
+                Err(e) => {
+                    warn!("{}", e);
+                    continue;
+                }
+            };
+            let message = match MavMessage::decode(frame.message_id, &frame.payload) {
+                Ok(message) => message,
+                Err(e) => {
+                    debug!("{}", e);
+                    continue;
+                }
+            };
+            if let MavMessage::Heartbeat { autopilot, .. } = message {
+                let mut target = self.target.write().await;
+                if target.is_none() && autopilot != MAV_AUTOPILOT_INVALID {
+                    info!(
+                        "MAVLink autopilot found at system {} component {}",
+                        frame.system_id, frame.component_id
+                    );
+                    *target = Some((frame.system_id, frame.component_id));
+                }
+                continue;
+            }
+            let Some((channel, data)) = telemetry(&self.options, &message) else {
+                continue;
+            };
+            match data.parse::<HubData>() {
+                Ok(data) => {
+                    self.channels.write().await.insert(channel.clone());
+                    let _ = self.sender.send(HubMessage::new(channel, data));
+                }
+                Err(e) => error!("MAVLink telemetry error {:?}", e),
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl NotificationHub for MavlinkClient {
+    /// Send message of a command channel to the autopilot. Messages of other channels are
+    /// ignored.
+    async fn send(&self, data: HubMessage) -> Result<(), std::io::Error> {
+        let Some(command) = self.options.command(&data.channel) else {
+            return Ok(());
+        };
+        let (target_system, target_component) = self.target().await.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotConnected,
+                "No MAVLink autopilot heartbeat received yet",
+            )
+        })?;
+        let params = command_params(data.data.as_str())
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+        let message = MavMessage::CommandLong {
+            target_system,
+            target_component,
+            command,
+            confirmation: 0,
+            params,
+        };
+        write_message(&self.link, &self.sequence, &self.options, &message).await
+    }
+
+    /// List telemetry channels received so far
+    async fn list_channels(&self) -> Result<Vec<HubChannelName>, std::io::Error> {
+        Ok(self.channels.read().await.iter().cloned().collect())
+    }
+
+    /// Start heartbeat and receive loops
+    async fn start(
+        &self,
+        sender: Option<broadcast::Sender<HubMessage>>,
+    ) -> Result<NodeTasks, std::io::Error> {
+        let running = Arc::new(self.running.lease("MAVLink client")?);
+        let mut tasks = NodeTasks::new();
+        if let Some(interval) = self.options.heartbeat_interval() {
+            let link = Arc::clone(&self.link);
+            let sequence = Arc::clone(&self.sequence);
+            let options = Arc::clone(&self.options);
+            let running = Arc::clone(&running);
+            let task = tokio::spawn(async move {
+                let _running = running;
+                let mut ticker = tokio::time::interval(interval);
+                loop {
+                    ticker.tick().await;
+                    let heartbeat = MavMessage::gcs_heartbeat();
+                    if let Err(e) = write_message(&link, &sequence, &options, &heartbeat).await {
+                        debug!("MAVLink heartbeat not sent: {}", e);
+                    }
+                }
+            });
+            tasks.push(task);
+        }
+
+        if let Some(sender) = sender {
+            let inbound = Inbound {
+                options: Arc::clone(&self.options),
+                target: Arc::clone(&self.target),
+                channels: Arc::clone(&self.channels),
+                sender,
+            };
+            let task = match self.link.as_ref() {
+                Link::Serial { reader, .. } => {
+                    let mut reader = reader.lease("MAVLink client")?;
+                    tokio::spawn(async move {
+                        let _running = running;
+                        let mut parser = FrameParser::new();
+                        let mut buffer = [0u8; READ_BUFFER_SIZE];
+                        loop {
+                            match reader.read(&mut buffer).await {
+                                Ok(n) if n > 0 => parser.push(&buffer[..n]),
+                                Ok(_) => {
+                                    tokio::time::sleep(tokio::time::Duration::from_millis(100))
+                                        .await;
+                                    continue;
+                                }
+                                Err(e) => {
+                                    error!("MAVLink port error {:?}", e);
+                                    break;
+                                }
+                            }
+                            inbound.handle(&mut parser).await;
+                        }
+                    })
+                }
+                Link::Udp { socket, remote } => {
+                    let socket = Arc::clone(socket);
+                    let remote = Arc::clone(remote);
+                    tokio::spawn(async move {
+                        let _running = running;
+                        let mut parser = FrameParser::new();
+                        let mut buffer = [0u8; READ_BUFFER_SIZE];
+                        loop {
+                            match socket.recv_from(&mut buffer).await {
+                                Ok((n, from)) => {
+                                    let mut remote = remote.write().await;
+                                    if remote.is_none() {
+                                        *remote = Some(from);
+                                    }
+                                    parser.push(&buffer[..n]);
+                                }
+                                Err(e) => {
+                                    error!("MAVLink socket error {:?}", e);
+                                    break;
+                                }
+                            }
+                            inbound.handle(&mut parser).await;
+                        }
+                    })
+                }
+            };
+            tasks.push(task);
+        }
+        Ok(tasks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::mavlink::messages::{COMMAND_LONG, MAV_CMD_COMPONENT_ARM_DISARM};
+    use crate::adapters::mavlink::MavlinkOptionsBuilder;
+    use tokio::time::{timeout, Duration};
+
+    // Frames of an ArduCopter autopilot, system 1 component 1
+    fn autopilot_frames() -> Vec<u8> {
+        let messages = [
+            MavMessage::Heartbeat {
+                mav_type: 2,
+                autopilot: 3,
+                base_mode: 0,
+                custom_mode: 0,
+                system_status: 4,
+            },
+            MavMessage::Attitude {
+                time_boot_ms: 1000,
+                roll: 0.1,
+                pitch: -0.2,
+                yaw: 1.5,
+                rollspeed: 0.0,
+                pitchspeed: 0.0,
+                yawspeed: 0.0,
+            },
+            MavMessage::GlobalPositionInt {
+                time_boot_ms: 1000,
+                lat: 473_977_419,
+                lon: 85_455_938,
+                alt: 488_000,
+                relative_alt: 10_000,
+                vx: 0,
+                vy: 0,
+                vz: 0,
+                hdg: 9000,
+            },
+            MavMessage::SysStatus {
+                voltage_battery: 12600,
+                current_battery: 1520,
+                battery_remaining: 87,
+            },
+        ];
+        let mut frames = Vec::new();
+        for (sequence, message) in messages.iter().enumerate() {
+            let frame = MavFrame {
+                sequence: sequence as u8,
+                system_id: 1,
+                component_id: 1,
+                message_id: message.id(),
+                payload: message.encode(),
+            };
+            frames.extend_from_slice(&frame.encode().unwrap());
+        }
+        frames
+    }
+
+    async fn recv_message(socket: &UdpSocket) -> MavMessage {
+        let mut buffer = [0u8; READ_BUFFER_SIZE];
+        let (n, _) = timeout(Duration::from_secs(1), socket.recv_from(&mut buffer))
+            .await
+            .unwrap()
+            .unwrap();
+        let mut parser = FrameParser::new();
+        parser.push(&buffer[..n]);
+        let frame = parser.next_frame().unwrap().unwrap();
+        MavMessage::decode(frame.message_id, &frame.payload).unwrap()
+    }
+
+    #[test]
+    fn test_command_params() {
+        assert_eq!(command_params("").unwrap(), [0.0; 7]);
+        assert_eq!(
+            command_params("1, 2.5").unwrap(),
+            [1.0, 2.5, 0.0, 0.0, 0.0, 0.0, 0.0]
+        );
+        assert!(command_params("1,2,3,4,5,6,7,8").is_err());
+        assert!(command_params("arm").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_udp_autopilot() {
+        let autopilot = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let options = MavlinkOptionsBuilder::new()
+            .command("arm", MAV_CMD_COMPONENT_ARM_DISARM)
+            .heartbeat_interval(Some(Duration::from_secs(60)))
+            .build()
+            .unwrap();
+        let client = MavlinkClient::udp(
+            "127.0.0.1:0".parse().unwrap(),
+            Some(autopilot.local_addr().unwrap()),
+            options,
+        )
+        .unwrap();
+        let (sender, mut receiver) = broadcast::channel(10);
+        client.start(Some(sender)).await.unwrap();
+        assert!(client.start(None).await.is_err());
+
+        // the hub announces itself first
+        assert_eq!(recv_message(&autopilot).await, MavMessage::gcs_heartbeat());
+        let arm = HubMessage::try_from_str("arm", "1").unwrap();
+        assert!(client.send(arm.clone()).await.is_err());
+
+        autopilot
+            .send_to(&autopilot_frames(), client.local_addr().unwrap())
+            .await
+            .unwrap();
+        for (channel, data) in [
+            ("attitude", "0.1,-0.2,1.5,0,0,0"),
+            ("gps", "47.3977419,8.5455938,488.000,10.000"),
+            ("battery", "12.600,15.20,87"),
+        ] {
+            let message = timeout(Duration::from_secs(1), receiver.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(message.channel.as_str(), channel);
+            assert_eq!(message.data.as_str(), data);
+        }
+        assert_eq!(client.target().await, Some((1, 1)));
+        assert_eq!(client.list_channels().await.unwrap().len(), 3);
+
+        // channels without a command are not sent
+        client
+            .send(HubMessage::try_from_str("status", "ok").unwrap())
+            .await
+            .unwrap();
+        client.send(arm).await.unwrap();
+        let command = recv_message(&autopilot).await;
+        assert_eq!(command.id(), COMMAND_LONG);
+        assert_eq!(
+            command,
+            MavMessage::CommandLong {
+                target_system: 1,
+                target_component: 1,
+                command: MAV_CMD_COMPONENT_ARM_DISARM,
+                confirmation: 0,
+                params: [1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            }
+        );
+    }
+
+    // Requires an autopilot on /dev/ttyACM0, e.g. a Pixhawk connected over USB
+    #[tokio::test]
+    #[ignore]
+    async fn test_serial_autopilot() {
+        let options = MavlinkOptionsBuilder::new().build().unwrap();
+        let client = MavlinkClient::serial("/dev/ttyACM0", 115200, options).unwrap();
+        let (sender, _receiver) = broadcast::channel(10);
+        client.start(Some(sender)).await.unwrap();
+        timeout(Duration::from_secs(5), async {
+            while client.target().await.is_none() {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+}
@@ -0,0 +1,280 @@
+use super::messages::crc_extra;
+
+/// Start of a MAVLink 1 frame
+pub const MAVLINK_V1_STX: u8 = 0xfe;
+/// Start of a MAVLink 2 frame
+pub const MAVLINK_V2_STX: u8 = 0xfd;
+
+// magic, length, sequence, system id, component id, message id
+const V1_HEADER_LEN: usize = 6;
+// magic, length, incompat flags, compat flags, sequence, system id, component id, message id
+const V2_HEADER_LEN: usize = 10;
+const CHECKSUM_LEN: usize = 2;
+const SIGNATURE_LEN: usize = 13;
+const INCOMPAT_FLAG_SIGNED: u8 = 0x01;
+
+/// MAVLink frame, with the payload of its message still encoded.
+///
+/// # Fields
+/// - `sequence`: Sequence number of the sender, to detect lost frames.
+/// - `system_id`: Id of the sender system (e.g. the vehicle).
+/// - `component_id`: Id of the sender component within the system (e.g. the autopilot).
+/// - `message_id`: Id of the message carried in `payload`.
+/// - `payload`: Encoded message. MAVLink 2 payloads may be truncated of trailing zero bytes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MavFrame {
+    pub sequence: u8,
+    pub system_id: u8,
+    pub component_id: u8,
+    pub message_id: u32,
+    pub payload: Vec<u8>,
+}
+
+// CRC-16/MCRF4XX, as computed by MAVLink
+fn crc_accumulate(bytes: &[u8], mut crc: u16) -> u16 {
+    for byte in bytes {
+        let mut tmp = byte ^ (crc as u8);
+        tmp ^= tmp << 4;
+        let tmp = tmp as u16;
+        crc = (crc >> 8) ^ (tmp << 8) ^ (tmp << 3) ^ (tmp >> 4);
+    }
+    crc
+}
+
+// Checksum of the frame without magic byte, seeded with the CRC extra of the message
+fn checksum(frame: &[u8], crc_extra: u8) -> u16 {
+    crc_accumulate(&[crc_extra], crc_accumulate(frame, 0xffff))
+}
+
+impl MavFrame {
+    /// Encodes frame as MAVLink 2, truncating trailing zero bytes of the payload
+    pub fn encode(&self) -> Result<Vec<u8>, String> {
+        let crc_extra = crc_extra(self.message_id)
+            .ok_or_else(|| format!("Unsupported MAVLink message {}", self.message_id))?;
+        let len = self
+            .payload
+            .iter()
+            .rposition(|byte| *byte != 0)
+            // an all zero payload is truncated to a single byte
+            .map_or(1, |last| last + 1);
+        if len > u8::MAX as usize {
+            return Err("MAVLink payload too long".to_string());
+        }
+        let mut frame = Vec::with_capacity(V2_HEADER_LEN + len + CHECKSUM_LEN);
+        frame.extend_from_slice(&[
+            MAVLINK_V2_STX,
+            len as u8,
+            0,
+            0,
+            self.sequence,
+            self.system_id,
+            self.component_id,
+        ]);
+        frame.extend_from_slice(&self.message_id.to_le_bytes()[..3]);
+        frame.extend_from_slice(&self.payload[..len.min(self.payload.len())]);
+        frame.resize(V2_HEADER_LEN + len, 0);
+        let crc = checksum(&frame[1..], crc_extra);
+        frame.extend_from_slice(&crc.to_le_bytes());
+        Ok(frame)
+    }
+}
+
+/// Extracts MAVLink 1 and MAVLink 2 frames from a byte stream. Bytes preceding a start
+/// marker are discarded, so that the parser recovers from corrupted frames.
+///
+/// Frames of messages without a known CRC extra (see `messages`) can't be validated, and
+/// are skipped whole. Signatures of signed frames are not verified.
+#[derive(Debug, Default)]
+pub struct FrameParser {
+    buffer: Vec<u8>,
+}
+
+impl FrameParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Next frame of a supported message, or `None` until a full frame is buffered
+    pub fn next_frame(&mut self) -> Option<Result<MavFrame, String>> {
+        loop {
+            let start = match self
+                .buffer
+                .iter()
+                .position(|byte| *byte == MAVLINK_V1_STX || *byte == MAVLINK_V2_STX)
+            {
+                Some(start) => start,
+                None => {
+                    self.buffer.clear();
+                    return None;
+                }
+            };
+            self.buffer.drain(..start);
+
+            let v2 = self.buffer[0] == MAVLINK_V2_STX;
+            let header_len = if v2 { V2_HEADER_LEN } else { V1_HEADER_LEN };
+            if self.buffer.len() < header_len {
+                return None;
+            }
+            let header = &self.buffer[..header_len];
+            let (sequence, system_id, component_id, message_id) = if v2 {
+                let message_id = u32::from_le_bytes([header[7], header[8], header[9], 0]);
+                (header[4], header[5], header[6], message_id)
+            } else {
+                (header[2], header[3], header[4], header[5] as u32)
+            };
+            // the checksum of unsupported messages can't be verified, so their length can't be
+            // trusted to skip them: drop start marker, and look for the next one
+            let Some(crc_extra) = crc_extra(message_id) else {
+                self.buffer.drain(..1);
+                continue;
+            };
+            let payload_len = self.buffer[1] as usize;
+            let signed = v2 && self.buffer[2] & INCOMPAT_FLAG_SIGNED != 0;
+            let crc_offset = header_len + payload_len;
+            let frame_len = crc_offset + CHECKSUM_LEN + if signed { SIGNATURE_LEN } else { 0 };
+            if self.buffer.len() < frame_len {
+                return None;
+            }
+
+            let crc = u16::from_le_bytes([self.buffer[crc_offset], self.buffer[crc_offset + 1]]);
+            if checksum(&self.buffer[1..crc_offset], crc_extra) != crc {
+                // drop start marker, and look for the next one
+                self.buffer.drain(..1);
+                return Some(Err("Invalid MAVLink frame checksum".to_string()));
+            }
+            let frame = MavFrame {
+                sequence,
+                system_id,
+                component_id,
+                message_id,
+                payload: self.buffer[header_len..crc_offset].to_vec(),
+            };
+            self.buffer.drain(..frame_len);
+            return Some(Ok(frame));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::mavlink::messages::{ATTITUDE, HEARTBEAT};
+
+    fn heartbeat() -> MavFrame {
+        MavFrame {
+            sequence: 0,
+            system_id: 255,
+            component_id: 190,
+            message_id: HEARTBEAT,
+            payload: vec![0, 0, 0, 0, 6, 8, 0, 4, 3],
+        }
+    }
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(
+            heartbeat().encode().unwrap(),
+            vec![
+                0xfd, 0x09, 0x00, 0x00, 0x00, 0xff, 0xbe, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x06, 0x08, 0x00, 0x04, 0x03, 0x3d, 0x48
+            ]
+        );
+
+        // trailing zero bytes are truncated
+        let mut frame = heartbeat();
+        frame.payload[8] = 0;
+        frame.payload[7] = 0;
+        let encoded = frame.encode().unwrap();
+        assert_eq!(encoded[1], 6);
+        let mut parser = FrameParser::new();
+        parser.push(&encoded);
+        let decoded = parser.next_frame().unwrap().unwrap();
+        assert_eq!(decoded.payload, &frame.payload[..6]);
+
+        frame.message_id = 0xabcd;
+        assert!(frame.encode().is_err());
+    }
+
+    #[test]
+    fn test_parse() {
+        // MAVLink 1 attitude of system 1, preceded by garbage
+        let mut bytes = vec![0x00, 0x42, MAVLINK_V1_STX, 28, 7, 1, 1, ATTITUDE as u8];
+        bytes.extend_from_slice(&1000u32.to_le_bytes());
+        for value in [0.1f32, -0.2, 1.5, 0.0, 0.0, 0.0] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        let crc = checksum(&bytes[3..], crc_extra(ATTITUDE).unwrap());
+        bytes.extend_from_slice(&crc.to_le_bytes());
+
+        let mut parser = FrameParser::new();
+        parser.push(&bytes[..12]);
+        assert!(parser.next_frame().is_none());
+        parser.push(&bytes[12..]);
+        let frame = parser.next_frame().unwrap().unwrap();
+        assert_eq!(
+            (frame.sequence, frame.system_id, frame.message_id),
+            (7, 1, ATTITUDE)
+        );
+        assert_eq!(frame.payload.len(), 28);
+        assert!(parser.next_frame().is_none());
+
+        // corrupted frame followed by a valid one
+        let mut corrupted = bytes.clone();
+        corrupted[10] ^= 0xff;
+        parser.push(&corrupted);
+        parser.push(&heartbeat().encode().unwrap());
+        assert!(parser.next_frame().unwrap().is_err());
+        assert_eq!(parser.next_frame().unwrap().unwrap(), heartbeat());
+    }
+
+    #[test]
+    fn test_skip_unsupported_and_signed() {
+        let mut parser = FrameParser::new();
+        // unsupported message id 0x0102, with a valid layout
+        parser.push(&[
+            MAVLINK_V2_STX,
+            2,
+            0,
+            0,
+            0,
+            1,
+            1,
+            0x02,
+            0x01,
+            0x00,
+            0xaa,
+            0xbb,
+            0x00,
+            0x00,
+        ]);
+        // signed heartbeat
+        let mut signed = heartbeat().encode().unwrap();
+        signed[2] = INCOMPAT_FLAG_SIGNED;
+        let crc_offset = signed.len() - CHECKSUM_LEN;
+        let crc = checksum(&signed[1..crc_offset], crc_extra(HEARTBEAT).unwrap());
+        signed.truncate(crc_offset);
+        signed.extend_from_slice(&crc.to_le_bytes());
+        signed.extend_from_slice(&[0x11; SIGNATURE_LEN]);
+        parser.push(&signed);
+        parser.push(&heartbeat().encode().unwrap());
+
+        assert_eq!(parser.next_frame().unwrap().unwrap(), heartbeat());
+        assert_eq!(parser.next_frame().unwrap().unwrap(), heartbeat());
+        assert!(parser.next_frame().is_none());
+    }
+
+    #[test]
+    fn test_resync_after_unsupported_header() {
+        let mut parser = FrameParser::new();
+        // header of an unsupported message, whose payload was lost
+        parser.push(&[MAVLINK_V2_STX, 9, 0, 0, 0, 1, 1, 0x02, 0x01, 0x00]);
+        parser.push(&heartbeat().encode().unwrap());
+
+        assert_eq!(parser.next_frame().unwrap().unwrap(), heartbeat());
+        assert!(parser.next_frame().is_none());
+    }
+}
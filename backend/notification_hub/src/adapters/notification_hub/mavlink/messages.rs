@@ -0,0 +1,386 @@
+//! Subset of the MAVLink common message set used by `MavlinkClient`. Payload fields are
+//! little endian, and ordered by decreasing size as defined by the MAVLink serialization.
+
+pub const HEARTBEAT: u32 = 0;
+pub const SYS_STATUS: u32 = 1;
+pub const ATTITUDE: u32 = 30;
+pub const GLOBAL_POSITION_INT: u32 = 33;
+pub const COMMAND_LONG: u32 = 76;
+
+pub const MAV_CMD_NAV_RETURN_TO_LAUNCH: u16 = 20;
+pub const MAV_CMD_NAV_LAND: u16 = 21;
+pub const MAV_CMD_NAV_TAKEOFF: u16 = 22;
+pub const MAV_CMD_DO_SET_MODE: u16 = 176;
+pub const MAV_CMD_DO_CHANGE_SPEED: u16 = 178;
+pub const MAV_CMD_COMPONENT_ARM_DISARM: u16 = 400;
+
+/// `MAV_TYPE_GCS`, sent in the heartbeats of ground control stations
+pub const MAV_TYPE_GCS: u8 = 6;
+/// `MAV_AUTOPILOT_INVALID`, sent in the heartbeats of components that are not autopilots
+pub const MAV_AUTOPILOT_INVALID: u8 = 8;
+const MAV_STATE_ACTIVE: u8 = 4;
+const MAVLINK_VERSION: u8 = 3;
+
+/// CRC extra of a supported message, derived from its definition. Frames are only valid if
+/// both ends agree on it.
+pub fn crc_extra(message_id: u32) -> Option<u8> {
+    match message_id {
+        HEARTBEAT => Some(50),
+        SYS_STATUS => Some(124),
+        ATTITUDE => Some(39),
+        GLOBAL_POSITION_INT => Some(104),
+        COMMAND_LONG => Some(152),
+        _ => None,
+    }
+}
+
+/// Decoded MAVLink message. Only the fields used by the hub are kept.
+///
+/// - `Heartbeat`: Presence of a system. Autopilots report their vehicle `mav_type`.
+/// - `SysStatus`: Battery voltage in mV, current in cA (-1 if unknown) and remaining capacity
+///   in % (-1 if unknown).
+/// - `Attitude`: Roll, pitch and yaw in rad, and their rates in rad/s.
+/// - `GlobalPositionInt`: Latitude and longitude in degE7, altitude above mean sea level and
+///   above home in mm, ground speed in cm/s and heading in cdeg (`u16::MAX` if unknown).
+/// - `CommandLong`: Command with up to seven parameters, for the component
+///   `target_component` of system `target_system`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MavMessage {
+    Heartbeat {
+        mav_type: u8,
+        autopilot: u8,
+        base_mode: u8,
+        custom_mode: u32,
+        system_status: u8,
+    },
+    SysStatus {
+        voltage_battery: u16,
+        current_battery: i16,
+        battery_remaining: i8,
+    },
+    Attitude {
+        time_boot_ms: u32,
+        roll: f32,
+        pitch: f32,
+        yaw: f32,
+        rollspeed: f32,
+        pitchspeed: f32,
+        yawspeed: f32,
+    },
+    GlobalPositionInt {
+        time_boot_ms: u32,
+        lat: i32,
+        lon: i32,
+        alt: i32,
+        relative_alt: i32,
+        vx: i16,
+        vy: i16,
+        vz: i16,
+        hdg: u16,
+    },
+    CommandLong {
+        target_system: u8,
+        target_component: u8,
+        command: u16,
+        confirmation: u8,
+        params: [f32; 7],
+    },
+}
+
+// Reads the fields of a payload, restoring the trailing zero bytes truncated by MAVLink 2
+struct PayloadReader {
+    bytes: Vec<u8>,
+    position: usize,
+}
+
+impl PayloadReader {
+    fn new(payload: &[u8], len: usize) -> Self {
+        let mut bytes = payload.to_vec();
+        if bytes.len() < len {
+            bytes.resize(len, 0);
+        }
+        Self { bytes, position: 0 }
+    }
+
+    fn take<const N: usize>(&mut self) -> [u8; N] {
+        let mut field = [0; N];
+        field.copy_from_slice(&self.bytes[self.position..self.position + N]);
+        self.position += N;
+        field
+    }
+
+    fn skip(&mut self, n: usize) {
+        self.position += n;
+    }
+
+    fn u8(&mut self) -> u8 {
+        self.take::<1>()[0]
+    }
+    fn i8(&mut self) -> i8 {
+        i8::from_le_bytes(self.take())
+    }
+    fn u16(&mut self) -> u16 {
+        u16::from_le_bytes(self.take())
+    }
+    fn i16(&mut self) -> i16 {
+        i16::from_le_bytes(self.take())
+    }
+    fn u32(&mut self) -> u32 {
+        u32::from_le_bytes(self.take())
+    }
+    fn i32(&mut self) -> i32 {
+        i32::from_le_bytes(self.take())
+    }
+    fn f32(&mut self) -> f32 {
+        f32::from_le_bytes(self.take())
+    }
+}
+
+impl MavMessage {
+    pub fn id(&self) -> u32 {
+        match self {
+            Self::Heartbeat { .. } => HEARTBEAT,
+            Self::SysStatus { .. } => SYS_STATUS,
+            Self::Attitude { .. } => ATTITUDE,
+            Self::GlobalPositionInt { .. } => GLOBAL_POSITION_INT,
+            Self::CommandLong { .. } => COMMAND_LONG,
+        }
+    }
+
+    /// Heartbeat identifying the hub as a ground control station
+    pub fn gcs_heartbeat() -> Self {
+        Self::Heartbeat {
+            mav_type: MAV_TYPE_GCS,
+            autopilot: MAV_AUTOPILOT_INVALID,
+            base_mode: 0,
+            custom_mode: 0,
+            system_status: MAV_STATE_ACTIVE,
+        }
+    }
+
+    /// Decodes the payload of message `message_id`
+    pub fn decode(message_id: u32, payload: &[u8]) -> Result<Self, String> {
+        match message_id {
+            HEARTBEAT => {
+                let mut reader = PayloadReader::new(payload, 9);
+                let custom_mode = reader.u32();
+                Ok(Self::Heartbeat {
+                    custom_mode,
+                    mav_type: reader.u8(),
+                    autopilot: reader.u8(),
+                    base_mode: reader.u8(),
+                    system_status: reader.u8(),
+                })
+            }
+            SYS_STATUS => {
+                let mut reader = PayloadReader::new(payload, 31);
+                // sensor bitmasks and load
+                reader.skip(14);
+                let voltage_battery = reader.u16();
+                let current_battery = reader.i16();
+                // link and error counters
+                reader.skip(12);
+                Ok(Self::SysStatus {
+                    voltage_battery,
+                    current_battery,
+                    battery_remaining: reader.i8(),
+                })
+            }
+            ATTITUDE => {
+                let mut reader = PayloadReader::new(payload, 28);
+                Ok(Self::Attitude {
+                    time_boot_ms: reader.u32(),
+                    roll: reader.f32(),
+                    pitch: reader.f32(),
+                    yaw: reader.f32(),
+                    rollspeed: reader.f32(),
+                    pitchspeed: reader.f32(),
+                    yawspeed: reader.f32(),
+                })
+            }
+            GLOBAL_POSITION_INT => {
+                let mut reader = PayloadReader::new(payload, 28);
+                Ok(Self::GlobalPositionInt {
+                    time_boot_ms: reader.u32(),
+                    lat: reader.i32(),
+                    lon: reader.i32(),
+                    alt: reader.i32(),
+                    relative_alt: reader.i32(),
+                    vx: reader.i16(),
+                    vy: reader.i16(),
+                    vz: reader.i16(),
+                    hdg: reader.u16(),
+                })
+            }
+            COMMAND_LONG => {
+                let mut reader = PayloadReader::new(payload, 33);
+                let mut params = [0.0; 7];
+                for param in params.iter_mut() {
+                    *param = reader.f32();
+                }
+                Ok(Self::CommandLong {
+                    params,
+                    command: reader.u16(),
+                    target_system: reader.u8(),
+                    target_component: reader.u8(),
+                    confirmation: reader.u8(),
+                })
+            }
+            message_id => Err(format!("Unsupported MAVLink message {}", message_id)),
+        }
+    }
+
+    /// Encodes the message payload, with the fields not kept set to zero
+    pub fn encode(&self) -> Vec<u8> {
+        let mut payload = Vec::new();
+        match self {
+            Self::Heartbeat {
+                mav_type,
+                autopilot,
+                base_mode,
+                custom_mode,
+                system_status,
+            } => {
+                payload.extend_from_slice(&custom_mode.to_le_bytes());
+                payload.extend_from_slice(&[
+                    *mav_type,
+                    *autopilot,
+                    *base_mode,
+                    *system_status,
+                    MAVLINK_VERSION,
+                ]);
+            }
+            Self::SysStatus {
+                voltage_battery,
+                current_battery,
+                battery_remaining,
+            } => {
+                payload.resize(14, 0);
+                payload.extend_from_slice(&voltage_battery.to_le_bytes());
+                payload.extend_from_slice(&current_battery.to_le_bytes());
+                payload.resize(30, 0);
+                payload.extend_from_slice(&battery_remaining.to_le_bytes());
+            }
+            Self::Attitude {
+                time_boot_ms,
+                roll,
+                pitch,
+                yaw,
+                rollspeed,
+                pitchspeed,
+                yawspeed,
+            } => {
+                payload.extend_from_slice(&time_boot_ms.to_le_bytes());
+                for value in [roll, pitch, yaw, rollspeed, pitchspeed, yawspeed] {
+                    payload.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+            Self::GlobalPositionInt {
+                time_boot_ms,
+                lat,
+                lon,
+                alt,
+                relative_alt,
+                vx,
+                vy,
+                vz,
+                hdg,
+            } => {
+                payload.extend_from_slice(&time_boot_ms.to_le_bytes());
+                for value in [lat, lon, alt, relative_alt] {
+                    payload.extend_from_slice(&value.to_le_bytes());
+                }
+                for value in [vx, vy, vz] {
+                    payload.extend_from_slice(&value.to_le_bytes());
+                }
+                payload.extend_from_slice(&hdg.to_le_bytes());
+            }
+            Self::CommandLong {
+                target_system,
+                target_component,
+                command,
+                confirmation,
+                params,
+            } => {
+                for param in params {
+                    payload.extend_from_slice(&param.to_le_bytes());
+                }
+                payload.extend_from_slice(&command.to_le_bytes());
+                payload.extend_from_slice(&[*target_system, *target_component, *confirmation]);
+            }
+        }
+        payload
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let messages = [
+            MavMessage::gcs_heartbeat(),
+            MavMessage::SysStatus {
+                voltage_battery: 12600,
+                current_battery: -1,
+                battery_remaining: 87,
+            },
+            MavMessage::Attitude {
+                time_boot_ms: 1000,
+                roll: 0.1,
+                pitch: -0.2,
+                yaw: 1.5,
+                rollspeed: 0.0,
+                pitchspeed: 0.01,
+                yawspeed: 0.0,
+            },
+            MavMessage::GlobalPositionInt {
+                time_boot_ms: 1000,
+                lat: 473_977_419,
+                lon: 85_455_938,
+                alt: 488_000,
+                relative_alt: 10_000,
+                vx: 100,
+                vy: -50,
+                vz: 0,
+                hdg: 9000,
+            },
+            MavMessage::CommandLong {
+                target_system: 1,
+                target_component: 1,
+                command: MAV_CMD_COMPONENT_ARM_DISARM,
+                confirmation: 0,
+                params: [1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            },
+        ];
+        let lens = [9, 31, 28, 28, 33];
+        for (message, len) in messages.into_iter().zip(lens) {
+            let payload = message.encode();
+            assert_eq!(payload.len(), len);
+            assert_eq!(MavMessage::decode(message.id(), &payload).unwrap(), message);
+        }
+    }
+
+    #[test]
+    fn test_decode_truncated() {
+        // MAVLink 2 attitude with zero rates, truncated after yaw
+        let mut payload = 500u32.to_le_bytes().to_vec();
+        for value in [0.5f32, 0.25, 2.0] {
+            payload.extend_from_slice(&value.to_le_bytes());
+        }
+        assert_eq!(
+            MavMessage::decode(ATTITUDE, &payload).unwrap(),
+            MavMessage::Attitude {
+                time_boot_ms: 500,
+                roll: 0.5,
+                pitch: 0.25,
+                yaw: 2.0,
+                rollspeed: 0.0,
+                pitchspeed: 0.0,
+                yawspeed: 0.0,
+            }
+        );
+        assert!(MavMessage::decode(0xabcd, &payload).is_err());
+    }
+}
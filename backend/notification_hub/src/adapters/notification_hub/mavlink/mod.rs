@@ -0,0 +1,11 @@
+/// MAVLink transport for the notification hub, bridging ArduPilot and PX4 autopilots over
+/// serial telemetry ports or UDP.
+pub mod client;
+pub mod frame;
+pub mod messages;
+pub mod options;
+
+pub use client::MavlinkClient;
+pub use frame::MavFrame;
+pub use messages::MavMessage;
+pub use options::{MavlinkOptions, MavlinkOptionsBuilder};
@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+use tokio::time::Duration;
+
+use crate::models::hub::HubChannelName;
+
+// System and component ids of ground control stations
+const DEFAULT_SYSTEM_ID: u8 = 255;
+const DEFAULT_COMPONENT_ID: u8 = 190;
+const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+const DEFAULT_ATTITUDE_CHANNEL: &str = "attitude";
+const DEFAULT_GPS_CHANNEL: &str = "gps";
+const DEFAULT_BATTERY_CHANNEL: &str = "battery";
+
+/// `MavlinkOptions` configures a `MavlinkClient`.
+///
+/// # Fields
+/// - `system_id`: System id of the hub in the MAVLink network.
+/// - `component_id`: Component id of the hub.
+/// - `target`: System and component ids of the autopilot receiving commands. Defaults to the
+///   first autopilot heartbeat received.
+/// - `attitude_channel`: Channel of the vehicle attitude, `None` to drop it.
+/// - `gps_channel`: Channel of the vehicle global position, `None` to drop it.
+/// - `battery_channel`: Channel of the vehicle battery status, `None` to drop it.
+/// - `commands`: MAVLink command sent for messages of each channel.
+/// - `heartbeat_interval`: Interval of the heartbeats sent to the autopilot, `None` to send
+///   none.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MavlinkOptions {
+    system_id: u8,
+    component_id: u8,
+    target: Option<(u8, u8)>,
+    attitude_channel: Option<HubChannelName>,
+    gps_channel: Option<HubChannelName>,
+    battery_channel: Option<HubChannelName>,
+    commands: HashMap<HubChannelName, u16>,
+    heartbeat_interval: Option<Duration>,
+}
+
+impl MavlinkOptions {
+    pub fn system_id(&self) -> u8 {
+        self.system_id
+    }
+    pub fn component_id(&self) -> u8 {
+        self.component_id
+    }
+    pub fn target(&self) -> Option<(u8, u8)> {
+        self.target
+    }
+    pub fn attitude_channel(&self) -> Option<&HubChannelName> {
+        self.attitude_channel.as_ref()
+    }
+    pub fn gps_channel(&self) -> Option<&HubChannelName> {
+        self.gps_channel.as_ref()
+    }
+    pub fn battery_channel(&self) -> Option<&HubChannelName> {
+        self.battery_channel.as_ref()
+    }
+    pub fn heartbeat_interval(&self) -> Option<Duration> {
+        self.heartbeat_interval
+    }
+
+    /// Command sent for messages of `channel`, if any
+    pub fn command(&self, channel: &HubChannelName) -> Option<u16> {
+        self.commands.get(channel).copied()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MavlinkOptionsBuilder {
+    system_id: Option<u8>,
+    component_id: Option<u8>,
+    target: Option<(u8, u8)>,
+    attitude_channel: Option<Option<String>>,
+    gps_channel: Option<Option<String>>,
+    battery_channel: Option<Option<String>>,
+    commands: Vec<(String, u16)>,
+    heartbeat_interval: Option<Option<Duration>>,
+}
+
+fn channel_name(
+    channel: Option<Option<String>>,
+    default: &str,
+) -> Result<Option<HubChannelName>, String> {
+    channel
+        .unwrap_or_else(|| Some(default.to_string()))
+        .map(HubChannelName::try_from)
+        .transpose()
+}
+
+impl MavlinkOptionsBuilder {
+    pub fn new() -> Self {
+        Self {
+            system_id: None,
+            component_id: None,
+            target: None,
+            attitude_channel: None,
+            gps_channel: None,
+            battery_channel: None,
+            commands: Vec::new(),
+            heartbeat_interval: None,
+        }
+    }
+
+    pub fn system_id(&self, system_id: u8) -> Self {
+        let mut new = self.clone();
+        new.system_id = Some(system_id);
+        new
+    }
+    pub fn component_id(&self, component_id: u8) -> Self {
+        let mut new = self.clone();
+        new.component_id = Some(component_id);
+        new
+    }
+    /// Sends commands to component `component_id` of system `system_id`
+    pub fn target(&self, system_id: u8, component_id: u8) -> Self {
+        let mut new = self.clone();
+        new.target = Some((system_id, component_id));
+        new
+    }
+    pub fn attitude_channel(&self, channel: Option<&str>) -> Self {
+        let mut new = self.clone();
+        new.attitude_channel = Some(channel.map(str::to_string));
+        new
+    }
+    pub fn gps_channel(&self, channel: Option<&str>) -> Self {
+        let mut new = self.clone();
+        new.gps_channel = Some(channel.map(str::to_string));
+        new
+    }
+    pub fn battery_channel(&self, channel: Option<&str>) -> Self {
+        let mut new = self.clone();
+        new.battery_channel = Some(channel.map(str::to_string));
+        new
+    }
+    /// Sends messages of `channel` as `command` (e.g. `MAV_CMD_COMPONENT_ARM_DISARM`). Message
+    /// data holds up to seven comma separated command parameters.
+    pub fn command(&self, channel: &str, command: u16) -> Self {
+        let mut new = self.clone();
+        new.commands.push((channel.to_string(), command));
+        new
+    }
+    pub fn heartbeat_interval(&self, heartbeat_interval: Option<Duration>) -> Self {
+        let mut new = self.clone();
+        new.heartbeat_interval = Some(heartbeat_interval);
+        new
+    }
+    pub fn build(self) -> Result<MavlinkOptions, String> {
+        let system_id = self.system_id.unwrap_or(DEFAULT_SYSTEM_ID);
+        if system_id == 0 {
+            return Err("System id 0 is reserved for broadcast".to_string());
+        }
+        if self.heartbeat_interval == Some(Some(Duration::ZERO)) {
+            return Err("Heartbeat interval must be positive".to_string());
+        }
+        let attitude_channel = channel_name(self.attitude_channel, DEFAULT_ATTITUDE_CHANNEL)?;
+        let gps_channel = channel_name(self.gps_channel, DEFAULT_GPS_CHANNEL)?;
+        let battery_channel = channel_name(self.battery_channel, DEFAULT_BATTERY_CHANNEL)?;
+        let telemetry = [&attitude_channel, &gps_channel, &battery_channel];
+
+        let mut commands = HashMap::new();
+        for (channel, command) in self.commands {
+            let channel = HubChannelName::try_from(channel.as_str())?;
+            if telemetry.iter().any(|name| name.as_ref() == Some(&channel)) {
+                return Err(format!(
+                    "Channel {} is already a telemetry channel",
+                    channel.as_str()
+                ));
+            }
+            if commands.insert(channel.clone(), command).is_some() {
+                return Err(format!(
+                    "Channel {} is mapped to more than one command",
+                    channel.as_str()
+                ));
+            }
+        }
+        Ok(MavlinkOptions {
+            system_id,
+            component_id: self.component_id.unwrap_or(DEFAULT_COMPONENT_ID),
+            target: self.target,
+            attitude_channel,
+            gps_channel,
+            battery_channel,
+            commands,
+            heartbeat_interval: self
+                .heartbeat_interval
+                .unwrap_or(Some(DEFAULT_HEARTBEAT_INTERVAL)),
+        })
+    }
+}
+
+impl Default for MavlinkOptionsBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::mavlink::messages::MAV_CMD_COMPONENT_ARM_DISARM;
+
+    #[test]
+    fn test_defaults() {
+        let options = MavlinkOptionsBuilder::new()
+            .gps_channel(None)
+            .command("arm", MAV_CMD_COMPONENT_ARM_DISARM)
+            .build()
+            .unwrap();
+        assert_eq!(options.system_id(), DEFAULT_SYSTEM_ID);
+        assert_eq!(options.target(), None);
+        assert_eq!(options.attitude_channel().unwrap().as_str(), "attitude");
+        assert!(options.gps_channel().is_none());
+        assert_eq!(
+            options.command(&HubChannelName::try_from("arm").unwrap()),
+            Some(MAV_CMD_COMPONENT_ARM_DISARM)
+        );
+        assert_eq!(options.heartbeat_interval(), Some(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        let builder = MavlinkOptionsBuilder::new();
+        assert!(builder.system_id(0).build().is_err());
+        assert!(builder.attitude_channel(Some("Attitude")).build().is_err());
+        assert!(builder.command("battery", 400).build().is_err());
+        assert!(builder
+            .command("arm", 400)
+            .command("arm", 22)
+            .build()
+            .is_err());
+        assert!(builder
+            .heartbeat_interval(Some(Duration::ZERO))
+            .build()
+            .is_err());
+    }
+}
@@ -0,0 +1,219 @@
+use async_trait::async_trait;
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::sync::{broadcast, mpsc, RwLock};
+
+use crate::adapters::lease::LeaseSlot;
+use crate::models::hub::{HubChannelName, HubMessage};
+use crate::ports::{NodeTasks, NotificationHub};
+
+/// `MemoryClient` is a hub node backed only by in-memory channels, so that tests can exercise
+/// `HubManager` routing without pipes, sockets or sleeps.
+///
+/// The client is created together with a `MemoryPeer`, which plays the remote side of the
+/// transport: messages injected by the peer are received by the hub, and messages sent by
+/// the hub are read from the peer in order.
+///
+/// # Fields
+/// - `incoming`: Messages injected by the peer. It is leased by the forward loop when the
+///   client is started. Messages injected before are kept until then.
+/// - `outgoing`: Messages sent by the hub, read by the peer.
+/// - `channels`: Channels injected so far.
+/// - `subscriptions`: Channels the hub subscribed to.
+#[derive(Debug)]
+pub struct MemoryClient {
+    incoming: LeaseSlot<mpsc::UnboundedReceiver<HubMessage>>,
+    outgoing: mpsc::UnboundedSender<HubMessage>,
+    channels: Arc<RwLock<HashSet<HubChannelName>>>,
+    subscriptions: Arc<RwLock<HashSet<HubChannelName>>>,
+}
+
+/// Remote side of a `MemoryClient`.
+///
+/// # Fields
+/// - `incoming`: Messages received by the hub.
+/// - `outgoing`: Messages sent by the hub.
+/// - `channels`: Channels injected so far, shared with the client.
+/// - `subscriptions`: Channels the hub subscribed to, shared with the client.
+#[derive(Debug)]
+pub struct MemoryPeer {
+    incoming: mpsc::UnboundedSender<HubMessage>,
+    outgoing: mpsc::UnboundedReceiver<HubMessage>,
+    channels: Arc<RwLock<HashSet<HubChannelName>>>,
+    subscriptions: Arc<RwLock<HashSet<HubChannelName>>>,
+}
+
+impl MemoryClient {
+    /// Client and its peer
+    pub fn new() -> (Self, MemoryPeer) {
+        let (incoming_sender, incoming_receiver) = mpsc::unbounded_channel();
+        let (outgoing_sender, outgoing_receiver) = mpsc::unbounded_channel();
+        let channels = Arc::new(RwLock::new(HashSet::new()));
+        let subscriptions = Arc::new(RwLock::new(HashSet::new()));
+        let client = Self {
+            incoming: LeaseSlot::new(incoming_receiver),
+            outgoing: outgoing_sender,
+            channels: Arc::clone(&channels),
+            subscriptions: Arc::clone(&subscriptions),
+        };
+        let peer = MemoryPeer {
+            incoming: incoming_sender,
+            outgoing: outgoing_receiver,
+            channels,
+            subscriptions,
+        };
+        (client, peer)
+    }
+}
+
+impl MemoryPeer {
+    /// Emulates `message` received by the client. Its channel is listed by the client right
+    /// away.
+    pub async fn inject(&self, message: HubMessage) -> Result<(), std::io::Error> {
+        self.channels.write().await.insert(message.channel.clone());
+        self.incoming.send(message).map_err(|_| {
+            std::io::Error::new(std::io::ErrorKind::BrokenPipe, "Memory client dropped")
+        })
+    }
+
+    /// Next message sent by the hub. Returns `None` once the client is dropped and every
+    /// message has been read.
+    pub async fn recv(&mut self) -> Option<HubMessage> {
+        self.outgoing.recv().await
+    }
+
+    /// Next message sent by the hub, without waiting
+    pub fn try_recv(&mut self) -> Option<HubMessage> {
+        self.outgoing.try_recv().ok()
+    }
+
+    /// Channels the hub is subscribed to
+    pub async fn subscriptions(&self) -> Vec<HubChannelName> {
+        self.subscriptions.read().await.iter().cloned().collect()
+    }
+}
+
+#[async_trait]
+impl NotificationHub for MemoryClient {
+    /// Queue message for the peer
+    async fn send(&self, data: HubMessage) -> Result<(), std::io::Error> {
+        self.outgoing
+            .send(data)
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::BrokenPipe, "Memory peer dropped"))
+    }
+
+    /// List channels injected so far
+    async fn list_channels(&self) -> Result<Vec<HubChannelName>, std::io::Error> {
+        Ok(self.channels.read().await.iter().cloned().collect())
+    }
+
+    /// Start forward loop. The loop ends when the peer is dropped.
+    async fn start(
+        &self,
+        sender: Option<broadcast::Sender<HubMessage>>,
+    ) -> Result<NodeTasks, std::io::Error> {
+        let mut tasks = NodeTasks::new();
+        if let Some(sender) = sender {
+            let mut incoming = self.incoming.lease("Memory client")?;
+            let task = tokio::spawn(async move {
+                while let Some(message) = incoming.recv().await {
+                    let _ = sender.send(message);
+                }
+            });
+            tasks.push(task);
+        }
+        Ok(tasks)
+    }
+
+    async fn subscribe(&self, channel: HubChannelName) -> Result<(), std::io::Error> {
+        self.subscriptions.write().await.insert(channel);
+        Ok(())
+    }
+
+    async fn unsubscribe(&self, channel: HubChannelName) -> Result<(), std::io::Error> {
+        self.subscriptions.write().await.remove(&channel);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::hub::{HubManager, NodeId};
+
+    #[tokio::test]
+    async fn test_loopback() {
+        let (client, mut peer) = MemoryClient::new();
+        let (sender, mut receiver) = broadcast::channel(10);
+
+        // injected before start, and kept until then
+        peer.inject(HubMessage::try_from_str("imu", "1,2,3").unwrap())
+            .await
+            .unwrap();
+        let tasks = client.start(Some(sender.clone())).await.unwrap();
+        assert!(client.start(Some(sender)).await.is_err());
+        assert_eq!(receiver.recv().await.unwrap().data.as_str(), "1,2,3");
+        assert_eq!(client.list_channels().await.unwrap().len(), 1);
+
+        client
+            .send(HubMessage::try_from_str("cmd", "go").unwrap())
+            .await
+            .unwrap();
+        assert_eq!(peer.recv().await.unwrap().channel.as_str(), "cmd");
+        assert!(peer.try_recv().is_none());
+
+        // forward loop ends with the peer
+        drop(peer);
+        for task in tasks {
+            task.await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hub_routing() {
+        let (imu, imu_peer) = MemoryClient::new();
+        let (motors, mut motors_peer) = MemoryClient::new();
+        let mut hub = HubManager::new();
+        hub.add("imu", Box::new(imu)).unwrap();
+        let motors_id = hub.add("motors", Box::new(motors)).unwrap();
+        assert_eq!(motors_id, NodeId::try_from("motors").unwrap());
+        hub.route(HubChannelName::try_from("cmd_vel").unwrap(), motors_id);
+        hub.start().await.unwrap();
+
+        let channel = HubChannelName::try_from("imu").unwrap();
+        let mut subscriber = hub.register_to_channel(channel.clone()).await.unwrap();
+        assert_eq!(imu_peer.subscriptions().await, vec![channel]);
+        imu_peer
+            .inject(HubMessage::try_from_str("imu", "0.1,0.2,9.8").unwrap())
+            .await
+            .unwrap();
+        assert_eq!(
+            subscriber.recv().await.unwrap().data.as_str(),
+            "0.1,0.2,9.8"
+        );
+
+        hub.publish(HubMessage::try_from_str("cmd_vel", "1,0").unwrap())
+            .await
+            .unwrap();
+        assert_eq!(motors_peer.recv().await.unwrap().data.as_str(), "1,0");
+        assert!(motors_peer.try_recv().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_hub_restart() {
+        let (client, peer) = MemoryClient::new();
+        let mut hub = HubManager::new();
+        hub.add("memory", Box::new(client)).unwrap();
+        hub.start().await.unwrap();
+        let channel = HubChannelName::try_from("imu").unwrap();
+        let mut subscriber = hub.register_to_channel(channel.clone()).await.unwrap();
+
+        // the forward loop aborted by stop gives the incoming messages back to the client
+        hub.stop().await;
+        hub.start().await.unwrap();
+        peer.inject(HubMessage::try_from_str("imu", "1").unwrap())
+            .await
+            .unwrap();
+        assert_eq!(subscriber.recv().await.unwrap().data.as_str(), "1");
+    }
+}
@@ -0,0 +1,4 @@
+/// In-memory transport for the notification hub, for deterministic tests without I/O.
+pub mod client;
+
+pub use client::{MemoryClient, MemoryPeer};
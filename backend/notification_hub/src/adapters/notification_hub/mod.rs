@@ -1,2 +1,36 @@
+pub mod batch;
+#[cfg(all(feature = "can", target_os = "linux"))]
+pub mod can;
+#[cfg(feature = "dbus")]
+pub mod dbus;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(all(feature = "i2c", target_os = "linux"))]
+pub mod i2c;
+pub(crate) mod lease;
+pub mod lora;
+pub mod mavlink;
+pub mod memory;
+#[cfg(feature = "nats")]
+pub mod nats;
+#[cfg(feature = "quic")]
+pub mod quic;
+pub mod reconnect;
+#[cfg(feature = "ros2")]
+pub mod ros2;
+pub mod sample;
 pub mod serial;
+#[cfg(feature = "shm")]
+pub mod shm;
+#[cfg(all(feature = "spi", target_os = "linux"))]
+pub mod spi;
+#[cfg(feature = "sse")]
+pub mod sse;
+pub mod stdio;
+pub mod udp;
 pub mod websocket;
+pub mod xbee;
+#[cfg(feature = "zenoh")]
+pub mod zenoh;
+#[cfg(feature = "zmq")]
+pub mod zmq;
@@ -0,0 +1,229 @@
+use async_nats::{ConnectOptions, Event};
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use log::{error, info, warn};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::{broadcast, mpsc, Mutex, RwLock};
+use tokio::task::JoinHandle;
+
+use crate::adapters::lease::LeaseSlot;
+use crate::models::hub::{HubChannelName, HubMessage};
+use crate::ports::{NodeTasks, NotificationHub};
+
+const CHANNEL_CAPACITY: usize = 100;
+const DEFAULT_SUBJECT_PREFIX: &str = "robopilot";
+
+fn nats_error(e: impl std::fmt::Display) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Other, e.to_string())
+}
+
+/// `NatsClient` is a hub node joining a NATS network, so that the robot hub exchanges messages
+/// with services already connected to NATS (e.g. in the base station).
+///
+/// Channels are mapped to NATS subjects under the subject prefix, with namespace separators
+/// '/' replaced by '.' (`sensors/imu` is published to `robopilot.sensors.imu`). Messages are
+/// JSON encoded `HubMessage`s. Every channel subscription opens a NATS subscription, forwarded
+/// to the hub by the receive loop.
+///
+/// Connection losses are handled by the NATS client, which reconnects and restores
+/// subscriptions. Messages published while disconnected are buffered by the NATS client.
+///
+/// # Fields
+/// - `client`: NATS connection.
+/// - `subject_prefix`: Subject prefix of every channel.
+/// - `subscriptions`: Tasks forwarding NATS subscriptions, per channel.
+/// - `incoming`: Messages received from NATS subscriptions, leased by the receive loop.
+/// - `channels`: Channels received so far.
+#[derive(Debug)]
+pub struct NatsClient {
+    client: async_nats::Client,
+    subject_prefix: String,
+    subscriptions: Mutex<HashMap<HubChannelName, JoinHandle<()>>>,
+    incoming: mpsc::Sender<HubMessage>,
+    incoming_receiver: LeaseSlot<mpsc::Receiver<HubMessage>>,
+    channels: Arc<RwLock<HashSet<HubChannelName>>>,
+}
+
+impl NatsClient {
+    /// Connects to NATS server at `url` (e.g. `nats://127.0.0.1:4222`)
+    pub async fn new(url: &str) -> Result<Self, std::io::Error> {
+        let options = ConnectOptions::new()
+            .retry_on_initial_connect()
+            .event_callback(|event| async move {
+                match event {
+                    Event::Connected => info!("Connected to NATS server"),
+                    Event::Disconnected => warn!("NATS connection lost! Reconnecting..."),
+                    event => info!("NATS event: {}", event),
+                }
+            });
+        let client = options.connect(url).await.map_err(nats_error)?;
+        info!("NATS client connected to {}", url);
+        let (incoming, incoming_receiver) = mpsc::channel(CHANNEL_CAPACITY);
+        Ok(Self {
+            client,
+            subject_prefix: DEFAULT_SUBJECT_PREFIX.to_string(),
+            subscriptions: Mutex::new(HashMap::new()),
+            incoming,
+            incoming_receiver: LeaseSlot::new(incoming_receiver),
+            channels: Arc::new(RwLock::new(HashSet::new())),
+        })
+    }
+
+    /// Sets the subject prefix of every channel. Defaults to `robopilot`
+    pub fn with_subject_prefix(mut self, subject_prefix: &str) -> Self {
+        self.subject_prefix = subject_prefix.to_string();
+        self
+    }
+
+    fn subject(&self, channel: &HubChannelName) -> String {
+        channel_to_subject(&self.subject_prefix, channel)
+    }
+}
+
+fn channel_to_subject(subject_prefix: &str, channel: &HubChannelName) -> String {
+    format!("{}.{}", subject_prefix, channel.as_str().replace('/', "."))
+}
+
+fn subject_to_channel(subject_prefix: &str, subject: &str) -> Result<HubChannelName, String> {
+    let channel = subject
+        .strip_prefix(subject_prefix)
+        .and_then(|subject| subject.strip_prefix('.'))
+        .ok_or_else(|| format!("Subject {} is not a hub channel", subject))?;
+    HubChannelName::try_from(channel.replace('.', "/"))
+}
+
+#[async_trait]
+impl NotificationHub for NatsClient {
+    /// Publish message to the subject of its channel
+    async fn send(&self, data: HubMessage) -> Result<(), std::io::Error> {
+        let payload = data.to_bytes()?;
+        self.client
+            .publish(self.subject(&data.channel), payload.into())
+            .await
+            .map_err(nats_error)
+    }
+
+    /// List channels received so far
+    async fn list_channels(&self) -> Result<Vec<HubChannelName>, std::io::Error> {
+        Ok(self.channels.read().await.iter().cloned().collect())
+    }
+
+    /// Start forwarding messages of subscribed channels
+    async fn start(
+        &self,
+        sender: Option<broadcast::Sender<HubMessage>>,
+    ) -> Result<NodeTasks, std::io::Error> {
+        let mut tasks = NodeTasks::new();
+        if let Some(sender) = sender {
+            let mut incoming = self.incoming_receiver.lease("NATS client")?;
+            let channels = Arc::clone(&self.channels);
+            let task = tokio::spawn(async move {
+                while let Some(message) = incoming.recv().await {
+                    channels.write().await.insert(message.channel.clone());
+                    let _ = sender.send(message);
+                }
+            });
+            tasks.push(task);
+        }
+        Ok(tasks)
+    }
+
+    async fn subscribe(&self, channel: HubChannelName) -> Result<(), std::io::Error> {
+        let mut subscriptions = self.subscriptions.lock().await;
+        if subscriptions.contains_key(&channel) {
+            return Ok(());
+        }
+        let mut subscriber = self
+            .client
+            .subscribe(self.subject(&channel))
+            .await
+            .map_err(nats_error)?;
+        let incoming = self.incoming.clone();
+        let subject_prefix = self.subject_prefix.clone();
+        let task = tokio::spawn(async move {
+            while let Some(message) = subscriber.next().await {
+                let hub_message =
+                    subject_to_channel(&subject_prefix, &message.subject).and_then(|channel| {
+                        let hub_message = HubMessage::try_from(message.payload.to_vec())?;
+                        if hub_message.channel != channel {
+                            return Err(format!(
+                                "Message from channel {} received in subject {}",
+                                hub_message.channel.as_str(),
+                                message.subject
+                            ));
+                        }
+                        Ok(hub_message)
+                    });
+                match hub_message {
+                    Ok(hub_message) => {
+                        if incoming.send(hub_message).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => error!("Invalid NATS message: {}", e),
+                }
+            }
+        });
+        subscriptions.insert(channel, task);
+        Ok(())
+    }
+
+    async fn unsubscribe(&self, channel: HubChannelName) -> Result<(), std::io::Error> {
+        // NATS subscription is closed when the subscriber is dropped with its task
+        if let Some(task) = self.subscriptions.lock().await.remove(&channel) {
+            task.abort();
+        }
+        Ok(())
+    }
+}
+
+impl Drop for NatsClient {
+    fn drop(&mut self) {
+        for task in self.subscriptions.get_mut().values() {
+            task.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time::{timeout, Duration};
+
+    const NATS_URL: &str = "nats://127.0.0.1:4222";
+
+    #[test]
+    fn test_subject_mapping() {
+        let channel = HubChannelName::try_from("sensors/imu").unwrap();
+        let subject = channel_to_subject("robopilot", &channel);
+        assert_eq!(subject, "robopilot.sensors.imu");
+        assert_eq!(subject_to_channel("robopilot", &subject).unwrap(), channel);
+
+        assert!(subject_to_channel("robopilot", "other.sensors.imu").is_err());
+        assert!(subject_to_channel("robopilot", "robopilotsensors.imu").is_err());
+        assert!(subject_to_channel("robopilot", "robopilot.sensors..imu").is_err());
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_nats() {
+        let node1 = NatsClient::new(NATS_URL).await.unwrap();
+        let node2 = NatsClient::new(NATS_URL).await.unwrap();
+        let (sender, mut receiver) = broadcast::channel(10);
+        node2.start(Some(sender)).await.unwrap();
+
+        let channel = HubChannelName::try_from("sensors/imu").unwrap();
+        node2.subscribe(channel.clone()).await.unwrap();
+        node1
+            .send(HubMessage::try_from_str("sensors/imu", "1,2,3").unwrap())
+            .await
+            .unwrap();
+        let received = timeout(Duration::from_secs(5), receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(received.channel, channel);
+        assert_eq!(node2.list_channels().await.unwrap(), vec![channel]);
+    }
+}
@@ -0,0 +1,4 @@
+/// NATS transport for the notification hub.
+pub mod client;
+
+pub use client::NatsClient;
@@ -0,0 +1,113 @@
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::models::hub::HubChannelName;
+
+/// Maximum size in bytes of a frame payload
+pub const MAX_FRAME_LEN: usize = 1 << 20;
+
+/// Kind of a QUIC stream, sent as the first byte of every stream.
+///
+/// Every node opens a single control stream, carrying its channel subscriptions, and a data
+/// stream per channel, carrying the messages of the channel. Frames in every stream are JSON
+/// payloads preceded by their length (4 bytes, big endian).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamKind {
+    Control = 0,
+    Data = 1,
+}
+
+impl TryFrom<u8> for StreamKind {
+    type Error = String;
+
+    fn try_from(kind: u8) -> Result<Self, Self::Error> {
+        match kind {
+            0 => Ok(Self::Control),
+            1 => Ok(Self::Data),
+            kind => Err(format!("Unknown stream kind {}", kind)),
+        }
+    }
+}
+
+/// Messages of the control stream
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ControlMessage {
+    Subscribe(HubChannelName),
+    Unsubscribe(HubChannelName),
+}
+
+pub async fn write_frame(
+    stream: &mut (impl AsyncWrite + Unpin),
+    payload: &[u8],
+) -> Result<(), std::io::Error> {
+    if payload.len() > MAX_FRAME_LEN {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Frame longer than {} bytes", MAX_FRAME_LEN),
+        ));
+    }
+    let mut frame = Vec::with_capacity(4 + payload.len());
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(payload);
+    stream.write_all(&frame).await
+}
+
+/// Reads next frame. Returns None when the stream is finished.
+pub async fn read_frame(
+    stream: &mut (impl AsyncRead + Unpin),
+) -> Result<Option<Vec<u8>>, std::io::Error> {
+    let mut len = [0; 4];
+    match stream.read_exact(&mut len).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_FRAME_LEN {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Frame longer than {} bytes", MAX_FRAME_LEN),
+        ));
+    }
+    let mut payload = vec![0; len];
+    stream.read_exact(&mut payload).await?;
+    Ok(Some(payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_frames() {
+        let mut stream = Vec::new();
+        write_frame(&mut stream, b"first").await.unwrap();
+        write_frame(&mut stream, b"").await.unwrap();
+        write_frame(&mut stream, b"second").await.unwrap();
+
+        let mut reader = stream.as_slice();
+        assert_eq!(read_frame(&mut reader).await.unwrap().unwrap(), b"first");
+        assert_eq!(read_frame(&mut reader).await.unwrap().unwrap(), b"");
+        assert_eq!(read_frame(&mut reader).await.unwrap().unwrap(), b"second");
+        assert!(read_frame(&mut reader).await.unwrap().is_none());
+
+        // truncated payload
+        let mut reader = &stream[..6];
+        assert!(read_frame(&mut reader).await.is_err());
+
+        let oversized = vec![0; MAX_FRAME_LEN + 1];
+        assert!(write_frame(&mut Vec::new(), &oversized).await.is_err());
+    }
+
+    #[test]
+    fn test_control_messages() {
+        let message = ControlMessage::Subscribe(HubChannelName::try_from("sensors/imu").unwrap());
+        let encoded = serde_json::to_vec(&message).unwrap();
+        assert_eq!(
+            serde_json::from_slice::<ControlMessage>(&encoded).unwrap(),
+            message
+        );
+        assert!(StreamKind::try_from(2).is_err());
+        assert_eq!(StreamKind::try_from(1).unwrap(), StreamKind::Data);
+    }
+}
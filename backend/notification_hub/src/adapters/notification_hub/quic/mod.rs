@@ -0,0 +1,5 @@
+/// QUIC transport for links between hubs, with a QUIC stream per channel.
+pub mod frame;
+pub mod node;
+
+pub use node::{QuicCertificate, QuicListener, QuicNode};
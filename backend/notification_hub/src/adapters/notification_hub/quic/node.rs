@@ -0,0 +1,426 @@
+use async_trait::async_trait;
+use log::{info, warn};
+use quinn::rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use quinn::rustls::RootCertStore;
+use quinn::{
+    ClientConfig, Connection, Endpoint, RecvStream, SendStream, ServerConfig, TransportConfig,
+    VarInt,
+};
+use std::collections::{HashMap, HashSet};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, Mutex, RwLock};
+use tokio::task::JoinSet;
+
+use super::frame::{read_frame, write_frame, ControlMessage, StreamKind};
+use crate::models::hub::{HubChannelName, HubMessage};
+use crate::ports::{NodeTasks, NotificationHub};
+
+/// Interval of keep alive packets, so that idle links are not closed
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(5);
+
+type DataStreams = Arc<Mutex<HashMap<HubChannelName, Arc<Mutex<SendStream>>>>>;
+
+fn quic_error(e: impl std::fmt::Display) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Other, e.to_string())
+}
+
+async fn resolve(address: &str) -> Result<SocketAddr, std::io::Error> {
+    tokio::net::lookup_host(address)
+        .await?
+        .next()
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Address {} not resolved", address),
+            )
+        })
+}
+
+fn transport_config() -> Arc<TransportConfig> {
+    let mut config = TransportConfig::default();
+    config.keep_alive_interval(Some(KEEP_ALIVE_INTERVAL));
+    Arc::new(config)
+}
+
+/// TLS certificate chain and private key of a `QuicListener`
+#[derive(Debug)]
+pub struct QuicCertificate {
+    chain: Vec<CertificateDer<'static>>,
+    key: PrivateKeyDer<'static>,
+}
+
+impl QuicCertificate {
+    pub fn new(chain: Vec<CertificateDer<'static>>, key: PrivateKeyDer<'static>) -> Self {
+        Self { chain, key }
+    }
+
+    /// Generates a self signed certificate valid for `names` (e.g. `["robot1.local"]`).
+    /// Peers trust it by passing `certificate()` to `QuicNode::connect`.
+    pub fn self_signed(names: &[&str]) -> Result<Self, String> {
+        let names: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+        let certified = rcgen::generate_simple_self_signed(names).map_err(|e| e.to_string())?;
+        let key = PrivatePkcs8KeyDer::from(certified.key_pair.serialize_der());
+        Ok(Self::new(vec![certified.cert.der().clone()], key.into()))
+    }
+
+    /// End entity certificate
+    pub fn certificate(&self) -> Option<&CertificateDer<'static>> {
+        self.chain.first()
+    }
+}
+
+/// Accepts QUIC connections from remote hubs
+#[derive(Debug)]
+pub struct QuicListener {
+    endpoint: Endpoint,
+}
+
+impl QuicListener {
+    /// Binds QUIC endpoint to `address` (e.g. `0.0.0.0:4433`)
+    pub async fn bind(address: &str, certificate: QuicCertificate) -> Result<Self, std::io::Error> {
+        let address = resolve(address).await?;
+        let mut config = ServerConfig::with_single_cert(certificate.chain, certificate.key)
+            .map_err(quic_error)?;
+        config.transport_config(transport_config());
+        let endpoint = Endpoint::server(config, address)?;
+        info!("QUIC listener bound to {}", endpoint.local_addr()?);
+        Ok(Self { endpoint })
+    }
+
+    /// Address the listener is bound to. Resolves the port chosen when binding to port 0
+    pub fn local_addr(&self) -> Result<SocketAddr, std::io::Error> {
+        self.endpoint.local_addr()
+    }
+
+    /// Waits for the next connection, and returns the node exchanging messages with it
+    pub async fn accept(&self) -> Result<QuicNode, std::io::Error> {
+        let incoming = self.endpoint.accept().await.ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotConnected, "QUIC listener closed")
+        })?;
+        let connection = incoming.await.map_err(quic_error)?;
+        info!(
+            "QUIC connection accepted from {}",
+            connection.remote_address()
+        );
+        Ok(QuicNode::new(self.endpoint.clone(), connection))
+    }
+}
+
+/// `QuicNode` is a hub node exchanging messages with a remote hub over a QUIC connection, e.g.
+/// between a robot and its operator station over Wi-Fi.
+///
+/// Every channel is sent on its own QUIC stream. Streams are delivered independently, so a
+/// packet lost on a lossy link only delays the messages of its channel, instead of stalling
+/// every channel as on a single TCP connection. Messages are only sent on channels subscribed
+/// by the peer, which sends its subscriptions on a control stream.
+///
+/// # Fields
+/// - `endpoint`: Local QUIC endpoint.
+/// - `connection`: Connection to the remote hub.
+/// - `control`: Control stream, opened with the first subscription.
+/// - `streams`: Data stream of every channel sent to the peer, opened with the first message.
+/// - `peer_subscriptions`: Channels subscribed by the peer.
+/// - `channels`: Channels received so far.
+#[derive(Debug)]
+pub struct QuicNode {
+    endpoint: Endpoint,
+    connection: Connection,
+    control: Mutex<Option<SendStream>>,
+    streams: DataStreams,
+    peer_subscriptions: Arc<RwLock<HashSet<HubChannelName>>>,
+    channels: Arc<RwLock<HashSet<HubChannelName>>>,
+}
+
+impl QuicNode {
+    fn new(endpoint: Endpoint, connection: Connection) -> Self {
+        Self {
+            endpoint,
+            connection,
+            control: Mutex::new(None),
+            streams: Arc::new(Mutex::new(HashMap::new())),
+            peer_subscriptions: Arc::new(RwLock::new(HashSet::new())),
+            channels: Arc::new(RwLock::new(HashSet::new())),
+        }
+    }
+
+    /// Connects to the `QuicListener` at `address`. The listener certificate must be valid for
+    /// `server_name`, and signed by `certificate` (or be `certificate` if self signed).
+    pub async fn connect(
+        address: &str,
+        server_name: &str,
+        certificate: &CertificateDer<'static>,
+    ) -> Result<Self, std::io::Error> {
+        let address = resolve(address).await?;
+        let mut roots = RootCertStore::empty();
+        roots.add(certificate.clone()).map_err(quic_error)?;
+        let mut config =
+            ClientConfig::with_root_certificates(Arc::new(roots)).map_err(quic_error)?;
+        config.transport_config(transport_config());
+
+        let local_address = if address.is_ipv6() {
+            SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0))
+        } else {
+            SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))
+        };
+        let mut endpoint = Endpoint::client(local_address)?;
+        endpoint.set_default_client_config(config);
+        let connection = endpoint
+            .connect(address, server_name)
+            .map_err(quic_error)?
+            .await
+            .map_err(quic_error)?;
+        info!("QUIC connection established with {}", address);
+        Ok(Self::new(endpoint, connection))
+    }
+
+    /// Address of the local endpoint
+    pub fn local_addr(&self) -> Result<SocketAddr, std::io::Error> {
+        self.endpoint.local_addr()
+    }
+
+    /// Address of the remote hub
+    pub fn remote_address(&self) -> SocketAddr {
+        self.connection.remote_address()
+    }
+
+    async fn open_stream(&self, kind: StreamKind) -> Result<SendStream, std::io::Error> {
+        let mut stream = self.connection.open_uni().await.map_err(quic_error)?;
+        stream.write_all(&[kind as u8]).await.map_err(quic_error)?;
+        Ok(stream)
+    }
+
+    async fn send_control(&self, message: ControlMessage) -> Result<(), std::io::Error> {
+        let payload = serde_json::to_vec(&message)?;
+        let mut control = self.control.lock().await;
+        if control.is_none() {
+            *control = Some(self.open_stream(StreamKind::Control).await?);
+        }
+        if let Some(stream) = control.as_mut() {
+            write_frame(stream, &payload).await?;
+        }
+        Ok(())
+    }
+}
+
+/// State updated by the frames received from the peer
+#[derive(Clone)]
+struct PeerState {
+    sender: Option<broadcast::Sender<HubMessage>>,
+    streams: DataStreams,
+    peer_subscriptions: Arc<RwLock<HashSet<HubChannelName>>>,
+    channels: Arc<RwLock<HashSet<HubChannelName>>>,
+}
+
+impl PeerState {
+    async fn control(&self, payload: &[u8]) -> Result<(), String> {
+        match serde_json::from_slice(payload).map_err(|e| e.to_string())? {
+            ControlMessage::Subscribe(channel) => {
+                self.peer_subscriptions.write().await.insert(channel);
+            }
+            ControlMessage::Unsubscribe(channel) => {
+                self.peer_subscriptions.write().await.remove(&channel);
+                // finish data stream, so that the peer releases it
+                if let Some(stream) = self.streams.lock().await.remove(&channel) {
+                    let _ = stream.lock().await.finish();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn data(&self, payload: Vec<u8>) -> Result<(), String> {
+        let message = HubMessage::try_from(payload)?;
+        self.channels.write().await.insert(message.channel.clone());
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(message);
+        }
+        Ok(())
+    }
+
+    async fn read_stream(self, mut stream: RecvStream) {
+        let mut kind = [0; 1];
+        if let Err(e) = stream.read_exact(&mut kind).await {
+            warn!("QUIC stream closed before its kind was received: {}", e);
+            return;
+        }
+        let kind = match StreamKind::try_from(kind[0]) {
+            Ok(kind) => kind,
+            Err(e) => {
+                warn!("Invalid QUIC stream: {}", e);
+                return;
+            }
+        };
+        loop {
+            let payload = match read_frame(&mut stream).await {
+                Ok(Some(payload)) => payload,
+                Ok(None) => break,
+                Err(e) => {
+                    warn!("QUIC stream error: {}", e);
+                    break;
+                }
+            };
+            let result = match kind {
+                StreamKind::Control => self.control(&payload).await,
+                StreamKind::Data => self.data(payload).await,
+            };
+            if let Err(e) = result {
+                warn!("Invalid QUIC frame: {}", e);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl NotificationHub for QuicNode {
+    /// Send message on the stream of its channel, if subscribed by the peer
+    async fn send(&self, data: HubMessage) -> Result<(), std::io::Error> {
+        if !self.peer_subscriptions.read().await.contains(&data.channel) {
+            return Ok(());
+        }
+        let payload = data.to_bytes()?;
+        let stream = {
+            let mut streams = self.streams.lock().await;
+            match streams.get(&data.channel) {
+                Some(stream) => Arc::clone(stream),
+                None => {
+                    let stream = Arc::new(Mutex::new(self.open_stream(StreamKind::Data).await?));
+                    streams.insert(data.channel.clone(), Arc::clone(&stream));
+                    stream
+                }
+            }
+        };
+        let result = write_frame(&mut *stream.lock().await, &payload).await;
+        if result.is_err() {
+            // stream is reopened with the next message
+            self.streams.lock().await.remove(&data.channel);
+        }
+        result
+    }
+
+    /// List channels received so far
+    async fn list_channels(&self) -> Result<Vec<HubChannelName>, std::io::Error> {
+        Ok(self.channels.read().await.iter().cloned().collect())
+    }
+
+    /// Start accepting the streams opened by the peer. Control streams are processed even if
+    /// the node doesn't forward messages to the hub.
+    async fn start(
+        &self,
+        sender: Option<broadcast::Sender<HubMessage>>,
+    ) -> Result<NodeTasks, std::io::Error> {
+        let connection = self.connection.clone();
+        let peer = PeerState {
+            sender,
+            streams: Arc::clone(&self.streams),
+            peer_subscriptions: Arc::clone(&self.peer_subscriptions),
+            channels: Arc::clone(&self.channels),
+        };
+        let task = tokio::spawn(async move {
+            // stream readers are aborted with the task
+            let mut readers = JoinSet::new();
+            loop {
+                tokio::select! {
+                    stream = connection.accept_uni() => {
+                        match stream {
+                            Ok(stream) => {
+                                readers.spawn(peer.clone().read_stream(stream));
+                            }
+                            Err(e) => {
+                                warn!("QUIC connection closed: {}", e);
+                                break;
+                            }
+                        }
+                    }
+                    Some(_) = readers.join_next() => {}
+                }
+            }
+        });
+        let mut tasks = NodeTasks::new();
+        tasks.push(task);
+        Ok(tasks)
+    }
+
+    async fn subscribe(&self, channel: HubChannelName) -> Result<(), std::io::Error> {
+        self.send_control(ControlMessage::Subscribe(channel)).await
+    }
+
+    async fn unsubscribe(&self, channel: HubChannelName) -> Result<(), std::io::Error> {
+        self.send_control(ControlMessage::Unsubscribe(channel))
+            .await
+    }
+}
+
+impl Drop for QuicNode {
+    fn drop(&mut self) {
+        self.connection.close(VarInt::from_u32(0), b"node dropped");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time::{timeout, Duration};
+
+    #[tokio::test]
+    async fn test_quic_nodes() {
+        let certificate = QuicCertificate::self_signed(&["localhost"]).unwrap();
+        let trusted = certificate.certificate().unwrap().clone();
+        let listener = QuicListener::bind("127.0.0.1:0", certificate)
+            .await
+            .unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let (station, robot) = tokio::join!(
+            listener.accept(),
+            QuicNode::connect(&address, "localhost", &trusted)
+        );
+        let (station, robot) = (station.unwrap(), robot.unwrap());
+
+        let (sender, mut receiver) = broadcast::channel(10);
+        station.start(Some(sender)).await.unwrap();
+        robot.start(None).await.unwrap();
+
+        let channel = HubChannelName::try_from("sensors/imu").unwrap();
+        station.subscribe(channel.clone()).await.unwrap();
+
+        // subscription takes a while to reach the robot
+        let received = timeout(Duration::from_secs(5), async {
+            loop {
+                robot
+                    .send(HubMessage::try_from_str("sensors/gps", "0,0").unwrap())
+                    .await
+                    .unwrap();
+                robot
+                    .send(HubMessage::try_from_str("sensors/imu", "1,2,3").unwrap())
+                    .await
+                    .unwrap();
+                if let Ok(Ok(message)) = timeout(Duration::from_millis(50), receiver.recv()).await {
+                    return message;
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(received.channel, channel);
+        assert_eq!(received.data.as_str(), "1,2,3");
+        assert_eq!(station.list_channels().await.unwrap(), vec![channel]);
+    }
+
+    #[tokio::test]
+    async fn test_untrusted_certificate() {
+        let certificate = QuicCertificate::self_signed(&["localhost"]).unwrap();
+        let other = QuicCertificate::self_signed(&["localhost"]).unwrap();
+        let listener = QuicListener::bind("127.0.0.1:0", certificate)
+            .await
+            .unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let accept = tokio::spawn(async move { listener.accept().await.map(|_| ()) });
+        assert!(
+            QuicNode::connect(&address, "localhost", other.certificate().unwrap())
+                .await
+                .is_err()
+        );
+        accept.abort();
+    }
+}
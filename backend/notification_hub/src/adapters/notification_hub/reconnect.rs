@@ -0,0 +1,49 @@
+use tokio::time::Duration;
+
+/// Backoff of the attempts of a hub node to reconnect to its transport (reopen a serial port,
+/// reconnect to a WebSocket server...).
+///
+/// # Fields
+/// - `initial`: Delay before the first attempt, doubled after every failed attempt.
+/// - `max`: Maximum delay between attempts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReconnectBackoff {
+    pub initial: Duration,
+    pub max: Duration,
+}
+
+impl ReconnectBackoff {
+    /// Delay following an attempt after `delay`
+    pub fn next_delay(&self, delay: Duration) -> Duration {
+        delay.saturating_mul(2).min(self.max)
+    }
+}
+
+impl Default for ReconnectBackoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(5),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reconnect_backoff() {
+        let backoff = ReconnectBackoff {
+            initial: Duration::from_millis(100),
+            max: Duration::from_millis(300),
+        };
+        let delay = backoff.next_delay(backoff.initial);
+        assert_eq!(delay, Duration::from_millis(200));
+        assert_eq!(backoff.next_delay(delay), Duration::from_millis(300));
+        assert_eq!(
+            backoff.next_delay(Duration::MAX),
+            Duration::from_millis(300)
+        );
+    }
+}
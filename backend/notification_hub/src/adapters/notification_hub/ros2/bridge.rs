@@ -0,0 +1,373 @@
+use async_trait::async_trait;
+use futures_util::{pin_mut, StreamExt};
+use log::{error, info};
+use ros2_client::{
+    Context, Message, MessageTypeName, Name, Node, NodeName, NodeOptions, Publisher, Subscription,
+    DEFAULT_PUBLISHER_QOS, DEFAULT_SUBSCRIPTION_QOS,
+};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::{broadcast, mpsc, Mutex, RwLock};
+use tokio::task::JoinHandle;
+
+use super::schema::{Float64, Float64MultiArray, Imu, Ros2Message, Ros2Schema, StdString};
+use crate::adapters::lease::LeaseSlot;
+use crate::models::hub::{HubChannelName, HubMessage};
+use crate::ports::{NodeTasks, NotificationHub};
+
+const CHANNEL_CAPACITY: usize = 100;
+
+impl Message for StdString {}
+impl Message for Float64 {}
+impl Message for Float64MultiArray {}
+impl Message for Imu {}
+
+fn ros2_error(e: impl std::fmt::Debug) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Other, format!("{:?}", e))
+}
+
+/// Publisher of a ROS 2 topic, typed after the topic schema
+enum Ros2Publisher {
+    String(Publisher<StdString>),
+    Float64(Publisher<Float64>),
+    Float64MultiArray(Publisher<Float64MultiArray>),
+    Imu(Publisher<Imu>),
+}
+
+impl Ros2Publisher {
+    fn publish(&self, message: Ros2Message) -> Result<(), std::io::Error> {
+        match (self, message) {
+            (Self::String(publisher), Ros2Message::String(message)) => {
+                publisher.publish(message).map_err(ros2_error)
+            }
+            (Self::Float64(publisher), Ros2Message::Float64(message)) => {
+                publisher.publish(message).map_err(ros2_error)
+            }
+            (Self::Float64MultiArray(publisher), Ros2Message::Float64MultiArray(message)) => {
+                publisher.publish(message).map_err(ros2_error)
+            }
+            (Self::Imu(publisher), Ros2Message::Imu(message)) => {
+                publisher.publish(message).map_err(ros2_error)
+            }
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Message doesn't match the topic schema",
+            )),
+        }
+    }
+}
+
+/// Maps channel to a ROS 2 topic name: `sensors/imu` is mapped to `/sensors/imu`
+fn topic_name(channel: &HubChannelName) -> Result<Name, std::io::Error> {
+    let namespace = match channel.namespace() {
+        Some(namespace) => format!("/{}", namespace.as_str()),
+        None => "/".to_string(),
+    };
+    Name::new(&namespace, channel.base_name()).map_err(ros2_error)
+}
+
+/// `Ros2Bridge` is a hub node mapping hub channels to ROS 2 topics, so that robopilot coexists
+/// with existing ROS 2 stacks. It joins the DDS network directly, without a ROS 2 installation.
+///
+/// Channels are mapped to topics with the same name (`sensors/imu` is mapped to
+/// `/sensors/imu`). Messages are converted to the ROS 2 message type of the schema registered
+/// for their channel, or sent as `std_msgs/msg/String` if no schema is registered (see
+/// `Ros2Schema`). Every channel subscription creates a ROS 2 subscription, forwarded to the
+/// hub by the receive loop.
+///
+/// # Fields
+/// - `node`: ROS 2 node.
+/// - `schemas`: Schema of the channels not mapped to `std_msgs/msg/String`.
+/// - `publishers`: Publisher of every channel sent to ROS 2, created with the first message.
+/// - `subscriptions`: Tasks forwarding ROS 2 subscriptions, per channel.
+/// - `incoming`: Messages received from ROS 2 subscriptions, leased by the receive loop.
+/// - `channels`: Channels received so far.
+pub struct Ros2Bridge {
+    node: Mutex<Node>,
+    schemas: HashMap<HubChannelName, Ros2Schema>,
+    publishers: Mutex<HashMap<HubChannelName, Ros2Publisher>>,
+    subscriptions: Mutex<HashMap<HubChannelName, JoinHandle<()>>>,
+    incoming: mpsc::Sender<HubMessage>,
+    incoming_receiver: LeaseSlot<mpsc::Receiver<HubMessage>>,
+    channels: Arc<RwLock<HashSet<HubChannelName>>>,
+}
+
+impl std::fmt::Debug for Ros2Bridge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Ros2Bridge")
+            .field("schemas", &self.schemas)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Ros2Bridge {
+    /// Creates ROS 2 node `node_name` in the default DDS domain
+    pub fn new(node_name: &str) -> Result<Self, std::io::Error> {
+        let context = Context::new().map_err(ros2_error)?;
+        let node = context
+            .new_node(
+                NodeName::new("/", node_name).map_err(ros2_error)?,
+                NodeOptions::new().enable_rosout(true),
+            )
+            .map_err(ros2_error)?;
+        info!("ROS 2 node {} created", node_name);
+        let (incoming, incoming_receiver) = mpsc::channel(CHANNEL_CAPACITY);
+        Ok(Self {
+            node: Mutex::new(node),
+            schemas: HashMap::new(),
+            publishers: Mutex::new(HashMap::new()),
+            subscriptions: Mutex::new(HashMap::new()),
+            incoming,
+            incoming_receiver: LeaseSlot::new(incoming_receiver),
+            channels: Arc::new(RwLock::new(HashSet::new())),
+        })
+    }
+
+    /// Registers the schema of the topic of `channel`
+    pub fn with_schema(mut self, channel: HubChannelName, schema: Ros2Schema) -> Self {
+        self.schemas.insert(channel, schema);
+        self
+    }
+
+    fn schema(&self, channel: &HubChannelName) -> Ros2Schema {
+        self.schemas.get(channel).cloned().unwrap_or_default()
+    }
+
+    async fn create_publisher(
+        &self,
+        channel: &HubChannelName,
+    ) -> Result<Ros2Publisher, std::io::Error> {
+        let schema = self.schema(channel);
+        let (package, name) = schema.message_type();
+        let mut node = self.node.lock().await;
+        let topic = node
+            .create_topic(
+                &topic_name(channel)?,
+                MessageTypeName::new(package, name),
+                &DEFAULT_PUBLISHER_QOS,
+            )
+            .map_err(ros2_error)?;
+        let publisher = match schema {
+            Ros2Schema::String => {
+                Ros2Publisher::String(node.create_publisher(&topic, None).map_err(ros2_error)?)
+            }
+            Ros2Schema::Float64 => {
+                Ros2Publisher::Float64(node.create_publisher(&topic, None).map_err(ros2_error)?)
+            }
+            Ros2Schema::Float64MultiArray => Ros2Publisher::Float64MultiArray(
+                node.create_publisher(&topic, None).map_err(ros2_error)?,
+            ),
+            Ros2Schema::Imu { .. } => {
+                Ros2Publisher::Imu(node.create_publisher(&topic, None).map_err(ros2_error)?)
+            }
+        };
+        Ok(publisher)
+    }
+
+    async fn create_subscription(
+        &self,
+        channel: &HubChannelName,
+    ) -> Result<JoinHandle<()>, std::io::Error> {
+        let schema = self.schema(channel);
+        let (package, name) = schema.message_type();
+        let mut node = self.node.lock().await;
+        let topic = node
+            .create_topic(
+                &topic_name(channel)?,
+                MessageTypeName::new(package, name),
+                &DEFAULT_SUBSCRIPTION_QOS,
+            )
+            .map_err(ros2_error)?;
+        let channel = channel.clone();
+        let incoming = self.incoming.clone();
+        let task = match schema {
+            Ros2Schema::String => forward(
+                node.create_subscription(&topic, None).map_err(ros2_error)?,
+                channel,
+                Ros2Message::String,
+                incoming,
+            ),
+            Ros2Schema::Float64 => forward(
+                node.create_subscription(&topic, None).map_err(ros2_error)?,
+                channel,
+                Ros2Message::Float64,
+                incoming,
+            ),
+            Ros2Schema::Float64MultiArray => forward(
+                node.create_subscription(&topic, None).map_err(ros2_error)?,
+                channel,
+                Ros2Message::Float64MultiArray,
+                incoming,
+            ),
+            Ros2Schema::Imu { .. } => forward(
+                node.create_subscription(&topic, None).map_err(ros2_error)?,
+                channel,
+                Ros2Message::Imu,
+                incoming,
+            ),
+        };
+        Ok(task)
+    }
+}
+
+// Forwards messages of ROS 2 subscription to the hub
+fn forward<M>(
+    subscription: Subscription<M>,
+    channel: HubChannelName,
+    wrap: fn(M) -> Ros2Message,
+    incoming: mpsc::Sender<HubMessage>,
+) -> JoinHandle<()>
+where
+    M: Message + Send + 'static,
+{
+    tokio::spawn(async move {
+        let stream = subscription.async_stream();
+        pin_mut!(stream);
+        while let Some(result) = stream.next().await {
+            let data = match result {
+                Ok((message, _info)) => wrap(message).to_hub_data(),
+                Err(e) => Err(format!("{:?}", e)),
+            };
+            match data {
+                Ok(data) => {
+                    let message = HubMessage::new(channel.clone(), data);
+                    if incoming.send(message).await.is_err() {
+                        break;
+                    }
+                }
+                Err(e) => error!("Invalid ROS 2 message on {}: {}", channel.as_str(), e),
+            }
+        }
+    })
+}
+
+#[async_trait]
+impl NotificationHub for Ros2Bridge {
+    /// Publish message on the topic of its channel
+    async fn send(&self, data: HubMessage) -> Result<(), std::io::Error> {
+        let message = Ros2Message::from_hub(&self.schema(&data.channel), &data)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        let mut publishers = self.publishers.lock().await;
+        if !publishers.contains_key(&data.channel) {
+            let publisher = self.create_publisher(&data.channel).await?;
+            publishers.insert(data.channel.clone(), publisher);
+        }
+        match publishers.get(&data.channel) {
+            Some(publisher) => publisher.publish(message),
+            None => Ok(()),
+        }
+    }
+
+    /// List channels received so far
+    async fn list_channels(&self) -> Result<Vec<HubChannelName>, std::io::Error> {
+        Ok(self.channels.read().await.iter().cloned().collect())
+    }
+
+    /// Start ROS 2 node spinner, and forward messages of subscribed channels
+    async fn start(
+        &self,
+        sender: Option<broadcast::Sender<HubMessage>>,
+    ) -> Result<NodeTasks, std::io::Error> {
+        let mut tasks = NodeTasks::new();
+        // spinner processes ROS 2 discovery and parameter events
+        let spinner = self.node.lock().await.spinner().map_err(ros2_error)?;
+        tasks.push(tokio::spawn(async move {
+            if let Err(e) = spinner.spin().await {
+                error!("ROS 2 spinner error: {:?}", e);
+            }
+        }));
+
+        if let Some(sender) = sender {
+            let mut incoming = self.incoming_receiver.lease("ROS 2 bridge")?;
+            let channels = Arc::clone(&self.channels);
+            let task = tokio::spawn(async move {
+                while let Some(message) = incoming.recv().await {
+                    channels.write().await.insert(message.channel.clone());
+                    let _ = sender.send(message);
+                }
+            });
+            tasks.push(task);
+        }
+        Ok(tasks)
+    }
+
+    async fn subscribe(&self, channel: HubChannelName) -> Result<(), std::io::Error> {
+        let mut subscriptions = self.subscriptions.lock().await;
+        if subscriptions.contains_key(&channel) {
+            return Ok(());
+        }
+        let task = self.create_subscription(&channel).await?;
+        subscriptions.insert(channel, task);
+        Ok(())
+    }
+
+    async fn unsubscribe(&self, channel: HubChannelName) -> Result<(), std::io::Error> {
+        // ROS 2 subscription is dropped with its task
+        if let Some(task) = self.subscriptions.lock().await.remove(&channel) {
+            task.abort();
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Ros2Bridge {
+    fn drop(&mut self) {
+        for task in self.subscriptions.get_mut().values() {
+            task.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time::{timeout, Duration};
+
+    #[test]
+    fn test_topic_names() {
+        let channel = HubChannelName::try_from("sensors/imu").unwrap();
+        let name = topic_name(&channel).unwrap();
+        assert_eq!(name.to_string(), "/sensors/imu");
+
+        let channel = HubChannelName::try_from("cmd_vel").unwrap();
+        assert_eq!(topic_name(&channel).unwrap().to_string(), "/cmd_vel");
+    }
+
+    // Requires DDS discovery over multicast
+    #[tokio::test]
+    #[ignore]
+    async fn test_ros2_bridge() {
+        let channel = HubChannelName::try_from("sensors/imu").unwrap();
+        let schema = Ros2Schema::Imu {
+            frame_id: "imu_link".to_string(),
+        };
+        let node1 = Ros2Bridge::new("robopilot_test_1")
+            .unwrap()
+            .with_schema(channel.clone(), schema.clone());
+        let node2 = Ros2Bridge::new("robopilot_test_2")
+            .unwrap()
+            .with_schema(channel.clone(), schema);
+        node1.start(None).await.unwrap();
+        let (sender, mut receiver) = broadcast::channel(10);
+        node2.start(Some(sender)).await.unwrap();
+        node2.subscribe(channel.clone()).await.unwrap();
+
+        // DDS discovery takes a while
+        let received = timeout(Duration::from_secs(10), async {
+            loop {
+                node1
+                    .send(HubMessage::try_from_str("sensors/imu", "0,0,9.8,0,0,0").unwrap())
+                    .await
+                    .unwrap();
+                if let Ok(Ok(message)) = timeout(Duration::from_millis(100), receiver.recv()).await
+                {
+                    return message;
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(received.channel, channel);
+        assert_eq!(received.data.as_str(), "0,0,9.8,0,0,0");
+    }
+}
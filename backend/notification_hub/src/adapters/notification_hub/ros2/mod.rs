@@ -0,0 +1,6 @@
+/// ROS 2 bridge, mapping hub channels to ROS 2 topics.
+pub mod bridge;
+pub mod schema;
+
+pub use bridge::Ros2Bridge;
+pub use schema::{Ros2Message, Ros2Schema};
@@ -0,0 +1,269 @@
+use serde::{Deserialize, Serialize};
+
+use crate::models::hub::{HubData, HubMessage};
+
+/// Schema of the ROS 2 topic a hub channel is mapped to.
+///
+/// `HubData` is converted from and to the ROS 2 message type of the schema:
+/// - `String`: `std_msgs/msg/String`. Data is sent as is.
+/// - `Float64`: `std_msgs/msg/Float64`. Data is a single number.
+/// - `Float64MultiArray`: `std_msgs/msg/Float64MultiArray`. Data is a comma separated list of
+///   numbers.
+/// - `Imu`: `sensor_msgs/msg/Imu`. Data is `ax,ay,az,gx,gy,gz`, with linear acceleration in m/s²
+///   and angular velocity in rad/s. Orientation is not provided.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Ros2Schema {
+    #[default]
+    String,
+    Float64,
+    Float64MultiArray,
+    Imu {
+        frame_id: String,
+    },
+}
+
+impl Ros2Schema {
+    /// ROS 2 package and name of the message type
+    pub fn message_type(&self) -> (&'static str, &'static str) {
+        match self {
+            Self::String => ("std_msgs", "String"),
+            Self::Float64 => ("std_msgs", "Float64"),
+            Self::Float64MultiArray => ("std_msgs", "Float64MultiArray"),
+            Self::Imu { .. } => ("sensor_msgs", "Imu"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StdString {
+    pub data: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Float64 {
+    pub data: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MultiArrayDimension {
+    pub label: String,
+    pub size: u32,
+    pub stride: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MultiArrayLayout {
+    pub dim: Vec<MultiArrayDimension>,
+    pub data_offset: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Float64MultiArray {
+    pub layout: MultiArrayLayout,
+    pub data: Vec<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Time {
+    pub sec: i32,
+    pub nanosec: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Header {
+    pub stamp: Time,
+    pub frame_id: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Quaternion {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+    pub w: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Vector3 {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Imu {
+    pub header: Header,
+    pub orientation: Quaternion,
+    pub orientation_covariance: [f64; 9],
+    pub angular_velocity: Vector3,
+    pub angular_velocity_covariance: [f64; 9],
+    pub linear_acceleration: Vector3,
+    pub linear_acceleration_covariance: [f64; 9],
+}
+
+/// ROS 2 message of one of the supported schemas
+#[derive(Debug, Clone, PartialEq)]
+pub enum Ros2Message {
+    String(StdString),
+    Float64(Float64),
+    Float64MultiArray(Float64MultiArray),
+    Imu(Imu),
+}
+
+fn parse_values(data: &str) -> Result<Vec<f64>, String> {
+    data.split(',')
+        .map(|value| {
+            value
+                .trim()
+                .parse::<f64>()
+                .map_err(|e| format!("Invalid number {}: {}", value, e))
+        })
+        .collect()
+}
+
+fn format_values(values: &[f64]) -> String {
+    values
+        .iter()
+        .map(|value| value.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+impl Ros2Message {
+    /// Converts hub message into a ROS 2 message of `schema`
+    pub fn from_hub(schema: &Ros2Schema, message: &HubMessage) -> Result<Self, String> {
+        let data = message.data.as_str();
+        match schema {
+            Ros2Schema::String => Ok(Self::String(StdString {
+                data: data.to_string(),
+            })),
+            Ros2Schema::Float64 => {
+                let data = data
+                    .trim()
+                    .parse::<f64>()
+                    .map_err(|e| format!("Invalid number {}: {}", data, e))?;
+                Ok(Self::Float64(Float64 { data }))
+            }
+            Ros2Schema::Float64MultiArray => {
+                let data = parse_values(data)?;
+                Ok(Self::Float64MultiArray(Float64MultiArray {
+                    layout: MultiArrayLayout {
+                        dim: vec![MultiArrayDimension {
+                            label: String::new(),
+                            size: data.len() as u32,
+                            stride: data.len() as u32,
+                        }],
+                        data_offset: 0,
+                    },
+                    data,
+                }))
+            }
+            Ros2Schema::Imu { frame_id } => {
+                let values = parse_values(data)?;
+                let [ax, ay, az, gx, gy, gz] = values[..] else {
+                    return Err(format!("Expected 6 IMU values, received {}", values.len()));
+                };
+                // orientation covariance starting with -1 flags orientation as unknown
+                let mut orientation_covariance = [0.0; 9];
+                orientation_covariance[0] = -1.0;
+                Ok(Self::Imu(Imu {
+                    header: Header {
+                        stamp: Time {
+                            sec: message.timestamp.wall.trunc() as i32,
+                            nanosec: (message.timestamp.wall.fract() * 1e9) as u32,
+                        },
+                        frame_id: frame_id.clone(),
+                    },
+                    orientation: Quaternion {
+                        x: 0.0,
+                        y: 0.0,
+                        z: 0.0,
+                        w: 1.0,
+                    },
+                    orientation_covariance,
+                    angular_velocity: Vector3 {
+                        x: gx,
+                        y: gy,
+                        z: gz,
+                    },
+                    angular_velocity_covariance: [0.0; 9],
+                    linear_acceleration: Vector3 {
+                        x: ax,
+                        y: ay,
+                        z: az,
+                    },
+                    linear_acceleration_covariance: [0.0; 9],
+                }))
+            }
+        }
+    }
+
+    /// Converts ROS 2 message into hub data, with the format of its schema
+    pub fn to_hub_data(&self) -> Result<HubData, String> {
+        let data = match self {
+            Self::String(message) => message.data.clone(),
+            Self::Float64(message) => message.data.to_string(),
+            Self::Float64MultiArray(message) => format_values(&message.data),
+            Self::Imu(message) => format_values(&[
+                message.linear_acceleration.x,
+                message.linear_acceleration.y,
+                message.linear_acceleration.z,
+                message.angular_velocity.x,
+                message.angular_velocity.y,
+                message.angular_velocity.z,
+            ]),
+        };
+        HubData::try_from(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::hub::HubTimestamp;
+
+    fn round_trip(schema: &Ros2Schema, data: &str) -> Result<String, String> {
+        let message = HubMessage::try_from_str("topic", data)?;
+        let ros2_message = Ros2Message::from_hub(schema, &message)?;
+        Ok(ros2_message.to_hub_data()?.as_str().to_string())
+    }
+
+    #[test]
+    fn test_schemas() {
+        assert_eq!(round_trip(&Ros2Schema::String, "hello").unwrap(), "hello");
+        assert_eq!(round_trip(&Ros2Schema::Float64, "1.5").unwrap(), "1.5");
+        assert!(round_trip(&Ros2Schema::Float64, "one").is_err());
+        assert_eq!(
+            round_trip(&Ros2Schema::Float64MultiArray, "1, 2.5,3").unwrap(),
+            "1,2.5,3"
+        );
+
+        let imu = Ros2Schema::Imu {
+            frame_id: "imu_link".to_string(),
+        };
+        assert_eq!(
+            round_trip(&imu, "0,0,9.8,0.1,0,0").unwrap(),
+            "0,0,9.8,0.1,0,0"
+        );
+        assert!(round_trip(&imu, "0,0,9.8").is_err());
+    }
+
+    #[test]
+    fn test_imu_message() {
+        let schema = Ros2Schema::Imu {
+            frame_id: "imu_link".to_string(),
+        };
+        let mut message = HubMessage::try_from_str("imu", "1,2,3,4,5,6").unwrap();
+        message.timestamp = HubTimestamp::from_wall(12.5);
+        let Ros2Message::Imu(imu) = Ros2Message::from_hub(&schema, &message).unwrap() else {
+            panic!("Expected IMU message");
+        };
+        assert_eq!(imu.header.frame_id, "imu_link");
+        assert_eq!(imu.header.stamp.sec, 12);
+        assert_eq!(imu.header.stamp.nanosec, 500_000_000);
+        assert_eq!(imu.linear_acceleration.z, 3.0);
+        assert_eq!(imu.angular_velocity.x, 4.0);
+        assert_eq!(imu.orientation_covariance[0], -1.0);
+        assert_eq!(schema.message_type(), ("sensor_msgs", "Imu"));
+    }
+}
@@ -0,0 +1,248 @@
+use crate::models::hub::HubData;
+
+/// Binary type of a sample field. Multi-byte types are either little (`Le`) or big (`Be`)
+/// endian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldKind {
+    U8,
+    I8,
+    U16Le,
+    U16Be,
+    I16Le,
+    I16Be,
+    U32Le,
+    U32Be,
+    I32Le,
+    I32Be,
+    F32Le,
+    F32Be,
+}
+
+impl FieldKind {
+    /// Size of the field in bytes
+    pub fn size(&self) -> usize {
+        match self {
+            Self::U8 | Self::I8 => 1,
+            Self::U16Le | Self::U16Be | Self::I16Le | Self::I16Be => 2,
+            Self::U32Le | Self::U32Be | Self::I32Le | Self::I32Be | Self::F32Le | Self::F32Be => 4,
+        }
+    }
+}
+
+/// Field of a binary sample read from a device (e.g. a register of an I2C sensor).
+///
+/// The physical value of the field is `raw * scale + bias`. When a mask is set, the raw value
+/// of integer fields keeps only the masked bits, shifted down to bit 0 (e.g. mask `0x03ff`
+/// keeps the 10 bit reading of an ADC). Masked signed fields are sign extended from the most
+/// significant bit of the mask.
+///
+/// # Fields
+/// - `name`: Field name, for error messages.
+/// - `offset`: Position of the field in the sample, in bytes.
+/// - `kind`: Binary type of the field.
+/// - `mask`: Bits of the raw value belonging to the field.
+/// - `scale`: Scale of the physical value.
+/// - `bias`: Bias of the physical value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SampleField {
+    pub name: String,
+    pub offset: usize,
+    pub kind: FieldKind,
+    pub mask: Option<u32>,
+    pub scale: f64,
+    pub bias: f64,
+}
+
+impl SampleField {
+    pub fn new(name: &str, offset: usize, kind: FieldKind) -> Self {
+        Self {
+            name: name.to_string(),
+            offset,
+            kind,
+            mask: None,
+            scale: 1.0,
+            bias: 0.0,
+        }
+    }
+
+    pub fn masked(mut self, mask: u32) -> Self {
+        self.mask = Some(mask);
+        self
+    }
+
+    pub fn scaled(mut self, scale: f64, bias: f64) -> Self {
+        self.scale = scale;
+        self.bias = bias;
+        self
+    }
+
+    fn end(&self) -> usize {
+        self.offset + self.kind.size()
+    }
+
+    // Applies mask to raw value, sign extending it for signed fields
+    fn apply_mask(&self, raw: u32, signed: bool) -> f64 {
+        let Some(mask) = self.mask.filter(|mask| *mask != 0) else {
+            return if signed {
+                match self.kind.size() {
+                    1 => raw as u8 as i8 as f64,
+                    2 => raw as u16 as i16 as f64,
+                    _ => raw as i32 as f64,
+                }
+            } else {
+                raw as f64
+            };
+        };
+        let value = (raw & mask) >> mask.trailing_zeros();
+        let bits = 32 - (mask >> mask.trailing_zeros()).leading_zeros();
+        if signed && bits < 32 && (value >> (bits - 1)) & 1 == 1 {
+            (value | (u32::MAX << bits)) as i32 as f64
+        } else if signed {
+            value as i32 as f64
+        } else {
+            value as f64
+        }
+    }
+
+    /// Physical value of the field in `sample`
+    pub fn decode(&self, sample: &[u8]) -> Result<f64, String> {
+        let bytes = sample
+            .get(self.offset..self.end())
+            .ok_or_else(|| format!("Sample too short for field {}", self.name))?;
+        let raw = match self.kind {
+            FieldKind::U8 | FieldKind::I8 => bytes[0] as u32,
+            FieldKind::U16Le | FieldKind::I16Le => u16::from_le_bytes([bytes[0], bytes[1]]) as u32,
+            FieldKind::U16Be | FieldKind::I16Be => u16::from_be_bytes([bytes[0], bytes[1]]) as u32,
+            FieldKind::U32Le | FieldKind::I32Le | FieldKind::F32Le => {
+                u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+            }
+            FieldKind::U32Be | FieldKind::I32Be | FieldKind::F32Be => {
+                u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+            }
+        };
+        let value = match self.kind {
+            FieldKind::F32Le | FieldKind::F32Be => f32::from_bits(raw) as f64,
+            FieldKind::I8
+            | FieldKind::I16Le
+            | FieldKind::I16Be
+            | FieldKind::I32Le
+            | FieldKind::I32Be => self.apply_mask(raw, true),
+            _ => self.apply_mask(raw, false),
+        };
+        Ok(value * self.scale + self.bias)
+    }
+}
+
+/// Layout of a binary sample, decoded into the comma separated physical values of its fields
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SampleLayout {
+    fields: Vec<SampleField>,
+}
+
+impl SampleLayout {
+    pub fn new(fields: Vec<SampleField>) -> Result<Self, String> {
+        if fields.is_empty() {
+            return Err("Sample layout without fields".to_string());
+        }
+        for field in &fields {
+            if !field.scale.is_finite() || field.scale == 0.0 || !field.bias.is_finite() {
+                return Err(format!("Field {} has an invalid scale", field.name));
+            }
+        }
+        Ok(Self { fields })
+    }
+
+    /// Length in bytes of the samples
+    pub fn len(&self) -> usize {
+        self.fields.iter().map(SampleField::end).max().unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    pub fn decode(&self, sample: &[u8]) -> Result<HubData, String> {
+        let values = self
+            .fields
+            .iter()
+            .map(|field| field.decode(sample).map(|value| value.to_string()))
+            .collect::<Result<Vec<_>, _>>()?;
+        HubData::try_from(values.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fields() {
+        let sample = [0xff, 0x38, 0x01, 0x00, 0x00, 0x80, 0x3f];
+        assert_eq!(
+            SampleField::new("a", 0, FieldKind::U8)
+                .decode(&sample)
+                .unwrap(),
+            255.0
+        );
+        assert_eq!(
+            SampleField::new("a", 0, FieldKind::I8)
+                .decode(&sample)
+                .unwrap(),
+            -1.0
+        );
+        assert_eq!(
+            SampleField::new("a", 1, FieldKind::U16Le)
+                .decode(&sample)
+                .unwrap(),
+            312.0
+        );
+        assert_eq!(
+            SampleField::new("a", 0, FieldKind::I16Be)
+                .decode(&sample)
+                .unwrap(),
+            -200.0
+        );
+        assert_eq!(
+            SampleField::new("a", 3, FieldKind::F32Le)
+                .decode(&sample)
+                .unwrap(),
+            1.0
+        );
+        assert!(SampleField::new("a", 4, FieldKind::U32Le)
+            .decode(&sample)
+            .is_err());
+    }
+
+    #[test]
+    fn test_masked_fields() {
+        // 10 bit reading of an MCP3008 ADC
+        let adc = SampleField::new("adc", 1, FieldKind::U16Be).masked(0x03ff);
+        assert_eq!(adc.decode(&[0xff, 0xfe, 0x10]).unwrap(), 528.0);
+
+        // 12 bit signed reading, left aligned
+        let signed = SampleField::new("temp", 0, FieldKind::I16Be).masked(0xfff0);
+        assert_eq!(signed.decode(&[0xff, 0xf0]).unwrap(), -1.0);
+        assert_eq!(signed.decode(&[0x01, 0x00]).unwrap(), 16.0);
+    }
+
+    #[test]
+    fn test_layout() {
+        let layout = SampleLayout::new(vec![
+            SampleField::new("x", 0, FieldKind::I16Be).scaled(1.0 / 16384.0, 0.0),
+            SampleField::new("y", 2, FieldKind::I16Be).scaled(1.0 / 16384.0, 0.0),
+            SampleField::new("z", 4, FieldKind::I16Be).scaled(1.0 / 16384.0, 0.0),
+        ])
+        .unwrap();
+        assert_eq!(layout.len(), 6);
+        let data = layout
+            .decode(&[0x40, 0x00, 0xc0, 0x00, 0x20, 0x00])
+            .unwrap();
+        assert_eq!(data.as_str(), "1,-1,0.5");
+
+        assert!(SampleLayout::new(vec![]).is_err());
+        assert!(SampleLayout::new(vec![
+            SampleField::new("x", 0, FieldKind::U8).scaled(0.0, 0.0)
+        ])
+        .is_err());
+    }
+}
@@ -0,0 +1,251 @@
+use bytes::{Bytes, BytesMut};
+use log::warn;
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+const BUFFER_SIZE: usize = 1024;
+const MAX_POOLED_BUFFERS: usize = 16;
+/// Maximum length of a line. Longer lines are discarded.
+pub const MAX_LINE_LEN: usize = 128 * 1024;
+
+/// `BufferPool` keeps a set of reusable read buffers, so that serial and pipe readers
+/// don't allocate new buffers every time they are (re)started.
+///
+/// # Fields
+/// - `buffers`: Idle buffers ready to be reused.
+/// - `buffer_size`: Minimum capacity of the buffers handed out by the pool.
+/// - `max_buffers`: Maximum number of idle buffers kept in the pool.
+#[derive(Debug)]
+pub struct BufferPool {
+    buffers: Mutex<Vec<BytesMut>>,
+    buffer_size: usize,
+    max_buffers: usize,
+}
+
+impl BufferPool {
+    pub fn new(buffer_size: usize, max_buffers: usize) -> Self {
+        Self {
+            buffers: Mutex::new(Vec::new()),
+            buffer_size,
+            max_buffers,
+        }
+    }
+
+    /// Pool shared by all readers in the process
+    pub fn shared() -> Arc<BufferPool> {
+        static POOL: OnceLock<Arc<BufferPool>> = OnceLock::new();
+        POOL.get_or_init(|| Arc::new(BufferPool::new(BUFFER_SIZE, MAX_POOLED_BUFFERS)))
+            .clone()
+    }
+
+    /// Returns an empty buffer, reusing an idle one if available
+    pub fn acquire(&self) -> BytesMut {
+        let buffer = self
+            .buffers
+            .lock()
+            .ok()
+            .and_then(|mut buffers| buffers.pop());
+        buffer.unwrap_or_else(|| BytesMut::with_capacity(self.buffer_size))
+    }
+
+    /// Returns a buffer to the pool. Buffers are dropped if the pool is full.
+    pub fn release(&self, mut buffer: BytesMut) {
+        buffer.clear();
+        if let Ok(mut buffers) = self.buffers.lock() {
+            if buffers.len() < self.max_buffers {
+                buffers.push(buffer);
+            }
+        }
+    }
+
+    #[cfg(test)]
+    fn idle(&self) -> usize {
+        self.buffers
+            .lock()
+            .map(|buffers| buffers.len())
+            .unwrap_or(0)
+    }
+}
+
+/// `LineBuffer` accumulates bytes read from a stream and splits them in lines without copying.
+/// Lines are returned as `Bytes` views of the underlying buffer, which is reclaimed once the lines
+/// are dropped. The buffer is returned to its `BufferPool` when the `LineBuffer` is dropped.
+#[derive(Debug)]
+pub struct LineBuffer {
+    buffer: Option<BytesMut>,
+    pool: Arc<BufferPool>,
+}
+
+impl LineBuffer {
+    pub fn new() -> Self {
+        Self::with_pool(BufferPool::shared())
+    }
+
+    pub fn with_pool(pool: Arc<BufferPool>) -> Self {
+        Self {
+            buffer: Some(pool.acquire()),
+            pool,
+        }
+    }
+
+    fn buffer(&mut self) -> &mut BytesMut {
+        self.buffer.get_or_insert_with(BytesMut::new)
+    }
+
+    /// Reads available bytes from `reader` into the buffer. Returns the number of bytes read.
+    pub async fn read_from<R: AsyncRead + Unpin>(
+        &mut self,
+        reader: &mut R,
+    ) -> Result<usize, std::io::Error> {
+        let buffer_size = self.pool.buffer_size;
+        let buffer = self.buffer();
+        buffer.reserve(buffer_size);
+        reader.read_buf(buffer).await
+    }
+
+    /// Appends bytes to the buffer
+    #[allow(dead_code)]
+    pub fn extend_from_slice(&mut self, data: &[u8]) {
+        self.buffer().extend_from_slice(data);
+    }
+
+    /// Returns the next complete line, including the trailing '\n', if any.
+    /// Pending bytes are discarded once they exceed `MAX_LINE_LEN` without a line terminator,
+    /// so that a peer never sending '\n' can't grow the buffer without bounds.
+    pub fn next_line(&mut self) -> Option<Bytes> {
+        self.next_delimited(b'\n')
+    }
+
+    /// Returns the next chunk terminated by `delimiter`, including it, if any. Pending bytes
+    /// are discarded once they exceed `MAX_LINE_LEN` without delimiter.
+    pub fn next_delimited(&mut self, delimiter: u8) -> Option<Bytes> {
+        let buffer = self.buffer();
+        match buffer.iter().position(|&b| b == delimiter) {
+            Some(pos) => Some(buffer.split_to(pos + 1).freeze()),
+            None => {
+                if buffer.len() > MAX_LINE_LEN {
+                    warn!("Discarding {} bytes without delimiter", buffer.len());
+                    buffer.clear();
+                }
+                None
+            }
+        }
+    }
+
+    /// Returns pending bytes not terminated by '\n', if any.
+    pub fn take_remaining(&mut self) -> Option<Bytes> {
+        let buffer = self.buffer();
+        if buffer.is_empty() {
+            return None;
+        }
+        Some(buffer.split().freeze())
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.buffer
+            .as_ref()
+            .map(|buffer| buffer.is_empty())
+            .unwrap_or(true)
+    }
+}
+
+impl Default for LineBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for LineBuffer {
+    fn drop(&mut self) {
+        if let Some(buffer) = self.buffer.take() {
+            self.pool.release(buffer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pool_reuses_buffers() {
+        let pool = BufferPool::new(64, 2);
+        let buffer = pool.acquire();
+        assert!(buffer.capacity() >= 64);
+        pool.release(buffer);
+        assert_eq!(pool.idle(), 1);
+        let _ = pool.acquire();
+        assert_eq!(pool.idle(), 0);
+    }
+
+    #[test]
+    fn test_pool_is_bounded() {
+        let pool = BufferPool::new(64, 1);
+        pool.release(BytesMut::new());
+        pool.release(BytesMut::new());
+        assert_eq!(pool.idle(), 1);
+    }
+
+    #[test]
+    fn test_line_buffer_splits_lines() {
+        let mut lines = LineBuffer::with_pool(Arc::new(BufferPool::new(64, 1)));
+        lines.extend_from_slice(b"##ch1## 1\n##ch2");
+        assert_eq!(&lines.next_line().unwrap()[..], b"##ch1## 1\n");
+        assert!(lines.next_line().is_none());
+
+        lines.extend_from_slice(b"## 2\n");
+        assert_eq!(&lines.next_line().unwrap()[..], b"##ch2## 2\n");
+        assert!(lines.is_empty());
+    }
+
+    #[test]
+    fn test_line_buffer_splits_delimited() {
+        let mut lines = LineBuffer::with_pool(Arc::new(BufferPool::new(64, 1)));
+        lines.extend_from_slice(b"\x03ab\x00\x02c");
+        assert_eq!(&lines.next_delimited(0).unwrap()[..], b"\x03ab\x00");
+        assert!(lines.next_delimited(0).is_none());
+        assert_eq!(&lines.take_remaining().unwrap()[..], b"\x02c");
+    }
+
+    #[test]
+    fn test_line_buffer_take_remaining() {
+        let mut lines = LineBuffer::with_pool(Arc::new(BufferPool::new(64, 1)));
+        assert!(lines.take_remaining().is_none());
+        lines.extend_from_slice(b"partial");
+        assert_eq!(&lines.take_remaining().unwrap()[..], b"partial");
+        assert!(lines.is_empty());
+    }
+
+    #[test]
+    fn test_line_buffer_discards_long_lines() {
+        let mut lines = LineBuffer::with_pool(Arc::new(BufferPool::new(64, 1)));
+        lines.extend_from_slice(&vec![b'a'; MAX_LINE_LEN]);
+        assert!(lines.next_line().is_none());
+        assert!(!lines.is_empty());
+
+        lines.extend_from_slice(b"a");
+        assert!(lines.next_line().is_none());
+        assert!(lines.is_empty());
+        lines.extend_from_slice(b"line\n");
+        assert_eq!(&lines.next_line().unwrap()[..], b"line\n");
+    }
+
+    #[test]
+    fn test_line_buffer_returns_buffer_to_pool() {
+        let pool = Arc::new(BufferPool::new(64, 1));
+        {
+            let _lines = LineBuffer::with_pool(Arc::clone(&pool));
+        }
+        assert_eq!(pool.idle(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_line_buffer_read_from() {
+        let mut lines = LineBuffer::with_pool(Arc::new(BufferPool::new(64, 1)));
+        let mut reader: &[u8] = b"##ch1## 1\n";
+        let n = lines.read_from(&mut reader).await.unwrap();
+        assert_eq!(n, 10);
+        assert_eq!(&lines.next_line().unwrap()[..], b"##ch1## 1\n");
+    }
+}
@@ -1,5 +1,6 @@
 use std::collections::HashSet;
 
+use crate::models::hub::hub_channel_name::MAX_CHANNEL_NAME_LEN;
 use crate::models::hub::HubChannelName;
 
 /// Module abstracts functionality for topic channels through a Serial port.
@@ -39,10 +40,25 @@ impl TryFrom<&str> for SerialChannelName {
             .filter(|&c| !c.is_whitespace() && c != '\n' && c != '\r')
             .collect();
 
-        if name.chars().all(|c| c.is_alphanumeric() || c == '_') {
-            return Ok(SerialChannelName(name));
+        if name.is_empty() || name.len() > MAX_CHANNEL_NAME_LEN {
+            return Err(format!(
+                "Invalid channel name. Length must be between 1 and {} bytes.",
+                MAX_CHANNEL_NAME_LEN
+            ));
         }
-        Err("Invalid channel name. Only alphanumeric and '_' characters allowed.".to_string())
+        if !name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '/')
+        {
+            return Err(
+                "Invalid channel name. Only alphanumeric, '_' and '/' characters allowed."
+                    .to_string(),
+            );
+        }
+        // Serial channels are converted into hub channels, so they follow the same
+        // namespace rules
+        HubChannelName::try_from(name.as_str())?;
+        Ok(SerialChannelName(name))
     }
 }
 
@@ -76,6 +92,11 @@ impl SerialPubChannels {
         self.channels.insert(channel_name);
     }
 
+    /// Replaces the channels with `channels`
+    pub fn replace(&mut self, channels: impl IntoIterator<Item = SerialChannelName>) {
+        self.channels = channels.into_iter().collect();
+    }
+
     #[allow(dead_code)]
     pub fn remove(&mut self, channel_name: SerialChannelName) {
         self.channels.remove(&channel_name);
@@ -154,6 +175,17 @@ mod tests {
         channels.remove(channel_name2.clone());
     }
 
+    #[test]
+    fn test_replace_channels() {
+        let mut channels = SerialPubChannels::new();
+        let channel_name1 = SerialChannelName::try_from("example1").unwrap();
+        let channel_name2 = SerialChannelName::try_from("example2").unwrap();
+        channels.add(channel_name1.clone());
+        channels.replace(vec![channel_name2.clone()]);
+        let collected: Vec<_> = channels.iter().collect();
+        assert_eq!(collected, vec![channel_name2]);
+    }
+
     #[test]
     fn test_iter_channels() {
         let mut channels = SerialPubChannels::new();
@@ -187,6 +219,12 @@ mod tests {
         assert_eq!(channel_name_str, "example".to_string());
     }
 
+    #[test]
+    fn test_invalid_channel_name_length() {
+        assert!(SerialChannelName::try_from("").is_err());
+        assert!(SerialChannelName::try_from("a".repeat(MAX_CHANNEL_NAME_LEN + 1)).is_err());
+    }
+
     #[test]
     fn test_tag() {
         let channel_name = SerialChannelName::try_from("example").unwrap();
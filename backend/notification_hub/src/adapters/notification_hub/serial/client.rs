@@ -1,60 +1,493 @@
 use async_trait::async_trait;
 use log::{error, info, warn};
-use serialport::SerialPort;
-use std::sync::Arc;
-use tokio::io::AsyncReadExt;
-use tokio::sync::{broadcast, RwLock};
-use tokio_serial::{DataBits, Parity, SerialPortBuilderExt, SerialStream, StopBits};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf};
+use tokio::sync::{broadcast, mpsc, Mutex, RwLock};
+use tokio::time::{Duration, Instant};
+use tokio_serial::{SerialPortBuilderExt, SerialStream};
 
+use super::buffer::LineBuffer;
 use super::channels::{SerialChannelName, SerialPubChannels};
+use super::cobs::{self, SerialFraming, COBS_DELIMITER};
+use super::compact::CompactTable;
+use super::control::ControlMessage;
+use super::discovery::is_banner;
+use super::format::LineFormat;
 use super::message::SerialRawMessage;
+use super::options::SerialOptions;
+use crate::adapters::batch::{spawn_batcher, BatchOptions};
+use crate::adapters::lease::LeaseSlot;
+use crate::adapters::lora::codec::FrameParser;
+use crate::adapters::reconnect::ReconnectBackoff;
 use crate::models::hub::{HubChannelName, HubMessage};
-use crate::ports::NotificationHub;
+use crate::ports::{NodeTasks, NotificationHub};
 
-const BUFFER_SIZE: usize = 1024;
+/// Line written by `SerialClient::self_test`, expected back unchanged from jumpered ports
+pub const SELF_TEST_LINE: &[u8] = b"##__selftest## 55aa00ff\n";
 
 /// The `SerialClient` struct represents a client that communicates with a serial port that can subscribe
 /// to specific topic channels. It allows to send and receive messages on specific topics.
 ///
-/// SerialClient holds a reference to the serial port and the topic channels
+/// SerialClient holds a reference to the serial port and the topic channels. The serial port is split
+/// into independent read and write halves, so that sending messages never waits for the read loop.
+/// The read loop wakes up when data is available, and data is handled as soon as it is read.
 ///
 /// # Fields
-/// - `port`: An `Arc<RwLock<SerialStream>>` that represents the serial port.
+/// - `port`: Path of the serial port, recorded as the source of received messages.
+/// - `reader`: Read half of the serial port. It is leased by the read loop when the client is
+///   started, and emptied when the loop stops at a read error, so the port is opened again at the
+///   next start.
+/// - `writer`: Write half of the serial port.
 /// - `serial_channels`: An `Arc<RwLock<SerialPubChannels>>` that holds the topic channels.
+/// - `batcher`: Optional batching task. When enabled, outgoing messages are written to the port
+///   as a single newline separated block.
+/// - `encoding`: Framing of the link, and optional compact encoding, used once the device accepts
+///   it. See `SerialFraming` and `CompactTable`.
+/// - `baud_rate`: Baud rate the port is opened at.
+/// - `options`: Framing and line settings the port is opened with. See `SerialOptions`.
+/// - `reconnect`: Backoff of the attempts to reopen the port after a read error (e.g. the device
+///   was unplugged). Without it, the read loop stops at the first error.
+/// - `crc`: Received lines must end with a CRC16 suffix (see `SerialRawMessage::verify_crc`).
+///   Lines failing the check are dropped instead of being published.
+/// - `counters`: Counters of the lines and frames received.
+/// - `line_format`: Delimiters of the lines received from the device. See `LineFormat`.
 
 #[derive(Debug)]
 pub struct SerialClient {
-    port: Arc<RwLock<SerialStream>>,
+    port: String,
+    reader: LeaseSlot<Option<ReadHalf<SerialStream>>>,
+    writer: Arc<Mutex<WriteHalf<SerialStream>>>,
     serial_channels: Arc<RwLock<SerialPubChannels>>,
+    batcher: Option<mpsc::UnboundedSender<HubMessage>>,
+    encoding: Arc<LinkEncoding>,
+    baud_rate: u32,
+    options: SerialOptions,
+    reconnect: Option<ReconnectBackoff>,
+    crc: bool,
+    counters: Arc<AtomicCounters>,
+    line_format: LineFormat,
+}
+
+/// Counters of the data received by a `SerialClient`
+///
+/// # Fields
+/// - `received`: Lines and compact frames received intact.
+/// - `corrupted`: Lines and compact frames dropped for a missing or mismatching checksum.
+/// - `invalid`: Lines and compact frames received intact that couldn't be parsed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SerialCounters {
+    pub received: u64,
+    pub corrupted: u64,
+    pub invalid: u64,
+}
+
+#[derive(Debug, Default)]
+struct AtomicCounters {
+    received: AtomicU64,
+    corrupted: AtomicU64,
+    invalid: AtomicU64,
+}
+
+impl AtomicCounters {
+    fn add(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> SerialCounters {
+        SerialCounters {
+            received: self.received.load(Ordering::Relaxed),
+            corrupted: self.corrupted.load(Ordering::Relaxed),
+            invalid: self.invalid.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Framing of the link, compact encoding offered to the device, and whether the device
+/// accepted it
+#[derive(Debug, Default)]
+struct LinkEncoding {
+    framing: OnceLock<SerialFraming>,
+    table: OnceLock<CompactTable>,
+    accepted: AtomicBool,
+}
+
+impl LinkEncoding {
+    fn framing(&self) -> SerialFraming {
+        self.framing.get().copied().unwrap_or_default()
+    }
+
+    /// Returns true if encoded messages must be followed by a newline
+    fn is_line(&self) -> bool {
+        self.framing() == SerialFraming::Lines && self.accepted_table().is_none()
+    }
+
+    /// Table of the compact encoding, if accepted by the device
+    fn accepted_table(&self) -> Option<&CompactTable> {
+        if !self.accepted.load(Ordering::Acquire) {
+            return None;
+        }
+        self.table.get()
+    }
+
+    /// Encodes `message` as a COBS frame, as a compact frame if the encoding was accepted, or
+    /// as JSON otherwise
+    fn encode(&self, message: &HubMessage) -> Result<Vec<u8>, std::io::Error> {
+        let encoded = match (self.framing(), self.accepted_table()) {
+            (SerialFraming::Cobs, _) => cobs::encode_message(message),
+            (_, Some(table)) => table.encode(message),
+            (_, None) => return Ok(message.to_bytes()?),
+        };
+        encoded.map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
+
+    /// Encodes control message `control` with the framing of the link
+    fn encode_control(&self, control: &ControlMessage) -> Result<Vec<u8>, std::io::Error> {
+        let encoded = match (self.framing(), self.accepted_table()) {
+            (SerialFraming::Cobs, _) => control
+                .to_message()
+                .and_then(|message| cobs::encode_message(&message)),
+            (_, Some(table)) => control
+                .to_message()
+                .and_then(|message| table.encode(&message)),
+            (_, None) => Ok(control.to_line().into_bytes()),
+        };
+        encoded.map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+    }
 }
 
 impl SerialClient {
-    pub fn new(port: &str, baud_rate: u32) -> Result<Self, std::io::Error> {
-        info!("Opening serial port {} with params {}...", port, baud_rate);
-        let mut port = tokio_serial::new(port, baud_rate)
-            .open_native_async()
-            .inspect_err(|_| {
-                error!("Serial port at {} not ready", port);
-            })?;
-        port.set_parity(Parity::None)?;
-        port.set_stop_bits(StopBits::One)?;
-        port.set_data_bits(DataBits::Eight)?;
+    pub fn new(port: &str, baud_rate: u32, options: SerialOptions) -> Result<Self, std::io::Error> {
+        info!(
+            "Opening serial port {} with params {} {:?}...",
+            port, baud_rate, options
+        );
+        let port_name = port.to_string();
+        let (reader, writer) = tokio::io::split(open(port, baud_rate, &options)?);
         let handler = Self {
-            port: Arc::new(RwLock::new(port)),
+            port: port_name,
+            reader: LeaseSlot::new(Some(reader)),
+            writer: Arc::new(Mutex::new(writer)),
             serial_channels: Arc::new(RwLock::new(SerialPubChannels::new())),
+            batcher: None,
+            encoding: Arc::new(LinkEncoding::default()),
+            baud_rate,
+            options,
+            reconnect: Some(ReconnectBackoff::default()),
+            crc: false,
+            counters: Arc::new(AtomicCounters::default()),
+            line_format: LineFormat::default(),
         };
         info!("Serial port opened...");
         Ok(handler)
     }
+
+    /// Enables batching of outgoing messages. Messages are grouped according to `options`
+    /// and written to the serial port in a single block
+    pub fn with_batching(mut self, options: BatchOptions) -> Self {
+        let writer = Arc::clone(&self.writer);
+        let encoding = Arc::clone(&self.encoding);
+        self.batcher = Some(spawn_batcher(options, move |batch| {
+            let writer = Arc::clone(&writer);
+            let encoding = Arc::clone(&encoding);
+            async move {
+                let mut block = Vec::new();
+                for message in batch {
+                    match encoding.encode(&message) {
+                        Ok(raw_bytes) => {
+                            block.extend_from_slice(&raw_bytes);
+                            // COBS and compact frames are self delimited
+                            if encoding.is_line() {
+                                block.push(b'\n');
+                            }
+                        }
+                        Err(e) => error!("Serial port batch conversion error {:?}", e),
+                    }
+                }
+                let mut writer = writer.lock().await;
+                if let Err(e) = writer.write_all(&block).await {
+                    error!("Serial port batch send error {:?}", e);
+                }
+            }
+        }));
+        self
+    }
+
+    /// Sets the framing of the data exchanged with the device. Lines by default
+    pub fn with_framing(self, framing: SerialFraming) -> Self {
+        // framing is only set once, by a new client
+        let _ = self.encoding.framing.set(framing);
+        self
+    }
+
+    /// Offers the compact encoding of `table` to the device when the client is started.
+    /// Messages are exchanged as text until the device accepts it. Not offered with COBS framing
+    pub fn with_compact_encoding(self, table: CompactTable) -> Self {
+        // compact encoding is only offered once, by a new client
+        let _ = self.encoding.table.set(table);
+        self
+    }
+
+    /// Sets the backoff of the attempts to reopen the port after a read error. With `None`,
+    /// the read loop stops at the first error
+    pub fn with_reconnect(mut self, reconnect: Option<ReconnectBackoff>) -> Self {
+        self.reconnect = reconnect;
+        self
+    }
+
+    /// Requires a CRC16 suffix in the lines received from the device, dropping the lines
+    /// corrupted by a noisy link. Compact frames are always checked
+    pub fn with_crc(mut self) -> Self {
+        self.crc = true;
+        self
+    }
+
+    /// Sets the delimiters of the lines received from the device, for firmware not sending
+    /// `##CHANNEL## DATA` lines. Lines sent by the hub on start (hello and compact encoding
+    /// offer) keep the default format
+    pub fn with_line_format(mut self, line_format: LineFormat) -> Self {
+        self.line_format = line_format;
+        self
+    }
+
+    /// Returns the path of the serial port
+    pub fn port(&self) -> &str {
+        &self.port
+    }
+
+    /// Returns the counters of the data received
+    pub fn counters(&self) -> SerialCounters {
+        self.counters.snapshot()
+    }
+
+    /// Returns true if the device accepted the compact encoding
+    pub fn is_compact(&self) -> bool {
+        self.encoding.accepted_table().is_some()
+    }
+
+    /// Writes a test line to the port and waits up to `timeout` for its echo, to check ports
+    /// with their TX and RX pins jumpered (e.g. before connecting a device in the field).
+    /// Returns the round trip time of the line. Fails if the client is started or its port was
+    /// closed, if a line other than the test line is received, or if the echo isn't received in
+    /// time
+    pub async fn self_test(&self, timeout: Duration) -> Result<Duration, std::io::Error> {
+        let mut reader = self.reader.lease("Serial port")?;
+        let reader = reader.as_mut().ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotConnected,
+                format!("Serial port {} closed", self.port),
+            )
+        })?;
+        let started = Instant::now();
+        let deadline = started + timeout;
+        self.writer.lock().await.write_all(SELF_TEST_LINE).await?;
+        let mut lines = LineBuffer::new();
+        loop {
+            let read = tokio::time::timeout_at(deadline, lines.read_from(reader))
+                .await
+                .map_err(|_| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        format!("No echo received from serial port {}", self.port),
+                    )
+                })?;
+            closed_as_error(read)?;
+            if let Some(line) = lines.next_line() {
+                if line != SELF_TEST_LINE {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!(
+                            "Corrupted echo {:?} from serial port {}",
+                            String::from_utf8_lossy(&line),
+                            self.port
+                        ),
+                    ));
+                }
+                return Ok(started.elapsed());
+            }
+        }
+    }
+
+    // Writes control message `control` to the device, bypassing the batcher
+    async fn write_control(&self, control: &ControlMessage) -> Result<(), std::io::Error> {
+        let raw_bytes = self.encoding.encode_control(control)?;
+        let mut writer = self.writer.lock().await;
+        writer.write_all(&raw_bytes).await
+    }
+
+    // Reads lines from the port until a hub message is received, or `timeout` expires
+    pub(super) async fn probe(&self, timeout: Duration) -> bool {
+        let Ok(mut reader) = self.reader.lease("Serial port") else {
+            return false;
+        };
+        let Some(reader) = reader.as_mut() else {
+            return false;
+        };
+        let mut lines = LineBuffer::new();
+        let deadline = Instant::now() + timeout;
+        loop {
+            let read = tokio::time::timeout_at(deadline, lines.read_from(reader)).await;
+            match read.map(closed_as_error) {
+                Ok(Ok(_)) => {
+                    while let Some(line) = lines.next_line() {
+                        if is_banner(line) {
+                            return true;
+                        }
+                    }
+                }
+                Ok(Err(_)) | Err(_) => return false,
+            }
+        }
+    }
+}
+
+// Reads wait until data is available, so reading no bytes means the port was closed (e.g. the
+// device hung up). It is handled as a read error, reopening the port
+fn closed_as_error(read: Result<usize, std::io::Error>) -> Result<usize, std::io::Error> {
+    match read {
+        Ok(0) => Err(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            "Serial port closed",
+        )),
+        read => read,
+    }
+}
+
+// Opens `port` with the settings of `options`
+fn open(
+    port: &str,
+    baud_rate: u32,
+    options: &SerialOptions,
+) -> Result<SerialStream, std::io::Error> {
+    let mut builder = tokio_serial::new(port, baud_rate)
+        .data_bits(options.data_bits())
+        .parity(options.parity())
+        .stop_bits(options.stop_bits())
+        .flow_control(options.flow_control());
+    if let Some(timeout) = options.timeout() {
+        builder = builder.timeout(timeout);
+    }
+    let stream = builder.open_native_async().inspect_err(|_| {
+        error!("Serial port at {} not ready", port);
+    })?;
+    Ok(stream)
+}
+
+// Writes the hello message asking the device for its channels, and offers the compact encoding
+// if set
+async fn write_handshake(
+    writer: &mut WriteHalf<SerialStream>,
+    encoding: &LinkEncoding,
+) -> Result<(), std::io::Error> {
+    writer
+        .write_all(&encoding.encode_control(&ControlMessage::Hello)?)
+        .await?;
+    if encoding.framing() == SerialFraming::Cobs {
+        return Ok(());
+    }
+    if let Some(table) = encoding.table.get() {
+        writer.write_all(table.handshake().as_bytes()).await?;
+    }
+    Ok(())
+}
+
+// Reopens `port` until it succeeds, waiting between attempts according to `backoff`. The write
+// half of the client is replaced, and the handshake is sent again to the device
+async fn reopen(
+    port: &str,
+    baud_rate: u32,
+    options: &SerialOptions,
+    backoff: ReconnectBackoff,
+    writer: &Mutex<WriteHalf<SerialStream>>,
+    encoding: &LinkEncoding,
+) -> ReadHalf<SerialStream> {
+    let mut delay = backoff.initial;
+    loop {
+        warn!("Reopening serial port {} in {:?}...", port, delay);
+        tokio::time::sleep(delay).await;
+        match open(port, baud_rate, options) {
+            Ok(stream) => {
+                let (reader, new_writer) = tokio::io::split(stream);
+                let mut writer = writer.lock().await;
+                *writer = new_writer;
+                encoding.accepted.store(false, Ordering::Release);
+                if let Err(e) = write_handshake(&mut writer, encoding).await {
+                    error!("Serial port handshake error {:?}", e);
+                }
+                info!("Serial port {} reconnected", port);
+                return reader;
+            }
+            Err(e) => {
+                error!("Serial port {} reconnect error {:?}", port, e);
+                delay = backoff.next_delay(delay);
+            }
+        }
+    }
+}
+
+// Handles a message received from the serial port. Control messages are answered or update the
+// channels of the port, and other messages are published, learning their channel
+async fn handle_received(
+    message: HubMessage,
+    port: &str,
+    serial_channels: &RwLock<SerialPubChannels>,
+    sender: &broadcast::Sender<HubMessage>,
+    writer: &Mutex<WriteHalf<SerialStream>>,
+    encoding: &LinkEncoding,
+) {
+    match ControlMessage::parse(&message) {
+        Some(Ok(ControlMessage::Hello)) => {
+            info!("Serial device at {} says hello", port);
+            let result = match encoding.encode_control(&ControlMessage::Hello) {
+                Ok(reply) => writer.lock().await.write_all(&reply).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                error!("Serial port hello error {:?}", e);
+            }
+            return;
+        }
+        Some(Ok(ControlMessage::Channels(channels))) => {
+            info!(
+                "Serial device at {} advertises channels {:?}",
+                port, channels
+            );
+            serial_channels.write().await.replace(channels);
+            return;
+        }
+        Some(Ok(control)) => {
+            warn!(
+                "Serial device at {} sent unexpected control message {:?}",
+                port, control
+            );
+            return;
+        }
+        Some(Err(e)) => {
+            error!("Serial port invalid control message {:?}", e);
+            return;
+        }
+        None => {}
+    }
+    let message = message.with_source("serial", port);
+    let mut serial_channels = serial_channels.write().await;
+    serial_channels.add(SerialChannelName::from(message.channel.clone()));
+    if let Err(e) = sender.send(message) {
+        error!("Serial port send error {:?}", e);
+    }
 }
 
 #[async_trait]
 impl NotificationHub for SerialClient {
     /// Send a message through channel
     async fn send(&self, data: HubMessage) -> Result<(), std::io::Error> {
-        let raw_bytes = data.to_bytes()?;
-        let mut port = self.port.write().await;
-        tokio::io::AsyncWriteExt::write_all(&mut *port, &raw_bytes).await
+        if let Some(batcher) = &self.batcher {
+            return batcher.send(data).map_err(|_| {
+                std::io::Error::new(std::io::ErrorKind::BrokenPipe, "Batcher stopped")
+            });
+        }
+        let raw_bytes = self.encoding.encode(&data)?;
+        let mut writer = self.writer.lock().await;
+        writer.write_all(&raw_bytes).await
     }
 
     /// List available topic channels
@@ -63,62 +496,210 @@ impl NotificationHub for SerialClient {
         Ok(serial_channels.iter().map(HubChannelName::from).collect())
     }
 
+    /// Asks the device to stream `channel`
+    async fn subscribe(&self, channel: HubChannelName) -> Result<(), std::io::Error> {
+        let control = ControlMessage::Subscribe(SerialChannelName::from(channel));
+        self.write_control(&control).await
+    }
+
+    /// Tells the device `channel` may stop being streamed
+    async fn unsubscribe(&self, channel: HubChannelName) -> Result<(), std::io::Error> {
+        let control = ControlMessage::Unsubscribe(SerialChannelName::from(channel));
+        self.write_control(&control).await
+    }
+
     /// Start client
     async fn start(
         &self,
         sender: Option<broadcast::Sender<HubMessage>>,
-    ) -> Result<(), std::io::Error> {
+    ) -> Result<NodeTasks, std::io::Error> {
+        let mut tasks = NodeTasks::new();
         if let Some(sender) = sender {
-            let port = self.port.clone();
-            let mut line_buffer = Vec::new();
+            let mut running = self.reader.lease("Serial port")?;
+            if running.is_none() {
+                // the previous read loop stopped at a read error
+                let (reader, writer) =
+                    tokio::io::split(open(&self.port, self.baud_rate, &self.options)?);
+                *self.writer.lock().await = writer;
+                self.encoding.accepted.store(false, Ordering::Release);
+                *running = Some(reader);
+            }
             let serial_channels = Arc::clone(&self.serial_channels);
+            let port = self.port.clone();
+            let encoding = Arc::clone(&self.encoding);
+            let writer = Arc::clone(&self.writer);
+            let baud_rate = self.baud_rate;
+            let options = self.options;
+            let reconnect = self.reconnect;
+            let crc = self.crc;
+            let counters = Arc::clone(&self.counters);
+            let framing = encoding.framing();
+            let line_format = self.line_format.clone();
             info!("Starting Serial port...");
+            write_handshake(&mut *self.writer.lock().await, &encoding).await?;
 
-            tokio::spawn(async move {
-                let mut buffer = vec![0u8; BUFFER_SIZE];
+            let task = tokio::spawn(async move {
+                let mut lines = LineBuffer::new();
+                // frames of the compact encoding, once accepted by the device
+                let mut frames: Option<FrameParser> = None;
+                let mut buffer = [0u8; 256];
                 loop {
-                    let mut port_write = port.write().await;
-                    match port_write.read(&mut buffer).await {
-                        Ok(n) if n > 0 => {
-                            line_buffer.extend_from_slice(&buffer[..n]);
-                            while let Some(pos) = line_buffer.iter().position(|&b| b == b'\n') {
-                                let line =
-                                    String::from_utf8_lossy(&line_buffer[..=pos]).to_string();
-
-                                if line.starts_with("##") {
-                                    let raw_serial_message =
-                                        SerialRawMessage::from_str(line.as_str());
+                    let Some(reader) = running.as_mut() else {
+                        break;
+                    };
+                    if let (Some(parser), Some(table)) = (frames.as_mut(), encoding.table.get()) {
+                        match closed_as_error(reader.read(&mut buffer).await) {
+                            Ok(n) => parser.push(&buffer[..n]),
+                            Err(e) => {
+                                error!("Serial port error {:?}", e);
+                                let Some(backoff) = reconnect else {
+                                    *running = None;
+                                    break;
+                                };
+                                *running = Some(
+                                    reopen(&port, baud_rate, &options, backoff, &writer, &encoding)
+                                        .await,
+                                );
+                                lines = LineBuffer::new();
+                                frames = None;
+                                continue;
+                            }
+                        }
+                        while let Some(frame) = parser.next_frame() {
+                            let decoded = match frame {
+                                Ok((id, payload)) => table.decode(id, &payload),
+                                Err(e) => {
+                                    AtomicCounters::add(&counters.corrupted);
+                                    error!("Serial port corrupted frame {:?}", e);
+                                    continue;
+                                }
+                            };
+                            match decoded {
+                                Ok(message) => {
+                                    AtomicCounters::add(&counters.received);
+                                    handle_received(
+                                        message,
+                                        &port,
+                                        &serial_channels,
+                                        &sender,
+                                        &writer,
+                                        &encoding,
+                                    )
+                                    .await
+                                }
+                                Err(e) => {
+                                    AtomicCounters::add(&counters.invalid);
+                                    error!("Serial port receive error {:?}", e)
+                                }
+                            }
+                        }
+                        continue;
+                    }
+                    match closed_as_error(lines.read_from(reader).await) {
+                        Ok(_) if framing == SerialFraming::Cobs => {
+                            while let Some(frame) = lines.next_delimited(COBS_DELIMITER) {
+                                // empty frames may be sent to resynchronize
+                                if frame.len() == 1 {
+                                    continue;
+                                }
+                                match cobs::decode_message(&frame) {
+                                    Ok(message) => {
+                                        AtomicCounters::add(&counters.received);
+                                        handle_received(
+                                            message,
+                                            &port,
+                                            &serial_channels,
+                                            &sender,
+                                            &writer,
+                                            &encoding,
+                                        )
+                                        .await
+                                    }
+                                    Err(e) => {
+                                        AtomicCounters::add(&counters.invalid);
+                                        error!("Serial port receive error {:?}", e)
+                                    }
+                                }
+                            }
+                        }
+                        Ok(_) => {
+                            while let Some(line) = lines.next_delimited(line_format.terminator()) {
+                                let line = line_format.trim(line);
+                                if line.is_empty() {
+                                    continue;
+                                }
+                                if line_format.is_message(&line) {
+                                    let mut raw_serial_message = SerialRawMessage::from_bytes(line);
+                                    if crc {
+                                        match raw_serial_message.verify_crc() {
+                                            Ok(verified) => raw_serial_message = verified,
+                                            Err(e) => {
+                                                AtomicCounters::add(&counters.corrupted);
+                                                warn!("Serial port corrupted line {:?}", e);
+                                                continue;
+                                            }
+                                        }
+                                    }
                                     // serial client learns available channels by inspecting received data
-                                    match HubMessage::try_from(raw_serial_message) {
-                                        Ok(message) => {
-                                            let mut serial_channels = serial_channels.write().await;
-                                            serial_channels.add(SerialChannelName::from(
-                                                message.channel.clone(),
-                                            ));
-                                            if let Err(e) = sender.send(message) {
-                                                error!("Serial port send error {:?}", e);
+                                    match line_format.parse(raw_serial_message.as_bytes()) {
+                                        Ok(message)
+                                            if encoding.table.get().is_some()
+                                                && CompactTable::is_accepted(&message) =>
+                                        {
+                                            info!(
+                                                "Serial port {} switched to compact encoding",
+                                                port
+                                            );
+                                            encoding.accepted.store(true, Ordering::Release);
+                                            // bytes following the reply are compact frames
+                                            let mut parser = FrameParser::new();
+                                            if let Some(remaining) = lines.take_remaining() {
+                                                parser.push(&remaining);
                                             }
+                                            frames = Some(parser);
+                                            break;
+                                        }
+                                        Ok(message) => {
+                                            AtomicCounters::add(&counters.received);
+                                            handle_received(
+                                                message,
+                                                &port,
+                                                &serial_channels,
+                                                &sender,
+                                                &writer,
+                                                &encoding,
+                                            )
+                                            .await
+                                        }
+                                        Err(e) => {
+                                            AtomicCounters::add(&counters.invalid);
+                                            error!("Serial port receive error {:?}", e)
                                         }
-                                        Err(e) => error!("Serial port receive error {:?}", e),
                                     }
                                 } else {
+                                    AtomicCounters::add(&counters.invalid);
                                     warn!("Invalid serial data. Waiting for valid channel prefix");
                                 }
-                                line_buffer.drain(0..=pos);
                             }
                         }
-                        Ok(_) => continue,
                         Err(e) => {
                             error!("Serial port error {:?}", e);
-                            break;
+                            let Some(backoff) = reconnect else {
+                                *running = None;
+                                break;
+                            };
+                            *running = Some(
+                                reopen(&port, baud_rate, &options, backoff, &writer, &encoding)
+                                    .await,
+                            );
+                            lines = LineBuffer::new();
                         }
                     }
-                    drop(port_write);
-                    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
                 }
             });
+            tasks.push(task);
         }
-        Ok(())
+        Ok(tasks)
     }
 }
 
@@ -129,10 +710,26 @@ mod tests {
     const PORT: &str = "/dev/ttyACM0";
     const BAUD_RATE: u32 = 9600;
 
+    #[test]
+    fn test_closed_as_error() {
+        assert_eq!(closed_as_error(Ok(3)).unwrap(), 3);
+        let err = closed_as_error(Ok(0)).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_self_test() {
+        // requires a port with its TX and RX pins jumpered
+        let client = SerialClient::new(PORT, BAUD_RATE, SerialOptions::default()).unwrap();
+        let round_trip = client.self_test(Duration::from_secs(1)).await.unwrap();
+        assert!(round_trip < Duration::from_secs(1));
+    }
+
     #[tokio::test]
     #[ignore]
     async fn test_serial() {
-        let client = SerialClient::new(PORT, BAUD_RATE).unwrap();
+        let client = SerialClient::new(PORT, BAUD_RATE, SerialOptions::default()).unwrap();
         let (sender, mut receiver) = broadcast::channel(100);
         client.start(Some(sender)).await.unwrap();
 
@@ -0,0 +1,184 @@
+use crate::models::hub::{HubChannelName, HubData, HubMessage};
+
+use super::channels::SerialChannelName;
+use super::control::is_control_channel;
+
+/// Delimiter of COBS frames. Encoded frames never contain it
+pub const COBS_DELIMITER: u8 = 0;
+
+/// Framing of the data exchanged with a serial device.
+///
+/// - `Lines`: `##CHANNEL## DATA` text lines terminated by a newline.
+/// - `Cobs`: Binary frames encoded with Consistent Overhead Byte Stuffing, and terminated by a
+///   zero byte, so that payloads can hold any byte (e.g. raw sensor packets) without escaping.
+///   A frame holds the `##CHANNEL##` tag followed by the payload, carried in the hub as a
+///   lowercase hex string (e.g. `0a1bff`). Control messages (e.g. `##__hello##`, `##__sub##`)
+///   carry their text as payload. The compact encoding is not offered in this mode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SerialFraming {
+    #[default]
+    Lines,
+    Cobs,
+}
+
+/// Encodes `data` with COBS, appending the frame delimiter
+pub fn encode(data: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(data.len() + data.len() / 254 + 2);
+    let mut code_index = 0;
+    frame.push(0);
+    let mut code = 1u8;
+    for &byte in data {
+        if byte != 0 {
+            frame.push(byte);
+            code += 1;
+        }
+        if byte == 0 || code == 0xff {
+            frame[code_index] = code;
+            code_index = frame.len();
+            frame.push(0);
+            code = 1;
+        }
+    }
+    frame[code_index] = code;
+    frame.push(COBS_DELIMITER);
+    frame
+}
+
+/// Decodes a COBS `frame`, with or without its trailing delimiter
+pub fn decode(frame: &[u8]) -> Result<Vec<u8>, String> {
+    let frame = frame.strip_suffix(&[COBS_DELIMITER]).unwrap_or(frame);
+    let mut data = Vec::with_capacity(frame.len());
+    let mut index = 0;
+    while index < frame.len() {
+        let code = frame[index] as usize;
+        if code == 0 || index + code > frame.len() {
+            return Err("Invalid COBS frame".to_string());
+        }
+        let block = &frame[index + 1..index + code];
+        if block.contains(&COBS_DELIMITER) {
+            return Err("Invalid COBS frame".to_string());
+        }
+        data.extend_from_slice(block);
+        index += code;
+        // blocks shorter than 254 bytes are followed by a zero, except the last one
+        if code < 0xff && index < frame.len() {
+            data.push(0);
+        }
+    }
+    Ok(data)
+}
+
+// Parses a hex string (e.g. `0a1bff`) into bytes
+pub(super) fn from_hex(data: &str) -> Result<Vec<u8>, String> {
+    if !data.is_ascii() || data.len() % 2 != 0 {
+        return Err(format!("Invalid hex payload {}", data));
+    }
+    (0..data.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&data[i..i + 2], 16)
+                .map_err(|e| format!("Invalid hex payload {}: {}", data, e))
+        })
+        .collect()
+}
+
+// Formats bytes as a lowercase hex string
+pub(super) fn to_hex(payload: &[u8]) -> String {
+    payload.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Encodes `message` into a COBS frame
+pub fn encode_message(message: &HubMessage) -> Result<Vec<u8>, String> {
+    let data = message.data.as_str().trim();
+    let payload = if is_control_channel(message.channel.as_str()) {
+        data.as_bytes().to_vec()
+    } else {
+        from_hex(data)?
+    };
+    let tag = SerialChannelName::from(message.channel.clone()).tag();
+    let mut frame = Vec::with_capacity(tag.len() + payload.len());
+    frame.extend_from_slice(tag.as_bytes());
+    frame.extend_from_slice(&payload);
+    Ok(encode(&frame))
+}
+
+/// Decodes a COBS frame into a message
+pub fn decode_message(frame: &[u8]) -> Result<HubMessage, String> {
+    let frame = decode(frame)?;
+    let tagged = frame
+        .strip_prefix(b"##")
+        .and_then(|rest| {
+            let end = rest.windows(2).position(|window| window == b"##")?;
+            Some((&rest[..end], &rest[end + 2..]))
+        })
+        .ok_or_else(|| "COBS frame without channel tag".to_string())?;
+    let (channel, payload) = tagged;
+    let channel = std::str::from_utf8(channel).map_err(|e| e.to_string())?;
+    let channel = HubChannelName::from(SerialChannelName::try_from(channel)?);
+    let data = if is_control_channel(channel.as_str()) {
+        String::from_utf8(payload.to_vec()).map_err(|e| e.to_string())?
+    } else {
+        to_hex(payload)
+    };
+    Ok(HubMessage::new(channel, HubData::try_from(data)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_decode() {
+        let cases: [(&[u8], &[u8]); 5] = [
+            (&[], &[0x01, 0x00]),
+            (&[0x00], &[0x01, 0x01, 0x00]),
+            (
+                &[0x11, 0x22, 0x00, 0x33],
+                &[0x03, 0x11, 0x22, 0x02, 0x33, 0x00],
+            ),
+            (
+                &[0x11, 0x00, 0x00, 0x00],
+                &[0x02, 0x11, 0x01, 0x01, 0x01, 0x00],
+            ),
+            (
+                &[0x11, 0x22, 0x33, 0x44],
+                &[0x05, 0x11, 0x22, 0x33, 0x44, 0x00],
+            ),
+        ];
+        for (data, frame) in cases {
+            assert_eq!(encode(data), frame);
+            assert_eq!(decode(frame).unwrap(), data);
+        }
+
+        // blocks of 254 non zero bytes
+        let data: Vec<u8> = (1..=255).cycle().take(600).collect();
+        let frame = encode(&data);
+        assert!(!frame[..frame.len() - 1].contains(&COBS_DELIMITER));
+        assert_eq!(decode(&frame).unwrap(), data);
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        assert!(decode(&[0x05, 0x11, 0x00]).is_err());
+        assert!(decode(&[0x03, 0x11, 0x00, 0x22]).is_err());
+        assert!(decode(&[0x00, 0x11]).is_err());
+    }
+
+    #[test]
+    fn test_message() {
+        let message = HubMessage::try_from_str("imu", "0a00ff").unwrap();
+        let frame = encode_message(&message).unwrap();
+        assert_eq!(frame.iter().filter(|&&byte| byte == 0).count(), 1);
+        let decoded = decode_message(&frame).unwrap();
+        assert_eq!(decoded.channel.as_str(), "imu");
+        assert_eq!(decoded.data.as_str(), "0a00ff");
+
+        let hello = HubMessage::try_from_str("__channels", "imu,battery").unwrap();
+        let decoded = decode_message(&encode_message(&hello).unwrap()).unwrap();
+        assert_eq!(decoded.data.as_str(), "imu,battery");
+
+        let invalid = HubMessage::try_from_str("imu", "0a0").unwrap();
+        assert!(encode_message(&invalid).is_err());
+        assert!(decode_message(&encode(b"imu 0a")).is_err());
+    }
+}
@@ -0,0 +1,293 @@
+use crate::adapters::lora::codec::{encode_frame, LoraEncoding};
+use crate::models::hub::{HubChannelName, HubData, HubMessage};
+
+use super::cobs::{from_hex, to_hex};
+use super::message::SerialRawMessage;
+
+/// Channel of the handshake negotiating the compact encoding
+pub const COMPACT_HANDSHAKE_CHANNEL: &str = "hub_compact";
+/// Reply of a device accepting the compact encoding
+pub const COMPACT_ACCEPTED: &str = "ok";
+/// Frame id of messages of channels not in the table, sent as text lines
+pub const TEXT_FRAME_ID: u8 = u8::MAX;
+/// Handshake marker of channels sending raw bytes
+pub const RAW_PAYLOAD: &str = "raw";
+
+/// Payload of the frames of a compact channel.
+///
+/// - `Quantized`: Data is a comma separated list of numbers, each sent as a 2 byte integer
+///   multiple of `scale` (e.g. a scale of 0.001 sends an acceleration of 9.81 as 9810).
+/// - `Raw`: Data is sent as is, as raw bytes (e.g. sensor packets or structs dumped by the
+///   firmware). They are carried in the hub as a lowercase hex string (e.g. `0a1bff`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompactPayload {
+    Quantized { scale: f64 },
+    Raw,
+}
+
+impl CompactPayload {
+    /// Encodes `data` into the payload of a frame
+    pub fn encode(&self, data: &HubData) -> Result<Vec<u8>, String> {
+        match self {
+            Self::Quantized { scale } => LoraEncoding::Quantized { scale: *scale }.encode(data),
+            Self::Raw => from_hex(data.as_str().trim()),
+        }
+    }
+
+    /// Decodes the payload of a frame into hub data
+    pub fn decode(&self, payload: &[u8]) -> Result<HubData, String> {
+        match self {
+            Self::Quantized { scale } => LoraEncoding::Quantized { scale: *scale }.decode(payload),
+            Self::Raw => HubData::try_from(to_hex(payload)),
+        }
+    }
+
+    // Marker of the payload in the handshake
+    fn marker(&self) -> String {
+        match self {
+            Self::Quantized { scale } => scale.to_string(),
+            Self::Raw => RAW_PAYLOAD.to_string(),
+        }
+    }
+}
+
+/// Channel sent with the compact serial encoding.
+///
+/// # Fields
+/// - `channel`: Hub channel name. It isn't sent over the link, the position of the channel in
+///   the table is sent instead.
+/// - `payload`: Payload of the frames of the channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompactChannel {
+    pub channel: HubChannelName,
+    pub payload: CompactPayload,
+}
+
+/// `CompactTable` is the channel id table of the compact serial encoding, a binary alternative
+/// to the `##CHANNEL## DATA` text lines for MCUs on slow links (e.g. Arduino boards at 9600
+/// baud).
+///
+/// The hub announces the table when the `SerialClient` starts, with the text line
+/// `##hub_compact## imu:0.001,battery:0.01,sonar:raw`, where the id of every channel is its
+/// position in the table, followed by the scale of its values or `raw` for raw bytes. The device enables the encoding by replying `##hub_compact## ok`. Devices not
+/// replying keep exchanging text lines.
+///
+/// Once accepted, every message is sent in both directions as a frame with the layout of
+/// LoRa frames:
+///
+/// ```text
+/// | 0xa5 | id (1) | len (1) | payload | crc8 (1) |
+/// ```
+/// Messages of channels in the table carry their data as big endian i16 fixed-point values, or
+/// as raw bytes, so that a sample costs its id and a few bytes instead of its channel name and
+/// text. Messages of other channels are sent with id 255, and their text line as payload.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompactTable {
+    channels: Vec<CompactChannel>,
+}
+
+impl CompactTable {
+    pub fn channels(&self) -> &[CompactChannel] {
+        &self.channels
+    }
+
+    /// Id and configuration of `channel`
+    pub fn by_channel(&self, channel: &HubChannelName) -> Option<(u8, &CompactChannel)> {
+        self.channels
+            .iter()
+            .position(|compact| &compact.channel == channel)
+            .map(|id| (id as u8, &self.channels[id]))
+    }
+
+    /// Configuration of the channel with `id`
+    pub fn by_id(&self, id: u8) -> Option<&CompactChannel> {
+        self.channels.get(id as usize)
+    }
+
+    /// Text line announcing the table to the device
+    pub fn handshake(&self) -> String {
+        let channels: Vec<_> = self
+            .channels
+            .iter()
+            .map(|compact| format!("{}:{}", compact.channel.as_str(), compact.payload.marker()))
+            .collect();
+        format!("##{}## {}\n", COMPACT_HANDSHAKE_CHANNEL, channels.join(","))
+    }
+
+    /// Returns true if `message` is the reply of a device accepting the encoding
+    pub fn is_accepted(message: &HubMessage) -> bool {
+        message.channel.as_str() == COMPACT_HANDSHAKE_CHANNEL
+            && message.data.as_str() == COMPACT_ACCEPTED
+    }
+
+    /// Encodes `message` into a compact frame
+    pub fn encode(&self, message: &HubMessage) -> Result<Vec<u8>, String> {
+        match self.by_channel(&message.channel) {
+            Some((id, compact)) => encode_frame(id, &compact.payload.encode(&message.data)?),
+            None => {
+                let line = SerialRawMessage::from(message.clone());
+                encode_frame(TEXT_FRAME_ID, line.as_str().as_bytes())
+            }
+        }
+    }
+
+    /// Decodes the payload of frame `id` into a message
+    pub fn decode(&self, id: u8, payload: &[u8]) -> Result<HubMessage, String> {
+        if id == TEXT_FRAME_ID {
+            let line = SerialRawMessage::from_bytes(payload.to_vec().into());
+            return HubMessage::try_from(line);
+        }
+        let compact = self
+            .by_id(id)
+            .ok_or_else(|| format!("Compact frame of unknown channel id {}", id))?;
+        let data = compact.payload.decode(payload)?;
+        Ok(HubMessage::new(compact.channel.clone(), data))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CompactTableBuilder {
+    channels: Vec<(String, CompactPayload)>,
+}
+
+impl CompactTableBuilder {
+    pub fn new() -> Self {
+        Self {
+            channels: Vec::new(),
+        }
+    }
+
+    /// Adds `channel` to the table, sending its values as multiples of `scale`
+    pub fn channel(&self, channel: &str, scale: f64) -> Self {
+        let mut new = self.clone();
+        new.channels
+            .push((channel.to_string(), CompactPayload::Quantized { scale }));
+        new
+    }
+    /// Adds `channel` to the table, sending its data as raw bytes
+    pub fn raw_channel(&self, channel: &str) -> Self {
+        let mut new = self.clone();
+        new.channels
+            .push((channel.to_string(), CompactPayload::Raw));
+        new
+    }
+    pub fn build(self) -> Result<CompactTable, String> {
+        if self.channels.is_empty() {
+            return Err("No compact serial channels".to_string());
+        }
+        // last id is reserved for text frames
+        if self.channels.len() > TEXT_FRAME_ID as usize {
+            return Err(format!(
+                "At most {} compact serial channels are supported",
+                TEXT_FRAME_ID
+            ));
+        }
+        let mut channels: Vec<CompactChannel> = Vec::with_capacity(self.channels.len());
+        for (channel, payload) in self.channels {
+            let channel = HubChannelName::try_from(channel.as_str())?;
+            if channels.iter().any(|other| other.channel == channel) {
+                return Err(format!(
+                    "Channel {} is in the compact table more than once",
+                    channel.as_str()
+                ));
+            }
+            if let CompactPayload::Quantized { scale } = payload {
+                if !scale.is_finite() || scale <= 0.0 {
+                    return Err(format!(
+                        "Channel {} has an invalid fixed-point scale",
+                        channel.as_str()
+                    ));
+                }
+            }
+            channels.push(CompactChannel { channel, payload });
+        }
+        Ok(CompactTable { channels })
+    }
+}
+
+impl Default for CompactTableBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::lora::codec::{FrameParser, FRAME_OVERHEAD};
+
+    fn table() -> CompactTable {
+        CompactTableBuilder::new()
+            .channel("imu", 0.001)
+            .channel("battery", 0.01)
+            .raw_channel("sonar")
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_handshake() {
+        let table = table();
+        assert_eq!(
+            table.handshake(),
+            "##hub_compact## imu:0.001,battery:0.01,sonar:raw\n"
+        );
+        let reply = HubMessage::try_from_str(COMPACT_HANDSHAKE_CHANNEL, "ok").unwrap();
+        assert!(CompactTable::is_accepted(&reply));
+        let other = HubMessage::try_from_str("imu", "ok").unwrap();
+        assert!(!CompactTable::is_accepted(&other));
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let table = table();
+        let message = HubMessage::try_from_str("imu", "0.123,-0.456,9.81").unwrap();
+        let frame = table.encode(&message).unwrap();
+        assert_eq!(frame.len(), 6 + FRAME_OVERHEAD);
+        // shorter than the text line
+        let line = SerialRawMessage::from(message.clone());
+        assert!(frame.len() < line.as_str().len());
+
+        let text = HubMessage::try_from_str("status", "armed").unwrap();
+        let mut parser = FrameParser::new();
+        parser.push(&frame);
+        parser.push(&table.encode(&text).unwrap());
+
+        let (id, payload) = parser.next_frame().unwrap().unwrap();
+        let decoded = table.decode(id, &payload).unwrap();
+        assert_eq!(decoded.channel.as_str(), "imu");
+        assert_eq!(decoded.data.as_str(), "0.123,-0.456,9.810");
+
+        let (id, payload) = parser.next_frame().unwrap().unwrap();
+        assert_eq!(id, TEXT_FRAME_ID);
+        let decoded = table.decode(id, &payload).unwrap();
+        assert_eq!(decoded.channel.as_str(), "status");
+        assert_eq!(decoded.data.as_str(), "armed");
+
+        let raw = HubMessage::try_from_str("sonar", "00ff1a").unwrap();
+        let frame = table.encode(&raw).unwrap();
+        assert_eq!(frame.len(), 3 + FRAME_OVERHEAD);
+        parser.push(&frame);
+        let (id, payload) = parser.next_frame().unwrap().unwrap();
+        assert_eq!(id, 2);
+        assert_eq!(payload, [0x00, 0xff, 0x1a]);
+        assert_eq!(table.decode(id, &payload).unwrap().data.as_str(), "00ff1a");
+        let invalid = HubMessage::try_from_str("sonar", "0g").unwrap();
+        assert!(table.encode(&invalid).is_err());
+
+        assert!(table.decode(7, &[0, 1]).is_err());
+        let out_of_range = HubMessage::try_from_str("imu", "100.0").unwrap();
+        assert!(table.encode(&out_of_range).is_err());
+    }
+
+    #[test]
+    fn test_invalid_table() {
+        let builder = CompactTableBuilder::new();
+        assert!(builder.clone().build().is_err());
+        let builder = builder.channel("imu", 0.001);
+        assert!(builder.channel("imu", 0.01).build().is_err());
+        assert!(builder.channel("battery", 0.0).build().is_err());
+        assert!(builder.channel("battery", f64::NAN).build().is_err());
+        assert!(builder.build().is_ok());
+    }
+}
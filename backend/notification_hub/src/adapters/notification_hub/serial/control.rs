@@ -0,0 +1,158 @@
+use crate::models::hub::HubMessage;
+
+use super::channels::SerialChannelName;
+use super::message::SerialRawMessage;
+
+/// Channel of the hello message, exchanged when the link is (re)established
+pub const HELLO_CHANNEL: &str = "__hello";
+/// Channel of the advertisement of the channels published by the device
+pub const CHANNELS_CHANNEL: &str = "__channels";
+/// Channel of the requests to stream a channel
+pub const SUBSCRIBE_CHANNEL: &str = "__sub";
+/// Channel of the requests to stop streaming a channel
+pub const UNSUBSCRIBE_CHANNEL: &str = "__unsub";
+
+/// `ControlMessage` is a message of the serial control protocol, letting devices advertise
+/// their channels as soon as the link is up, instead of the hub learning them from the first
+/// message of every channel. Control messages use the `##CHANNEL## DATA` line format with
+/// reserved channels, and are not published to the hub.
+///
+/// - `Hello`: `##__hello##`. Sent by the hub when the port is opened, asking the device to
+///   advertise its channels. A device sending it (e.g. after a reset) is answered with another
+///   hello.
+/// - `Channels`: `##__channels## imu,battery`. Channels published by the device, replacing the
+///   channels previously known for the port.
+/// - `Subscribe`: `##__sub## imu`. Sent by the hub when a client subscribes to a channel, so
+///   that the device streams it.
+/// - `Unsubscribe`: `##__unsub## imu`. Sent by the hub when a client unsubscribes from a
+///   channel, so that the device may stop streaming it and save bandwidth.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ControlMessage {
+    Hello,
+    Channels(Vec<SerialChannelName>),
+    Subscribe(SerialChannelName),
+    Unsubscribe(SerialChannelName),
+}
+
+impl ControlMessage {
+    /// Parses `message` if it is a control message. Returns `None` for data messages
+    pub fn parse(message: &HubMessage) -> Option<Result<Self, String>> {
+        match message.channel.as_str() {
+            HELLO_CHANNEL => Some(Ok(Self::Hello)),
+            CHANNELS_CHANNEL => Some(
+                message
+                    .data
+                    .as_str()
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|channel| !channel.is_empty())
+                    .map(SerialChannelName::try_from)
+                    .collect::<Result<Vec<_>, _>>()
+                    .map(Self::Channels),
+            ),
+            SUBSCRIBE_CHANNEL => {
+                Some(SerialChannelName::try_from(message.data.as_str().trim()).map(Self::Subscribe))
+            }
+            UNSUBSCRIBE_CHANNEL => Some(
+                SerialChannelName::try_from(message.data.as_str().trim()).map(Self::Unsubscribe),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Text line of the message, terminated by a newline
+    pub fn to_line(&self) -> String {
+        let (channel, data) = match self {
+            Self::Hello => (HELLO_CHANNEL, String::new()),
+            Self::Channels(channels) => {
+                let channels: Vec<_> = channels.iter().map(SerialChannelName::as_str).collect();
+                (CHANNELS_CHANNEL, channels.join(","))
+            }
+            Self::Subscribe(channel) => (SUBSCRIBE_CHANNEL, channel.as_str().to_string()),
+            Self::Unsubscribe(channel) => (UNSUBSCRIBE_CHANNEL, channel.as_str().to_string()),
+        };
+        if data.is_empty() {
+            return format!("##{}##\n", channel);
+        }
+        format!("##{}## {}\n", channel, data)
+    }
+
+    /// Message carrying the control message, e.g. to be sent as a compact frame
+    pub fn to_message(&self) -> Result<HubMessage, String> {
+        let line = self.to_line();
+        HubMessage::try_from(SerialRawMessage::from_str(line.trim_end()))
+    }
+}
+
+/// Returns true if `channel` is reserved for control messages
+pub fn is_control_channel(channel: &str) -> bool {
+    matches!(
+        channel,
+        HELLO_CHANNEL | CHANNELS_CHANNEL | SUBSCRIBE_CHANNEL | UNSUBSCRIBE_CHANNEL
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Option<Result<ControlMessage, String>> {
+        let message = HubMessage::try_from(SerialRawMessage::from_str(line)).unwrap();
+        ControlMessage::parse(&message)
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("##__hello##"), Some(Ok(ControlMessage::Hello)));
+        let channels = vec![
+            SerialChannelName::try_from("imu").unwrap(),
+            SerialChannelName::try_from("battery").unwrap(),
+        ];
+        assert_eq!(
+            parse("##__channels## imu, battery"),
+            Some(Ok(ControlMessage::Channels(channels)))
+        );
+        assert_eq!(
+            parse("##__channels##"),
+            Some(Ok(ControlMessage::Channels(Vec::new())))
+        );
+        assert!(matches!(parse("##__channels## imu,b@d"), Some(Err(_))));
+        assert_eq!(
+            parse("##__sub## imu"),
+            Some(Ok(ControlMessage::Subscribe(
+                SerialChannelName::try_from("imu").unwrap()
+            )))
+        );
+        assert_eq!(
+            parse("##__unsub## imu"),
+            Some(Ok(ControlMessage::Unsubscribe(
+                SerialChannelName::try_from("imu").unwrap()
+            )))
+        );
+        assert!(matches!(parse("##__sub##"), Some(Err(_))));
+        assert_eq!(parse("##imu## 1,2,3"), None);
+    }
+
+    #[test]
+    fn test_to_line() {
+        assert_eq!(ControlMessage::Hello.to_line(), "##__hello##\n");
+        let channels = ControlMessage::Channels(vec![
+            SerialChannelName::try_from("imu").unwrap(),
+            SerialChannelName::try_from("battery").unwrap(),
+        ]);
+        assert_eq!(channels.to_line(), "##__channels## imu,battery\n");
+        let message = channels.to_message().unwrap();
+        assert!(is_control_channel(message.channel.as_str()));
+        assert_eq!(ControlMessage::parse(&message), Some(Ok(channels)));
+
+        let imu = SerialChannelName::try_from("imu").unwrap();
+        assert_eq!(
+            ControlMessage::Subscribe(imu.clone()).to_line(),
+            "##__sub## imu\n"
+        );
+        assert_eq!(
+            ControlMessage::Unsubscribe(imu).to_line(),
+            "##__unsub## imu\n"
+        );
+    }
+}
@@ -0,0 +1,171 @@
+use bytes::Bytes;
+use log::{info, warn};
+use serialport::{SerialPortInfo, SerialPortType};
+use std::time::Duration;
+
+use super::client::SerialClient;
+use super::message::SerialRawMessage;
+use super::options::SerialOptions;
+use crate::models::hub::HubMessage;
+
+/// Time waited for the banner of a device when probing ports
+pub const DEFAULT_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// `PortFilter` selects the serial ports of hub devices among the ports available in the system,
+/// so that devices are found wherever they are plugged instead of at a fixed path.
+///
+/// - `Usb`: USB serial ports with vendor id `vid`, and product id `pid` if set
+///   (e.g. `0x2341` for Arduino boards).
+/// - `Banner`: USB serial ports sending a `##CHANNEL## DATA` line within `timeout` once opened.
+///   Lines read while probing are not published.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PortFilter {
+    Usb { vid: u16, pid: Option<u16> },
+    Banner { timeout: Duration },
+}
+
+impl PortFilter {
+    /// Filter of USB serial ports with vendor id `vid` and any product id
+    pub fn vendor(vid: u16) -> Self {
+        Self::Usb { vid, pid: None }
+    }
+
+    /// Filter of ports sending a hub message within the default probe timeout
+    pub fn banner() -> Self {
+        Self::Banner {
+            timeout: DEFAULT_PROBE_TIMEOUT,
+        }
+    }
+
+    /// Returns true if the USB device `vid:pid` may match the filter. Devices matched by
+    /// banner are probed afterwards
+    pub fn matches_usb(&self, vid: u16, pid: u16) -> bool {
+        match self {
+            Self::Usb {
+                vid: filter_vid,
+                pid: filter_pid,
+            } => *filter_vid == vid && filter_pid.is_none_or(|filter_pid| filter_pid == pid),
+            Self::Banner { .. } => true,
+        }
+    }
+}
+
+/// Returns true if `line` is a valid `##CHANNEL## DATA` hub message
+pub(super) fn is_banner(line: Bytes) -> bool {
+    line.starts_with(b"##") && HubMessage::try_from(SerialRawMessage::from_bytes(line)).is_ok()
+}
+
+// Names of the available ports that may match `filter`
+pub(super) fn candidate_ports(ports: &[SerialPortInfo], filter: &PortFilter) -> Vec<String> {
+    ports
+        .iter()
+        .filter(|port| match &port.port_type {
+            SerialPortType::UsbPort(usb) => filter.matches_usb(usb.vid, usb.pid),
+            _ => false,
+        })
+        .map(|port| port.port_name.clone())
+        .collect()
+}
+
+impl SerialClient {
+    /// Opens at `baud_rate`, with `options`, every available serial port matching `filter`.
+    /// Ports failing to open, or not sending a banner when probed, are skipped
+    pub async fn discover(
+        filter: &PortFilter,
+        baud_rate: u32,
+        options: SerialOptions,
+    ) -> Result<Vec<SerialClient>, std::io::Error> {
+        let ports = serialport::available_ports()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::NotFound, e))?;
+        let mut clients = Vec::new();
+        for port in candidate_ports(&ports, filter) {
+            if let Some(client) =
+                SerialClient::open_matching(&port, filter, baud_rate, options).await
+            {
+                clients.push(client);
+            }
+        }
+        Ok(clients)
+    }
+
+    // Opens candidate `port`, probing it if `filter` matches by banner
+    pub(super) async fn open_matching(
+        port: &str,
+        filter: &PortFilter,
+        baud_rate: u32,
+        options: SerialOptions,
+    ) -> Option<SerialClient> {
+        let client = match SerialClient::new(port, baud_rate, options) {
+            Ok(client) => client,
+            Err(e) => {
+                warn!("Skipping serial port {}: {}", port, e);
+                return None;
+            }
+        };
+        if let PortFilter::Banner { timeout } = filter {
+            if !client.probe(*timeout).await {
+                info!("No hub device found at serial port {}", port);
+                return None;
+            }
+        }
+        info!("Discovered hub device at serial port {}", port);
+        Some(client)
+    }
+
+    /// Opens the first available serial port matching `filter`
+    pub async fn discover_first(
+        filter: &PortFilter,
+        baud_rate: u32,
+        options: SerialOptions,
+    ) -> Result<SerialClient, std::io::Error> {
+        SerialClient::discover(filter, baud_rate, options)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("No serial port matches {:?}", filter),
+                )
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usb_filter() {
+        let filter = PortFilter::vendor(0x2341);
+        assert!(filter.matches_usb(0x2341, 0x0043));
+        assert!(!filter.matches_usb(0x1a86, 0x7523));
+
+        let filter = PortFilter::Usb {
+            vid: 0x2341,
+            pid: Some(0x0043),
+        };
+        assert!(filter.matches_usb(0x2341, 0x0043));
+        assert!(!filter.matches_usb(0x2341, 0x0001));
+
+        assert!(PortFilter::banner().matches_usb(0x1a86, 0x7523));
+    }
+
+    #[test]
+    fn test_banner() {
+        assert!(is_banner(Bytes::from_static(
+            b"##acceleration## 0.1,0.2,9.8"
+        )));
+        assert!(!is_banner(Bytes::from_static(b"booting...")));
+        assert!(!is_banner(Bytes::from_static(b"## no channel")));
+    }
+
+    #[test]
+    fn test_candidate_ports() {
+        let ports = vec![SerialPortInfo {
+            port_name: "/dev/ttyS0".to_string(),
+            port_type: SerialPortType::Unknown,
+        }];
+        assert!(candidate_ports(&ports, &PortFilter::banner()).is_empty());
+    }
+}
@@ -0,0 +1,213 @@
+use bytes::Bytes;
+
+use super::channels::SerialChannelName;
+use crate::models::hub::{HubChannelName, HubData, HubMessage};
+
+const DEFAULT_PREFIX: &str = "##";
+const DEFAULT_SEPARATOR: &str = "##";
+const DEFAULT_TERMINATOR: u8 = b'\n';
+
+/// `LineFormat` defines the delimiters of the text lines exchanged with serial devices, so that
+/// firmware not sending `##CHANNEL## DATA` lines can be ingested without changes.
+///
+/// A line is the `prefix`, the channel name, the `separator` and the data, followed by the
+/// `terminator`. For example, NMEA-like sentences such as `$GPGGA,123519,4807.038,N` are read
+/// with prefix `$` and separator `,` into channel `gpgga` with data `123519,4807.038,N`.
+///
+/// # Fields
+/// - `prefix`: Marker opening the channel name. Lines not starting with it are discarded.
+/// - `separator`: Marker closing the channel name, followed by the data.
+/// - `terminator`: Byte terminating every line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineFormat {
+    prefix: String,
+    separator: String,
+    terminator: u8,
+}
+
+impl LineFormat {
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+    pub fn separator(&self) -> &str {
+        &self.separator
+    }
+    pub fn terminator(&self) -> u8 {
+        self.terminator
+    }
+
+    /// Returns true if `line` starts with the prefix of the format
+    pub fn is_message(&self, line: &[u8]) -> bool {
+        line.starts_with(self.prefix.as_bytes())
+    }
+
+    /// Removes the terminator, and any trailing '\r' or '\n', from `line` without copying
+    pub fn trim(&self, line: Bytes) -> Bytes {
+        let mut end = line.len();
+        while end > 0
+            && (matches!(line[end - 1], b'\n' | b'\r') || line[end - 1] == self.terminator)
+        {
+            end -= 1;
+        }
+        line.slice(..end)
+    }
+
+    /// Parses `line` into a message
+    pub fn parse(&self, line: &[u8]) -> Result<HubMessage, String> {
+        let invalid = || format!("Invalid line {:?}", String::from_utf8_lossy(line));
+        let rest = line
+            .strip_prefix(self.prefix.as_bytes())
+            .ok_or_else(invalid)?;
+        let separator = self.separator.as_bytes();
+        let end = rest
+            .windows(separator.len())
+            .position(|window| window == separator)
+            .ok_or_else(invalid)?;
+        let channel = std::str::from_utf8(&rest[..end]).map_err(|_| invalid())?;
+        let channel = HubChannelName::from(SerialChannelName::try_from(channel)?);
+        let data = String::from_utf8_lossy(&rest[end + separator.len()..]);
+        let data = data
+            .trim_matches(|c: char| matches!(c, '\n' | '\r' | ' ') || c == self.terminator as char);
+        Ok(HubMessage::new(channel, data.parse::<HubData>()?))
+    }
+
+    /// Formats `message` into a line, including the terminator
+    pub fn to_line(&self, message: &HubMessage) -> Vec<u8> {
+        let channel = SerialChannelName::from(message.channel.clone());
+        let space = if self.separator == DEFAULT_SEPARATOR {
+            " "
+        } else {
+            ""
+        };
+        let mut line = format!(
+            "{}{}{}{}{}",
+            self.prefix,
+            channel.as_str(),
+            self.separator,
+            space,
+            message.data.as_str()
+        )
+        .into_bytes();
+        line.push(self.terminator);
+        line
+    }
+}
+
+impl Default for LineFormat {
+    fn default() -> Self {
+        Self {
+            prefix: DEFAULT_PREFIX.to_string(),
+            separator: DEFAULT_SEPARATOR.to_string(),
+            terminator: DEFAULT_TERMINATOR,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LineFormatBuilder {
+    prefix: Option<String>,
+    separator: Option<String>,
+    terminator: Option<u8>,
+}
+
+impl LineFormatBuilder {
+    pub fn new() -> Self {
+        Self {
+            prefix: None,
+            separator: None,
+            terminator: None,
+        }
+    }
+
+    pub fn prefix(&self, prefix: &str) -> Self {
+        let mut new = self.clone();
+        new.prefix = Some(prefix.to_string());
+        new
+    }
+    pub fn separator(&self, separator: &str) -> Self {
+        let mut new = self.clone();
+        new.separator = Some(separator.to_string());
+        new
+    }
+    pub fn terminator(&self, terminator: u8) -> Self {
+        let mut new = self.clone();
+        new.terminator = Some(terminator);
+        new
+    }
+    pub fn build(self) -> Result<LineFormat, String> {
+        let prefix = self.prefix.unwrap_or_else(|| DEFAULT_PREFIX.to_string());
+        let separator = self
+            .separator
+            .unwrap_or_else(|| DEFAULT_SEPARATOR.to_string());
+        let terminator = self.terminator.unwrap_or(DEFAULT_TERMINATOR);
+        if separator.is_empty() {
+            return Err("Line separator can't be empty".to_string());
+        }
+        if !terminator.is_ascii() {
+            return Err("Line terminator must be an ASCII character".to_string());
+        }
+        if prefix.as_bytes().contains(&terminator) || separator.as_bytes().contains(&terminator) {
+            return Err("Line markers can't contain the line terminator".to_string());
+        }
+        Ok(LineFormat {
+            prefix,
+            separator,
+            terminator,
+        })
+    }
+}
+
+impl Default for LineFormatBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_format() {
+        let format = LineFormat::default();
+        let line = format.trim(Bytes::from_static(b"##Channel## 1,2\r\n"));
+        assert!(format.is_message(&line));
+        let message = format.parse(&line).unwrap();
+        assert_eq!(message.channel.as_str(), "channel");
+        assert_eq!(message.data.as_str(), "1,2");
+        assert_eq!(format.to_line(&message), b"##channel## 1,2\n");
+
+        assert!(!format.is_message(b"booting..."));
+        assert!(format.parse(b"##channel").is_err());
+        assert!(format.parse(b"##ch@nnel## 1").is_err());
+    }
+
+    #[test]
+    fn test_custom_format() {
+        let nmea = LineFormatBuilder::new()
+            .prefix("$")
+            .separator(",")
+            .build()
+            .unwrap();
+        let message = nmea.parse(b"$GPGGA,123519,4807.038,N\r\n").unwrap();
+        assert_eq!(message.channel.as_str(), "gpgga");
+        assert_eq!(message.data.as_str(), "123519,4807.038,N");
+        assert_eq!(nmea.to_line(&message), b"$gpgga,123519,4807.038,N\n");
+
+        let semicolon = LineFormatBuilder::new().terminator(b';').build().unwrap();
+        let line = semicolon.trim(Bytes::from_static(b"##imu## 1,2;"));
+        assert_eq!(&line[..], b"##imu## 1,2");
+        assert_eq!(semicolon.parse(&line).unwrap().data.as_str(), "1,2");
+    }
+
+    #[test]
+    fn test_invalid_format() {
+        assert!(LineFormatBuilder::new().separator("").build().is_err());
+        assert!(LineFormatBuilder::new()
+            .prefix("$;")
+            .terminator(b';')
+            .build()
+            .is_err());
+        assert!(LineFormatBuilder::new().terminator(0xff).build().is_err());
+    }
+}
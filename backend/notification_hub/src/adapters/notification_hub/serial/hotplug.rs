@@ -0,0 +1,143 @@
+use log::{error, info, warn};
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use tokio::time::{Duration, MissedTickBehavior};
+
+use super::client::SerialClient;
+use super::discovery::{candidate_ports, PortFilter};
+use super::manager::ManagedPorts;
+use super::options::SerialOptions;
+use crate::models::hub::HubMessage;
+
+/// Time between two scans of the serial ports of the system
+pub const DEFAULT_WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// `PortWatcher` configures the hot-plug monitoring of a `SerialManager`, so that devices
+/// attached while the hub runs (e.g. an Arduino swapped in the field) are connected without
+/// restarting the backend.
+///
+/// The serial ports of the system are scanned every `interval`. New ports matching `filter` are
+/// opened and attached to the manager, and ports no longer present are detached. Attached ports
+/// are not reopened after read errors, as unplugged devices are attached again by the watcher
+/// when they come back, possibly at another path.
+///
+/// # Fields
+/// - `filter`: Filter of the ports attached.
+/// - `baud_rate`: Baud rate the ports are opened at.
+/// - `options`: Framing and line settings the ports are opened with.
+/// - `interval`: Time between two scans. Defaults to 1s.
+#[derive(Debug, Clone)]
+pub struct PortWatcher {
+    filter: PortFilter,
+    baud_rate: u32,
+    options: SerialOptions,
+    interval: Duration,
+}
+
+impl PortWatcher {
+    pub fn new(filter: PortFilter, baud_rate: u32) -> Self {
+        Self {
+            filter,
+            baud_rate,
+            options: SerialOptions::default(),
+            interval: DEFAULT_WATCH_INTERVAL,
+        }
+    }
+
+    /// Opens the ports with `options`, instead of 8N1 without flow control
+    pub fn with_options(mut self, options: SerialOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Scans the ports every `interval`
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn filter(&self) -> &PortFilter {
+        &self.filter
+    }
+    pub fn baud_rate(&self) -> u32 {
+        self.baud_rate
+    }
+    pub fn options(&self) -> SerialOptions {
+        self.options
+    }
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    // Attaches to `ports` the devices found every interval, and detaches the devices removed.
+    // Received messages are published to `sender`
+    pub(super) async fn watch(
+        self,
+        ports: Arc<ManagedPorts>,
+        sender: Option<broadcast::Sender<HubMessage>>,
+    ) {
+        let mut interval = tokio::time::interval(self.interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        // ports matching the filter without a hub device, not probed again until removed
+        let mut rejected: HashSet<String> = HashSet::new();
+        loop {
+            interval.tick().await;
+            let available = match tokio::task::spawn_blocking(serialport::available_ports).await {
+                Ok(Ok(available)) => available,
+                Ok(Err(e)) => {
+                    warn!("Serial ports can't be listed: {}", e);
+                    continue;
+                }
+                Err(e) => {
+                    error!("Serial port scan error {:?}", e);
+                    continue;
+                }
+            };
+            let names: HashSet<&str> = available
+                .iter()
+                .map(|port| port.port_name.as_str())
+                .collect();
+            rejected.retain(|port| names.contains(port.as_str()));
+            for port in ports.detach_missing(&names).await {
+                info!("Serial device at {} detached", port);
+            }
+            for port in candidate_ports(&available, &self.filter) {
+                if rejected.contains(&port) || ports.contains(&port).await {
+                    continue;
+                }
+                let client =
+                    SerialClient::open_matching(&port, &self.filter, self.baud_rate, self.options)
+                        .await;
+                let Some(client) = client else {
+                    rejected.insert(port);
+                    continue;
+                };
+                match ports
+                    .attach(client.with_reconnect(None), sender.clone())
+                    .await
+                {
+                    Ok(()) => info!("Serial device at {} attached", port),
+                    Err(e) => error!("Serial device at {} attach error {:?}", port, e),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_port_watcher() {
+        let watcher = PortWatcher::new(PortFilter::vendor(0x2341), 9600);
+        assert_eq!(watcher.interval(), DEFAULT_WATCH_INTERVAL);
+        assert_eq!(watcher.options(), SerialOptions::default());
+
+        let watcher = watcher.with_interval(Duration::from_millis(500));
+        assert_eq!(watcher.interval(), Duration::from_millis(500));
+        assert_eq!(watcher.filter(), &PortFilter::vendor(0x2341));
+        assert_eq!(watcher.baud_rate(), 9600);
+    }
+}
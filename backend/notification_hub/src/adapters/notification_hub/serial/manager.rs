@@ -0,0 +1,263 @@
+use async_trait::async_trait;
+use log::{error, warn};
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::sync::{broadcast, RwLock};
+
+use super::client::SerialClient;
+use super::discovery::PortFilter;
+use super::hotplug::PortWatcher;
+use super::options::SerialOptions;
+use crate::models::hub::{HubChannelName, HubMessage};
+use crate::ports::{NodeTasks, NotificationHub};
+
+/// `SerialManager` manages several serial ports as a single hub node, so that a robot with
+/// multiple boards (e.g. a motor controller and an IMU) is registered once.
+///
+/// Channels published by all ports are aggregated. Outgoing messages are written to the ports
+/// owning their channel, that is, the ports that published or advertised it. Messages to channels
+/// not owned by any port are written to every port, as devices may listen to channels they
+/// never publish.
+///
+/// With a `PortWatcher`, ports are attached and detached while the manager runs, as devices are
+/// plugged and unplugged. See `PortWatcher`.
+///
+/// # Fields
+/// - `ports`: Serial clients of the managed ports, and channels subscribed.
+/// - `watcher`: Optional hot-plug monitoring of the serial ports, started with the manager.
+#[derive(Debug)]
+pub struct SerialManager {
+    ports: Arc<ManagedPorts>,
+    watcher: Option<PortWatcher>,
+}
+
+/// Serial clients of the ports of a `SerialManager`, and channels subscribed, forwarded to the
+/// ports attached later
+#[derive(Debug)]
+pub(super) struct ManagedPorts {
+    clients: RwLock<Vec<Arc<SerialClient>>>,
+    subscriptions: RwLock<HashSet<HubChannelName>>,
+}
+
+impl ManagedPorts {
+    // Returns true if `port` is managed
+    pub(super) async fn contains(&self, port: &str) -> bool {
+        self.clients
+            .read()
+            .await
+            .iter()
+            .any(|client| client.port() == port)
+    }
+
+    // Starts `client` and manages its port. Its tasks are not supervised, they end when the
+    // device is unplugged
+    pub(super) async fn attach(
+        &self,
+        client: SerialClient,
+        sender: Option<broadcast::Sender<HubMessage>>,
+    ) -> Result<(), std::io::Error> {
+        client.start(sender).await?;
+        for channel in self.subscriptions.read().await.iter() {
+            if let Err(e) = client.subscribe(channel.clone()).await {
+                error!("Serial port {} subscribe error {:?}", client.port(), e);
+            }
+        }
+        self.clients.write().await.push(Arc::new(client));
+        Ok(())
+    }
+
+    // Stops managing the ports not in `available`. Returns the ports removed
+    pub(super) async fn detach_missing(&self, available: &HashSet<&str>) -> Vec<String> {
+        let mut clients = self.clients.write().await;
+        let mut detached = Vec::new();
+        clients.retain(|client| {
+            let present = available.contains(client.port());
+            if !present {
+                detached.push(client.port().to_string());
+            }
+            present
+        });
+        detached
+    }
+
+    // Clients of the ports owning `channel`, or all clients if no port owns it
+    async fn owners(
+        &self,
+        channel: &HubChannelName,
+    ) -> Result<Vec<Arc<SerialClient>>, std::io::Error> {
+        let clients = self.clients.read().await;
+        let mut owners = Vec::new();
+        for client in clients.iter() {
+            if client.list_channels().await?.contains(channel) {
+                owners.push(Arc::clone(client));
+            }
+        }
+        if owners.is_empty() {
+            return Ok(clients.clone());
+        }
+        Ok(owners)
+    }
+}
+
+impl SerialManager {
+    pub fn new(clients: Vec<SerialClient>) -> Self {
+        let ports = ManagedPorts {
+            clients: RwLock::new(clients.into_iter().map(Arc::new).collect()),
+            subscriptions: RwLock::new(HashSet::new()),
+        };
+        Self {
+            ports: Arc::new(ports),
+            watcher: None,
+        }
+    }
+
+    /// Manager of the ports attached by `watcher` while it runs
+    pub fn watch(watcher: PortWatcher) -> Self {
+        Self::new(Vec::new()).with_watcher(watcher)
+    }
+
+    /// Enables hot-plug monitoring of the serial ports with `watcher`
+    pub fn with_watcher(mut self, watcher: PortWatcher) -> Self {
+        self.watcher = Some(watcher);
+        self
+    }
+
+    /// Opens every port in `ports` at its baud rate, with `options`
+    pub fn open(ports: &[(&str, u32)], options: SerialOptions) -> Result<Self, std::io::Error> {
+        let clients = ports
+            .iter()
+            .map(|(port, baud_rate)| SerialClient::new(port, *baud_rate, options))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::new(clients))
+    }
+
+    /// Opens at `baud_rate`, with `options`, every available serial port matching `filter`
+    pub async fn discover(
+        filter: &PortFilter,
+        baud_rate: u32,
+        options: SerialOptions,
+    ) -> Result<Self, std::io::Error> {
+        let clients = SerialClient::discover(filter, baud_rate, options).await?;
+        if clients.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("No serial port matches {:?}", filter),
+            ));
+        }
+        Ok(Self::new(clients))
+    }
+
+    /// Returns the paths of the managed ports
+    pub async fn ports(&self) -> Vec<String> {
+        self.ports
+            .clients
+            .read()
+            .await
+            .iter()
+            .map(|client| client.port().to_string())
+            .collect()
+    }
+}
+
+#[async_trait]
+impl NotificationHub for SerialManager {
+    /// Send a message to the ports owning its channel
+    async fn send(&self, data: HubMessage) -> Result<(), std::io::Error> {
+        let owners = self.ports.owners(&data.channel).await?;
+        if owners.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotConnected,
+                "No serial port managed",
+            ));
+        }
+        // a failing port doesn't prevent delivery to the rest
+        let mut result = Ok(());
+        for client in owners {
+            if let Err(e) = client.send(data.clone()).await {
+                warn!("Serial port {} send error {:?}", client.port(), e);
+                result = Err(e);
+            }
+        }
+        result
+    }
+
+    /// List the topic channels of all ports
+    async fn list_channels(&self) -> Result<Vec<HubChannelName>, std::io::Error> {
+        let mut channels = Vec::new();
+        for client in self.ports.clients.read().await.iter() {
+            for channel in client.list_channels().await? {
+                if !channels.contains(&channel) {
+                    channels.push(channel);
+                }
+            }
+        }
+        Ok(channels)
+    }
+
+    /// Start all ports, and the hot-plug monitoring if enabled
+    async fn start(
+        &self,
+        sender: Option<broadcast::Sender<HubMessage>>,
+    ) -> Result<NodeTasks, std::io::Error> {
+        let mut tasks = NodeTasks::new();
+        for client in self.ports.clients.read().await.iter() {
+            tasks.extend(client.start(sender.clone()).await?);
+        }
+        if let Some(watcher) = &self.watcher {
+            let watch = watcher.clone().watch(Arc::clone(&self.ports), sender);
+            tasks.push(tokio::spawn(watch));
+        }
+        Ok(tasks)
+    }
+
+    async fn subscribe(&self, channel: HubChannelName) -> Result<(), std::io::Error> {
+        self.ports
+            .subscriptions
+            .write()
+            .await
+            .insert(channel.clone());
+        for client in self.ports.clients.read().await.iter() {
+            client.subscribe(channel.clone()).await?;
+        }
+        Ok(())
+    }
+
+    async fn unsubscribe(&self, channel: HubChannelName) -> Result<(), std::io::Error> {
+        self.ports.subscriptions.write().await.remove(&channel);
+        for client in self.ports.clients.read().await.iter() {
+            client.unsubscribe(channel.clone()).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_empty_manager() {
+        let manager = SerialManager::new(Vec::new());
+        assert!(manager.list_channels().await.unwrap().is_empty());
+        assert!(manager.start(None).await.unwrap().is_empty());
+        assert!(manager.ports().await.is_empty());
+        let message = HubMessage::try_from_str("cmd_vel", "1,0").unwrap();
+        let err = manager.send(message).await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::NotConnected);
+    }
+
+    #[tokio::test]
+    async fn test_subscriptions() {
+        let manager = SerialManager::new(Vec::new());
+        let channel = HubChannelName::try_from("cmd_vel").unwrap();
+        manager.subscribe(channel.clone()).await.unwrap();
+        assert!(manager.ports.subscriptions.read().await.contains(&channel));
+        manager.unsubscribe(channel.clone()).await.unwrap();
+        assert!(manager.ports.subscriptions.read().await.is_empty());
+        assert!(manager
+            .ports
+            .detach_missing(&HashSet::new())
+            .await
+            .is_empty());
+    }
+}
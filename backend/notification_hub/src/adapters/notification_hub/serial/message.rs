@@ -1,9 +1,10 @@
-use imu_common::types::Clock;
-use serde::Serialize;
+use bytes::{BufMut, Bytes, BytesMut};
+use serde::{Serialize, Serializer};
 
 use super::channels::SerialChannelName;
 
-use crate::models::hub::{HubChannelName, HubData, HubMessage};
+use crate::models::hub::hub_data::MAX_DATA_LEN;
+use crate::models::hub::{HubChannelName, HubData, HubMessage, HubTimestamp};
 use std::convert::TryFrom;
 
 struct SerialData(String);
@@ -17,36 +18,116 @@ impl SerialData {
     }
 }
 
-#[derive(Debug, Serialize)]
-pub struct SerialRawMessage(String);
+/// Raw line received from or sent to a serial port, with the format `##CHANNEL## DATA`.
+/// Backed by `Bytes`, so that lines split from a read buffer can be parsed without copying.
+///
+/// Lines carry only the channel and data of a message. Microcontrollers on the other end parse
+/// this fixed format, so `HubMessage` headers (units, correlation id...) are not sent, and
+/// messages received from a serial port have no headers besides their source.
+#[derive(Debug, Clone)]
+pub struct SerialRawMessage(Bytes);
+
+const TAG_SEPARATOR: &[u8] = b"##";
+/// Separator of the optional CRC suffix of a line, `##CHANNEL## DATA*XXXX`
+const CRC_SEPARATOR: u8 = b'*';
+// Number of hex digits of the CRC suffix
+const CRC_DIGITS: usize = 4;
+
+// CRC-16/CCITT-FALSE, polynomial 0x1021 and initial value 0xffff
+fn crc16(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0xffff, |crc, &byte| {
+        (0..8).fold(crc ^ ((byte as u16) << 8), |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
 
 impl SerialRawMessage {
     pub fn from_str(data: &str) -> Self {
-        Self(data.to_string())
+        Self(Bytes::copy_from_slice(data.as_bytes()))
+    }
+
+    pub fn from_bytes(data: Bytes) -> Self {
+        Self(data)
     }
 
+    /// Returns message as str. Invalid UTF-8 messages return an empty str.
     #[allow(dead_code)]
     pub fn as_str(&self) -> &str {
-        self.0.as_str()
+        std::str::from_utf8(&self.0).unwrap_or_default()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
     }
 
     pub fn to_bytes(&self) -> Result<Vec<u8>, serde_json::Error> {
         serde_json::to_vec(self)
     }
+
+    /// Appends the CRC16 of the line as a `*XXXX` suffix of 4 uppercase hex digits
+    pub fn with_crc(&self) -> Self {
+        let line = self.0.trim_ascii_end();
+        let mut raw = BytesMut::with_capacity(line.len() + 1 + CRC_DIGITS);
+        raw.put_slice(line);
+        raw.put_u8(CRC_SEPARATOR);
+        raw.put_slice(format!("{:04X}", crc16(line)).as_bytes());
+        SerialRawMessage(raw.freeze())
+    }
+
+    /// Checks the `*XXXX` CRC16 suffix of the line, computed over the bytes preceding it, and
+    /// returns the line without it. Lines without suffix or with a mismatching CRC are rejected
+    pub fn verify_crc(&self) -> Result<Self, String> {
+        let line = self.0.trim_ascii_end();
+        let split = line
+            .len()
+            .checked_sub(CRC_DIGITS + 1)
+            .filter(|&split| line[split] == CRC_SEPARATOR)
+            .ok_or_else(|| "Serial line without CRC".to_string())?;
+        let crc = std::str::from_utf8(&line[split + 1..])
+            .ok()
+            .and_then(|crc| u16::from_str_radix(crc, 16).ok())
+            .ok_or_else(|| "Serial line with invalid CRC".to_string())?;
+        let expected = crc16(&line[..split]);
+        if crc != expected {
+            return Err(format!(
+                "Serial line CRC mismatch: {:04X} != {:04X}",
+                crc, expected
+            ));
+        }
+        Ok(SerialRawMessage(self.0.slice(..split)))
+    }
+}
+
+impl Serialize for SerialRawMessage {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&String::from_utf8_lossy(&self.0))
+    }
+}
+
+fn find_separator(data: &[u8]) -> Option<usize> {
+    data.windows(TAG_SEPARATOR.len())
+        .position(|window| window == TAG_SEPARATOR)
 }
 
 impl SerialRawMessage {
+    // Messages with invalid channel names or data longer than `MAX_DATA_LEN` are rejected
     fn extract_info(&self) -> Option<(SerialChannelName, SerialData)> {
-        let raw_data = self.0.as_str();
-        if let Some(start) = raw_data.find("##") {
-            if let Some(end) = raw_data[start + 2..].find("##") {
-                let raw_channel_name = &raw_data[start + 2..start + 2 + end];
-                let data = raw_data[start + 2 + end + 2..]
-                    .trim_matches(|c| c == '\n' || c == '\r' || c == ' ');
-                if let Ok(channel_name) = SerialChannelName::try_from(raw_channel_name) {
-                    return Some((channel_name, SerialData(data.to_string())));
-                }
-            }
+        let raw_data = self.0.as_ref();
+        let start = find_separator(raw_data)? + TAG_SEPARATOR.len();
+        let end = start + find_separator(&raw_data[start..])?;
+        let raw_channel_name = std::str::from_utf8(&raw_data[start..end]).ok()?;
+        let data = String::from_utf8_lossy(&raw_data[end + TAG_SEPARATOR.len()..]);
+        let data = data.trim_matches(|c| c == '\n' || c == '\r' || c == ' ');
+        if data.len() > MAX_DATA_LEN {
+            return None;
+        }
+        if let Ok(channel_name) = SerialChannelName::try_from(raw_channel_name) {
+            return Some((channel_name, SerialData(data.to_string())));
         }
         None
     }
@@ -55,9 +136,12 @@ impl SerialRawMessage {
 impl From<(SerialChannelName, SerialData)> for HubMessage {
     fn from(value: (SerialChannelName, SerialData)) -> Self {
         HubMessage {
-            timestamp: Clock::now().as_secs(),
+            timestamp: HubTimestamp::now(),
             channel: HubChannelName::from(value.0),
             data: HubData::from(value.1),
+            // serial lines don't carry headers
+            headers: Default::default(),
+            origin: None,
         }
     }
 }
@@ -79,7 +163,11 @@ impl From<HubMessage> for SerialRawMessage {
     fn from(hub_msg: HubMessage) -> Self {
         let channel = SerialChannelName::from(hub_msg.channel).tag();
         let msg = hub_msg.data.as_str();
-        SerialRawMessage(format!("{} {}", channel, msg))
+        let mut raw = BytesMut::with_capacity(channel.len() + 1 + msg.len());
+        raw.put_slice(channel.as_bytes());
+        raw.put_u8(b' ');
+        raw.put_slice(msg.as_bytes());
+        SerialRawMessage(raw.freeze())
     }
 }
 
@@ -135,6 +223,71 @@ mod tests {
         assert!(extracted_info.is_none());
     }
 
+    #[test]
+    fn test_serial_raw_message_extract_info_pathological() {
+        for data in [
+            "####data",
+            "##",
+            "## ##data",
+            "##channel",
+            "##ch@nnel##data",
+        ] {
+            assert!(SerialRawMessage::from_str(data).extract_info().is_none());
+        }
+        let data = format!("##channel##{}", "a".repeat(MAX_DATA_LEN + 1));
+        assert!(SerialRawMessage::from_str(&data).extract_info().is_none());
+        let invalid_utf8 = SerialRawMessage::from_bytes(Bytes::from_static(b"##channel##\xff\xfe"));
+        assert!(invalid_utf8.extract_info().is_some());
+    }
+
+    #[test]
+    fn test_serial_raw_message_from_bytes() {
+        let serial_raw_message =
+            SerialRawMessage::from_bytes(Bytes::from_static(b"##channel## 1,2\r\n"));
+        let (channel_name, serial_data) = serial_raw_message.extract_info().unwrap();
+        assert_eq!(channel_name.as_str(), "channel");
+        assert_eq!(serial_data.as_str(), "1,2");
+    }
+
+    #[test]
+    fn test_serial_raw_message_to_bytes() {
+        let hub_message = HubMessage::try_from_str("channel", "1,2").unwrap();
+        let serial_raw_message = SerialRawMessage::from(hub_message);
+        assert_eq!(serial_raw_message.as_str(), "##channel## 1,2");
+        assert_eq!(
+            serial_raw_message.to_bytes().unwrap(),
+            b"\"##channel## 1,2\"".to_vec()
+        );
+    }
+
+    #[test]
+    fn test_crc16() {
+        assert_eq!(crc16(b"123456789"), 0x29b1);
+    }
+
+    #[test]
+    fn test_serial_raw_message_crc() {
+        let serial_raw_message = SerialRawMessage::from_str("##channel## 1,2").with_crc();
+        assert_eq!(serial_raw_message.as_str(), "##channel## 1,2*5624");
+
+        let received =
+            SerialRawMessage::from_bytes(Bytes::from_static(b"##channel## 1,2*5624\r\n"));
+        let verified = received.verify_crc().unwrap();
+        assert_eq!(verified.as_str(), "##channel## 1,2");
+        let hub_message = HubMessage::try_from(verified).unwrap();
+        assert_eq!(hub_message.data.as_str(), "1,2");
+
+        for corrupted in [
+            "##channel## 1,3*5624",
+            "##channel## 1,2*EB8",
+            "##channel## 1,2",
+            "##channel## 1,2*ZZZZ",
+            "*5624",
+        ] {
+            assert!(SerialRawMessage::from_str(corrupted).verify_crc().is_err());
+        }
+    }
+
     #[test]
     fn test_hub_message_from_serial_raw_message() {
         let data = "##channel##data";
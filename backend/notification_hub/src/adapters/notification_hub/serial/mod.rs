@@ -1,6 +1,26 @@
 /// Functionality for serial communication within the notification hub.
+pub mod buffer;
 pub mod channels;
 pub mod client;
+pub mod cobs;
+pub mod compact;
+pub mod control;
+pub mod discovery;
+pub mod format;
+pub mod hotplug;
+pub mod manager;
 pub mod message;
+pub mod options;
 
-pub use client::SerialClient;
+pub use client::{SerialClient, SerialCounters};
+pub use cobs::SerialFraming;
+// kept for compatibility, the backoff is shared by every reconnecting node
+pub use crate::adapters::reconnect::ReconnectBackoff;
+pub use compact::{CompactChannel, CompactPayload, CompactTable, CompactTableBuilder};
+pub use control::ControlMessage;
+pub use discovery::PortFilter;
+pub use format::{LineFormat, LineFormatBuilder};
+pub use hotplug::PortWatcher;
+pub use manager::SerialManager;
+pub use options::{SerialOptions, SerialOptionsBuilder};
+pub use tokio_serial::{DataBits, FlowControl, Parity, StopBits};
@@ -0,0 +1,202 @@
+use std::time::Duration;
+use tokio_serial::{DataBits, FlowControl, Parity, StopBits};
+
+/// `SerialOptions` defines the character framing and line settings a serial port is opened with,
+/// so that devices not using 8N1 without flow control (e.g. 7E1 industrial sensors, or modems
+/// requiring RTS/CTS) can be connected.
+///
+/// # Fields
+/// - `data_bits`: Bits of every character. Defaults to 8.
+/// - `parity`: Parity checking mode. Defaults to none.
+/// - `stop_bits`: Stop bits of every character. Defaults to 1.
+/// - `flow_control`: Flow control mode. Defaults to none.
+/// - `timeout`: Read timeout of the port driver. Defaults to the driver timeout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SerialOptions {
+    data_bits: DataBits,
+    parity: Parity,
+    stop_bits: StopBits,
+    flow_control: FlowControl,
+    timeout: Option<Duration>,
+}
+
+impl SerialOptions {
+    pub fn data_bits(&self) -> DataBits {
+        self.data_bits
+    }
+    pub fn parity(&self) -> Parity {
+        self.parity
+    }
+    pub fn stop_bits(&self) -> StopBits {
+        self.stop_bits
+    }
+    pub fn flow_control(&self) -> FlowControl {
+        self.flow_control
+    }
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+}
+
+impl Default for SerialOptions {
+    fn default() -> Self {
+        Self {
+            data_bits: DataBits::Eight,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+            flow_control: FlowControl::None,
+            timeout: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SerialOptionsBuilder {
+    mode: Option<String>,
+    data_bits: Option<DataBits>,
+    parity: Option<Parity>,
+    stop_bits: Option<StopBits>,
+    flow_control: Option<FlowControl>,
+    timeout: Option<Duration>,
+}
+
+impl SerialOptionsBuilder {
+    pub fn new() -> Self {
+        Self {
+            mode: None,
+            data_bits: None,
+            parity: None,
+            stop_bits: None,
+            flow_control: None,
+            timeout: None,
+        }
+    }
+
+    /// Sets data bits, parity and stop bits from their usual notation (e.g. `8N1`, `7E1`).
+    /// Settings set individually take precedence
+    pub fn mode(&self, mode: &str) -> Self {
+        let mut new = self.clone();
+        new.mode = Some(mode.to_string());
+        new
+    }
+    pub fn data_bits(&self, data_bits: DataBits) -> Self {
+        let mut new = self.clone();
+        new.data_bits = Some(data_bits);
+        new
+    }
+    pub fn parity(&self, parity: Parity) -> Self {
+        let mut new = self.clone();
+        new.parity = Some(parity);
+        new
+    }
+    pub fn stop_bits(&self, stop_bits: StopBits) -> Self {
+        let mut new = self.clone();
+        new.stop_bits = Some(stop_bits);
+        new
+    }
+    pub fn flow_control(&self, flow_control: FlowControl) -> Self {
+        let mut new = self.clone();
+        new.flow_control = Some(flow_control);
+        new
+    }
+    pub fn timeout(&self, timeout: Duration) -> Self {
+        let mut new = self.clone();
+        new.timeout = Some(timeout);
+        new
+    }
+    pub fn build(self) -> Result<SerialOptions, String> {
+        let defaults = match &self.mode {
+            Some(mode) => parse_mode(mode)?,
+            None => SerialOptions::default(),
+        };
+        if self.timeout == Some(Duration::ZERO) {
+            return Err("Serial read timeout can't be zero".to_string());
+        }
+        Ok(SerialOptions {
+            data_bits: self.data_bits.unwrap_or(defaults.data_bits),
+            parity: self.parity.unwrap_or(defaults.parity),
+            stop_bits: self.stop_bits.unwrap_or(defaults.stop_bits),
+            flow_control: self.flow_control.unwrap_or(defaults.flow_control),
+            timeout: self.timeout,
+        })
+    }
+}
+
+impl Default for SerialOptionsBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Parses a `8N1` like mode into the default options with its data bits, parity and stop bits
+fn parse_mode(mode: &str) -> Result<SerialOptions, String> {
+    let invalid = || format!("Invalid serial mode {}", mode);
+    let &[data_bits, parity, stop_bits] = mode.as_bytes() else {
+        return Err(invalid());
+    };
+    let data_bits = match data_bits {
+        b'5' => DataBits::Five,
+        b'6' => DataBits::Six,
+        b'7' => DataBits::Seven,
+        b'8' => DataBits::Eight,
+        _ => return Err(invalid()),
+    };
+    let parity = match parity.to_ascii_uppercase() {
+        b'N' => Parity::None,
+        b'O' => Parity::Odd,
+        b'E' => Parity::Even,
+        _ => return Err(invalid()),
+    };
+    let stop_bits = match stop_bits {
+        b'1' => StopBits::One,
+        b'2' => StopBits::Two,
+        _ => return Err(invalid()),
+    };
+    Ok(SerialOptions {
+        data_bits,
+        parity,
+        stop_bits,
+        ..SerialOptions::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_options() {
+        let options = SerialOptionsBuilder::new().build().unwrap();
+        assert_eq!(options, SerialOptions::default());
+
+        let options = SerialOptionsBuilder::new()
+            .mode("7e1")
+            .flow_control(FlowControl::Hardware)
+            .timeout(Duration::from_millis(50))
+            .build()
+            .unwrap();
+        assert_eq!(options.data_bits(), DataBits::Seven);
+        assert_eq!(options.parity(), Parity::Even);
+        assert_eq!(options.stop_bits(), StopBits::One);
+        assert_eq!(options.flow_control(), FlowControl::Hardware);
+        assert_eq!(options.timeout(), Some(Duration::from_millis(50)));
+
+        let options = SerialOptionsBuilder::new()
+            .mode("7E1")
+            .stop_bits(StopBits::Two)
+            .build()
+            .unwrap();
+        assert_eq!(options.stop_bits(), StopBits::Two);
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        for mode in ["", "8N", "9N1", "8X1", "8N3", "8N11"] {
+            assert!(SerialOptionsBuilder::new().mode(mode).build().is_err());
+        }
+        assert!(SerialOptionsBuilder::new()
+            .timeout(Duration::ZERO)
+            .build()
+            .is_err());
+    }
+}
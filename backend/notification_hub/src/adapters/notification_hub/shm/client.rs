@@ -0,0 +1,316 @@
+use async_trait::async_trait;
+use log::{debug, info, warn};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::{broadcast, RwLock};
+use tokio::time::{Duration, MissedTickBehavior};
+
+use super::ring::{ShmReader, ShmRing};
+use crate::adapters::lease::LeaseSlot;
+use crate::models::hub::hub_data::MAX_DATA_LEN;
+use crate::models::hub::{HubChannelName, HubMessage};
+use crate::ports::{NodeTasks, NotificationHub};
+
+const DEFAULT_CAPACITY: usize = 64;
+// room for the largest data, plus channel, timestamp and JSON escaping
+const DEFAULT_SLOT_SIZE: usize = MAX_DATA_LEN + 4096;
+const DEFAULT_POLL_PERIOD: Duration = Duration::from_millis(1);
+// Retry period while the ring of the peer doesn't exist yet
+const OPEN_RETRY_PERIOD: Duration = Duration::from_millis(100);
+
+/// `ShmOptions` configures the ring written by a `ShmClient`.
+///
+/// # Fields
+/// - `capacity`: Number of slots of the ring. Readers more than `capacity` messages behind
+///   lose the oldest ones.
+/// - `slot_size`: Max size of a serialized message, in bytes.
+/// - `poll_period`: Period at which the ring of the peer is checked for new messages.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShmOptions {
+    capacity: usize,
+    slot_size: usize,
+    poll_period: Duration,
+}
+
+impl Default for ShmOptions {
+    fn default() -> Self {
+        Self {
+            capacity: DEFAULT_CAPACITY,
+            slot_size: DEFAULT_SLOT_SIZE,
+            poll_period: DEFAULT_POLL_PERIOD,
+        }
+    }
+}
+
+impl ShmOptions {
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+    pub fn slot_size(&self) -> usize {
+        self.slot_size
+    }
+    pub fn poll_period(&self) -> Duration {
+        self.poll_period
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ShmOptionsBuilder {
+    capacity: Option<usize>,
+    slot_size: Option<usize>,
+    poll_period: Option<Duration>,
+}
+
+impl ShmOptionsBuilder {
+    pub fn new() -> Self {
+        Self {
+            capacity: None,
+            slot_size: None,
+            poll_period: None,
+        }
+    }
+
+    pub fn capacity(&self, capacity: usize) -> Self {
+        let mut new = self.clone();
+        new.capacity = Some(capacity);
+        new
+    }
+    pub fn slot_size(&self, slot_size: usize) -> Self {
+        let mut new = self.clone();
+        new.slot_size = Some(slot_size);
+        new
+    }
+    pub fn poll_period(&self, poll_period: Duration) -> Self {
+        let mut new = self.clone();
+        new.poll_period = Some(poll_period);
+        new
+    }
+    pub fn build(self) -> Result<ShmOptions, String> {
+        let capacity = self.capacity.unwrap_or(DEFAULT_CAPACITY);
+        if capacity == 0 {
+            return Err("Ring capacity must be positive".to_string());
+        }
+        let slot_size = self.slot_size.unwrap_or(DEFAULT_SLOT_SIZE);
+        if slot_size == 0 {
+            return Err("Ring slot size must be positive".to_string());
+        }
+        let poll_period = self.poll_period.unwrap_or(DEFAULT_POLL_PERIOD);
+        if poll_period.is_zero() {
+            return Err("Poll period must be positive".to_string());
+        }
+        Ok(ShmOptions {
+            capacity,
+            slot_size,
+            poll_period,
+        })
+    }
+}
+
+impl Default for ShmOptionsBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// `ShmClient` is a hub node exchanging messages with another process of the same host through
+/// shared memory, for high-frequency data (camera frames, 1 kHz IMU) where sockets add too much
+/// overhead.
+///
+/// Each process writes its messages to its own ring, and reads the ring of its peer: the
+/// publish path of one client is the subscribe path of the other. Writing never blocks, so a
+/// slow reader loses the oldest messages instead of slowing down the writer.
+///
+/// # Fields
+/// - `publish_path`: Path of the ring written by this client, created on `new`.
+/// - `subscribe_path`: Path of the ring written by the peer.
+/// - `writer`: Ring written by this client.
+/// - `options`: Ring and poll configuration.
+/// - `reader`: Reader of the ring of the peer, once opened. It is leased by the read loop, so
+///   reading resumes where it stopped when the node is started again.
+/// - `channels`: Channels received so far.
+/// - `lost`: Messages of the peer overwritten before they were read.
+#[derive(Debug)]
+pub struct ShmClient {
+    publish_path: PathBuf,
+    subscribe_path: PathBuf,
+    writer: std::sync::Mutex<ShmRing>,
+    options: ShmOptions,
+    reader: LeaseSlot<Option<ShmReader>>,
+    channels: Arc<RwLock<HashSet<HubChannelName>>>,
+    lost: Arc<AtomicU64>,
+}
+
+impl ShmClient {
+    /// Creates the ring at `publish_path`, e.g. `/dev/shm/robopilot_camera`
+    pub fn new(
+        publish_path: impl AsRef<Path>,
+        subscribe_path: impl AsRef<Path>,
+        options: ShmOptions,
+    ) -> Result<Self, std::io::Error> {
+        let publish_path = publish_path.as_ref().to_path_buf();
+        let writer = ShmRing::create(&publish_path, options.capacity(), options.slot_size())?;
+        info!(
+            "Shared memory ring created at {} ({} slots of {} bytes)",
+            publish_path.display(),
+            options.capacity(),
+            options.slot_size()
+        );
+        Ok(Self {
+            publish_path,
+            subscribe_path: subscribe_path.as_ref().to_path_buf(),
+            writer: std::sync::Mutex::new(writer),
+            options,
+            reader: LeaseSlot::new(None),
+            channels: Arc::new(RwLock::new(HashSet::new())),
+            lost: Arc::new(AtomicU64::new(0)),
+        })
+    }
+
+    /// Messages of the peer overwritten before they were read
+    pub fn lost(&self) -> u64 {
+        self.lost.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for ShmClient {
+    fn drop(&mut self) {
+        // mappings of the peer remain valid after the file is removed
+        let _ = std::fs::remove_file(&self.publish_path);
+    }
+}
+
+#[async_trait]
+impl NotificationHub for ShmClient {
+    /// Write message to the ring of this client
+    async fn send(&self, data: HubMessage) -> Result<(), std::io::Error> {
+        let raw_bytes = data.to_bytes()?;
+        let writer = match self.writer.lock() {
+            Ok(writer) => writer,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        writer
+            .push(&raw_bytes)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))
+    }
+
+    /// List channels received so far
+    async fn list_channels(&self) -> Result<Vec<HubChannelName>, std::io::Error> {
+        Ok(self.channels.read().await.iter().cloned().collect())
+    }
+
+    /// Start read loop of the ring of the peer
+    async fn start(
+        &self,
+        sender: Option<broadcast::Sender<HubMessage>>,
+    ) -> Result<NodeTasks, std::io::Error> {
+        let mut tasks = NodeTasks::new();
+        if let Some(sender) = sender {
+            let mut reader = self.reader.lease("Shared memory client")?;
+            if reader.is_none() {
+                // open the ring now if it exists, so that no message sent from now on is missed
+                *reader = ShmReader::open(&self.subscribe_path).ok();
+            }
+            let path = self.subscribe_path.clone();
+            let poll_period = self.options.poll_period();
+            let channels = Arc::clone(&self.channels);
+            let lost = Arc::clone(&self.lost);
+            let task = tokio::spawn(async move {
+                while reader.is_none() {
+                    match ShmReader::open(&path) {
+                        Ok(opened) => *reader = Some(opened),
+                        Err(e) => {
+                            debug!("Shared memory ring {} not ready: {}", path.display(), e);
+                            tokio::time::sleep(OPEN_RETRY_PERIOD).await;
+                        }
+                    }
+                }
+                let Some(reader) = reader.as_mut() else {
+                    return;
+                };
+                info!("Shared memory ring {} opened", path.display());
+                let mut interval = tokio::time::interval(poll_period);
+                interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+                loop {
+                    interval.tick().await;
+                    while let Some(raw_bytes) = reader.next_message() {
+                        match HubMessage::try_from(raw_bytes) {
+                            Ok(message) => {
+                                if !channels.read().await.contains(&message.channel) {
+                                    channels.write().await.insert(message.channel.clone());
+                                }
+                                let _ = sender.send(message);
+                            }
+                            Err(e) => warn!("Invalid shared memory message: {}", e),
+                        }
+                    }
+                    lost.store(reader.lost(), Ordering::Relaxed);
+                }
+            });
+            tasks.push(task);
+        }
+        Ok(tasks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time::timeout;
+
+    fn ring_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("robopilot_{}_{}", name, uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn test_options() {
+        let options = ShmOptionsBuilder::new()
+            .capacity(8)
+            .slot_size(1024)
+            .build()
+            .unwrap();
+        assert_eq!(options.capacity(), 8);
+        assert_eq!(options.slot_size(), 1024);
+        assert_eq!(options.poll_period(), DEFAULT_POLL_PERIOD);
+        assert!(ShmOptionsBuilder::new().capacity(0).build().is_err());
+        assert!(ShmOptionsBuilder::new().slot_size(0).build().is_err());
+        assert!(ShmOptionsBuilder::new()
+            .poll_period(Duration::ZERO)
+            .build()
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_shm_clients() {
+        let path1 = ring_path("a");
+        let path2 = ring_path("b");
+        let options = ShmOptionsBuilder::new().slot_size(256).build().unwrap();
+        let node1 = ShmClient::new(&path1, &path2, options).unwrap();
+        let node2 = ShmClient::new(&path2, &path1, options).unwrap();
+        let (sender, mut receiver) = broadcast::channel(10);
+        node2.start(Some(sender.clone())).await.unwrap();
+        assert!(node2.start(Some(sender)).await.is_err());
+
+        node1
+            .send(HubMessage::try_from_str("imu", "0.1,0.2,9.8").unwrap())
+            .await
+            .unwrap();
+        let large = "x".repeat(256);
+        assert!(node1
+            .send(HubMessage::try_from_str("camera", &large).unwrap())
+            .await
+            .is_err());
+
+        let received = timeout(Duration::from_secs(1), receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(received.channel.as_str(), "imu");
+        assert_eq!(received.data.as_str(), "0.1,0.2,9.8");
+        assert_eq!(node2.lost(), 0);
+
+        drop(node1);
+        assert!(!path1.exists());
+    }
+}
@@ -0,0 +1,7 @@
+/// Shared memory transport for the notification hub, for high-frequency data between processes
+/// of the same host.
+pub mod client;
+pub mod ring;
+
+pub use client::{ShmClient, ShmOptions, ShmOptionsBuilder};
+pub use ring::{ShmReader, ShmRing};
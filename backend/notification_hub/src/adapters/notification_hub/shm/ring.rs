@@ -0,0 +1,293 @@
+//! Module implements a lock-free ring buffer in a memory mapped file, shared by processes of
+//! the same host.
+//!
+//! A single writer overwrites the slots in order, and any number of readers follow it with
+//! their own cursor. Every slot carries the sequence number of the message it holds, so that
+//! readers detect slots overwritten while they were being read (as in a seqlock), and count
+//! messages lost when they fall more than a full ring behind the writer.
+//!
+//! Layout of the file:
+//! - Header (128 bytes): magic, capacity and slot size, followed by the write index in its
+//!   own cache line.
+//! - `capacity` slots: sequence number, length, and `slot_size` bytes of data.
+
+use memmap2::MmapMut;
+use std::fs::OpenOptions;
+use std::path::Path;
+use std::sync::atomic::{fence, AtomicU64, Ordering};
+
+const MAGIC: u64 = 0x524f_424f_5348_4d31;
+const MAGIC_OFFSET: usize = 0;
+const CAPACITY_OFFSET: usize = 8;
+const SLOT_SIZE_OFFSET: usize = 16;
+const WRITE_INDEX_OFFSET: usize = 64;
+const HEADER_LEN: usize = 128;
+const SLOT_HEADER_LEN: usize = 16;
+
+// Slots are 8 byte aligned, so that their header can be accessed atomically
+fn stride(slot_size: usize) -> usize {
+    SLOT_HEADER_LEN + slot_size.div_ceil(8) * 8
+}
+
+fn invalid(msg: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Ring buffer mapped from a file, e.g. under `/dev/shm`
+#[derive(Debug)]
+pub struct ShmRing {
+    map: MmapMut,
+    ptr: *mut u8,
+    capacity: u64,
+    slot_size: usize,
+}
+
+// Safety: the mapping lives as long as the ring, and concurrent accesses to it are
+// synchronized through the atomic write index and slot sequence numbers
+unsafe impl Send for ShmRing {}
+unsafe impl Sync for ShmRing {}
+
+impl ShmRing {
+    /// Creates the ring at `path`, replacing any existing file
+    pub fn create(path: &Path, capacity: usize, slot_size: usize) -> Result<Self, std::io::Error> {
+        if capacity == 0 || slot_size == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Ring capacity and slot size must be positive",
+            ));
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.set_len((HEADER_LEN + capacity * stride(slot_size)) as u64)?;
+        // Safety: the file is owned by the writer, readers only map it
+        let mut map = unsafe { MmapMut::map_mut(&file)? };
+        let ptr = map.as_mut_ptr();
+        let ring = Self {
+            map,
+            ptr,
+            capacity: capacity as u64,
+            slot_size,
+        };
+        ring.atomic(CAPACITY_OFFSET)
+            .store(capacity as u64, Ordering::Relaxed);
+        ring.atomic(SLOT_SIZE_OFFSET)
+            .store(slot_size as u64, Ordering::Relaxed);
+        // magic is written last, so that readers never see a partial header
+        ring.atomic(MAGIC_OFFSET).store(MAGIC, Ordering::Release);
+        Ok(ring)
+    }
+
+    /// Maps the existing ring at `path`
+    pub fn open(path: &Path) -> Result<Self, std::io::Error> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        // Safety: the file is only modified through the atomic protocol of the ring
+        let mut map = unsafe { MmapMut::map_mut(&file)? };
+        if map.len() < HEADER_LEN {
+            return Err(invalid("Shared memory ring too short"));
+        }
+        let ptr = map.as_mut_ptr();
+        let mut ring = Self {
+            map,
+            ptr,
+            capacity: 0,
+            slot_size: 0,
+        };
+        if ring.atomic(MAGIC_OFFSET).load(Ordering::Acquire) != MAGIC {
+            return Err(invalid("Not a shared memory ring"));
+        }
+        ring.capacity = ring.atomic(CAPACITY_OFFSET).load(Ordering::Relaxed);
+        ring.slot_size = ring.atomic(SLOT_SIZE_OFFSET).load(Ordering::Relaxed) as usize;
+        let len = (ring.capacity as usize)
+            .checked_mul(stride(ring.slot_size))
+            .and_then(|len| len.checked_add(HEADER_LEN));
+        match len {
+            Some(len) if ring.capacity > 0 && len <= ring.map.len() => Ok(ring),
+            _ => Err(invalid("Invalid shared memory ring header")),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity as usize
+    }
+
+    pub fn slot_size(&self) -> usize {
+        self.slot_size
+    }
+
+    fn atomic(&self, offset: usize) -> &AtomicU64 {
+        // Safety: offsets are 8 byte aligned and within the mapping
+        unsafe { &*(self.ptr.add(offset) as *const AtomicU64) }
+    }
+
+    fn slot_offset(&self, index: u64) -> usize {
+        HEADER_LEN + (index % self.capacity) as usize * stride(self.slot_size)
+    }
+
+    /// Number of messages written so far
+    pub fn write_index(&self) -> u64 {
+        self.atomic(WRITE_INDEX_OFFSET).load(Ordering::Acquire)
+    }
+
+    /// Writes `data` to the next slot. There must be a single writer per ring.
+    pub fn push(&self, data: &[u8]) -> Result<(), String> {
+        if data.len() > self.slot_size {
+            return Err(format!(
+                "Message of {} bytes exceeds slot size of {} bytes",
+                data.len(),
+                self.slot_size
+            ));
+        }
+        let index = self.atomic(WRITE_INDEX_OFFSET).load(Ordering::Relaxed);
+        let slot = self.slot_offset(index);
+        // invalidate the slot while it is written
+        self.atomic(slot).store(0, Ordering::Relaxed);
+        fence(Ordering::Release);
+        self.atomic(slot + 8)
+            .store(data.len() as u64, Ordering::Relaxed);
+        // Safety: the slot holds at least `slot_size` bytes
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                data.as_ptr(),
+                self.ptr.add(slot + SLOT_HEADER_LEN),
+                data.len(),
+            );
+        }
+        self.atomic(slot).store(index + 1, Ordering::Release);
+        self.atomic(WRITE_INDEX_OFFSET)
+            .store(index + 1, Ordering::Release);
+        Ok(())
+    }
+
+    // Copies message `index`, or returns `None` if its slot was overwritten
+    fn read(&self, index: u64) -> Option<Vec<u8>> {
+        let slot = self.slot_offset(index);
+        if self.atomic(slot).load(Ordering::Acquire) != index + 1 {
+            return None;
+        }
+        let len = (self.atomic(slot + 8).load(Ordering::Relaxed) as usize).min(self.slot_size);
+        let mut data = vec![0; len];
+        // Safety: the slot holds at least `slot_size` bytes
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                self.ptr.add(slot + SLOT_HEADER_LEN),
+                data.as_mut_ptr(),
+                len,
+            );
+        }
+        fence(Ordering::Acquire);
+        if self.atomic(slot).load(Ordering::Relaxed) != index + 1 {
+            return None;
+        }
+        Some(data)
+    }
+}
+
+/// Reader following the writer of a `ShmRing`
+///
+/// # Fields
+/// - `ring`: Mapped ring.
+/// - `cursor`: Index of the next message to read.
+/// - `lost`: Messages overwritten before they were read.
+#[derive(Debug)]
+pub struct ShmReader {
+    ring: ShmRing,
+    cursor: u64,
+    lost: u64,
+}
+
+impl ShmReader {
+    /// Maps the ring at `path`, and starts reading from the messages written from now on
+    pub fn open(path: &Path) -> Result<Self, std::io::Error> {
+        Ok(Self::new(ShmRing::open(path)?))
+    }
+
+    pub fn new(ring: ShmRing) -> Self {
+        let cursor = ring.write_index();
+        Self {
+            ring,
+            cursor,
+            lost: 0,
+        }
+    }
+
+    pub fn lost(&self) -> u64 {
+        self.lost
+    }
+
+    /// Next message, or `None` if the reader caught up with the writer
+    pub fn next_message(&mut self) -> Option<Vec<u8>> {
+        loop {
+            let write_index = self.ring.write_index();
+            if self.cursor >= write_index {
+                return None;
+            }
+            if write_index - self.cursor > self.ring.capacity {
+                let oldest = write_index - self.ring.capacity;
+                self.lost += oldest - self.cursor;
+                self.cursor = oldest;
+            }
+            let index = self.cursor;
+            self.cursor += 1;
+            match self.ring.read(index) {
+                Some(data) => return Some(data),
+                None => self.lost += 1,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ring_path() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("robopilot_ring_{}", uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn test_ring() {
+        let path = ring_path();
+        let writer = ShmRing::create(&path, 4, 10).unwrap();
+        let mut reader = ShmReader::open(&path).unwrap();
+        assert_eq!(reader.ring.capacity(), 4);
+        assert_eq!(reader.ring.slot_size(), 10);
+        assert!(reader.next_message().is_none());
+
+        writer.push(b"imu").unwrap();
+        writer.push(b"").unwrap();
+        assert!(writer.push(&[0; 11]).is_err());
+        assert_eq!(reader.next_message().unwrap(), b"imu");
+        assert_eq!(reader.next_message().unwrap(), b"");
+        assert!(reader.next_message().is_none());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_overrun() {
+        let path = ring_path();
+        let writer = ShmRing::create(&path, 4, 1).unwrap();
+        let mut reader = ShmReader::open(&path).unwrap();
+        for i in 0..10u8 {
+            writer.push(&[i]).unwrap();
+        }
+        let received: Vec<u8> = std::iter::from_fn(|| reader.next_message())
+            .map(|data| data[0])
+            .collect();
+        assert_eq!(received, vec![6, 7, 8, 9]);
+        assert_eq!(reader.lost(), 6);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_ring() {
+        let path = ring_path();
+        std::fs::write(&path, [0u8; HEADER_LEN]).unwrap();
+        assert!(ShmRing::open(&path).is_err());
+        assert!(ShmRing::create(&path, 0, 1).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+}
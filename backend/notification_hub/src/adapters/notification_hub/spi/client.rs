@@ -0,0 +1,212 @@
+use async_trait::async_trait;
+use log::{error, info, warn};
+use spidev::{SpiModeFlags, Spidev, SpidevOptions, SpidevTransfer};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{broadcast, RwLock};
+use tokio::time::MissedTickBehavior;
+
+use super::options::{SpiSensorOptions, SpiTransfer};
+use crate::models::hub::{HubChannelName, HubMessage};
+use crate::ports::{NodeTasks, NotificationHub};
+
+fn mode_flags(mode: u8) -> SpiModeFlags {
+    match mode {
+        1 => SpiModeFlags::SPI_MODE_1,
+        2 => SpiModeFlags::SPI_MODE_2,
+        3 => SpiModeFlags::SPI_MODE_3,
+        _ => SpiModeFlags::SPI_MODE_0,
+    }
+}
+
+// Request of a transfer, padded with zeros to the length of the transfer
+fn request_frame(transfer: &SpiTransfer) -> Vec<u8> {
+    let mut frame = transfer.request.clone();
+    frame.resize(transfer.len(), 0);
+    frame
+}
+
+// Performs every transfer in order. Runs on a blocking thread, as spidev transfers are
+// synchronous.
+fn transfer_all(spi: &Mutex<Spidev>, requests: &[Vec<u8>]) -> Vec<Result<Vec<u8>, String>> {
+    let spi = match spi.lock() {
+        Ok(spi) => spi,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    requests
+        .iter()
+        .map(|request| {
+            let mut response = vec![0; request.len()];
+            let mut transfer = SpidevTransfer::read_write(request, &mut response);
+            spi.transfer(&mut transfer).map_err(|e| e.to_string())?;
+            Ok(response)
+        })
+        .collect()
+}
+
+/// `SpiSensorClient` is a hub node sampling high-rate SPI sensors (ADCs, encoders...) through
+/// the Linux spidev interface, and publishing the decoded samples on hub channels.
+///
+/// On every poll, each configured transfer is performed and its response is decoded with its
+/// `SampleLayout` into comma separated values. Sensors are read only, so messages sent to the
+/// node are ignored.
+///
+/// # Fields
+/// - `options`: Device, clock speed, poll rate and transfers.
+/// - `spi`: Open spidev device, shared with the poll loop.
+/// - `channels`: Channels published so far.
+pub struct SpiSensorClient {
+    options: Arc<SpiSensorOptions>,
+    spi: Arc<Mutex<Spidev>>,
+    channels: Arc<RwLock<HashSet<HubChannelName>>>,
+}
+
+impl std::fmt::Debug for SpiSensorClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SpiSensorClient")
+            .field("options", &self.options)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SpiSensorClient {
+    /// Opens and configures the spidev device of `options`
+    pub fn new(options: SpiSensorOptions) -> Result<Self, std::io::Error> {
+        let mut spi = Spidev::open(options.device())?;
+        let spi_options = SpidevOptions::new()
+            .bits_per_word(8)
+            .max_speed_hz(options.clock_speed())
+            .mode(mode_flags(options.mode()))
+            .build();
+        spi.configure(&spi_options)?;
+        info!(
+            "SPI sensor client opened {} at {} Hz",
+            options.device(),
+            options.clock_speed()
+        );
+        Ok(Self {
+            options: Arc::new(options),
+            spi: Arc::new(Mutex::new(spi)),
+            channels: Arc::new(RwLock::new(HashSet::new())),
+        })
+    }
+
+    pub fn options(&self) -> &SpiSensorOptions {
+        &self.options
+    }
+}
+
+#[async_trait]
+impl NotificationHub for SpiSensorClient {
+    /// Sensors are read only, messages are ignored
+    async fn send(&self, _data: HubMessage) -> Result<(), std::io::Error> {
+        Ok(())
+    }
+
+    /// List channels published so far
+    async fn list_channels(&self) -> Result<Vec<HubChannelName>, std::io::Error> {
+        Ok(self.channels.read().await.iter().cloned().collect())
+    }
+
+    /// Start poll loop
+    async fn start(
+        &self,
+        sender: Option<broadcast::Sender<HubMessage>>,
+    ) -> Result<NodeTasks, std::io::Error> {
+        let mut tasks = NodeTasks::new();
+        if let Some(sender) = sender {
+            let options = Arc::clone(&self.options);
+            let spi = Arc::clone(&self.spi);
+            let channels = Arc::clone(&self.channels);
+            let requests: Arc<Vec<Vec<u8>>> =
+                Arc::new(options.transfers().iter().map(request_frame).collect());
+            let task = tokio::spawn(async move {
+                let mut interval = tokio::time::interval(options.period());
+                interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+                loop {
+                    interval.tick().await;
+                    let responses = {
+                        let spi = Arc::clone(&spi);
+                        let requests = Arc::clone(&requests);
+                        tokio::task::spawn_blocking(move || transfer_all(&spi, &requests)).await
+                    };
+                    let responses = match responses {
+                        Ok(responses) => responses,
+                        Err(e) => {
+                            error!("SPI poll task failed: {:?}", e);
+                            break;
+                        }
+                    };
+                    for (transfer, response) in options.transfers().iter().zip(responses) {
+                        match response.and_then(|response| transfer.layout.decode(&response)) {
+                            Ok(data) => {
+                                if !channels.read().await.contains(&transfer.channel) {
+                                    channels.write().await.insert(transfer.channel.clone());
+                                }
+                                let message = HubMessage::new(transfer.channel.clone(), data);
+                                let _ = sender.send(message);
+                            }
+                            Err(e) => warn!(
+                                "SPI transfer of channel {} failed: {}",
+                                transfer.channel.as_str(),
+                                e
+                            ),
+                        }
+                    }
+                }
+            });
+            tasks.push(task);
+        }
+        Ok(tasks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::sample::{FieldKind, SampleField, SampleLayout};
+    use crate::adapters::spi::SpiSensorOptionsBuilder;
+    use tokio::time::{timeout, Duration};
+
+    #[test]
+    fn test_request_frame() {
+        let transfer = SpiTransfer {
+            request: vec![0x01, 0x80],
+            channel: HubChannelName::try_from("adc").unwrap(),
+            layout: SampleLayout::new(vec![
+                SampleField::new("ch0", 1, FieldKind::U16Be).masked(0x03ff)
+            ])
+            .unwrap(),
+        };
+        assert_eq!(request_frame(&transfer), vec![0x01, 0x80, 0x00]);
+        assert_eq!(mode_flags(3), SpiModeFlags::SPI_MODE_3);
+    }
+
+    // Requires an MCP3008 ADC on /dev/spidev0.0
+    #[tokio::test]
+    #[ignore]
+    async fn test_mcp3008() {
+        // start bit, then single ended input 0. The 10 bit reading is in the last two bytes.
+        let layout = SampleLayout::new(vec![SampleField::new("ch0", 1, FieldKind::U16Be)
+            .masked(0x03ff)
+            .scaled(3.3 / 1023.0, 0.0)])
+        .unwrap();
+        let options = SpiSensorOptionsBuilder::new()
+            .clock_speed(1_350_000)
+            .rate(1000.0)
+            .transfer(&[0x01, 0x80, 0x00], "adc/ch0", layout)
+            .build()
+            .unwrap();
+        let node = SpiSensorClient::new(options).unwrap();
+        let (sender, mut receiver) = broadcast::channel(100);
+        node.start(Some(sender)).await.unwrap();
+
+        let received = timeout(Duration::from_secs(1), receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(received.channel.as_str(), "adc/ch0");
+        let volts: f64 = received.data.as_str().parse().unwrap();
+        assert!((0.0..=3.3).contains(&volts));
+    }
+}
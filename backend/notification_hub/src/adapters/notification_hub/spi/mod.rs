@@ -0,0 +1,6 @@
+/// SPI sensor transport for the notification hub, sampling devices over Linux spidev.
+pub mod client;
+pub mod options;
+
+pub use client::SpiSensorClient;
+pub use options::{SpiSensorOptions, SpiSensorOptionsBuilder, SpiTransfer};
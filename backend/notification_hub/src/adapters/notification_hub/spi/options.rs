@@ -0,0 +1,230 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use crate::adapters::sample::SampleLayout;
+use crate::models::hub::HubChannelName;
+
+const DEFAULT_DEVICE: &str = "/dev/spidev0.0";
+const DEFAULT_CLOCK_SPEED_HZ: u32 = 1_000_000;
+const DEFAULT_RATE_HZ: f64 = 100.0;
+const MAX_RATE_HZ: f64 = 10_000.0;
+// Default buffer size of the spidev driver
+const MAX_TRANSFER_LEN: usize = 4096;
+
+/// Full duplex transfer performed on every poll, whose response is published on a hub channel.
+///
+/// `request` is clocked out while the response is clocked in, so the transfer is as long as
+/// the longest of `request` and `layout`, with `request` padded with zeros. Field offsets of
+/// `layout` are relative to the start of the transfer.
+///
+/// # Fields
+/// - `request`: Bytes sent to the device, e.g. a command selecting an ADC input.
+/// - `channel`: Hub channel where samples are published.
+/// - `layout`: Layout of the response, decoded into the published data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpiTransfer {
+    pub request: Vec<u8>,
+    pub channel: HubChannelName,
+    pub layout: SampleLayout,
+}
+
+impl SpiTransfer {
+    /// Length of the transfer in bytes
+    pub fn len(&self) -> usize {
+        self.request.len().max(self.layout.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// `SpiSensorOptions` configures an `SpiSensorClient`.
+///
+/// # Fields
+/// - `device`: Path of the spidev device, selecting bus and chip select. Defaults to
+///   `/dev/spidev0.0`.
+/// - `clock_speed`: Max clock speed in Hz. Defaults to 1 MHz.
+/// - `mode`: SPI mode, 0 to 3, setting clock polarity and phase. Defaults to 0.
+/// - `period`: Poll period. Defaults to 100 Hz.
+/// - `transfers`: Transfers performed on every poll, in order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpiSensorOptions {
+    device: String,
+    clock_speed: u32,
+    mode: u8,
+    period: Duration,
+    transfers: Vec<SpiTransfer>,
+}
+
+impl SpiSensorOptions {
+    pub fn device(&self) -> &str {
+        &self.device
+    }
+    pub fn clock_speed(&self) -> u32 {
+        self.clock_speed
+    }
+    pub fn mode(&self) -> u8 {
+        self.mode
+    }
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+    pub fn transfers(&self) -> &[SpiTransfer] {
+        &self.transfers
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SpiSensorOptionsBuilder {
+    device: Option<String>,
+    clock_speed: Option<u32>,
+    mode: Option<u8>,
+    rate: Option<f64>,
+    transfers: Vec<(Vec<u8>, String, SampleLayout)>,
+}
+
+impl SpiSensorOptionsBuilder {
+    pub fn new() -> Self {
+        Self {
+            device: None,
+            clock_speed: None,
+            mode: None,
+            rate: None,
+            transfers: Vec::new(),
+        }
+    }
+
+    pub fn device(&self, device: &str) -> Self {
+        let mut new = self.clone();
+        new.device = Some(device.to_string());
+        new
+    }
+    /// Max clock speed in Hz
+    pub fn clock_speed(&self, clock_speed: u32) -> Self {
+        let mut new = self.clone();
+        new.clock_speed = Some(clock_speed);
+        new
+    }
+    pub fn mode(&self, mode: u8) -> Self {
+        let mut new = self.clone();
+        new.mode = Some(mode);
+        new
+    }
+    /// Poll rate in Hz
+    pub fn rate(&self, rate: f64) -> Self {
+        let mut new = self.clone();
+        new.rate = Some(rate);
+        new
+    }
+    /// Sends `request` and publishes the response, decoded with `layout`, on `channel`
+    pub fn transfer(&self, request: &[u8], channel: &str, layout: SampleLayout) -> Self {
+        let mut new = self.clone();
+        new.transfers
+            .push((request.to_vec(), channel.to_string(), layout));
+        new
+    }
+    pub fn build(self) -> Result<SpiSensorOptions, String> {
+        let clock_speed = self.clock_speed.unwrap_or(DEFAULT_CLOCK_SPEED_HZ);
+        if clock_speed == 0 {
+            return Err("SPI clock speed must be positive".to_string());
+        }
+        let mode = self.mode.unwrap_or(0);
+        if mode > 3 {
+            return Err(format!("Invalid SPI mode {}", mode));
+        }
+        let rate = self.rate.unwrap_or(DEFAULT_RATE_HZ);
+        if !rate.is_finite() || rate <= 0.0 || rate > MAX_RATE_HZ {
+            return Err(format!(
+                "Poll rate must be positive and at most {} Hz",
+                MAX_RATE_HZ
+            ));
+        }
+        if self.transfers.is_empty() {
+            return Err("No SPI transfers to perform".to_string());
+        }
+        let mut channels = HashSet::new();
+        let mut transfers = Vec::with_capacity(self.transfers.len());
+        for (request, channel, layout) in self.transfers {
+            let channel = HubChannelName::try_from(channel.as_str())?;
+            if !channels.insert(channel.clone()) {
+                return Err(format!(
+                    "Channel {} is transferred more than once",
+                    channel.as_str()
+                ));
+            }
+            let transfer = SpiTransfer {
+                request,
+                channel,
+                layout,
+            };
+            if transfer.len() > MAX_TRANSFER_LEN {
+                return Err(format!(
+                    "Transfers of channel {} exceed {} bytes",
+                    transfer.channel.as_str(),
+                    MAX_TRANSFER_LEN
+                ));
+            }
+            transfers.push(transfer);
+        }
+        Ok(SpiSensorOptions {
+            device: self.device.unwrap_or(DEFAULT_DEVICE.to_string()),
+            clock_speed,
+            mode,
+            period: Duration::from_secs_f64(1.0 / rate),
+            transfers,
+        })
+    }
+}
+
+impl Default for SpiSensorOptionsBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::sample::{FieldKind, SampleField};
+
+    fn layout(len: usize) -> SampleLayout {
+        SampleLayout::new(vec![SampleField::new("x", len - 1, FieldKind::U8)]).unwrap()
+    }
+
+    #[test]
+    fn test_options() {
+        let options = SpiSensorOptionsBuilder::new()
+            .clock_speed(1_350_000)
+            .rate(1000.0)
+            .transfer(&[0x01, 0x80, 0x00], "adc/0", layout(3))
+            .transfer(&[0x01], "adc/1", layout(3))
+            .build()
+            .unwrap();
+        assert_eq!(options.device(), "/dev/spidev0.0");
+        assert_eq!(options.clock_speed(), 1_350_000);
+        assert_eq!(options.mode(), 0);
+        assert_eq!(options.period(), Duration::from_millis(1));
+        assert_eq!(options.transfers().len(), 2);
+        assert_eq!(options.transfers()[1].len(), 3);
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        let builder = SpiSensorOptionsBuilder::new();
+        assert!(builder.clone().build().is_err());
+        let builder = builder.transfer(&[0x01], "encoder", layout(2));
+        assert!(builder.clock_speed(0).build().is_err());
+        assert!(builder.mode(4).build().is_err());
+        assert!(builder.rate(20_000.0).build().is_err());
+        assert!(builder
+            .transfer(&[0x02], "encoder", layout(2))
+            .build()
+            .is_err());
+        assert!(builder
+            .transfer(&[], "big", layout(MAX_TRANSFER_LEN + 1))
+            .build()
+            .is_err());
+        assert!(builder.build().is_ok());
+    }
+}
@@ -0,0 +1,4 @@
+/// HTTP bridge streaming hub messages as Server-Sent Events.
+pub mod server;
+
+pub use server::SseServer;
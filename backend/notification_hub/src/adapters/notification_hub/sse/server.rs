@@ -0,0 +1,261 @@
+use async_trait::async_trait;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::routing::{get, post};
+use axum::Router;
+use futures_util::Stream;
+use log::{error, info, warn};
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast, RwLock};
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
+use tokio_stream::StreamExt;
+
+use crate::adapters::lease::LeaseSlot;
+use crate::models::hub::{HubChannelName, HubMessage};
+use crate::ports::{NodeTasks, NotificationHub};
+
+const CHANNEL_CAPACITY: usize = 100;
+
+type HttpError = (StatusCode, String);
+
+/// `SseServer` is a hub node serving hub messages over plain HTTP, so that browsers and simple
+/// scripts consume telemetry without a WebSocket client.
+///
+/// - `GET /events/<channel>` streams the messages sent to the hub on the channel as
+///   Server-Sent Events. Every event is named after the channel, and its data is the JSON
+///   encoded `HubMessage`.
+/// - `POST /publish/<channel>` publishes the request body as data of a message on the channel.
+///
+/// Channel names may include namespaces (e.g. `GET /events/robot1/pose`).
+///
+/// # Fields
+/// - `address`: Address the server is listening to.
+/// - `listener`: TCP listener bound by `new`, consumed by the server task when the node is first
+///   started. Its lease marks the server as running, and the address is bound again when the
+///   node is started again.
+/// - `state`: State shared with the HTTP handlers.
+#[derive(Debug)]
+pub struct SseServer {
+    address: SocketAddr,
+    listener: LeaseSlot<Option<TcpListener>>,
+    state: SseState,
+}
+
+/// # Fields
+/// - `hub_sender`: Sender of messages published by clients, set when the node is started.
+/// - `outgoing`: Messages sent to the node, fanned out to every event stream.
+/// - `channels`: Channels published by clients so far.
+#[derive(Debug, Clone)]
+struct SseState {
+    hub_sender: Arc<RwLock<Option<broadcast::Sender<HubMessage>>>>,
+    outgoing: broadcast::Sender<HubMessage>,
+    channels: Arc<RwLock<HashSet<HubChannelName>>>,
+}
+
+impl SseServer {
+    /// Binds HTTP server to `address` (e.g. `0.0.0.0:8080`)
+    pub async fn new(address: &str) -> Result<Self, std::io::Error> {
+        let listener = TcpListener::bind(address).await?;
+        let address = listener.local_addr()?;
+        info!("SSE server listening on {}", address);
+        let (outgoing, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Ok(Self {
+            address,
+            listener: LeaseSlot::new(Some(listener)),
+            state: SseState {
+                hub_sender: Arc::new(RwLock::new(None)),
+                outgoing,
+                channels: Arc::new(RwLock::new(HashSet::new())),
+            },
+        })
+    }
+
+    /// Address the server is listening to. Resolves the port chosen when binding to port 0
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+}
+
+fn router(state: SseState) -> Router {
+    Router::new()
+        .route("/events/*channel", get(events))
+        .route("/publish/*channel", post(publish))
+        .with_state(state)
+}
+
+async fn events(
+    State(state): State<SseState>,
+    Path(channel): Path<String>,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, HttpError> {
+    let channel = HubChannelName::try_from(channel).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let stream =
+        BroadcastStream::new(state.outgoing.subscribe()).filter_map(move |message| match message {
+            Ok(message) if message.channel == channel => {
+                Some(Event::default().event(channel.as_str()).json_data(&message))
+            }
+            Ok(_) => None,
+            Err(BroadcastStreamRecvError::Lagged(skipped)) => {
+                warn!("SSE client lagged, {} messages skipped", skipped);
+                None
+            }
+        });
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+async fn publish(
+    State(state): State<SseState>,
+    Path(channel): Path<String>,
+    data: String,
+) -> Result<StatusCode, HttpError> {
+    let message =
+        HubMessage::try_from_str(&channel, &data).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    state.channels.write().await.insert(message.channel.clone());
+    let hub_sender = state.hub_sender.read().await;
+    let hub_sender = hub_sender.as_ref().ok_or_else(|| {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Hub does not accept messages".to_string(),
+        )
+    })?;
+    let _ = hub_sender.send(message);
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[async_trait]
+impl NotificationHub for SseServer {
+    /// Send message to clients streaming its channel
+    async fn send(&self, data: HubMessage) -> Result<(), std::io::Error> {
+        // no event stream open
+        let _ = self.state.outgoing.send(data);
+        Ok(())
+    }
+
+    /// List channels published by clients so far
+    async fn list_channels(&self) -> Result<Vec<HubChannelName>, std::io::Error> {
+        Ok(self.state.channels.read().await.iter().cloned().collect())
+    }
+
+    /// Start serving HTTP requests
+    async fn start(
+        &self,
+        sender: Option<broadcast::Sender<HubMessage>>,
+    ) -> Result<NodeTasks, std::io::Error> {
+        let mut running = self.listener.lease("SSE server")?;
+        let listener = match running.take() {
+            Some(listener) => listener,
+            None => TcpListener::bind(self.address).await?,
+        };
+        *self.state.hub_sender.write().await = sender;
+
+        let router = router(self.state.clone());
+        let task = tokio::spawn(async move {
+            let _running = running;
+            if let Err(e) = axum::serve(listener, router).await {
+                error!("SSE server error: {}", e);
+            }
+        });
+        let mut tasks = NodeTasks::new();
+        tasks.push(task);
+        Ok(tasks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+    use tokio::time::{timeout, Duration};
+
+    // Reads from stream until response contains pattern
+    async fn read_until(stream: &mut TcpStream, response: &mut String, pattern: &str) {
+        let mut buffer = [0; 1024];
+        while !response.contains(pattern) {
+            let n = stream.read(&mut buffer).await.unwrap();
+            assert!(n > 0, "connection closed");
+            response.push_str(&String::from_utf8_lossy(&buffer[..n]));
+        }
+    }
+
+    async fn start() -> (SseServer, broadcast::Receiver<HubMessage>) {
+        let server = SseServer::new("127.0.0.1:0").await.unwrap();
+        let (sender, receiver) = broadcast::channel(10);
+        server.start(Some(sender)).await.unwrap();
+        (server, receiver)
+    }
+
+    #[tokio::test]
+    async fn test_events() {
+        let (server, _) = start().await;
+        let mut stream = TcpStream::connect(server.local_addr()).await.unwrap();
+        stream
+            .write_all(b"GET /events/robot1/pose HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        read_until(&mut stream, &mut response, "\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.contains("text/event-stream"));
+
+        server
+            .send(HubMessage::try_from_str("robot2/pose", "0,0,0").unwrap())
+            .await
+            .unwrap();
+        server
+            .send(HubMessage::try_from_str("robot1/pose", "1,2,3").unwrap())
+            .await
+            .unwrap();
+        timeout(
+            Duration::from_secs(5),
+            read_until(&mut stream, &mut response, "\n\n"),
+        )
+        .await
+        .unwrap();
+        assert!(response.contains("event: robot1/pose"));
+        assert!(response.contains("1,2,3"));
+        assert!(!response.contains("0,0,0"));
+    }
+
+    #[tokio::test]
+    async fn test_publish() {
+        let (server, mut receiver) = start().await;
+        let request = |path: &str, body: &str| {
+            format!(
+                "POST {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                path,
+                body.len(),
+                body
+            )
+        };
+
+        let mut stream = TcpStream::connect(server.local_addr()).await.unwrap();
+        stream
+            .write_all(request("/publish/cmd_vel", "1,0").as_bytes())
+            .await
+            .unwrap();
+        let mut response = String::new();
+        read_until(&mut stream, &mut response, "\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 202"));
+
+        let message = timeout(Duration::from_secs(5), receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(message.channel.as_str(), "cmd_vel");
+        assert_eq!(message.data.as_str(), "1,0");
+        assert_eq!(server.list_channels().await.unwrap(), vec![message.channel]);
+
+        let mut stream = TcpStream::connect(server.local_addr()).await.unwrap();
+        stream
+            .write_all(request("/publish/cmd%20vel", "1,0").as_bytes())
+            .await
+            .unwrap();
+        let mut response = String::new();
+        read_until(&mut stream, &mut response, "\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 400"));
+    }
+}
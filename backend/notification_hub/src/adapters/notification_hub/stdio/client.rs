@@ -0,0 +1,210 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use log::{error, info, warn};
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::sync::{broadcast, Mutex, RwLock};
+
+use crate::adapters::lease::LeaseSlot;
+use crate::adapters::serial::buffer::LineBuffer;
+use crate::adapters::serial::message::SerialRawMessage;
+use crate::models::hub::{HubChannelName, HubMessage};
+use crate::ports::{NodeTasks, NotificationHub};
+
+type Reader = Box<dyn AsyncRead + Send + Unpin>;
+type Writer = Box<dyn AsyncWrite + Send + Unpin>;
+
+/// `StdioClient` is a hub node reading messages from stdin and writing messages to stdout, so
+/// that shell scripts and other executables pipe data into and out of the hub.
+///
+/// Messages use the line format of serial ports, `##CHANNEL## DATA`, in both directions.
+/// Lines without a channel tag are ignored. Logs go to stderr, so they don't mix with the
+/// messages written to stdout.
+///
+/// # Fields
+/// - `reader`: Input stream. It is leased by the read loop when the client is started.
+/// - `writer`: Output stream.
+/// - `channels`: Channels read so far.
+pub struct StdioClient {
+    reader: LeaseSlot<Reader>,
+    writer: Mutex<Writer>,
+    channels: Arc<RwLock<HashSet<HubChannelName>>>,
+}
+
+impl std::fmt::Debug for StdioClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StdioClient").finish_non_exhaustive()
+    }
+}
+
+impl StdioClient {
+    /// Client reading from stdin and writing to stdout
+    pub fn new() -> Self {
+        Self::from_streams(tokio::io::stdin(), tokio::io::stdout())
+    }
+
+    /// Client reading from `reader` and writing to `writer` (e.g. the pipes of a child process)
+    pub fn from_streams(
+        reader: impl AsyncRead + Send + Unpin + 'static,
+        writer: impl AsyncWrite + Send + Unpin + 'static,
+    ) -> Self {
+        Self {
+            reader: LeaseSlot::new(Box::new(reader)),
+            writer: Mutex::new(Box::new(writer)),
+            channels: Arc::new(RwLock::new(HashSet::new())),
+        }
+    }
+}
+
+impl Default for StdioClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+async fn forward_line(
+    line: Bytes,
+    channels: &RwLock<HashSet<HubChannelName>>,
+    sender: &broadcast::Sender<HubMessage>,
+) {
+    if !line.starts_with(b"##") {
+        warn!("Invalid stdin line. Waiting for valid channel prefix");
+        return;
+    }
+    match HubMessage::try_from(SerialRawMessage::from_bytes(line)) {
+        Ok(message) => {
+            channels.write().await.insert(message.channel.clone());
+            let _ = sender.send(message.with_source("stdio", "stdin"));
+        }
+        Err(e) => error!("Stdin receive error {:?}", e),
+    }
+}
+
+#[async_trait]
+impl NotificationHub for StdioClient {
+    /// Write message as a line
+    async fn send(&self, data: HubMessage) -> Result<(), std::io::Error> {
+        let line = SerialRawMessage::from(data);
+        let mut writer = self.writer.lock().await;
+        writer.write_all(line.as_str().as_bytes()).await?;
+        writer.write_all(b"\n").await?;
+        // downstream commands must see every line as soon as it is sent
+        writer.flush().await
+    }
+
+    /// List channels read so far
+    async fn list_channels(&self) -> Result<Vec<HubChannelName>, std::io::Error> {
+        Ok(self.channels.read().await.iter().cloned().collect())
+    }
+
+    /// Start read loop. The loop ends when the input stream is closed.
+    async fn start(
+        &self,
+        sender: Option<broadcast::Sender<HubMessage>>,
+    ) -> Result<NodeTasks, std::io::Error> {
+        let mut tasks = NodeTasks::new();
+        if let Some(sender) = sender {
+            let mut reader = self.reader.lease("Stdio client")?;
+            let channels = Arc::clone(&self.channels);
+            let task = tokio::spawn(async move {
+                let mut lines = LineBuffer::new();
+                loop {
+                    match lines.read_from(&mut *reader).await {
+                        Ok(n) if n > 0 => {
+                            while let Some(line) = lines.next_line() {
+                                forward_line(line, &channels, &sender).await;
+                            }
+                        }
+                        Ok(_) => {
+                            // last line may not be terminated
+                            if let Some(line) = lines.take_remaining() {
+                                forward_line(line, &channels, &sender).await;
+                            }
+                            info!("Stdin closed");
+                            break;
+                        }
+                        Err(e) => {
+                            error!("Stdin error {:?}", e);
+                            break;
+                        }
+                    }
+                }
+            });
+            tasks.push(task);
+        }
+        Ok(tasks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use tokio::time::{timeout, Duration};
+
+    #[tokio::test]
+    async fn test_read() {
+        let (mut input, reader) = tokio::io::duplex(1024);
+        let client = StdioClient::from_streams(reader, tokio::io::sink());
+        let (sender, mut receiver) = broadcast::channel(10);
+        let tasks = client.start(Some(sender)).await.unwrap();
+
+        input
+            .write_all(b"##imu## 1,2,3\nnoise\n##cmd##go")
+            .await
+            .unwrap();
+        drop(input);
+        for (channel, data) in [("imu", "1,2,3"), ("cmd", "go")] {
+            let message = timeout(Duration::from_secs(1), receiver.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(message.channel.as_str(), channel);
+            assert_eq!(message.data.as_str(), data);
+        }
+        for task in tasks {
+            timeout(Duration::from_secs(1), task)
+                .await
+                .unwrap()
+                .unwrap();
+        }
+        assert_eq!(client.list_channels().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_restart() {
+        let (mut input, reader) = tokio::io::duplex(1024);
+        let client = StdioClient::from_streams(reader, tokio::io::sink());
+        let (sender, mut receiver) = broadcast::channel(10);
+        let tasks = client.start(Some(sender.clone())).await.unwrap();
+        let err = client.start(Some(sender.clone())).await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+
+        for task in tasks {
+            task.abort();
+            assert!(task.await.unwrap_err().is_cancelled());
+        }
+        let _tasks = client.start(Some(sender)).await.unwrap();
+        input.write_all(b"##imu## 1,2,3\n").await.unwrap();
+        let message = timeout(Duration::from_secs(1), receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(message.data.as_str(), "1,2,3");
+    }
+
+    #[tokio::test]
+    async fn test_write() {
+        let (writer, mut output) = tokio::io::duplex(1024);
+        let client = StdioClient::from_streams(tokio::io::empty(), writer);
+        client
+            .send(HubMessage::try_from_str("battery", "12.6").unwrap())
+            .await
+            .unwrap();
+        drop(client);
+        let mut written = String::new();
+        output.read_to_string(&mut written).await.unwrap();
+        assert_eq!(written, "##battery## 12.6\n");
+    }
+}
@@ -0,0 +1,4 @@
+/// stdin/stdout transport for the notification hub, for shell pipelines.
+pub mod client;
+
+pub use client::StdioClient;
@@ -0,0 +1,358 @@
+use async_trait::async_trait;
+use log::{error, info, warn};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::HashSet;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::net::UdpSocket;
+use tokio::sync::{broadcast, RwLock};
+use uuid::Uuid;
+
+use super::datagram::{self, DatagramHeader, LossTracker, HEADER_LEN};
+use crate::models::hub::{HubChannelName, HubMessage};
+use crate::ports::{NodeTasks, NotificationHub};
+
+const DEFAULT_GROUP: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(239, 255, 76, 67), 7667);
+const DEFAULT_TTL: u32 = 1;
+// Ethernet MTU minus IPv4 and UDP headers, so that datagrams are not fragmented
+const DEFAULT_MAX_PACKET_SIZE: usize = 1472;
+const MAX_UDP_PAYLOAD: usize = 65507;
+
+/// `UdpMulticastOptions` configures a `UdpMulticastClient`.
+///
+/// # Fields
+/// - `group`: Multicast group address and port.
+/// - `interface`: Address of the local interface joining the group. Defaults to any interface.
+/// - `ttl`: Multicast TTL. The default of 1 keeps datagrams in the local network.
+/// - `max_packet_size`: Maximum datagram size in bytes, header included. Larger messages are
+///   rejected when sent, and dropped when received.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UdpMulticastOptions {
+    group: SocketAddrV4,
+    interface: Ipv4Addr,
+    ttl: u32,
+    max_packet_size: usize,
+}
+
+impl Default for UdpMulticastOptions {
+    fn default() -> Self {
+        Self {
+            group: DEFAULT_GROUP,
+            interface: Ipv4Addr::UNSPECIFIED,
+            ttl: DEFAULT_TTL,
+            max_packet_size: DEFAULT_MAX_PACKET_SIZE,
+        }
+    }
+}
+
+impl UdpMulticastOptions {
+    pub fn group(&self) -> SocketAddrV4 {
+        self.group
+    }
+    pub fn interface(&self) -> Ipv4Addr {
+        self.interface
+    }
+    pub fn ttl(&self) -> u32 {
+        self.ttl
+    }
+    pub fn max_packet_size(&self) -> usize {
+        self.max_packet_size
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct UdpMulticastOptionsBuilder {
+    group: Option<SocketAddrV4>,
+    interface: Option<Ipv4Addr>,
+    ttl: Option<u32>,
+    max_packet_size: Option<usize>,
+}
+
+impl UdpMulticastOptionsBuilder {
+    pub fn new() -> Self {
+        Self {
+            group: None,
+            interface: None,
+            ttl: None,
+            max_packet_size: None,
+        }
+    }
+
+    pub fn group(&self, group: SocketAddrV4) -> Self {
+        let mut new = self.clone();
+        new.group = Some(group);
+        new
+    }
+    pub fn interface(&self, interface: Ipv4Addr) -> Self {
+        let mut new = self.clone();
+        new.interface = Some(interface);
+        new
+    }
+    pub fn ttl(&self, ttl: u32) -> Self {
+        let mut new = self.clone();
+        new.ttl = Some(ttl);
+        new
+    }
+    pub fn max_packet_size(&self, max_packet_size: usize) -> Self {
+        let mut new = self.clone();
+        new.max_packet_size = Some(max_packet_size);
+        new
+    }
+    pub fn build(self) -> Result<UdpMulticastOptions, String> {
+        let group = self.group.unwrap_or(DEFAULT_GROUP);
+        if !group.ip().is_multicast() {
+            return Err(format!("{} is not a multicast address", group.ip()));
+        }
+        let ttl = self.ttl.unwrap_or(DEFAULT_TTL);
+        if ttl > 255 {
+            return Err("Multicast TTL must be at most 255".to_string());
+        }
+        let max_packet_size = self.max_packet_size.unwrap_or(DEFAULT_MAX_PACKET_SIZE);
+        if max_packet_size <= HEADER_LEN || max_packet_size > MAX_UDP_PAYLOAD {
+            return Err(format!(
+                "Max packet size must be between {} and {} bytes",
+                HEADER_LEN + 1,
+                MAX_UDP_PAYLOAD
+            ));
+        }
+        Ok(UdpMulticastOptions {
+            group,
+            interface: self.interface.unwrap_or(Ipv4Addr::UNSPECIFIED),
+            ttl,
+            max_packet_size,
+        })
+    }
+}
+
+impl Default for UdpMulticastOptionsBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Traffic counters of a `UdpMulticastClient`
+///
+/// # Fields
+/// - `sent`: Datagrams sent.
+/// - `received`: Datagrams received from other nodes and forwarded to the hub.
+/// - `lost`: Datagrams missing from the sequence of other nodes.
+/// - `oversized`: Messages rejected or dropped for exceeding the max packet size.
+/// - `invalid`: Datagrams received that couldn't be decoded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UdpCounters {
+    pub sent: u64,
+    pub received: u64,
+    pub lost: u64,
+    pub oversized: u64,
+    pub invalid: u64,
+}
+
+#[derive(Debug, Default)]
+struct AtomicCounters {
+    sent: AtomicU64,
+    received: AtomicU64,
+    lost: AtomicU64,
+    oversized: AtomicU64,
+    invalid: AtomicU64,
+}
+
+impl AtomicCounters {
+    fn add(counter: &AtomicU64, n: u64) {
+        counter.fetch_add(n, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> UdpCounters {
+        UdpCounters {
+            sent: self.sent.load(Ordering::Relaxed),
+            received: self.received.load(Ordering::Relaxed),
+            lost: self.lost.load(Ordering::Relaxed),
+            oversized: self.oversized.load(Ordering::Relaxed),
+            invalid: self.invalid.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// `UdpMulticastClient` is a hub node exchanging messages as datagrams on a multicast group,
+/// for low latency telemetry in the local network. Unlike WebSocket, a lost datagram never
+/// delays the following ones, but it is not retransmitted either.
+///
+/// Every node in the group receives every message, so subscriptions have no effect. Channels
+/// are learned from the messages received, and lost datagrams are counted from the sequence
+/// numbers of every sender (see `datagram`).
+#[derive(Debug)]
+pub struct UdpMulticastClient {
+    socket: Arc<UdpSocket>,
+    options: UdpMulticastOptions,
+    node_id: Uuid,
+    sequence: AtomicU64,
+    channels: Arc<RwLock<HashSet<HubChannelName>>>,
+    counters: Arc<AtomicCounters>,
+}
+
+// Binds the group port allowing other processes in the host to join the same group
+fn bind(options: &UdpMulticastOptions) -> Result<UdpSocket, std::io::Error> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    let address = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, options.group.port());
+    socket.bind(&SocketAddr::V4(address).into())?;
+    socket.join_multicast_v4(options.group.ip(), &options.interface)?;
+    socket.set_multicast_ttl_v4(options.ttl)?;
+    socket.set_multicast_loop_v4(true)?;
+    UdpSocket::from_std(socket.into())
+}
+
+impl UdpMulticastClient {
+    pub fn new(options: UdpMulticastOptions) -> Result<Self, std::io::Error> {
+        let socket = bind(&options)?;
+        info!("Joined multicast group {}", options.group);
+        Ok(Self {
+            socket: Arc::new(socket),
+            options,
+            node_id: Uuid::new_v4(),
+            sequence: AtomicU64::new(0),
+            channels: Arc::new(RwLock::new(HashSet::new())),
+            counters: Arc::new(AtomicCounters::default()),
+        })
+    }
+
+    /// Returns the traffic counters of the node
+    pub fn counters(&self) -> UdpCounters {
+        self.counters.snapshot()
+    }
+}
+
+#[async_trait]
+impl NotificationHub for UdpMulticastClient {
+    /// Send message to the multicast group
+    async fn send(&self, data: HubMessage) -> Result<(), std::io::Error> {
+        let header = DatagramHeader {
+            node_id: self.node_id,
+            sequence: self.sequence.fetch_add(1, Ordering::Relaxed),
+        };
+        let datagram = datagram::encode(&header, &data)?;
+        if datagram.len() > self.options.max_packet_size {
+            AtomicCounters::add(&self.counters.oversized, 1);
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "Datagram of {} bytes exceeds max packet size of {} bytes",
+                    datagram.len(),
+                    self.options.max_packet_size
+                ),
+            ));
+        }
+        self.socket.send_to(&datagram, self.options.group).await?;
+        AtomicCounters::add(&self.counters.sent, 1);
+        Ok(())
+    }
+
+    /// List channels received so far
+    async fn list_channels(&self) -> Result<Vec<HubChannelName>, std::io::Error> {
+        Ok(self.channels.read().await.iter().cloned().collect())
+    }
+
+    /// Start receiving datagrams from the multicast group
+    async fn start(
+        &self,
+        sender: Option<broadcast::Sender<HubMessage>>,
+    ) -> Result<NodeTasks, std::io::Error> {
+        let mut tasks = NodeTasks::new();
+        if let Some(sender) = sender {
+            let socket = Arc::clone(&self.socket);
+            let channels = Arc::clone(&self.channels);
+            let counters = Arc::clone(&self.counters);
+            let node_id = self.node_id;
+            let max_packet_size = self.options.max_packet_size;
+
+            let task = tokio::spawn(async move {
+                let mut buffer = vec![0; MAX_UDP_PAYLOAD];
+                let mut tracker = LossTracker::new();
+                loop {
+                    let (n, peer) = match socket.recv_from(&mut buffer).await {
+                        Ok(received) => received,
+                        Err(e) => {
+                            error!("Multicast receive error {:?}", e);
+                            break;
+                        }
+                    };
+                    if n > max_packet_size {
+                        AtomicCounters::add(&counters.oversized, 1);
+                        continue;
+                    }
+                    let (header, message) = match datagram::decode(&buffer[..n]) {
+                        Ok(decoded) => decoded,
+                        Err(e) => {
+                            warn!("Invalid multicast datagram: {}", e);
+                            AtomicCounters::add(&counters.invalid, 1);
+                            continue;
+                        }
+                    };
+                    // own datagrams are looped back by the group
+                    if header.node_id == node_id {
+                        continue;
+                    }
+                    AtomicCounters::add(&counters.lost, tracker.record(&header));
+                    AtomicCounters::add(&counters.received, 1);
+                    channels.write().await.insert(message.channel.clone());
+                    let _ = sender.send(message.with_source("udp", peer));
+                }
+            });
+            tasks.push(task);
+        }
+        Ok(tasks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time::{timeout, Duration};
+
+    #[test]
+    fn test_options() {
+        let options = UdpMulticastOptionsBuilder::new().build().unwrap();
+        assert_eq!(options, UdpMulticastOptions::default());
+
+        let unicast = SocketAddrV4::new(Ipv4Addr::LOCALHOST, 7667);
+        assert!(UdpMulticastOptionsBuilder::new()
+            .group(unicast)
+            .build()
+            .is_err());
+        assert!(UdpMulticastOptionsBuilder::new().ttl(256).build().is_err());
+        assert!(UdpMulticastOptionsBuilder::new()
+            .max_packet_size(HEADER_LEN)
+            .build()
+            .is_err());
+    }
+
+    // Requires a network interface with multicast support
+    #[tokio::test]
+    #[ignore]
+    async fn test_multicast() {
+        let options = UdpMulticastOptionsBuilder::new()
+            .max_packet_size(256)
+            .build()
+            .unwrap();
+        let node1 = UdpMulticastClient::new(options.clone()).unwrap();
+        let node2 = UdpMulticastClient::new(options).unwrap();
+        let (sender, mut receiver) = broadcast::channel(10);
+        node2.start(Some(sender)).await.unwrap();
+
+        let message = HubMessage::try_from_str("imu", "1,2,3").unwrap();
+        node1.send(message).await.unwrap();
+        let received = timeout(Duration::from_secs(1), receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(received.data.as_str(), "1,2,3");
+        assert!(received.source().unwrap().starts_with("udp:"));
+        assert_eq!(node2.counters().received, 1);
+
+        let large = HubMessage::try_from_str("imu", &"1".repeat(256)).unwrap();
+        assert!(node1.send(large).await.is_err());
+        assert_eq!(node1.counters().oversized, 1);
+        assert_eq!(node1.counters().sent, 1);
+    }
+}
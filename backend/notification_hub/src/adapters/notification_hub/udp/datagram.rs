@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::models::hub::HubMessage;
+
+/// Size in bytes of the datagram header
+pub const HEADER_LEN: usize = 16 + 8;
+
+/// Header of the datagrams of the UDP multicast transport.
+///
+/// Every datagram carries a single `HubMessage`, preceded by a header with the id of the sending
+/// node (16 bytes) and the sequence number of the datagram (8 bytes, big endian). Receivers use
+/// sequence numbers to count lost datagrams, and the node id to ignore their own datagrams
+/// looped back by the multicast group.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DatagramHeader {
+    pub node_id: Uuid,
+    pub sequence: u64,
+}
+
+pub fn encode(header: &DatagramHeader, message: &HubMessage) -> Result<Vec<u8>, serde_json::Error> {
+    let payload = message.to_bytes()?;
+    let mut datagram = Vec::with_capacity(HEADER_LEN + payload.len());
+    datagram.extend_from_slice(header.node_id.as_bytes());
+    datagram.extend_from_slice(&header.sequence.to_be_bytes());
+    datagram.extend_from_slice(&payload);
+    Ok(datagram)
+}
+
+pub fn decode(datagram: &[u8]) -> Result<(DatagramHeader, HubMessage), String> {
+    if datagram.len() < HEADER_LEN {
+        return Err(format!("Datagram shorter than {} bytes", HEADER_LEN));
+    }
+    let (node_id, rest) = datagram.split_at(16);
+    let (sequence, payload) = rest.split_at(8);
+    let header = DatagramHeader {
+        node_id: Uuid::from_slice(node_id).map_err(|e| e.to_string())?,
+        sequence: u64::from_be_bytes(sequence.try_into().map_err(|_| "Invalid sequence")?),
+    };
+    let message = HubMessage::try_from(payload.to_vec())?;
+    Ok((header, message))
+}
+
+/// Tracks the last sequence number received from every node, to count lost datagrams
+#[derive(Debug, Default)]
+pub struct LossTracker {
+    last: HashMap<Uuid, u64>,
+}
+
+impl LossTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records datagram header. Returns the number of datagrams lost since the previous datagram
+    /// of the same node. Reordered or duplicated datagrams are not counted as lost.
+    pub fn record(&mut self, header: &DatagramHeader) -> u64 {
+        match self.last.get_mut(&header.node_id) {
+            Some(last) if header.sequence > *last => {
+                let lost = header.sequence - *last - 1;
+                *last = header.sequence;
+                lost
+            }
+            Some(_) => 0,
+            None => {
+                self.last.insert(header.node_id, header.sequence);
+                0
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_decode() {
+        let header = DatagramHeader {
+            node_id: Uuid::new_v4(),
+            sequence: 42,
+        };
+        let message = HubMessage::try_from_str("imu", "1,2,3").unwrap();
+        let datagram = encode(&header, &message).unwrap();
+        let (decoded_header, decoded_message) = decode(&datagram).unwrap();
+        assert_eq!(decoded_header, header);
+        assert_eq!(decoded_message.channel, message.channel);
+        assert_eq!(decoded_message.data.as_str(), "1,2,3");
+
+        assert!(decode(&datagram[..HEADER_LEN - 1]).is_err());
+        assert!(decode(&datagram[..HEADER_LEN + 1]).is_err());
+    }
+
+    #[test]
+    fn test_loss_tracker() {
+        let node_id = Uuid::new_v4();
+        let header = |sequence| DatagramHeader { node_id, sequence };
+        let mut tracker = LossTracker::new();
+        assert_eq!(tracker.record(&header(5)), 0);
+        assert_eq!(tracker.record(&header(6)), 0);
+        assert_eq!(tracker.record(&header(9)), 2);
+        assert_eq!(tracker.record(&header(7)), 0);
+        assert_eq!(tracker.record(&header(10)), 0);
+
+        let other = DatagramHeader {
+            node_id: Uuid::new_v4(),
+            sequence: 100,
+        };
+        assert_eq!(tracker.record(&other), 0);
+    }
+}
@@ -0,0 +1,7 @@
+/// UDP multicast transport for the notification hub.
+pub mod client;
+pub mod datagram;
+
+pub use client::{
+    UdpCounters, UdpMulticastClient, UdpMulticastOptions, UdpMulticastOptionsBuilder,
+};
@@ -0,0 +1,160 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use crate::models::hub::HubChannelName;
+
+/// Time a peer has to authenticate after connecting, before being disconnected
+pub const DEFAULT_AUTH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Channels a token may publish or subscribe to
+///
+/// # Fields
+/// - `publish`: Channels the token may publish to. Any channel if not set.
+/// - `subscribe`: Channels the token may subscribe to. Any channel if not set.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WsPermissions {
+    publish: Option<HashSet<HubChannelName>>,
+    subscribe: Option<HashSet<HubChannelName>>,
+}
+
+impl WsPermissions {
+    /// Permissions to publish and subscribe to any channel
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    /// Restricts publishing to `channels`
+    pub fn with_publish(mut self, channels: impl IntoIterator<Item = HubChannelName>) -> Self {
+        self.publish = Some(channels.into_iter().collect());
+        self
+    }
+
+    /// Restricts subscriptions to `channels`
+    pub fn with_subscribe(mut self, channels: impl IntoIterator<Item = HubChannelName>) -> Self {
+        self.subscribe = Some(channels.into_iter().collect());
+        self
+    }
+
+    pub fn can_publish(&self, channel: &HubChannelName) -> bool {
+        self.publish
+            .as_ref()
+            .map_or(true, |channels| channels.contains(channel))
+    }
+
+    pub fn can_subscribe(&self, channel: &HubChannelName) -> bool {
+        self.subscribe
+            .as_ref()
+            .map_or(true, |channels| channels.contains(channel))
+    }
+}
+
+/// `WsAuth` defines the tokens accepted by a `WebSocketServer`, so that only known peers
+/// (e.g. the operator laptop) publish commands or receive telemetry.
+///
+/// Peers authenticate by sending a `WsMessage::Auth` with a bearer token or pre-shared key as
+/// their first message. Peers sending another message, an unknown token, or nothing within
+/// `timeout` are disconnected with a policy violation close frame. The timeout starts when the
+/// peer connects, so peers stalling the TLS or WebSocket handshake are disconnected as well.
+///
+/// # Fields
+/// - `tokens`: Accepted tokens, and channels each token may publish or subscribe to.
+/// - `timeout`: Time a peer has to complete the handshakes and authenticate. Defaults to 5s.
+#[derive(Debug, Clone)]
+pub struct WsAuth {
+    tokens: Vec<(String, WsPermissions)>,
+    timeout: Duration,
+}
+
+impl WsAuth {
+    pub fn new() -> Self {
+        Self {
+            tokens: Vec::new(),
+            timeout: DEFAULT_AUTH_TIMEOUT,
+        }
+    }
+
+    /// Accepts `token`, with `permissions`
+    pub fn with_token(mut self, token: &str, permissions: WsPermissions) -> Self {
+        self.tokens.retain(|(accepted, _)| accepted != token);
+        self.tokens.push((token.to_string(), permissions));
+        self
+    }
+
+    /// Disconnects peers not authenticated within `timeout`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Permissions of `token`, if accepted. Every token is compared in constant time, so that
+    /// response times don't leak accepted tokens
+    pub fn permissions(&self, token: &str) -> Option<&WsPermissions> {
+        self.tokens
+            .iter()
+            .fold(None, |found, (accepted, permissions)| {
+                if constant_time_eq(accepted.as_bytes(), token.as_bytes()) {
+                    Some(permissions)
+                } else {
+                    found
+                }
+            })
+    }
+}
+
+impl Default for WsAuth {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Compares `a` and `b` in a time depending only on their lengths
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(name: &str) -> HubChannelName {
+        HubChannelName::try_from(name).unwrap()
+    }
+
+    #[test]
+    fn test_permissions() {
+        let permissions = WsPermissions::all();
+        assert!(permissions.can_publish(&channel("cmd_vel")));
+        assert!(permissions.can_subscribe(&channel("imu")));
+
+        let permissions = WsPermissions::all()
+            .with_publish([channel("cmd_vel")])
+            .with_subscribe([channel("imu"), channel("odom")]);
+        assert!(permissions.can_publish(&channel("cmd_vel")));
+        assert!(!permissions.can_publish(&channel("imu")));
+        assert!(permissions.can_subscribe(&channel("odom")));
+        assert!(!permissions.can_subscribe(&channel("cmd_vel")));
+    }
+
+    #[test]
+    fn test_tokens() {
+        let viewer = WsPermissions::all().with_publish([]);
+        let auth = WsAuth::new()
+            .with_token("operator", WsPermissions::all())
+            .with_token("viewer", viewer.clone());
+        assert_eq!(auth.permissions("operator"), Some(&WsPermissions::all()));
+        assert_eq!(auth.permissions("viewer"), Some(&viewer));
+        assert_eq!(auth.permissions("operato"), None);
+        assert_eq!(auth.permissions(""), None);
+        assert_eq!(auth.timeout(), DEFAULT_AUTH_TIMEOUT);
+
+        let auth = auth.with_token("viewer", WsPermissions::all());
+        assert_eq!(auth.permissions("viewer"), Some(&WsPermissions::all()));
+    }
+}
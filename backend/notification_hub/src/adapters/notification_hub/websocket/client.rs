@@ -1,86 +1,403 @@
 use async_trait::async_trait;
 use futures_util::{
     stream::{SplitSink, SplitStream},
-    StreamExt,
+    SinkExt, StreamExt,
 };
 use log::{error, info, warn};
+use std::collections::HashSet;
 use std::sync::Arc;
+use std::time::Instant;
 use tokio::net::TcpStream;
-use tokio::sync::{broadcast, Mutex};
+use tokio::sync::{broadcast, mpsc, Mutex};
+use tokio::time::Interval;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::{header::SEC_WEBSOCKET_PROTOCOL, HeaderValue};
 use tokio_tungstenite::tungstenite::protocol::Message;
-use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+use tokio_tungstenite::{
+    connect_async_tls_with_config, Connector, MaybeTlsStream, WebSocketStream,
+};
 
-use crate::models::hub::{HubChannelName, HubMessage};
-use crate::ports::NotificationHub;
+use crate::adapters::batch::{spawn_batcher, BatchOptions};
+use crate::adapters::reconnect::ReconnectBackoff;
+use crate::models::hub::{HubChannelName, HubMessage, LEGACY_PROTOCOL_VERSION, PROTOCOL_VERSION};
+use crate::ports::{NodeTasks, NotificationHub};
 
+use super::compression::WsCompression;
+use super::encoding::WsEncoding;
 use super::handlers;
-use super::message::WsMessage;
-use super::server::WebSocketServer;
+use super::keepalive::WsKeepalive;
+use super::message::{negotiate_version, ws_config, WsBatchEntry, WsMessage, VERSION_HEADER};
+#[cfg(feature = "tls")]
+use super::tls::WsTrustedCerts;
 
 type WsWrite = SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>;
 type WsRead = SplitStream<WebSocketStream<MaybeTlsStream<TcpStream>>>;
+type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+// Write half of the connection to the server, with the compression and protocol version
+// negotiated on it. Both are negotiated again on every reconnection
+#[derive(Debug)]
+struct WsConnection {
+    write: WsWrite,
+    compression: WsCompression,
+    version: u16,
+}
+
+impl WsConnection {
+    async fn send(
+        &mut self,
+        ws_message: WsMessage,
+        encoding: WsEncoding,
+    ) -> Result<(), std::io::Error> {
+        handlers::handle_send_ws_message(
+            &mut self.write,
+            ws_message,
+            encoding,
+            self.compression,
+            self.version,
+        )
+        .await
+    }
+}
 
-/// `WebSocketClient` manages a bidirectional WebSocket connection.
+/// `WebSocketClient` manages a bidirectional WebSocket connection to a running
+/// `WebSocketServer`, local or remote. The client doesn't launch a server.
 /// It reads messages from the WebSocket and broadcasts them to subscribers.
+/// Data frames are encoded with the `WsEncoding` negotiated with the server at connect time,
+/// and compressed with the `WsCompression` the server accepted, if any. Text frames use the
+/// envelope of the protocol version negotiated with the server.
 #[derive(Debug, Clone)]
 pub struct WebSocketClient {
     client_url: String,
-    ws_write: Arc<Mutex<WsWrite>>,
+    encoding: WsEncoding,
+    // compression requested on every connection
+    compression: WsCompression,
+    connection: Arc<Mutex<WsConnection>>,
     ws_read: Arc<Mutex<WsRead>>,
+    batcher: Option<mpsc::UnboundedSender<HubMessage>>,
+    token: Option<String>,
+    keepalive: Option<WsKeepalive>,
+    reconnect: Option<ReconnectBackoff>,
+    subscriptions: Arc<Mutex<HashSet<HubChannelName>>>,
+    #[cfg(feature = "tls")]
+    trusted: Option<WsTrustedCerts>,
 }
 
 impl WebSocketClient {
-    // Constructor to initialize WebSocketClient with a URL and broadcast channels
+    // Constructor to initialize WebSocketClient with a URL and broadcast channels. With the
+    // `tls` feature, `wss://` URLs are connected over TLS, trusting the public web PKI roots
     pub async fn new(url: &str) -> Result<Self, std::io::Error> {
-        let client_url = format!("ws://{}", url);
+        Self::new_with_encoding(url, WsEncoding::Json).await
+    }
 
-        // Launch server will fail it its already launched. Not very nice
-        let _ = launch_server(url).await;
+    // Constructor requesting a specific wire encoding for data frames
+    pub async fn new_with_encoding(
+        url: &str,
+        encoding: WsEncoding,
+    ) -> Result<Self, std::io::Error> {
+        Self::new_with_compression(url, encoding, WsCompression::None).await
+    }
 
-        match connect_async(client_url.as_str()).await {
-            Ok((ws_stream, _)) => {
-                let (write, read) = ws_stream.split();
-                info!("Connected to WebSocket server at {}", client_url);
-                Ok(Self {
-                    client_url,
-                    ws_write: Arc::new(Mutex::new(write)),
-                    ws_read: Arc::new(Mutex::new(read)),
-                })
+    // Constructor requesting a specific wire encoding and compression of large frames.
+    // Frames are sent uncompressed if the server doesn't accept the compression
+    pub async fn new_with_compression(
+        url: &str,
+        encoding: WsEncoding,
+        compression: WsCompression,
+    ) -> Result<Self, std::io::Error> {
+        Self::connect(url, encoding, compression, None).await
+    }
+
+    // Constructor connecting to the `wss://` server at `url` over TLS, trusting only the
+    // certificates in `trusted`
+    #[cfg(feature = "tls")]
+    pub async fn new_with_tls(
+        url: &str,
+        encoding: WsEncoding,
+        compression: WsCompression,
+        trusted: WsTrustedCerts,
+    ) -> Result<Self, std::io::Error> {
+        let connector = trusted.connector()?;
+        let mut client = Self::connect(url, encoding, compression, Some(connector)).await?;
+        client.trusted = Some(trusted);
+        Ok(client)
+    }
+
+    // Connects to the server at `url`, which is either a `ws://` or `wss://` URL, or `host:port`
+    // for plain connections. TLS connections use `connector`, or the public web PKI roots if
+    // not set
+    async fn connect(
+        url: &str,
+        encoding: WsEncoding,
+        compression: WsCompression,
+        connector: Option<Connector>,
+    ) -> Result<Self, std::io::Error> {
+        let client_url = if url.contains("://") {
+            url.to_string()
+        } else {
+            format!("ws://{}", url)
+        };
+
+        let (ws_stream, accepted, version) =
+            open(&client_url, encoding, compression, connector).await?;
+        let (write, read) = ws_stream.split();
+        info!(
+            "Connected to WebSocket server at {} with encoding {:?}, compression {:?} and protocol version {}",
+            client_url, encoding, accepted, version
+        );
+        Ok(Self {
+            client_url,
+            encoding,
+            compression,
+            connection: Arc::new(Mutex::new(WsConnection {
+                write,
+                compression: accepted,
+                version,
+            })),
+            ws_read: Arc::new(Mutex::new(read)),
+            batcher: None,
+            token: None,
+            keepalive: None,
+            reconnect: Some(ReconnectBackoff::default()),
+            subscriptions: Arc::new(Mutex::new(HashSet::new())),
+            #[cfg(feature = "tls")]
+            trusted: None,
+        })
+    }
+
+    /// Authenticates to the server with `token`, for servers requiring it. Connections opened
+    /// later by the client authenticate with it too
+    pub async fn with_token(mut self, token: &str) -> Result<Self, std::io::Error> {
+        let ws_message = WsMessage::auth(token);
+        self.connection
+            .lock()
+            .await
+            .send(ws_message, self.encoding)
+            .await?;
+        self.token = Some(token.to_string());
+        Ok(self)
+    }
+
+    /// Pings the server as configured by `keepalive` while the client is started. The
+    /// connection is considered lost if the server stops answering
+    pub fn with_keepalive(mut self, keepalive: WsKeepalive) -> Self {
+        self.keepalive = Some(keepalive);
+        self
+    }
+
+    /// Sets the backoff of the attempts to reconnect after the connection is lost, while the
+    /// client is started. The client authenticates and subscribes again to its channels on the
+    /// new connection, with the compression and protocol version negotiated on it. With `None`,
+    /// the client stops receiving messages when the connection is lost
+    pub fn with_reconnect(mut self, reconnect: Option<ReconnectBackoff>) -> Self {
+        self.reconnect = reconnect;
+        self
+    }
+
+    /// Enables batching of outgoing messages. Messages are grouped according to `options`
+    /// and sent as a single `WsMessage::Batch` frame
+    pub fn with_batching(mut self, options: BatchOptions) -> Self {
+        let connection = Arc::clone(&self.connection);
+        let encoding = self.encoding;
+        self.batcher = Some(spawn_batcher(options, move |batch| {
+            let connection = Arc::clone(&connection);
+            async move {
+                let ws_message = WsMessage::batch(batch);
+                if let Err(e) = connection.lock().await.send(ws_message, encoding).await {
+                    error!("Failed to send batch: {:?}", e);
+                }
             }
+        }));
+        self
+    }
+
+    // Connector of the TLS connections to the server, trusting the certificates set, if any
+    fn connector(&self) -> Result<Option<Connector>, std::io::Error> {
+        #[cfg(feature = "tls")]
+        let connector = self
+            .trusted
+            .as_ref()
+            .map(WsTrustedCerts::connector)
+            .transpose()?;
+        #[cfg(not(feature = "tls"))]
+        let connector = None;
+        Ok(connector)
+    }
+
+    // Host and port of the server
+    fn server(&self) -> &str {
+        self.client_url
+            .split_once("://")
+            .map_or(self.client_url.as_str(), |(_, server)| server)
+    }
+}
 
+// Reconnects `client` to its server until it succeeds, waiting between attempts according to
+// `backoff`. The connection of the client is replaced, with the compression and protocol
+// version negotiated on the new connection, and the client authenticates and subscribes again
+// to its channels
+async fn reconnect(client: &WebSocketClient, backoff: ReconnectBackoff) -> WsRead {
+    let mut delay = backoff.initial;
+    loop {
+        warn!(
+            "Reconnecting to WebSocket server {} in {:?}...",
+            client.client_url, delay
+        );
+        tokio::time::sleep(delay).await;
+        let connector = match client.connector() {
+            Ok(connector) => connector,
+            Err(e) => {
+                error!("WebSocket TLS configuration error {:?}", e);
+                delay = backoff.next_delay(delay);
+                continue;
+            }
+        };
+        match open(
+            &client.client_url,
+            client.encoding,
+            client.compression,
+            connector,
+        )
+        .await
+        {
+            Ok((ws_stream, compression, version)) => {
+                let (write, read) = ws_stream.split();
+                let mut connection = client.connection.lock().await;
+                *connection = WsConnection {
+                    write,
+                    compression,
+                    version,
+                };
+                let token = client.token.iter().map(|token| WsMessage::auth(token));
+                let subscriptions: Vec<_> = client
+                    .subscriptions
+                    .lock()
+                    .await
+                    .iter()
+                    .cloned()
+                    .map(WsMessage::subscribe_channel)
+                    .collect();
+                for ws_message in token.chain(subscriptions) {
+                    if let Err(e) = connection.send(ws_message, client.encoding).await {
+                        error!("Failed to restore WebSocket session: {:?}", e);
+                    }
+                }
+                info!(
+                    "Reconnected to WebSocket server {} with compression {:?} and protocol version {}",
+                    client.client_url, compression, version
+                );
+                return read;
+            }
             Err(e) => {
-                error!("WebSocket connection failed: {:?}", e);
-                Err(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    "Connection failed",
-                ))
+                error!("WebSocket reconnect error {:?}", e);
+                delay = backoff.next_delay(delay);
             }
         }
     }
 }
 
-async fn launch_server(url: &str) -> Result<(), std::io::Error> {
-    let server = WebSocketServer::new(url);
-    server.start().await
+// Waits for the next ping of `pings`, forever if keepalive is disabled
+async fn next_ping(pings: &mut Option<Interval>) {
+    match pings {
+        Some(pings) => {
+            pings.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+// Opens a connection to the server at `client_url`, requesting `encoding` and `compression`.
+// Returns the connection, and the compression and protocol version negotiated
+async fn open(
+    client_url: &str,
+    encoding: WsEncoding,
+    compression: WsCompression,
+    connector: Option<Connector>,
+) -> Result<(WsStream, WsCompression, u16), std::io::Error> {
+    let mut request = client_url
+        .into_client_request()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    if let Some(protocol) = encoding.protocol() {
+        request
+            .headers_mut()
+            .insert(SEC_WEBSOCKET_PROTOCOL, HeaderValue::from_static(protocol));
+    }
+    if let Some(token) = compression.token() {
+        request
+            .headers_mut()
+            .insert(WsCompression::HEADER, HeaderValue::from_static(token));
+    }
+    request
+        .headers_mut()
+        .insert(VERSION_HEADER, HeaderValue::from(PROTOCOL_VERSION));
+
+    match connect_async_tls_with_config(request, Some(ws_config()), false, connector).await {
+        Ok((ws_stream, response)) => {
+            let compression = response
+                .headers()
+                .get(WsCompression::HEADER)
+                .and_then(|value| value.to_str().ok())
+                .map(WsCompression::from_header)
+                .filter(|accepted| *accepted == compression)
+                .unwrap_or_default();
+            let version = negotiate_version(
+                response
+                    .headers()
+                    .get(VERSION_HEADER)
+                    .and_then(|value| value.to_str().ok()),
+            );
+            Ok((ws_stream, compression, version))
+        }
+
+        Err(e) => {
+            error!("WebSocket connection failed: {:?}", e);
+            Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                "Connection failed",
+            ))
+        }
+    }
 }
 
 #[async_trait]
 impl NotificationHub for WebSocketClient {
     // Send data to the WebSocket server
     async fn send(&self, data: HubMessage) -> Result<(), std::io::Error> {
+        if let Some(batcher) = &self.batcher {
+            return batcher.send(data).map_err(|_| {
+                std::io::Error::new(std::io::ErrorKind::BrokenPipe, "Batcher stopped")
+            });
+        }
         let ws_message = WsMessage::from(data);
-        let mut ws_write = self.ws_write.lock().await;
-        let _ = handlers::handle_send_ws_message(&mut ws_write, ws_message).await?;
-        Ok(())
+        self.connection
+            .lock()
+            .await
+            .send(ws_message, self.encoding)
+            .await
     }
 
     async fn list_channels(&self) -> Result<Vec<HubChannelName>, std::io::Error> {
         let ws_message = WsMessage::list_channels_req();
         info!("Sending List channels request message...");
-        let (ws_stream, _) = connect_async(self.client_url.as_str())
-            .await
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        let (ws_stream, _) = connect_async_tls_with_config(
+            self.client_url.as_str(),
+            Some(ws_config()),
+            false,
+            self.connector()?,
+        )
+        .await
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
         let (mut ws_write, mut ws_read) = ws_stream.split();
+        if let Some(token) = &self.token {
+            handlers::handle_send_ws_message(
+                &mut ws_write,
+                WsMessage::auth(token),
+                WsEncoding::Json,
+                WsCompression::None,
+                LEGACY_PROTOCOL_VERSION,
+            )
+            .await?;
+        }
 
         handlers::handle_send_ws_message_with_response(&mut ws_write, &mut ws_read, ws_message)
             .await
@@ -90,68 +407,187 @@ impl NotificationHub for WebSocketClient {
     async fn start(
         &self,
         sender: Option<broadcast::Sender<HubMessage>>,
-    ) -> Result<(), std::io::Error> {
+    ) -> Result<NodeTasks, std::io::Error> {
+        let mut tasks = NodeTasks::new();
         if let Some(sender) = sender {
             let sender = Arc::new(Mutex::new(sender));
             let sender_clone = sender.clone();
-            tokio::spawn({
+            let task = tokio::spawn({
+                let client = self.clone();
                 let ws_read = Arc::clone(&self.ws_read);
+                let connection = Arc::clone(&self.connection);
+                let encoding = self.encoding;
+                let keepalive = self.keepalive;
+                let server = self.server().to_string();
                 async move {
                     let mut stream = ws_read.lock().await;
-                    while let Some(message) = stream.next().await {
-                        match message {
-                            Ok(Message::Text(text)) => {
-                                // When a text message is received, handle it
-                                info!("Received message from server: {}", text);
-                                match WsMessage::try_from(text) {
-                                    Ok(ws_message) => match ws_message {
-                                        WsMessage::Data(channel, data) => {
-                                            let hub_message = HubMessage::new(channel, data);
-                                            handlers::handle_incoming_data(
-                                                Arc::clone(&sender_clone),
-                                                hub_message,
-                                            )
-                                            .await;
-                                        }
-                                        _ => {
-                                            warn!("Unexpexted WsMessage received")
-                                        }
-                                    },
-                                    Err(e) => {
-                                        error!("Error in conversion: {}", e)
+                    loop {
+                        let mut pings =
+                            keepalive.map(|keepalive| tokio::time::interval(keepalive.interval()));
+                        let mut last_seen = Instant::now();
+                        loop {
+                            let message = tokio::select! {
+                                message = stream.next() => message,
+                                _ = next_ping(&mut pings) => {
+                                    if keepalive.is_some_and(|keepalive| keepalive.expired(last_seen)) {
+                                        warn!("WebSocket server {} not responding to pings", server);
+                                        break;
                                     }
+                                    if let Err(e) = connection.lock().await.write.send(Message::Ping(Vec::new())).await {
+                                        warn!("Failed to send ping: {:?}", e);
+                                    }
+                                    continue;
                                 }
-                            }
-                            Ok(m) => warn!("Unknown wsMessage type: {:?}", m),
-                            Err(e) => {
-                                error!("Error reading WebSocket message: {:?}", e);
+                            };
+                            let Some(message) = message else {
                                 break;
+                            };
+                            last_seen = Instant::now();
+                            match message {
+                                Ok(message @ (Message::Text(_) | Message::Binary(_))) => {
+                                    // When a data frame is received, handle it
+                                    info!("Received message from server: {:?}", message);
+                                    match encoding.decode(message) {
+                                        Ok(ws_message) => match ws_message {
+                                            WsMessage::Data(channel, data, headers) => {
+                                                let hub_message = HubMessage::from(WsBatchEntry(
+                                                    channel, data, headers,
+                                                ))
+                                                .with_source("ws", &server);
+                                                handlers::handle_incoming_data(
+                                                    Arc::clone(&sender_clone),
+                                                    hub_message,
+                                                )
+                                                .await;
+                                            }
+                                            WsMessage::Batch(batch) => {
+                                                for entry in batch {
+                                                    let hub_message = HubMessage::from(entry)
+                                                        .with_source("ws", &server);
+                                                    handlers::handle_incoming_data(
+                                                        Arc::clone(&sender_clone),
+                                                        hub_message,
+                                                    )
+                                                    .await;
+                                                }
+                                            }
+                                            _ => {
+                                                warn!("Unexpexted WsMessage received")
+                                            }
+                                        },
+                                        Err(e) => {
+                                            error!("Error in conversion: {}", e)
+                                        }
+                                    }
+                                }
+                                Ok(Message::Ping(_) | Message::Pong(_)) => {}
+                                Ok(m) => warn!("Unknown wsMessage type: {:?}", m),
+                                Err(e) => {
+                                    error!("Error reading WebSocket message: {:?}", e);
+                                    break;
+                                }
                             }
                         }
+                        let Some(backoff) = client.reconnect else {
+                            info!("WebSocket connection to {} lost", server);
+                            break;
+                        };
+                        info!("WebSocket connection lost! Reconnecting...");
+                        *stream = reconnect(&client, backoff).await;
                     }
-                    info!("WebSocket connection lost! Reconnecting...");
                 }
             });
+            tasks.push(task);
         }
-        Ok(())
+        Ok(tasks)
     }
 
     async fn subscribe(&self, channel: HubChannelName) -> Result<(), std::io::Error> {
+        self.subscriptions.lock().await.insert(channel.clone());
         let ws_message = WsMessage::subscribe_channel(channel);
         info!("Send Subscription request: {:?}", ws_message);
-        let mut ws_write = self.ws_write.lock().await;
-        if let Err(e) = handlers::handle_send_ws_message(&mut ws_write, ws_message).await {
+        if let Err(e) = self
+            .connection
+            .lock()
+            .await
+            .send(ws_message, self.encoding)
+            .await
+        {
             error!("Failed to send subscribe message: {:?}", e);
         }
         Ok(())
     }
 
     async fn unsubscribe(&self, channel: HubChannelName) -> Result<(), std::io::Error> {
+        self.subscriptions.lock().await.remove(&channel);
         let ws_message = WsMessage::unsubscribe_channel(channel);
-        let mut ws_write = self.ws_write.lock().await;
-        if let Err(e) = handlers::handle_send_ws_message(&mut ws_write, ws_message).await {
+        if let Err(e) = self
+            .connection
+            .lock()
+            .await
+            .send(ws_message, self.encoding)
+            .await
+        {
             error!("Failed to send unsubscribe message: {:?}", e);
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::websocket::WebSocketServer;
+    use tokio::time::Duration;
+
+    #[tokio::test]
+    async fn test_reconnect_restores_subscriptions() {
+        let server = WebSocketServer::new("127.0.0.1:0");
+        let addr = server.start().await.unwrap();
+        let backoff = ReconnectBackoff {
+            initial: Duration::from_millis(50),
+            max: Duration::from_millis(50),
+        };
+        let client = WebSocketClient::new(&format!("ws://{}", addr))
+            .await
+            .unwrap()
+            .with_reconnect(Some(backoff));
+        let (sender, mut receiver) = broadcast::channel(16);
+        let _tasks = client.start(Some(sender)).await.unwrap();
+        let channel = HubChannelName::try_from("topic1").unwrap();
+        client.subscribe(channel.clone()).await.unwrap();
+
+        // the server is restarted while the client is connected, before anything is published
+        server.stop();
+        let server = WebSocketServer::new(&addr.to_string());
+        server.start().await.unwrap();
+        let publisher = WebSocketClient::new(&format!("ws://{}", addr))
+            .await
+            .unwrap();
+
+        // the channel is known to the new server once the client has reconnected and
+        // subscribed again
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !publisher
+                .list_channels()
+                .await
+                .is_ok_and(|channels| channels.contains(&channel))
+            {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("client didn't subscribe again");
+        publisher
+            .send(HubMessage::try_from_str("topic1", "1,2,3").unwrap())
+            .await
+            .unwrap();
+        let received = tokio::time::timeout(Duration::from_secs(1), receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(received.channel, channel);
+        assert_eq!(client.connection.lock().await.version, PROTOCOL_VERSION);
+        server.stop();
+    }
+}
@@ -0,0 +1,160 @@
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+use super::message::MAX_WS_MESSAGE_LEN;
+
+/// Magic bytes starting compressed frames
+const MAGIC: [u8; 2] = *b"RZ";
+const TEXT_FRAME: u8 = 0;
+const BINARY_FRAME: u8 = 1;
+// magic, frame kind and uncompressed length
+const HEADER_LEN: usize = MAGIC.len() + 1 + 4;
+
+/// Frames smaller than this number of bytes are sent uncompressed
+pub(crate) const COMPRESSION_THRESHOLD: usize = 4096;
+
+/// Compression of WebSocket frames, negotiated at connect time through the
+/// `robopilot-compression` header. The client offers the compression, and the server
+/// enables it by echoing the header back.
+///
+/// - `WsCompression::None` -> Frames are sent as encoded. This is the default.
+/// - `WsCompression::Lz4` -> Frames larger than `COMPRESSION_THRESHOLD` bytes, such as camera
+///   frames or LIDAR scans, are compressed with LZ4 and sent as binary frames.
+///
+/// Compressed frames start with magic bytes, followed by the kind of the original frame
+/// (text or binary), its length as a little endian u32, and the LZ4 block:
+///
+/// ```text
+/// | 'R' 'Z' | kind (1) | len (4) | lz4 block |
+/// ```
+/// Decoding accepts compressed frames regardless of the negotiated compression.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum WsCompression {
+    #[default]
+    None,
+    Lz4,
+}
+
+impl WsCompression {
+    pub(crate) const HEADER: &'static str = "robopilot-compression";
+    const LZ4: &'static str = "lz4";
+
+    /// Value of the `robopilot-compression` header negotiating this compression
+    pub fn token(&self) -> Option<&'static str> {
+        match self {
+            WsCompression::None => None,
+            WsCompression::Lz4 => Some(Self::LZ4),
+        }
+    }
+
+    /// Selects the compression from the comma separated list of compressions offered by a peer
+    pub fn from_header(value: &str) -> Self {
+        if value.split(',').any(|token| token.trim() == Self::LZ4) {
+            return WsCompression::Lz4;
+        }
+        WsCompression::None
+    }
+
+    /// Compresses `frame` if compression is enabled and the frame exceeds the threshold
+    pub(crate) fn compress(&self, frame: Message) -> Message {
+        let (kind, payload) = match (self, &frame) {
+            (WsCompression::Lz4, Message::Text(text)) => (TEXT_FRAME, text.as_bytes()),
+            (WsCompression::Lz4, Message::Binary(bytes)) => (BINARY_FRAME, bytes.as_slice()),
+            _ => return frame,
+        };
+        if payload.len() <= COMPRESSION_THRESHOLD {
+            return frame;
+        }
+        let mut compressed = Vec::with_capacity(HEADER_LEN + payload.len() / 2);
+        compressed.extend_from_slice(&MAGIC);
+        compressed.push(kind);
+        compressed.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        compressed.extend_from_slice(&lz4_flex::compress(payload));
+        Message::Binary(compressed)
+    }
+
+    /// Restores the original frame of a compressed frame. Other frames are returned as is
+    pub(crate) fn decompress(frame: Message) -> Result<Message, String> {
+        let bytes = match &frame {
+            Message::Binary(bytes) if bytes.starts_with(&MAGIC) => bytes,
+            _ => return Ok(frame),
+        };
+        if bytes.len() < HEADER_LEN {
+            return Err("Compressed frame too short".to_string());
+        }
+        let len = u32::from_le_bytes([bytes[3], bytes[4], bytes[5], bytes[6]]) as usize;
+        // the length is checked before allocating, so that a peer can't claim a huge frame
+        if len > MAX_WS_MESSAGE_LEN {
+            return Err(format!(
+                "Compressed frame of {} bytes exceeds maximum of {} bytes",
+                len, MAX_WS_MESSAGE_LEN
+            ));
+        }
+        let payload = lz4_flex::decompress(&bytes[HEADER_LEN..], len)
+            .map_err(|e| format!("Invalid compressed frame: {}", e))?;
+        if payload.len() != len {
+            return Err("Invalid compressed frame: length mismatch".to_string());
+        }
+        match bytes[2] {
+            TEXT_FRAME => String::from_utf8(payload)
+                .map(Message::Text)
+                .map_err(|e| format!("Invalid compressed text frame: {}", e)),
+            BINARY_FRAME => Ok(Message::Binary(payload)),
+            kind => Err(format!("Unknown compressed frame kind {}", kind)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_header() {
+        assert_eq!(WsCompression::from_header("lz4"), WsCompression::Lz4);
+        assert_eq!(WsCompression::from_header("zstd, lz4"), WsCompression::Lz4);
+        assert_eq!(WsCompression::from_header("zstd"), WsCompression::None);
+    }
+
+    #[test]
+    fn test_compress_roundtrip() {
+        let text = Message::Text("0,".repeat(COMPRESSION_THRESHOLD));
+        let compressed = WsCompression::Lz4.compress(text.clone());
+        assert!(compressed.is_binary());
+        assert!(compressed.len() < text.len());
+        assert_eq!(WsCompression::decompress(compressed).unwrap(), text);
+
+        let binary = Message::Binary(vec![7; COMPRESSION_THRESHOLD + 1]);
+        let compressed = WsCompression::Lz4.compress(binary.clone());
+        assert!(compressed.len() < binary.len());
+        assert_eq!(WsCompression::decompress(compressed).unwrap(), binary);
+    }
+
+    #[test]
+    fn test_small_frames_are_not_compressed() {
+        let frame = Message::Text("1,2,3".to_string());
+        assert_eq!(WsCompression::Lz4.compress(frame.clone()), frame);
+        let large = Message::Text("0,".repeat(COMPRESSION_THRESHOLD));
+        assert_eq!(WsCompression::None.compress(large.clone()), large);
+        assert_eq!(WsCompression::decompress(frame.clone()).unwrap(), frame);
+    }
+
+    #[test]
+    fn test_decompress_invalid_frames() {
+        let mut frame = MAGIC.to_vec();
+        frame.push(BINARY_FRAME);
+        assert!(WsCompression::decompress(Message::Binary(frame.clone())).is_err());
+
+        // claimed length above the maximum message length
+        frame.extend_from_slice(&u32::MAX.to_le_bytes());
+        frame.extend_from_slice(&[0; 8]);
+        assert!(WsCompression::decompress(Message::Binary(frame)).is_err());
+
+        let compressed = WsCompression::Lz4.compress(Message::Binary(vec![1; 5000]));
+        let mut truncated = match compressed {
+            Message::Binary(bytes) => bytes,
+            _ => unreachable!(),
+        };
+        truncated.truncate(truncated.len() - 1);
+        assert!(WsCompression::decompress(Message::Binary(truncated)).is_err());
+    }
+}
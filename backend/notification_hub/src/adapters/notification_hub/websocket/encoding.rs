@@ -0,0 +1,310 @@
+use std::borrow::Cow;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+use super::compression::WsCompression;
+use super::message::WsMessage;
+#[cfg(feature = "protobuf")]
+use crate::models::hub::hub_proto;
+use crate::models::hub::{hub_cbor, hub_codec, HubMessage, LEGACY_PROTOCOL_VERSION};
+#[cfg(feature = "protobuf")]
+use prost::Message as _;
+
+/// Wire encoding of WebSocket data frames, negotiated at connect time through the
+/// `Sec-WebSocket-Protocol` header.
+///
+/// - `WsEncoding::Json` -> Every WsMessage is sent as a JSON text frame. This is the default,
+///   and the encoding used by external clients such as the frontend.
+/// - `WsEncoding::Binary` -> Data and Batch messages are sent as binary frames using the compact
+///   `hub_codec` format. Control messages (subscriptions, channel listing) remain JSON.
+/// - `WsEncoding::Cbor` -> Data and Batch messages are sent as binary frames using the
+///   `hub_cbor` format, for peers expecting a standard encoding. Control messages remain JSON.
+/// - `WsEncoding::MsgPack` -> Every message, control messages included, is sent as a binary
+///   frame with the MessagePack serialization of `WsMessage`.
+/// - `WsEncoding::Protobuf` -> Every message is sent as a binary frame with the `WsMessage`
+///   protobuf message of `proto/hub_messages.proto`. Requires the `protobuf` feature.
+///
+/// Text frames are sent in the envelope of the protocol version negotiated with the peer
+/// (see `WsMessage::to_versioned_string`). Every encoding carries the headers of data
+/// messages, except for `LEGACY_PROTOCOL_VERSION` peers.
+///
+/// Decoding accepts text frames and `hub_codec` frames, told apart by their magic bytes,
+/// regardless of the negotiated encoding. Other binary frames are decoded as MessagePack or
+/// protobuf when negotiated, and as CBOR otherwise. Compressed frames (see `WsCompression`) are
+/// decompressed before decoding.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum WsEncoding {
+    #[default]
+    Json,
+    Binary,
+    Cbor,
+    MsgPack,
+    #[cfg(feature = "protobuf")]
+    Protobuf,
+}
+
+impl WsEncoding {
+    pub(crate) const BINARY_PROTOCOL: &'static str = "robopilot.bin";
+    pub(crate) const CBOR_PROTOCOL: &'static str = "robopilot.cbor";
+    pub(crate) const MSGPACK_PROTOCOL: &'static str = "robopilot.msgpack";
+    #[cfg(feature = "protobuf")]
+    pub(crate) const PROTOBUF_PROTOCOL: &'static str = "robopilot.proto";
+
+    /// Websocket subprotocol requested to negotiate this encoding
+    pub fn protocol(&self) -> Option<&'static str> {
+        match self {
+            WsEncoding::Json => None,
+            WsEncoding::Binary => Some(Self::BINARY_PROTOCOL),
+            WsEncoding::Cbor => Some(Self::CBOR_PROTOCOL),
+            WsEncoding::MsgPack => Some(Self::MSGPACK_PROTOCOL),
+            #[cfg(feature = "protobuf")]
+            WsEncoding::Protobuf => Some(Self::PROTOBUF_PROTOCOL),
+        }
+    }
+
+    /// Selects the encoding from the comma separated list of subprotocols offered by a peer.
+    /// The first supported subprotocol is selected
+    pub fn from_protocols(protocols: &str) -> Self {
+        protocols
+            .split(',')
+            .find_map(|protocol| match protocol.trim() {
+                Self::BINARY_PROTOCOL => Some(WsEncoding::Binary),
+                Self::CBOR_PROTOCOL => Some(WsEncoding::Cbor),
+                Self::MSGPACK_PROTOCOL => Some(WsEncoding::MsgPack),
+                #[cfg(feature = "protobuf")]
+                Self::PROTOBUF_PROTOCOL => Some(WsEncoding::Protobuf),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    // Encodes data messages into a binary frame
+    fn encode_messages(&self, messages: &[HubMessage]) -> Result<Vec<u8>, String> {
+        match self {
+            WsEncoding::Cbor => hub_cbor::encode(messages),
+            _ => Ok(hub_codec::encode(messages)),
+        }
+    }
+
+    /// Encodes `message` into a frame. Text frames use the envelope of protocol `version`
+    pub(crate) fn encode(&self, message: &WsMessage, version: u16) -> Result<Message, String> {
+        let message = if version == LEGACY_PROTOCOL_VERSION {
+            message.without_headers()
+        } else {
+            Cow::Borrowed(message)
+        };
+        let message = message.as_ref();
+        match (self, message) {
+            (WsEncoding::Json, _) => Ok(Message::Text(message.to_versioned_string(version)?)),
+            (WsEncoding::MsgPack, _) => Ok(Message::Binary(
+                rmp_serde::to_vec(message)
+                    .map_err(|e| format!("Error encoding MessagePack frame: {}", e))?,
+            )),
+            #[cfg(feature = "protobuf")]
+            (WsEncoding::Protobuf, _) => Ok(Message::Binary(
+                hub_proto::WsMessage::from(message).encode_to_vec(),
+            )),
+            (_, WsMessage::Data(..)) => {
+                Ok(Message::Binary(self.encode_messages(&[
+                    HubMessage::try_from(message.clone())?,
+                ])?))
+            }
+            (_, WsMessage::Batch(batch)) => {
+                let messages: Vec<_> = batch.iter().cloned().map(HubMessage::from).collect();
+                Ok(Message::Binary(self.encode_messages(&messages)?))
+            }
+            _ => Ok(Message::Text(message.to_versioned_string(version)?)),
+        }
+    }
+
+    // Decoded data messages are a Data message when single, and a Batch otherwise
+    fn from_messages(mut messages: Vec<HubMessage>) -> WsMessage {
+        if messages.len() == 1 {
+            let message = messages.remove(0);
+            return WsMessage::from(message);
+        }
+        WsMessage::batch(messages)
+    }
+
+    pub(crate) fn decode(&self, message: Message) -> Result<WsMessage, String> {
+        match WsCompression::decompress(message)? {
+            Message::Text(text) => WsMessage::try_from(text),
+            Message::Binary(bytes) if bytes.starts_with(&hub_codec::MAGIC) => {
+                Ok(Self::from_messages(hub_codec::decode(&bytes)?))
+            }
+            Message::Binary(bytes) => match self {
+                WsEncoding::MsgPack => rmp_serde::from_slice(&bytes)
+                    .map_err(|e| format!("Invalid MessagePack frame: {}", e)),
+                #[cfg(feature = "protobuf")]
+                WsEncoding::Protobuf => hub_proto::WsMessage::decode(bytes.as_slice())
+                    .map_err(|e| format!("Invalid protobuf frame: {}", e))?
+                    .try_into(),
+                _ => Ok(Self::from_messages(hub_cbor::decode(&bytes)?)),
+            },
+            m => Err(format!("Unsupported WebSocket frame {:?}", m)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::hub::{HubChannelName, PROTOCOL_VERSION};
+
+    #[test]
+    fn test_from_protocols() {
+        assert_eq!(
+            WsEncoding::from_protocols("robopilot.bin"),
+            WsEncoding::Binary
+        );
+        assert_eq!(
+            WsEncoding::from_protocols("chat, robopilot.bin"),
+            WsEncoding::Binary
+        );
+        assert_eq!(
+            WsEncoding::from_protocols("robopilot.cbor, robopilot.bin"),
+            WsEncoding::Cbor
+        );
+        assert_eq!(WsEncoding::from_protocols("chat"), WsEncoding::Json);
+    }
+
+    #[test]
+    fn test_cbor_batch_roundtrip() {
+        let messages = vec![
+            HubMessage::try_from_str("channel1", "1").unwrap(),
+            HubMessage::try_from_str("channel2", "2").unwrap(),
+        ];
+        let frame = WsEncoding::Cbor
+            .encode(&WsMessage::batch(messages), PROTOCOL_VERSION)
+            .unwrap();
+        assert!(frame.is_binary());
+        assert!(
+            matches!(WsEncoding::Cbor.decode(frame).unwrap(), WsMessage::Batch(batch) if batch.len() == 2)
+        );
+        let frame = WsEncoding::Cbor
+            .encode(&WsMessage::list_channels_req(), PROTOCOL_VERSION)
+            .unwrap();
+        assert!(frame.is_text());
+    }
+
+    #[test]
+    fn test_msgpack_roundtrip() {
+        assert_eq!(
+            WsEncoding::from_protocols("robopilot.msgpack"),
+            WsEncoding::MsgPack
+        );
+        let messages = vec![
+            WsMessage::subscribe("channel1").unwrap(),
+            WsMessage::list_channels_req(),
+            WsMessage::send_data("channel1", "1,2,3").unwrap(),
+            WsMessage::batch(vec![
+                HubMessage::try_from_str("channel1", "1").unwrap(),
+                HubMessage::try_from_str("channel2", "2").unwrap(),
+            ]),
+        ];
+        for message in messages {
+            let frame = WsEncoding::MsgPack
+                .encode(&message, PROTOCOL_VERSION)
+                .unwrap();
+            assert!(frame.is_binary());
+            let decoded = WsEncoding::MsgPack.decode(frame).unwrap();
+            assert_eq!(decoded.to_string(), message.to_string());
+        }
+        assert!(WsEncoding::MsgPack
+            .decode(Message::Binary(vec![0xc1]))
+            .is_err());
+    }
+
+    #[cfg(feature = "protobuf")]
+    #[test]
+    fn test_protobuf_roundtrip() {
+        assert_eq!(
+            WsEncoding::from_protocols("robopilot.proto"),
+            WsEncoding::Protobuf
+        );
+        let messages = vec![
+            WsMessage::unsubscribe("channel1").unwrap(),
+            WsMessage::ListChannelsResponse(vec![HubChannelName::try_from("channel1").unwrap()]),
+            WsMessage::send_data("channel1", "1,2,3").unwrap(),
+            WsMessage::batch(vec![
+                HubMessage::try_from_str("channel1", "1").unwrap(),
+                HubMessage::try_from_str("channel2", "2").unwrap(),
+            ]),
+            WsMessage::auth("token"),
+        ];
+        for message in messages {
+            let frame = WsEncoding::Protobuf
+                .encode(&message, PROTOCOL_VERSION)
+                .unwrap();
+            assert!(frame.is_binary());
+            let decoded = WsEncoding::Protobuf.decode(frame).unwrap();
+            assert_eq!(decoded.to_string(), message.to_string());
+        }
+        // empty frames decode to a message without content, which is rejected
+        assert!(WsEncoding::Protobuf
+            .decode(Message::Binary(Vec::new()))
+            .is_err());
+    }
+
+    #[test]
+    fn test_binary_data_roundtrip() {
+        let channel = HubChannelName::try_from("test_channel").unwrap();
+        let message = WsMessage::send_data_channel(channel.clone(), "1,2,3".parse().unwrap());
+        let frame = WsEncoding::Binary
+            .encode(&message, PROTOCOL_VERSION)
+            .unwrap();
+        assert!(frame.is_binary());
+        match WsEncoding::Binary.decode(frame).unwrap() {
+            WsMessage::Data(ch, data, _) => {
+                assert_eq!(ch, channel);
+                assert_eq!(data.as_str(), "1,2,3");
+            }
+            _ => panic!("Expected WsMessage::Data"),
+        }
+    }
+
+    #[test]
+    fn test_binary_batch_roundtrip() {
+        let messages = vec![
+            HubMessage::try_from_str("channel1", "1").unwrap(),
+            HubMessage::try_from_str("channel2", "2").unwrap(),
+        ];
+        let frame = WsEncoding::Binary
+            .encode(&WsMessage::batch(messages), PROTOCOL_VERSION)
+            .unwrap();
+        assert!(
+            matches!(WsEncoding::Binary.decode(frame).unwrap(), WsMessage::Batch(batch) if batch.len() == 2)
+        );
+    }
+
+    #[test]
+    fn test_headers_roundtrip() {
+        let message = HubMessage::try_from_str("channel1", "1")
+            .unwrap()
+            .with_header("frame_id", "base_link");
+        for encoding in [WsEncoding::Binary, WsEncoding::Cbor, WsEncoding::MsgPack] {
+            let frame = encoding
+                .encode(&WsMessage::from(message.clone()), PROTOCOL_VERSION)
+                .unwrap();
+            let decoded = HubMessage::try_from(encoding.decode(frame).unwrap()).unwrap();
+            assert_eq!(decoded.headers, message.headers, "{:?}", encoding);
+
+            let frame = encoding
+                .encode(&WsMessage::from(message.clone()), LEGACY_PROTOCOL_VERSION)
+                .unwrap();
+            let decoded = HubMessage::try_from(encoding.decode(frame).unwrap()).unwrap();
+            assert!(decoded.headers.is_empty(), "{:?}", encoding);
+        }
+    }
+
+    #[test]
+    fn test_control_messages_are_json() {
+        let frame = WsEncoding::Binary
+            .encode(&WsMessage::list_channels_req(), PROTOCOL_VERSION)
+            .unwrap();
+        assert!(frame.is_text());
+        assert!(matches!(
+            WsEncoding::Binary.decode(frame).unwrap(),
+            WsMessage::ListChannelsReq
+        ));
+    }
+}
@@ -9,8 +9,8 @@ use tokio::sync::{broadcast, Mutex};
 use tokio_tungstenite::tungstenite::protocol::Message;
 use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
 
-use crate::adapters::websocket::WsMessage;
-use crate::models::hub::{HubChannelName, HubMessage};
+use crate::adapters::websocket::{WsCompression, WsEncoding, WsMessage};
+use crate::models::hub::{HubChannelName, HubMessage, LEGACY_PROTOCOL_VERSION};
 
 const TIMEOUT_SECS: u64 = 1;
 
@@ -26,21 +26,22 @@ pub(crate) async fn handle_incoming_data(
 pub(crate) async fn handle_send_ws_message(
     write: &mut SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>,
     message: WsMessage,
+    encoding: WsEncoding,
+    compression: WsCompression,
+    version: u16,
 ) -> Result<(), std::io::Error> {
     info!("Sending new WeMessage: {:?}", message);
     // Establish WebSocket connection
-    let message_string = message.to_string().map_err(|e| {
+    let frame = encoding.encode(&message, version).map_err(|e| {
         error!("Message conversion failed: {:?}", e);
         std::io::Error::new(std::io::ErrorKind::Other, "Conversion failed")
     })?;
+    let frame = compression.compress(frame);
 
-    write
-        .send(Message::Text(message_string))
-        .await
-        .map_err(|e| {
-            error!("WebSocket send error: {:?}", e);
-            std::io::Error::new(std::io::ErrorKind::Other, "WebSocket send failed")
-        })?;
+    write.send(frame).await.map_err(|e| {
+        error!("WebSocket send error: {:?}", e);
+        std::io::Error::new(std::io::ErrorKind::Other, "WebSocket send failed")
+    })?;
 
     Ok(())
 }
@@ -50,7 +51,15 @@ pub(crate) async fn handle_send_ws_message_with_response(
     read: &mut SplitStream<WebSocketStream<MaybeTlsStream<TcpStream>>>,
     message: WsMessage,
 ) -> Result<Vec<HubChannelName>, std::io::Error> {
-    handle_send_ws_message(write, message).await?;
+    // request is sent on a new connection, which doesn't negotiate the protocol version
+    handle_send_ws_message(
+        write,
+        message,
+        WsEncoding::Json,
+        WsCompression::None,
+        LEGACY_PROTOCOL_VERSION,
+    )
+    .await?;
 
     let timeout_duration = std::time::Duration::from_secs(TIMEOUT_SECS);
     //let mut receiver = response.lock().await;
@@ -0,0 +1,75 @@
+use std::time::{Duration, Instant};
+
+/// Default time between two pings
+pub const DEFAULT_PING_INTERVAL: Duration = Duration::from_secs(5);
+/// Default time without frames from a peer after which its connection is considered dead
+pub const DEFAULT_PING_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// `WsKeepalive` configures the detection of dead peers with ping/pong, so that half-open
+/// connections (e.g. after a Wi-Fi drop, where no close frame is ever received) are closed
+/// instead of silently dropping the messages of their channels.
+///
+/// A ping is sent to the peer every `interval`. Connections without any frame from the peer,
+/// pongs included, for `timeout` are closed.
+///
+/// # Fields
+/// - `interval`: Time between two pings. Defaults to 5s.
+/// - `timeout`: Time without frames after which the peer is considered dead. Defaults to 15s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WsKeepalive {
+    interval: Duration,
+    timeout: Duration,
+}
+
+impl WsKeepalive {
+    pub fn new(interval: Duration, timeout: Duration) -> Result<Self, String> {
+        if interval.is_zero() {
+            return Err("Keepalive ping interval can't be zero".to_string());
+        }
+        if timeout <= interval {
+            return Err("Keepalive timeout must be longer than the ping interval".to_string());
+        }
+        Ok(Self { interval, timeout })
+    }
+
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    // Returns true if a peer last seen at `last_seen` is considered dead
+    pub(crate) fn expired(&self, last_seen: Instant) -> bool {
+        last_seen.elapsed() >= self.timeout
+    }
+}
+
+impl Default for WsKeepalive {
+    fn default() -> Self {
+        Self {
+            interval: DEFAULT_PING_INTERVAL,
+            timeout: DEFAULT_PING_TIMEOUT,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keepalive() {
+        let keepalive = WsKeepalive::default();
+        assert_eq!(keepalive.interval(), DEFAULT_PING_INTERVAL);
+        assert_eq!(keepalive.timeout(), DEFAULT_PING_TIMEOUT);
+        assert!(!keepalive.expired(Instant::now()));
+
+        let keepalive =
+            WsKeepalive::new(Duration::from_millis(10), Duration::from_millis(30)).unwrap();
+        assert!(keepalive.expired(Instant::now() - Duration::from_millis(30)));
+
+        assert!(WsKeepalive::new(Duration::ZERO, Duration::from_secs(1)).is_err());
+        assert!(WsKeepalive::new(Duration::from_secs(1), Duration::from_secs(1)).is_err());
+    }
+}
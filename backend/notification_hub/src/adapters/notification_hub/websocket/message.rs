@@ -1,14 +1,82 @@
+use log::debug;
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
 
-use crate::models::hub::{HubChannelName, HubData, HubMessage};
+#[cfg(feature = "protobuf")]
+use crate::models::hub::hub_proto::{self, ws_message};
+use crate::models::hub::{
+    HubChannelName, HubData, HubMessage, LEGACY_PROTOCOL_VERSION, PROTOCOL_VERSION,
+};
 
+/// Maximum size in bytes of a WebSocket message. Larger messages are rejected.
+pub(crate) const MAX_WS_MESSAGE_LEN: usize = 1024 * 1024;
+
+/// Header negotiating the protocol version of JSON frames at connect time. The client
+/// announces its version, and the server replies with the version of the connection, the
+/// lowest of both. Peers not sending the header use `LEGACY_PROTOCOL_VERSION`.
+pub(crate) const VERSION_HEADER: &str = "robopilot-version";
+
+/// Protocol version of a connection with a peer announcing `version`
+pub(crate) fn negotiate_version(version: Option<&str>) -> u16 {
+    version
+        .and_then(|version| version.trim().parse::<u16>().ok())
+        .map(|version| version.min(PROTOCOL_VERSION))
+        .unwrap_or(LEGACY_PROTOCOL_VERSION)
+}
+
+/// WebSocket configuration enforcing `MAX_WS_MESSAGE_LEN` on both server and client sides
+pub(crate) fn ws_config() -> WebSocketConfig {
+    WebSocketConfig {
+        max_message_size: Some(MAX_WS_MESSAGE_LEN),
+        max_frame_size: Some(MAX_WS_MESSAGE_LEN),
+        ..Default::default()
+    }
+}
+
+/// Messages exchanged with WebSocket peers.
+///
+/// Data messages carry the headers of the `HubMessage` after its data. Headers are left out
+/// when empty, so messages without headers keep the `[channel, data]` form of older releases,
+/// and are stripped from messages sent to `LEGACY_PROTOCOL_VERSION` peers.
 #[derive(Serialize, Debug, Clone, Deserialize)]
 pub(crate) enum WsMessage {
     Subscribe(HubChannelName),
     Unsubscribe(HubChannelName),
     ListChannelsReq,
     ListChannelsResponse(Vec<HubChannelName>),
-    Data(HubChannelName, HubData),
+    Data(
+        HubChannelName,
+        HubData,
+        #[serde(default, skip_serializing_if = "HashMap::is_empty")] HashMap<String, String>,
+    ),
+    Batch(Vec<WsBatchEntry>),
+    Auth(String),
+}
+
+/// Channel, data and headers of a message in a `WsMessage::Batch`
+#[derive(Serialize, Debug, Clone, Deserialize)]
+pub(crate) struct WsBatchEntry(
+    pub HubChannelName,
+    pub HubData,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")] pub HashMap<String, String>,
+);
+
+impl From<HubMessage> for WsBatchEntry {
+    fn from(message: HubMessage) -> Self {
+        WsBatchEntry(message.channel, message.data, message.headers)
+    }
+}
+
+impl From<WsBatchEntry> for HubMessage {
+    fn from(entry: WsBatchEntry) -> Self {
+        let WsBatchEntry(channel, data, headers) = entry;
+        HubMessage {
+            headers,
+            ..HubMessage::new(channel, data)
+        }
+    }
 }
 
 impl WsMessage {
@@ -32,6 +100,10 @@ impl WsMessage {
         WsMessage::Unsubscribe(channel)
     }
 
+    pub fn auth(token: &str) -> Self {
+        WsMessage::Auth(token.to_string())
+    }
+
     pub fn list_channels_req() -> Self {
         WsMessage::ListChannelsReq
     }
@@ -39,25 +111,89 @@ impl WsMessage {
     #[allow(dead_code)]
     pub fn send_data(channel: &str, data: &str) -> Result<Self, String> {
         let channel_name = HubChannelName::try_from(channel)?;
-        let data = data.parse::<HubData>().unwrap();
-        Ok(WsMessage::Data(channel_name, data))
+        let data = data.parse::<HubData>()?;
+        Ok(WsMessage::Data(channel_name, data, HashMap::new()))
     }
 
     #[allow(dead_code)]
     pub fn send_data_channel(channel: HubChannelName, data: HubData) -> Self {
-        WsMessage::Data(channel, data)
+        WsMessage::Data(channel, data, HashMap::new())
+    }
+
+    pub fn batch(messages: Vec<HubMessage>) -> Self {
+        WsMessage::Batch(messages.into_iter().map(WsBatchEntry::from).collect())
+    }
+
+    /// Message without the headers of its data, for `LEGACY_PROTOCOL_VERSION` peers
+    pub(crate) fn without_headers(&self) -> Cow<'_, WsMessage> {
+        match self {
+            WsMessage::Data(channel, data, headers) if !headers.is_empty() => Cow::Owned(
+                WsMessage::Data(channel.clone(), data.clone(), HashMap::new()),
+            ),
+            WsMessage::Batch(batch) if batch.iter().any(|entry| !entry.2.is_empty()) => {
+                Cow::Owned(WsMessage::Batch(
+                    batch
+                        .iter()
+                        .map(|entry| WsBatchEntry(entry.0.clone(), entry.1.clone(), HashMap::new()))
+                        .collect(),
+                ))
+            }
+            _ => Cow::Borrowed(self),
+        }
     }
 
     pub fn to_string(&self) -> Result<String, String> {
         serde_json::to_string(self).map_err(|e| e.to_string())
     }
+
+    /// Serializes the message in the envelope of protocol `version`. Legacy messages are sent
+    /// bare and without headers, and newer ones as `{"v": version, "msg": message}`
+    pub fn to_versioned_string(&self, version: u16) -> Result<String, String> {
+        if version == LEGACY_PROTOCOL_VERSION {
+            return self.without_headers().to_string();
+        }
+        serde_json::to_string(&WsEnvelopeRef {
+            v: version,
+            msg: self,
+        })
+        .map_err(|e| e.to_string())
+    }
+}
+
+#[derive(Serialize)]
+struct WsEnvelopeRef<'a> {
+    v: u16,
+    msg: &'a WsMessage,
+}
+
+#[derive(Deserialize)]
+struct WsEnvelope {
+    v: u16,
+    msg: WsMessage,
 }
 
 impl TryFrom<String> for WsMessage {
     type Error = String;
 
     fn try_from(value: String) -> Result<Self, Self::Error> {
-        serde_json::from_str::<WsMessage>(&value).map_err(|e| e.to_string())
+        if value.len() > MAX_WS_MESSAGE_LEN {
+            return Err(format!(
+                "WsMessage of {} bytes exceeds maximum of {} bytes",
+                value.len(),
+                MAX_WS_MESSAGE_LEN
+            ));
+        }
+        // legacy messages are bare, and fail fast on the version field of envelopes
+        serde_json::from_str::<WsMessage>(&value).or_else(|e| {
+            let envelope = serde_json::from_str::<WsEnvelope>(&value).map_err(|_| e.to_string())?;
+            if envelope.v > PROTOCOL_VERSION {
+                debug!(
+                    "Decoding WsMessage of newer protocol version {}",
+                    envelope.v
+                );
+            }
+            Ok(envelope.msg)
+        })
     }
 }
 impl TryFrom<WsMessage> for HubMessage {
@@ -65,7 +201,9 @@ impl TryFrom<WsMessage> for HubMessage {
 
     fn try_from(value: WsMessage) -> Result<Self, Self::Error> {
         match value {
-            WsMessage::Data(channel, data) => Ok(HubMessage::new(channel, data)),
+            WsMessage::Data(channel, data, headers) => {
+                Ok(HubMessage::from(WsBatchEntry(channel, data, headers)))
+            }
             _ => Err("Invalid message type".to_string()),
         }
     }
@@ -73,7 +211,101 @@ impl TryFrom<WsMessage> for HubMessage {
 
 impl From<HubMessage> for WsMessage {
     fn from(value: HubMessage) -> Self {
-        WsMessage::Data(value.channel, value.data)
+        WsMessage::Data(value.channel, value.data, value.headers)
+    }
+}
+
+#[cfg(feature = "protobuf")]
+impl From<&WsMessage> for hub_proto::WsMessage {
+    fn from(value: &WsMessage) -> Self {
+        let proto_message =
+            |channel: &HubChannelName, data: &HubData, headers: &HashMap<String, String>| {
+                hub_proto::HubMessage {
+                    channel: channel.as_str().to_string(),
+                    data: data.as_str().to_string(),
+                    headers: headers.clone(),
+                    ..Default::default()
+                }
+            };
+        let message = match value {
+            WsMessage::Subscribe(channel) => {
+                ws_message::Message::Subscribe(channel.as_str().to_string())
+            }
+            WsMessage::Unsubscribe(channel) => {
+                ws_message::Message::Unsubscribe(channel.as_str().to_string())
+            }
+            WsMessage::ListChannelsReq => {
+                ws_message::Message::ListChannelsReq(hub_proto::ListChannelsRequest {})
+            }
+            WsMessage::ListChannelsResponse(channels) => {
+                ws_message::Message::ListChannelsResponse(hub_proto::ChannelList {
+                    channels: channels
+                        .iter()
+                        .map(|channel| channel.as_str().to_string())
+                        .collect(),
+                })
+            }
+            WsMessage::Data(channel, data, headers) => {
+                ws_message::Message::Data(proto_message(channel, data, headers))
+            }
+            WsMessage::Batch(batch) => ws_message::Message::Batch(hub_proto::HubMessageBatch {
+                messages: batch
+                    .iter()
+                    .map(|WsBatchEntry(channel, data, headers)| {
+                        proto_message(channel, data, headers)
+                    })
+                    .collect(),
+            }),
+            WsMessage::Auth(token) => ws_message::Message::Auth(token.clone()),
+        };
+        Self {
+            message: Some(message),
+        }
+    }
+}
+
+#[cfg(feature = "protobuf")]
+impl TryFrom<hub_proto::WsMessage> for WsMessage {
+    type Error = String;
+
+    fn try_from(value: hub_proto::WsMessage) -> Result<Self, Self::Error> {
+        let entry = |message: hub_proto::HubMessage| -> Result<_, String> {
+            Ok(WsBatchEntry(
+                HubChannelName::try_from(message.channel)?,
+                HubData::try_from(message.data)?,
+                message.headers,
+            ))
+        };
+        match value.message {
+            Some(ws_message::Message::Subscribe(channel)) => {
+                Ok(WsMessage::Subscribe(HubChannelName::try_from(channel)?))
+            }
+            Some(ws_message::Message::Unsubscribe(channel)) => {
+                Ok(WsMessage::Unsubscribe(HubChannelName::try_from(channel)?))
+            }
+            Some(ws_message::Message::ListChannelsReq(_)) => Ok(WsMessage::ListChannelsReq),
+            Some(ws_message::Message::ListChannelsResponse(list)) => {
+                Ok(WsMessage::ListChannelsResponse(
+                    list.channels
+                        .into_iter()
+                        .map(HubChannelName::try_from)
+                        .collect::<Result<_, _>>()?,
+                ))
+            }
+            Some(ws_message::Message::Data(message)) => {
+                let WsBatchEntry(channel, data, headers) = entry(message)?;
+                Ok(WsMessage::Data(channel, data, headers))
+            }
+            Some(ws_message::Message::Batch(batch)) => Ok(WsMessage::Batch(
+                batch
+                    .messages
+                    .into_iter()
+                    .map(entry)
+                    .collect::<Result<_, _>>()?,
+            )),
+            Some(ws_message::Message::Auth(token)) => Ok(WsMessage::Auth(token)),
+            None => Err("Empty protobuf WsMessage".to_string()),
+        }
     }
 }
 
@@ -143,7 +375,7 @@ mod tests {
         let data = "test_data";
         let result = WsMessage::send_data(channel, data);
         assert!(result.is_ok());
-        if let Ok(WsMessage::Data(channel_name, _)) = result {
+        if let Ok(WsMessage::Data(channel_name, _, _)) = result {
             assert_eq!(channel_name.as_str(), channel);
         } else {
             panic!("Expected WsMessage::Data");
@@ -155,7 +387,7 @@ mod tests {
         let channel_name = HubChannelName::try_from("test_channel").unwrap();
         let data = "test_data".parse::<HubData>().unwrap();
         let message = WsMessage::send_data_channel(channel_name.clone(), data.clone());
-        if let WsMessage::Data(ch, _) = message {
+        if let WsMessage::Data(ch, _, _) = message {
             assert_eq!(ch, channel_name);
         } else {
             panic!("Expected WsMessage::Data");
@@ -192,12 +424,12 @@ mod tests {
     fn test_data_to_string() {
         let channel_name = HubChannelName::try_from("test_channel").unwrap();
         let data = "test_data".parse::<HubData>().unwrap();
-        let message = WsMessage::Data(channel_name.clone(), data.clone());
+        let message = WsMessage::send_data_channel(channel_name.clone(), data.clone());
         let result = message.to_string();
         assert!(result.is_ok());
         let json_str = result.unwrap();
         let parsed_message: WsMessage = serde_json::from_str(&json_str).unwrap();
-        if let WsMessage::Data(ch, d) = parsed_message {
+        if let WsMessage::Data(ch, d, _) = parsed_message {
             assert_eq!(ch, channel_name);
             assert_eq!(d, data);
         } else {
@@ -210,7 +442,7 @@ mod tests {
         let json_str = r#"{"Data":["test_channel","test_data1, test_data2"]}"#.to_string();
         let result = WsMessage::try_from(json_str);
         assert!(result.is_ok());
-        if let Ok(WsMessage::Data(channel_name, data)) = result {
+        if let Ok(WsMessage::Data(channel_name, data, _)) = result {
             assert_eq!(channel_name.as_str(), "test_channel");
             assert_eq!(data.as_str(), "test_data1, test_data2");
         } else {
@@ -218,11 +450,94 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_versioned_envelope() {
+        let message = WsMessage::send_data("channel", "1,2").unwrap();
+        let legacy = message
+            .to_versioned_string(LEGACY_PROTOCOL_VERSION)
+            .unwrap();
+        assert_eq!(legacy, message.to_string().unwrap());
+        let versioned = message.to_versioned_string(PROTOCOL_VERSION).unwrap();
+        assert_eq!(versioned, r#"{"v":1,"msg":{"Data":["channel","1,2"]}}"#);
+
+        // both envelopes are decoded
+        for json in [legacy, versioned] {
+            assert!(matches!(
+                WsMessage::try_from(json).unwrap(),
+                WsMessage::Data(channel, _, _) if channel.as_str() == "channel"
+            ));
+        }
+        let newer = r#"{"v":9,"msg":"ListChannelsReq","ttl":5}"#.to_string();
+        assert!(matches!(
+            WsMessage::try_from(newer).unwrap(),
+            WsMessage::ListChannelsReq
+        ));
+        assert!(WsMessage::try_from(r#"{"v":1,"msg":{"Unknown":null}}"#.to_string()).is_err());
+    }
+
+    #[test]
+    fn test_headers() {
+        let message = HubMessage::try_from_str("channel", "1,2")
+            .unwrap()
+            .with_header("units", "m");
+        let ws_message = WsMessage::from(message.clone());
+        let versioned = ws_message.to_versioned_string(PROTOCOL_VERSION).unwrap();
+        assert_eq!(
+            versioned,
+            r#"{"v":1,"msg":{"Data":["channel","1,2",{"units":"m"}]}}"#
+        );
+        let decoded = HubMessage::try_from(WsMessage::try_from(versioned).unwrap()).unwrap();
+        assert_eq!(decoded.headers, message.headers);
+
+        let batch = WsMessage::batch(vec![message.clone()])
+            .to_versioned_string(PROTOCOL_VERSION)
+            .unwrap();
+        assert!(
+            matches!(WsMessage::try_from(batch).unwrap(), WsMessage::Batch(batch) if batch[0].2 == message.headers)
+        );
+
+        // legacy peers don't expect headers
+        assert_eq!(
+            ws_message
+                .to_versioned_string(LEGACY_PROTOCOL_VERSION)
+                .unwrap(),
+            r#"{"Data":["channel","1,2"]}"#
+        );
+    }
+
+    #[test]
+    fn test_negotiate_version() {
+        assert_eq!(negotiate_version(None), LEGACY_PROTOCOL_VERSION);
+        assert_eq!(negotiate_version(Some("1")), 1);
+        assert_eq!(negotiate_version(Some(" 42 ")), PROTOCOL_VERSION);
+        assert_eq!(negotiate_version(Some("v2")), LEGACY_PROTOCOL_VERSION);
+    }
+
+    #[test]
+    fn test_try_from_pathological_input() {
+        for input in [
+            "",
+            "{",
+            r#"{"Subscribe":""}"#,
+            r#"{"Subscribe":"invalid channel"}"#,
+            r#"{"Data":["channel"]}"#,
+            r#"{"Batch":[["",""]]}"#,
+            r#"{"Unknown":null}"#,
+        ] {
+            assert!(WsMessage::try_from(input.to_string()).is_err());
+        }
+        let too_long = format!(
+            r#"{{"Data":["channel","{}"]}}"#,
+            "a".repeat(MAX_WS_MESSAGE_LEN)
+        );
+        assert!(WsMessage::try_from(too_long).is_err());
+    }
+
     #[test]
     fn test_try_from_ws_message() {
         let channel_name = HubChannelName::try_from("test_channel").unwrap();
         let data = "test_data".parse::<HubData>().unwrap();
-        let ws_message = WsMessage::Data(channel_name.clone(), data.clone());
+        let ws_message = WsMessage::send_data_channel(channel_name.clone(), data.clone());
         let result = HubMessage::try_from(ws_message);
         assert!(result.is_ok());
         let hub_message = result.unwrap();
@@ -230,13 +545,33 @@ mod tests {
         assert_eq!(hub_message.data, data);
     }
 
+    #[test]
+    fn test_batch_to_string() {
+        let messages = vec![
+            HubMessage::try_from_str("channel1", "data1").unwrap(),
+            HubMessage::try_from_str("channel2", "data2").unwrap(),
+        ];
+        let json_str = WsMessage::batch(messages).to_string().unwrap();
+        assert_eq!(
+            json_str,
+            r#"{"Batch":[["channel1","data1"],["channel2","data2"]]}"#
+        );
+        if let Ok(WsMessage::Batch(batch)) = WsMessage::try_from(json_str) {
+            assert_eq!(batch.len(), 2);
+            assert_eq!(batch[1].0.as_str(), "channel2");
+            assert_eq!(batch[1].1.as_str(), "data2");
+        } else {
+            panic!("Expected WsMessage::Batch");
+        }
+    }
+
     #[test]
     fn test_from_hub_message() {
         let channel_name = HubChannelName::try_from("test_channel").unwrap();
         let data = "test_data".parse::<HubData>().unwrap();
         let hub_message = HubMessage::new(channel_name.clone(), data.clone());
         let ws_message: WsMessage = hub_message.into();
-        if let WsMessage::Data(ch, d) = ws_message {
+        if let WsMessage::Data(ch, d, _) = ws_message {
             assert_eq!(ch, channel_name);
             assert_eq!(d, data);
         } else {
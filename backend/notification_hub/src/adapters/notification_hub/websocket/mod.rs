@@ -1,8 +1,20 @@
+pub mod auth;
 pub mod client;
+pub mod compression;
+pub mod encoding;
 mod handlers;
+pub mod keepalive;
 pub(crate) mod message;
 pub(crate) mod server;
+#[cfg(feature = "tls")]
+pub mod tls;
 
+pub use auth::{WsAuth, WsPermissions};
 pub use client::WebSocketClient;
+pub use compression::WsCompression;
+pub use encoding::WsEncoding;
+pub use keepalive::WsKeepalive;
 pub(crate) use message::WsMessage;
 pub use server::WebSocketServer;
+#[cfg(feature = "tls")]
+pub use tls::{WsCertificate, WsTrustedCerts};
@@ -1,16 +1,123 @@
+use dashmap::DashMap;
 use futures_channel::mpsc::{unbounded, UnboundedSender};
 use futures_util::{future, pin_mut, stream::TryStreamExt, StreamExt};
 use log::{debug, error, info, warn};
-use std::{collections::HashMap, net::SocketAddr, sync::Arc};
-use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::Mutex;
-use tokio_tungstenite::tungstenite::protocol::Message;
+use std::{
+    collections::HashMap,
+    future::Future,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{lookup_host, TcpListener, TcpSocket, TcpStream};
+use tokio::task::{AbortHandle, JoinSet};
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::{header::SEC_WEBSOCKET_PROTOCOL, HeaderValue};
+use tokio_tungstenite::tungstenite::protocol::{frame::coding::CloseCode, CloseFrame, Message};
+use tokio_tungstenite::WebSocketStream;
 
-use crate::adapters::websocket::message::WsMessage;
-use crate::models::hub::{HubChannelName, HubData};
+use crate::adapters::websocket::auth::{WsAuth, WsPermissions};
+use crate::adapters::websocket::compression::WsCompression;
+use crate::adapters::websocket::encoding::WsEncoding;
+use crate::adapters::websocket::keepalive::WsKeepalive;
+use crate::adapters::websocket::message::{
+    negotiate_version, ws_config, WsBatchEntry, WsMessage, VERSION_HEADER,
+};
+#[cfg(feature = "tls")]
+use crate::adapters::websocket::tls::WsCertificate;
+use crate::models::hub::{HubChannelName, HubData, LEGACY_PROTOCOL_VERSION};
 
-type PeerMap = HashMap<SocketAddr, UnboundedSender<Message>>;
-type ChannelMap = Arc<Mutex<HashMap<HubChannelName, PeerMap>>>;
+type PeerMap = HashMap<SocketAddr, WsPeer>;
+type PeerBatch = (WsPeer, Vec<WsBatchEntry>);
+/// Channel map is sharded so that data messages from different channels don't
+/// serialize on a single lock.
+type ChannelMap = Arc<DashMap<HubChannelName, WsChannel>>;
+
+const LISTEN_BACKLOG: u32 = 1024;
+
+/// Settings of the connections accepted by the server
+#[derive(Debug, Clone, Default)]
+struct ConnectionSettings {
+    auth: Option<Arc<WsAuth>>,
+    keepalive: Option<WsKeepalive>,
+}
+
+impl ConnectionSettings {
+    // Time by which a peer connecting now must have completed the handshakes and authenticated
+    fn auth_deadline(&self) -> Option<Instant> {
+        self.auth
+            .as_ref()
+            .map(|auth| Instant::now() + auth.timeout())
+    }
+}
+
+/// Runs `future` until `deadline`, if set. Returns None if the deadline expired first
+async fn until<F: Future>(deadline: Option<Instant>, future: F) -> Option<F::Output> {
+    match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline.into(), future).await.ok(),
+        None => Some(future.await),
+    }
+}
+
+/// Connected peer. Frames sent to the peer use the encoding, compression and protocol
+/// version it negotiated at connect time
+#[derive(Debug, Clone)]
+struct WsPeer {
+    tx: UnboundedSender<Message>,
+    encoding: WsEncoding,
+    compression: WsCompression,
+    version: u16,
+}
+
+impl WsPeer {
+    fn encode(&self, message: &WsMessage) -> Result<Message, String> {
+        Ok(self
+            .compression
+            .compress(self.encoding.encode(message, self.version)?))
+    }
+}
+
+/// Subscribers of a channel, and last time the channel had data or subscribers
+#[derive(Debug)]
+struct WsChannel {
+    peers: PeerMap,
+    last_active: Instant,
+}
+
+impl Default for WsChannel {
+    fn default() -> Self {
+        Self {
+            peers: PeerMap::new(),
+            last_active: Instant::now(),
+        }
+    }
+}
+
+/// Caches the encoded frame of a message for every wire encoding, compression and protocol
+/// version, so that a message is encoded at most once per encoding when broadcast to many
+/// subscribers
+#[derive(Default)]
+struct FrameCache(HashMap<(WsEncoding, WsCompression, u16), Message>);
+
+impl FrameCache {
+    fn get(&mut self, peer: &WsPeer, message: &WsMessage) -> Option<Message> {
+        let key = (peer.encoding, peer.compression, peer.version);
+        if let Some(frame) = self.0.get(&key) {
+            return Some(frame.clone());
+        }
+        match peer.encode(message) {
+            Ok(frame) => {
+                self.0.insert(key, frame.clone());
+                Some(frame)
+            }
+            Err(e) => {
+                error!("Message conversion failed: {:?}", e);
+                None
+            }
+        }
+    }
+}
 
 /// WebSocket Server of Pub Sub Topic network
 /// Server can receive 4 different WsMessages:
@@ -18,85 +125,307 @@ type ChannelMap = Arc<Mutex<HashMap<HubChannelName, PeerMap>>>;
 /// - WsMessage::Unsubscribe -> Server removes subcriber from topic channel
 /// - WsMessage::Data -> Server broascasts message from topic to all registered
 ///   subscribers
+/// - WsMessage::Batch -> Server broadcasts every message in the batch, grouping
+///   messages per subscriber into a single batch frame
 /// - WsMessage::ListChannelsReq -> Responds with WsMessage::ListChannelsRep
 ///   containing available topic channels
+///
+/// Peers may negotiate a binary wire encoding for data frames through the
+/// `Sec-WebSocket-Protocol` header (see `WsEncoding`), and compression of large frames
+/// through the `robopilot-compression` header (see `WsCompression`). Text frames use the
+/// envelope of the protocol version negotiated through the `robopilot-version` header.
+///
+/// Servers are started explicitly with `start` or `run_until`, or run standalone with the
+/// `ws_server` binary. Clients only connect to them. Connections are owned by the accept loop
+/// that accepted them, so `stop` closes every connection together with the listeners.
 #[derive(Debug)]
 pub struct WebSocketServer {
     url: String,
     channel_map: ChannelMap,
+    acceptors: usize,
+    channel_ttl: Option<Duration>,
+    accept_loops: Mutex<Vec<AbortHandle>>,
+    auth: Option<Arc<WsAuth>>,
+    keepalive: Option<WsKeepalive>,
+    #[cfg(feature = "tls")]
+    certificate: Option<WsCertificate>,
 }
 
 impl WebSocketServer {
     pub fn new(url: &str) -> Self {
         Self {
             url: url.to_string(),
-            channel_map: Arc::new(Mutex::new(HashMap::new())),
+            channel_map: Arc::new(DashMap::new()),
+            acceptors: 1,
+            channel_ttl: None,
+            accept_loops: Mutex::new(Vec::new()),
+            auth: None,
+            keepalive: None,
+            #[cfg(feature = "tls")]
+            certificate: None,
         }
     }
 
-    /// Start server
-    pub async fn start(&self) -> Result<(), std::io::Error> {
-        let listener = TcpListener::bind(&self.url).await?;
-        let channel_map = self.channel_map.clone(); // Clone the channel map
-        info!("Listening on: {}", self.url);
+    /// Sets the number of accept loops. Several listeners are bound to the same address with
+    /// SO_REUSEPORT, and the kernel partitions incoming connections across their accept loops.
+    /// All connections share the same channel map. Values greater than 1 require SO_REUSEPORT
+    /// support, and fall back to a single accept loop otherwise.
+    pub fn with_acceptors(mut self, acceptors: usize) -> Self {
+        self.acceptors = acceptors.max(1);
+        self
+    }
 
-        tokio::spawn(async move {
-            loop {
-                match listener.accept().await {
-                    Ok((stream, addr)) => {
-                        tokio::spawn(handle_connection(channel_map.clone(), stream, addr));
-                    }
-                    Err(e) => {
-                        warn!("Failed to accept connection: {:?}", e);
+    /// Sets the time after which channels without subscribers nor data are removed, so that
+    /// peers publishing in dynamically named channels don't grow the channel map without bound
+    pub fn with_channel_ttl(mut self, channel_ttl: Duration) -> Self {
+        self.channel_ttl = Some(channel_ttl).filter(|ttl| !ttl.is_zero());
+        self
+    }
+
+    /// Requires peers to authenticate with one of the tokens of `auth` as their first message.
+    /// Peers may only publish and subscribe to the channels allowed to their token
+    pub fn with_auth(mut self, auth: WsAuth) -> Self {
+        self.auth = Some(Arc::new(auth));
+        self
+    }
+
+    /// Pings peers as configured by `keepalive`, and closes the connections of peers not
+    /// answering, removing them from the channels they subscribed to
+    pub fn with_keepalive(mut self, keepalive: WsKeepalive) -> Self {
+        self.keepalive = Some(keepalive);
+        self
+    }
+
+    /// Accepts connections over TLS, presenting `certificate`. Peers connect with `wss://` URLs
+    #[cfg(feature = "tls")]
+    pub fn with_tls(mut self, certificate: WsCertificate) -> Self {
+        self.certificate = Some(certificate);
+        self
+    }
+
+    /// Start server. Returns the address the server is listening on.
+    pub async fn start(&self) -> Result<SocketAddr, std::io::Error> {
+        #[cfg(feature = "tls")]
+        let acceptor = self
+            .certificate
+            .as_ref()
+            .map(WsCertificate::acceptor)
+            .transpose()?;
+        let listeners = self.bind().await?;
+        let local_addr = listeners[0].local_addr()?;
+        info!(
+            "Listening on: {} with {} acceptors",
+            local_addr,
+            listeners.len()
+        );
+
+        let mut accept_loops = self.accept_loops.lock().unwrap_or_else(|e| e.into_inner());
+        for listener in listeners {
+            let channel_map = self.channel_map.clone(); // Clone the channel map
+            let settings = ConnectionSettings {
+                auth: self.auth.clone(),
+                keepalive: self.keepalive,
+            };
+            #[cfg(feature = "tls")]
+            let acceptor = acceptor.clone();
+            let accept_loop = tokio::spawn(async move {
+                // connections are aborted when the accept loop is stopped
+                let mut connections = JoinSet::new();
+                loop {
+                    tokio::select! {
+                        accepted = listener.accept() => match accepted {
+                            Ok((stream, addr)) => {
+                                #[cfg(feature = "tls")]
+                                let connection = handle_tls_connection(channel_map.clone(), settings.clone(), acceptor.clone(), stream, addr);
+                                #[cfg(not(feature = "tls"))]
+                                let connection = handle_connection(channel_map.clone(), settings.clone(), stream, addr, settings.auth_deadline());
+                                connections.spawn(connection);
+                            }
+                            Err(e) => {
+                                warn!("Failed to accept connection: {:?}", e);
+                            }
+                        },
+                        Some(_) = connections.join_next() => {}
                     }
                 }
-            }
-        });
+            });
+            accept_loops.push(accept_loop.abort_handle());
+        }
+        if let Some(channel_ttl) = self.channel_ttl {
+            let channel_map = self.channel_map.clone();
+            let channel_gc = tokio::spawn(async move {
+                let mut interval = tokio::time::interval(channel_ttl);
+                loop {
+                    interval.tick().await;
+                    remove_idle_channels(&channel_map, channel_ttl);
+                }
+            });
+            accept_loops.push(channel_gc.abort_handle());
+        }
         info!("WS server started");
+        Ok(local_addr)
+    }
+
+    /// Runs the server until `shutdown` completes (e.g. on Ctrl+C), then stops it
+    pub async fn run_until<F>(&self, shutdown: F) -> Result<(), std::io::Error>
+    where
+        F: Future<Output = ()>,
+    {
+        self.start().await?;
+        shutdown.await;
+        self.stop();
         Ok(())
     }
+
+    /// Stops the server. Listeners and open connections are closed.
+    pub fn stop(&self) {
+        let mut accept_loops = self.accept_loops.lock().unwrap_or_else(|e| e.into_inner());
+        for accept_loop in accept_loops.drain(..) {
+            accept_loop.abort();
+        }
+        info!("WS server stopped");
+    }
+
+    // Binds server listeners. A single listener is bound without SO_REUSEPORT, so that
+    // starting a second server on the same address fails.
+    async fn bind(&self) -> Result<Vec<TcpListener>, std::io::Error> {
+        if self.acceptors == 1 || !cfg!(unix) {
+            if self.acceptors > 1 {
+                warn!("SO_REUSEPORT not supported. Starting a single acceptor");
+            }
+            return Ok(vec![TcpListener::bind(&self.url).await?]);
+        }
+
+        let addr = lookup_host(&self.url).await?.next().ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Couldn't resolve {}", self.url),
+            )
+        })?;
+        let first = bind_reuseport(addr)?;
+        // if port 0 was requested, remaining listeners bind to the port assigned to the first one
+        let addr = first.local_addr()?;
+        let mut listeners = vec![first];
+        for _ in 1..self.acceptors {
+            listeners.push(bind_reuseport(addr)?);
+        }
+        Ok(listeners)
+    }
+}
+
+#[cfg(unix)]
+fn bind_reuseport(addr: SocketAddr) -> Result<TcpListener, std::io::Error> {
+    let socket = if addr.is_ipv4() {
+        TcpSocket::new_v4()?
+    } else {
+        TcpSocket::new_v6()?
+    };
+    socket.set_reuseaddr(true)?;
+    socket.set_reuseport(true)?;
+    socket.bind(addr)?;
+    socket.listen(LISTEN_BACKLOG)
+}
+
+#[cfg(not(unix))]
+fn bind_reuseport(addr: SocketAddr) -> Result<TcpListener, std::io::Error> {
+    let socket = if addr.is_ipv4() {
+        TcpSocket::new_v4()?
+    } else {
+        TcpSocket::new_v6()?
+    };
+    socket.bind(addr)?;
+    socket.listen(LISTEN_BACKLOG)
+}
+
+/// Removes channels without subscribers nor data for `ttl`. Channels with subscribers are
+/// considered active
+fn remove_idle_channels(channel_map: &ChannelMap, ttl: Duration) {
+    let now = Instant::now();
+    channel_map.retain(|channel_name, channel| {
+        if !channel.peers.is_empty() {
+            channel.last_active = now;
+        }
+        let active = now.duration_since(channel.last_active) < ttl;
+        if !active {
+            info!("Idle channel removed: {:?}", channel_name);
+        }
+        active
+    });
 }
 
 // Handlers
 
-/// WsMessage::Data handler. Broadcasts received data to all subscribers registered to channel
-async fn handle_ws_data(
+/// WsMessage::Data handler. Broadcasts received data, with its headers, to all subscribers
+/// registered to channel
+fn handle_ws_data(
     channel_map: &ChannelMap,
     channel_name: &HubChannelName,
     data: HubData,
+    headers: HashMap<String, String>,
     addr: SocketAddr,
 ) {
-    let mut channels = channel_map.lock().await;
-    // Add new topic if necessary
-    channels.entry(channel_name.clone()).or_insert_with({
+    // Add new topic if necessary. Only the shard holding this channel is locked
+    let mut channel = channel_map.entry(channel_name.clone()).or_insert_with({
         info!("New channel created: {:?}", channel_name);
-        HashMap::new
+        WsChannel::default
     });
+    channel.last_active = Instant::now();
+    let subscribers = &channel.peers;
 
     // broadcast message to subscribers
-    if let Some(subscribers) = channels.get(channel_name) {
-        let ws_message = WsMessage::send_data_channel(channel_name.clone(), data)
-            .to_string()
-            .unwrap();
+    let ws_message = WsMessage::Data(channel_name.clone(), data, headers);
+    let mut frames = FrameCache::default();
 
-        info!(
-            "Broadcasting message: {:?}  with subscribers {:?}",
-            ws_message, subscribers
-        );
-        for (&peer_addr, peer_tx) in subscribers {
+    info!(
+        "Broadcasting message: {:?}  with subscribers {:?}",
+        ws_message, subscribers
+    );
+    for (&peer_addr, peer) in subscribers.iter() {
+        if peer_addr != addr {
+            if let Some(frame) = frames.get(peer, &ws_message) {
+                debug!("Message sent to {:?}", peer_addr);
+                let _ = peer.tx.unbounded_send(frame);
+            }
+        }
+    }
+}
+
+/// WsMessage::Batch handler. Messages in the batch are regrouped per subscriber so that
+/// each peer receives a single batch frame
+fn handle_ws_batch(channel_map: &ChannelMap, batch: Vec<WsBatchEntry>, addr: SocketAddr) {
+    let mut peer_batches: HashMap<SocketAddr, PeerBatch> = HashMap::new();
+    for entry in batch {
+        let channel_name = &entry.0;
+        let mut channel = channel_map.entry(channel_name.clone()).or_default();
+        channel.last_active = Instant::now();
+        for (&peer_addr, peer) in channel.peers.iter() {
             if peer_addr != addr {
-                debug!("Message sent to {:?}", addr);
-                let _ = peer_tx.unbounded_send(Message::Text(ws_message.clone()));
+                peer_batches
+                    .entry(peer_addr)
+                    .or_insert_with(|| (peer.clone(), Vec::new()))
+                    .1
+                    .push(entry.clone());
             }
         }
     }
+
+    for (peer_addr, (peer, batch)) in peer_batches {
+        debug!("Batch of {} messages sent to {:?}", batch.len(), peer_addr);
+        match peer.encode(&WsMessage::Batch(batch)) {
+            Ok(frame) => {
+                let _ = peer.tx.unbounded_send(frame);
+            }
+            Err(e) => error!("Message conversion failed: {:?}", e),
+        }
+    }
 }
 
-/// WsMessage::Subscribe handler. Registers new subscriber to channel
-async fn handle_ws_subscribe(
+/// WsMessage::Subscribe handler. Registers new subscriber to channel, creating the channel if
+/// nothing was published to it yet
+fn handle_ws_subscribe(
     channel_map: &ChannelMap,
     channel_name: &HubChannelName,
-    tx: UnboundedSender<Message>,
+    peer: WsPeer,
     addr: SocketAddr,
 ) {
     info!(
@@ -104,15 +433,16 @@ async fn handle_ws_subscribe(
         channel_name, addr
     );
 
-    let mut channels = channel_map.lock().await;
-    if let Some(channel) = channels.get_mut(channel_name) {
-        channel.insert(addr, tx.clone());
-        info!("Client {} subscribed to {:?}", addr, channel_name);
-    }
+    let mut channel = channel_map.entry(channel_name.clone()).or_insert_with({
+        info!("New channel created: {:?}", channel_name);
+        WsChannel::default
+    });
+    channel.peers.insert(addr, peer);
+    info!("Client {} subscribed to {:?}", addr, channel_name);
 }
 
 /// WsMessage::Unsubscribe handler. Deregisters new subscriber from channel
-async fn handle_ws_unsubscribe(
+fn handle_ws_unsubscribe(
     channel_map: &ChannelMap,
     channel_name: &HubChannelName,
     addr: SocketAddr,
@@ -121,59 +451,259 @@ async fn handle_ws_unsubscribe(
         "Unsubscription request from channel {:?} from {:?}",
         channel_name, addr
     );
-    let mut channels = channel_map.lock().await;
-    if let Some(subscribers) = channels.get_mut(channel_name) {
-        subscribers.remove(&addr);
+    if let Some(mut channel) = channel_map.get_mut(channel_name) {
+        channel.peers.remove(&addr);
         info!("Client {} unsubscribed from {:?}", addr, channel_name);
     }
 }
 
 /// WsMessage::ListChannelsReq handler. Sends requester a WsMessage::ListChannelsResp containing
-/// the available topic channels
-async fn handle_ws_list_channels(channel_map: &ChannelMap, tx: UnboundedSender<Message>) {
-    let channels = channel_map.lock().await;
-    let available_channels: Vec<HubChannelName> = channels.keys().cloned().collect();
+/// the available topic channels the requester may subscribe to
+fn handle_ws_list_channels(
+    channel_map: &ChannelMap,
+    permissions: &WsPermissions,
+    tx: UnboundedSender<Message>,
+    version: u16,
+) {
+    let available_channels: Vec<HubChannelName> = channel_map
+        .iter()
+        .map(|entry| entry.key().clone())
+        .filter(|channel_name| permissions.can_subscribe(channel_name))
+        .collect();
     let ws_list_channels_resp = WsMessage::ListChannelsResponse(available_channels.clone());
     info!(
         "Received List Channels Request. Sending Response: {:?}",
         ws_list_channels_resp
     );
-    let _ = tx.unbounded_send(Message::Text(ws_list_channels_resp.to_string().unwrap()));
+    match ws_list_channels_resp.to_versioned_string(version) {
+        Ok(text) => {
+            let _ = tx.unbounded_send(Message::Text(text));
+        }
+        Err(e) => error!("Message conversion failed: {:?}", e),
+    }
+}
+
+/// Completes the TLS handshake with the peer if `acceptor` is set, and handles the connection.
+/// With authentication, the handshake counts towards the authentication timeout
+#[cfg(feature = "tls")]
+async fn handle_tls_connection(
+    channel_map: ChannelMap,
+    settings: ConnectionSettings,
+    acceptor: Option<tokio_rustls::TlsAcceptor>,
+    stream: TcpStream,
+    addr: SocketAddr,
+) {
+    let deadline = settings.auth_deadline();
+    let Some(acceptor) = acceptor else {
+        return handle_connection(channel_map, settings, stream, addr, deadline).await;
+    };
+    match until(deadline, acceptor.accept(stream)).await {
+        Some(Ok(stream)) => handle_connection(channel_map, settings, stream, addr, deadline).await,
+        Some(Err(e)) => warn!("TLS handshake with {} failed: {:?}", addr, e),
+        None => warn!("TLS handshake with {} timed out", addr),
+    }
+}
+
+/// Pings the peer every keepalive interval. Returns when no frame was received from the peer,
+/// last at `last_seen`, for the keepalive timeout. Never returns if keepalive is disabled
+async fn ping_peer(
+    keepalive: Option<WsKeepalive>,
+    tx: UnboundedSender<Message>,
+    last_seen: Arc<Mutex<Instant>>,
+    addr: SocketAddr,
+) {
+    let Some(keepalive) = keepalive else {
+        return future::pending().await;
+    };
+    let mut interval = tokio::time::interval(keepalive.interval());
+    loop {
+        interval.tick().await;
+        if keepalive.expired(*last_seen.lock().unwrap_or_else(|e| e.into_inner())) {
+            warn!("Peer {} not responding to pings", addr);
+            return;
+        }
+        let _ = tx.unbounded_send(Message::Ping(Vec::new()));
+    }
 }
 
-/// Dispatches received message to handler
-async fn handle_connection(channel_map: ChannelMap, raw_stream: TcpStream, addr: SocketAddr) {
+/// Waits for the first message of a peer, and returns the permissions of the token it carries.
+/// Returns None if the peer doesn't send an accepted token by `deadline`
+async fn authenticate<S>(
+    ws_stream: &mut WebSocketStream<S>,
+    auth: &WsAuth,
+    encoding: WsEncoding,
+    deadline: Option<Instant>,
+) -> Option<WsPermissions>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let first_message = async {
+        while let Some(Ok(message)) = ws_stream.next().await {
+            if message.is_ping() || message.is_pong() {
+                continue;
+            }
+            return encoding.decode(message).ok();
+        }
+        None
+    };
+    match until(deadline, first_message).await {
+        Some(Some(WsMessage::Auth(token))) => auth.permissions(&token).cloned(),
+        _ => None,
+    }
+}
+
+/// Dispatches received message to handler. With authentication, the peer must complete the
+/// WebSocket handshake and authenticate by `auth_deadline`
+async fn handle_connection<S>(
+    channel_map: ChannelMap,
+    settings: ConnectionSettings,
+    raw_stream: S,
+    addr: SocketAddr,
+    auth_deadline: Option<Instant>,
+) where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
     info!("Incoming TCP connection from: {}", addr);
 
-    let ws_stream = match tokio_tungstenite::accept_async(raw_stream).await {
-        Ok(ws) => ws,
-        Err(e) => {
+    let mut encoding = WsEncoding::Json;
+    let mut compression = WsCompression::None;
+    let mut version = LEGACY_PROTOCOL_VERSION;
+    let negotiate_encoding =
+        |request: &Request, mut response: Response| -> Result<Response, ErrorResponse> {
+            if let Some(protocols) = request
+                .headers()
+                .get(SEC_WEBSOCKET_PROTOCOL)
+                .and_then(|protocols| protocols.to_str().ok())
+            {
+                encoding = WsEncoding::from_protocols(protocols);
+                if let Some(protocol) = encoding.protocol() {
+                    response
+                        .headers_mut()
+                        .insert(SEC_WEBSOCKET_PROTOCOL, HeaderValue::from_static(protocol));
+                }
+            }
+            if let Some(compressions) = request
+                .headers()
+                .get(WsCompression::HEADER)
+                .and_then(|compressions| compressions.to_str().ok())
+            {
+                compression = WsCompression::from_header(compressions);
+                if let Some(token) = compression.token() {
+                    response
+                        .headers_mut()
+                        .insert(WsCompression::HEADER, HeaderValue::from_static(token));
+                }
+            }
+            version = negotiate_version(
+                request
+                    .headers()
+                    .get(VERSION_HEADER)
+                    .and_then(|version| version.to_str().ok()),
+            );
+            if version != LEGACY_PROTOCOL_VERSION {
+                response
+                    .headers_mut()
+                    .insert(VERSION_HEADER, HeaderValue::from(version));
+            }
+            Ok(response)
+        };
+    let handshake = tokio_tungstenite::accept_hdr_async_with_config(
+        raw_stream,
+        negotiate_encoding,
+        Some(ws_config()),
+    );
+    let mut ws_stream = match until(auth_deadline, handshake).await {
+        Some(Ok(ws)) => ws,
+        Some(Err(e)) => {
             error!("Websocket handshake failed: {:?}", e);
             return;
         }
+        None => {
+            warn!("Websocket handshake with {} timed out", addr);
+            return;
+        }
     };
-    info!("WebSocket connection established: {}", addr);
+    info!(
+        "WebSocket connection established: {} with encoding {:?}, compression {:?} and protocol version {}",
+        addr, encoding, compression, version
+    );
+
+    let permissions = match &settings.auth {
+        Some(auth) => match authenticate(&mut ws_stream, auth, encoding, auth_deadline).await {
+            Some(permissions) => permissions,
+            None => {
+                warn!("Peer {} failed to authenticate", addr);
+                let _ = ws_stream
+                    .close(Some(CloseFrame {
+                        code: CloseCode::Policy,
+                        reason: "Unauthorized".into(),
+                    }))
+                    .await;
+                return;
+            }
+        },
+        None => WsPermissions::all(),
+    };
+    let permissions = Arc::new(permissions);
 
     let (tx, rx) = unbounded();
     let (outgoing, incoming) = ws_stream.split();
+    let last_seen = Arc::new(Mutex::new(Instant::now()));
 
     let broadcast_incoming = incoming.try_for_each(|msg| {
-        let msg_text = msg.to_text().unwrap_or_default().to_string();
         let channel_map = channel_map.clone();
         let tx = tx.clone();
+        let permissions = Arc::clone(&permissions);
+        *last_seen.lock().unwrap_or_else(|e| e.into_inner()) = Instant::now();
         async move {
-            match WsMessage::try_from(msg_text) {
+            // pings are answered by tungstenite, and pongs only keep the connection alive
+            if msg.is_ping() || msg.is_pong() {
+                return Ok(());
+            }
+            match encoding.decode(msg) {
                 Ok(ws_message) => match ws_message {
-                    WsMessage::Data(channel_name, data) => {
-                        handle_ws_data(&channel_map, &channel_name, data, addr).await
+                    WsMessage::Data(channel_name, _, _)
+                        if !permissions.can_publish(&channel_name) =>
+                    {
+                        warn!("Peer {} not allowed to publish to {:?}", addr, channel_name)
+                    }
+                    WsMessage::Data(channel_name, data, headers) => {
+                        handle_ws_data(&channel_map, &channel_name, data, headers, addr)
+                    }
+                    WsMessage::Batch(mut batch) => {
+                        batch.retain(|WsBatchEntry(channel_name, _, _)| {
+                            let allowed = permissions.can_publish(channel_name);
+                            if !allowed {
+                                warn!("Peer {} not allowed to publish to {:?}", addr, channel_name);
+                            }
+                            allowed
+                        });
+                        handle_ws_batch(&channel_map, batch, addr)
+                    }
+                    WsMessage::ListChannelsReq => {
+                        handle_ws_list_channels(&channel_map, &permissions, tx, version)
+                    }
+                    WsMessage::Subscribe(channel_name)
+                        if !permissions.can_subscribe(&channel_name) =>
+                    {
+                        warn!(
+                            "Peer {} not allowed to subscribe to {:?}",
+                            addr, channel_name
+                        )
                     }
-                    WsMessage::ListChannelsReq => handle_ws_list_channels(&channel_map, tx).await,
                     WsMessage::Subscribe(channel_name) => {
-                        handle_ws_subscribe(&channel_map, &channel_name, tx, addr).await
+                        let peer = WsPeer {
+                            tx,
+                            encoding,
+                            compression,
+                            version,
+                        };
+                        handle_ws_subscribe(&channel_map, &channel_name, peer, addr)
                     }
                     WsMessage::Unsubscribe(channel_name) => {
-                        handle_ws_unsubscribe(&channel_map, &channel_name, addr).await
+                        handle_ws_unsubscribe(&channel_map, &channel_name, addr)
                     }
+                    WsMessage::Auth(_) => debug!("Peer {} already authenticated", addr),
                     _ => warn!("Unknown WsMessage received"),
                 },
                 Err(e) => {
@@ -186,12 +716,526 @@ async fn handle_connection(channel_map: ChannelMap, raw_stream: TcpStream, addr:
     });
 
     let receive_from_others = rx.map(Ok).forward(outgoing);
+    let keepalive = ping_peer(settings.keepalive, tx.clone(), Arc::clone(&last_seen), addr);
 
-    pin_mut!(broadcast_incoming, receive_from_others);
-    future::select(broadcast_incoming, receive_from_others).await;
+    pin_mut!(broadcast_incoming, receive_from_others, keepalive);
+    future::select(
+        future::select(broadcast_incoming, receive_from_others),
+        keepalive,
+    )
+    .await;
     info!("{} disconnected", &addr);
-    let mut channels = channel_map.lock().await;
-    for subscribers in channels.values_mut() {
-        subscribers.remove(&addr);
+    for mut channel in channel_map.iter_mut() {
+        channel.peers.remove(&addr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer_addr(port: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+
+    fn json_peer(tx: UnboundedSender<Message>) -> WsPeer {
+        WsPeer {
+            tx,
+            encoding: WsEncoding::Json,
+            compression: WsCompression::None,
+            version: LEGACY_PROTOCOL_VERSION,
+        }
+    }
+
+    #[test]
+    fn test_data_is_encoded_per_subscriber() {
+        let channel_map: ChannelMap = Arc::new(DashMap::new());
+        let channel = HubChannelName::try_from("topic1").unwrap();
+        let (json_tx, mut json_rx) = unbounded();
+        let (binary_tx, mut binary_rx) = unbounded();
+        let binary_peer = WsPeer {
+            tx: binary_tx,
+            encoding: WsEncoding::Binary,
+            compression: WsCompression::None,
+            version: LEGACY_PROTOCOL_VERSION,
+        };
+
+        handle_ws_data(
+            &channel_map,
+            &channel,
+            "init".parse().unwrap(),
+            HashMap::new(),
+            peer_addr(1),
+        );
+        handle_ws_subscribe(&channel_map, &channel, json_peer(json_tx), peer_addr(2));
+        handle_ws_subscribe(&channel_map, &channel, binary_peer, peer_addr(3));
+        handle_ws_data(
+            &channel_map,
+            &channel,
+            "data".parse().unwrap(),
+            HashMap::new(),
+            peer_addr(1),
+        );
+
+        assert!(json_rx.try_next().unwrap().unwrap().is_text());
+        let frame = binary_rx.try_next().unwrap().unwrap();
+        assert!(frame.is_binary());
+        assert!(
+            matches!(WsEncoding::Binary.decode(frame).unwrap(), WsMessage::Data(ch, _, _) if ch == channel)
+        );
+    }
+
+    #[test]
+    fn test_data_is_broadcast_to_other_subscribers() {
+        let channel_map: ChannelMap = Arc::new(DashMap::new());
+        let channel = HubChannelName::try_from("topic1").unwrap();
+        let (tx1, mut rx1) = unbounded();
+        let (tx2, mut rx2) = unbounded();
+
+        handle_ws_data(
+            &channel_map,
+            &channel,
+            "init".parse().unwrap(),
+            HashMap::new(),
+            peer_addr(1),
+        );
+        handle_ws_subscribe(&channel_map, &channel, json_peer(tx1), peer_addr(1));
+        handle_ws_subscribe(&channel_map, &channel, json_peer(tx2), peer_addr(2));
+        handle_ws_data(
+            &channel_map,
+            &channel,
+            "data".parse().unwrap(),
+            HashMap::new(),
+            peer_addr(1),
+        );
+
+        assert!(rx1.try_next().is_err());
+        let message = rx2.try_next().unwrap().unwrap();
+        let ws_message = WsMessage::try_from(message.to_text().unwrap().to_string()).unwrap();
+        assert!(matches!(ws_message, WsMessage::Data(ch, _, _) if ch == channel));
+    }
+
+    #[test]
+    fn test_data_is_sent_in_negotiated_envelope() {
+        use crate::models::hub::PROTOCOL_VERSION;
+
+        let channel_map: ChannelMap = Arc::new(DashMap::new());
+        let channel = HubChannelName::try_from("topic1").unwrap();
+        let (legacy_tx, mut legacy_rx) = unbounded();
+        let (versioned_tx, mut versioned_rx) = unbounded();
+        let versioned_peer = WsPeer {
+            tx: versioned_tx,
+            encoding: WsEncoding::Json,
+            compression: WsCompression::None,
+            version: PROTOCOL_VERSION,
+        };
+
+        handle_ws_data(
+            &channel_map,
+            &channel,
+            "init".parse().unwrap(),
+            HashMap::new(),
+            peer_addr(3),
+        );
+        handle_ws_subscribe(&channel_map, &channel, json_peer(legacy_tx), peer_addr(1));
+        handle_ws_subscribe(&channel_map, &channel, versioned_peer, peer_addr(2));
+        handle_ws_data(
+            &channel_map,
+            &channel,
+            "data".parse().unwrap(),
+            HashMap::new(),
+            peer_addr(3),
+        );
+
+        let legacy = legacy_rx.try_next().unwrap().unwrap();
+        assert!(legacy.to_text().unwrap().starts_with(r#"{"Data""#));
+        let versioned = versioned_rx.try_next().unwrap().unwrap();
+        assert!(versioned.to_text().unwrap().starts_with(r#"{"v":1,"#));
+        let ws_message = WsMessage::try_from(versioned.to_text().unwrap().to_string()).unwrap();
+        assert!(matches!(ws_message, WsMessage::Data(ch, _, _) if ch == channel));
+    }
+
+    #[test]
+    fn test_batch_is_regrouped_per_subscriber() {
+        let channel_map: ChannelMap = Arc::new(DashMap::new());
+        let channel1 = HubChannelName::try_from("topic1").unwrap();
+        let channel2 = HubChannelName::try_from("topic2").unwrap();
+        let (tx, mut rx) = unbounded();
+
+        handle_ws_data(
+            &channel_map,
+            &channel1,
+            "init".parse().unwrap(),
+            HashMap::new(),
+            peer_addr(1),
+        );
+        handle_ws_data(
+            &channel_map,
+            &channel2,
+            "init".parse().unwrap(),
+            HashMap::new(),
+            peer_addr(1),
+        );
+        handle_ws_subscribe(&channel_map, &channel1, json_peer(tx.clone()), peer_addr(2));
+        handle_ws_subscribe(&channel_map, &channel2, json_peer(tx), peer_addr(2));
+        handle_ws_batch(
+            &channel_map,
+            vec![
+                WsBatchEntry(channel1.clone(), "data1".parse().unwrap(), HashMap::new()),
+                WsBatchEntry(channel2.clone(), "data2".parse().unwrap(), HashMap::new()),
+            ],
+            peer_addr(1),
+        );
+
+        let message = rx.try_next().unwrap().unwrap();
+        let ws_message = WsMessage::try_from(message.to_text().unwrap().to_string()).unwrap();
+        assert!(matches!(ws_message, WsMessage::Batch(batch) if batch.len() == 2));
+        assert!(rx.try_next().is_err());
+    }
+
+    #[test]
+    fn test_subscribe_before_data() {
+        let channel_map: ChannelMap = Arc::new(DashMap::new());
+        let channel = HubChannelName::try_from("topic1").unwrap();
+        let (tx, mut rx) = unbounded();
+
+        handle_ws_subscribe(&channel_map, &channel, json_peer(tx), peer_addr(2));
+        handle_ws_data(
+            &channel_map,
+            &channel,
+            "data".parse().unwrap(),
+            HashMap::new(),
+            peer_addr(1),
+        );
+        assert!(rx.try_next().unwrap().unwrap().is_text());
+    }
+
+    #[test]
+    fn test_unsubscribe_and_list_channels() {
+        let channel_map: ChannelMap = Arc::new(DashMap::new());
+        let channel = HubChannelName::try_from("topic1").unwrap();
+        let (tx, mut rx) = unbounded();
+
+        handle_ws_data(
+            &channel_map,
+            &channel,
+            "init".parse().unwrap(),
+            HashMap::new(),
+            peer_addr(1),
+        );
+        handle_ws_subscribe(&channel_map, &channel, json_peer(tx.clone()), peer_addr(2));
+        handle_ws_unsubscribe(&channel_map, &channel, peer_addr(2));
+        assert!(channel_map.get(&channel).unwrap().peers.is_empty());
+
+        handle_ws_list_channels(
+            &channel_map,
+            &WsPermissions::all(),
+            tx,
+            LEGACY_PROTOCOL_VERSION,
+        );
+        let message = rx.try_next().unwrap().unwrap();
+        let ws_message = WsMessage::try_from(message.to_text().unwrap().to_string()).unwrap();
+        assert!(
+            matches!(ws_message, WsMessage::ListChannelsResponse(channels) if channels == vec![channel.clone()])
+        );
+    }
+
+    #[test]
+    fn test_large_frames_are_compressed_per_subscriber() {
+        let channel_map: ChannelMap = Arc::new(DashMap::new());
+        let channel = HubChannelName::try_from("camera").unwrap();
+        let (json_tx, mut json_rx) = unbounded();
+        let (lz4_tx, mut lz4_rx) = unbounded();
+        let lz4_peer = WsPeer {
+            tx: lz4_tx,
+            encoding: WsEncoding::Json,
+            compression: WsCompression::Lz4,
+            version: LEGACY_PROTOCOL_VERSION,
+        };
+        handle_ws_data(
+            &channel_map,
+            &channel,
+            "init".parse().unwrap(),
+            HashMap::new(),
+            peer_addr(1),
+        );
+        handle_ws_subscribe(&channel_map, &channel, json_peer(json_tx), peer_addr(2));
+        handle_ws_subscribe(&channel_map, &channel, lz4_peer, peer_addr(3));
+
+        let frame = "0,".repeat(4096);
+        handle_ws_data(
+            &channel_map,
+            &channel,
+            frame.parse().unwrap(),
+            HashMap::new(),
+            peer_addr(1),
+        );
+
+        let json_frame = json_rx.try_next().unwrap().unwrap();
+        let lz4_frame = lz4_rx.try_next().unwrap().unwrap();
+        assert!(json_frame.is_text());
+        assert!(lz4_frame.is_binary());
+        assert!(lz4_frame.len() < json_frame.len());
+        assert!(matches!(
+            WsEncoding::Json.decode(lz4_frame).unwrap(),
+            WsMessage::Data(_, data, _) if data.as_str() == frame
+        ));
+    }
+
+    #[test]
+    fn test_idle_channels_are_removed() {
+        let channel_map: ChannelMap = Arc::new(DashMap::new());
+        let idle = HubChannelName::try_from("sensor/7").unwrap();
+        let subscribed = HubChannelName::try_from("topic1").unwrap();
+        let (tx, _rx) = unbounded();
+
+        handle_ws_data(
+            &channel_map,
+            &idle,
+            "1".parse().unwrap(),
+            HashMap::new(),
+            peer_addr(1),
+        );
+        handle_ws_data(
+            &channel_map,
+            &subscribed,
+            "1".parse().unwrap(),
+            HashMap::new(),
+            peer_addr(1),
+        );
+        handle_ws_subscribe(&channel_map, &subscribed, json_peer(tx), peer_addr(2));
+
+        let ttl = Duration::from_millis(20);
+        remove_idle_channels(&channel_map, ttl);
+        assert_eq!(channel_map.len(), 2);
+
+        std::thread::sleep(ttl);
+        remove_idle_channels(&channel_map, ttl);
+        assert!(channel_map.get(&idle).is_none());
+        assert!(channel_map.get(&subscribed).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_stop_closes_connections() {
+        use tokio_tungstenite::connect_async;
+
+        let server = WebSocketServer::new("127.0.0.1:0");
+        let addr = server.start().await.unwrap();
+        let url = format!("ws://{}", addr);
+        let (mut client, _) = connect_async(url.as_str()).await.unwrap();
+
+        server.stop();
+        assert!(!matches!(client.next().await, Some(Ok(_))));
+        tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+        assert!(connect_async(url.as_str()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_auth() {
+        use futures_util::SinkExt;
+        use tokio_tungstenite::connect_async;
+
+        let cmd_vel = HubChannelName::try_from("cmd_vel").unwrap();
+        let auth = WsAuth::new()
+            .with_token("operator", WsPermissions::all())
+            .with_token("viewer", WsPermissions::all().with_publish([]))
+            .with_timeout(Duration::from_millis(200));
+        let server = WebSocketServer::new("127.0.0.1:0").with_auth(auth);
+        let addr = server.start().await.unwrap();
+        let url = format!("ws://{}", addr);
+        let text = |message: WsMessage| Message::Text(message.to_string().unwrap());
+        let is_closed = |message: Option<Result<Message, _>>| {
+            matches!(message, None | Some(Err(_)) | Some(Ok(Message::Close(_))))
+        };
+
+        // wrong token, no token, and silent peers are disconnected
+        let (mut client, _) = connect_async(url.as_str()).await.unwrap();
+        client
+            .send(text(WsMessage::auth("intruder")))
+            .await
+            .unwrap();
+        assert!(is_closed(client.next().await));
+        let (mut client, _) = connect_async(url.as_str()).await.unwrap();
+        client
+            .send(text(WsMessage::list_channels_req()))
+            .await
+            .unwrap();
+        assert!(is_closed(client.next().await));
+        let (mut client, _) = connect_async(url.as_str()).await.unwrap();
+        assert!(is_closed(client.next().await));
+
+        // viewer can't publish
+        let (mut viewer, _) = connect_async(url.as_str()).await.unwrap();
+        viewer.send(text(WsMessage::auth("viewer"))).await.unwrap();
+        viewer
+            .send(text(WsMessage::send_data("cmd_vel", "1,0").unwrap()))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!server.channel_map.contains_key(&cmd_vel));
+
+        let (mut operator, _) = connect_async(url.as_str()).await.unwrap();
+        operator
+            .send(text(WsMessage::auth("operator")))
+            .await
+            .unwrap();
+        operator
+            .send(text(WsMessage::send_data("cmd_vel", "1,0").unwrap()))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(server.channel_map.contains_key(&cmd_vel));
+        server.stop();
+    }
+
+    #[tokio::test]
+    async fn test_peer_stalling_handshake_is_disconnected() {
+        use tokio::io::AsyncReadExt;
+
+        let auth = WsAuth::new()
+            .with_token("operator", WsPermissions::all())
+            .with_timeout(Duration::from_millis(100));
+        let server = WebSocketServer::new("127.0.0.1:0").with_auth(auth);
+        let addr = server.start().await.unwrap();
+
+        // the peer connects, but never sends its handshake request
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let read = tokio::time::timeout(Duration::from_secs(1), stream.read(&mut [0u8; 16]))
+            .await
+            .expect("peer not disconnected");
+        assert!(matches!(read, Ok(0) | Err(_)));
+        server.stop();
+    }
+
+    #[test]
+    fn test_list_channels_filtered_by_permissions() {
+        let channel_map: ChannelMap = Arc::new(DashMap::new());
+        let imu = HubChannelName::try_from("imu").unwrap();
+        let cmd_vel = HubChannelName::try_from("cmd_vel").unwrap();
+        for channel in [&imu, &cmd_vel] {
+            handle_ws_data(
+                &channel_map,
+                channel,
+                "1".parse().unwrap(),
+                HashMap::new(),
+                peer_addr(1),
+            );
+        }
+        let (tx, mut rx) = unbounded();
+        let permissions = WsPermissions::all().with_subscribe([imu.clone()]);
+
+        handle_ws_list_channels(&channel_map, &permissions, tx, LEGACY_PROTOCOL_VERSION);
+        let message = rx.try_next().unwrap().unwrap();
+        let ws_message = WsMessage::try_from(message.to_text().unwrap().to_string()).unwrap();
+        assert!(
+            matches!(ws_message, WsMessage::ListChannelsResponse(channels) if channels == vec![imu])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_keepalive_removes_dead_peers() {
+        use futures_util::SinkExt;
+        use tokio_tungstenite::connect_async;
+
+        let keepalive = WsKeepalive::new(Duration::from_millis(50), Duration::from_millis(200));
+        let server = WebSocketServer::new("127.0.0.1:0").with_keepalive(keepalive.unwrap());
+        let addr = server.start().await.unwrap();
+        let url = format!("ws://{}", addr);
+        let text = |message: WsMessage| Message::Text(message.to_string().unwrap());
+        let channel = HubChannelName::try_from("topic1").unwrap();
+        let subscribers = || server.channel_map.get(&channel).unwrap().peers.len();
+
+        // the client never reads, so pings are never answered
+        let (mut client, _) = connect_async(url.as_str()).await.unwrap();
+        client
+            .send(text(WsMessage::send_data("topic1", "init").unwrap()))
+            .await
+            .unwrap();
+        client
+            .send(text(WsMessage::subscribe("topic1").unwrap()))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(subscribers(), 1);
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(subscribers(), 0);
+        server.stop();
+    }
+
+    #[cfg(feature = "tls")]
+    #[tokio::test]
+    async fn test_tls_connection() {
+        use crate::adapters::websocket::tls::WsTrustedCerts;
+        use futures_util::SinkExt;
+        use tokio_tungstenite::{connect_async, connect_async_tls_with_config};
+
+        let certificate = WsCertificate::self_signed(&["localhost"]).unwrap();
+        let trusted = WsTrustedCerts::new(vec![certificate.certificate().unwrap().clone()]);
+        let server = WebSocketServer::new("127.0.0.1:0").with_tls(certificate);
+        let addr = server.start().await.unwrap();
+
+        let url = format!("wss://localhost:{}", addr.port());
+        let connector = trusted.connector().unwrap();
+        let (mut client, _) =
+            connect_async_tls_with_config(url.as_str(), None, false, Some(connector))
+                .await
+                .unwrap();
+        client
+            .send(Message::Text(
+                WsMessage::list_channels_req().to_string().unwrap(),
+            ))
+            .await
+            .unwrap();
+        assert!(matches!(client.next().await, Some(Ok(Message::Text(_)))));
+
+        // certificates not trusted and plain connections are rejected
+        assert!(connect_async(url.as_str()).await.is_err());
+        let url = format!("ws://{}", addr);
+        let plain = connect_async(url.as_str()).await;
+        assert!(plain.is_err());
+        server.stop();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_multiple_acceptors_share_channels() {
+        use futures_util::SinkExt;
+        use tokio_tungstenite::connect_async;
+
+        let server = WebSocketServer::new("127.0.0.1:0").with_acceptors(2);
+        let addr = server.start().await.unwrap();
+        let url = format!("ws://{}", addr);
+
+        let mut clients = Vec::new();
+        for _ in 0..4 {
+            let (ws_stream, _) = connect_async(url.as_str()).await.unwrap();
+            clients.push(ws_stream);
+        }
+        let text = |message: WsMessage| Message::Text(message.to_string().unwrap());
+
+        clients[0]
+            .send(text(WsMessage::send_data("topic1", "init").unwrap()))
+            .await
+            .unwrap();
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+        for client in clients.iter_mut().skip(1) {
+            client
+                .send(text(WsMessage::subscribe("topic1").unwrap()))
+                .await
+                .unwrap();
+        }
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+        clients[0]
+            .send(text(WsMessage::send_data("topic1", "data").unwrap()))
+            .await
+            .unwrap();
+
+        for client in clients.iter_mut().skip(1) {
+            let frame = client.next().await.unwrap().unwrap();
+            assert!(matches!(
+                WsEncoding::Binary.decode(frame).unwrap(),
+                WsMessage::Data(_, data, _) if data.as_str() == "data"
+            ));
+        }
     }
 }
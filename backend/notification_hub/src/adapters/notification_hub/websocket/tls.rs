@@ -0,0 +1,127 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use tokio_rustls::rustls::{ClientConfig, RootCertStore, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+use tokio_tungstenite::Connector;
+
+fn tls_error(e: impl std::fmt::Display) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string())
+}
+
+// Reads the PEM encoded certificates in `path`
+fn read_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, std::io::Error> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let certs = rustls_pemfile::certs(&mut reader).collect::<Result<Vec<_>, _>>()?;
+    if certs.is_empty() {
+        return Err(tls_error(format!("No certificate in {}", path.display())));
+    }
+    Ok(certs)
+}
+
+/// TLS certificate chain and private key of a `WebSocketServer`, so that peers connect with
+/// `wss://` and messages aren't sent in cleartext (e.g. joystick commands over Wi-Fi).
+#[derive(Debug)]
+pub struct WsCertificate {
+    chain: Vec<CertificateDer<'static>>,
+    key: PrivateKeyDer<'static>,
+}
+
+impl WsCertificate {
+    pub fn new(chain: Vec<CertificateDer<'static>>, key: PrivateKeyDer<'static>) -> Self {
+        Self { chain, key }
+    }
+
+    /// Reads the PEM encoded certificate chain in `cert_path`, and private key in `key_path`
+    pub fn from_pem(
+        cert_path: impl AsRef<Path>,
+        key_path: impl AsRef<Path>,
+    ) -> Result<Self, std::io::Error> {
+        let chain = read_certs(cert_path.as_ref())?;
+        let mut reader = BufReader::new(File::open(key_path.as_ref())?);
+        let key = rustls_pemfile::private_key(&mut reader)?.ok_or_else(|| {
+            tls_error(format!("No private key in {}", key_path.as_ref().display()))
+        })?;
+        Ok(Self::new(chain, key))
+    }
+
+    /// Generates a self signed certificate valid for `names` (e.g. `["robot1.local"]`).
+    /// Clients trust it by passing `certificate()` to `WsTrustedCerts::new`.
+    pub fn self_signed(names: &[&str]) -> Result<Self, String> {
+        let names: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+        let certified = rcgen::generate_simple_self_signed(names).map_err(|e| e.to_string())?;
+        let key = PrivatePkcs8KeyDer::from(certified.key_pair.serialize_der());
+        Ok(Self::new(vec![certified.cert.der().clone()], key.into()))
+    }
+
+    /// End entity certificate
+    pub fn certificate(&self) -> Option<&CertificateDer<'static>> {
+        self.chain.first()
+    }
+
+    // Acceptor of TLS connections presenting this certificate
+    pub(crate) fn acceptor(&self) -> Result<TlsAcceptor, std::io::Error> {
+        let config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(tls_error)?
+            .with_no_client_auth()
+            .with_single_cert(self.chain.clone(), self.key.clone_key())
+            .map_err(tls_error)?;
+        Ok(TlsAcceptor::from(Arc::new(config)))
+    }
+}
+
+/// Certificates trusted by a `WebSocketClient` connecting to a `wss://` server, instead of
+/// the public web PKI roots. Typically the self signed certificate of a robot, or the
+/// certificate authority of a fleet.
+#[derive(Debug, Clone)]
+pub struct WsTrustedCerts {
+    certs: Vec<CertificateDer<'static>>,
+}
+
+impl WsTrustedCerts {
+    pub fn new(certs: Vec<CertificateDer<'static>>) -> Self {
+        Self { certs }
+    }
+
+    /// Reads the PEM encoded certificates in `path`
+    pub fn from_pem(path: impl AsRef<Path>) -> Result<Self, std::io::Error> {
+        Ok(Self::new(read_certs(path.as_ref())?))
+    }
+
+    // Connector of TLS connections trusting only these certificates
+    pub(crate) fn connector(&self) -> Result<Connector, std::io::Error> {
+        let mut roots = RootCertStore::empty();
+        for cert in &self.certs {
+            roots.add(cert.clone()).map_err(tls_error)?;
+        }
+        let config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(tls_error)?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        Ok(Connector::Rustls(Arc::new(config)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_self_signed() {
+        let certificate = WsCertificate::self_signed(&["localhost"]).unwrap();
+        assert!(certificate.acceptor().is_ok());
+        let trusted = WsTrustedCerts::new(vec![certificate.certificate().unwrap().clone()]);
+        assert!(trusted.connector().is_ok());
+    }
+
+    #[test]
+    fn test_missing_pem() {
+        assert!(WsCertificate::from_pem("/nonexistent/cert.pem", "/nonexistent/key.pem").is_err());
+        assert!(WsTrustedCerts::from_pem("/nonexistent/ca.pem").is_err());
+    }
+}
@@ -0,0 +1,217 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use log::{debug, error, info, warn};
+use serialport::SerialPort;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf};
+use tokio::sync::{broadcast, Mutex, RwLock};
+use tokio_serial::{DataBits, Parity, SerialPortBuilderExt, SerialStream, StopBits};
+
+use super::frame::{ApiFrame, FrameParser};
+use super::options::XBeeOptions;
+use crate::adapters::lease::LeaseSlot;
+use crate::adapters::serial::message::SerialRawMessage;
+use crate::models::hub::{HubChannelName, HubMessage};
+use crate::ports::{NodeTasks, NotificationHub};
+
+/// `XBeeClient` is a hub node sharing channels between robots of a ZigBee mesh, through an
+/// XBee module in API mode.
+///
+/// Messages are sent as transmit request frames, with the `##CHANNEL## DATA` line format of
+/// serial ports as payload, to the remote nodes the channel is routed to. Messages received
+/// from any node are forwarded to the hub.
+///
+/// # Fields
+/// - `options`: API mode, payload size and routes.
+/// - `reader`: Read half of the serial port. It is leased by the read loop when the client is
+///   started.
+/// - `writer`: Write half of the serial port.
+/// - `frame_id`: Id of the last transmit request, to match transmit statuses.
+/// - `channels`: Channels received so far.
+/// - `remote_nodes`: 64 bit addresses of the nodes messages were received from.
+#[derive(Debug)]
+pub struct XBeeClient {
+    options: Arc<XBeeOptions>,
+    reader: LeaseSlot<ReadHalf<SerialStream>>,
+    writer: Mutex<WriteHalf<SerialStream>>,
+    frame_id: AtomicU8,
+    channels: Arc<RwLock<HashSet<HubChannelName>>>,
+    remote_nodes: Arc<RwLock<HashSet<u64>>>,
+}
+
+impl XBeeClient {
+    pub fn new(port: &str, baud_rate: u32, options: XBeeOptions) -> Result<Self, std::io::Error> {
+        info!(
+            "Opening XBee module at {} with params {}...",
+            port, baud_rate
+        );
+        let mut port = tokio_serial::new(port, baud_rate)
+            .open_native_async()
+            .inspect_err(|_| {
+                error!("XBee module at {} not ready", port);
+            })?;
+        port.set_parity(Parity::None)?;
+        port.set_stop_bits(StopBits::One)?;
+        port.set_data_bits(DataBits::Eight)?;
+        let (reader, writer) = tokio::io::split(port);
+        Ok(Self {
+            options: Arc::new(options),
+            reader: LeaseSlot::new(reader),
+            writer: Mutex::new(writer),
+            frame_id: AtomicU8::new(0),
+            channels: Arc::new(RwLock::new(HashSet::new())),
+            remote_nodes: Arc::new(RwLock::new(HashSet::new())),
+        })
+    }
+
+    pub fn options(&self) -> &XBeeOptions {
+        &self.options
+    }
+
+    /// 64 bit addresses of the nodes messages were received from
+    pub async fn remote_nodes(&self) -> Vec<u64> {
+        self.remote_nodes.read().await.iter().copied().collect()
+    }
+
+    // Frame id 0 disables the transmit status, so ids cycle from 1 to 255
+    fn next_frame_id(&self) -> u8 {
+        let frame_id = self
+            .frame_id
+            .fetch_add(1, Ordering::Relaxed)
+            .wrapping_add(1);
+        if frame_id == 0 {
+            self.next_frame_id()
+        } else {
+            frame_id
+        }
+    }
+}
+
+#[async_trait]
+impl NotificationHub for XBeeClient {
+    /// Send message to the remote nodes its channel is routed to
+    async fn send(&self, data: HubMessage) -> Result<(), std::io::Error> {
+        let destinations = self.options.destinations(&data.channel);
+        if destinations.is_empty() {
+            return Ok(());
+        }
+        let payload = SerialRawMessage::from(data);
+        let payload = payload.as_str().as_bytes();
+        if payload.len() > self.options.max_payload() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Message exceeds XBee max payload",
+            ));
+        }
+        let mut writer = self.writer.lock().await;
+        for destination in destinations {
+            let frame = ApiFrame::TransmitRequest {
+                frame_id: self.next_frame_id(),
+                destination,
+                data: payload.to_vec(),
+            }
+            .encode(self.options.escaped())
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+            writer.write_all(&frame).await?;
+        }
+        Ok(())
+    }
+
+    /// List channels received so far
+    async fn list_channels(&self) -> Result<Vec<HubChannelName>, std::io::Error> {
+        Ok(self.channels.read().await.iter().cloned().collect())
+    }
+
+    /// Start receive loop
+    async fn start(
+        &self,
+        sender: Option<broadcast::Sender<HubMessage>>,
+    ) -> Result<NodeTasks, std::io::Error> {
+        let mut tasks = NodeTasks::new();
+        if let Some(sender) = sender {
+            let mut reader = self.reader.lease("XBee module")?;
+            let escaped = self.options.escaped();
+            let channels = Arc::clone(&self.channels);
+            let remote_nodes = Arc::clone(&self.remote_nodes);
+            let task = tokio::spawn(async move {
+                let mut parser = FrameParser::new(escaped);
+                let mut buffer = [0u8; 256];
+                loop {
+                    match reader.read(&mut buffer).await {
+                        Ok(n) if n > 0 => parser.push(&buffer[..n]),
+                        Ok(_) => {
+                            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+                            continue;
+                        }
+                        Err(e) => {
+                            error!("XBee module error {:?}", e);
+                            break;
+                        }
+                    }
+                    while let Some(frame) = parser.next_frame() {
+                        match frame {
+                            Ok(ApiFrame::ReceivePacket { source, data }) => {
+                                remote_nodes.write().await.insert(source);
+                                let raw_message = SerialRawMessage::from_bytes(Bytes::from(data));
+                                match HubMessage::try_from(raw_message) {
+                                    Ok(message) => {
+                                        channels.write().await.insert(message.channel.clone());
+                                        let message = message
+                                            .with_source("xbee", format_args!("{:#018x}", source));
+                                        let _ = sender.send(message);
+                                    }
+                                    Err(e) => {
+                                        warn!("Invalid XBee payload from {:#x}: {}", source, e)
+                                    }
+                                }
+                            }
+                            Ok(ApiFrame::TransmitStatus {
+                                frame_id,
+                                delivery_status,
+                            }) if delivery_status != 0 => warn!(
+                                "XBee transmit request {} failed with status {:#04x}",
+                                frame_id, delivery_status
+                            ),
+                            Ok(frame) => debug!("XBee frame {:?} ignored", frame),
+                            Err(e) => warn!("{}", e),
+                        }
+                    }
+                }
+            });
+            tasks.push(task);
+        }
+        Ok(tasks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::xbee::XBeeOptionsBuilder;
+    use tokio::time::{timeout, Duration};
+
+    // Requires two XBee modules in API mode joined to the same ZigBee network
+    #[tokio::test]
+    #[ignore]
+    async fn test_xbee_mesh() {
+        let options = || XBeeOptionsBuilder::new().build().unwrap();
+        let node1 = XBeeClient::new("/dev/ttyUSB0", 9600, options()).unwrap();
+        let node2 = XBeeClient::new("/dev/ttyUSB1", 9600, options()).unwrap();
+        let (sender, mut receiver) = broadcast::channel(10);
+        node2.start(Some(sender)).await.unwrap();
+
+        node1
+            .send(HubMessage::try_from_str("robot1/pose", "1,2,0.5").unwrap())
+            .await
+            .unwrap();
+        let received = timeout(Duration::from_secs(5), receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(received.channel.as_str(), "robot1/pose");
+        assert_eq!(received.data.as_str(), "1,2,0.5");
+        assert_eq!(node2.remote_nodes().await.len(), 1);
+    }
+}
@@ -0,0 +1,317 @@
+/// Start of every API frame
+pub const START_DELIMITER: u8 = 0x7e;
+/// 64 bit address reaching every node of the network
+pub const BROADCAST_ADDRESS: u64 = 0xffff;
+
+const ESCAPE: u8 = 0x7d;
+const XOR: u8 = 0x20;
+// Bytes escaped in API mode 2
+const ESCAPED: [u8; 4] = [START_DELIMITER, ESCAPE, 0x11, 0x13];
+const UNKNOWN_ADDRESS16: u16 = 0xfffe;
+
+const TRANSMIT_REQUEST: u8 = 0x10;
+const TRANSMIT_STATUS: u8 = 0x8b;
+const RECEIVE_PACKET: u8 = 0x90;
+
+/// XBee API frame.
+///
+/// - `TransmitRequest`: Sends `data` to the node with 64 bit address `destination`. Frame id 0
+///   disables the transmit status.
+/// - `TransmitStatus`: Delivery status of the transmit request with `frame_id`. Status 0 is
+///   success.
+/// - `ReceivePacket`: `data` received from the node with 64 bit address `source`.
+/// - `Other`: Frames not used by the hub node, e.g. AT command responses.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ApiFrame {
+    TransmitRequest {
+        frame_id: u8,
+        destination: u64,
+        data: Vec<u8>,
+    },
+    TransmitStatus {
+        frame_id: u8,
+        delivery_status: u8,
+    },
+    ReceivePacket {
+        source: u64,
+        data: Vec<u8>,
+    },
+    Other {
+        frame_type: u8,
+        data: Vec<u8>,
+    },
+}
+
+fn checksum(frame_data: &[u8]) -> u8 {
+    0xff - frame_data
+        .iter()
+        .fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    let mut address = [0; 8];
+    address.copy_from_slice(&bytes[..8]);
+    u64::from_be_bytes(address)
+}
+
+impl ApiFrame {
+    fn frame_data(&self) -> Vec<u8> {
+        match self {
+            Self::TransmitRequest {
+                frame_id,
+                destination,
+                data,
+            } => {
+                let mut frame_data = vec![TRANSMIT_REQUEST, *frame_id];
+                frame_data.extend_from_slice(&destination.to_be_bytes());
+                frame_data.extend_from_slice(&UNKNOWN_ADDRESS16.to_be_bytes());
+                // max broadcast radius, default transmit options
+                frame_data.extend_from_slice(&[0, 0]);
+                frame_data.extend_from_slice(data);
+                frame_data
+            }
+            Self::TransmitStatus {
+                frame_id,
+                delivery_status,
+            } => {
+                let mut frame_data = vec![TRANSMIT_STATUS, *frame_id];
+                frame_data.extend_from_slice(&UNKNOWN_ADDRESS16.to_be_bytes());
+                frame_data.extend_from_slice(&[0, *delivery_status, 0]);
+                frame_data
+            }
+            Self::ReceivePacket { source, data } => {
+                let mut frame_data = vec![RECEIVE_PACKET];
+                frame_data.extend_from_slice(&source.to_be_bytes());
+                frame_data.extend_from_slice(&UNKNOWN_ADDRESS16.to_be_bytes());
+                frame_data.push(0);
+                frame_data.extend_from_slice(data);
+                frame_data
+            }
+            Self::Other { frame_type, data } => {
+                let mut frame_data = vec![*frame_type];
+                frame_data.extend_from_slice(data);
+                frame_data
+            }
+        }
+    }
+
+    /// Parses frame data, from frame type to the byte preceding the checksum
+    pub fn parse(frame_data: &[u8]) -> Result<Self, String> {
+        let (frame_type, data) = frame_data
+            .split_first()
+            .ok_or_else(|| "Empty XBee frame".to_string())?;
+        let too_short = || format!("XBee frame {:#04x} too short", frame_type);
+        match *frame_type {
+            TRANSMIT_REQUEST => {
+                if data.len() < 13 {
+                    return Err(too_short());
+                }
+                Ok(Self::TransmitRequest {
+                    frame_id: data[0],
+                    destination: read_u64(&data[1..]),
+                    data: data[13..].to_vec(),
+                })
+            }
+            TRANSMIT_STATUS => {
+                if data.len() < 5 {
+                    return Err(too_short());
+                }
+                Ok(Self::TransmitStatus {
+                    frame_id: data[0],
+                    delivery_status: data[4],
+                })
+            }
+            RECEIVE_PACKET => {
+                if data.len() < 11 {
+                    return Err(too_short());
+                }
+                Ok(Self::ReceivePacket {
+                    source: read_u64(data),
+                    data: data[11..].to_vec(),
+                })
+            }
+            frame_type => Ok(Self::Other {
+                frame_type,
+                data: data.to_vec(),
+            }),
+        }
+    }
+
+    /// Encodes frame with delimiter, length and checksum. In `escaped` mode (API mode 2),
+    /// reserved bytes following the delimiter are escaped.
+    pub fn encode(&self, escaped: bool) -> Result<Vec<u8>, String> {
+        let frame_data = self.frame_data();
+        let len = u16::try_from(frame_data.len()).map_err(|_| "XBee frame too long".to_string())?;
+        let mut body = Vec::with_capacity(frame_data.len() + 3);
+        body.extend_from_slice(&len.to_be_bytes());
+        body.extend_from_slice(&frame_data);
+        body.push(checksum(&frame_data));
+
+        let mut frame = Vec::with_capacity(body.len() + 1);
+        frame.push(START_DELIMITER);
+        for byte in body {
+            if escaped && ESCAPED.contains(&byte) {
+                frame.extend_from_slice(&[ESCAPE, byte ^ XOR]);
+            } else {
+                frame.push(byte);
+            }
+        }
+        Ok(frame)
+    }
+}
+
+/// Extracts API frames from the byte stream received from the module. Bytes preceding a start
+/// delimiter are discarded, so that the parser recovers from corrupted frames.
+#[derive(Debug, Default)]
+pub struct FrameParser {
+    escaped: bool,
+    pending_escape: bool,
+    buffer: Vec<u8>,
+}
+
+impl FrameParser {
+    pub fn new(escaped: bool) -> Self {
+        Self {
+            escaped,
+            ..Self::default()
+        }
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) {
+        if !self.escaped {
+            self.buffer.extend_from_slice(bytes);
+            return;
+        }
+        for byte in bytes {
+            if *byte == START_DELIMITER {
+                // a delimiter is never escaped, and starts a new frame
+                self.pending_escape = false;
+                self.buffer.push(*byte);
+            } else if self.pending_escape {
+                self.pending_escape = false;
+                self.buffer.push(byte ^ XOR);
+            } else if *byte == ESCAPE {
+                self.pending_escape = true;
+            } else {
+                self.buffer.push(*byte);
+            }
+        }
+    }
+
+    /// Next frame, or `None` until a full frame is buffered
+    pub fn next_frame(&mut self) -> Option<Result<ApiFrame, String>> {
+        let start = match self.buffer.iter().position(|byte| *byte == START_DELIMITER) {
+            Some(start) => start,
+            None => {
+                self.buffer.clear();
+                return None;
+            }
+        };
+        self.buffer.drain(..start);
+        if self.buffer.len() < 3 {
+            return None;
+        }
+        let len = u16::from_be_bytes([self.buffer[1], self.buffer[2]]) as usize + 4;
+        if self.buffer.len() < len {
+            return None;
+        }
+        let frame_data = &self.buffer[3..len - 1];
+        if checksum(frame_data) != self.buffer[len - 1] {
+            // drop delimiter, and look for the next one
+            self.buffer.drain(..1);
+            return Some(Err("Invalid XBee frame checksum".to_string()));
+        }
+        let frame = ApiFrame::parse(frame_data);
+        self.buffer.drain(..len);
+        Some(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        // transmit request example of the XBee manual
+        let frame = ApiFrame::TransmitRequest {
+            frame_id: 1,
+            destination: 0x0013_a200_400a_0127,
+            data: b"TxData0A".to_vec(),
+        };
+        let mut expected = vec![
+            0x7e, 0x00, 0x16, 0x10, 0x01, 0x00, 0x13, 0xa2, 0x00, 0x40, 0x0a, 0x01, 0x27, 0xff,
+            0xfe, 0x00, 0x00,
+        ];
+        expected.extend_from_slice(b"TxData0A");
+        expected.push(0x13);
+        assert_eq!(frame.encode(false).unwrap(), expected);
+
+        // 0x13 is escaped in API mode 2
+        let escaped = frame.encode(true).unwrap();
+        assert_eq!(escaped.len(), expected.len() + 2);
+        assert_eq!(&escaped[escaped.len() - 2..], &[0x7d, 0x33]);
+    }
+
+    #[test]
+    fn test_parse() {
+        // receive packet example of the XBee manual
+        let mut bytes = vec![
+            0x7e, 0x00, 0x12, 0x90, 0x00, 0x13, 0xa2, 0x00, 0x40, 0x52, 0x2b, 0xaa, 0x7d, 0x84,
+            0x01,
+        ];
+        bytes.extend_from_slice(b"RxData");
+        bytes.push(0x0d);
+
+        let mut parser = FrameParser::new(false);
+        parser.push(&[0x00, 0x11]);
+        parser.push(&bytes[..10]);
+        assert!(parser.next_frame().is_none());
+        parser.push(&bytes[10..]);
+        assert_eq!(
+            parser.next_frame().unwrap().unwrap(),
+            ApiFrame::ReceivePacket {
+                source: 0x0013_a200_4052_2baa,
+                data: b"RxData".to_vec(),
+            }
+        );
+        assert!(parser.next_frame().is_none());
+
+        // corrupted frame followed by a valid one
+        let mut corrupted = bytes.clone();
+        corrupted[16] ^= 0xff;
+        parser.push(&corrupted);
+        parser.push(&bytes);
+        assert!(parser.next_frame().unwrap().is_err());
+        assert!(parser.next_frame().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_escaped_round_trip() {
+        let frames = [
+            ApiFrame::TransmitRequest {
+                frame_id: 0x7e,
+                destination: BROADCAST_ADDRESS,
+                data: vec![0x7d, 0x11, 0x13, 0x7e],
+            },
+            ApiFrame::TransmitStatus {
+                frame_id: 0x7e,
+                delivery_status: 0x21,
+            },
+            ApiFrame::Other {
+                frame_type: 0x88,
+                data: vec![1, 2],
+            },
+        ];
+        let mut parser = FrameParser::new(true);
+        for frame in &frames {
+            let encoded = frame.encode(true).unwrap();
+            // delimiter only at the start of the frame
+            assert!(!encoded[1..].contains(&START_DELIMITER));
+            parser.push(&encoded);
+        }
+        for frame in frames {
+            assert_eq!(parser.next_frame().unwrap().unwrap(), frame);
+        }
+    }
+}
@@ -0,0 +1,8 @@
+/// XBee transport for the notification hub, sharing channels over ZigBee meshes in API mode.
+pub mod client;
+pub mod frame;
+pub mod options;
+
+pub use client::XBeeClient;
+pub use frame::{ApiFrame, BROADCAST_ADDRESS};
+pub use options::{XBeeOptions, XBeeOptionsBuilder};
@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+
+use super::frame::BROADCAST_ADDRESS;
+use crate::models::hub::HubChannelName;
+
+// RF payload of a ZigBee transmission without fragmentation or encryption
+const DEFAULT_MAX_PAYLOAD: usize = 84;
+const MAX_PAYLOAD: usize = 255;
+
+/// `XBeeOptions` configures an `XBeeClient`.
+///
+/// # Fields
+/// - `escaped`: Whether the module runs in escaped API mode (`AP=2`). Defaults to `AP=1`.
+/// - `max_payload`: Max size in bytes of a serialized message. Larger messages are rejected.
+/// - `routes`: 64 bit addresses of the remote nodes receiving each channel.
+/// - `default_destination`: Destination of channels without a route. Defaults to broadcast,
+///   `None` drops them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct XBeeOptions {
+    escaped: bool,
+    max_payload: usize,
+    routes: HashMap<HubChannelName, Vec<u64>>,
+    default_destination: Option<u64>,
+}
+
+impl XBeeOptions {
+    pub fn escaped(&self) -> bool {
+        self.escaped
+    }
+    pub fn max_payload(&self) -> usize {
+        self.max_payload
+    }
+    pub fn default_destination(&self) -> Option<u64> {
+        self.default_destination
+    }
+
+    /// Addresses of the remote nodes receiving `channel`
+    pub fn destinations(&self, channel: &HubChannelName) -> Vec<u64> {
+        match self.routes.get(channel) {
+            Some(destinations) => destinations.clone(),
+            None => self.default_destination.into_iter().collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct XBeeOptionsBuilder {
+    escaped: Option<bool>,
+    max_payload: Option<usize>,
+    routes: Vec<(String, Vec<u64>)>,
+    default_destination: Option<Option<u64>>,
+}
+
+impl XBeeOptionsBuilder {
+    pub fn new() -> Self {
+        Self {
+            escaped: None,
+            max_payload: None,
+            routes: Vec::new(),
+            default_destination: None,
+        }
+    }
+
+    pub fn escaped(&self, escaped: bool) -> Self {
+        let mut new = self.clone();
+        new.escaped = Some(escaped);
+        new
+    }
+    pub fn max_payload(&self, max_payload: usize) -> Self {
+        let mut new = self.clone();
+        new.max_payload = Some(max_payload);
+        new
+    }
+    /// Sends `channel` to the remote nodes with 64 bit addresses `destinations`
+    pub fn route(&self, channel: &str, destinations: &[u64]) -> Self {
+        let mut new = self.clone();
+        new.routes
+            .push((channel.to_string(), destinations.to_vec()));
+        new
+    }
+    pub fn default_destination(&self, default_destination: Option<u64>) -> Self {
+        let mut new = self.clone();
+        new.default_destination = Some(default_destination);
+        new
+    }
+    pub fn build(self) -> Result<XBeeOptions, String> {
+        let max_payload = self.max_payload.unwrap_or(DEFAULT_MAX_PAYLOAD);
+        if max_payload == 0 || max_payload > MAX_PAYLOAD {
+            return Err(format!(
+                "Max payload must be between 1 and {} bytes",
+                MAX_PAYLOAD
+            ));
+        }
+        let mut routes = HashMap::new();
+        for (channel, destinations) in self.routes {
+            let channel = HubChannelName::try_from(channel.as_str())?;
+            if destinations.is_empty() {
+                return Err(format!(
+                    "Route of channel {} without nodes",
+                    channel.as_str()
+                ));
+            }
+            if routes.insert(channel.clone(), destinations).is_some() {
+                return Err(format!(
+                    "Channel {} is routed more than once",
+                    channel.as_str()
+                ));
+            }
+        }
+        Ok(XBeeOptions {
+            escaped: self.escaped.unwrap_or(false),
+            max_payload,
+            routes,
+            default_destination: self.default_destination.unwrap_or(Some(BROADCAST_ADDRESS)),
+        })
+    }
+}
+
+impl Default for XBeeOptionsBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routes() {
+        let robot1 = 0x0013_a200_4052_2baa;
+        let robot2 = 0x0013_a200_4052_2bab;
+        let options = XBeeOptionsBuilder::new()
+            .route("formation", &[robot1, robot2])
+            .build()
+            .unwrap();
+        let channel = |name: &str| HubChannelName::try_from(name).unwrap();
+        assert_eq!(
+            options.destinations(&channel("formation")),
+            vec![robot1, robot2]
+        );
+        assert_eq!(
+            options.destinations(&channel("pose")),
+            vec![BROADCAST_ADDRESS]
+        );
+        assert!(!options.escaped());
+
+        let options = XBeeOptionsBuilder::new()
+            .default_destination(None)
+            .build()
+            .unwrap();
+        assert!(options.destinations(&channel("pose")).is_empty());
+    }
+
+    #[test]
+    fn test_invalid_options() {
+        let builder = XBeeOptionsBuilder::new();
+        assert!(builder.max_payload(0).build().is_err());
+        assert!(builder.route("pose", &[]).build().is_err());
+        assert!(builder.route("Pose", &[1]).build().is_err());
+        assert!(builder
+            .route("pose", &[1])
+            .route("pose", &[2])
+            .build()
+            .is_err());
+    }
+}
@@ -0,0 +1,228 @@
+use async_trait::async_trait;
+use log::{error, info};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::{broadcast, mpsc, Mutex, RwLock};
+use tokio::task::JoinHandle;
+
+use crate::adapters::lease::LeaseSlot;
+use crate::models::hub::{HubChannelName, HubMessage};
+use crate::ports::{NodeTasks, NotificationHub};
+
+const CHANNEL_CAPACITY: usize = 100;
+const DEFAULT_KEY_PREFIX: &str = "robopilot";
+
+fn zenoh_error(e: impl std::fmt::Display) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Other, e.to_string())
+}
+
+/// `ZenohClient` is a hub node joining a Zenoh network, so that the hub interoperates with
+/// Zenoh based robotics software (e.g. ROS 2 with `rmw_zenoh`), and messages are routed peer
+/// to peer between robots and operator stations.
+///
+/// Channels are mapped to Zenoh key expressions under the key prefix: namespace separators
+/// are kept, as '/' is also the Zenoh key separator (`sensors/imu` is published to
+/// `robopilot/sensors/imu`). Messages are JSON encoded `HubMessage`s. Every channel
+/// subscription declares a Zenoh subscriber, forwarded to the hub by the receive loop.
+///
+/// # Fields
+/// - `session`: Zenoh session.
+/// - `key_prefix`: Key prefix of every channel.
+/// - `subscriptions`: Tasks forwarding Zenoh subscribers, per channel.
+/// - `incoming`: Messages received from Zenoh subscribers, leased by the receive loop.
+/// - `channels`: Channels received so far.
+#[derive(Debug)]
+pub struct ZenohClient {
+    session: ::zenoh::Session,
+    key_prefix: String,
+    subscriptions: Mutex<HashMap<HubChannelName, JoinHandle<()>>>,
+    incoming: mpsc::Sender<HubMessage>,
+    incoming_receiver: LeaseSlot<mpsc::Receiver<HubMessage>>,
+    channels: Arc<RwLock<HashSet<HubChannelName>>>,
+}
+
+impl ZenohClient {
+    /// Opens Zenoh session with `config`. The default configuration joins the local network
+    /// in peer mode, discovering other peers with multicast scouting.
+    pub async fn new(config: ::zenoh::Config) -> Result<Self, std::io::Error> {
+        let session = ::zenoh::open(config).await.map_err(zenoh_error)?;
+        info!("Zenoh session {} opened", session.zid());
+        let (incoming, incoming_receiver) = mpsc::channel(CHANNEL_CAPACITY);
+        Ok(Self {
+            session,
+            key_prefix: DEFAULT_KEY_PREFIX.to_string(),
+            subscriptions: Mutex::new(HashMap::new()),
+            incoming,
+            incoming_receiver: LeaseSlot::new(incoming_receiver),
+            channels: Arc::new(RwLock::new(HashSet::new())),
+        })
+    }
+
+    /// Sets the key prefix of every channel. Defaults to `robopilot`
+    pub fn with_key_prefix(mut self, key_prefix: &str) -> Self {
+        self.key_prefix = key_prefix.trim_end_matches('/').to_string();
+        self
+    }
+
+    fn key_expr(&self, channel: &HubChannelName) -> String {
+        channel_to_key_expr(&self.key_prefix, channel)
+    }
+}
+
+fn channel_to_key_expr(key_prefix: &str, channel: &HubChannelName) -> String {
+    format!("{}/{}", key_prefix, channel.as_str())
+}
+
+fn key_expr_to_channel(key_prefix: &str, key_expr: &str) -> Result<HubChannelName, String> {
+    let channel = key_expr
+        .strip_prefix(key_prefix)
+        .and_then(|key_expr| key_expr.strip_prefix('/'))
+        .ok_or_else(|| format!("Key expression {} is not a hub channel", key_expr))?;
+    HubChannelName::try_from(channel)
+}
+
+#[async_trait]
+impl NotificationHub for ZenohClient {
+    /// Put message on the key expression of its channel
+    async fn send(&self, data: HubMessage) -> Result<(), std::io::Error> {
+        let payload = data.to_bytes()?;
+        self.session
+            .put(self.key_expr(&data.channel), payload)
+            .await
+            .map_err(zenoh_error)
+    }
+
+    /// List channels received so far
+    async fn list_channels(&self) -> Result<Vec<HubChannelName>, std::io::Error> {
+        Ok(self.channels.read().await.iter().cloned().collect())
+    }
+
+    /// Start forwarding messages of subscribed channels
+    async fn start(
+        &self,
+        sender: Option<broadcast::Sender<HubMessage>>,
+    ) -> Result<NodeTasks, std::io::Error> {
+        let mut tasks = NodeTasks::new();
+        if let Some(sender) = sender {
+            let mut incoming = self.incoming_receiver.lease("Zenoh client")?;
+            let channels = Arc::clone(&self.channels);
+            let task = tokio::spawn(async move {
+                while let Some(message) = incoming.recv().await {
+                    channels.write().await.insert(message.channel.clone());
+                    let _ = sender.send(message);
+                }
+            });
+            tasks.push(task);
+        }
+        Ok(tasks)
+    }
+
+    async fn subscribe(&self, channel: HubChannelName) -> Result<(), std::io::Error> {
+        let mut subscriptions = self.subscriptions.lock().await;
+        if subscriptions.contains_key(&channel) {
+            return Ok(());
+        }
+        let subscriber = self
+            .session
+            .declare_subscriber(self.key_expr(&channel))
+            .await
+            .map_err(zenoh_error)?;
+        let incoming = self.incoming.clone();
+        let key_prefix = self.key_prefix.clone();
+        let task = tokio::spawn(async move {
+            while let Ok(sample) = subscriber.recv_async().await {
+                let key_expr = sample.key_expr().as_str();
+                let hub_message = key_expr_to_channel(&key_prefix, key_expr).and_then(|channel| {
+                    let payload = sample.payload().to_bytes().to_vec();
+                    let hub_message = HubMessage::try_from(payload)?;
+                    if hub_message.channel != channel {
+                        return Err(format!(
+                            "Message from channel {} received in key expression {}",
+                            hub_message.channel.as_str(),
+                            key_expr
+                        ));
+                    }
+                    Ok(hub_message)
+                });
+                match hub_message {
+                    Ok(hub_message) => {
+                        if incoming.send(hub_message).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => error!("Invalid Zenoh sample: {}", e),
+                }
+            }
+        });
+        subscriptions.insert(channel, task);
+        Ok(())
+    }
+
+    async fn unsubscribe(&self, channel: HubChannelName) -> Result<(), std::io::Error> {
+        // Zenoh subscriber is undeclared when it is dropped with its task
+        if let Some(task) = self.subscriptions.lock().await.remove(&channel) {
+            task.abort();
+        }
+        Ok(())
+    }
+}
+
+impl Drop for ZenohClient {
+    fn drop(&mut self) {
+        for task in self.subscriptions.get_mut().values() {
+            task.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time::{timeout, Duration};
+
+    #[test]
+    fn test_key_expr_mapping() {
+        let channel = HubChannelName::try_from("sensors/imu").unwrap();
+        let key_expr = channel_to_key_expr("robopilot", &channel);
+        assert_eq!(key_expr, "robopilot/sensors/imu");
+        assert_eq!(
+            key_expr_to_channel("robopilot", &key_expr).unwrap(),
+            channel
+        );
+
+        assert!(key_expr_to_channel("robopilot", "other/sensors/imu").is_err());
+        assert!(key_expr_to_channel("robopilot", "robopilotsensors/imu").is_err());
+        assert!(key_expr_to_channel("robopilot", "robopilot/sensors/*").is_err());
+    }
+
+    // Requires multicast scouting on the local network
+    #[tokio::test]
+    #[ignore]
+    async fn test_zenoh() {
+        let node1 = ZenohClient::new(::zenoh::Config::default()).await.unwrap();
+        let node2 = ZenohClient::new(::zenoh::Config::default()).await.unwrap();
+        let (sender, mut receiver) = broadcast::channel(10);
+        node2.start(Some(sender)).await.unwrap();
+
+        let channel = HubChannelName::try_from("sensors/imu").unwrap();
+        node2.subscribe(channel.clone()).await.unwrap();
+
+        // peers take a while to discover each other
+        let received = timeout(Duration::from_secs(10), async {
+            loop {
+                node1
+                    .send(HubMessage::try_from_str("sensors/imu", "1,2,3").unwrap())
+                    .await
+                    .unwrap();
+                if let Ok(Ok(message)) = timeout(Duration::from_millis(100), receiver.recv()).await
+                {
+                    return message;
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(received.channel, channel);
+        assert_eq!(node2.list_channels().await.unwrap(), vec![channel]);
+    }
+}
@@ -0,0 +1,4 @@
+/// Zenoh transport for the notification hub.
+pub mod client;
+
+pub use client::ZenohClient;
@@ -0,0 +1,335 @@
+use async_trait::async_trait;
+use bytes::Bytes;
+use log::{error, info, warn};
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::sync::{broadcast, mpsc, Mutex, RwLock};
+use zeromq::{PubSocket, Socket, SocketRecv, SocketSend, SubSocket, ZmqMessage};
+
+use crate::adapters::lease::LeaseSlot;
+use crate::models::hub::{HubChannelName, HubMessage};
+use crate::ports::{NodeTasks, NotificationHub};
+
+/// Address of a ZeroMQ socket. Sockets either bind the endpoint and wait for peers,
+/// or connect to a peer bound to it (e.g. `tcp://127.0.0.1:5556`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ZmqEndpoint {
+    Bind(String),
+    Connect(String),
+}
+
+#[derive(Debug)]
+enum SubscriptionCommand {
+    Subscribe(HubChannelName),
+    Unsubscribe(HubChannelName),
+}
+
+/// State of the receive loop, kept across restarts of the client
+///
+/// # Fields
+/// - `subscriber`: SUB socket.
+/// - `requests`: Subscription requests sent by the client.
+/// - `subscribed`: Channels subscribed on the SUB socket.
+#[derive(Debug)]
+struct Receiver {
+    subscriber: SubSocket,
+    requests: mpsc::UnboundedReceiver<SubscriptionCommand>,
+    subscribed: HashSet<HubChannelName>,
+}
+
+fn zmq_error(e: zeromq::ZmqError) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Other, e.to_string())
+}
+
+// Binds or connects socket. Returns the resolved endpoint
+async fn open(socket: &mut impl Socket, endpoint: &ZmqEndpoint) -> Result<String, std::io::Error> {
+    match endpoint {
+        ZmqEndpoint::Bind(address) => {
+            Ok(socket.bind(address).await.map_err(zmq_error)?.to_string())
+        }
+        ZmqEndpoint::Connect(address) => {
+            socket.connect(address).await.map_err(zmq_error)?;
+            Ok(address.clone())
+        }
+    }
+}
+
+/// `ZmqClient` is a hub node exchanging messages with other processes through ZeroMQ PUB/SUB
+/// sockets, with lower overhead than WebSocket for high-rate sensor data.
+///
+/// Every message is sent as a two frame ZMQ message: the topic, made of the topic prefix
+/// followed by the channel name, and the JSON encoded `HubMessage`. Channel subscriptions
+/// are mapped to SUB subscriptions on the channel topic. As ZMQ subscriptions match topic
+/// prefixes, messages from other channels sharing the prefix are filtered out.
+///
+/// # Fields
+/// - `publisher`: PUB socket where messages are sent.
+/// - `subscriptions`: Subscription requests processed by the receive loop, which owns the SUB socket.
+/// - `receiver`: SUB socket and subscription requests. They are leased by the receive loop when
+///   the client is started.
+/// - `channels`: Channels received so far.
+/// - `topic_prefix`: Prefix of the ZMQ topics, to share sockets with other applications.
+#[derive(Debug)]
+pub struct ZmqClient {
+    publisher: Mutex<PubSocket>,
+    pub_endpoint: String,
+    sub_endpoint: String,
+    subscriptions: mpsc::UnboundedSender<SubscriptionCommand>,
+    receiver: LeaseSlot<Receiver>,
+    channels: Arc<RwLock<HashSet<HubChannelName>>>,
+    topic_prefix: String,
+}
+
+impl ZmqClient {
+    /// Opens PUB socket at `pub_endpoint` and SUB socket at `sub_endpoint`
+    pub async fn new(
+        pub_endpoint: ZmqEndpoint,
+        sub_endpoint: ZmqEndpoint,
+    ) -> Result<Self, std::io::Error> {
+        let mut publisher = PubSocket::new();
+        let pub_endpoint = open(&mut publisher, &pub_endpoint).await?;
+        let mut subscriber = SubSocket::new();
+        let sub_endpoint = open(&mut subscriber, &sub_endpoint).await?;
+        info!(
+            "ZMQ client publishing at {} and subscribed at {}",
+            pub_endpoint, sub_endpoint
+        );
+        let (subscriptions, subscription_requests) = mpsc::unbounded_channel();
+        Ok(Self {
+            publisher: Mutex::new(publisher),
+            pub_endpoint,
+            sub_endpoint,
+            subscriptions,
+            receiver: LeaseSlot::new(Receiver {
+                subscriber,
+                requests: subscription_requests,
+                subscribed: HashSet::new(),
+            }),
+            channels: Arc::new(RwLock::new(HashSet::new())),
+            topic_prefix: String::new(),
+        })
+    }
+
+    /// Prepends `topic_prefix` to the ZMQ topic of every channel
+    pub fn with_topic_prefix(mut self, topic_prefix: &str) -> Self {
+        self.topic_prefix = topic_prefix.to_string();
+        self
+    }
+
+    /// Endpoint of the PUB socket. Bound endpoints are resolved (e.g. the port chosen for
+    /// `tcp://127.0.0.1:0`)
+    pub fn pub_endpoint(&self) -> &str {
+        &self.pub_endpoint
+    }
+
+    /// Endpoint of the SUB socket
+    pub fn sub_endpoint(&self) -> &str {
+        &self.sub_endpoint
+    }
+
+    fn topic(&self, channel: &HubChannelName) -> String {
+        format!("{}{}", self.topic_prefix, channel.as_str())
+    }
+}
+
+// Decodes ZMQ message into a HubMessage of a subscribed channel
+fn decode(
+    message: &ZmqMessage,
+    topic_prefix: &str,
+    subscribed: &HashSet<HubChannelName>,
+) -> Result<Option<HubMessage>, String> {
+    let (Some(topic), Some(payload)) = (message.get(0), message.get(1)) else {
+        return Err(format!("Expected 2 frames, received {}", message.len()));
+    };
+    let topic = std::str::from_utf8(topic).map_err(|e| e.to_string())?;
+    let Some(channel) = topic.strip_prefix(topic_prefix) else {
+        return Ok(None);
+    };
+    let channel = HubChannelName::try_from(channel)?;
+    if !subscribed.contains(&channel) {
+        return Ok(None);
+    }
+    let hub_message = HubMessage::try_from(payload.to_vec())?;
+    if hub_message.channel != channel {
+        return Err(format!(
+            "Message from channel {} received in topic {}",
+            hub_message.channel.as_str(),
+            topic
+        ));
+    }
+    Ok(Some(hub_message))
+}
+
+#[async_trait]
+impl NotificationHub for ZmqClient {
+    /// Publish message on its channel topic
+    async fn send(&self, data: HubMessage) -> Result<(), std::io::Error> {
+        let mut message = ZmqMessage::from(self.topic(&data.channel));
+        message.push_back(Bytes::from(data.to_bytes()?));
+        self.publisher
+            .lock()
+            .await
+            .send(message)
+            .await
+            .map_err(zmq_error)
+    }
+
+    /// List channels received so far
+    async fn list_channels(&self) -> Result<Vec<HubChannelName>, std::io::Error> {
+        Ok(self.channels.read().await.iter().cloned().collect())
+    }
+
+    /// Start receive loop
+    async fn start(
+        &self,
+        sender: Option<broadcast::Sender<HubMessage>>,
+    ) -> Result<NodeTasks, std::io::Error> {
+        let mut tasks = NodeTasks::new();
+        if let Some(sender) = sender {
+            let mut receiver = self.receiver.lease("ZMQ client")?;
+            let channels = Arc::clone(&self.channels);
+            let topic_prefix = self.topic_prefix.clone();
+
+            let task = tokio::spawn(async move {
+                let Receiver {
+                    subscriber,
+                    requests,
+                    subscribed,
+                } = &mut *receiver;
+                loop {
+                    tokio::select! {
+                        request = requests.recv() => {
+                            let result = match request {
+                                Some(SubscriptionCommand::Subscribe(channel)) => {
+                                    let topic = format!("{}{}", topic_prefix, channel.as_str());
+                                    if subscribed.insert(channel) {
+                                        subscriber.subscribe(&topic).await
+                                    } else {
+                                        Ok(())
+                                    }
+                                }
+                                Some(SubscriptionCommand::Unsubscribe(channel)) => {
+                                    let topic = format!("{}{}", topic_prefix, channel.as_str());
+                                    if subscribed.remove(&channel) {
+                                        subscriber.unsubscribe(&topic).await
+                                    } else {
+                                        Ok(())
+                                    }
+                                }
+                                // client dropped
+                                None => break,
+                            };
+                            if let Err(e) = result {
+                                error!("ZMQ subscription error {:?}", e);
+                            }
+                        }
+                        message = subscriber.recv() => {
+                            let message = match message {
+                                Ok(message) => message,
+                                Err(e) => {
+                                    error!("ZMQ receive error {:?}", e);
+                                    break;
+                                }
+                            };
+                            match decode(&message, &topic_prefix, subscribed) {
+                                Ok(Some(message)) => {
+                                    channels.write().await.insert(message.channel.clone());
+                                    let _ = sender.send(message);
+                                }
+                                Ok(None) => {}
+                                Err(e) => warn!("Invalid ZMQ message: {}", e),
+                            }
+                        }
+                    }
+                }
+            });
+            tasks.push(task);
+        }
+        Ok(tasks)
+    }
+
+    async fn subscribe(&self, channel: HubChannelName) -> Result<(), std::io::Error> {
+        self.subscriptions
+            .send(SubscriptionCommand::Subscribe(channel))
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::BrokenPipe, "ZMQ client stopped"))
+    }
+
+    async fn unsubscribe(&self, channel: HubChannelName) -> Result<(), std::io::Error> {
+        self.subscriptions
+            .send(SubscriptionCommand::Unsubscribe(channel))
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::BrokenPipe, "ZMQ client stopped"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time::{timeout, Duration};
+
+    const LOCALHOST: &str = "tcp://127.0.0.1:0";
+
+    #[test]
+    fn test_decode_filters_topics() {
+        let subscribed = HashSet::from([HubChannelName::try_from("imu").unwrap()]);
+        let zmq_message = |topic: &str, message: &HubMessage| {
+            let mut zmq_message = ZmqMessage::from(topic.to_string());
+            zmq_message.push_back(Bytes::from(message.to_bytes().unwrap()));
+            zmq_message
+        };
+
+        let imu = HubMessage::try_from_str("imu", "1,2,3").unwrap();
+        let decoded = decode(&zmq_message("robot/imu", &imu), "robot/", &subscribed).unwrap();
+        assert_eq!(decoded.unwrap().data.as_str(), "1,2,3");
+
+        let imu_raw = HubMessage::try_from_str("imu_raw", "1,2,3").unwrap();
+        let decoded = decode(
+            &zmq_message("robot/imu_raw", &imu_raw),
+            "robot/",
+            &subscribed,
+        );
+        assert!(decoded.unwrap().is_none());
+        assert!(decode(&zmq_message("robot/imu", &imu_raw), "robot/", &subscribed).is_err());
+        assert!(decode(
+            &ZmqMessage::from("robot/imu".to_string()),
+            "robot/",
+            &subscribed
+        )
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_pub_sub() {
+        let node1 = ZmqClient::new(
+            ZmqEndpoint::Bind(LOCALHOST.to_string()),
+            ZmqEndpoint::Bind(LOCALHOST.to_string()),
+        )
+        .await
+        .unwrap();
+        let node2 = ZmqClient::new(
+            ZmqEndpoint::Connect(node1.sub_endpoint().to_string()),
+            ZmqEndpoint::Connect(node1.pub_endpoint().to_string()),
+        )
+        .await
+        .unwrap();
+
+        let (sender, mut receiver) = broadcast::channel(10);
+        node2.start(Some(sender)).await.unwrap();
+        let channel = HubChannelName::try_from("imu").unwrap();
+        node2.subscribe(channel.clone()).await.unwrap();
+
+        // subscriptions take a while to reach the publisher
+        let received = timeout(Duration::from_secs(5), async {
+            loop {
+                let message = HubMessage::try_from_str("imu", "1,2,3").unwrap();
+                node1.send(message).await.unwrap();
+                if let Ok(Ok(message)) = timeout(Duration::from_millis(50), receiver.recv()).await {
+                    return message;
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(received.channel, channel);
+        assert_eq!(node2.list_channels().await.unwrap(), vec![channel]);
+    }
+}
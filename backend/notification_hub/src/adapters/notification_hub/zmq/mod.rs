@@ -0,0 +1,4 @@
+/// ZeroMQ PUB/SUB transport for the notification hub.
+pub mod client;
+
+pub use client::{ZmqClient, ZmqEndpoint};
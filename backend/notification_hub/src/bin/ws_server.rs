@@ -0,0 +1,34 @@
+use notification_hub::adapters::websocket::{WebSocketServer, WsKeepalive};
+use notification_hub::config::RuntimeOptions;
+
+use tokio::signal::ctrl_c;
+
+// Address the server listens on, unless given as first argument
+const DEFAULT_ADDR: &str = "0.0.0.0:8080";
+
+/// Standalone WebSocket server, for hubs and frontends connecting to it as clients
+fn main() -> std::io::Result<()> {
+    env_logger::init();
+    let runtime_options = RuntimeOptions::from_env()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let runtime = runtime_options.build_runtime()?;
+    let addr = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| DEFAULT_ADDR.to_string());
+    runtime.block_on(run(&addr))
+}
+
+async fn run(addr: &str) -> std::io::Result<()> {
+    let server = WebSocketServer::new(addr).with_keepalive(WsKeepalive::default());
+    println!(
+        "WebSocket server listening on {}. Press Ctrl+C to exit...",
+        addr
+    );
+    server
+        .run_until(async {
+            let _ = ctrl_c().await;
+        })
+        .await?;
+    println!("Received Ctrl+C, shutting down.");
+    Ok(())
+}
@@ -0,0 +1,571 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use super::qos::{ChannelQos, ChannelQosBuilder, Reliability};
+use super::remap::RemapRules;
+use super::retry::RetryPolicy;
+use crate::models::hub::HubChannelName;
+
+const DEFAULT_DISPATCH_WORKERS: usize = 1;
+const DEFAULT_DISPATCH_QUEUE_CAPACITY: usize = 100;
+const DEFAULT_CHANNEL_WATCH_INTERVAL: Duration = Duration::from_millis(500);
+const DEFAULT_HISTORY_DEPTH: usize = 0;
+const DEFAULT_HEALTH_INTERVAL: Duration = Duration::from_secs(1);
+const DEFAULT_STATS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Action taken by the hub when one of its background tasks fails
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SupervisionPolicy {
+    /// Failures are reported on the hub errors meta-channel
+    #[default]
+    Report,
+    /// Failures are reported, and failed hub tasks are restarted up to `max_restarts` times.
+    /// Hub node tasks are only reported.
+    Restart { max_restarts: usize },
+}
+
+/// Action taken by the hub when a message it publishes doesn't follow the schema declared for
+/// its channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SchemaViolation {
+    /// Messages are published without validation
+    #[default]
+    Publish,
+    /// Messages are rejected with `ErrorKind::InvalidData`
+    Reject,
+    /// Messages are rejected, and delivered on the dead-letter meta-channel for inspection
+    DeadLetter,
+}
+
+/// Validates a robot identifier, which is used as a top-level channel namespace
+pub(crate) fn robot_namespace(robot_id: &str) -> Result<HubChannelName, String> {
+    let namespace = HubChannelName::try_from(robot_id)?;
+    if namespace.namespace().is_some() {
+        return Err(format!("Invalid robot id {}: '/' is not allowed", robot_id));
+    }
+    Ok(namespace)
+}
+
+/// `HubOptions` configures a `HubManager`.
+///
+/// # Fields
+/// - `dispatch_workers`: Number of tasks delivering messages to subscribers. Channels are
+///   partitioned across workers, so a slow channel only delays channels sharing its worker.
+///   With a single worker, messages are delivered from the hub receive loop.
+/// - `dispatch_queue_capacity`: Number of messages queued per worker. Messages are dropped
+///   when a worker queue is full.
+/// - `channel_watch_interval`: Period at which the hub refreshes the channels available in the
+///   hub nodes to emit channel lifecycle events.
+/// - `history_depth`: Number of messages kept per channel to be replayed to new subscribers.
+///   History is disabled with 0.
+/// - `channel_history_depths`: Number of messages kept in specific channels, overriding
+///   `history_depth`.
+/// - `retain_last_message`: Keeps the last message of every channel, even with history
+///   disabled, and delivers it to new subscribers before live messages, so they get the
+///   current state without waiting for the next publish.
+/// - `validate_schemas`: Blocks received messages not following the schema declared for their
+///   channel with `HubManager::declare_schema`.
+/// - `schema_violation`: Action taken when a message published with `HubManager::publish` or
+///   a `HubSender` doesn't follow the schema declared for its channel. Published messages
+///   aren't validated by default.
+/// - `supervision`: Policy applied when a background task of the hub fails.
+/// - `health_interval`: Period at which the hub publishes the health of its nodes.
+/// - `stats_interval`: Period at which the hub publishes the statistics of its channels.
+/// - `channel_ttl`: Time after which channels without subscribers nor published messages
+///   are removed from the hub, together with their history and statistics. Prevents
+///   unbounded growth when nodes publish in dynamically named channels. Channels are kept
+///   by default.
+/// - `node_restart_delay`: Delay before restarting a hub node whose tasks exited (e.g. serial
+///   port unplugged, WebSocket disconnected). Nodes are not restarted by default.
+/// - `node_retry`: Timeout and retries of the `send`, `subscribe` and `list_channels`
+///   operations of hub nodes, so an unresponsive node fails its callers instead of stalling
+///   them. Node operations wait without bound by default.
+/// - `remap`: Channel remap rules applied to every hub node. Nodes added with
+///   `HubManager::add_remapped` extend them with their own rules.
+/// - `robot_id`: Identifier of the robot running the hub in a fleet. Hubs bridging this
+///   robot namespace its channels as `<robot_id>/...`.
+/// - `qos`: Quality of service of channels. Channels without QoS are best effort, dropping
+///   messages for subscribers that don't keep up.
+/// - `priority_channels`: Safety-critical channels (e.g. `estop`, `cmd_vel`) dispatched in a
+///   separate lane, ahead of queued telemetry. Priority channels are reliable, and get a
+///   reliable QoS unless one is set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HubOptions {
+    dispatch_workers: usize,
+    dispatch_queue_capacity: usize,
+    channel_watch_interval: Duration,
+    history_depth: usize,
+    channel_history_depths: HashMap<HubChannelName, usize>,
+    retain_last_message: bool,
+    validate_schemas: bool,
+    schema_violation: SchemaViolation,
+    supervision: SupervisionPolicy,
+    health_interval: Duration,
+    stats_interval: Duration,
+    channel_ttl: Option<Duration>,
+    node_restart_delay: Option<Duration>,
+    node_retry: Option<RetryPolicy>,
+    remap: RemapRules,
+    robot_id: Option<String>,
+    qos: HashMap<HubChannelName, ChannelQos>,
+    priority_channels: HashSet<HubChannelName>,
+}
+
+impl Default for HubOptions {
+    fn default() -> Self {
+        Self {
+            dispatch_workers: DEFAULT_DISPATCH_WORKERS,
+            dispatch_queue_capacity: DEFAULT_DISPATCH_QUEUE_CAPACITY,
+            channel_watch_interval: DEFAULT_CHANNEL_WATCH_INTERVAL,
+            history_depth: DEFAULT_HISTORY_DEPTH,
+            channel_history_depths: HashMap::new(),
+            retain_last_message: false,
+            validate_schemas: false,
+            schema_violation: SchemaViolation::default(),
+            supervision: SupervisionPolicy::default(),
+            health_interval: DEFAULT_HEALTH_INTERVAL,
+            stats_interval: DEFAULT_STATS_INTERVAL,
+            channel_ttl: None,
+            node_restart_delay: None,
+            node_retry: None,
+            remap: RemapRules::default(),
+            robot_id: None,
+            qos: HashMap::new(),
+            priority_channels: HashSet::new(),
+        }
+    }
+}
+
+impl HubOptions {
+    pub fn dispatch_workers(&self) -> usize {
+        self.dispatch_workers
+    }
+    pub fn dispatch_queue_capacity(&self) -> usize {
+        self.dispatch_queue_capacity
+    }
+    pub fn channel_watch_interval(&self) -> Duration {
+        self.channel_watch_interval
+    }
+    pub fn history_depth(&self) -> usize {
+        self.history_depth
+    }
+    pub fn channel_history_depths(&self) -> &HashMap<HubChannelName, usize> {
+        &self.channel_history_depths
+    }
+    pub fn retain_last_message(&self) -> bool {
+        self.retain_last_message
+    }
+    pub fn validate_schemas(&self) -> bool {
+        self.validate_schemas
+    }
+    pub fn schema_violation(&self) -> SchemaViolation {
+        self.schema_violation
+    }
+    pub fn supervision(&self) -> SupervisionPolicy {
+        self.supervision
+    }
+    pub fn health_interval(&self) -> Duration {
+        self.health_interval
+    }
+    pub fn stats_interval(&self) -> Duration {
+        self.stats_interval
+    }
+    pub fn channel_ttl(&self) -> Option<Duration> {
+        self.channel_ttl
+    }
+    pub fn node_restart_delay(&self) -> Option<Duration> {
+        self.node_restart_delay
+    }
+    pub fn node_retry(&self) -> Option<RetryPolicy> {
+        self.node_retry
+    }
+    pub fn remap(&self) -> &RemapRules {
+        &self.remap
+    }
+    pub fn robot_id(&self) -> Option<&str> {
+        self.robot_id.as_deref()
+    }
+    pub fn qos(&self) -> &HashMap<HubChannelName, ChannelQos> {
+        &self.qos
+    }
+    pub fn priority_channels(&self) -> &HashSet<HubChannelName> {
+        &self.priority_channels
+    }
+
+    /// Returns the QoS of `channel`, if any
+    pub fn channel_qos(&self, channel: &HubChannelName) -> Option<&ChannelQos> {
+        self.qos.get(channel)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HubOptionsBuilder {
+    dispatch_workers: Option<usize>,
+    dispatch_queue_capacity: Option<usize>,
+    channel_watch_interval: Option<Duration>,
+    history_depth: Option<usize>,
+    channel_history_depths: Vec<(String, usize)>,
+    retain_last_message: Option<bool>,
+    validate_schemas: Option<bool>,
+    schema_violation: Option<SchemaViolation>,
+    supervision: Option<SupervisionPolicy>,
+    health_interval: Option<Duration>,
+    stats_interval: Option<Duration>,
+    channel_ttl: Option<Duration>,
+    node_restart_delay: Option<Duration>,
+    node_retry: Option<RetryPolicy>,
+    remap: Option<RemapRules>,
+    robot_id: Option<String>,
+    qos: Vec<(String, ChannelQos)>,
+    priority_channels: Vec<String>,
+}
+
+impl HubOptionsBuilder {
+    pub fn new() -> Self {
+        Self {
+            dispatch_workers: None,
+            dispatch_queue_capacity: None,
+            channel_watch_interval: None,
+            history_depth: None,
+            channel_history_depths: Vec::new(),
+            retain_last_message: None,
+            validate_schemas: None,
+            schema_violation: None,
+            supervision: None,
+            health_interval: None,
+            stats_interval: None,
+            channel_ttl: None,
+            node_restart_delay: None,
+            node_retry: None,
+            remap: None,
+            robot_id: None,
+            qos: Vec::new(),
+            priority_channels: Vec::new(),
+        }
+    }
+
+    pub fn dispatch_workers(&self, dispatch_workers: usize) -> Self {
+        let mut new = self.clone();
+        new.dispatch_workers = Some(dispatch_workers);
+        new
+    }
+    pub fn dispatch_queue_capacity(&self, dispatch_queue_capacity: usize) -> Self {
+        let mut new = self.clone();
+        new.dispatch_queue_capacity = Some(dispatch_queue_capacity);
+        new
+    }
+    pub fn channel_watch_interval(&self, channel_watch_interval: Duration) -> Self {
+        let mut new = self.clone();
+        new.channel_watch_interval = Some(channel_watch_interval);
+        new
+    }
+    pub fn history_depth(&self, history_depth: usize) -> Self {
+        let mut new = self.clone();
+        new.history_depth = Some(history_depth);
+        new
+    }
+    /// Sets the number of messages kept in the history of `channel`
+    pub fn channel_history_depth(&self, channel: &str, depth: usize) -> Self {
+        let mut new = self.clone();
+        new.channel_history_depths
+            .push((channel.to_string(), depth));
+        new
+    }
+    pub fn retain_last_message(&self, retain_last_message: bool) -> Self {
+        let mut new = self.clone();
+        new.retain_last_message = Some(retain_last_message);
+        new
+    }
+    pub fn validate_schemas(&self, validate_schemas: bool) -> Self {
+        let mut new = self.clone();
+        new.validate_schemas = Some(validate_schemas);
+        new
+    }
+    pub fn schema_violation(&self, schema_violation: SchemaViolation) -> Self {
+        let mut new = self.clone();
+        new.schema_violation = Some(schema_violation);
+        new
+    }
+    pub fn supervision(&self, supervision: SupervisionPolicy) -> Self {
+        let mut new = self.clone();
+        new.supervision = Some(supervision);
+        new
+    }
+    pub fn health_interval(&self, health_interval: Duration) -> Self {
+        let mut new = self.clone();
+        new.health_interval = Some(health_interval);
+        new
+    }
+    pub fn stats_interval(&self, stats_interval: Duration) -> Self {
+        let mut new = self.clone();
+        new.stats_interval = Some(stats_interval);
+        new
+    }
+    pub fn channel_ttl(&self, channel_ttl: Duration) -> Self {
+        let mut new = self.clone();
+        new.channel_ttl = Some(channel_ttl);
+        new
+    }
+    pub fn node_restart_delay(&self, node_restart_delay: Duration) -> Self {
+        let mut new = self.clone();
+        new.node_restart_delay = Some(node_restart_delay);
+        new
+    }
+    pub fn node_retry(&self, node_retry: RetryPolicy) -> Self {
+        let mut new = self.clone();
+        new.node_retry = Some(node_retry);
+        new
+    }
+    pub fn remap(&self, remap: RemapRules) -> Self {
+        let mut new = self.clone();
+        new.remap = Some(remap);
+        new
+    }
+    pub fn robot_id(&self, robot_id: &str) -> Self {
+        let mut new = self.clone();
+        new.robot_id = Some(robot_id.to_string());
+        new
+    }
+    /// Sets the quality of service of `channel`
+    pub fn qos(&self, channel: &str, qos: ChannelQos) -> Self {
+        let mut new = self.clone();
+        new.qos.push((channel.to_string(), qos));
+        new
+    }
+    /// Marks `channel` as high priority
+    pub fn priority_channel(&self, channel: &str) -> Self {
+        let mut new = self.clone();
+        new.priority_channels.push(channel.to_string());
+        new
+    }
+    pub fn build(self) -> Result<HubOptions, String> {
+        let dispatch_workers = self.dispatch_workers.unwrap_or(DEFAULT_DISPATCH_WORKERS);
+        if dispatch_workers == 0 {
+            return Err("Number of dispatch workers must be greater than 0".to_string());
+        }
+        let dispatch_queue_capacity = self
+            .dispatch_queue_capacity
+            .unwrap_or(DEFAULT_DISPATCH_QUEUE_CAPACITY);
+        if dispatch_queue_capacity == 0 {
+            return Err("Dispatch queue capacity must be greater than 0".to_string());
+        }
+        let channel_watch_interval = self
+            .channel_watch_interval
+            .unwrap_or(DEFAULT_CHANNEL_WATCH_INTERVAL);
+        if channel_watch_interval.is_zero() {
+            return Err("Channel watch interval must be greater than 0".to_string());
+        }
+        let health_interval = self.health_interval.unwrap_or(DEFAULT_HEALTH_INTERVAL);
+        if health_interval.is_zero() {
+            return Err("Health interval must be greater than 0".to_string());
+        }
+        let stats_interval = self.stats_interval.unwrap_or(DEFAULT_STATS_INTERVAL);
+        if stats_interval.is_zero() {
+            return Err("Stats interval must be greater than 0".to_string());
+        }
+        if self
+            .channel_ttl
+            .is_some_and(|channel_ttl| channel_ttl.is_zero())
+        {
+            return Err("Channel TTL must be greater than 0".to_string());
+        }
+        let robot_id = match self.robot_id {
+            Some(robot_id) => Some(robot_namespace(&robot_id)?.as_str().to_string()),
+            None => None,
+        };
+        let mut channel_history_depths = HashMap::new();
+        for (channel, depth) in self.channel_history_depths {
+            let channel = HubChannelName::try_from(channel.as_str())?;
+            if channel_history_depths
+                .insert(channel.clone(), depth)
+                .is_some()
+            {
+                return Err(format!(
+                    "Channel {} has more than one history depth",
+                    channel.as_str()
+                ));
+            }
+        }
+        let mut qos = HashMap::new();
+        for (channel, channel_qos) in self.qos {
+            let channel = HubChannelName::try_from(channel.as_str())?;
+            if qos.insert(channel.clone(), channel_qos).is_some() {
+                return Err(format!(
+                    "Channel {} has more than one QoS",
+                    channel.as_str()
+                ));
+            }
+        }
+        let mut priority_channels = HashSet::new();
+        for channel in self.priority_channels {
+            let channel = HubChannelName::try_from(channel.as_str())?;
+            match qos.get(&channel) {
+                Some(channel_qos) if channel_qos.reliability() != Reliability::Reliable => {
+                    return Err(format!(
+                        "Priority channel {} can't drop messages",
+                        channel.as_str()
+                    ));
+                }
+                Some(_) => (),
+                None => {
+                    let reliable = ChannelQosBuilder::new()
+                        .reliability(Reliability::Reliable)
+                        .build()?;
+                    qos.insert(channel.clone(), reliable);
+                }
+            }
+            priority_channels.insert(channel);
+        }
+        Ok(HubOptions {
+            dispatch_workers,
+            dispatch_queue_capacity,
+            channel_watch_interval,
+            history_depth: self.history_depth.unwrap_or(DEFAULT_HISTORY_DEPTH),
+            channel_history_depths,
+            retain_last_message: self.retain_last_message.unwrap_or_default(),
+            validate_schemas: self.validate_schemas.unwrap_or_default(),
+            schema_violation: self.schema_violation.unwrap_or_default(),
+            supervision: self.supervision.unwrap_or_default(),
+            health_interval,
+            stats_interval,
+            channel_ttl: self.channel_ttl,
+            node_restart_delay: self.node_restart_delay,
+            node_retry: self.node_retry,
+            remap: self.remap.unwrap_or_default(),
+            robot_id,
+            qos,
+            priority_channels,
+        })
+    }
+}
+
+impl Default for HubOptionsBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_options() {
+        let options = HubOptionsBuilder::new().build().unwrap();
+        assert_eq!(options, HubOptions::default());
+        assert_eq!(options.dispatch_workers(), 1);
+        assert_eq!(options.history_depth(), 0);
+        assert!(!options.retain_last_message());
+        assert_eq!(options.supervision(), SupervisionPolicy::Report);
+        assert_eq!(options.channel_ttl(), None);
+        assert_eq!(options.node_retry(), None);
+    }
+
+    #[test]
+    fn test_dispatch_workers() {
+        let options = HubOptionsBuilder::new()
+            .dispatch_workers(4)
+            .dispatch_queue_capacity(10)
+            .build()
+            .unwrap();
+        assert_eq!(options.dispatch_workers(), 4);
+        assert_eq!(options.dispatch_queue_capacity(), 10);
+    }
+
+    #[test]
+    fn test_zero_values_are_invalid() {
+        assert!(HubOptionsBuilder::new()
+            .dispatch_workers(0)
+            .build()
+            .is_err());
+        assert!(HubOptionsBuilder::new()
+            .dispatch_queue_capacity(0)
+            .build()
+            .is_err());
+        assert!(HubOptionsBuilder::new()
+            .channel_watch_interval(Duration::ZERO)
+            .build()
+            .is_err());
+        assert!(HubOptionsBuilder::new()
+            .health_interval(Duration::ZERO)
+            .build()
+            .is_err());
+        assert!(HubOptionsBuilder::new()
+            .stats_interval(Duration::ZERO)
+            .build()
+            .is_err());
+        assert!(HubOptionsBuilder::new()
+            .channel_ttl(Duration::ZERO)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_channel_history_depths() {
+        let options = HubOptionsBuilder::new()
+            .channel_history_depth("Pose", 50)
+            .build()
+            .unwrap();
+        let channel = HubChannelName::try_from("pose").unwrap();
+        assert_eq!(options.channel_history_depths().get(&channel), Some(&50));
+        assert!(HubOptionsBuilder::new()
+            .channel_history_depth("pose", 50)
+            .channel_history_depth("pose", 10)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_robot_id() {
+        let options = HubOptionsBuilder::new().robot_id("Robot2").build().unwrap();
+        assert_eq!(options.robot_id(), Some("robot2"));
+        assert!(HubOptionsBuilder::new()
+            .robot_id("fleet/robot2")
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_channel_qos() {
+        use crate::config::{ChannelQosBuilder, Reliability};
+
+        let reliable = ChannelQosBuilder::new()
+            .reliability(Reliability::Reliable)
+            .build()
+            .unwrap();
+        let options = HubOptionsBuilder::new()
+            .qos("cmd_vel", reliable.clone())
+            .build()
+            .unwrap();
+        let channel = HubChannelName::try_from("cmd_vel").unwrap();
+        assert_eq!(options.channel_qos(&channel), Some(&reliable));
+        assert!(HubOptionsBuilder::new()
+            .qos("cmd_vel", reliable.clone())
+            .qos("cmd_vel", reliable)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_priority_channels() {
+        use crate::config::{ChannelQosBuilder, Reliability};
+
+        let options = HubOptionsBuilder::new()
+            .priority_channel("estop")
+            .build()
+            .unwrap();
+        let channel = HubChannelName::try_from("estop").unwrap();
+        assert!(options.priority_channels().contains(&channel));
+        assert_eq!(
+            options.channel_qos(&channel).unwrap().reliability(),
+            Reliability::Reliable
+        );
+
+        let best_effort = ChannelQosBuilder::new().build().unwrap();
+        assert!(HubOptionsBuilder::new()
+            .qos("estop", best_effort)
+            .priority_channel("estop")
+            .build()
+            .is_err());
+    }
+}
@@ -0,0 +1,13 @@
+pub mod hub;
+pub mod node;
+pub mod qos;
+pub mod remap;
+pub mod retry;
+pub mod runtime;
+
+pub use hub::{HubOptions, HubOptionsBuilder, SchemaViolation, SupervisionPolicy};
+pub use node::{NodeConfig, NodeConfigBuilder, NodeFactory, NodeTransport};
+pub use qos::{Backpressure, ChannelQos, ChannelQosBuilder, Reliability};
+pub use remap::{RemapRules, RemapRulesBuilder};
+pub use retry::{RetryPolicy, RetryPolicyBuilder};
+pub use runtime::{RuntimeFlavor, RuntimeOptions, RuntimeOptionsBuilder};
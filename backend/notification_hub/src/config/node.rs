@@ -0,0 +1,403 @@
+use futures_util::future::{BoxFuture, FutureExt};
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+
+use super::hub::robot_namespace;
+use super::remap::RemapRules;
+use crate::adapters::batch::BatchOptions;
+use crate::adapters::serial::{PortFilter, SerialOptions};
+use crate::models::hub::NodeId;
+use crate::ports::NotificationHub;
+use crate::services::hub::RoutePattern;
+
+/// Function building a hub node
+pub type NodeFactory = Arc<
+    dyn Fn() -> BoxFuture<'static, Result<Box<dyn NotificationHub>, std::io::Error>> + Send + Sync,
+>;
+
+/// Transport of a hub node
+///
+/// - `Serial`: Serial port `port` opened at `baud_rate`.
+/// - `SerialDiscover`: First available serial port matching `filter`, opened at `baud_rate`.
+/// - `SerialHotplug`: Serial ports matching `filter`, opened at `baud_rate` as devices are
+///   plugged while the hub runs.
+/// - `WebSocket`: WebSocket client connected to `url`.
+/// - `Pipe`: Named pipes (or files) read and written with the serial line format. Opening a
+///   named pipe waits for its other end to be opened.
+/// - `Stdio`: Standard input and output.
+/// - `Custom`: Node built by a factory, for transports defined outside this crate.
+#[derive(Clone)]
+pub enum NodeTransport {
+    Serial {
+        port: String,
+        baud_rate: u32,
+    },
+    SerialDiscover {
+        filter: PortFilter,
+        baud_rate: u32,
+    },
+    SerialHotplug {
+        filter: PortFilter,
+        baud_rate: u32,
+    },
+    WebSocket {
+        url: String,
+    },
+    Pipe {
+        read_path: String,
+        write_path: String,
+    },
+    Stdio,
+    Custom(NodeFactory),
+}
+
+impl fmt::Debug for NodeTransport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Serial { port, baud_rate } => f
+                .debug_struct("Serial")
+                .field("port", port)
+                .field("baud_rate", baud_rate)
+                .finish(),
+            Self::SerialDiscover { filter, baud_rate } => f
+                .debug_struct("SerialDiscover")
+                .field("filter", filter)
+                .field("baud_rate", baud_rate)
+                .finish(),
+            Self::SerialHotplug { filter, baud_rate } => f
+                .debug_struct("SerialHotplug")
+                .field("filter", filter)
+                .field("baud_rate", baud_rate)
+                .finish(),
+            Self::WebSocket { url } => f.debug_struct("WebSocket").field("url", url).finish(),
+            Self::Pipe {
+                read_path,
+                write_path,
+            } => f
+                .debug_struct("Pipe")
+                .field("read_path", read_path)
+                .field("write_path", write_path)
+                .finish(),
+            Self::Stdio => f.write_str("Stdio"),
+            Self::Custom(_) => f.write_str("Custom"),
+        }
+    }
+}
+
+/// `NodeConfig` declares a hub node added by `HubManagerBuilder`.
+///
+/// # Fields
+/// - `name`: Name identifying the node in the hub. Robot nodes are named after their robot.
+/// - `transport`: Transport of the node.
+/// - `remap`: Channel remap rules of the node.
+/// - `robot_id`: Robot bridged by the node. Channels of the robot are namespaced with its id.
+/// - `routes`: Channels whose messages are only published to this node. Routes ending with
+///   `/*` match every channel of a namespace (e.g. `telemetry/*`).
+/// - `batching`: Batching of outgoing messages, for serial and WebSocket nodes.
+/// - `serial_options`: Framing and line settings of serial ports, for serial nodes. Ports are
+///   opened with 8N1 framing and no flow control if not set.
+/// - `optional`: Optional nodes failing to connect are skipped, instead of failing the hub.
+#[derive(Debug, Clone)]
+pub struct NodeConfig {
+    name: NodeId,
+    transport: NodeTransport,
+    remap: RemapRules,
+    robot_id: Option<String>,
+    routes: Vec<RoutePattern>,
+    batching: Option<BatchOptions>,
+    serial_options: Option<SerialOptions>,
+    optional: bool,
+}
+
+impl NodeConfig {
+    pub fn name(&self) -> &NodeId {
+        &self.name
+    }
+    pub fn transport(&self) -> &NodeTransport {
+        &self.transport
+    }
+    pub fn remap(&self) -> &RemapRules {
+        &self.remap
+    }
+    pub fn robot_id(&self) -> Option<&str> {
+        self.robot_id.as_deref()
+    }
+    pub fn routes(&self) -> &[RoutePattern] {
+        &self.routes
+    }
+    pub fn batching(&self) -> Option<BatchOptions> {
+        self.batching
+    }
+    pub fn serial_options(&self) -> SerialOptions {
+        self.serial_options.unwrap_or_default()
+    }
+    pub fn optional(&self) -> bool {
+        self.optional
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NodeConfigBuilder {
+    name: Option<String>,
+    transport: Option<NodeTransport>,
+    remap: Option<RemapRules>,
+    robot_id: Option<String>,
+    routes: Vec<String>,
+    batching: Option<BatchOptions>,
+    serial_options: Option<SerialOptions>,
+    optional: Option<bool>,
+}
+
+impl NodeConfigBuilder {
+    pub fn new() -> Self {
+        Self {
+            name: None,
+            transport: None,
+            remap: None,
+            robot_id: None,
+            routes: Vec::new(),
+            batching: None,
+            serial_options: None,
+            optional: None,
+        }
+    }
+
+    pub fn name(&self, name: &str) -> Self {
+        let mut new = self.clone();
+        new.name = Some(name.to_string());
+        new
+    }
+    pub fn serial(&self, port: &str, baud_rate: u32) -> Self {
+        self.transport(NodeTransport::Serial {
+            port: port.to_string(),
+            baud_rate,
+        })
+    }
+    /// Sets the first available serial port matching `filter`, instead of a fixed port
+    pub fn serial_discover(&self, filter: PortFilter, baud_rate: u32) -> Self {
+        self.transport(NodeTransport::SerialDiscover { filter, baud_rate })
+    }
+    /// Sets the serial ports matching `filter`, attached as they are plugged
+    pub fn serial_hotplug(&self, filter: PortFilter, baud_rate: u32) -> Self {
+        self.transport(NodeTransport::SerialHotplug { filter, baud_rate })
+    }
+    pub fn websocket(&self, url: &str) -> Self {
+        self.transport(NodeTransport::WebSocket {
+            url: url.to_string(),
+        })
+    }
+    pub fn pipe(&self, read_path: &str, write_path: &str) -> Self {
+        self.transport(NodeTransport::Pipe {
+            read_path: read_path.to_string(),
+            write_path: write_path.to_string(),
+        })
+    }
+    pub fn stdio(&self) -> Self {
+        self.transport(NodeTransport::Stdio)
+    }
+    /// Sets a node built by `factory`
+    pub fn custom<F, Fut, N>(&self, factory: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<N, std::io::Error>> + Send + 'static,
+        N: NotificationHub + 'static,
+    {
+        let factory: NodeFactory = Arc::new(move || {
+            let node = factory();
+            async move { Ok(Box::new(node.await?) as Box<dyn NotificationHub>) }.boxed()
+        });
+        self.transport(NodeTransport::Custom(factory))
+    }
+    pub fn transport(&self, transport: NodeTransport) -> Self {
+        let mut new = self.clone();
+        new.transport = Some(transport);
+        new
+    }
+    pub fn remap(&self, remap: RemapRules) -> Self {
+        let mut new = self.clone();
+        new.remap = Some(remap);
+        new
+    }
+    pub fn robot_id(&self, robot_id: &str) -> Self {
+        let mut new = self.clone();
+        new.robot_id = Some(robot_id.to_string());
+        new
+    }
+    /// Routes messages published to `pattern` to this node. `pattern` is a channel name, or a
+    /// namespace followed by `/*`
+    pub fn route(&self, pattern: &str) -> Self {
+        let mut new = self.clone();
+        new.routes.push(pattern.to_string());
+        new
+    }
+    pub fn batching(&self, batching: BatchOptions) -> Self {
+        let mut new = self.clone();
+        new.batching = Some(batching);
+        new
+    }
+    pub fn serial_options(&self, serial_options: SerialOptions) -> Self {
+        let mut new = self.clone();
+        new.serial_options = Some(serial_options);
+        new
+    }
+    pub fn optional(&self, optional: bool) -> Self {
+        let mut new = self.clone();
+        new.optional = Some(optional);
+        new
+    }
+    pub fn build(self) -> Result<NodeConfig, String> {
+        let transport = self
+            .transport
+            .ok_or_else(|| "Node transport is not set".to_string())?;
+        if self.batching.is_some()
+            && !matches!(
+                transport,
+                NodeTransport::Serial { .. }
+                    | NodeTransport::SerialDiscover { .. }
+                    | NodeTransport::WebSocket { .. }
+            )
+        {
+            return Err(format!("{:?} nodes don't batch messages", transport));
+        }
+        if self.serial_options.is_some()
+            && !matches!(
+                transport,
+                NodeTransport::Serial { .. }
+                    | NodeTransport::SerialDiscover { .. }
+                    | NodeTransport::SerialHotplug { .. }
+            )
+        {
+            return Err(format!("{:?} nodes aren't serial ports", transport));
+        }
+        if self.robot_id.is_some() && self.remap.is_some() {
+            return Err("Robot nodes are remapped into the robot namespace".to_string());
+        }
+        let robot_id = match self.robot_id {
+            Some(robot_id) => Some(robot_namespace(&robot_id)?.as_str().to_string()),
+            None => None,
+        };
+        let name = match (self.name, &robot_id) {
+            (Some(_), Some(_)) => return Err("Robot nodes are named after the robot".to_string()),
+            (Some(name), None) => NodeId::try_from(name.as_str())?,
+            (None, Some(robot_id)) => NodeId::try_from(robot_id.as_str())?,
+            (None, None) => return Err("Node name is not set".to_string()),
+        };
+        let mut routes = Vec::new();
+        for pattern in self.routes {
+            let pattern = RoutePattern::try_from(pattern.as_str())?;
+            if !routes.contains(&pattern) {
+                routes.push(pattern);
+            }
+        }
+        Ok(NodeConfig {
+            name,
+            transport,
+            remap: self.remap.unwrap_or_default(),
+            robot_id,
+            routes,
+            batching: self.batching,
+            serial_options: self.serial_options,
+            optional: self.optional.unwrap_or_default(),
+        })
+    }
+}
+
+impl Default for NodeConfigBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::serial::{Parity, SerialOptionsBuilder};
+    use crate::config::RemapRulesBuilder;
+
+    #[test]
+    fn test_node_config() {
+        let config = NodeConfigBuilder::new()
+            .name("imu")
+            .serial("/dev/ttyACM0", 9600)
+            .route("cmd_vel")
+            .route("cmd_vel")
+            .route("telemetry/*")
+            .optional(true)
+            .build()
+            .unwrap();
+        assert_eq!(config.name().as_str(), "imu");
+        assert!(matches!(
+            config.transport(),
+            NodeTransport::Serial {
+                baud_rate: 9600,
+                ..
+            }
+        ));
+        assert_eq!(
+            config.routes(),
+            &[
+                RoutePattern::try_from("cmd_vel").unwrap(),
+                RoutePattern::try_from("telemetry/*").unwrap()
+            ]
+        );
+        assert!(config.optional());
+        assert!(config.remap().is_empty());
+
+        let config = NodeConfigBuilder::new()
+            .name("imu")
+            .serial_discover(PortFilter::vendor(0x2341), 9600)
+            .batching(BatchOptions::new(10, 5))
+            .serial_options(SerialOptionsBuilder::new().mode("7E1").build().unwrap())
+            .build()
+            .unwrap();
+        assert!(matches!(
+            config.transport(),
+            NodeTransport::SerialDiscover {
+                filter: PortFilter::Usb { vid: 0x2341, .. },
+                baud_rate: 9600,
+            }
+        ));
+        assert_eq!(config.serial_options().parity(), Parity::Even);
+
+        let hotplug = NodeConfigBuilder::new()
+            .name("arduino")
+            .serial_hotplug(PortFilter::banner(), 115200);
+        assert!(matches!(
+            hotplug.clone().build().unwrap().transport(),
+            NodeTransport::SerialHotplug {
+                filter: PortFilter::Banner { .. },
+                baud_rate: 115200,
+            }
+        ));
+        assert!(hotplug.batching(BatchOptions::new(10, 5)).build().is_err());
+    }
+
+    #[test]
+    fn test_invalid_node_config() {
+        assert!(NodeConfigBuilder::new().build().is_err());
+        assert!(NodeConfigBuilder::new().stdio().build().is_err());
+        assert!(NodeConfigBuilder::new()
+            .name("std io")
+            .stdio()
+            .build()
+            .is_err());
+
+        let robot = NodeConfigBuilder::new().stdio().robot_id("robot2");
+        assert_eq!(robot.build().unwrap().name().as_str(), "robot2");
+        assert!(robot.name("stdio").build().is_err());
+
+        let stdio = NodeConfigBuilder::new().name("stdio").stdio();
+        let batching = BatchOptions::new(10, 5);
+        assert!(stdio.batching(batching).build().is_err());
+        assert!(stdio
+            .serial_options(SerialOptions::default())
+            .build()
+            .is_err());
+        assert!(stdio.robot_id("fleet/robot2").build().is_err());
+        assert!(stdio.route("telemetry/*/imu").build().is_err());
+
+        let rules = RemapRulesBuilder::new().prefix("imu").build().unwrap();
+        assert!(stdio.robot_id("robot2").remap(rules).build().is_err());
+    }
+}
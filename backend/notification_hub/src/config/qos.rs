@@ -0,0 +1,218 @@
+use std::time::Duration;
+
+const DEFAULT_DEPTH: usize = 100;
+
+/// Delivery guarantee of a channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Reliability {
+    /// Every message is delivered to every subscriber, however slow. Meant for commands.
+    Reliable,
+    /// Subscribers falling behind lose the oldest messages, so that they resume with fresh
+    /// data. Meant for sensor streams.
+    #[default]
+    BestEffort,
+}
+
+/// Action taken when the queue of a subscriber is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backpressure {
+    /// Oldest queued messages are dropped, so the subscriber resumes with fresh data
+    DropOldest,
+    /// New messages are dropped until the subscriber catches up
+    DropNewest,
+    /// Dispatch waits for room in the queue. This delays every channel sharing the dispatch
+    /// worker, and eventually the hub nodes publishing in the hub.
+    Block,
+    /// The queue grows without bound
+    Expand,
+}
+
+impl Backpressure {
+    // Delivery guarantee provided by the backpressure strategy
+    fn reliability(&self) -> Reliability {
+        match self {
+            Backpressure::DropOldest | Backpressure::DropNewest => Reliability::BestEffort,
+            Backpressure::Block | Backpressure::Expand => Reliability::Reliable,
+        }
+    }
+}
+
+/// `ChannelQos` sets how the hub delivers the messages of a channel to its subscribers.
+///
+/// # Fields
+/// - `reliability`: Delivery guarantee.
+/// - `backpressure`: Action taken when a subscriber queue is full. Defaults to
+///   `Backpressure::Expand` for reliable channels, and `Backpressure::DropOldest` for best
+///   effort channels. When only the backpressure is set, the reliability follows from it.
+/// - `depth`: Number of messages buffered per subscriber. Expanding queues buffer every
+///   message not received yet.
+/// - `max_rate`: Maximum number of messages per second delivered in a best effort channel.
+///   Messages above the rate are dropped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelQos {
+    reliability: Reliability,
+    backpressure: Backpressure,
+    depth: usize,
+    max_rate: Option<f64>,
+}
+
+impl ChannelQos {
+    pub fn reliability(&self) -> Reliability {
+        self.reliability
+    }
+    pub fn backpressure(&self) -> Backpressure {
+        self.backpressure
+    }
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+    pub fn max_rate(&self) -> Option<f64> {
+        self.max_rate
+    }
+
+    /// Minimum interval between messages delivered, from the max rate
+    pub fn min_interval(&self) -> Option<Duration> {
+        self.max_rate
+            .map(|rate| Duration::from_secs_f64(1.0 / rate))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ChannelQosBuilder {
+    reliability: Option<Reliability>,
+    backpressure: Option<Backpressure>,
+    depth: Option<usize>,
+    max_rate: Option<f64>,
+}
+
+impl ChannelQosBuilder {
+    pub fn new() -> Self {
+        Self {
+            reliability: None,
+            backpressure: None,
+            depth: None,
+            max_rate: None,
+        }
+    }
+
+    pub fn reliability(&self, reliability: Reliability) -> Self {
+        let mut new = self.clone();
+        new.reliability = Some(reliability);
+        new
+    }
+    pub fn backpressure(&self, backpressure: Backpressure) -> Self {
+        let mut new = self.clone();
+        new.backpressure = Some(backpressure);
+        new
+    }
+    pub fn depth(&self, depth: usize) -> Self {
+        let mut new = self.clone();
+        new.depth = Some(depth);
+        new
+    }
+    pub fn max_rate(&self, max_rate: f64) -> Self {
+        let mut new = self.clone();
+        new.max_rate = Some(max_rate);
+        new
+    }
+    pub fn build(self) -> Result<ChannelQos, String> {
+        let backpressure = match (self.backpressure, self.reliability) {
+            (Some(backpressure), _) => backpressure,
+            (None, Some(Reliability::Reliable)) => Backpressure::Expand,
+            (None, _) => Backpressure::DropOldest,
+        };
+        let reliability = backpressure.reliability();
+        if let Some(expected) = self.reliability {
+            if expected != reliability {
+                return Err(format!(
+                    "{:?} backpressure is not compatible with {:?} delivery",
+                    backpressure, expected
+                ));
+            }
+        }
+        if backpressure == Backpressure::Expand && self.depth.is_some() {
+            return Err("Expanding queues buffer every pending message".to_string());
+        }
+        if reliability == Reliability::Reliable && self.max_rate.is_some() {
+            return Err("Reliable channels can't drop messages above a rate".to_string());
+        }
+        let depth = self.depth.unwrap_or(DEFAULT_DEPTH);
+        if depth == 0 {
+            return Err("Channel depth must be greater than 0".to_string());
+        }
+        if let Some(max_rate) = self.max_rate {
+            if !max_rate.is_finite() || max_rate <= 0.0 {
+                return Err(format!("Invalid channel max rate {}", max_rate));
+            }
+        }
+        Ok(ChannelQos {
+            reliability,
+            backpressure,
+            depth,
+            max_rate: self.max_rate,
+        })
+    }
+}
+
+impl Default for ChannelQosBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_best_effort() {
+        let qos = ChannelQosBuilder::new()
+            .depth(1)
+            .max_rate(50.0)
+            .build()
+            .unwrap();
+        assert_eq!(qos.reliability(), Reliability::BestEffort);
+        assert_eq!(qos.backpressure(), Backpressure::DropOldest);
+        assert_eq!(qos.depth(), 1);
+        assert_eq!(qos.min_interval(), Some(Duration::from_millis(20)));
+    }
+
+    #[test]
+    fn test_invalid_qos() {
+        let reliable = ChannelQosBuilder::new().reliability(Reliability::Reliable);
+        assert!(reliable.build().is_ok());
+        assert!(reliable.depth(10).build().is_err());
+        assert!(reliable.max_rate(10.0).build().is_err());
+        assert!(ChannelQosBuilder::new().depth(0).build().is_err());
+        assert!(ChannelQosBuilder::new().max_rate(0.0).build().is_err());
+        assert!(ChannelQosBuilder::new().max_rate(f64::NAN).build().is_err());
+    }
+
+    #[test]
+    fn test_backpressure() {
+        let reliable = ChannelQosBuilder::new()
+            .reliability(Reliability::Reliable)
+            .build()
+            .unwrap();
+        assert_eq!(reliable.backpressure(), Backpressure::Expand);
+
+        let blocking = ChannelQosBuilder::new()
+            .backpressure(Backpressure::Block)
+            .depth(10)
+            .build()
+            .unwrap();
+        assert_eq!(blocking.reliability(), Reliability::Reliable);
+        assert_eq!(blocking.depth(), 10);
+
+        assert!(ChannelQosBuilder::new()
+            .reliability(Reliability::Reliable)
+            .backpressure(Backpressure::DropNewest)
+            .build()
+            .is_err());
+        assert!(ChannelQosBuilder::new()
+            .reliability(Reliability::BestEffort)
+            .backpressure(Backpressure::Expand)
+            .build()
+            .is_err());
+    }
+}
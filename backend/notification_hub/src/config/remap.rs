@@ -0,0 +1,211 @@
+use log::warn;
+use std::collections::HashMap;
+
+use crate::models::hub::HubChannelName;
+
+/// `RemapRules` translates between the channel names used by a hub node and the channel
+/// names seen in the hub, so devices with fixed firmware channel names can be integrated
+/// into a structured namespace.
+///
+/// # Fields
+/// - `aliases`: Node channel names renamed in the hub (e.g. `imu_raw` to `sensors/imu`).
+/// - `prefix`: Namespace prepended to every other channel of the node (e.g. `robot2`
+///   maps `imu` to `robot2/imu`).
+///
+/// Rules are applied on ingest, to messages and channels coming from the node, and reversed on
+/// egress, to messages and subscriptions going to the node. Hub channels outside of the prefix
+/// namespace are passed to the node unchanged.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RemapRules {
+    aliases: HashMap<HubChannelName, HubChannelName>,
+    reverse_aliases: HashMap<HubChannelName, HubChannelName>,
+    prefix: Option<HubChannelName>,
+}
+
+impl RemapRules {
+    pub fn is_empty(&self) -> bool {
+        self.aliases.is_empty() && self.prefix.is_none()
+    }
+
+    pub fn prefix(&self) -> Option<&str> {
+        self.prefix.as_ref().map(HubChannelName::as_str)
+    }
+
+    /// Returns rules with the aliases of both `self` and `other`. Aliases and prefix of `other`
+    /// take precedence.
+    pub fn merge(&self, other: &RemapRules) -> RemapRules {
+        let mut merged = self.clone();
+        for (node_channel, hub_channel) in &other.aliases {
+            merged.add_alias(node_channel.clone(), hub_channel.clone());
+        }
+        if other.prefix.is_some() {
+            merged.prefix = other.prefix.clone();
+        }
+        merged
+    }
+
+    fn add_alias(&mut self, node_channel: HubChannelName, hub_channel: HubChannelName) {
+        if let Some(previous) = self
+            .aliases
+            .insert(node_channel.clone(), hub_channel.clone())
+        {
+            self.reverse_aliases.remove(&previous);
+        }
+        self.reverse_aliases.insert(hub_channel, node_channel);
+    }
+
+    /// Maps a channel name used by the node to the hub namespace
+    pub fn ingest(&self, channel: &HubChannelName) -> HubChannelName {
+        if let Some(alias) = self.aliases.get(channel) {
+            return alias.clone();
+        }
+        let Some(prefix) = &self.prefix else {
+            return channel.clone();
+        };
+        prefix.join(channel).unwrap_or_else(|e| {
+            warn!("Channel {} can't be remapped: {}", channel.as_str(), e);
+            channel.clone()
+        })
+    }
+
+    /// Maps a hub channel name to the name used by the node
+    pub fn egress(&self, channel: &HubChannelName) -> HubChannelName {
+        if let Some(node_channel) = self.reverse_aliases.get(channel) {
+            return node_channel.clone();
+        }
+        self.prefix
+            .as_ref()
+            .and_then(|prefix| channel.strip_namespace(prefix))
+            .unwrap_or_else(|| channel.clone())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RemapRulesBuilder {
+    aliases: Vec<(String, String)>,
+    prefix: Option<String>,
+}
+
+impl RemapRulesBuilder {
+    pub fn new() -> Self {
+        Self {
+            aliases: Vec::new(),
+            prefix: None,
+        }
+    }
+
+    pub fn alias(&self, node_channel: &str, hub_channel: &str) -> Self {
+        let mut new = self.clone();
+        new.aliases
+            .push((node_channel.to_string(), hub_channel.to_string()));
+        new
+    }
+    pub fn prefix(&self, prefix: &str) -> Self {
+        let mut new = self.clone();
+        new.prefix = Some(prefix.to_string());
+        new
+    }
+    pub fn build(self) -> Result<RemapRules, String> {
+        let mut rules = RemapRules::default();
+        for (node_channel, hub_channel) in self.aliases {
+            let node_channel = HubChannelName::try_from(node_channel)?;
+            let hub_channel = HubChannelName::try_from(hub_channel)?;
+            if rules.reverse_aliases.contains_key(&hub_channel) {
+                return Err(format!(
+                    "Channel {} is the alias of more than one channel",
+                    hub_channel.as_str()
+                ));
+            }
+            rules.add_alias(node_channel, hub_channel);
+        }
+        if let Some(prefix) = self.prefix {
+            // prefix must be a valid namespace on its own
+            rules.prefix = Some(HubChannelName::try_from(prefix)?);
+        }
+        Ok(rules)
+    }
+}
+
+impl Default for RemapRulesBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(name: &str) -> HubChannelName {
+        HubChannelName::try_from(name).unwrap()
+    }
+
+    #[test]
+    fn test_alias() {
+        let rules = RemapRulesBuilder::new()
+            .alias("imu_raw", "sensors/imu")
+            .build()
+            .unwrap();
+        assert_eq!(rules.ingest(&channel("imu_raw")), channel("sensors/imu"));
+        assert_eq!(rules.egress(&channel("sensors/imu")), channel("imu_raw"));
+        assert_eq!(rules.ingest(&channel("odometry")), channel("odometry"));
+        assert_eq!(rules.egress(&channel("odometry")), channel("odometry"));
+    }
+
+    #[test]
+    fn test_prefix() {
+        let rules = RemapRulesBuilder::new()
+            .alias("imu_raw", "sensors/imu")
+            .prefix("robot2")
+            .build()
+            .unwrap();
+        assert_eq!(rules.ingest(&channel("imu_raw")), channel("sensors/imu"));
+        assert_eq!(
+            rules.ingest(&channel("odometry")),
+            channel("robot2/odometry")
+        );
+        assert_eq!(
+            rules.egress(&channel("robot2/odometry")),
+            channel("odometry")
+        );
+        assert_eq!(
+            rules.egress(&channel("robot22/odometry")),
+            channel("robot22/odometry")
+        );
+        assert_eq!(rules.egress(&channel("robot2")), channel("robot2"));
+    }
+
+    #[test]
+    fn test_merge() {
+        let global = RemapRulesBuilder::new()
+            .alias("imu_raw", "sensors/imu")
+            .build()
+            .unwrap();
+        let node = RemapRulesBuilder::new()
+            .alias("imu_raw", "sensors/imu2")
+            .prefix("robot2")
+            .build()
+            .unwrap();
+        let rules = global.merge(&node);
+        assert_eq!(rules.ingest(&channel("imu_raw")), channel("sensors/imu2"));
+        assert_eq!(
+            rules.egress(&channel("sensors/imu")),
+            channel("sensors/imu")
+        );
+        assert_eq!(rules.prefix(), Some("robot2"));
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        assert!(RemapRulesBuilder::new()
+            .alias("imu_raw", "sensors//imu")
+            .build()
+            .is_err());
+        assert!(RemapRulesBuilder::new().prefix("robot2/").build().is_err());
+        assert!(RemapRulesBuilder::new()
+            .alias("imu1", "sensors/imu")
+            .alias("imu2", "sensors/imu")
+            .build()
+            .is_err());
+    }
+}
@@ -0,0 +1,173 @@
+use std::future::Future;
+use std::time::Duration;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+const DEFAULT_RETRIES: usize = 2;
+const DEFAULT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// `RetryPolicy` bounds the time the hub waits for an operation of a hub node (`send`,
+/// `subscribe`, `list_channels`), so a hung serial port or a dead WebSocket connection can't
+/// stall the callers of the hub.
+///
+/// # Fields
+/// - `timeout`: Maximum duration of every attempt. Attempts taking longer fail with
+///   `ErrorKind::TimedOut`.
+/// - `retries`: Number of times a failed or timed out operation is attempted again.
+/// - `backoff`: Delay before the first retry, doubled after every retry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    timeout: Duration,
+    retries: usize,
+    backoff: Duration,
+}
+
+impl RetryPolicy {
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+    pub fn retries(&self) -> usize {
+        self.retries
+    }
+    pub fn backoff(&self) -> Duration {
+        self.backoff
+    }
+
+    /// Runs the operation returned by `attempt` until it succeeds, or the retries are
+    /// exhausted. Returns the error of the last attempt
+    pub async fn run<T, F, Fut>(&self, mut attempt: F) -> Result<T, std::io::Error>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, std::io::Error>>,
+    {
+        let mut backoff = self.backoff;
+        let mut retries = self.retries;
+        loop {
+            let error = match tokio::time::timeout(self.timeout, attempt()).await {
+                Ok(Ok(value)) => return Ok(value),
+                Ok(Err(e)) => e,
+                Err(_) => std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    format!("Operation timed out after {:?}", self.timeout),
+                ),
+            };
+            if retries == 0 {
+                return Err(error);
+            }
+            retries -= 1;
+            tokio::time::sleep(backoff).await;
+            backoff = backoff.saturating_mul(2);
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            timeout: DEFAULT_TIMEOUT,
+            retries: DEFAULT_RETRIES,
+            backoff: DEFAULT_BACKOFF,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicyBuilder {
+    timeout: Option<Duration>,
+    retries: Option<usize>,
+    backoff: Option<Duration>,
+}
+
+impl RetryPolicyBuilder {
+    pub fn new() -> Self {
+        Self {
+            timeout: None,
+            retries: None,
+            backoff: None,
+        }
+    }
+
+    pub fn timeout(&self, timeout: Duration) -> Self {
+        let mut new = self.clone();
+        new.timeout = Some(timeout);
+        new
+    }
+    pub fn retries(&self, retries: usize) -> Self {
+        let mut new = self.clone();
+        new.retries = Some(retries);
+        new
+    }
+    pub fn backoff(&self, backoff: Duration) -> Self {
+        let mut new = self.clone();
+        new.backoff = Some(backoff);
+        new
+    }
+    pub fn build(self) -> Result<RetryPolicy, String> {
+        let timeout = self.timeout.unwrap_or(DEFAULT_TIMEOUT);
+        if timeout.is_zero() {
+            return Err("Retry timeout must be greater than 0".to_string());
+        }
+        Ok(RetryPolicy {
+            timeout,
+            retries: self.retries.unwrap_or(DEFAULT_RETRIES),
+            backoff: self.backoff.unwrap_or(DEFAULT_BACKOFF),
+        })
+    }
+}
+
+impl Default for RetryPolicyBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_retry_policy() {
+        let policy = RetryPolicyBuilder::new().retries(0).build().unwrap();
+        assert_eq!(policy.timeout(), DEFAULT_TIMEOUT);
+        assert_eq!(policy.retries(), 0);
+        assert_eq!(
+            RetryPolicyBuilder::new().build().unwrap(),
+            RetryPolicy::default()
+        );
+        assert!(RetryPolicyBuilder::new()
+            .timeout(Duration::ZERO)
+            .build()
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_retries_until_success() {
+        let policy = RetryPolicyBuilder::new()
+            .timeout(Duration::from_millis(20))
+            .retries(2)
+            .backoff(Duration::from_millis(1))
+            .build()
+            .unwrap();
+        let attempts = &AtomicUsize::new(0);
+        // first attempt hangs, second fails, third succeeds
+        let result = policy
+            .run(move || async move {
+                match attempts.fetch_add(1, Ordering::SeqCst) {
+                    0 => std::future::pending().await,
+                    1 => Err(std::io::Error::new(
+                        std::io::ErrorKind::BrokenPipe,
+                        "failed",
+                    )),
+                    attempt => Ok(attempt),
+                }
+            })
+            .await;
+        assert_eq!(result.unwrap(), 2);
+
+        let error = policy
+            .run(std::future::pending::<Result<(), std::io::Error>>)
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
+    }
+}
@@ -0,0 +1,223 @@
+use tokio::runtime::{Builder, Runtime};
+
+const ENV_FLAVOR: &str = "ROBOPILOT_RUNTIME_FLAVOR";
+const ENV_WORKER_THREADS: &str = "ROBOPILOT_WORKER_THREADS";
+const ENV_BLOCKING_THREADS: &str = "ROBOPILOT_BLOCKING_THREADS";
+
+/// Tokio scheduler used by the backend.
+/// - `RuntimeFlavor::CurrentThread` runs every task on the main thread. Intended for
+///   small single core boards (e.g. Pi Zero).
+/// - `RuntimeFlavor::MultiThread` uses a work stealing pool of worker threads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuntimeFlavor {
+    CurrentThread,
+    MultiThread,
+}
+
+impl TryFrom<&str> for RuntimeFlavor {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "current_thread" => Ok(RuntimeFlavor::CurrentThread),
+            "multi_thread" => Ok(RuntimeFlavor::MultiThread),
+            other => Err(format!("Invalid runtime flavor: {}", other)),
+        }
+    }
+}
+
+/// `RuntimeOptions` configures the tokio executor the backend runs on.
+///
+/// # Fields
+/// - `flavor`: Scheduler flavor.
+/// - `worker_threads`: Number of worker threads. Only valid for the multi thread flavor.
+///   Defaults to the number of cores.
+/// - `max_blocking_threads`: Size of the blocking thread pool, used among others for
+///   serial port I/O.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeOptions {
+    flavor: RuntimeFlavor,
+    worker_threads: Option<usize>,
+    max_blocking_threads: Option<usize>,
+}
+
+impl Default for RuntimeOptions {
+    fn default() -> Self {
+        Self {
+            flavor: RuntimeFlavor::MultiThread,
+            worker_threads: None,
+            max_blocking_threads: None,
+        }
+    }
+}
+
+impl RuntimeOptions {
+    pub fn flavor(&self) -> RuntimeFlavor {
+        self.flavor
+    }
+    pub fn worker_threads(&self) -> Option<usize> {
+        self.worker_threads
+    }
+    pub fn max_blocking_threads(&self) -> Option<usize> {
+        self.max_blocking_threads
+    }
+
+    /// Reads runtime options from environment variables:
+    /// - `ROBOPILOT_RUNTIME_FLAVOR`: `current_thread` or `multi_thread`
+    /// - `ROBOPILOT_WORKER_THREADS`: number of worker threads
+    /// - `ROBOPILOT_BLOCKING_THREADS`: max number of blocking threads
+    pub fn from_env() -> Result<Self, String> {
+        Self::from_lookup(|key| std::env::var(key).ok())
+    }
+
+    fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Self, String> {
+        let mut builder = RuntimeOptionsBuilder::new();
+        if let Some(flavor) = lookup(ENV_FLAVOR) {
+            builder = builder.flavor(RuntimeFlavor::try_from(flavor.as_str())?);
+        }
+        if let Some(worker_threads) = lookup(ENV_WORKER_THREADS) {
+            builder = builder.worker_threads(parse_count(ENV_WORKER_THREADS, &worker_threads)?);
+        }
+        if let Some(blocking_threads) = lookup(ENV_BLOCKING_THREADS) {
+            builder =
+                builder.max_blocking_threads(parse_count(ENV_BLOCKING_THREADS, &blocking_threads)?);
+        }
+        builder.build()
+    }
+
+    /// Builds a tokio runtime with these options
+    pub fn build_runtime(&self) -> Result<Runtime, std::io::Error> {
+        let mut builder = match self.flavor {
+            RuntimeFlavor::CurrentThread => Builder::new_current_thread(),
+            RuntimeFlavor::MultiThread => Builder::new_multi_thread(),
+        };
+        if let Some(worker_threads) = self.worker_threads {
+            builder.worker_threads(worker_threads);
+        }
+        if let Some(max_blocking_threads) = self.max_blocking_threads {
+            builder.max_blocking_threads(max_blocking_threads);
+        }
+        builder.enable_all().build()
+    }
+}
+
+fn parse_count(key: &str, value: &str) -> Result<usize, String> {
+    value
+        .trim()
+        .parse::<usize>()
+        .map_err(|e| format!("Invalid value for {}: {}", key, e))
+}
+
+#[derive(Debug, Clone)]
+pub struct RuntimeOptionsBuilder {
+    flavor: Option<RuntimeFlavor>,
+    worker_threads: Option<usize>,
+    max_blocking_threads: Option<usize>,
+}
+
+impl RuntimeOptionsBuilder {
+    pub fn new() -> Self {
+        Self {
+            flavor: None,
+            worker_threads: None,
+            max_blocking_threads: None,
+        }
+    }
+
+    pub fn flavor(&self, flavor: RuntimeFlavor) -> Self {
+        let mut new = self.clone();
+        new.flavor = Some(flavor);
+        new
+    }
+    pub fn worker_threads(&self, worker_threads: usize) -> Self {
+        let mut new = self.clone();
+        new.worker_threads = Some(worker_threads);
+        new
+    }
+    pub fn max_blocking_threads(&self, max_blocking_threads: usize) -> Self {
+        let mut new = self.clone();
+        new.max_blocking_threads = Some(max_blocking_threads);
+        new
+    }
+    pub fn build(self) -> Result<RuntimeOptions, String> {
+        let flavor = self.flavor.unwrap_or(RuntimeFlavor::MultiThread);
+        if self.worker_threads == Some(0) || self.max_blocking_threads == Some(0) {
+            return Err("Number of threads must be greater than 0".to_string());
+        }
+        if flavor == RuntimeFlavor::CurrentThread && self.worker_threads.is_some() {
+            return Err("Worker threads can't be configured in current thread runtime".to_string());
+        }
+        Ok(RuntimeOptions {
+            flavor,
+            worker_threads: self.worker_threads,
+            max_blocking_threads: self.max_blocking_threads,
+        })
+    }
+}
+
+impl Default for RuntimeOptionsBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_default_options() {
+        let options = RuntimeOptionsBuilder::new().build().unwrap();
+        assert_eq!(options, RuntimeOptions::default());
+    }
+
+    #[test]
+    fn test_current_thread_with_workers_is_invalid() {
+        let options = RuntimeOptionsBuilder::new()
+            .flavor(RuntimeFlavor::CurrentThread)
+            .worker_threads(2)
+            .build();
+        assert!(options.is_err());
+    }
+
+    #[test]
+    fn test_zero_threads_is_invalid() {
+        assert!(RuntimeOptionsBuilder::new()
+            .worker_threads(0)
+            .build()
+            .is_err());
+        assert!(RuntimeOptionsBuilder::new()
+            .max_blocking_threads(0)
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_from_lookup() {
+        let env = HashMap::from([(ENV_FLAVOR, "current_thread"), (ENV_BLOCKING_THREADS, "4")]);
+        let options =
+            RuntimeOptions::from_lookup(|key| env.get(key).map(|v| v.to_string())).unwrap();
+        assert_eq!(options.flavor(), RuntimeFlavor::CurrentThread);
+        assert_eq!(options.worker_threads(), None);
+        assert_eq!(options.max_blocking_threads(), Some(4));
+    }
+
+    #[test]
+    fn test_from_lookup_invalid_value() {
+        let env = HashMap::from([(ENV_WORKER_THREADS, "many")]);
+        let options = RuntimeOptions::from_lookup(|key| env.get(key).map(|v| v.to_string()));
+        assert!(options.is_err());
+    }
+
+    #[test]
+    fn test_build_current_thread_runtime() {
+        let options = RuntimeOptionsBuilder::new()
+            .flavor(RuntimeFlavor::CurrentThread)
+            .max_blocking_threads(1)
+            .build()
+            .unwrap();
+        let runtime = options.build_runtime().unwrap();
+        assert_eq!(runtime.block_on(async { 1 + 1 }), 2);
+    }
+}
@@ -0,0 +1,72 @@
+//! Entry points of the fuzz targets in `fuzz/`. Parsers fed with bytes received from serial
+//! ports, LoRa modems, XBee modules or WebSocket peers must reject invalid input without panicking.
+
+use bytes::Bytes;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+use crate::adapters::lora::codec::FrameParser;
+use crate::adapters::lora::LoraEncoding;
+use crate::adapters::serial::cobs;
+use crate::adapters::serial::message::SerialRawMessage;
+use crate::adapters::websocket::WsEncoding;
+use crate::adapters::xbee::frame;
+use crate::models::hub::{HubData, HubMessage};
+
+/// Checks the CRC of a line received from a serial port, and parses it. Decodes it as a COBS
+/// frame as well
+pub fn serial_message(data: &[u8]) {
+    let _ = cobs::decode_message(data);
+    let line = SerialRawMessage::from_bytes(Bytes::copy_from_slice(data));
+    let _ = line.verify_crc();
+    let _ = HubMessage::try_from(line);
+}
+
+/// Parses message data
+pub fn hub_data(data: &[u8]) {
+    if let Ok(data) = std::str::from_utf8(data) {
+        let _ = data.parse::<HubData>();
+    }
+}
+
+/// Decodes a WebSocket message received as a binary frame with every encoding, and as a text
+/// frame if valid UTF-8
+pub fn ws_message(data: &[u8]) {
+    for encoding in [WsEncoding::Binary, WsEncoding::MsgPack] {
+        let _ = encoding.decode(Message::Binary(data.to_vec()));
+    }
+    if let Ok(text) = std::str::from_utf8(data) {
+        let _ = WsEncoding::Json.decode(Message::Text(text.to_string()));
+    }
+}
+
+/// Extracts frames from bytes received from a LoRa modem, and expands their payloads with
+/// every encoding
+pub fn lora_frame(data: &[u8]) {
+    let mut parser = FrameParser::new();
+    parser.push(data);
+    while let Some(frame) = parser.next_frame() {
+        if let Ok((_, payload)) = frame {
+            for encoding in [
+                LoraEncoding::Text,
+                LoraEncoding::F32,
+                LoraEncoding::Quantized { scale: 0.01 },
+            ] {
+                let _ = encoding.decode(&payload);
+            }
+        }
+    }
+}
+
+/// Extracts API frames from bytes received from an XBee module, in both API modes, and parses
+/// the payloads of received packets
+pub fn xbee_frame(data: &[u8]) {
+    for escaped in [false, true] {
+        let mut parser = frame::FrameParser::new(escaped);
+        parser.push(data);
+        while let Some(api_frame) = parser.next_frame() {
+            if let Ok(frame::ApiFrame::ReceivePacket { data, .. }) = api_frame {
+                serial_message(&data);
+            }
+        }
+    }
+}
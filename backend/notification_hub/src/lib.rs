@@ -1,4 +1,7 @@
 pub mod adapters;
+pub mod config;
+#[doc(hidden)]
+pub mod fuzz;
 pub mod models;
 pub mod ports;
 pub mod services;
@@ -1,26 +1,65 @@
-use notification_hub::adapters::serial::SerialClient;
-use notification_hub::adapters::websocket::WebSocketClient;
-use notification_hub::services::hub::HubManager;
+use notification_hub::adapters::serial::{PortFilter, SerialClient, SerialOptions};
+use notification_hub::adapters::websocket::WebSocketServer;
+use notification_hub::config::{NodeConfigBuilder, RuntimeOptions};
+use notification_hub::services::hub::HubManagerBuilder;
 
+use std::time::Duration;
 use tokio::signal::ctrl_c;
 
-mod adapters;
-mod models;
-mod ports;
-mod services;
+// Serial port checked for loopback at startup, e.g. a port with its TX and RX pins jumpered
+const ENV_SERIAL_SELF_TEST: &str = "ROBOPILOT_SERIAL_SELF_TEST";
+// URL of a running WebSocket server to connect to, instead of starting one
+const ENV_WS_URL: &str = "ROBOPILOT_WS_URL";
+// Address of the WebSocket server started when `ROBOPILOT_WS_URL` is not set
+const LOCAL_WS_ADDR: &str = "localhost:8080";
 
-#[tokio::main]
-async fn main() -> std::io::Result<()> {
+fn main() -> std::io::Result<()> {
     env_logger::init();
-    let mut hub = HubManager::new();
-    if let Ok(serial_client) = SerialClient::new("/dev/ttyACM0", 9600) {
-        hub.add(Box::new(serial_client));
-    }
-    if let Ok(ws_client) = WebSocketClient::new("localhost:8080").await {
-        hub.add(Box::new(ws_client));
-    }
+    let runtime_options = RuntimeOptions::from_env()
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let runtime = runtime_options.build_runtime()?;
+    runtime.block_on(run())
+}
+
+// Runs the loopback self-test of the serial port in `ROBOPILOT_SERIAL_SELF_TEST`, if set
+async fn diagnostics() -> std::io::Result<()> {
+    let Ok(port) = std::env::var(ENV_SERIAL_SELF_TEST) else {
+        return Ok(());
+    };
+    let client = SerialClient::new(&port, 9600, SerialOptions::default())?;
+    let round_trip = client.self_test(Duration::from_secs(1)).await?;
+    println!("Serial port {} self-test passed in {:?}", port, round_trip);
+    Ok(())
+}
 
-    hub.start().await?;
+async fn run() -> std::io::Result<()> {
+    diagnostics().await?;
+    let (_ws_server, ws_url) = match std::env::var(ENV_WS_URL) {
+        Ok(url) => (None, url),
+        Err(_) => {
+            let server = WebSocketServer::new(LOCAL_WS_ADDR);
+            server.start().await?;
+            (Some(server), LOCAL_WS_ADDR.to_string())
+        }
+    };
+    let invalid_input = |e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e);
+    let serial = NodeConfigBuilder::new()
+        .name("serial")
+        .serial_discover(PortFilter::banner(), 9600)
+        .optional(true)
+        .build()
+        .map_err(invalid_input)?;
+    let ws = NodeConfigBuilder::new()
+        .name("ws")
+        .websocket(&ws_url)
+        .optional(true)
+        .build()
+        .map_err(invalid_input)?;
+    let _hub = HubManagerBuilder::new()
+        .node(serial)
+        .node(ws)
+        .build()
+        .await?;
 
     println!("Press Ctrl+C to exit...");
     ctrl_c().await?;
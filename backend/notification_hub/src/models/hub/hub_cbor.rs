@@ -0,0 +1,141 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::{HubChannelName, HubData, HubMessage, HubTimestamp};
+
+/// CBOR codec for `HubMessage`s, an alternative to JSON on bandwidth-constrained links
+/// (e.g. LoRa, 9600-baud serial) that keeps a self-describing, standard format.
+///
+/// A frame is a CBOR array of messages, and every message a CBOR array with its channel name,
+/// wall clock timestamp and data, so field names are not repeated in every message. Messages
+/// with headers carry them in a map after their data:
+///
+/// ```text
+/// [[channel (text), timestamp (float), data (text), {key (text): value (text)}?], ...]
+/// ```
+/// Timestamps are encoded with the smallest float type representing them exactly.
+///
+/// `encode` packs a list of messages into a single CBOR frame.
+pub fn encode(messages: &[HubMessage]) -> Result<Vec<u8>, String> {
+    let items: Vec<_> = messages
+        .iter()
+        .map(|message| {
+            let (channel, timestamp, data) = (
+                message.channel.as_str(),
+                message.timestamp.wall,
+                message.data.as_str(),
+            );
+            if message.headers.is_empty() {
+                CborItemRef::Plain(channel, timestamp, data)
+            } else {
+                CborItemRef::WithHeaders(channel, timestamp, data, &message.headers)
+            }
+        })
+        .collect();
+    let mut frame = Vec::new();
+    ciborium::into_writer(&items, &mut frame)
+        .map_err(|e| format!("Error encoding CBOR frame: {}", e))?;
+    Ok(frame)
+}
+
+/// Decodes a CBOR frame into the list of messages it contains
+pub fn decode(bytes: &[u8]) -> Result<Vec<HubMessage>, String> {
+    let mut reader = bytes;
+    let items: Vec<CborItem> =
+        ciborium::from_reader(&mut reader).map_err(|e| format!("Invalid CBOR frame: {}", e))?;
+    if !reader.is_empty() {
+        return Err("Invalid CBOR frame: trailing bytes".to_string());
+    }
+    items
+        .into_iter()
+        .map(|item| {
+            let (channel, timestamp, data, headers) = match item {
+                CborItem::Plain(channel, timestamp, data) => {
+                    (channel, timestamp, data, HashMap::new())
+                }
+                CborItem::WithHeaders(channel, timestamp, data, headers) => {
+                    (channel, timestamp, data, headers)
+                }
+            };
+            Ok(HubMessage {
+                channel: HubChannelName::try_from(channel.as_str())?,
+                timestamp: HubTimestamp::from_wall(timestamp),
+                data: data.parse::<HubData>()?,
+                headers,
+                origin: None,
+            })
+        })
+        .collect()
+}
+
+// Encoded message, borrowing the fields of the message. Headers are left out when empty
+#[derive(Serialize)]
+#[serde(untagged)]
+enum CborItemRef<'a> {
+    Plain(&'a str, f64, &'a str),
+    WithHeaders(&'a str, f64, &'a str, &'a HashMap<String, String>),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CborItem {
+    WithHeaders(String, f64, String, HashMap<String, String>),
+    Plain(String, f64, String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_decode_roundtrip() {
+        let messages = vec![
+            HubMessage::try_from_str("imu", "0.1,0.2,9.8").unwrap(),
+            HubMessage::try_from_str("status", "armed").unwrap(),
+        ];
+        let frame = encode(&messages).unwrap();
+        let decoded = decode(&frame).unwrap();
+
+        assert_eq!(decoded.len(), 2);
+        for (message, decoded) in messages.iter().zip(decoded.iter()) {
+            assert_eq!(message.channel, decoded.channel);
+            assert_eq!(message.data, decoded.data);
+            assert_eq!(message.timestamp.wall, decoded.timestamp.wall);
+        }
+        assert!(decode(&encode(&[]).unwrap()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_headers_roundtrip() {
+        let messages = vec![
+            HubMessage::try_from_str("imu", "0.1,0.2,9.8")
+                .unwrap()
+                .with_header("frame_id", "imu_link"),
+            HubMessage::try_from_str("status", "armed").unwrap(),
+        ];
+        let decoded = decode(&encode(&messages).unwrap()).unwrap();
+        assert_eq!(decoded[0].headers, messages[0].headers);
+        assert!(decoded[1].headers.is_empty());
+    }
+
+    #[test]
+    fn test_frame_is_smaller_than_json() {
+        let message = HubMessage::try_from_str("imu", "0.1,0.2,9.8").unwrap();
+        let frame = encode(std::slice::from_ref(&message)).unwrap();
+        assert!(frame.len() < message.to_bytes().unwrap().len());
+    }
+
+    #[test]
+    fn test_decode_invalid_frames() {
+        let frame = encode(&[HubMessage::try_from_str("imu", "1").unwrap()]).unwrap();
+        assert!(decode(&frame[..frame.len() - 1]).is_err());
+
+        let mut trailing = frame.clone();
+        trailing.push(0);
+        assert!(decode(&trailing).is_err());
+
+        let mut invalid_channel = Vec::new();
+        ciborium::into_writer(&vec![("bad channel!", 0.0, "1")], &mut invalid_channel).unwrap();
+        assert!(decode(&invalid_channel).is_err());
+    }
+}
@@ -1,20 +1,155 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashSet;
+use std::sync::{Arc, OnceLock, RwLock};
+
+/// Maximum length in bytes of a channel name
+pub const MAX_CHANNEL_NAME_LEN: usize = 64;
+/// Separator of the namespace segments of a channel name
+pub const NAMESPACE_SEPARATOR: char = '/';
 
 /// Represents a channel name in the hub.
 ///
 /// This struct ensures that the channel name adheres to specific rules:
-/// - Only alphanumeric characters and underscores are allowed.
+/// - The channel name is not empty, and is at most `MAX_CHANNEL_NAME_LEN` bytes long.
+/// - Only alphanumeric characters, underscores and '/' are allowed.
+/// - '/' separates namespace segments (e.g. `sensors/imu`). Segments can't be empty.
 /// - No spaces are allowed in the middle of the string.
 /// - Leading and trailing whitespaces, newlines, and carriage returns are trimmed.
 /// - The channel name is converted to lowercase.
 ///
+/// Channel names are interned: every `HubChannelName` with the same name shares the same
+/// allocation, so cloning is a reference count increment and equality checks in the
+/// dispatch path short-circuit on pointer equality. Deserialized names are validated
+/// like any other name, so that peers can't intern arbitrary strings. Names no longer
+/// referenced by any `HubChannelName` are evicted from the registry, so that peers sending
+/// ever changing names don't grow it without bound.
 
-#[derive(Serialize, Debug, Clone, Deserialize, PartialEq, Eq, Hash)]
-pub struct HubChannelName(String);
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct HubChannelName(Arc<str>);
 
 impl HubChannelName {
     pub fn as_str(&self) -> &str {
-        self.0.as_str()
+        &self.0
+    }
+
+    /// Segments of the name, from the top-level namespace to the base name
+    pub fn segments(&self) -> impl Iterator<Item = &str> {
+        self.0.split(NAMESPACE_SEPARATOR)
+    }
+
+    /// Last segment of the name (e.g. `imu` for `robot1/sensors/imu`)
+    pub fn base_name(&self) -> &str {
+        self.0
+            .rsplit_once(NAMESPACE_SEPARATOR)
+            .map_or(&self.0, |(_, base_name)| base_name)
+    }
+
+    /// Namespace of the channel (e.g. `robot1/sensors` for `robot1/sensors/imu`), `None` for
+    /// channels without namespace
+    pub fn namespace(&self) -> Option<HubChannelName> {
+        let (namespace, _) = self.0.rsplit_once(NAMESPACE_SEPARATOR)?;
+        Some(HubChannelName(intern(namespace)))
+    }
+
+    /// Top-level namespace of the channel (e.g. `robot1` for `robot1/sensors/imu`), `None` for
+    /// channels without namespace
+    pub fn root_namespace(&self) -> Option<HubChannelName> {
+        let (namespace, _) = self.0.split_once(NAMESPACE_SEPARATOR)?;
+        Some(HubChannelName(intern(namespace)))
+    }
+
+    /// Whether the channel is nested in `namespace`. Only whole segments match, so
+    /// `robot10/imu` is not in namespace `robot1`.
+    pub fn is_in_namespace(&self, namespace: &HubChannelName) -> bool {
+        self.strip_namespace_str(namespace).is_some()
+    }
+
+    /// Channel `name` nested in namespace `self` (e.g. `robot1/sensors/imu` for namespace
+    /// `robot1` and name `sensors/imu`). Fails if the joined name is too long.
+    pub fn join(&self, name: &HubChannelName) -> Result<HubChannelName, String> {
+        HubChannelName::try_from(format!(
+            "{}{}{}",
+            self.as_str(),
+            NAMESPACE_SEPARATOR,
+            name.as_str()
+        ))
+    }
+
+    /// Name of the channel relative to `namespace`, `None` if not nested in it
+    pub fn strip_namespace(&self, namespace: &HubChannelName) -> Option<HubChannelName> {
+        self.strip_namespace_str(namespace)
+            .map(|name| HubChannelName(intern(name)))
+    }
+
+    fn strip_namespace_str(&self, namespace: &HubChannelName) -> Option<&str> {
+        self.0
+            .strip_prefix(namespace.as_str())?
+            .strip_prefix(NAMESPACE_SEPARATOR)
+    }
+}
+
+// Minimum number of interned names before unused names are evicted
+const MIN_PRUNE_LEN: usize = 1024;
+
+// Interned channel names. Unused names are evicted when the registry reaches `prune_len`
+// names, which is then set to twice the names still in use, so eviction is amortized
+struct Registry {
+    names: HashSet<Arc<str>>,
+    prune_len: usize,
+}
+
+impl Registry {
+    fn insert(&mut self, name: &str) -> Arc<str> {
+        if self.names.len() >= self.prune_len {
+            // names only referenced by the registry are not used by any `HubChannelName`.
+            // Readers clone names under the read lock, so counts can't grow while pruning
+            self.names.retain(|name| Arc::strong_count(name) > 1);
+            self.prune_len = (2 * self.names.len()).max(MIN_PRUNE_LEN);
+        }
+        let interned: Arc<str> = Arc::from(name);
+        self.names.insert(Arc::clone(&interned));
+        interned
+    }
+}
+
+fn registry() -> &'static RwLock<Registry> {
+    static REGISTRY: OnceLock<RwLock<Registry>> = OnceLock::new();
+    REGISTRY.get_or_init(|| {
+        RwLock::new(Registry {
+            names: HashSet::new(),
+            prune_len: MIN_PRUNE_LEN,
+        })
+    })
+}
+
+/// Global registry of interned channel names
+fn intern(name: &str) -> Arc<str> {
+    let registry = registry();
+    if let Some(interned) = registry
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .names
+        .get(name)
+    {
+        return Arc::clone(interned);
+    }
+    let mut registry = registry.write().unwrap_or_else(|e| e.into_inner());
+    if let Some(interned) = registry.names.get(name) {
+        return Arc::clone(interned);
+    }
+    registry.insert(name)
+}
+
+impl Serialize for HubChannelName {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for HubChannelName {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        HubChannelName::try_from(name.as_str()).map_err(serde::de::Error::custom)
     }
 }
 
@@ -33,6 +168,16 @@ impl TryFrom<&str> for HubChannelName {
         // Trim leading and trailing spaces, newlines, and carriage returns
         let trimmed = value.trim_matches(|c: char| c.is_whitespace() || c == '\n' || c == '\r');
 
+        if trimmed.is_empty() {
+            return Err("Invalid channel name: Empty name.".to_string());
+        }
+        if trimmed.len() > MAX_CHANNEL_NAME_LEN {
+            return Err(format!(
+                "Invalid channel name: Longer than {} bytes.",
+                MAX_CHANNEL_NAME_LEN
+            ));
+        }
+
         // Check if there are any newlines or carriage returns in the middle of the string
         if trimmed.contains('\n') || trimmed.contains('\r') {
             return Err(
@@ -40,19 +185,23 @@ impl TryFrom<&str> for HubChannelName {
             );
         }
 
-        // Ensure only alphanumeric characters and '_' exist, and no spaces in the middle
-        if trimmed
-            .chars()
-            .any(|c| !(c.is_alphanumeric() || c == '_' || c.is_whitespace()))
-        {
+        // Ensure only alphanumeric characters, '_' and '/' exist, and no spaces in the middle
+        if trimmed.chars().any(|c| {
+            !(c.is_alphanumeric() || c == '_' || c == NAMESPACE_SEPARATOR || c.is_whitespace())
+        }) {
             return Err(
-                "Invalid channel name: Only alphanumeric characters and '_' are allowed."
+                "Invalid channel name: Only alphanumeric characters, '_' and '/' are allowed."
                     .to_string(),
             );
         }
 
+        // Reject leading, trailing or repeated '/'
+        if trimmed.split(NAMESPACE_SEPARATOR).any(str::is_empty) {
+            return Err("Invalid channel name: Empty namespace segment.".to_string());
+        }
+
         // Reject spaces in the middle of the string
-        if trimmed.contains(' ') {
+        if trimmed.contains(char::is_whitespace) {
             return Err(
                 "Invalid channel name: Whitespace is only allowed at the beginning or end."
                     .to_string(),
@@ -60,7 +209,10 @@ impl TryFrom<&str> for HubChannelName {
         }
 
         // Return the valid channel name (in lowercase)
-        Ok(HubChannelName(trimmed.to_ascii_lowercase()))
+        if trimmed.bytes().any(|b| b.is_ascii_uppercase()) {
+            return Ok(HubChannelName(intern(&trimmed.to_ascii_lowercase())));
+        }
+        Ok(HubChannelName(intern(trimmed)))
     }
 }
 
@@ -135,6 +287,96 @@ mod tests {
         assert_eq!(hub_channel_name.as_str(), valid_name.to_string());
     }
 
+    #[test]
+    fn test_namespaced_channel_name() {
+        let hub_channel_name = HubChannelName::try_from("Robot2/Sensors/imu").unwrap();
+        assert_eq!(hub_channel_name.as_str(), "robot2/sensors/imu");
+
+        for invalid_name in ["/imu", "sensors/", "sensors//imu", "/"] {
+            assert!(HubChannelName::try_from(invalid_name).is_err());
+        }
+    }
+
+    #[test]
+    fn test_namespace_helpers() {
+        let channel = HubChannelName::try_from("robot1/sensors/imu").unwrap();
+        let name = |name: &str| HubChannelName::try_from(name).unwrap();
+        assert_eq!(
+            channel.segments().collect::<Vec<_>>(),
+            vec!["robot1", "sensors", "imu"]
+        );
+        assert_eq!(channel.base_name(), "imu");
+        assert_eq!(channel.namespace(), Some(name("robot1/sensors")));
+        assert_eq!(channel.root_namespace(), Some(name("robot1")));
+
+        assert!(channel.is_in_namespace(&name("robot1")));
+        assert!(channel.is_in_namespace(&name("robot1/sensors")));
+        assert!(!channel.is_in_namespace(&name("robot")));
+        assert!(!channel.is_in_namespace(&channel));
+        assert!(!name("robot10/imu").is_in_namespace(&name("robot1")));
+        assert_eq!(
+            channel.strip_namespace(&name("robot1")),
+            Some(name("sensors/imu"))
+        );
+        assert_eq!(channel.strip_namespace(&name("robot2")), None);
+        assert_eq!(name("robot1").join(&name("sensors/imu")).unwrap(), channel);
+
+        let top_level = name("imu");
+        assert_eq!(top_level.base_name(), "imu");
+        assert_eq!(top_level.namespace(), None);
+        assert_eq!(top_level.root_namespace(), None);
+        let long_name = name(&"a".repeat(MAX_CHANNEL_NAME_LEN));
+        assert!(long_name.join(&top_level).is_err());
+    }
+
+    #[test]
+    fn test_channel_names_are_interned() {
+        let name1 = HubChannelName::try_from("interned_channel").unwrap();
+        let name2 = HubChannelName::try_from("Interned_Channel\n").unwrap();
+        let name3: HubChannelName = serde_json::from_str(r#""interned_channel""#).unwrap();
+        assert!(Arc::ptr_eq(&name1.0, &name2.0));
+        assert!(Arc::ptr_eq(&name1.0, &name3.0));
+    }
+
+    #[test]
+    fn test_unused_channel_names_are_evicted() {
+        let kept = HubChannelName::try_from("kept_channel").unwrap();
+        for i in 0..4 * MIN_PRUNE_LEN {
+            HubChannelName::try_from(format!("evicted_channel_{}", i)).unwrap();
+        }
+        let len = registry()
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .names
+            .len();
+        assert!(len <= 2 * MIN_PRUNE_LEN, "{} interned names", len);
+
+        let name = HubChannelName::try_from("kept_channel").unwrap();
+        assert!(Arc::ptr_eq(&kept.0, &name.0));
+    }
+
+    #[test]
+    fn test_invalid_channel_name_length() {
+        assert!(HubChannelName::try_from("").is_err());
+        assert!(HubChannelName::try_from(" \n").is_err());
+        assert!(HubChannelName::try_from("a".repeat(MAX_CHANNEL_NAME_LEN).as_str()).is_ok());
+        assert!(HubChannelName::try_from("a".repeat(MAX_CHANNEL_NAME_LEN + 1).as_str()).is_err());
+    }
+
+    #[test]
+    fn test_deserialize_validates_name() {
+        assert!(serde_json::from_str::<HubChannelName>(r#""invalid channel""#).is_err());
+        assert!(serde_json::from_str::<HubChannelName>(r#""""#).is_err());
+        let name: HubChannelName = serde_json::from_str(r#""Valid_Channel""#).unwrap();
+        assert_eq!(name.as_str(), "valid_channel");
+    }
+
+    #[test]
+    fn test_serialize() {
+        let name = HubChannelName::try_from("valid_channel").unwrap();
+        assert_eq!(serde_json::to_string(&name).unwrap(), r#""valid_channel""#);
+    }
+
     #[test]
     fn test_try_from_string() {
         let valid_name = "valid_channel_123".to_string();
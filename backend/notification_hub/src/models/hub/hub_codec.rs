@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+
+use super::{HubChannelName, HubData, HubMessage, HubTimestamp};
+
+pub(crate) const MAGIC: [u8; 2] = *b"RH";
+// Frames without headers
+const VERSION: u8 = 1;
+// Frames with the headers of each message after its payload
+const HEADERS_VERSION: u8 = 2;
+// channel_id + timestamp + payload_len
+const MESSAGE_HEADER_LEN: usize = 2 + 8 + 4;
+
+/// Compact binary codec for `HubMessage`s, used on high-rate paths instead of JSON.
+///
+/// A frame consists of a fixed header, a table with the channel names referenced in the
+/// frame and the list of messages. Each message references its channel by its index in the
+/// table and carries the raw payload bytes.
+///
+/// ```text
+/// | magic (2) | version (u8) | n_channels (u16) | n_messages (u32) |
+/// | channel table: [name_len (u16) | name] * n_channels |
+/// | messages: [channel_id (u16) | timestamp (f64) | payload_len (u32) | payload | headers]
+///             * n_messages |
+/// | headers: n_headers (u16) | [key_len (u16) | key | value_len (u32) | value] * n_headers |
+/// ```
+/// All integers are little endian. Timestamps carry the wall clock time only, and are decoded
+/// in the wall clock domain.
+///
+/// Message headers are only present in version 2 frames. Frames whose messages have no headers
+/// are encoded as version 1 frames, without them, so they are still decoded by older releases.
+///
+/// `encode` packs a list of messages into a single binary frame.
+pub fn encode(messages: &[HubMessage]) -> Vec<u8> {
+    let mut channel_ids: HashMap<&HubChannelName, u16> = HashMap::new();
+    let mut channel_table: Vec<&HubChannelName> = Vec::new();
+    for message in messages {
+        channel_ids.entry(&message.channel).or_insert_with(|| {
+            channel_table.push(&message.channel);
+            (channel_table.len() - 1) as u16
+        });
+    }
+
+    let with_headers = messages.iter().any(|message| !message.headers.is_empty());
+
+    let mut frame = Vec::new();
+    frame.extend_from_slice(&MAGIC);
+    frame.push(if with_headers {
+        HEADERS_VERSION
+    } else {
+        VERSION
+    });
+    frame.extend_from_slice(&(channel_table.len() as u16).to_le_bytes());
+    frame.extend_from_slice(&(messages.len() as u32).to_le_bytes());
+    for channel in channel_table {
+        frame.extend_from_slice(&(channel.as_str().len() as u16).to_le_bytes());
+        frame.extend_from_slice(channel.as_str().as_bytes());
+    }
+    for message in messages {
+        let payload = message.data.as_str().as_bytes();
+        frame.extend_from_slice(&channel_ids[&message.channel].to_le_bytes());
+        frame.extend_from_slice(&message.timestamp.wall.to_le_bytes());
+        frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        frame.extend_from_slice(payload);
+        if with_headers {
+            frame.extend_from_slice(&(message.headers.len() as u16).to_le_bytes());
+            for (key, value) in &message.headers {
+                frame.extend_from_slice(&(key.len() as u16).to_le_bytes());
+                frame.extend_from_slice(key.as_bytes());
+                frame.extend_from_slice(&(value.len() as u32).to_le_bytes());
+                frame.extend_from_slice(value.as_bytes());
+            }
+        }
+    }
+    frame
+}
+
+/// Decodes a binary frame into the list of messages it contains
+pub fn decode(bytes: &[u8]) -> Result<Vec<HubMessage>, String> {
+    let mut reader = FrameReader { bytes, pos: 0 };
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err("Invalid binary frame: wrong magic".to_string());
+    }
+    let version = reader.u8()?;
+    if version != VERSION && version != HEADERS_VERSION {
+        return Err(format!("Unsupported binary frame version {}", version));
+    }
+    let n_channels = reader.u16()? as usize;
+    let n_messages = reader.u32()? as usize;
+
+    let mut channel_table = Vec::with_capacity(n_channels);
+    for _ in 0..n_channels {
+        let len = reader.u16()? as usize;
+        let name = std::str::from_utf8(reader.take(len)?)
+            .map_err(|e| format!("Invalid channel name in binary frame: {}", e))?;
+        channel_table.push(HubChannelName::try_from(name)?);
+    }
+
+    // Don't trust the declared number of messages when reserving memory
+    let mut messages = Vec::with_capacity(n_messages.min(reader.remaining() / MESSAGE_HEADER_LEN));
+    for _ in 0..n_messages {
+        let channel_id = reader.u16()? as usize;
+        let channel = channel_table
+            .get(channel_id)
+            .ok_or_else(|| format!("Invalid channel id {} in binary frame", channel_id))?
+            .clone();
+        let timestamp = HubTimestamp::from_wall(reader.f64()?);
+        let len = reader.u32()? as usize;
+        let payload = std::str::from_utf8(reader.take(len)?)
+            .map_err(|e| format!("Invalid payload in binary frame: {}", e))?;
+        let data = payload.parse::<HubData>()?;
+        let mut headers = HashMap::new();
+        if version == HEADERS_VERSION {
+            for _ in 0..reader.u16()? {
+                let len = reader.u16()? as usize;
+                let key = reader.str(len)?;
+                let len = reader.u32()? as usize;
+                let value = reader.str(len)?;
+                headers.insert(key.to_string(), value.to_string());
+            }
+        }
+        messages.push(HubMessage {
+            channel,
+            timestamp,
+            data,
+            headers,
+            origin: None,
+        });
+    }
+
+    if reader.remaining() != 0 {
+        return Err("Invalid binary frame: trailing bytes".to_string());
+    }
+    Ok(messages)
+}
+
+struct FrameReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> FrameReader<'a> {
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.pos
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if len > self.remaining() {
+            return Err("Invalid binary frame: unexpected end of frame".to_string());
+        }
+        let slice = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(slice)
+    }
+
+    fn str(&mut self, len: usize) -> Result<&'a str, String> {
+        std::str::from_utf8(self.take(len)?)
+            .map_err(|e| format!("Invalid header in binary frame: {}", e))
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let mut buf = [0u8; 2];
+        buf.copy_from_slice(self.take(2)?);
+        Ok(u16::from_le_bytes(buf))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let mut buf = [0u8; 4];
+        buf.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(buf))
+    }
+
+    fn f64(&mut self) -> Result<f64, String> {
+        let mut buf = [0u8; 8];
+        buf.copy_from_slice(self.take(8)?);
+        Ok(f64::from_le_bytes(buf))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_decode_roundtrip() {
+        let messages = vec![
+            HubMessage::try_from_str("channel1", "1,2,3").unwrap(),
+            HubMessage::try_from_str("channel2", "4,5,6").unwrap(),
+            HubMessage::try_from_str("channel1", "7,8,9").unwrap(),
+        ];
+        let frame = encode(&messages);
+        let decoded = decode(&frame).unwrap();
+
+        assert_eq!(decoded.len(), 3);
+        for (message, decoded) in messages.iter().zip(decoded.iter()) {
+            assert_eq!(message.channel, decoded.channel);
+            assert_eq!(message.data, decoded.data);
+            assert_eq!(message.timestamp.wall, decoded.timestamp.wall);
+        }
+    }
+
+    #[test]
+    fn test_headers_roundtrip() {
+        let messages = vec![
+            HubMessage::try_from_str("channel1", "1")
+                .unwrap()
+                .with_header("units", "m")
+                .with_header("frame_id", "base_link"),
+            HubMessage::try_from_str("channel2", "2").unwrap(),
+        ];
+        let frame = encode(&messages);
+        assert_eq!(frame[2], HEADERS_VERSION);
+        let decoded = decode(&frame).unwrap();
+        assert_eq!(decoded[0].headers, messages[0].headers);
+        assert!(decoded[1].headers.is_empty());
+        assert!(decode(&frame[..frame.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_frames_without_headers_keep_version_1() {
+        let frame = encode(&[HubMessage::try_from_str("channel1", "1").unwrap()]);
+        assert_eq!(frame[2], VERSION);
+    }
+
+    #[test]
+    fn test_channel_table_is_deduplicated() {
+        let messages = vec![
+            HubMessage::try_from_str("channel1", "1").unwrap(),
+            HubMessage::try_from_str("channel1", "2").unwrap(),
+        ];
+        let frame = encode(&messages);
+        assert_eq!(u16::from_le_bytes([frame[3], frame[4]]), 1);
+    }
+
+    #[test]
+    fn test_decode_empty_frame() {
+        let frame = encode(&[]);
+        assert!(decode(&frame).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_decode_invalid_magic() {
+        let mut frame = encode(&[HubMessage::try_from_str("channel1", "1").unwrap()]);
+        frame[0] = b'X';
+        assert!(decode(&frame).is_err());
+    }
+
+    #[test]
+    fn test_decode_truncated_frame() {
+        let frame = encode(&[HubMessage::try_from_str("channel1", "1,2,3").unwrap()]);
+        assert!(decode(&frame[..frame.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_decode_invalid_channel_id() {
+        let mut frame = encode(&[HubMessage::try_from_str("channel1", "1").unwrap()]);
+        // channel id of first message follows header (9 bytes) and table (2 + 8 bytes)
+        frame[19] = 5;
+        assert!(decode(&frame).is_err());
+    }
+}
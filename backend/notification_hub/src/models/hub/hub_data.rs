@@ -2,21 +2,87 @@ use std::str::FromStr;
 
 use serde::{Deserialize, Serialize};
 
+/// Maximum size in bytes of the data carried by a `HubMessage`
+pub const MAX_DATA_LEN: usize = 64 * 1024;
+
 /// The `HubData` struct represents a wrapper around a `String` that provides
 /// additional functionality for handling and manipulating string data.
+///
+/// Data longer than `MAX_DATA_LEN` bytes is rejected.
+///
+/// Numeric vectors, the most common data of sensor channels, are carried as comma separated
+/// numbers (e.g. `1.0,2.0,3.0`), and can be read and written with `as_f64_vec`,
+/// `as_f64_array` and `from_f64_slice`. Empty data is an empty vector.
 
 #[derive(Serialize, Debug, Clone, Deserialize, PartialEq)]
+#[serde(try_from = "String")]
 pub struct HubData(String);
 
+fn check_len(data: &str) -> Result<(), String> {
+    if data.len() > MAX_DATA_LEN {
+        return Err(format!(
+            "Invalid data: {} bytes exceed maximum of {} bytes",
+            data.len(),
+            MAX_DATA_LEN
+        ));
+    }
+    Ok(())
+}
+
 impl HubData {
     pub fn as_str(&self) -> &str {
         self.0.as_str()
     }
+
+    /// Creates data with `values` as comma separated numbers
+    pub fn from_f64_slice(values: &[f64]) -> Result<Self, String> {
+        let data = values
+            .iter()
+            .map(|value| value.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        Self::try_from(data)
+    }
+
+    /// Returns the number of comma separated values of the data
+    pub fn dims(&self) -> usize {
+        if self.0.is_empty() {
+            return 0;
+        }
+        self.0.split(',').count()
+    }
+
+    /// Parses the data as a vector of comma separated numbers
+    pub fn as_f64_vec(&self) -> Result<Vec<f64>, String> {
+        if self.0.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.0
+            .split(',')
+            .map(|value| {
+                value
+                    .trim()
+                    .parse::<f64>()
+                    .map_err(|e| format!("Invalid number {}: {}", value, e))
+            })
+            .collect()
+    }
+
+    /// Parses the data as a vector of exactly `N` comma separated numbers (e.g. an `[f64; 3]`
+    /// for XYZ samples)
+    pub fn as_f64_array<const N: usize>(&self) -> Result<[f64; N], String> {
+        let values = self.as_f64_vec()?;
+        let dims = values.len();
+        values
+            .try_into()
+            .map_err(|_| format!("Invalid data: expected {} values, found {}", N, dims))
+    }
 }
 
 impl FromStr for HubData {
     type Err = String;
     fn from_str(data: &str) -> Result<Self, Self::Err> {
+        check_len(data)?;
         Ok(Self(
             data.trim_matches(|c| c == ' ' || c == '\n' || c == '\r')
                 .to_string(),
@@ -24,6 +90,15 @@ impl FromStr for HubData {
     }
 }
 
+impl TryFrom<String> for HubData {
+    type Error = String;
+
+    fn try_from(data: String) -> Result<Self, Self::Error> {
+        check_len(&data)?;
+        Ok(Self(data))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -46,6 +121,38 @@ mod tests {
         assert_eq!(data, HubData("example data".to_string()));
     }
 
+    #[test]
+    fn test_data_too_long() {
+        let data = "a".repeat(MAX_DATA_LEN + 1);
+        assert!(data.parse::<HubData>().is_err());
+        assert!(serde_json::from_str::<HubData>(&format!("\"{}\"", data)).is_err());
+        assert!("a".repeat(MAX_DATA_LEN).parse::<HubData>().is_ok());
+    }
+
+    #[test]
+    fn test_f64_vec() {
+        let data = HubData::from_f64_slice(&[1.0, -2.5, 3.25]).unwrap();
+        assert_eq!(data.as_str(), "1,-2.5,3.25");
+        assert_eq!(data.dims(), 3);
+        assert_eq!(data.as_f64_vec().unwrap(), vec![1.0, -2.5, 3.25]);
+
+        let data = "0.1, 0.2".parse::<HubData>().unwrap();
+        assert_eq!(data.as_f64_vec().unwrap(), vec![0.1, 0.2]);
+        assert!("1.0,high".parse::<HubData>().unwrap().as_f64_vec().is_err());
+
+        let empty = HubData::from_f64_slice(&[]).unwrap();
+        assert_eq!(empty.dims(), 0);
+        assert!(empty.as_f64_vec().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_f64_array() {
+        let data = "1,2,3".parse::<HubData>().unwrap();
+        assert_eq!(data.as_f64_array::<3>().unwrap(), [1.0, 2.0, 3.0]);
+        assert!(data.as_f64_array::<4>().is_err());
+        assert!(data.as_f64_array::<2>().is_err());
+    }
+
     #[test]
     fn test_empty_string() {
         let data = "   ".parse::<HubData>().unwrap();
@@ -1,21 +1,52 @@
-use imu_common::types::Clock;
-use serde::{Deserialize, Serialize};
+use log::debug;
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::HashMap;
 
-use super::{HubChannelName, HubData};
+use super::{HubChannelName, HubData, HubPayload, HubTimestamp, NodeId};
+
+/// Well known header keys
+pub const UNITS_HEADER: &str = "units";
+pub const FRAME_ID_HEADER: &str = "frame_id";
+pub const CORRELATION_ID_HEADER: &str = "correlation_id";
+/// Remote peer that produced the message, as `<transport>:<peer>` (e.g. `serial:/dev/ttyACM0`
+/// or `ws:192.168.1.7:4132`)
+pub const SOURCE_HEADER: &str = "source";
+
+/// Version of the serialized envelope of `HubMessage`s and WebSocket messages
+pub const PROTOCOL_VERSION: u16 = 1;
+/// Version of envelopes without a version field, sent by older robopilot releases
+pub const LEGACY_PROTOCOL_VERSION: u16 = 0;
 
 /// Represents a message in the hub system.
 ///
 /// # Fields
 ///
 /// * `channel` - The name of the channel the message is associated with.
-/// * `timestamp` - The timestamp when the message was created.
+/// * `timestamp` - The time the message was created, with its wall clock and monotonic times.
 /// * `data` - The data contained in the message.
+/// * `headers` - Metadata of the message (units, frame id, correlation id...). Headers are
+///   carried by every encoding (JSON, binary, CBOR, protobuf) and by WebSocket and gRPC
+///   adapters. Serial lines carry only channel and data, so serial adapters drop them.
+/// * `origin` - The hub node the message was received from, `None` for messages published
+///   locally. Origin is local to a hub, so it is not serialized.
+///
+/// Adapters receiving messages from remote peers record the peer in the `SOURCE_HEADER`
+/// header, so subscribers can tell apart robots publishing the same channel names, together
+/// with the `origin` hub node.
+///
+/// Serialized messages carry the `PROTOCOL_VERSION` they were encoded with in field `v`.
+/// Messages without it are decoded as `LEGACY_PROTOCOL_VERSION` messages, and fields unknown
+/// to this version are ignored, so robots running different versions can exchange messages
+/// during rolling upgrades.
 
-#[derive(Serialize, Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(from = "HubMessageEnvelope")]
 pub struct HubMessage {
     pub channel: HubChannelName,
-    pub timestamp: f64,
+    pub timestamp: HubTimestamp,
     pub data: HubData,
+    pub headers: HashMap<String, String>,
+    pub origin: Option<NodeId>,
 }
 
 impl HubMessage {
@@ -23,8 +54,10 @@ impl HubMessage {
         let channel = HubChannelName::try_from(channel)?;
         Ok(Self {
             channel,
-            data: data.parse::<HubData>().unwrap(),
-            timestamp: Clock::now().as_secs(),
+            data: data.parse::<HubData>()?,
+            timestamp: HubTimestamp::now(),
+            headers: HashMap::new(),
+            origin: None,
         })
     }
 
@@ -32,15 +65,106 @@ impl HubMessage {
         Self {
             channel,
             data,
-            timestamp: Clock::now().as_secs(),
+            timestamp: HubTimestamp::now(),
+            headers: HashMap::new(),
+            origin: None,
         }
     }
 
+    /// Creates a message in `channel` carrying `payload`
+    pub fn from_payload<T: HubPayload>(channel: &str, payload: &T) -> Result<Self, String> {
+        let channel = HubChannelName::try_from(channel)?;
+        Ok(Self::new(channel, payload.to_hub_data()?))
+    }
+
+    /// Reads the data of the message into a `T`
+    pub fn payload<T: HubPayload>(&self) -> Result<T, String> {
+        T::from_hub_data(&self.data)
+    }
+
+    /// Sets header `key` to `value`
+    pub fn with_header(mut self, key: &str, value: &str) -> Self {
+        self.headers.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// Returns the value of header `key`, if set
+    pub fn header(&self, key: &str) -> Option<&str> {
+        self.headers.get(key).map(String::as_str)
+    }
+
+    /// Records `peer` of `transport` as the source of the message. Messages relayed by other
+    /// hubs keep the source they were first received from
+    pub fn with_source(mut self, transport: &str, peer: impl std::fmt::Display) -> Self {
+        self.headers
+            .entry(SOURCE_HEADER.to_string())
+            .or_insert_with(|| format!("{}:{}", transport, peer));
+        self
+    }
+
+    /// Returns the remote peer that produced the message, if known
+    pub fn source(&self) -> Option<&str> {
+        self.header(SOURCE_HEADER)
+    }
+
     pub fn to_bytes(&self) -> Result<Vec<u8>, serde_json::Error> {
         serde_json::to_vec(self)
     }
 }
 
+// Serialized HubMessage, borrowing the fields of the message
+#[derive(Serialize)]
+struct HubMessageEnvelopeRef<'a> {
+    v: u16,
+    channel: &'a HubChannelName,
+    timestamp: &'a HubTimestamp,
+    data: &'a HubData,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    headers: &'a HashMap<String, String>,
+}
+
+impl Serialize for HubMessage {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        HubMessageEnvelopeRef {
+            v: PROTOCOL_VERSION,
+            channel: &self.channel,
+            timestamp: &self.timestamp,
+            data: &self.data,
+            headers: &self.headers,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[derive(Deserialize)]
+struct HubMessageEnvelope {
+    #[serde(default)]
+    v: u16,
+    channel: HubChannelName,
+    timestamp: HubTimestamp,
+    data: HubData,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+}
+
+impl From<HubMessageEnvelope> for HubMessage {
+    fn from(envelope: HubMessageEnvelope) -> Self {
+        if envelope.v > PROTOCOL_VERSION {
+            debug!(
+                "Decoding HubMessage of newer protocol version {}",
+                envelope.v
+            );
+        }
+        Self {
+            channel: envelope.channel,
+            timestamp: envelope.timestamp,
+            data: envelope.data,
+            headers: envelope.headers,
+            origin: None,
+        }
+    }
+}
+
 impl TryFrom<Vec<u8>> for HubMessage {
     type Error = String;
 
@@ -136,6 +260,36 @@ mod tests {
         assert_eq!(message.data, deserialized_message.data);
     }
 
+    #[test]
+    fn test_hub_message_headers() {
+        let message = HubMessage::try_from_str("lidar", "1,2,3")
+            .unwrap()
+            .with_header(UNITS_HEADER, "m")
+            .with_header(FRAME_ID_HEADER, "base_link");
+        assert_eq!(message.header(UNITS_HEADER), Some("m"));
+        assert_eq!(message.header(CORRELATION_ID_HEADER), None);
+
+        let deserialized = HubMessage::try_from(message.to_bytes().unwrap()).unwrap();
+        assert_eq!(deserialized.headers, message.headers);
+
+        // messages without headers serialize as before
+        let message = HubMessage::try_from_str("lidar", "1,2,3").unwrap();
+        let json = serde_json::to_string(&message).unwrap();
+        assert!(!json.contains("headers"));
+        assert!(HubMessage::try_from(json).unwrap().headers.is_empty());
+    }
+
+    #[test]
+    fn test_hub_message_source() {
+        let message = HubMessage::try_from_str("imu", "1,2,3").unwrap();
+        assert_eq!(message.source(), None);
+        let message = message.with_source("serial", "/dev/ttyACM0");
+        assert_eq!(message.source(), Some("serial:/dev/ttyACM0"));
+        // relayed messages keep their first source
+        let message = message.with_source("ws", "192.168.1.7:4132");
+        assert_eq!(message.source(), Some("serial:/dev/ttyACM0"));
+    }
+
     #[test]
     fn test_hub_message_try_from_bytes() {
         let channel = "valid_channel";
@@ -146,4 +300,31 @@ mod tests {
         assert_eq!(message.channel, deserialized_message.channel);
         assert_eq!(message.data, deserialized_message.data);
     }
+
+    #[test]
+    fn test_hub_message_payload() {
+        let message = HubMessage::from_payload("odometry", &vec![1.0, 2.5]).unwrap();
+        assert_eq!(message.data.as_str(), "[1.0,2.5]");
+        assert_eq!(message.payload::<Vec<f64>>().unwrap(), vec![1.0, 2.5]);
+        assert!(message.payload::<bool>().is_err());
+        assert!(HubMessage::from_payload("invalid channel!", &1.0).is_err());
+    }
+
+    #[test]
+    fn test_hub_message_versioned_envelope() {
+        let message = HubMessage::try_from_str("imu", "1,2,3").unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&message.to_bytes().unwrap()).unwrap();
+        assert_eq!(json["v"], PROTOCOL_VERSION);
+
+        // messages of older releases have no version
+        let legacy = r#"{"channel":"imu","timestamp":1000.5,"data":"1,2,3"}"#;
+        let decoded = HubMessage::try_from(legacy.to_string()).unwrap();
+        assert_eq!(decoded.data.as_str(), "1,2,3");
+        assert!(decoded.headers.is_empty());
+
+        // fields of newer releases are ignored
+        let newer = r#"{"v":7,"channel":"imu","timestamp":1000.5,"data":"1","qos":2}"#;
+        let decoded = HubMessage::try_from(newer.to_string()).unwrap();
+        assert_eq!(decoded.channel.as_str(), "imu");
+    }
 }
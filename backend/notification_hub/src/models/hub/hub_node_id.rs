@@ -0,0 +1,70 @@
+use std::fmt;
+use std::sync::Arc;
+
+/// Maximum length in bytes of a node name
+pub const MAX_NODE_NAME_LEN: usize = 32;
+
+/// Identifies a hub node added to a `HubManager` by its name (e.g. `imu_serial`).
+///
+/// Node names are given when the node is added, so per-node operations (routing, health,
+/// removal) keep targeting the same node whichever nodes failed to be added before it.
+/// Names are not empty, at most `MAX_NODE_NAME_LEN` bytes long, and only contain
+/// alphanumeric characters, '_', '-' and '.'. Cloning is a reference count increment, as
+/// messages received by a node are tagged with its identifier.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct NodeId(Arc<str>);
+
+impl NodeId {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl TryFrom<&str> for NodeId {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let name = value.trim();
+        if name.is_empty() {
+            return Err("Invalid node name: Empty name.".to_string());
+        }
+        if name.len() > MAX_NODE_NAME_LEN {
+            return Err(format!(
+                "Invalid node name: Longer than {} bytes.",
+                MAX_NODE_NAME_LEN
+            ));
+        }
+        if name
+            .chars()
+            .any(|c| !(c.is_alphanumeric() || c == '_' || c == '-' || c == '.'))
+        {
+            return Err(format!("Invalid node name {}: Invalid characters.", name));
+        }
+        Ok(NodeId(Arc::from(name)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_node_names() {
+        let node = NodeId::try_from(" imu_serial ").unwrap();
+        assert_eq!(node.as_str(), "imu_serial");
+        assert_eq!(node.to_string(), "imu_serial");
+        assert_eq!(node, NodeId::try_from("imu_serial").unwrap());
+        assert!(NodeId::try_from("ws-1.local").is_ok());
+
+        assert!(NodeId::try_from("").is_err());
+        assert!(NodeId::try_from("imu,serial").is_err());
+        assert!(NodeId::try_from("imu serial").is_err());
+        assert!(NodeId::try_from(&"a".repeat(MAX_NODE_NAME_LEN + 1)[..]).is_err());
+    }
+}
@@ -0,0 +1,115 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+use super::HubData;
+
+/// Types that can be carried as the data of a `HubMessage`, so that they can be published
+/// and received without formatting or parsing `HubData` by hand.
+///
+/// `HubPayload` is implemented for every type implementing `Serialize` and
+/// `DeserializeOwned`, so `#[derive(Serialize, Deserialize)]` is enough for user structs:
+///
+/// - Types serialized as a string (e.g. `XYZ` samples serialized as `"1.0,2.0,3.0"`) are carried
+///   as the bare string, the same data serial devices send.
+/// - Other types are carried as JSON (e.g. `{"x":1.0,"y":2.0}` or `9.8`).
+///
+/// Decoding accepts both, so data received from devices can be read into types serialized as
+/// a string without quoting it first.
+pub trait HubPayload: Sized {
+    fn to_hub_data(&self) -> Result<HubData, String>;
+    fn from_hub_data(data: &HubData) -> Result<Self, String>;
+}
+
+impl<T: Serialize + DeserializeOwned> HubPayload for T {
+    fn to_hub_data(&self) -> Result<HubData, String> {
+        let value =
+            serde_json::to_value(self).map_err(|e| format!("Error encoding payload: {}", e))?;
+        match value {
+            Value::String(data) => HubData::try_from(data),
+            value => HubData::try_from(value.to_string()),
+        }
+    }
+
+    fn from_hub_data(data: &HubData) -> Result<Self, String> {
+        serde_json::from_str(data.as_str())
+            .or_else(|_| serde_json::from_value(Value::String(data.as_str().to_string())))
+            .map_err(|e| format!("Error decoding payload: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Pose {
+        x: f64,
+        y: f64,
+        label: String,
+    }
+
+    // Sample serialized as comma separated values, like the samples sent by devices
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(into = "String", try_from = "String")]
+    struct Sample(f64, f64);
+
+    impl From<Sample> for String {
+        fn from(sample: Sample) -> Self {
+            format!("{},{}", sample.0, sample.1)
+        }
+    }
+
+    impl TryFrom<String> for Sample {
+        type Error = String;
+
+        fn try_from(value: String) -> Result<Self, Self::Error> {
+            let values: Vec<f64> = value
+                .split(',')
+                .map(|v| v.trim().parse::<f64>().map_err(|e| e.to_string()))
+                .collect::<Result<_, _>>()?;
+            match values[..] {
+                [a, b] => Ok(Sample(a, b)),
+                _ => Err(format!("Invalid sample {}", value)),
+            }
+        }
+    }
+
+    #[test]
+    fn test_struct_payload() {
+        let pose = Pose {
+            x: 1.0,
+            y: -2.5,
+            label: "dock".to_string(),
+        };
+        let data = pose.to_hub_data().unwrap();
+        assert!(data.as_str().starts_with('{'));
+        assert_eq!(Pose::from_hub_data(&data).unwrap(), pose);
+    }
+
+    #[test]
+    fn test_string_payload() {
+        let data = Sample(1.5, 2.0).to_hub_data().unwrap();
+        assert_eq!(data.as_str(), "1.5,2");
+        assert_eq!(Sample::from_hub_data(&data).unwrap(), Sample(1.5, 2.0));
+
+        let data = "0.1,0.2".parse::<HubData>().unwrap();
+        assert_eq!(Sample::from_hub_data(&data).unwrap(), Sample(0.1, 0.2));
+    }
+
+    #[test]
+    fn test_scalar_payload() {
+        let data = 9.8f64.to_hub_data().unwrap();
+        assert_eq!(data.as_str(), "9.8");
+        assert_eq!(f64::from_hub_data(&data).unwrap(), 9.8);
+        assert_eq!(String::from_hub_data(&data).unwrap(), "9.8");
+    }
+
+    #[test]
+    fn test_invalid_payload() {
+        let data = "1.0,high".parse::<HubData>().unwrap();
+        assert!(Sample::from_hub_data(&data).is_err());
+        assert!(Pose::from_hub_data(&data).is_err());
+    }
+}
@@ -0,0 +1,107 @@
+use prost::Message;
+
+use crate::models::hub::{self, ClockDomain, HubChannelName, HubData, HubTimestamp};
+
+/// Types generated from the protobuf schema in `proto/hub_messages.proto`. With the `grpc`
+/// feature, the types of the gRPC service in `proto/hub.proto` are generated as well.
+#[allow(clippy::derive_partial_eq_without_eq)]
+mod generated {
+    include!(concat!(env!("OUT_DIR"), "/robopilot.hub.rs"));
+}
+
+pub use generated::*;
+
+impl From<hub::HubMessage> for HubMessage {
+    fn from(message: hub::HubMessage) -> Self {
+        Self {
+            channel: message.channel.as_str().to_string(),
+            timestamp: message.timestamp.wall,
+            data: message.data.as_str().to_string(),
+            headers: message.headers,
+            monotonic: message.timestamp.monotonic,
+            clock_domain: message.timestamp.domain.as_str().to_string(),
+        }
+    }
+}
+
+impl TryFrom<HubMessage> for hub::HubMessage {
+    type Error = String;
+
+    fn try_from(message: HubMessage) -> Result<Self, Self::Error> {
+        let timestamp = if message.clock_domain.is_empty() {
+            HubTimestamp::from_wall(message.timestamp)
+        } else {
+            HubTimestamp::new(
+                message.timestamp,
+                message.monotonic,
+                ClockDomain::from(message.clock_domain),
+            )
+        };
+        Ok(Self {
+            channel: HubChannelName::try_from(message.channel)?,
+            timestamp,
+            data: HubData::try_from(message.data)?,
+            headers: message.headers,
+            origin: None,
+        })
+    }
+}
+
+/// Encodes a list of messages into a protobuf `HubMessageBatch`
+pub fn encode(messages: &[hub::HubMessage]) -> Vec<u8> {
+    HubMessageBatch {
+        messages: messages.iter().cloned().map(HubMessage::from).collect(),
+    }
+    .encode_to_vec()
+}
+
+/// Decodes a protobuf `HubMessageBatch` into the list of messages it contains
+pub fn decode(bytes: &[u8]) -> Result<Vec<hub::HubMessage>, String> {
+    HubMessageBatch::decode(bytes)
+        .map_err(|e| format!("Invalid protobuf frame: {}", e))?
+        .messages
+        .into_iter()
+        .map(hub::HubMessage::try_from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_conversion() {
+        let message = hub::HubMessage::try_from_str("imu", "1,2,3")
+            .unwrap()
+            .with_header(hub::hub_message::UNITS_HEADER, "m/s2");
+        let proto_message = HubMessage::from(message.clone());
+        let converted = hub::HubMessage::try_from(proto_message).unwrap();
+        assert_eq!(converted.channel, message.channel);
+        assert_eq!(converted.timestamp, message.timestamp);
+        assert_eq!(converted.data.as_str(), "1,2,3");
+        assert_eq!(converted.headers, message.headers);
+
+        let invalid = HubMessage {
+            channel: "invalid channel".to_string(),
+            data: String::new(),
+            ..Default::default()
+        };
+        assert!(hub::HubMessage::try_from(invalid).is_err());
+    }
+
+    #[test]
+    fn test_encode_decode_roundtrip() {
+        let messages = vec![
+            hub::HubMessage::try_from_str("imu", "0.1,0.2,9.8").unwrap(),
+            hub::HubMessage::try_from_str("status", "armed").unwrap(),
+        ];
+        let decoded = decode(&encode(&messages)).unwrap();
+        assert_eq!(decoded.len(), 2);
+        for (message, decoded) in messages.iter().zip(decoded.iter()) {
+            assert_eq!(message.channel, decoded.channel);
+            assert_eq!(message.timestamp, decoded.timestamp);
+            assert_eq!(message.data, decoded.data);
+        }
+        assert!(decode(&[0xff]).is_err());
+    }
+}
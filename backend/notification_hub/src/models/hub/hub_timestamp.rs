@@ -0,0 +1,229 @@
+use imu_common::types::Clock;
+use serde::{Deserialize, Serialize};
+use std::ops::{AddAssign, SubAssign};
+use std::sync::{Arc, OnceLock};
+use std::time::Instant;
+use uuid::Uuid;
+
+const WALL_DOMAIN: &str = "wall";
+
+/// Clock a monotonic time is measured with. Monotonic times are only comparable within the
+/// same domain.
+///
+/// - `ClockDomain::local()` -> Monotonic clock of this hub, in seconds since the hub started.
+///   Its name is unique to the hub process, so timestamps relayed from other hubs are never
+///   mistaken for local ones.
+/// - `ClockDomain::wall()` -> Only the wall clock time is known, and the monotonic time is the
+///   wall clock time. Used by wire formats carrying a single timestamp.
+/// - `ClockDomain::new(name)` -> Clock of a remote device, such as a microcontroller counting
+///   time since boot.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub struct ClockDomain(Arc<str>);
+
+impl ClockDomain {
+    pub fn new(name: &str) -> Self {
+        Self(Arc::from(name))
+    }
+
+    pub fn local() -> Self {
+        static LOCAL: OnceLock<ClockDomain> = OnceLock::new();
+        LOCAL
+            .get_or_init(|| Self::new(&format!("hub:{}", Uuid::new_v4().simple())))
+            .clone()
+    }
+
+    pub fn wall() -> Self {
+        Self::new(WALL_DOMAIN)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for ClockDomain {
+    fn from(value: String) -> Self {
+        Self(Arc::from(value))
+    }
+}
+
+impl From<ClockDomain> for String {
+    fn from(value: ClockDomain) -> Self {
+        value.0.to_string()
+    }
+}
+
+// Start of the monotonic clock of the local domain
+fn local_start() -> Instant {
+    static START: OnceLock<Instant> = OnceLock::new();
+    *START.get_or_init(Instant::now)
+}
+
+/// Time of a `HubMessage`, carrying both the wall clock and the monotonic time of the event.
+///
+/// Wall clock time may jump (NTP adjustments, RTC resets), so durations between messages of
+/// the same clock domain are measured with the monotonic time, and wall clock time is used
+/// to relate timestamps of different domains.
+///
+/// # Fields
+/// - `wall`: Seconds since the UNIX epoch.
+/// - `monotonic`: Seconds on the monotonic clock of `domain`.
+/// - `domain`: Clock the monotonic time is measured with.
+///
+/// Timestamps serialized as a plain number of seconds, as sent by older peers, are read as
+/// wall clock timestamps.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "WireTimestamp")]
+pub struct HubTimestamp {
+    pub wall: f64,
+    pub monotonic: f64,
+    pub domain: ClockDomain,
+}
+
+impl HubTimestamp {
+    pub fn new(wall: f64, monotonic: f64, domain: ClockDomain) -> Self {
+        Self {
+            wall,
+            monotonic,
+            domain,
+        }
+    }
+
+    /// Current time in the local clock domain
+    pub fn now() -> Self {
+        Self {
+            wall: Clock::now().as_secs(),
+            monotonic: local_start().elapsed().as_secs_f64(),
+            domain: ClockDomain::local(),
+        }
+    }
+
+    /// Timestamp known only by its wall clock time
+    pub fn from_wall(wall: f64) -> Self {
+        Self {
+            wall,
+            monotonic: wall,
+            domain: ClockDomain::wall(),
+        }
+    }
+
+    /// Difference between the wall clock and the monotonic clock of the domain when the
+    /// timestamp was taken
+    pub fn offset(&self) -> f64 {
+        self.wall - self.monotonic
+    }
+
+    /// Converts the timestamp into the clock domain of `reference`, relating both domains
+    /// through their wall clock offsets
+    pub fn to_domain(&self, reference: &HubTimestamp) -> HubTimestamp {
+        if self.domain == reference.domain {
+            return self.clone();
+        }
+        Self {
+            wall: self.wall,
+            monotonic: self.wall - reference.offset(),
+            domain: reference.domain.clone(),
+        }
+    }
+
+    /// Seconds elapsed from `earlier` to this timestamp. Monotonic time is used within the
+    /// same clock domain, and wall clock time otherwise
+    pub fn elapsed_since(&self, earlier: &HubTimestamp) -> f64 {
+        if self.domain == earlier.domain {
+            return self.monotonic - earlier.monotonic;
+        }
+        self.wall - earlier.wall
+    }
+}
+
+/// Shifts the timestamp later by a number of seconds
+impl AddAssign<f64> for HubTimestamp {
+    fn add_assign(&mut self, secs: f64) {
+        self.wall += secs;
+        self.monotonic += secs;
+    }
+}
+
+/// Shifts the timestamp earlier by a number of seconds
+impl SubAssign<f64> for HubTimestamp {
+    fn sub_assign(&mut self, secs: f64) {
+        self.wall -= secs;
+        self.monotonic -= secs;
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum WireTimestamp {
+    Wall(f64),
+    Full {
+        wall: f64,
+        monotonic: f64,
+        domain: ClockDomain,
+    },
+}
+
+impl From<WireTimestamp> for HubTimestamp {
+    fn from(value: WireTimestamp) -> Self {
+        match value {
+            WireTimestamp::Wall(wall) => HubTimestamp::from_wall(wall),
+            WireTimestamp::Full {
+                wall,
+                monotonic,
+                domain,
+            } => HubTimestamp::new(wall, monotonic, domain),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_timestamps_are_monotonic() {
+        let first = HubTimestamp::now();
+        let second = HubTimestamp::now();
+        assert_eq!(first.domain, ClockDomain::local());
+        assert!(second.elapsed_since(&first) >= 0.0);
+        assert!(ClockDomain::local().as_str().starts_with("hub:"));
+    }
+
+    #[test]
+    fn test_elapsed_ignores_wall_clock_jumps() {
+        let first = HubTimestamp::new(1000.0, 5.0, ClockDomain::local());
+        // wall clock stepped back by NTP
+        let second = HubTimestamp::new(900.0, 6.0, ClockDomain::local());
+        assert_eq!(second.elapsed_since(&first), 1.0);
+
+        let remote = HubTimestamp::new(950.0, 2.0, ClockDomain::new("imu"));
+        assert_eq!(remote.elapsed_since(&first), -50.0);
+    }
+
+    #[test]
+    fn test_to_domain() {
+        let reference = HubTimestamp::new(1000.0, 10.0, ClockDomain::local());
+        let remote = HubTimestamp::new(1002.5, 7.0, ClockDomain::new("imu"));
+        let converted = remote.to_domain(&reference);
+        assert_eq!(converted.domain, reference.domain);
+        assert_eq!(converted.monotonic, 12.5);
+        assert_eq!(converted.elapsed_since(&reference), 2.5);
+        assert_eq!(reference.to_domain(&reference), reference);
+    }
+
+    #[test]
+    fn test_serde() {
+        let timestamp = HubTimestamp::new(1000.0, 10.0, ClockDomain::new("imu"));
+        let json = serde_json::to_string(&timestamp).unwrap();
+        assert_eq!(
+            serde_json::from_str::<HubTimestamp>(&json).unwrap(),
+            timestamp
+        );
+        // timestamps of older peers
+        assert_eq!(
+            serde_json::from_str::<HubTimestamp>("1000.5").unwrap(),
+            HubTimestamp::from_wall(1000.5)
+        );
+    }
+}
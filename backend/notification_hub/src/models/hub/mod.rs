@@ -1,7 +1,17 @@
+pub mod hub_cbor;
 pub mod hub_channel_name;
+pub mod hub_codec;
 pub mod hub_data;
 pub mod hub_message;
+pub mod hub_node_id;
+pub mod hub_payload;
+#[cfg(feature = "protobuf")]
+pub mod hub_proto;
+pub mod hub_timestamp;
 
 pub use hub_channel_name::HubChannelName;
 pub use hub_data::HubData;
-pub use hub_message::HubMessage;
+pub use hub_message::{HubMessage, LEGACY_PROTOCOL_VERSION, PROTOCOL_VERSION};
+pub use hub_node_id::NodeId;
+pub use hub_payload::HubPayload;
+pub use hub_timestamp::{ClockDomain, HubTimestamp};
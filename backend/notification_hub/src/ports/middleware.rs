@@ -0,0 +1,11 @@
+use crate::models::hub::HubMessage;
+
+/// A trait representing a processing stage applied to messages received by the hub before they
+/// are delivered to subscribers.
+///
+/// Middlewares can filter, transform or enrich messages (e.g. add units, convert frames).
+/// Returning `None` blocks the message.
+pub trait MessageMiddleware: Send + Sync + std::fmt::Debug {
+    /// Processes message. Returns the message to deliver, if any.
+    fn process(&self, message: HubMessage) -> Option<HubMessage>;
+}
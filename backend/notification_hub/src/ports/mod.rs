@@ -1,3 +1,5 @@
-pub mod notofication_hub;
+pub mod middleware;
+pub mod notification_hub;
 
-pub use notofication_hub::NotificationHub;
+pub use middleware::MessageMiddleware;
+pub use notification_hub::{NodeTasks, NotificationHub};
@@ -1,8 +1,12 @@
 use async_trait::async_trait;
 use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
 
 use crate::models::hub::{HubChannelName, HubMessage};
 
+/// Handles of the background tasks spawned by a notification hub
+pub type NodeTasks = Vec<JoinHandle<()>>;
+
 /// A trait representing a notification hub that can send and manage messages across different channels.
 
 #[async_trait]
@@ -10,10 +14,11 @@ pub trait NotificationHub: Send + Sync + std::fmt::Debug {
     /// Sends a message to the notification hub.
     async fn send(&self, data: HubMessage) -> Result<(), std::io::Error>;
     /// Starts the notification hub with the given sender.
+    /// Returns the handles of the background tasks spawned, so that they can be supervised.
     async fn start(
         &self,
         sender: Option<broadcast::Sender<HubMessage>>,
-    ) -> Result<(), std::io::Error>;
+    ) -> Result<NodeTasks, std::io::Error>;
     /// Lists all available channels in the notification hub.
     async fn list_channels(&self) -> Result<Vec<HubChannelName>, std::io::Error>;
     /// Subscribes to a specific channel in the notification hub.
@@ -0,0 +1,193 @@
+use futures_util::stream::{self, StreamExt};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::task::JoinHandle;
+
+use crate::config::hub::robot_namespace;
+use crate::models::hub::{HubChannelName, HubMessage};
+use crate::services::hub::HubHandle;
+
+/// Channel where robots publish their pose
+pub const POSE_CHANNEL: &str = "pose";
+/// Channel where robots publish their health
+pub const HEALTH_CHANNEL: &str = "health";
+
+/// Last known state of a robot in the fleet
+///
+/// # Fields
+/// - `pose`: Last message received in the robot pose channel.
+/// - `health`: Last message received in the robot health channel.
+/// - `last_seen`: Time when the last pose or health message was received.
+#[derive(Debug, Clone, Default)]
+pub struct RobotStatus {
+    pub pose: Option<HubMessage>,
+    pub health: Option<HubMessage>,
+    pub last_seen: Option<Instant>,
+}
+
+/// `FleetCoordinator` supervises several robots from a base station hub, where every robot hub
+/// is bridged with `HubManager::add_robot`.
+///
+/// The coordinator subscribes to the pose and health channels of every robot
+/// (`<robot_id>/pose` and `<robot_id>/health`), and keeps the last status of each robot.
+/// Commands are published to `<robot_id>/<channel>`, which the hub routes to the robot only.
+/// Subscriptions are released when the coordinator is dropped.
+#[derive(Debug)]
+pub struct FleetCoordinator {
+    hub: HubHandle,
+    robots: Arc<Mutex<HashMap<HubChannelName, RobotStatus>>>,
+    tasks: Vec<JoinHandle<()>>,
+}
+
+fn invalid_input(e: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, e)
+}
+
+fn robot_channel(robot: &HubChannelName, channel: &str) -> Result<HubChannelName, std::io::Error> {
+    HubChannelName::try_from(channel)
+        .and_then(|channel| robot.join(&channel))
+        .map_err(invalid_input)
+}
+
+impl FleetCoordinator {
+    /// Starts tracking robots `robot_ids` in hub
+    pub async fn start(hub: HubHandle, robot_ids: &[&str]) -> Result<Self, std::io::Error> {
+        let robots = Arc::new(Mutex::new(HashMap::new()));
+        let mut tasks = Vec::new();
+        for robot_id in robot_ids {
+            let robot = robot_namespace(robot_id).map_err(invalid_input)?;
+            let pose_channel = robot_channel(&robot, POSE_CHANNEL)?;
+            let pose = hub.subscribe(pose_channel.clone()).await?;
+            let health = hub
+                .subscribe(robot_channel(&robot, HEALTH_CHANNEL)?)
+                .await?;
+            robots
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(robot.clone(), RobotStatus::default());
+
+            let robots = Arc::clone(&robots);
+            tasks.push(tokio::spawn(async move {
+                let mut messages = stream::select(pose, health);
+                while let Some(message) = messages.next().await {
+                    let mut robots = robots.lock().unwrap_or_else(|e| e.into_inner());
+                    let status = robots.entry(robot.clone()).or_default();
+                    status.last_seen = Some(Instant::now());
+                    if message.channel == pose_channel {
+                        status.pose = Some(message);
+                    } else {
+                        status.health = Some(message);
+                    }
+                }
+            }));
+        }
+        Ok(Self { hub, robots, tasks })
+    }
+
+    /// Returns the last status of robot `robot_id`, if tracked
+    pub fn status(&self, robot_id: &str) -> Option<RobotStatus> {
+        let robot = robot_namespace(robot_id).ok()?;
+        self.robots
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&robot)
+            .cloned()
+    }
+
+    /// Returns the last status of every tracked robot
+    pub fn fleet(&self) -> HashMap<String, RobotStatus> {
+        self.robots
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|(robot, status)| (robot.as_str().to_string(), status.clone()))
+            .collect()
+    }
+
+    /// Publishes command `data` to `channel` of robot `robot_id`
+    pub async fn send_command(
+        &self,
+        robot_id: &str,
+        channel: &str,
+        data: &str,
+    ) -> Result<(), std::io::Error> {
+        let robot = robot_namespace(robot_id).map_err(invalid_input)?;
+        let tracked = self
+            .robots
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .contains_key(&robot);
+        if !tracked {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Robot {} is not part of the fleet", robot.as_str()),
+            ));
+        }
+        let channel = robot_channel(&robot, channel)?;
+        let message = HubMessage::try_from_str(channel.as_str(), data).map_err(invalid_input)?;
+        self.hub.publish(message).await
+    }
+}
+
+impl Drop for FleetCoordinator {
+    fn drop(&mut self) {
+        // receivers owned by the tasks unsubscribe when dropped
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::hub::mock::MockNode;
+    use crate::services::hub::HubManager;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_fleet_coordinator() {
+        let robot1 = Arc::new(MockNode::default());
+        let robot2 = Arc::new(MockNode::default());
+        let mut hub = HubManager::new();
+        hub.add_robot("robot1", Box::new(robot1.clone())).unwrap();
+        hub.add_robot("robot2", Box::new(robot2.clone())).unwrap();
+        hub.start().await.unwrap();
+        let coordinator = FleetCoordinator::start(hub.spawn(), &["robot1", "robot2"])
+            .await
+            .unwrap();
+        assert_eq!(
+            *robot2.subscribed.lock().await,
+            vec![
+                HubChannelName::try_from(POSE_CHANNEL).unwrap(),
+                HubChannelName::try_from(HEALTH_CHANNEL).unwrap()
+            ]
+        );
+
+        robot2.receive(HubMessage::try_from_str(POSE_CHANNEL, "1,2,3").unwrap());
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while coordinator.status("robot2").unwrap().pose.is_none() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        let status = coordinator.status("robot2").unwrap();
+        assert_eq!(status.pose.unwrap().channel.as_str(), "robot2/pose");
+        assert!(status.health.is_none());
+        assert!(coordinator.status("robot1").unwrap().last_seen.is_none());
+        assert_eq!(coordinator.fleet().len(), 2);
+
+        coordinator
+            .send_command("robot1", "cmd_vel", "1")
+            .await
+            .unwrap();
+        assert_eq!(robot1.sent.lock().await[0].channel.as_str(), "cmd_vel");
+        assert!(robot2.sent.lock().await.is_empty());
+        assert!(coordinator
+            .send_command("robot3", "cmd_vel", "1")
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,3 @@
+pub mod coordinator;
+
+pub use coordinator::{FleetCoordinator, RobotStatus, HEALTH_CHANNEL, POSE_CHANNEL};
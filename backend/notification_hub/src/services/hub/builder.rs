@@ -0,0 +1,204 @@
+use log::warn;
+use tokio::fs::{File, OpenOptions};
+
+use super::controller::HubManager;
+use crate::adapters::serial::{PortWatcher, SerialClient, SerialManager};
+use crate::adapters::stdio::StdioClient;
+use crate::adapters::websocket::WebSocketClient;
+use crate::config::{HubOptions, NodeConfig, NodeTransport};
+use crate::ports::NotificationHub;
+
+/// `HubManagerBuilder` builds and starts a `HubManager` from declarative node configurations,
+/// connecting the adapter of every node and applying its options (remap rules, robot
+/// namespace, routes, batching).
+#[derive(Debug, Clone)]
+pub struct HubManagerBuilder {
+    options: Option<HubOptions>,
+    nodes: Vec<NodeConfig>,
+}
+
+impl HubManagerBuilder {
+    pub fn new() -> Self {
+        Self {
+            options: None,
+            nodes: Vec::new(),
+        }
+    }
+
+    pub fn options(&self, options: HubOptions) -> Self {
+        let mut new = self.clone();
+        new.options = Some(options);
+        new
+    }
+    /// Adds a hub node. Nodes are added to the hub in order, and are identified by the name of
+    /// their configuration
+    pub fn node(&self, node: NodeConfig) -> Self {
+        let mut new = self.clone();
+        new.nodes.push(node);
+        new
+    }
+    /// Connects the hub nodes, and returns the started hub
+    pub async fn build(self) -> Result<HubManager, std::io::Error> {
+        let invalid_input = |e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e);
+        let mut hub = HubManager::with_options(self.options.unwrap_or_default());
+        for config in self.nodes {
+            let hub_node = match connect(&config).await {
+                Ok(hub_node) => hub_node,
+                Err(e) if config.optional() => {
+                    warn!("Skipping hub node {}: {:?}", config.name(), e);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            let node = match config.robot_id() {
+                Some(robot_id) => hub.add_robot(robot_id, hub_node).map_err(invalid_input)?,
+                None => hub
+                    .add_remapped(config.name().as_str(), hub_node, config.remap().clone())
+                    .map_err(invalid_input)?,
+            };
+            for pattern in config.routes() {
+                hub.route_pattern(pattern.clone(), node.clone());
+            }
+        }
+        hub.start().await?;
+        Ok(hub)
+    }
+}
+
+impl Default for HubManagerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Builds the adapter of the node
+async fn connect(config: &NodeConfig) -> Result<Box<dyn NotificationHub>, std::io::Error> {
+    let hub_node: Box<dyn NotificationHub> = match config.transport() {
+        NodeTransport::Serial { port, baud_rate } => {
+            let client = SerialClient::new(port, *baud_rate, config.serial_options())?;
+            match config.batching() {
+                Some(batching) => Box::new(client.with_batching(batching)),
+                None => Box::new(client),
+            }
+        }
+        NodeTransport::SerialDiscover { filter, baud_rate } => {
+            let client =
+                SerialClient::discover_first(filter, *baud_rate, config.serial_options()).await?;
+            match config.batching() {
+                Some(batching) => Box::new(client.with_batching(batching)),
+                None => Box::new(client),
+            }
+        }
+        NodeTransport::SerialHotplug { filter, baud_rate } => {
+            let watcher =
+                PortWatcher::new(filter.clone(), *baud_rate).with_options(config.serial_options());
+            Box::new(SerialManager::watch(watcher))
+        }
+        NodeTransport::WebSocket { url } => {
+            let client = WebSocketClient::new(url).await?;
+            match config.batching() {
+                Some(batching) => Box::new(client.with_batching(batching)),
+                None => Box::new(client),
+            }
+        }
+        NodeTransport::Pipe {
+            read_path,
+            write_path,
+        } => {
+            let reader = File::open(read_path).await?;
+            let writer = OpenOptions::new().write(true).open(write_path).await?;
+            Box::new(StdioClient::from_streams(reader, writer))
+        }
+        NodeTransport::Stdio => Box::new(StdioClient::new()),
+        NodeTransport::Custom(factory) => factory().await?,
+    };
+    Ok(hub_node)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::NodeConfigBuilder;
+    use crate::models::hub::{HubChannelName, HubMessage, NodeId};
+    use crate::services::hub::mock::MockNode;
+    use std::sync::Arc;
+
+    fn mock(node: &Arc<MockNode>) -> NodeConfigBuilder {
+        let node = node.clone();
+        NodeConfigBuilder::new().custom(move || {
+            let node = node.clone();
+            async move { Ok::<_, std::io::Error>(node) }
+        })
+    }
+
+    #[tokio::test]
+    async fn test_build_hub_from_node_configs() {
+        let node1 = Arc::new(MockNode::default());
+        let node2 = Arc::new(MockNode::default());
+        let missing = NodeConfigBuilder::new()
+            .name("pipe")
+            .pipe("/nonexistent/read", "/nonexistent/write");
+        let hub = HubManagerBuilder::new()
+            .node(mock(&node1).name("node1").build().unwrap())
+            .node(missing.optional(true).build().unwrap())
+            .node(
+                mock(&node2)
+                    .name("node2")
+                    .route("cmd_vel")
+                    .route("telemetry/*")
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .await
+            .unwrap();
+        assert_eq!(
+            hub.nodes(),
+            vec![
+                NodeId::try_from("node1").unwrap(),
+                NodeId::try_from("node2").unwrap()
+            ]
+        );
+
+        hub.publish(HubMessage::try_from_str("cmd_vel", "1").unwrap())
+            .await
+            .unwrap();
+        hub.publish(HubMessage::try_from_str("telemetry/battery", "1").unwrap())
+            .await
+            .unwrap();
+        assert!(node1.sent.lock().await.is_empty());
+        assert_eq!(node2.sent.lock().await.len(), 2);
+
+        assert!(HubManagerBuilder::new()
+            .node(mock(&node1).name("node").build().unwrap())
+            .node(mock(&node2).name("node").build().unwrap())
+            .build()
+            .await
+            .is_err());
+        assert!(HubManagerBuilder::new()
+            .node(missing.build().unwrap())
+            .build()
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_build_robot_node() {
+        let node = Arc::new(MockNode::default());
+        let mut hub = HubManagerBuilder::new()
+            .node(mock(&node).robot_id("robot2").build().unwrap())
+            .build()
+            .await
+            .unwrap();
+        assert_eq!(hub.robots(), vec!["robot2".to_string()]);
+
+        let _receiver = hub
+            .register_to_channel(HubChannelName::try_from("robot2/imu").unwrap())
+            .await
+            .unwrap();
+        assert_eq!(
+            *node.subscribed.lock().await,
+            vec![HubChannelName::try_from("imu").unwrap()]
+        );
+    }
+}
@@ -1,93 +1,630 @@
+use arc_swap::ArcSwap;
+use log::error;
 use std::collections::{HashMap, HashSet};
-use tokio::sync::broadcast;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{broadcast, mpsc};
 use uuid::Uuid;
 
-use super::controller::HubReceiver;
+use super::filter::MessageFilter;
+use super::receiver::{DropCounter, HubReceiver, PromotionSlot};
+use crate::config::{Backpressure, ChannelQos};
 use crate::models::hub::{HubChannelName, HubMessage};
 
 const CHANNEL_CAPACITY: usize = 100;
 
+/// Messages waiting for room in full subscriber queues of blocking channels
+pub(crate) type Deferred = Vec<(mpsc::Sender<HubMessage>, HubMessage)>;
+
+/// `SubscriberQueue` is the dedicated queue of a subscriber, handling a full queue according
+/// to the channel backpressure.
+/// - `SubscriberQueue::Unbounded` -> Queue expands without bound.
+/// - `SubscriberQueue::Lagging` -> Broadcast channel with a single receiver, so the oldest
+///   messages are dropped, and counted by the receiver when it lags behind.
+/// - `SubscriberQueue::Dropping` -> New messages are dropped, and counted in the receiver
+///   drop counter.
+/// - `SubscriberQueue::Blocking` -> Messages are deferred until there is room.
+#[derive(Debug, Clone)]
+pub(crate) enum SubscriberQueue {
+    Unbounded(mpsc::UnboundedSender<HubMessage>),
+    Lagging(broadcast::Sender<HubMessage>),
+    Dropping(mpsc::Sender<HubMessage>, DropCounter),
+    Blocking(mpsc::Sender<HubMessage>),
+}
+
+impl SubscriberQueue {
+    // Creates the queue of a subscriber of a channel with `qos`. Queues hold up to `depth`
+    // messages, except expanding queues. Like best effort channels, drop oldest queues round
+    // their depth up to a power of two.
+    fn new(user_id: Uuid, qos: &ChannelQos, depth: usize) -> (Self, HubReceiver) {
+        match qos.backpressure() {
+            Backpressure::Expand => {
+                let (sender, receiver) = mpsc::unbounded_channel();
+                (
+                    SubscriberQueue::Unbounded(sender),
+                    HubReceiver::reliable(user_id, receiver),
+                )
+            }
+            Backpressure::DropOldest => {
+                let (sender, receiver) = broadcast::channel(depth);
+                (
+                    SubscriberQueue::Lagging(sender),
+                    HubReceiver::broadcast(user_id, receiver),
+                )
+            }
+            Backpressure::DropNewest => {
+                let (sender, receiver) = mpsc::channel(depth);
+                let receiver = HubReceiver::direct(user_id, receiver, PromotionSlot::default());
+                (
+                    SubscriberQueue::Dropping(sender, receiver.drop_counter()),
+                    receiver,
+                )
+            }
+            Backpressure::Block => {
+                let (sender, receiver) = mpsc::channel(depth);
+                let receiver = HubReceiver::direct(user_id, receiver, PromotionSlot::default());
+                (SubscriberQueue::Blocking(sender), receiver)
+            }
+        }
+    }
+
+    // Queues message. Queues of unsubscribed users are closed until routes are replaced,
+    // so closed queues are ignored.
+    fn send(&self, message: HubMessage, deferred: &mut Deferred) {
+        match self {
+            SubscriberQueue::Unbounded(sender) => {
+                let _ = sender.send(message);
+            }
+            SubscriberQueue::Lagging(sender) => {
+                let _ = sender.send(message);
+            }
+            SubscriberQueue::Dropping(sender, dropped) => {
+                if let Err(TrySendError::Full(_)) = sender.try_send(message) {
+                    dropped.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            SubscriberQueue::Blocking(sender) => {
+                if let Err(TrySendError::Full(message)) = sender.try_send(message) {
+                    deferred.push((sender.clone(), message));
+                }
+            }
+        }
+    }
+
+    // Number of messages waiting in the queue. Unbounded queues don't report their length.
+    fn backlog(&self) -> usize {
+        match self {
+            SubscriberQueue::Unbounded(_) => 0,
+            SubscriberQueue::Lagging(sender) => sender.len(),
+            SubscriberQueue::Dropping(sender, _) | SubscriberQueue::Blocking(sender) => {
+                sender.max_capacity() - sender.capacity()
+            }
+        }
+    }
+
+    // Number of messages dropped by the queue. Lagging receivers count their own drops.
+    fn dropped(&self) -> u64 {
+        match self {
+            SubscriberQueue::Dropping(_, dropped) => dropped.load(Ordering::Relaxed),
+            _ => 0,
+        }
+    }
+}
+
+/// `ChannelSender` is the sender side of a hub channel.
+/// - `ChannelSender::Direct` -> Channel has a single subscriber, and messages are delivered
+///   through a dedicated mpsc channel, avoiding broadcast clone and fan-out. Messages are
+///   dropped if the subscriber is not keeping up.
+/// - `ChannelSender::Broadcast` -> Channel has several subscribers, or is a drop oldest
+///   channel with QoS.
+/// - `ChannelSender::Queues` -> Channel has QoS with a backpressure other than drop oldest.
+///   Every subscriber has a dedicated queue.
+/// - `ChannelSender::Filtered` -> Sender of a filtered subscriber, only sending messages
+///   passing its filter.
+/// - `ChannelSender::RoundRobin` -> Senders of the members of a consumer group. Every message
+///   is sent to the next member in turn.
+/// - `ChannelSender::Fanout` -> Channel has subscribers with dedicated queues or consumer
+///   groups. Messages are sent to every sender.
+#[derive(Debug, Clone)]
+pub(crate) enum ChannelSender {
+    Direct(mpsc::Sender<HubMessage>, DropCounter),
+    Broadcast(broadcast::Sender<HubMessage>),
+    Queues(Vec<SubscriberQueue>),
+    Filtered(MessageFilter, Box<ChannelSender>),
+    RoundRobin(Arc<AtomicUsize>, Vec<ChannelSender>),
+    Fanout(Vec<ChannelSender>),
+}
+
+impl ChannelSender {
+    // Delivers message to channel subscribers. Returns the messages deferred for full queues
+    // of blocking channels, to be sent once there is room.
+    pub(crate) fn send(&self, message: HubMessage) -> Result<Deferred, String> {
+        let mut deferred = Deferred::new();
+        match self {
+            ChannelSender::Direct(sender, dropped) => {
+                if let Err(e) = sender.try_send(message) {
+                    if let TrySendError::Full(_) = e {
+                        dropped.fetch_add(1, Ordering::Relaxed);
+                    }
+                    return Err(format!("Direct channel send error: {}", e));
+                }
+            }
+            ChannelSender::Broadcast(sender) => {
+                sender
+                    .send(message)
+                    .map_err(|e| format!("Broadcast channel send error: {}", e))?;
+            }
+            ChannelSender::Queues(queues) => {
+                for queue in queues {
+                    queue.send(message.clone(), &mut deferred);
+                }
+            }
+            ChannelSender::Filtered(filter, sender) => {
+                if filter.matches(&message) {
+                    return sender.send(message);
+                }
+            }
+            ChannelSender::RoundRobin(next, senders) => {
+                if !senders.is_empty() {
+                    let idx = next.fetch_add(1, Ordering::Relaxed) % senders.len();
+                    return senders[idx].send(message);
+                }
+            }
+            ChannelSender::Fanout(senders) => {
+                // every sender is tried, so that errors don't affect other subscribers
+                for sender in senders {
+                    match sender.send(message.clone()) {
+                        Ok(pending) => deferred.extend(pending),
+                        Err(e) => error!("Error : {:?}", e),
+                    }
+                }
+            }
+        }
+        Ok(deferred)
+    }
+
+    // Number of messages waiting in the fullest subscriber queue
+    pub(crate) fn backlog(&self) -> usize {
+        match self {
+            ChannelSender::Direct(sender, _) => sender.max_capacity() - sender.capacity(),
+            ChannelSender::Broadcast(sender) => sender.len(),
+            ChannelSender::Queues(queues) => queues
+                .iter()
+                .map(|queue| queue.backlog())
+                .max()
+                .unwrap_or(0),
+            ChannelSender::Filtered(_, sender) => sender.backlog(),
+            ChannelSender::RoundRobin(_, senders) | ChannelSender::Fanout(senders) => senders
+                .iter()
+                .map(ChannelSender::backlog)
+                .max()
+                .unwrap_or(0),
+        }
+    }
+
+    // Number of messages dropped by full subscriber queues
+    pub(crate) fn dropped(&self) -> u64 {
+        match self {
+            ChannelSender::Direct(_, dropped) => dropped.load(Ordering::Relaxed),
+            ChannelSender::Broadcast(_) => 0,
+            ChannelSender::Queues(queues) => queues.iter().map(|queue| queue.dropped()).sum(),
+            ChannelSender::Filtered(_, sender) => sender.dropped(),
+            ChannelSender::RoundRobin(_, senders) | ChannelSender::Fanout(senders) => {
+                senders.iter().map(ChannelSender::dropped).sum()
+            }
+        }
+    }
+}
+
 /// `HubChanelInfo` holds information about a channel, including
-/// associated sender channel and the set of subscribed users
+/// associated sender channel and the set of subscribed users.
+/// `promotion` is the slot where the single subscriber of a direct channel
+/// receives its broadcast receiver when the channel is promoted.
+/// `queues` are the dedicated subscriber queues of a channel with QoS.
 #[derive(Debug)]
 struct HubChannelInfo {
-    sender: broadcast::Sender<HubMessage>,
+    sender: ChannelSender,
     subscribers: HashSet<Uuid>,
+    promotion: Option<PromotionSlot>,
+    queues: HashMap<Uuid, SubscriberQueue>,
+}
+
+impl HubChannelInfo {
+    fn new(sender: ChannelSender) -> Self {
+        Self {
+            sender,
+            subscribers: HashSet::new(),
+            promotion: None,
+            queues: HashMap::new(),
+        }
+    }
+}
+
+/// `ConsumerGroup` holds the members of a consumer group of a channel, in subscription order,
+/// and the position of the member receiving the next message.
+#[derive(Debug, Default)]
+struct ConsumerGroup {
+    next: Arc<AtomicUsize>,
+    members: Vec<(Uuid, ChannelSender)>,
 }
 
+impl ConsumerGroup {
+    fn sender(&self) -> ChannelSender {
+        let senders = self
+            .members
+            .iter()
+            .map(|(_, sender)| sender.clone())
+            .collect();
+        ChannelSender::RoundRobin(self.next.clone(), senders)
+    }
+}
+
+/// `HubRoutes` maps each channel with subscribers to its sender channel.
+pub(crate) type HubRoutes = HashMap<HubChannelName, ChannelSender>;
+
 /// `HubChannels` manages the available hub channels identified by their name.
 /// Each channel has an associated sender  and set of subscribers UUIDs.
+///
+/// An immutable snapshot of the channel -> sender map is published in `routes` every time
+/// a channel is added, removed or promoted, so that the dispatch path can route messages without locking.
+///
+/// Channels with a QoS in `qos` are delivered according to its backpressure: drop oldest
+/// channels are broadcast with a capacity of `depth` messages, so subscribers falling behind
+/// lose the oldest ones. Otherwise, every subscriber has a dedicated queue.
+///
+/// Subscribers with a `MessageFilter` or their own buffer capacity are kept in `dedicated`,
+/// each with a dedicated queue. Their senders are added to the channel route, so messages are
+/// filtered when dispatched.
+///
+/// Members of consumer groups are kept in `groups`, each with a dedicated queue. Every group
+/// receives all messages of the channel, distributed round-robin among its members.
 #[derive(Debug)]
-pub(crate) struct HubChannels(HashMap<HubChannelName, HubChannelInfo>);
+pub(crate) struct HubChannels {
+    channels: HashMap<HubChannelName, HubChannelInfo>,
+    dedicated: HashMap<HubChannelName, HashMap<Uuid, ChannelSender>>,
+    groups: HashMap<HubChannelName, HashMap<String, ConsumerGroup>>,
+    routes: Arc<ArcSwap<HubRoutes>>,
+    qos: HashMap<HubChannelName, ChannelQos>,
+}
 
 impl HubChannels {
     pub(crate) fn new() -> Self {
-        Self(HashMap::new())
+        Self::with_qos(HashMap::new())
+    }
+
+    pub(crate) fn with_qos(qos: HashMap<HubChannelName, ChannelQos>) -> Self {
+        Self {
+            channels: HashMap::new(),
+            dedicated: HashMap::new(),
+            groups: HashMap::new(),
+            routes: Arc::new(ArcSwap::from_pointee(HubRoutes::new())),
+            qos,
+        }
+    }
+
+    // Returns a handle to the routes snapshot.
+    pub(crate) fn routes(&self) -> Arc<ArcSwap<HubRoutes>> {
+        self.routes.clone()
+    }
+
+    // Publishes a new routes snapshot built from current channels
+    fn publish_routes(&self) {
+        let mut routes: HubRoutes = self
+            .channels
+            .iter()
+            .map(|(channel, channel_info)| (channel.clone(), channel_info.sender.clone()))
+            .collect();
+        for (channel, senders) in &self.dedicated {
+            let mut fanout: Vec<ChannelSender> = routes.remove(channel).into_iter().collect();
+            fanout.extend(senders.values().cloned());
+            routes.insert(channel.clone(), ChannelSender::Fanout(fanout));
+        }
+        for (channel, groups) in &self.groups {
+            let mut fanout: Vec<ChannelSender> = routes.remove(channel).into_iter().collect();
+            fanout.extend(groups.values().map(ConsumerGroup::sender));
+            routes.insert(channel.clone(), ChannelSender::Fanout(fanout));
+        }
+        self.routes.store(Arc::new(routes));
     }
 
     // Subscribe new user to channel. Returns a HubReceiver consisting of
     //  newly associated user ID and receiver channel.
+    #[cfg(test)]
     pub(crate) fn subscribe_user(&mut self, channel: &HubChannelName) -> HubReceiver {
+        self.subscribe_user_as(channel, Uuid::new_v4())
+    }
+
+    // Subscribe user identified by user_id to channel. Users subscribe to several channels
+    // with the same user_id, but only once to each channel.
+    // First subscriber to a channel without QoS gets a direct channel. When a second
+    // subscriber arrives, the channel is promoted to broadcast.
+    pub(crate) fn subscribe_user_as(
+        &mut self,
+        channel: &HubChannelName,
+        user_id: Uuid,
+    ) -> HubReceiver {
+        match self.qos.get(channel).cloned() {
+            Some(qos) if qos.backpressure() == Backpressure::DropOldest => {
+                self.subscribe_best_effort(channel, qos.depth(), user_id)
+            }
+            Some(qos) => self.subscribe_queued(channel, &qos, user_id),
+            None => self.subscribe_default(channel, user_id),
+        }
+    }
+
+    // Creates the dedicated queue of a new user of channel, following the channel backpressure.
+    // The queue holds up to `capacity` messages, or the channel QoS depth otherwise.
+    fn dedicated_queue(
+        &self,
+        channel: &HubChannelName,
+        user_id: Uuid,
+        capacity: Option<usize>,
+    ) -> (ChannelSender, HubReceiver) {
+        match self.qos.get(channel) {
+            Some(qos) => {
+                let depth = capacity.unwrap_or(qos.depth());
+                let (queue, receiver) = SubscriberQueue::new(user_id, qos, depth);
+                (ChannelSender::Queues(vec![queue]), receiver)
+            }
+            None => {
+                let (sender, receiver) = mpsc::channel(capacity.unwrap_or(CHANNEL_CAPACITY));
+                let receiver = HubReceiver::direct(user_id, receiver, PromotionSlot::default());
+                (
+                    ChannelSender::Direct(sender, receiver.drop_counter()),
+                    receiver,
+                )
+            }
+        }
+    }
+
+    // Subscribe new user to channel, receiving only messages passing `filter`. The user gets a
+    // dedicated queue following the channel backpressure.
+    pub(crate) fn subscribe_user_filtered(
+        &mut self,
+        channel: &HubChannelName,
+        filter: MessageFilter,
+        user_id: Uuid,
+    ) -> HubReceiver {
+        let (sender, receiver) = self.dedicated_queue(channel, user_id, None);
+        self.dedicated
+            .entry(channel.clone())
+            .or_default()
+            .insert(user_id, ChannelSender::Filtered(filter, Box::new(sender)));
+        self.publish_routes();
+        receiver
+    }
+
+    // Subscribe new user to channel with a dedicated queue of `capacity` messages, following
+    // the channel backpressure. Used to size the buffer of subscribers to high-rate channels
+    // independently of other subscribers.
+    pub(crate) fn subscribe_user_with_capacity(
+        &mut self,
+        channel: &HubChannelName,
+        capacity: usize,
+        user_id: Uuid,
+    ) -> HubReceiver {
+        let (sender, receiver) = self.dedicated_queue(channel, user_id, Some(capacity));
+        self.dedicated
+            .entry(channel.clone())
+            .or_default()
+            .insert(user_id, sender);
+        self.publish_routes();
+        receiver
+    }
+
+    // Subscribe new user to channel as a member of consumer group `group`. The user gets a
+    // dedicated queue following the channel backpressure, and receives its share of the
+    // channel messages.
+    pub(crate) fn subscribe_user_grouped(
+        &mut self,
+        channel: &HubChannelName,
+        group: &str,
+        user_id: Uuid,
+    ) -> HubReceiver {
+        let (sender, receiver) = self.dedicated_queue(channel, user_id, None);
+        self.groups
+            .entry(channel.clone())
+            .or_default()
+            .entry(group.to_string())
+            .or_default()
+            .members
+            .push((receiver.user_id(), sender));
+        self.publish_routes();
+        receiver
+    }
+
+    // Subscribes user to a channel with QoS with a dedicated queue
+    fn subscribe_queued(
+        &mut self,
+        channel: &HubChannelName,
+        qos: &ChannelQos,
+        user_id: Uuid,
+    ) -> HubReceiver {
+        let (queue, receiver) = SubscriberQueue::new(user_id, qos, qos.depth());
         let channel_info = self
-            .0
+            .channels
             .entry(channel.clone())
-            .or_insert_with(|| HubChannelInfo {
-                sender: broadcast::channel(CHANNEL_CAPACITY).0,
-                subscribers: HashSet::new(),
-            });
-        let user_id = Uuid::new_v4();
+            .or_insert_with(|| HubChannelInfo::new(ChannelSender::Queues(Vec::new())));
+        channel_info.subscribers.insert(user_id);
+        channel_info.queues.insert(user_id, queue);
+        channel_info.sender =
+            ChannelSender::Queues(channel_info.queues.values().cloned().collect());
+        self.publish_routes();
+        receiver
+    }
+
+    // Subscribes user to a best effort channel, broadcast from the first subscriber so that
+    // lagging subscribers lose the oldest messages
+    fn subscribe_best_effort(
+        &mut self,
+        channel: &HubChannelName,
+        depth: usize,
+        user_id: Uuid,
+    ) -> HubReceiver {
+        let created = !self.channels.contains_key(channel);
+        let channel_info = self.channels.entry(channel.clone()).or_insert_with(|| {
+            HubChannelInfo::new(ChannelSender::Broadcast(broadcast::channel(depth).0))
+        });
         channel_info.subscribers.insert(user_id);
-        let receiver = channel_info.sender.subscribe();
-        HubReceiver(user_id, receiver)
+        let ChannelSender::Broadcast(sender) = &channel_info.sender else {
+            unreachable!("best effort channels are broadcast")
+        };
+        let receiver = HubReceiver::broadcast(user_id, sender.subscribe());
+        if created {
+            self.publish_routes();
+        }
+        receiver
+    }
+
+    fn subscribe_default(&mut self, channel: &HubChannelName, user_id: Uuid) -> HubReceiver {
+        let Some(channel_info) = self.channels.get_mut(channel) else {
+            let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+            let promotion = PromotionSlot::default();
+            let receiver = HubReceiver::direct(user_id, receiver, promotion.clone());
+            let mut channel_info =
+                HubChannelInfo::new(ChannelSender::Direct(sender, receiver.drop_counter()));
+            channel_info.subscribers.insert(user_id);
+            channel_info.promotion = Some(promotion);
+            self.channels.insert(channel.clone(), channel_info);
+            self.publish_routes();
+            return receiver;
+        };
+
+        channel_info.subscribers.insert(user_id);
+        match &channel_info.sender {
+            ChannelSender::Broadcast(sender) => HubReceiver::broadcast(user_id, sender.subscribe()),
+            ChannelSender::Direct(..) => {
+                // Dropping the direct sender closes the direct channel once the
+                // routes snapshot is replaced.
+                let sender = broadcast::channel(CHANNEL_CAPACITY).0;
+                if let Some(promotion) = channel_info.promotion.take() {
+                    if let Ok(mut slot) = promotion.lock() {
+                        *slot = Some(sender.subscribe());
+                    }
+                }
+                let receiver = HubReceiver::broadcast(user_id, sender.subscribe());
+                channel_info.sender = ChannelSender::Broadcast(sender);
+                self.publish_routes();
+                receiver
+            }
+            ChannelSender::Queues(_)
+            | ChannelSender::Filtered(..)
+            | ChannelSender::RoundRobin(..)
+            | ChannelSender::Fanout(_) => {
+                unreachable!("channels without QoS are direct or broadcast")
+            }
+        }
     }
 
     // Unsubscribe user identified by user_id from channel. If channel doesnt have
-    // any additional subscrobers, channel is removed from `HubChannels`
-    pub(crate) fn unsubscribe_user(&mut self, channel: &HubChannelName, user_id: Uuid) {
-        if let Some(channel_info) = self.0.get_mut(channel) {
+    // any additional subscrobers, channel is removed from `HubChannels`.
+    // Returns true if the channel was removed.
+    pub(crate) fn unsubscribe_user(&mut self, channel: &HubChannelName, user_id: Uuid) -> bool {
+        if let Some(senders) = self.dedicated.get_mut(channel) {
+            if senders.remove(&user_id).is_some() {
+                if senders.is_empty() {
+                    self.dedicated.remove(channel);
+                }
+                self.publish_routes();
+                return self.is_empty(channel);
+            }
+        }
+        if let Some(groups) = self.groups.get_mut(channel) {
+            let mut removed = false;
+            groups.retain(|_, group| {
+                let members = group.members.len();
+                group.members.retain(|(member_id, _)| *member_id != user_id);
+                removed |= group.members.len() < members;
+                !group.members.is_empty()
+            });
+            if removed {
+                if groups.is_empty() {
+                    self.groups.remove(channel);
+                }
+                self.publish_routes();
+                return self.is_empty(channel);
+            }
+        }
+        if let Some(channel_info) = self.channels.get_mut(channel) {
             channel_info.subscribers.remove(&user_id);
-            if self.is_empty(channel) {
-                self.0.remove(channel);
+            // dropping a dedicated queue closes it once routes are replaced
+            let queued = channel_info.queues.remove(&user_id).is_some();
+            if queued {
+                channel_info.sender =
+                    ChannelSender::Queues(channel_info.queues.values().cloned().collect());
+            }
+            if channel_info.subscribers.is_empty() {
+                self.channels.remove(channel);
+                self.publish_routes();
+                return self.is_empty(channel);
+            }
+            if queued {
+                self.publish_routes();
             }
         }
+        false
+    }
+
+    // Whether consumer group `group` of channel has members
+    pub(crate) fn has_group_members(&self, channel: &HubChannelName, group: &str) -> bool {
+        self.groups
+            .get(channel)
+            .and_then(|groups| groups.get(group))
+            .is_some_and(|group| !group.members.is_empty())
     }
 
     // Returns number of subscribers in a given channel
     pub(crate) fn get_number_subscribers(&self, channel: &HubChannelName) -> usize {
-        if let Some(channel_info) = self.0.get(channel) {
-            return channel_info.subscribers.len();
-        }
-        0
+        let subscribers = self
+            .channels
+            .get(channel)
+            .map_or(0, |channel_info| channel_info.subscribers.len());
+        subscribers
+            + self.dedicated.get(channel).map_or(0, HashMap::len)
+            + self.groups.get(channel).map_or(0, group_members)
+    }
+
+    // Returns number of channels with subscribers
+    pub(crate) fn number_of_channels(&self) -> usize {
+        let mut channels: HashSet<_> = self.channels.keys().collect();
+        channels.extend(self.dedicated.keys());
+        channels.extend(self.groups.keys());
+        channels.len()
+    }
+
+    // Returns number of subscriptions across all channels
+    pub(crate) fn number_of_subscriptions(&self) -> usize {
+        let subscriptions: usize = self
+            .channels
+            .values()
+            .map(|channel_info| channel_info.subscribers.len())
+            .sum();
+        subscriptions
+            + self.dedicated.values().map(HashMap::len).sum::<usize>()
+            + self.groups.values().map(group_members).sum::<usize>()
     }
 
     // Returns true if there are no subscribers in a given channel
     pub(crate) fn is_empty(&self, channel: &HubChannelName) -> bool {
-        if let Some(channel_info) = self.0.get(channel) {
-            return channel_info.subscribers.is_empty();
-        }
-        true
+        self.get_number_subscribers(channel) == 0
     }
+}
 
-    // Returns  the channel sender associated to a hub channel
-    pub(crate) fn get_sender(
-        &self,
-        channel: &HubChannelName,
-    ) -> Option<broadcast::Sender<HubMessage>> {
-        if let Some(channel_info) = self.0.get(channel) {
-            return Some(channel_info.sender.clone());
-        }
-        None
-    }
+// Returns number of members of the consumer groups of a channel
+fn group_members(groups: &HashMap<String, ConsumerGroup>) -> usize {
+    groups.values().map(|group| group.members.len()).sum()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::{ChannelQosBuilder, Reliability};
 
     #[test]
     fn test_new_hub_channels() {
         let hub_channels = HubChannels::new();
-        assert!(hub_channels.0.is_empty());
+        assert!(hub_channels.channels.is_empty());
     }
 
     #[test]
@@ -97,10 +634,10 @@ mod tests {
         let hub_receiver = hub_channels.subscribe_user(&channel_name);
 
         assert_eq!(hub_channels.get_number_subscribers(&channel_name), 1);
-        assert!(hub_channels.0.contains_key(&channel_name));
-        assert!(hub_channels.0[&channel_name]
+        assert!(hub_channels.channels.contains_key(&channel_name));
+        assert!(hub_channels.channels[&channel_name]
             .subscribers
-            .contains(&hub_receiver.0));
+            .contains(&hub_receiver.user_id()));
     }
 
     #[test]
@@ -109,9 +646,10 @@ mod tests {
         let channel_name = HubChannelName::try_from("test_channel").unwrap();
         let hub_receiver = hub_channels.subscribe_user(&channel_name);
 
-        hub_channels.unsubscribe_user(&channel_name, hub_receiver.0);
+        assert!(hub_channels.unsubscribe_user(&channel_name, hub_receiver.user_id()));
+        assert!(!hub_channels.unsubscribe_user(&channel_name, hub_receiver.user_id()));
         assert_eq!(hub_channels.get_number_subscribers(&channel_name), 0);
-        assert!(!hub_channels.0.contains_key(&channel_name));
+        assert!(!hub_channels.channels.contains_key(&channel_name));
     }
 
     #[test]
@@ -122,6 +660,8 @@ mod tests {
         hub_channels.subscribe_user(&channel_name);
 
         assert_eq!(hub_channels.get_number_subscribers(&channel_name), 2);
+        assert_eq!(hub_channels.number_of_channels(), 1);
+        assert_eq!(hub_channels.number_of_subscriptions(), 2);
     }
 
     #[test]
@@ -135,12 +675,313 @@ mod tests {
     }
 
     #[test]
-    fn test_get_sender() {
+    fn test_single_subscriber_is_direct() {
         let mut hub_channels = HubChannels::new();
         let channel_name = HubChannelName::try_from("test_channel").unwrap();
-        hub_channels.subscribe_user(&channel_name);
+        let hub_receiver = hub_channels.subscribe_user(&channel_name);
+
+        assert!(hub_receiver.is_direct());
+        assert!(matches!(
+            hub_channels.routes().load().get(&channel_name),
+            Some(ChannelSender::Direct(..))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_second_subscriber_promotes_channel() {
+        let mut hub_channels = HubChannels::new();
+        let routes = hub_channels.routes();
+        let channel_name = HubChannelName::try_from("test_channel").unwrap();
+        let mut receiver1 = hub_channels.subscribe_user(&channel_name);
+        routes.load()[&channel_name]
+            .send(HubMessage::try_from_str("test_channel", "1").unwrap())
+            .unwrap();
+
+        let mut receiver2 = hub_channels.subscribe_user(&channel_name);
+        assert!(!receiver2.is_direct());
+        assert!(matches!(
+            routes.load().get(&channel_name),
+            Some(ChannelSender::Broadcast(_))
+        ));
+        routes.load()[&channel_name]
+            .send(HubMessage::try_from_str("test_channel", "2").unwrap())
+            .unwrap();
+
+        assert_eq!(receiver1.recv().await.unwrap().data.as_str(), "1");
+        assert_eq!(receiver1.recv().await.unwrap().data.as_str(), "2");
+        assert!(!receiver1.is_direct());
+        assert_eq!(receiver2.recv().await.unwrap().data.as_str(), "2");
+    }
+
+    #[test]
+    fn test_routes_follow_subscriptions() {
+        let mut hub_channels = HubChannels::new();
+        let routes = hub_channels.routes();
+        let channel_name = HubChannelName::try_from("test_channel").unwrap();
+        assert!(routes.load().is_empty());
+
+        let receiver1 = hub_channels.subscribe_user(&channel_name);
+        let receiver2 = hub_channels.subscribe_user(&channel_name);
+        assert!(routes.load().contains_key(&channel_name));
+
+        hub_channels.unsubscribe_user(&channel_name, receiver1.user_id());
+        assert!(routes.load().contains_key(&channel_name));
+
+        hub_channels.unsubscribe_user(&channel_name, receiver2.user_id());
+        assert!(routes.load().is_empty());
+    }
+
+    fn qos(channel: &HubChannelName, qos: ChannelQos) -> HashMap<HubChannelName, ChannelQos> {
+        HashMap::from([(channel.clone(), qos)])
+    }
+
+    #[tokio::test]
+    async fn test_reliable_channel_never_drops() {
+        let channel_name = HubChannelName::try_from("cmd").unwrap();
+        let reliable = ChannelQosBuilder::new()
+            .reliability(Reliability::Reliable)
+            .build()
+            .unwrap();
+        let mut hub_channels = HubChannels::with_qos(qos(&channel_name, reliable));
+        let routes = hub_channels.routes();
+        let mut receiver1 = hub_channels.subscribe_user(&channel_name);
+        let mut receiver2 = hub_channels.subscribe_user(&channel_name);
+        assert!(matches!(
+            routes.load().get(&channel_name),
+            Some(ChannelSender::Queues(queues)) if queues.len() == 2
+        ));
+
+        let n = 2 * CHANNEL_CAPACITY;
+        for i in 0..n {
+            routes.load()[&channel_name]
+                .send(HubMessage::try_from_str("cmd", &i.to_string()).unwrap())
+                .unwrap();
+        }
+        for i in 0..n {
+            assert_eq!(receiver1.recv().await.unwrap().data.as_str(), i.to_string());
+            assert_eq!(receiver2.recv().await.unwrap().data.as_str(), i.to_string());
+        }
 
-        let sender = hub_channels.get_sender(&channel_name);
-        assert!(sender.is_some());
+        hub_channels.unsubscribe_user(&channel_name, receiver2.user_id());
+        assert!(matches!(
+            routes.load().get(&channel_name),
+            Some(ChannelSender::Queues(queues)) if queues.len() == 1
+        ));
+        assert!(receiver2.recv().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_best_effort_channel_drops_stale_messages() {
+        let channel_name = HubChannelName::try_from("imu").unwrap();
+        let best_effort = ChannelQosBuilder::new().depth(2).build().unwrap();
+        let mut hub_channels = HubChannels::with_qos(qos(&channel_name, best_effort));
+        let routes = hub_channels.routes();
+        let mut receiver = hub_channels.subscribe_user(&channel_name);
+        assert!(!receiver.is_direct());
+
+        for data in ["1", "2", "3", "4"] {
+            routes.load()[&channel_name]
+                .send(HubMessage::try_from_str("imu", data).unwrap())
+                .unwrap();
+        }
+        assert!(receiver.recv().await.is_err());
+        assert_eq!(receiver.recv().await.unwrap().data.as_str(), "3");
+        assert_eq!(receiver.recv().await.unwrap().data.as_str(), "4");
+    }
+
+    #[tokio::test]
+    async fn test_filtered_subscriber() {
+        let mut hub_channels = HubChannels::new();
+        let routes = hub_channels.routes();
+        let channel_name = HubChannelName::try_from("battery").unwrap();
+        let mut receiver = hub_channels.subscribe_user(&channel_name);
+        let mut filtered = hub_channels.subscribe_user_filtered(
+            &channel_name,
+            MessageFilter::Below {
+                field: 0,
+                threshold: 11.0,
+            },
+            Uuid::new_v4(),
+        );
+        assert_eq!(hub_channels.get_number_subscribers(&channel_name), 2);
+        assert_eq!(hub_channels.number_of_channels(), 1);
+        assert!(matches!(
+            routes.load().get(&channel_name),
+            Some(ChannelSender::Fanout(senders)) if senders.len() == 2
+        ));
+
+        for data in ["12.1", "10.9", "11.5", "10.2"] {
+            routes.load()[&channel_name]
+                .send(HubMessage::try_from_str("battery", data).unwrap())
+                .unwrap();
+        }
+        for data in ["12.1", "10.9", "11.5", "10.2"] {
+            assert_eq!(receiver.recv().await.unwrap().data.as_str(), data);
+        }
+        assert_eq!(filtered.recv().await.unwrap().data.as_str(), "10.9");
+        assert_eq!(filtered.recv().await.unwrap().data.as_str(), "10.2");
+
+        assert!(!hub_channels.unsubscribe_user(&channel_name, receiver.user_id()));
+        assert!(matches!(
+            routes.load().get(&channel_name),
+            Some(ChannelSender::Fanout(senders)) if senders.len() == 1
+        ));
+        assert!(hub_channels.unsubscribe_user(&channel_name, filtered.user_id()));
+        assert!(routes.load().is_empty());
+        assert!(filtered.recv().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_consumer_group() {
+        let mut hub_channels = HubChannels::new();
+        let routes = hub_channels.routes();
+        let channel_name = HubChannelName::try_from("camera").unwrap();
+        let mut worker1 =
+            hub_channels.subscribe_user_grouped(&channel_name, "workers", Uuid::new_v4());
+        let mut worker2 =
+            hub_channels.subscribe_user_grouped(&channel_name, "workers", Uuid::new_v4());
+        let mut recorder =
+            hub_channels.subscribe_user_grouped(&channel_name, "recorder", Uuid::new_v4());
+        assert_eq!(hub_channels.get_number_subscribers(&channel_name), 3);
+        assert_eq!(hub_channels.number_of_channels(), 1);
+
+        for data in ["1", "2", "3", "4"] {
+            routes.load()[&channel_name]
+                .send(HubMessage::try_from_str("camera", data).unwrap())
+                .unwrap();
+        }
+        assert_eq!(worker1.recv().await.unwrap().data.as_str(), "1");
+        assert_eq!(worker2.recv().await.unwrap().data.as_str(), "2");
+        assert_eq!(worker1.recv().await.unwrap().data.as_str(), "3");
+        assert_eq!(worker2.recv().await.unwrap().data.as_str(), "4");
+        for data in ["1", "2", "3", "4"] {
+            assert_eq!(recorder.recv().await.unwrap().data.as_str(), data);
+        }
+
+        assert!(!hub_channels.unsubscribe_user(&channel_name, worker1.user_id()));
+        routes.load()[&channel_name]
+            .send(HubMessage::try_from_str("camera", "5").unwrap())
+            .unwrap();
+        assert_eq!(worker2.recv().await.unwrap().data.as_str(), "5");
+        assert!(!hub_channels.unsubscribe_user(&channel_name, worker2.user_id()));
+        assert!(hub_channels.unsubscribe_user(&channel_name, recorder.user_id()));
+        assert!(routes.load().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_drop_newest_channel() {
+        let channel_name = HubChannelName::try_from("camera").unwrap();
+        let drop_newest = ChannelQosBuilder::new()
+            .backpressure(Backpressure::DropNewest)
+            .depth(2)
+            .build()
+            .unwrap();
+        let mut hub_channels = HubChannels::with_qos(qos(&channel_name, drop_newest));
+        let routes = hub_channels.routes();
+        let mut receiver = hub_channels.subscribe_user(&channel_name);
+
+        for data in ["1", "2", "3", "4"] {
+            routes.load()[&channel_name]
+                .send(HubMessage::try_from_str("camera", data).unwrap())
+                .unwrap();
+        }
+        assert_eq!(receiver.dropped(), 2);
+        assert_eq!(receiver.recv().await.unwrap().data.as_str(), "1");
+        assert_eq!(receiver.recv().await.unwrap().data.as_str(), "2");
+    }
+
+    #[tokio::test]
+    async fn test_blocking_channel_defers_messages() {
+        let channel_name = HubChannelName::try_from("cmd").unwrap();
+        let blocking = ChannelQosBuilder::new()
+            .backpressure(Backpressure::Block)
+            .depth(1)
+            .build()
+            .unwrap();
+        let mut hub_channels = HubChannels::with_qos(qos(&channel_name, blocking));
+        let routes = hub_channels.routes();
+        let mut receiver = hub_channels.subscribe_user(&channel_name);
+
+        let sender = routes.load()[&channel_name].clone();
+        assert!(sender
+            .send(HubMessage::try_from_str("cmd", "1").unwrap())
+            .unwrap()
+            .is_empty());
+        let deferred = sender
+            .send(HubMessage::try_from_str("cmd", "2").unwrap())
+            .unwrap();
+        assert_eq!(deferred.len(), 1);
+
+        assert_eq!(receiver.recv().await.unwrap().data.as_str(), "1");
+        for (queue, message) in deferred {
+            queue.send(message).await.unwrap();
+        }
+        assert_eq!(receiver.recv().await.unwrap().data.as_str(), "2");
+        assert_eq!(receiver.dropped(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_direct_channel_counts_drops() {
+        let mut hub_channels = HubChannels::new();
+        let routes = hub_channels.routes();
+        let channel_name = HubChannelName::try_from("test_channel").unwrap();
+        let receiver = hub_channels.subscribe_user(&channel_name);
+
+        for i in 0..CHANNEL_CAPACITY + 5 {
+            let _ = routes.load()[&channel_name]
+                .send(HubMessage::try_from_str("test_channel", &i.to_string()).unwrap());
+        }
+        assert_eq!(receiver.dropped(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_subscriber_capacity() {
+        let mut hub_channels = HubChannels::new();
+        let routes = hub_channels.routes();
+        let channel_name = HubChannelName::try_from("cmd").unwrap();
+        let mut default = hub_channels.subscribe_user(&channel_name);
+        let mut small = hub_channels.subscribe_user_with_capacity(&channel_name, 2, Uuid::new_v4());
+        assert_eq!(hub_channels.get_number_subscribers(&channel_name), 2);
+
+        for i in 0..5 {
+            let _ = routes.load()[&channel_name]
+                .send(HubMessage::try_from_str("cmd", &i.to_string()).unwrap());
+        }
+        assert_eq!(small.dropped(), 3);
+        assert_eq!(default.dropped(), 0);
+        for i in 0..2 {
+            assert_eq!(small.recv().await.unwrap().data.as_str(), i.to_string());
+        }
+        for i in 0..5 {
+            assert_eq!(default.recv().await.unwrap().data.as_str(), i.to_string());
+        }
+
+        assert!(!hub_channels.unsubscribe_user(&channel_name, small.user_id()));
+        assert!(small.recv().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_drop_oldest_dedicated_queue() {
+        use tokio::sync::broadcast::error::RecvError;
+
+        let channel_name = HubChannelName::try_from("camera").unwrap();
+        let qos = ChannelQosBuilder::new()
+            .backpressure(Backpressure::DropOldest)
+            .build()
+            .unwrap();
+        let mut hub_channels = HubChannels::with_qos(HashMap::from([(channel_name.clone(), qos)]));
+        let routes = hub_channels.routes();
+        let mut receiver =
+            hub_channels.subscribe_user_with_capacity(&channel_name, 2, Uuid::new_v4());
+
+        for i in 0..5 {
+            let _ = routes.load()[&channel_name]
+                .send(HubMessage::try_from_str("camera", &i.to_string()).unwrap());
+        }
+        assert!(matches!(receiver.recv().await, Err(RecvError::Lagged(3))));
+        assert_eq!(receiver.dropped(), 3);
+        for i in 3..5 {
+            assert_eq!(receiver.recv().await.unwrap().data.as_str(), i.to_string());
+        }
     }
 }
@@ -1,35 +1,84 @@
-use log::{error, info};
-use std::collections::HashSet;
+use arc_swap::ArcSwap;
+use futures_util::stream::BoxStream;
+use log::{error, info, warn};
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
-use tokio::sync::{broadcast, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, mpsc, Mutex};
 use uuid::Uuid;
 
-use super::channel::HubChannels;
+use super::channel::{HubChannels, HubRoutes};
+use super::dispatch::Dispatcher;
+use super::events::{is_meta_channel, ChannelEvent, ChannelWatcher};
+use super::filter::MessageFilter;
+use super::health::{HealthTracker, MonitoredNode, NodeHealth};
+use super::history::{HubHistory, Replay};
+use super::middleware::{MiddlewarePipeline, RateLimit};
+use super::pause::PausedChannels;
+pub use super::receiver::HubReceiver;
+use super::receiver::{TypedReceiver, Unsubscriber};
+use super::remap::RemappedNode;
+use super::routing::{HubNode, HubRouting, NodeId, RoutePattern};
+use super::rpc::{self, RpcMessage};
+use super::schema::{ChannelSchema, PublishValidation, SchemaRegistry, SchemaValidation};
+use super::sender::HubSender;
+use super::stats::{ChannelMetrics, HubStats};
+use super::supervisor::Supervisor;
 use super::user::HubUsers;
-use crate::models::hub::{HubChannelName, HubMessage};
-use crate::ports::NotificationHub;
+use crate::config::hub::robot_namespace;
+use crate::config::{Backpressure, HubOptions, RemapRules, RemapRulesBuilder};
+use crate::models::hub::{HubChannelName, HubData, HubMessage, HubPayload};
+use crate::ports::{MessageMiddleware, NotificationHub};
 
 const CHANNEL_CAPACITY: usize = 100;
 
-/// Tuple cosisting of user id (Uuid) and channel receiver
+/// Lifecycle state of a `HubManager`
 #[derive(Debug)]
-pub struct HubReceiver(pub Uuid, pub(crate) broadcast::Receiver<HubMessage>);
+enum Lifecycle {
+    Created,
+    Running(Supervisor),
+    Stopped,
+}
 
-impl HubReceiver {
-    pub(crate) fn resubscribe(&self) -> Self {
-        let receiver = self.1.resubscribe();
-        Self(self.0, receiver)
-    }
-    pub fn user_id(&self) -> Uuid {
-        self.0
-    }
-    pub fn receiver(&self) -> broadcast::Receiver<HubMessage> {
-        self.1.resubscribe()
-    }
+/// Delivery of the messages of a channel to a subscriber
+/// - `SubscriptionMode::Shared` -> Subscriber shares the channel sender with other subscribers.
+/// - `SubscriptionMode::Filtered` -> Subscriber gets the messages passing the filter.
+/// - `SubscriptionMode::Grouped` -> Subscriber is a member of a consumer group sharing the
+///   messages of the channel.
+/// - `SubscriptionMode::Capacity` -> Subscriber gets a buffer of the given capacity, instead of
+///   the channel default.
+#[derive(Debug, Clone)]
+enum SubscriptionMode {
+    Shared,
+    Filtered(MessageFilter),
+    Grouped(String),
+    Capacity(usize),
+}
+
+/// Options of a subscription to a channel.
+///
+/// # Fields
+/// - `replay`: Messages of the channel history delivered before live data. The retained
+///   message of the channel, if any, when not set.
+/// - `mode`: Delivery of the channel messages to the subscriber.
+/// - `user_id`: Identifier of the subscriber. A new identifier by default.
+#[derive(Debug, Clone)]
+struct SubscribeOptions {
+    replay: Option<Replay>,
+    mode: SubscriptionMode,
+    user_id: Uuid,
 }
 
-const SERIAL_IDX: usize = 0;
-const WS_IDX: usize = 1;
+impl Default for SubscribeOptions {
+    fn default() -> Self {
+        Self {
+            replay: None,
+            mode: SubscriptionMode::Shared,
+            user_id: Uuid::new_v4(),
+        }
+    }
+}
 
 /// `HubManager` controls communications through a NotificationHub network by
 /// maintaining the set of topic channels in the hub, the set of subscribers
@@ -39,7 +88,7 @@ const WS_IDX: usize = 1;
 /// These nodes mimic a pub sub network, where one can subscribe to a given topic channel.
 /// Hub_sender and hub_receiver are the sender and receiver channels where the HubManager
 /// receives information from hub nodes. This information is then dispatched to subscribed
-/// users
+/// users, from one or several dispatch workers as configured in `HubOptions`.
 ///
 /// A subscriber is typically a processing entity that wants to receive certain
 ///  data from the hub. For example, a control unit that needs to compute the path
@@ -48,27 +97,221 @@ const WS_IDX: usize = 1;
 /// sensor data is available through the receiver channel in the form of `HubMessages`
 #[derive(Debug)]
 pub struct HubManager {
+    // channels are removed as soon as their last subscriber leaves
     channels: Arc<Mutex<HubChannels>>,
-    subscribers: HubUsers,
+    // snapshot of the channel routes read by dispatch, only replaced when channels are added
+    // or removed
+    routes: Arc<ArcSwap<HubRoutes>>,
+    history: Arc<HubHistory>,
+    subscribers: Arc<Mutex<HubUsers>>,
+    // unsubscribe requests of dropped receivers, processed by a task spawned when the hub is
+    // started
+    unsubscriber: Unsubscriber,
+    unsubscribe_requests: Arc<Mutex<mpsc::UnboundedReceiver<(HubChannelName, Uuid)>>>,
     hub_sender: broadcast::Sender<HubMessage>,
     hub_receiver: Arc<Mutex<broadcast::Receiver<HubMessage>>>,
-    hub_nodes: Vec<Box<dyn NotificationHub>>,
+    hub_nodes: Vec<HubNode>,
+    health: Vec<(NodeId, Arc<HealthTracker>)>,
+    routing: HubRouting,
+    robots: HashMap<HubChannelName, NodeId>,
+    middlewares: MiddlewarePipeline,
+    metrics: Arc<ChannelMetrics>,
+    paused: Arc<PausedChannels>,
+    // dispatcher of the running hub, routing the messages held by paused channels when they
+    // are resumed
+    dispatcher: std::sync::Mutex<Option<Arc<Dispatcher>>>,
+    schemas: SchemaRegistry,
+    channel_watcher: ChannelWatcher,
+    lifecycle: std::sync::Mutex<Lifecycle>,
+    options: HubOptions,
 }
 
 impl HubManager {
     pub fn new() -> Self {
+        Self::with_options(HubOptions::default())
+    }
+
+    /// Creates a hub configured by `options` (dispatch workers, channel QoS and priorities,
+    /// history, supervision, channel TTL...). Hubs can also be assembled from declarative node
+    /// configurations with `HubManagerBuilder`.
+    pub fn with_options(options: HubOptions) -> Self {
         let (hub_sender, hub_receiver) = broadcast::channel(CHANNEL_CAPACITY);
+        let channels = HubChannels::with_qos(options.qos().clone());
+        let routes = channels.routes();
+        let mut middlewares = MiddlewarePipeline::default();
+        for (channel, qos) in options.qos() {
+            if let Some(min_interval) = qos.min_interval() {
+                middlewares.add_to_channel(channel.clone(), Arc::new(RateLimit::new(min_interval)));
+            }
+        }
+        let (unsubscriber, unsubscribe_requests) = mpsc::unbounded_channel();
         Self {
-            channels: Arc::new(Mutex::new(HubChannels::new())),
-            subscribers: HubUsers::new(),
+            channels: Arc::new(Mutex::new(channels)),
+            routes,
+            history: Arc::new(HubHistory::from_options(&options)),
+            subscribers: Arc::new(Mutex::new(HubUsers::new())),
+            unsubscriber,
+            unsubscribe_requests: Arc::new(Mutex::new(unsubscribe_requests)),
             hub_sender,
             hub_receiver: Arc::new(Mutex::new(hub_receiver)),
             hub_nodes: Vec::new(),
+            health: Vec::new(),
+            routing: HubRouting::new(),
+            robots: HashMap::new(),
+            middlewares,
+            metrics: Arc::new(ChannelMetrics::default()),
+            paused: Arc::new(PausedChannels::default()),
+            schemas: SchemaRegistry::default(),
+            channel_watcher: ChannelWatcher::new(),
+            dispatcher: std::sync::Mutex::new(None),
+            lifecycle: std::sync::Mutex::new(Lifecycle::Created),
+            options,
+        }
+    }
+
+    /// Adds hub node named `name`. Returns the node identifier used in routing rules. Fails if
+    /// the name is invalid or already taken by another node
+    pub fn add(
+        &mut self,
+        name: &str,
+        hub_node: Box<dyn NotificationHub>,
+    ) -> Result<NodeId, String> {
+        self.add_remapped(name, hub_node, RemapRules::default())
+    }
+
+    /// Adds hub node named `name` whose channels are translated with `rules`, on top of the
+    /// remap rules of `HubOptions`. Returns the node identifier used in routing rules
+    pub fn add_remapped(
+        &mut self,
+        name: &str,
+        hub_node: Box<dyn NotificationHub>,
+        rules: RemapRules,
+    ) -> Result<NodeId, String> {
+        let node = NodeId::try_from(name)?;
+        if self.node(&node).is_some() {
+            return Err(format!("Hub node {} already added", node));
+        }
+        let rules = self.options.remap().merge(&rules);
+        let hub_node: Arc<dyn NotificationHub> = if rules.is_empty() {
+            Arc::from(hub_node)
+        } else {
+            Arc::new(RemappedNode::new(hub_node, rules))
+        };
+        let health = Arc::new(HealthTracker::default());
+        self.health.push((node.clone(), health.clone()));
+        let monitored = MonitoredNode::new(
+            node.clone(),
+            hub_node,
+            health,
+            self.options.node_restart_delay(),
+            self.options.node_retry(),
+        );
+        self.hub_nodes.push((node.clone(), Arc::new(monitored)));
+        Ok(node)
+    }
+
+    /// Removes hub node `node`, together with its routing rules and health. Removing a node
+    /// after the hub is started stops publishing and subscribing through it, but its tasks
+    /// keep running until it disconnects.
+    pub fn remove(&mut self, node: &NodeId) -> Result<(), String> {
+        if self.node(node).is_none() {
+            return Err(format!("Unknown hub node {}", node));
+        }
+        self.hub_nodes.retain(|(id, _)| id != node);
+        self.health.retain(|(id, _)| id != node);
+        self.routing.remove_node(node);
+        self.robots.retain(|_, robot_node| robot_node != node);
+        Ok(())
+    }
+
+    // Returns hub node `node`, if added
+    fn node(&self, node: &NodeId) -> Option<&Arc<dyn NotificationHub>> {
+        self.hub_nodes
+            .iter()
+            .find(|(id, _)| id == node)
+            .map(|(_, hub_node)| hub_node)
+    }
+
+    /// Returns the identifiers of the hub nodes, in the order they were added
+    pub fn nodes(&self) -> Vec<NodeId> {
+        self.hub_nodes.iter().map(|(id, _)| id.clone()).collect()
+    }
+
+    /// Adds hub node bridging the hub of robot `robot_id` (e.g. a WebSocket client connected
+    /// to the robot), named after the robot. Channels of the robot are namespaced as
+    /// `<robot_id>/...`, and messages and subscriptions in the namespace are only sent to the
+    /// robot. Returns the node identifier used in routing rules. Hubs on different machines can
+    /// be federated with `FederationExport` instead, so only subscribed channels cross the link
+    pub fn add_robot(
+        &mut self,
+        robot_id: &str,
+        hub_node: Box<dyn NotificationHub>,
+    ) -> Result<NodeId, String> {
+        let namespace = robot_namespace(robot_id)?;
+        if self.robots.contains_key(&namespace) {
+            return Err(format!("Robot {} already added", namespace.as_str()));
         }
+        let rules = RemapRulesBuilder::new()
+            .prefix(namespace.as_str())
+            .build()?;
+        let node = self.add_remapped(namespace.as_str(), hub_node, rules)?;
+        self.routing
+            .add_namespace_route(namespace.clone(), node.clone());
+        self.robots.insert(namespace, node.clone());
+        Ok(node)
+    }
+
+    /// Returns the identifier of the robot running the hub, if set in `HubOptions`
+    pub fn robot_id(&self) -> Option<&str> {
+        self.options.robot_id()
+    }
+
+    /// Returns the identifiers of the robots bridged with `add_robot`
+    pub fn robots(&self) -> Vec<String> {
+        self.robots
+            .keys()
+            .map(|robot_id| robot_id.as_str().to_string())
+            .collect()
+    }
+
+    /// Routes messages published to `channel` to `node`, which owns or bridges the channel.
+    /// Channels without routes are published to all hub nodes.
+    pub fn route(&mut self, channel: HubChannelName, node: NodeId) {
+        self.routing.add_route(channel, node);
+    }
+
+    /// Routes messages published to the channels matched by `pattern` to `node` (e.g.
+    /// `telemetry/*` to the WebSocket node). Channel routes take precedence over namespace
+    /// routes.
+    pub fn route_pattern(&mut self, pattern: RoutePattern, node: NodeId) {
+        self.routing.add_pattern_route(pattern, node);
     }
 
-    pub fn add(&mut self, hub_node: Box<dyn NotificationHub>) {
-        self.hub_nodes.push(hub_node);
+    /// Adds middleware applied to messages of every channel before they are delivered to
+    /// subscribers. Middlewares are applied in the order they are added, and can filter,
+    /// transform or block messages. When several hub nodes bridge the same channel, a
+    /// `Deduplicate` middleware keeps subscribers from receiving the same sample twice.
+    pub fn add_middleware(&mut self, middleware: Box<dyn MessageMiddleware>) {
+        self.middlewares.add(Arc::from(middleware));
+    }
+
+    /// Adds middleware applied to messages of `channel`, after global middlewares
+    pub fn add_channel_middleware(
+        &mut self,
+        channel: HubChannelName,
+        middleware: Box<dyn MessageMiddleware>,
+    ) {
+        self.middlewares
+            .add_to_channel(channel, Arc::from(middleware));
+    }
+
+    /// Returns a `HubSender` publishing messages to the hub nodes and routes added so far
+    pub fn sender(&self) -> HubSender {
+        HubSender::spawn(
+            self.hub_nodes.clone(),
+            self.routing.clone(),
+            self.publish_validation(),
+        )
     }
 
     /// Request hub node to register to specific channel
@@ -76,40 +319,199 @@ impl HubManager {
         &self,
         channel: &HubChannelName,
     ) -> Result<(), std::io::Error> {
-        for node in &self.hub_nodes {
+        for node in self.routing.route_subscription(channel, &self.hub_nodes) {
             node.subscribe(channel.clone()).await?;
         }
         Ok(())
     }
 
-    // Request hub node to unregister from speficic channel
-    async fn unregister_from_hub_channel(
-        &self,
-        channel: &HubChannelName,
-    ) -> Result<(), std::io::Error> {
-        for node in &self.hub_nodes {
-            node.unsubscribe(channel.clone()).await?;
+    fn lifecycle(&self) -> std::sync::MutexGuard<'_, Lifecycle> {
+        self.lifecycle.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Starts the hub nodes and the background tasks of the hub. A stopped hub is started
+    /// again: hub nodes are resubscribed to the channels with subscribers, and messages
+    /// received while stopped are discarded. Fails if the hub is already running.
+    /// Tasks are supervised: failures are published on the `HUB_ERRORS` meta-channel, and hub
+    /// tasks are restarted according to the `SupervisionPolicy` in `HubOptions`
+    pub async fn start(&self) -> Result<(), std::io::Error> {
+        let supervisor = Supervisor::new(self.hub_sender.clone(), self.options.supervision());
+        let restarting = {
+            let mut lifecycle = self.lifecycle();
+            let restarting = match *lifecycle {
+                Lifecycle::Running(_) => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::AlreadyExists,
+                        "Hub is already running",
+                    ))
+                }
+                Lifecycle::Created => false,
+                Lifecycle::Stopped => true,
+            };
+            *lifecycle = Lifecycle::Running(supervisor.clone());
+            restarting
+        };
+        if let Err(e) = self.launch(&supervisor, restarting).await {
+            self.stop().await;
+            return Err(e);
         }
         Ok(())
     }
 
-    // Start hub.
-    pub async fn start(&self) -> Result<(), std::io::Error> {
+    /// Stops the background tasks of the hub and the tasks of its nodes, keeping channels and
+    /// subscriptions, so the hub can be started again. Stopping a hub that isn't running has
+    /// no effect
+    pub async fn stop(&self) {
+        let lifecycle = std::mem::replace(&mut *self.lifecycle(), Lifecycle::Stopped);
+        let Lifecycle::Running(supervisor) = lifecycle else {
+            *self.lifecycle() = lifecycle;
+            return;
+        };
+        supervisor.shutdown().await;
+        *self.dispatcher.lock().unwrap_or_else(|e| e.into_inner()) = None;
+        for (_, health) in &self.health {
+            health.set_alive(false);
+        }
+    }
+
+    /// Whether the hub is started and not stopped
+    pub fn is_running(&self) -> bool {
+        matches!(*self.lifecycle(), Lifecycle::Running(_))
+    }
+
+    // Starts hub nodes and hub tasks under `supervisor`
+    async fn launch(
+        &self,
+        supervisor: &Supervisor,
+        restarting: bool,
+    ) -> Result<(), std::io::Error> {
         let hub_sender = self.hub_sender.clone();
-        for node in &self.hub_nodes {
-            node.start(Some(hub_sender.clone())).await?;
+        for (id, node) in &self.hub_nodes {
+            for task in node.start(Some(hub_sender.clone())).await? {
+                supervisor.monitor(format!("hub node {}", id), task);
+            }
+        }
+        if restarting {
+            {
+                let mut hub_receiver = self.hub_receiver.lock().await;
+                *hub_receiver = hub_receiver.resubscribe();
+            }
+            let channels: Vec<_> = self
+                .routes
+                .load()
+                .keys()
+                .filter(|channel| !is_meta_channel(channel))
+                .cloned()
+                .collect();
+            for channel in channels {
+                self.register_to_hub_channel(&channel).await?;
+            }
         }
+
         let hub_receiver = self.hub_receiver.clone();
-        let channels = self.channels.clone();
+        let mut middlewares = self.middlewares.clone();
+        if self.options.validate_schemas() {
+            middlewares.add(Arc::new(SchemaValidation::new(self.schemas.clone())));
+        }
+        let dispatcher = Arc::new(Dispatcher::spawn(
+            self.routes.clone(),
+            self.history.clone(),
+            middlewares,
+            self.metrics.clone(),
+            self.paused.clone(),
+            &self.options,
+            supervisor,
+        ));
+        *self.dispatcher.lock().unwrap_or_else(|e| e.into_inner()) = Some(dispatcher.clone());
+        let watcher = self.channel_watcher.clone();
+        let events_sender = hub_sender.clone();
+        supervisor.supervise("dispatch", move || {
+            let hub_receiver = hub_receiver.clone();
+            let dispatcher = dispatcher.clone();
+            let watcher = watcher.clone();
+            let events_sender = events_sender.clone();
+            async move {
+                let mut receiver = hub_receiver.lock().await;
+                loop {
+                    match receiver.recv().await {
+                        Ok(data) => {
+                            watcher.observe(&data.channel, &events_sender);
+                            dispatcher.dispatch(data).await
+                        }
+                        Err(RecvError::Lagged(n)) => {
+                            warn!("Hub dispatch lagged behind. {} messages lost", n)
+                        }
+                        Err(RecvError::Closed) => break,
+                    }
+                }
+            }
+        });
 
-        tokio::spawn(async move {
-            let mut receiver = hub_receiver.lock().await;
-            while let Ok(data) = receiver.recv().await {
-                // retrieve channel from data and broadcast to all registered clients
-                let channels_lock = channels.lock().await;
-                if let Some(sender) = channels_lock.get_sender(&data.channel) {
-                    info!("Received data: {:?}", data);
-                    let _ = sender.send(data).map_err(|e| error!("Error : {:?}", e));
+        let health = self.health.clone();
+        let health_sender = hub_sender.clone();
+        let health_interval = self.options.health_interval();
+        supervisor.supervise("health", move || {
+            publish_health(health.clone(), health_sender.clone(), health_interval)
+        });
+
+        let metrics = self.metrics.clone();
+        let routes = self.routes.clone();
+        let stats_sender = hub_sender.clone();
+        let stats_interval = self.options.stats_interval();
+        supervisor.supervise("stats", move || {
+            publish_stats(
+                metrics.clone(),
+                routes.clone(),
+                stats_sender.clone(),
+                stats_interval,
+            )
+        });
+
+        if let Some(channel_ttl) = self.options.channel_ttl() {
+            let metrics = self.metrics.clone();
+            let routes = self.routes.clone();
+            let history = self.history.clone();
+            supervisor.supervise("channel gc", move || {
+                collect_idle_channels(
+                    metrics.clone(),
+                    routes.clone(),
+                    history.clone(),
+                    channel_ttl,
+                )
+            });
+        }
+
+        let watcher = self.channel_watcher.clone();
+        let hub_nodes = self.hub_nodes.clone();
+        let interval = self.options.channel_watch_interval();
+        supervisor.supervise("channel watcher", move || {
+            watcher
+                .clone()
+                .run(hub_nodes.clone(), hub_sender.clone(), interval)
+        });
+
+        // unsubscribe dropped receivers
+        let unsubscribe_requests = self.unsubscribe_requests.clone();
+        let channels = self.channels.clone();
+        let subscribers = self.subscribers.clone();
+        let hub_nodes = self.hub_nodes.clone();
+        let routing = self.routing.clone();
+        supervisor.supervise("unsubscribe", move || {
+            let unsubscribe_requests = unsubscribe_requests.clone();
+            let channels = channels.clone();
+            let subscribers = subscribers.clone();
+            let hub_nodes = hub_nodes.clone();
+            let routing = routing.clone();
+            async move {
+                let mut unsubscribe_requests = unsubscribe_requests.lock().await;
+                while let Some((channel, user_id)) = unsubscribe_requests.recv().await {
+                    let nodes = routing.route_subscription(&channel, &hub_nodes);
+                    if let Err(e) =
+                        release_subscription(&channels, &subscribers, &nodes, &channel, user_id)
+                            .await
+                    {
+                        error!("Failed to unsubscribe from {:?}: {:?}", channel, e);
+                    }
                 }
             }
         });
@@ -119,26 +521,231 @@ impl HubManager {
     // List availabe topic channels in the Hub network
     pub async fn list_channels(&self) -> Result<HashSet<HubChannelName>, std::io::Error> {
         let mut channels = HashSet::new();
-        for node in &self.hub_nodes {
+        for (_, node) in &self.hub_nodes {
             channels.extend(node.list_channels().await?);
         }
         Ok(channels)
     }
 
+    /// Returns a stream of channel lifecycle events. The stream starts with a `ChannelAdded`
+    /// event for every channel already available, so no channel is missed. New channels are
+    /// reported as soon as their first message is received, without waiting for the next
+    /// refresh of the hub node channels. Events are also published on `CHANNEL_EVENTS`.
+    pub fn watch_channels(&self) -> BoxStream<'static, ChannelEvent> {
+        self.channel_watcher.watch()
+    }
+
     // Returns a receiver channel for a specific channel that the requestor can listen to
-    // to obtain data from a topic channel
+    // to obtain data from a topic channel. The receiver unsubscribes when dropped
     pub async fn register_to_channel(
         &mut self,
         channel: HubChannelName,
     ) -> Result<HubReceiver, std::io::Error> {
+        self.subscribe(channel, SubscribeOptions::default()).await
+    }
+
+    /// Returns a receiver for a specific channel that decodes the data of every message into a
+    /// `T`. Messages that can't be decoded are skipped and counted by the receiver.
+    pub async fn register_typed<T: HubPayload>(
+        &mut self,
+        channel: HubChannelName,
+    ) -> Result<TypedReceiver<T>, std::io::Error> {
+        Ok(TypedReceiver::new(self.register_to_channel(channel).await?))
+    }
+
+    /// Returns a receiver for a specific channel that delivers up to the last `replay` messages
+    /// kept in the channel history before live data. The number of replayed messages is
+    /// bounded by the history depth configured in `HubOptions`, for all or each channel.
+    pub async fn register_to_channel_with_history(
+        &mut self,
+        channel: HubChannelName,
+        replay: usize,
+    ) -> Result<HubReceiver, std::io::Error> {
+        let options = SubscribeOptions {
+            replay: Some(Replay::Last(replay)),
+            ..Default::default()
+        };
+        self.subscribe(channel, options).await
+    }
+
+    /// Returns a receiver for a specific channel that delivers the messages kept in the channel
+    /// history selected by `replay` (e.g. the last few seconds of sensor data) before live
+    /// data. Replayed messages are bounded by the history depth configured in `HubOptions`.
+    pub async fn register_to_channel_with_replay(
+        &mut self,
+        channel: HubChannelName,
+        replay: Replay,
+    ) -> Result<HubReceiver, std::io::Error> {
+        let options = SubscribeOptions {
+            replay: Some(replay),
+            ..Default::default()
+        };
+        self.subscribe(channel, options).await
+    }
+
+    /// Returns a receiver for a specific channel that only delivers messages passing `filter`.
+    /// Messages are filtered when dispatched, so the receiver is not woken up by other messages.
+    pub async fn register_to_channel_with_filter(
+        &mut self,
+        channel: HubChannelName,
+        filter: MessageFilter,
+    ) -> Result<HubReceiver, std::io::Error> {
+        let options = SubscribeOptions {
+            mode: SubscriptionMode::Filtered(filter),
+            ..Default::default()
+        };
+        self.subscribe(channel, options).await
+    }
+
+    /// Returns a receiver for a specific channel buffering up to `capacity` messages, instead of
+    /// the default capacity shared by the channel subscribers. High-rate channels (e.g. lidar
+    /// scans) can get deep buffers, and command channels tiny ones. Messages for a full buffer
+    /// are handled according to the channel backpressure, and dropped by default. Channels
+    /// with `Backpressure::Expand` buffer every pending message, so they don't take a capacity.
+    pub async fn register_to_channel_with_capacity(
+        &mut self,
+        channel: HubChannelName,
+        capacity: usize,
+    ) -> Result<HubReceiver, std::io::Error> {
+        if capacity == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Buffer capacity must be greater than 0",
+            ));
+        }
+        if let Some(qos) = self.options.channel_qos(&channel) {
+            if qos.backpressure() == Backpressure::Expand {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("Channel {} buffers every pending message", channel.as_str()),
+                ));
+            }
+        }
+        let options = SubscribeOptions {
+            mode: SubscriptionMode::Capacity(capacity),
+            ..Default::default()
+        };
+        self.subscribe(channel, options).await
+    }
+
+    /// Returns a receiver for a specific channel as a member of consumer group `group`. Messages
+    /// of the channel are distributed round-robin among the members of the group instead of
+    /// being delivered to all of them (e.g. a pool of workers processing camera frames). Other
+    /// subscribers and groups of the channel still receive every message.
+    pub async fn register_to_channel_in_group(
+        &mut self,
+        channel: HubChannelName,
+        group: &str,
+    ) -> Result<HubReceiver, std::io::Error> {
+        let options = SubscribeOptions {
+            mode: SubscriptionMode::Grouped(group.to_string()),
+            ..Default::default()
+        };
+        self.subscribe(channel, options).await
+    }
+
+    /// Returns a receiver for a specific channel, subscribed as user `user_id`. Users keep the
+    /// same identifier across the channels they subscribe to, so that they are unsubscribed
+    /// from all of them at once with `drop_user`. Fails if the user is already subscribed to
+    /// the channel.
+    pub async fn register_user_to_channel(
+        &mut self,
+        user_id: Uuid,
+        channel: HubChannelName,
+    ) -> Result<HubReceiver, std::io::Error> {
+        if self
+            .subscribers
+            .lock()
+            .await
+            .is_subscribed(&channel, user_id)
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!(
+                    "User {} already subscribed to {}",
+                    user_id,
+                    channel.as_str()
+                ),
+            ));
+        }
+        let options = SubscribeOptions {
+            user_id,
+            ..Default::default()
+        };
+        self.subscribe(channel, options).await
+    }
+
+    // Subscribes to `channel` as configured by `options`
+    async fn subscribe(
+        &mut self,
+        channel: HubChannelName,
+        options: SubscribeOptions,
+    ) -> Result<HubReceiver, std::io::Error> {
+        let SubscribeOptions {
+            replay,
+            mode,
+            user_id,
+        } = options;
+
         // subscribe user to channel
         let mut channels = self.channels.lock().await;
-        let receiver = channels.subscribe_user(&channel);
-        self.subscribers.subscribe_user(&channel, &receiver);
-        if channels.get_number_subscribers(&channel) == 1 {
-            self.register_to_hub_channel(&channel).await?;
+        // the retained message is delivered once per consumer group, to its first member
+        let replay = match &mode {
+            SubscriptionMode::Grouped(group)
+                if replay.is_none() && channels.has_group_members(&channel, group) =>
+            {
+                Some(Replay::Last(0))
+            }
+            _ => replay,
+        };
+        let (receiver, mut history) = self.history.replay(&channel, replay, || match &mode {
+            SubscriptionMode::Shared => channels.subscribe_user_as(&channel, user_id),
+            SubscriptionMode::Filtered(filter) => {
+                channels.subscribe_user_filtered(&channel, filter.clone(), user_id)
+            }
+            SubscriptionMode::Grouped(group) => {
+                channels.subscribe_user_grouped(&channel, group, user_id)
+            }
+            SubscriptionMode::Capacity(capacity) => {
+                channels.subscribe_user_with_capacity(&channel, *capacity, user_id)
+            }
+        });
+        if let SubscriptionMode::Filtered(filter) = &mode {
+            history.retain(|message| filter.matches(message));
         }
-        Ok(receiver.resubscribe())
+        let receiver = receiver.with_history(history);
+        self.subscribers
+            .lock()
+            .await
+            .subscribe_user(&channel, user_id);
+        let first_subscriber =
+            channels.get_number_subscribers(&channel) == 1 && !is_meta_channel(&channel);
+        // nodes are called without the channels lock, so that dispatch and other
+        // subscriptions don't wait for node I/O
+        drop(channels);
+
+        if first_subscriber {
+            if let Err(e) = self.register_to_hub_channel(&channel).await {
+                // nodes that accepted the subscription are unsubscribed
+                let nodes = self.routing.route_subscription(&channel, &self.hub_nodes);
+                let release = release_subscription(
+                    &self.channels,
+                    &self.subscribers,
+                    &nodes,
+                    &channel,
+                    user_id,
+                );
+                if let Err(release_error) = release.await {
+                    warn!(
+                        "Failed to release subscription to {}: {:?}",
+                        channel.as_str(),
+                        release_error
+                    );
+                }
+                return Err(e);
+            }
+        }
+        Ok(receiver.with_guard(channel, self.unsubscriber.clone()))
     }
 
     // Unsubscribes from topic channel
@@ -147,119 +754,1173 @@ impl HubManager {
         channel: HubChannelName,
         user_id: Uuid,
     ) -> Result<(), std::io::Error> {
-        let mut channels = self.channels.lock().await;
-        channels.unsubscribe_user(&channel, user_id);
-        self.subscribers.unsubscribe_user(&channel, user_id);
-        if channels.is_empty(&channel) {
-            self.unregister_from_hub_channel(&channel).await?;
+        release_subscription(
+            &self.channels,
+            &self.subscribers,
+            &self.routing.route_subscription(&channel, &self.hub_nodes),
+            &channel,
+            user_id,
+        )
+        .await
+    }
+
+    /// Unsubscribes user from every channel it is subscribed to, so long-running applications
+    /// can tear down a consumer without tracking its channels. Hub nodes are requested to
+    /// unregister from channels left without subscribers.
+    pub async fn drop_user(&mut self, user_id: Uuid) -> Result<(), std::io::Error> {
+        let channels = self.subscribers.lock().await.channels(user_id);
+        for channel in channels {
+            self.unregister_from_channel(channel, user_id).await?;
         }
         Ok(())
     }
 
-    // Send HubMessage to topic channel
-    pub async fn send_to_channel(
-        &self,
-        message: HubMessage,
-        channel_idx: usize,
-    ) -> Result<(), std::io::Error> {
-        if let Some(node) = self.hub_nodes.get(channel_idx) {
-            node.send(message).await?;
+    /// Declares the expected data format of `channel`, replacing any previous schema. With
+    /// `validate_schemas` in `HubOptions`, received messages not following the schema are
+    /// blocked after global middlewares. Published messages are checked according to
+    /// `schema_violation`, and rejected or also delivered on the `HUB_DEAD_LETTER` meta-channel
+    pub fn declare_schema(&self, channel: HubChannelName, schema: ChannelSchema) {
+        self.schemas.declare(channel, schema);
+    }
+
+    /// Returns the schema declared for `channel`, if any
+    pub fn channel_schema(&self, channel: &HubChannelName) -> Option<ChannelSchema> {
+        self.schemas.get(channel)
+    }
+
+    /// Pauses dispatch of `channel`, keeping its subscriptions. Messages of reliable channels
+    /// are buffered until the channel is resumed, and messages of best effort channels are
+    /// dropped. Up to the QoS depth of the channel are buffered, dropping the oldest messages.
+    /// Meta-channels can't be paused.
+    pub fn pause_channel(&self, channel: HubChannelName) -> Result<(), std::io::Error> {
+        if is_meta_channel(&channel) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("Meta-channel {} can't be paused", channel.as_str()),
+            ));
         }
+        let depth = self
+            .options
+            .channel_qos(&channel)
+            .map_or(CHANNEL_CAPACITY, |qos| qos.depth());
+        self.paused.pause(channel, depth);
         Ok(())
     }
+
+    /// Resumes dispatch of `channel`, delivering the messages buffered while it was paused
+    /// before new messages. Resuming a channel that isn't paused has no effect. Like messages
+    /// received while the hub is stopped, buffered messages are dropped if the hub is stopped.
+    pub async fn resume_channel(&self, channel: &HubChannelName) {
+        let dispatcher = self
+            .dispatcher
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        let Some(dispatcher) = dispatcher else {
+            while let Some(messages) = self.paused.drain(channel) {
+                warn!(
+                    "Hub stopped. {} held messages of {:?} discarded",
+                    messages.len(),
+                    channel
+                );
+                for message in messages {
+                    self.metrics.record_drop(&message.channel);
+                }
+            }
+            return;
+        };
+        dispatcher.resume(channel).await;
+    }
+
+    /// Returns the paused channels
+    pub fn paused_channels(&self) -> Vec<HubChannelName> {
+        self.paused.paused()
+    }
+
+    /// Returns the last message delivered in `channel`, if kept in the hub history. Messages
+    /// are kept with `retain_last_message` or a history depth in `HubOptions`
+    pub fn last_message(&self, channel: &HubChannelName) -> Option<HubMessage> {
+        self.history.last(channel)
+    }
+
+    /// Returns the health of the hub nodes added with `add`, `add_remapped` or `add_robot`,
+    /// also published periodically on the `HUB_HEALTH` meta-channel. With a node restart delay
+    /// in `HubOptions`, nodes whose tasks exited are started again and resubscribed
+    pub fn health(&self) -> Vec<NodeHealth> {
+        self.health
+            .iter()
+            .map(|(node, health)| health.health(node))
+            .collect()
+    }
+
+    /// Returns a summary of the hub state. Message and byte rates, subscriber lag and drops of
+    /// every channel are counted as messages are dispatched, and published periodically on the
+    /// `HUB_STATS` meta-channel
+    pub async fn stats(&self) -> HubStats {
+        let channels = self.channels.lock().await;
+        HubStats {
+            hub_nodes: self.hub_nodes.len(),
+            channels: channels.number_of_channels(),
+            subscriptions: channels.number_of_subscriptions(),
+            channel_stats: self.metrics.snapshot(&self.routes.load()),
+        }
+    }
+
+    /// Publishes HubMessage to the hub nodes routing its channel, so application code never
+    /// addresses transport nodes directly
+    pub async fn publish(&self, message: HubMessage) -> Result<(), std::io::Error> {
+        self.publish_validation().check(&message)?;
+        self.routing.publish(&self.hub_nodes, message).await
+    }
+
+    fn publish_validation(&self) -> PublishValidation {
+        PublishValidation::new(
+            self.schemas.clone(),
+            self.options.schema_violation(),
+            self.hub_sender.clone(),
+        )
+    }
+
+    /// Publishes HubMessage to hub node `node` only, bypassing routing rules
+    pub async fn send_to(&self, node: &NodeId, message: HubMessage) -> Result<(), std::io::Error> {
+        let hub_node = self.node(node).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("Unknown hub node {}", node),
+            )
+        })?;
+        hub_node.send(message).await
+    }
+
+    /// Publishes request `data` in `channel` as an `RpcMessage`, and waits up to `timeout` for
+    /// the correlated response published by the responder in `<channel>/reply`. Returns the
+    /// response data
+    pub async fn request(
+        &mut self,
+        channel: HubChannelName,
+        data: HubData,
+        timeout: Duration,
+    ) -> Result<HubData, std::io::Error> {
+        let invalid_input = |e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e);
+        let request = RpcMessage::new(data);
+        let reply_channel = rpc::reply_channel(&channel).map_err(invalid_input)?;
+        let message = request.to_message(channel).map_err(invalid_input)?;
+        // subscribe before publishing, so the response can't be missed
+        let mut receiver = self
+            .register_to_channel_with_filter(reply_channel, rpc::correlated(request.correlation_id))
+            .await?;
+        self.publish(message).await?;
+        rpc::await_reply(&mut receiver, timeout).await
+    }
+}
+
+// Publishes the health of hub nodes on the HUB_HEALTH meta-channel every `interval`
+async fn publish_health(
+    health: Vec<(NodeId, Arc<HealthTracker>)>,
+    hub_sender: broadcast::Sender<HubMessage>,
+    interval: Duration,
+) {
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        interval.tick().await;
+        for (node, health) in &health {
+            // nobody may be listening
+            let _ = hub_sender.send(health.health(node).to_message());
+        }
+    }
+}
+
+// Publishes the statistics of the hub channels every interval
+async fn publish_stats(
+    metrics: Arc<ChannelMetrics>,
+    routes: Arc<ArcSwap<HubRoutes>>,
+    hub_sender: broadcast::Sender<HubMessage>,
+    interval: Duration,
+) {
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        interval.tick().await;
+        for stats in metrics.snapshot(&routes.load()) {
+            // nobody may be listening
+            let _ = hub_sender.send(stats.to_message());
+        }
+    }
+}
+
+// Removes the metrics and history of channels without subscribers nor traffic for `ttl`.
+// Channels are checked every `ttl`, so they are removed between `ttl` and twice `ttl` after
+// they became idle
+async fn collect_idle_channels(
+    metrics: Arc<ChannelMetrics>,
+    routes: Arc<ArcSwap<HubRoutes>>,
+    history: Arc<HubHistory>,
+    ttl: Duration,
+) {
+    let mut interval = tokio::time::interval(ttl);
+    loop {
+        interval.tick().await;
+        for channel in metrics.expire(&routes.load(), ttl) {
+            info!("Removing idle channel {:?}", channel);
+            history.remove(&channel);
+        }
+    }
+}
+
+// Unsubscribes user from channel. Hub nodes are requested to unregister from the channel
+// once it has no subscribers left. Nodes are called without the channels lock, so a node
+// that hangs doesn't hold dispatch and other subscriptions. A user subscribing meanwhile
+// may have resubscribed the nodes before they unsubscribed, so they are subscribed again.
+async fn release_subscription(
+    channels: &Mutex<HubChannels>,
+    subscribers: &Mutex<HubUsers>,
+    hub_nodes: &[&Arc<dyn NotificationHub>],
+    channel: &HubChannelName,
+    user_id: Uuid,
+) -> Result<(), std::io::Error> {
+    let last_subscriber = {
+        let mut channels = channels.lock().await;
+        subscribers.lock().await.unsubscribe_user(channel, user_id);
+        channels.unsubscribe_user(channel, user_id)
+    };
+    if !last_subscriber || is_meta_channel(channel) {
+        return Ok(());
+    }
+    for node in hub_nodes {
+        node.unsubscribe(channel.clone()).await?;
+    }
+    if channels.lock().await.get_number_subscribers(channel) > 0 {
+        for node in hub_nodes {
+            node.subscribe(channel.clone()).await?;
+        }
+    }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::adapters::websocket::WebSocketClient;
-    use crate::models::hub::HubData;
+    use crate::config::HubOptionsBuilder;
+    use crate::services::hub::events::CHANNEL_EVENTS;
+    use crate::services::hub::mock::MockNode;
+    use futures_util::StreamExt;
 
-    const URL: &str = "localhost:8080";
+    fn node_id(name: &str) -> NodeId {
+        NodeId::try_from(name).unwrap()
+    }
+
+    fn hub_node(name: &str, node: &Arc<MockNode>) -> HubNode {
+        (node_id(name), node.clone())
+    }
 
     #[tokio::test]
-    async fn test_wsocket() {
-        let _ = env_logger::builder().is_test(true).try_init();
+    async fn test_drop_receiver_unsubscribes() {
+        let node = Arc::new(MockNode::default());
+        let mut hub = HubManager::new();
+        hub.hub_nodes.push(hub_node("node", &node));
+        hub.start().await.unwrap();
 
-        let client1 = WebSocketClient::new(URL).await.unwrap();
-        let client2 = WebSocketClient::new(URL).await.unwrap();
-        let mut hub_ws = HubManager::new();
-        hub_ws.add(Box::new(client1));
-        hub_ws.add(Box::new(client2));
+        let channel = HubChannelName::try_from("topic1").unwrap();
+        let receiver1 = hub.register_to_channel(channel.clone()).await.unwrap();
+        let receiver2 = hub.register_to_channel(channel.clone()).await.unwrap();
+        assert_eq!(*node.subscribed.lock().await, vec![channel.clone()]);
 
-        hub_ws.start().await.unwrap();
+        drop(receiver1);
+        tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+        assert!(node.unsubscribed.lock().await.is_empty());
+        assert_eq!(
+            hub.channels.lock().await.get_number_subscribers(&channel),
+            1
+        );
 
-        tokio::time::sleep(tokio::time::Duration::from_secs(3)).await;
+        drop(receiver2);
+        tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+        assert_eq!(*node.unsubscribed.lock().await, vec![channel.clone()]);
+        assert!(hub.routes.load().is_empty());
+    }
 
-        // check channels are empty
-        info!("###################  List channels");
-        let channels = hub_ws.list_channels().await.unwrap();
-        assert!(channels.is_empty());
+    #[tokio::test]
+    async fn test_publish_routes_to_owner() {
+        let mut hub = HubManager::new();
+        let node1 = Arc::new(MockNode::default());
+        let node2 = Arc::new(MockNode::default());
+        hub.hub_nodes.push(hub_node("node1", &node1));
+        hub.hub_nodes.push(hub_node("node2", &node2));
+        hub.route(
+            HubChannelName::try_from("cmd_vel").unwrap(),
+            node_id("node2"),
+        );
 
-        // Send message to topic1. This will create a new channel
-        info!("###################  Send first message to empty subscription list. This will create new channel");
-        let ws_data = HubMessage::try_from_str("topic1", "test topic1").unwrap();
-        hub_ws.send_to_channel(ws_data, 0).await.unwrap();
+        hub.publish(HubMessage::try_from_str("cmd_vel", "1").unwrap())
+            .await
+            .unwrap();
+        hub.publish(HubMessage::try_from_str("status", "ok").unwrap())
+            .await
+            .unwrap();
 
-        let channels: Vec<_> = hub_ws.list_channels().await.unwrap().into_iter().collect();
-        assert_eq!(channels, vec![HubChannelName::try_from("topic1").unwrap()]);
+        assert_eq!(node1.sent.lock().await.len(), 1);
+        assert_eq!(node2.sent.lock().await.len(), 2);
+    }
 
-        // send message to topic1. Check that only topic1 is an active channel
-        info!("###################  Send message to empty subscription list to existing channel");
-        let ws_data = HubMessage::try_from_str("topic1", "test topic1").unwrap();
-        hub_ws.send_to_channel(ws_data, 0).await.unwrap();
+    #[tokio::test]
+    async fn test_relayed_message_is_not_echoed_to_origin() {
+        let mut hub = HubManager::new();
+        let serial = Arc::new(MockNode::default());
+        let websocket = Arc::new(MockNode::default());
+        hub.add("serial", Box::new(serial.clone())).unwrap();
+        hub.add("ws", Box::new(websocket.clone())).unwrap();
+        hub.start().await.unwrap();
 
-        let channels: Vec<_> = hub_ws.list_channels().await.unwrap().into_iter().collect();
-        assert_eq!(channels, vec![HubChannelName::try_from("topic1").unwrap()]);
+        let mut receiver = hub
+            .register_to_channel(HubChannelName::try_from("pose").unwrap())
+            .await
+            .unwrap();
+        serial.receive(HubMessage::try_from_str("pose", "1,2").unwrap());
+        let message = receiver.recv().await.unwrap();
+        assert_eq!(message.origin, Some(node_id("serial")));
+
+        hub.publish(message).await.unwrap();
+        assert!(serial.sent.lock().await.is_empty());
+        assert_eq!(websocket.sent.lock().await.len(), 1);
+    }
 
-        // subscribe to channel topic 1 and send message
-        tokio::time::sleep(tokio::time::Duration::from_secs(3)).await;
-        info!("##################  Subscribe to channel and send new message");
-        let receiver = hub_ws
-            .register_to_channel(HubChannelName::try_from("topic1").unwrap())
+    #[tokio::test]
+    async fn test_explicit_unregister_is_not_repeated_on_drop() {
+        let node = Arc::new(MockNode::default());
+        let mut hub = HubManager::new();
+        hub.hub_nodes.push(hub_node("node", &node));
+        hub.start().await.unwrap();
+
+        let channel = HubChannelName::try_from("topic1").unwrap();
+        let receiver = hub.register_to_channel(channel.clone()).await.unwrap();
+        hub.unregister_from_channel(channel.clone(), receiver.user_id())
             .await
             .unwrap();
-        let user_id = receiver.user_id();
+        drop(receiver);
+        tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+        assert_eq!(node.unsubscribed.lock().await.len(), 1);
+    }
 
-        tokio::time::sleep(tokio::time::Duration::from_secs(3)).await;
-        let ws_data = HubMessage::try_from_str("topic1", "new message test topic1").unwrap();
-        hub_ws.send_to_channel(ws_data, 0).await.unwrap();
+    #[tokio::test]
+    async fn test_drop_user_unsubscribes_all_channels() {
+        let node = Arc::new(MockNode::default());
+        let mut hub = HubManager::new();
+        hub.hub_nodes.push(hub_node("node", &node));
+        hub.start().await.unwrap();
 
-        let receiver = Arc::new(receiver);
-        let receiver_clone = Arc::clone(&receiver);
-        tokio::spawn(async move {
-            let mut receiver = receiver_clone.receiver();
-            if let Ok(msg) = receiver.recv().await {
-                assert_eq!(msg.channel, HubChannelName::try_from("topic1").unwrap());
-                assert_eq!(
-                    msg.data,
-                    "new message test topic1".parse::<HubData>().unwrap()
-                );
-            }
-        })
+        let user_id = Uuid::new_v4();
+        let imu = HubChannelName::try_from("imu").unwrap();
+        let gps = HubChannelName::try_from("gps").unwrap();
+        let receiver1 = hub
+            .register_user_to_channel(user_id, imu.clone())
+            .await
+            .unwrap();
+        let receiver2 = hub
+            .register_user_to_channel(user_id, gps.clone())
+            .await
+            .unwrap();
+        assert_eq!(receiver1.user_id(), user_id);
+        assert_eq!(receiver2.user_id(), user_id);
+        assert!(hub
+            .register_user_to_channel(user_id, imu.clone())
+            .await
+            .is_err());
+        let _other = hub.register_to_channel(gps.clone()).await.unwrap();
+        assert_eq!(hub.stats().await.subscriptions, 3);
+
+        hub.drop_user(user_id).await.unwrap();
+        assert_eq!(hub.stats().await.subscriptions, 1);
+        assert_eq!(*node.unsubscribed.lock().await, vec![imu]);
+        assert!(hub.subscribers.lock().await.channels(user_id).is_empty());
+
+        drop(receiver1);
+        drop(receiver2);
+        tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+        assert_eq!(node.unsubscribed.lock().await.len(), 1);
+        assert_eq!(hub.stats().await.subscriptions, 1);
+    }
+
+    #[tokio::test]
+    async fn test_channel_events() {
+        let node = Arc::new(MockNode::default());
+        let options = HubOptionsBuilder::new()
+            .channel_watch_interval(Duration::from_millis(10))
+            .build()
+            .unwrap();
+        let mut hub = HubManager::with_options(options);
+        hub.hub_nodes.push(hub_node("node", &node));
+        hub.start().await.unwrap();
+
+        let mut events = hub.watch_channels();
+        let mut meta_receiver = hub
+            .register_to_channel(HubChannelName::try_from(CHANNEL_EVENTS).unwrap())
+            .await
+            .unwrap();
+        assert!(node.subscribed.lock().await.is_empty());
+
+        let channel = HubChannelName::try_from("topic1").unwrap();
+        node.channels.lock().await.push(channel.clone());
+        let added = ChannelEvent::ChannelAdded(channel.clone());
+        assert_eq!(events.next().await.unwrap(), added);
+        let message = meta_receiver.recv().await.unwrap();
+        assert_eq!(ChannelEvent::try_from(&message).unwrap(), added);
+
+        node.channels.lock().await.clear();
+        assert_eq!(
+            events.next().await.unwrap(),
+            ChannelEvent::ChannelRemoved(channel)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_received_channels_are_discovered() {
+        let node = Arc::new(MockNode::default());
+        let options = HubOptionsBuilder::new()
+            .channel_watch_interval(Duration::from_secs(60))
+            .build()
+            .unwrap();
+        let mut hub = HubManager::with_options(options);
+        hub.hub_nodes.push(hub_node("node", &node));
+        hub.start().await.unwrap();
+
+        let mut events = hub.watch_channels();
+        node.receive(HubMessage::try_from_str("sensor/7", "1").unwrap());
+        let event = tokio::time::timeout(Duration::from_secs(1), events.next())
+            .await
+            .unwrap();
+        assert_eq!(
+            event,
+            Some(ChannelEvent::ChannelAdded(
+                HubChannelName::try_from("sensor/7").unwrap()
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_robots_are_namespaced() {
+        let mut hub = HubManager::new();
+        hub.add("serial", Box::new(MockNode::default())).unwrap();
+        let robot = hub
+            .add_robot("robot2", Box::new(MockNode::default()))
+            .unwrap();
+        assert_eq!(robot, node_id("robot2"));
+        assert!(hub
+            .add_robot("Robot2", Box::new(MockNode::default()))
+            .is_err());
+        assert!(hub
+            .add_robot("fleet/robot3", Box::new(MockNode::default()))
+            .is_err());
+        assert_eq!(hub.robots(), vec!["robot2".to_string()]);
+        assert_eq!(
+            hub.routing
+                .route(
+                    &HubChannelName::try_from("robot2/cmd_vel").unwrap(),
+                    &hub.hub_nodes
+                )
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_nodes_are_addressed_by_name() {
+        let mut hub = HubManager::new();
+        let serial = Arc::new(MockNode::default());
+        let websocket = Arc::new(MockNode::default());
+        let serial_id = hub.add("serial", Box::new(serial.clone())).unwrap();
+        let ws_id = hub.add("ws", Box::new(websocket.clone())).unwrap();
+        assert!(hub.add("serial", Box::new(MockNode::default())).is_err());
+        assert!(hub.add("serial,1", Box::new(MockNode::default())).is_err());
+        assert_eq!(hub.nodes(), vec![serial_id.clone(), ws_id.clone()]);
+
+        hub.route(HubChannelName::try_from("cmd_vel").unwrap(), ws_id.clone());
+        hub.send_to(
+            &serial_id,
+            HubMessage::try_from_str("cmd_vel", "1").unwrap(),
+        )
         .await
         .unwrap();
+        assert_eq!(serial.sent.lock().await.len(), 1);
+        assert!(websocket.sent.lock().await.is_empty());
 
-        // unsubscribe to channel topic 1 and send message
-        tokio::time::sleep(tokio::time::Duration::from_secs(3)).await;
-        info!("##################  UnSubscribe from channel and send new message");
-        hub_ws
-            .unregister_from_channel(HubChannelName::try_from("topic1").unwrap(), user_id)
+        hub.remove(&ws_id).unwrap();
+        assert!(hub.remove(&ws_id).is_err());
+        assert_eq!(hub.nodes(), vec![serial_id.clone()]);
+        assert_eq!(hub.health().len(), 1);
+        assert!(hub
+            .send_to(&ws_id, HubMessage::try_from_str("cmd_vel", "1").unwrap())
+            .await
+            .is_err());
+        // channel route to the removed node is dropped, so the message goes to every node
+        hub.publish(HubMessage::try_from_str("cmd_vel", "2").unwrap())
             .await
             .unwrap();
+        assert_eq!(serial.sent.lock().await.len(), 2);
+    }
 
-        tokio::time::sleep(tokio::time::Duration::from_secs(3)).await;
-        let ws_data = HubMessage::try_from_str("topic1", "new message test topic1").unwrap();
-        hub_ws.send_to_channel(ws_data, 0).await.unwrap();
+    #[tokio::test]
+    async fn test_middlewares_apply_before_delivery() {
+        use crate::services::hub::Validate;
 
-        tokio::spawn(async move {
-            let receiver = Arc::clone(&receiver);
-            let mut receiver = receiver.receiver();
-            if receiver.recv().await.is_ok() {
-                panic!("Data shouldn't be available after unregister")
+        let mut hub = HubManager::new();
+        hub.add_channel_middleware(
+            HubChannelName::try_from("topic1").unwrap(),
+            Box::new(Validate::new(|message| match message.data.as_str() {
+                "blocked" => Err("blocked data".to_string()),
+                _ => Ok(()),
+            })),
+        );
+        hub.start().await.unwrap();
+
+        let channel = HubChannelName::try_from("topic1").unwrap();
+        let mut receiver = hub.register_to_channel(channel).await.unwrap();
+        for data in ["blocked", "1"] {
+            hub.hub_sender
+                .send(HubMessage::try_from_str("topic1", data).unwrap())
+                .unwrap();
+        }
+        assert_eq!(receiver.recv().await.unwrap().data.as_str(), "1");
+    }
+
+    #[tokio::test]
+    async fn test_register_with_history() {
+        let options = HubOptionsBuilder::new().history_depth(2).build().unwrap();
+        let mut hub = HubManager::with_options(options);
+        hub.start().await.unwrap();
+
+        for data in ["1", "2", "3"] {
+            hub.hub_sender
+                .send(HubMessage::try_from_str("topic1", data).unwrap())
+                .unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let channel = HubChannelName::try_from("topic1").unwrap();
+        let mut receiver = hub
+            .register_to_channel_with_history(channel.clone(), 5)
+            .await
+            .unwrap();
+        hub.hub_sender
+            .send(HubMessage::try_from_str("topic1", "4").unwrap())
+            .unwrap();
+        for data in ["2", "3", "4"] {
+            assert_eq!(receiver.recv().await.unwrap().data.as_str(), data);
+        }
+
+        let mut receiver = hub.register_to_channel(channel).await.unwrap();
+        hub.hub_sender
+            .send(HubMessage::try_from_str("topic1", "5").unwrap())
+            .unwrap();
+        assert_eq!(receiver.recv().await.unwrap().data.as_str(), "5");
+    }
+
+    #[tokio::test]
+    async fn test_channel_qos() {
+        use crate::config::{ChannelQosBuilder, Reliability};
+
+        let options = HubOptionsBuilder::new()
+            .qos(
+                "cmd",
+                ChannelQosBuilder::new()
+                    .reliability(Reliability::Reliable)
+                    .build()
+                    .unwrap(),
+            )
+            .qos(
+                "imu",
+                ChannelQosBuilder::new().max_rate(1.0).build().unwrap(),
+            )
+            .build()
+            .unwrap();
+        let mut hub = HubManager::with_options(options);
+        hub.start().await.unwrap();
+
+        let mut cmd = hub
+            .register_to_channel(HubChannelName::try_from("cmd").unwrap())
+            .await
+            .unwrap();
+        let mut imu = hub
+            .register_to_channel(HubChannelName::try_from("imu").unwrap())
+            .await
+            .unwrap();
+        for data in ["1", "2"] {
+            for channel in ["cmd", "imu"] {
+                hub.hub_sender
+                    .send(HubMessage::try_from_str(channel, data).unwrap())
+                    .unwrap();
+            }
+        }
+        hub.hub_sender
+            .send(HubMessage::try_from_str("cmd", "3").unwrap())
+            .unwrap();
+        for data in ["1", "2", "3"] {
+            assert_eq!(cmd.recv().await.unwrap().data.as_str(), data);
+        }
+        // second imu message is above the max rate
+        assert_eq!(imu.recv().await.unwrap().data.as_str(), "1");
+        assert!(tokio::time::timeout(Duration::from_millis(50), imu.recv())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_register_with_filter() {
+        let mut hub = HubManager::new();
+        hub.start().await.unwrap();
+
+        let channel = HubChannelName::try_from("status").unwrap();
+        let mut receiver = hub
+            .register_to_channel_with_filter(
+                channel.clone(),
+                MessageFilter::Contains("fault".to_string()),
+            )
+            .await
+            .unwrap();
+        assert_eq!(hub.stats().await.subscriptions, 1);
+        for data in ["ok", "motor fault", "ok", "sensor fault"] {
+            hub.hub_sender
+                .send(HubMessage::try_from_str("status", data).unwrap())
+                .unwrap();
+        }
+        assert_eq!(receiver.recv().await.unwrap().data.as_str(), "motor fault");
+        assert_eq!(receiver.recv().await.unwrap().data.as_str(), "sensor fault");
+
+        drop(receiver);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(hub.routes.load().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_register_with_capacity() {
+        let mut hub = HubManager::new();
+        hub.start().await.unwrap();
+
+        let channel = HubChannelName::try_from("cmd").unwrap();
+        assert!(hub
+            .register_to_channel_with_capacity(channel.clone(), 0)
+            .await
+            .is_err());
+        let mut receiver = hub
+            .register_to_channel_with_capacity(channel.clone(), 1)
+            .await
+            .unwrap();
+        assert_eq!(hub.stats().await.subscriptions, 1);
+        for data in ["1", "2"] {
+            hub.hub_sender
+                .send(HubMessage::try_from_str("cmd", data).unwrap())
+                .unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(receiver.dropped(), 1);
+        assert_eq!(receiver.recv().await.unwrap().data.as_str(), "1");
+
+        drop(receiver);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(hub.routes.load().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_register_with_capacity_rejects_expanding_queues() {
+        use crate::config::ChannelQosBuilder;
+
+        let options = HubOptionsBuilder::new()
+            .qos(
+                "cmd",
+                ChannelQosBuilder::new()
+                    .backpressure(Backpressure::Expand)
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap();
+        let mut hub = HubManager::with_options(options);
+        let channel = HubChannelName::try_from("cmd").unwrap();
+        let result = hub.register_to_channel_with_capacity(channel, 10).await;
+        assert_eq!(
+            result.err().map(|e| e.kind()),
+            Some(std::io::ErrorKind::InvalidInput)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_register_in_group() {
+        let node = Arc::new(MockNode::default());
+        let mut hub = HubManager::new();
+        hub.hub_nodes.push(hub_node("node", &node));
+        hub.start().await.unwrap();
+
+        let channel = HubChannelName::try_from("logs").unwrap();
+        let mut writer1 = hub
+            .register_to_channel_in_group(channel.clone(), "writers")
+            .await
+            .unwrap();
+        let mut writer2 = hub
+            .register_to_channel_in_group(channel.clone(), "writers")
+            .await
+            .unwrap();
+        assert_eq!(*node.subscribed.lock().await, vec![channel.clone()]);
+        for data in ["1", "2", "3", "4"] {
+            hub.hub_sender
+                .send(HubMessage::try_from_str("logs", data).unwrap())
+                .unwrap();
+        }
+        assert_eq!(writer1.recv().await.unwrap().data.as_str(), "1");
+        assert_eq!(writer2.recv().await.unwrap().data.as_str(), "2");
+        assert_eq!(writer1.recv().await.unwrap().data.as_str(), "3");
+        assert_eq!(writer2.recv().await.unwrap().data.as_str(), "4");
+
+        drop(writer1);
+        drop(writer2);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(*node.unsubscribed.lock().await, vec![channel]);
+    }
+
+    #[tokio::test]
+    async fn test_node_subscription_does_not_hold_channels_lock() {
+        let node = Arc::new(MockNode::default());
+        let mut hub = HubManager::new();
+        hub.hub_nodes.push(hub_node("node", &node));
+        hub.start().await.unwrap();
+
+        let channel = HubChannelName::try_from("topic1").unwrap();
+        let gate = node.gate.lock().await;
+        let channels = Arc::clone(&hub.channels);
+        let (receiver, _) = tokio::join!(hub.register_to_channel(channel.clone()), async move {
+            // the node is subscribing while channels are locked by others
+            tokio::time::timeout(Duration::from_secs(1), channels.lock())
+                .await
+                .expect("channels locked during node subscription");
+            drop(gate);
+        });
+        assert!(receiver.is_ok());
+        assert_eq!(*node.subscribed.lock().await, vec![channel]);
+    }
+
+    #[tokio::test]
+    async fn test_node_unsubscription_does_not_hold_channels_lock() {
+        let node = Arc::new(MockNode::default());
+        let mut hub = HubManager::new();
+        hub.hub_nodes.push(hub_node("node", &node));
+        hub.start().await.unwrap();
+
+        let channel = HubChannelName::try_from("topic1").unwrap();
+        let receiver = hub.register_to_channel(channel.clone()).await.unwrap();
+        let gate = node.gate.lock().await;
+        // node unsubscription waits for the gate
+        drop(receiver);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let channels = Arc::clone(&hub.channels);
+        let (receiver, _) = tokio::join!(hub.register_to_channel(channel.clone()), async move {
+            tokio::time::timeout(Duration::from_secs(1), channels.lock())
+                .await
+                .expect("channels locked during node unsubscription");
+            drop(gate);
+        });
+        assert!(receiver.is_ok());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        // node unsubscribed after the new subscription, so it is subscribed again
+        assert_eq!(*node.unsubscribed.lock().await, vec![channel.clone()]);
+        assert_eq!(node.subscribed.lock().await.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_failed_node_subscription_is_released() {
+        let node = Arc::new(MockNode::default());
+        node.fail_subscribe
+            .store(true, std::sync::atomic::Ordering::Relaxed);
+        let mut hub = HubManager::new();
+        hub.hub_nodes.push(hub_node("node", &node));
+        hub.start().await.unwrap();
+
+        let channel = HubChannelName::try_from("topic1").unwrap();
+        assert!(hub.register_to_channel(channel.clone()).await.is_err());
+        assert_eq!(hub.stats().await.subscriptions, 0);
+        assert_eq!(*node.unsubscribed.lock().await, vec![channel]);
+    }
+
+    #[tokio::test]
+    async fn test_retained_message() {
+        let options = HubOptionsBuilder::new()
+            .retain_last_message(true)
+            .build()
+            .unwrap();
+        let mut hub = HubManager::with_options(options);
+        hub.start().await.unwrap();
+
+        let channel = HubChannelName::try_from("pose").unwrap();
+        assert!(hub.last_message(&channel).is_none());
+        for data in ["1", "2"] {
+            hub.hub_sender
+                .send(HubMessage::try_from_str("pose", data).unwrap())
+                .unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(hub.last_message(&channel).unwrap().data.as_str(), "2");
+
+        let mut receiver = hub.register_to_channel(channel.clone()).await.unwrap();
+        assert_eq!(receiver.recv().await.unwrap().data.as_str(), "2");
+        // an explicit empty replay gets no retained message
+        let mut live = hub
+            .register_to_channel_with_history(channel.clone(), 0)
+            .await
+            .unwrap();
+        // the retained message is delivered once to the group
+        let mut member1 = hub
+            .register_to_channel_in_group(channel.clone(), "planners")
+            .await
+            .unwrap();
+        let mut member2 = hub
+            .register_to_channel_in_group(channel.clone(), "planners")
+            .await
+            .unwrap();
+        assert_eq!(member1.recv().await.unwrap().data.as_str(), "2");
+
+        hub.hub_sender
+            .send(HubMessage::try_from_str("pose", "3").unwrap())
+            .unwrap();
+        assert_eq!(receiver.recv().await.unwrap().data.as_str(), "3");
+        assert_eq!(live.recv().await.unwrap().data.as_str(), "3");
+        let member = tokio::select! {
+            message = member1.recv() => message,
+            message = member2.recv() => message,
+        };
+        assert_eq!(member.unwrap().data.as_str(), "3");
+        assert!(tokio::time::timeout(Duration::from_millis(50), async {
+            tokio::select! {
+                message = member1.recv() => message,
+                message = member2.recv() => message,
             }
         })
         .await
-        .unwrap();
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_request_response() {
+        use crate::adapters::memory::MemoryClient;
+
+        let (client, mut peer) = MemoryClient::new();
+        let mut hub = HubManager::new();
+        hub.add("memory", Box::new(client)).unwrap();
+        hub.start().await.unwrap();
+
+        // responder answering battery queries, after an unrelated response
+        tokio::spawn(async move {
+            while let Some(message) = peer.recv().await {
+                let request = RpcMessage::try_from(&message).unwrap();
+                let other = RpcMessage::new("0.0".parse().unwrap());
+                for response in [
+                    other.reply(&message.channel, "0.0".parse().unwrap()),
+                    request.reply(&message.channel, "12.4".parse().unwrap()),
+                ] {
+                    peer.inject(response.unwrap()).await.unwrap();
+                }
+            }
+        });
+
+        let channel = HubChannelName::try_from("battery").unwrap();
+        let response = hub
+            .request(
+                channel.clone(),
+                "query".parse().unwrap(),
+                Duration::from_secs(1),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.as_str(), "12.4");
+
+        let mut silent = HubManager::new();
+        silent.start().await.unwrap();
+        let error = silent
+            .request(channel, "query".parse().unwrap(), Duration::from_millis(50))
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
+    }
+
+    #[tokio::test]
+    async fn test_register_with_replay_window() {
+        let options = HubOptionsBuilder::new()
+            .channel_history_depth("imu", 10)
+            .build()
+            .unwrap();
+        let mut hub = HubManager::with_options(options);
+        hub.start().await.unwrap();
+
+        let mut stale = HubMessage::try_from_str("imu", "0").unwrap();
+        stale.timestamp -= 10.0;
+        hub.hub_sender.send(stale).unwrap();
+        for data in ["1", "2"] {
+            hub.hub_sender
+                .send(HubMessage::try_from_str("imu", data).unwrap())
+                .unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let mut receiver = hub
+            .register_to_channel_with_replay(
+                HubChannelName::try_from("imu").unwrap(),
+                Replay::Since(Duration::from_secs(1)),
+            )
+            .await
+            .unwrap();
+        for data in ["1", "2"] {
+            assert_eq!(receiver.recv().await.unwrap().data.as_str(), data);
+        }
+        assert!(
+            tokio::time::timeout(Duration::from_millis(50), receiver.recv())
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_node_health_is_published() {
+        use crate::services::hub::health::HUB_HEALTH;
+
+        let options = HubOptionsBuilder::new()
+            .health_interval(Duration::from_millis(10))
+            .build()
+            .unwrap();
+        let mut hub = HubManager::with_options(options);
+        let node = Arc::new(MockNode::default());
+        let id = hub.add("node", Box::new(node.clone())).unwrap();
+        hub.start().await.unwrap();
+
+        let mut receiver = hub
+            .register_to_channel(HubChannelName::try_from(HUB_HEALTH).unwrap())
+            .await
+            .unwrap();
+        assert!(node.subscribed.lock().await.is_empty());
+        let health = NodeHealth::try_from(&receiver.recv().await.unwrap()).unwrap();
+        assert_eq!(health.node, id);
+        assert!(health.alive);
+
+        hub.publish(HubMessage::try_from_str("cmd", "1").unwrap())
+            .await
+            .unwrap();
+        assert!(hub.health()[0].since_last_write.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_channel_stats_are_published() {
+        use crate::services::hub::stats::{ChannelStats, HUB_STATS};
+
+        let options = HubOptionsBuilder::new()
+            .stats_interval(Duration::from_millis(10))
+            .build()
+            .unwrap();
+        let mut hub = HubManager::with_options(options);
+        let node = Arc::new(MockNode::default());
+        hub.add("node", Box::new(node.clone())).unwrap();
+        hub.start().await.unwrap();
+
+        let mut imu = hub
+            .register_to_channel(HubChannelName::try_from("imu").unwrap())
+            .await
+            .unwrap();
+        node.receive(HubMessage::try_from_str("imu", "1.0,2.0").unwrap());
+        imu.recv().await.unwrap();
+
+        let stats = hub.stats().await.channel_stats;
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].messages, 1);
+        assert_eq!(stats[0].bytes, 7);
+
+        let mut receiver = hub
+            .register_to_channel(HubChannelName::try_from(HUB_STATS).unwrap())
+            .await
+            .unwrap();
+        let stats = ChannelStats::try_from(&receiver.recv().await.unwrap()).unwrap();
+        assert_eq!(stats.channel.as_str(), "imu");
+        assert_eq!(stats.messages, 1);
+        assert_eq!(hub.stats().await.channel_stats.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_restart_keeps_subscriptions() {
+        let node = Arc::new(MockNode::default());
+        let mut hub = HubManager::new();
+        hub.add("node", Box::new(node.clone())).unwrap();
+        hub.start().await.unwrap();
+        assert!(hub.start().await.is_err());
+
+        let channel = HubChannelName::try_from("imu").unwrap();
+        let mut receiver = hub.register_to_channel(channel.clone()).await.unwrap();
+        hub.stop().await;
+        assert!(!hub.is_running());
+        assert!(!hub.health()[0].alive);
+
+        hub.start().await.unwrap();
+        assert!(hub.is_running());
+        assert_eq!(
+            *node.subscribed.lock().await,
+            vec![channel.clone(), channel.clone()]
+        );
+        node.receive(HubMessage::try_from_str("imu", "1").unwrap());
+        assert_eq!(receiver.recv().await.unwrap().data.as_str(), "1");
+    }
+
+    #[tokio::test]
+    async fn test_paused_channels() {
+        use crate::config::{ChannelQosBuilder, Reliability};
+
+        let options = HubOptionsBuilder::new()
+            .qos(
+                "cmd",
+                ChannelQosBuilder::new()
+                    .reliability(Reliability::Reliable)
+                    .build()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap();
+        let mut hub = HubManager::with_options(options);
+        hub.start().await.unwrap();
+
+        let cmd = HubChannelName::try_from("cmd").unwrap();
+        let camera = HubChannelName::try_from("camera").unwrap();
+        let mut cmd_receiver = hub.register_to_channel(cmd.clone()).await.unwrap();
+        let mut camera_receiver = hub.register_to_channel(camera.clone()).await.unwrap();
+        hub.pause_channel(cmd.clone()).unwrap();
+        hub.pause_channel(camera.clone()).unwrap();
+        assert!(hub
+            .pause_channel(HubChannelName::try_from(CHANNEL_EVENTS).unwrap())
+            .is_err());
+        for data in ["1", "2"] {
+            for channel in ["cmd", "camera"] {
+                hub.hub_sender
+                    .send(HubMessage::try_from_str(channel, data).unwrap())
+                    .unwrap();
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(
+            tokio::time::timeout(Duration::from_millis(10), cmd_receiver.recv())
+                .await
+                .is_err()
+        );
+
+        hub.resume_channel(&cmd).await;
+        hub.resume_channel(&camera).await;
+        assert!(hub.paused_channels().is_empty());
+        hub.hub_sender
+            .send(HubMessage::try_from_str("camera", "3").unwrap())
+            .unwrap();
+        // buffered commands are delivered, paused camera frames are dropped
+        for data in ["1", "2"] {
+            assert_eq!(cmd_receiver.recv().await.unwrap().data.as_str(), data);
+        }
+        assert_eq!(camera_receiver.recv().await.unwrap().data.as_str(), "3");
+        let stats = hub.stats().await.channel_stats;
+        let camera_stats = stats.iter().find(|stats| stats.channel == camera).unwrap();
+        assert_eq!(camera_stats.dropped, 2);
+    }
+
+    #[tokio::test]
+    async fn test_idle_channels_are_collected() {
+        let options = HubOptionsBuilder::new()
+            .channel_ttl(Duration::from_millis(20))
+            .retain_last_message(true)
+            .build()
+            .unwrap();
+        let mut hub = HubManager::with_options(options);
+        let node = Arc::new(MockNode::default());
+        hub.add("node", Box::new(node.clone())).unwrap();
+        hub.start().await.unwrap();
+
+        let imu = HubChannelName::try_from("imu").unwrap();
+        let sensor = HubChannelName::try_from("sensor/7").unwrap();
+        let mut receiver = hub.register_to_channel(imu.clone()).await.unwrap();
+        node.receive(HubMessage::try_from_str("sensor/7", "1").unwrap());
+        node.receive(HubMessage::try_from_str("imu", "1").unwrap());
+        receiver.recv().await.unwrap();
+        assert_eq!(hub.stats().await.channel_stats.len(), 2);
+        assert!(hub.history.last(&sensor).is_some());
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let stats = hub.stats().await.channel_stats;
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].channel, imu);
+        assert!(hub.history.last(&sensor).is_none());
+        assert!(hub.history.last(&imu).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_schema_validation() {
+        let options = HubOptionsBuilder::new()
+            .validate_schemas(true)
+            .build()
+            .unwrap();
+        let mut hub = HubManager::with_options(options);
+        hub.start().await.unwrap();
+
+        let channel = HubChannelName::try_from("imu").unwrap();
+        let schema = ChannelSchema::floats(&["x", "y", "z"]);
+        hub.declare_schema(channel.clone(), schema.clone());
+        assert_eq!(hub.channel_schema(&channel), Some(schema));
+
+        let mut receiver = hub.register_to_channel(channel).await.unwrap();
+        for data in ["1.0,2.0", "1.0,2.0,3.0"] {
+            hub.hub_sender
+                .send(HubMessage::try_from_str("imu", data).unwrap())
+                .unwrap();
+        }
+        assert_eq!(receiver.recv().await.unwrap().data.as_str(), "1.0,2.0,3.0");
+    }
+
+    #[tokio::test]
+    async fn test_publish_schema_violation() {
+        use crate::config::SchemaViolation;
+        use crate::services::hub::schema::DEAD_LETTER_CHANNEL_HEADER;
+        use crate::services::hub::HUB_DEAD_LETTER;
+
+        let node = Arc::new(MockNode::default());
+        let options = HubOptionsBuilder::new()
+            .schema_violation(SchemaViolation::DeadLetter)
+            .build()
+            .unwrap();
+        let mut hub = HubManager::with_options(options);
+        hub.hub_nodes.push(hub_node("node", &node));
+        hub.start().await.unwrap();
+
+        let channel = HubChannelName::try_from("imu").unwrap();
+        hub.declare_schema(channel, ChannelSchema::floats(&["x", "y"]));
+        let mut dead_letters = hub
+            .register_to_channel(HubChannelName::try_from(HUB_DEAD_LETTER).unwrap())
+            .await
+            .unwrap();
+
+        let error = hub
+            .publish(HubMessage::try_from_str("imu", "1.0").unwrap())
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        hub.publish(HubMessage::try_from_str("imu", "1.0,2.0").unwrap())
+            .await
+            .unwrap();
+
+        let dead_letter = dead_letters.recv().await.unwrap();
+        assert_eq!(dead_letter.data.as_str(), "1.0");
+        assert_eq!(dead_letter.header(DEAD_LETTER_CHANNEL_HEADER), Some("imu"));
+        let sent = node.sent.lock().await;
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].data.as_str(), "1.0,2.0");
+    }
+
+    #[tokio::test]
+    async fn test_register_typed() {
+        let mut hub = HubManager::new();
+        hub.start().await.unwrap();
+
+        let channel = HubChannelName::try_from("odometry").unwrap();
+        let mut receiver = hub.register_typed::<Vec<f64>>(channel).await.unwrap();
+        for data in ["1.0,2.0", "[1.0,2.0]"] {
+            hub.hub_sender
+                .send(HubMessage::try_from_str("odometry", data).unwrap())
+                .unwrap();
+        }
+        assert_eq!(receiver.recv().await.unwrap(), vec![1.0, 2.0]);
+        assert_eq!(receiver.decode_errors(), 1);
     }
 }
@@ -0,0 +1,448 @@
+use arc_swap::ArcSwap;
+use log::{error, info};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex};
+
+use super::channel::{Deferred, HubRoutes};
+use super::events::is_meta_channel;
+use super::history::HubHistory;
+use super::middleware::MiddlewarePipeline;
+use super::pause::{Hold, PausedChannels};
+use super::stats::ChannelMetrics;
+use super::supervisor::Supervisor;
+use crate::config::{HubOptions, Reliability};
+use crate::models::hub::{HubChannelName, HubMessage};
+
+/// `Dispatcher` delivers messages received by the hub to the subscribers of their channel.
+///
+/// With a single worker, messages are delivered inline. Otherwise, channels are partitioned
+/// across worker tasks by hashing the channel name, which keeps messages of a channel in order
+/// while isolating unrelated channels from each other.
+///
+/// Messages go through the middleware pipeline before being assigned to a worker, so that
+/// middlewares renaming channels are taken into account. Meta-channel messages published by
+/// the hub itself skip middlewares.
+///
+/// Messages are dropped when their worker queue is full, except for messages of `reliable`
+/// channels, which wait for room in the queue. Delivery waits as well for room in the
+/// subscriber queues of channels with `Backpressure::Block`.
+///
+/// Messages of paused channels are held by `PausedChannels` after the middlewares, and
+/// routed to their worker like new messages when their channel is resumed.
+///
+/// Messages of priority channels are delivered by a separate task from an unbounded priority
+/// queue, so they are never dropped, and never wait behind telemetry queued in the workers.
+/// Worker and priority tasks are supervised with the other hub tasks.
+///
+/// Delivered messages are recorded in the hub history. Messages passing the middlewares, and
+/// messages dropped for full worker queues, are counted in the channel metrics.
+#[derive(Debug)]
+pub(crate) struct Dispatcher {
+    routes: Arc<ArcSwap<HubRoutes>>,
+    history: Arc<HubHistory>,
+    middlewares: MiddlewarePipeline,
+    metrics: Arc<ChannelMetrics>,
+    paused: Arc<PausedChannels>,
+    workers: Vec<mpsc::Sender<HubMessage>>,
+    reliable: HashSet<HubChannelName>,
+    priority: Option<mpsc::UnboundedSender<HubMessage>>,
+    priority_channels: HashSet<HubChannelName>,
+}
+
+impl Dispatcher {
+    // Worker and priority tasks are spawned by `supervisor`, so they are restarted after a
+    // failure and stopped with the hub
+    pub(crate) fn spawn(
+        routes: Arc<ArcSwap<HubRoutes>>,
+        history: Arc<HubHistory>,
+        middlewares: MiddlewarePipeline,
+        metrics: Arc<ChannelMetrics>,
+        paused: Arc<PausedChannels>,
+        options: &HubOptions,
+        supervisor: &Supervisor,
+    ) -> Self {
+        let mut workers = Vec::new();
+        if options.dispatch_workers() > 1 {
+            for _ in 0..options.dispatch_workers() {
+                let (sender, receiver) = mpsc::channel(options.dispatch_queue_capacity());
+                let receiver = Arc::new(Mutex::new(receiver));
+                let routes = Arc::clone(&routes);
+                let history = Arc::clone(&history);
+                supervisor.supervise("dispatch worker", move || {
+                    let receiver = receiver.clone();
+                    let routes = routes.clone();
+                    let history = history.clone();
+                    async move {
+                        let mut receiver = receiver.lock().await;
+                        while let Some(message) = receiver.recv().await {
+                            deliver(&routes, &history, message).await;
+                        }
+                    }
+                });
+                workers.push(sender);
+            }
+        }
+        let priority = if options.priority_channels().is_empty() {
+            None
+        } else {
+            let (sender, receiver) = mpsc::unbounded_channel();
+            let receiver = Arc::new(Mutex::new(receiver));
+            let routes = Arc::clone(&routes);
+            let history = Arc::clone(&history);
+            supervisor.supervise("priority dispatch", move || {
+                let receiver = receiver.clone();
+                let routes = routes.clone();
+                let history = history.clone();
+                async move {
+                    let mut receiver = receiver.lock().await;
+                    while let Some(message) = receiver.recv().await {
+                        deliver(&routes, &history, message).await;
+                    }
+                }
+            });
+            Some(sender)
+        };
+        let reliable = options
+            .qos()
+            .iter()
+            .filter(|(_, qos)| qos.reliability() == Reliability::Reliable)
+            .map(|(channel, _)| channel.clone())
+            .collect();
+        Self {
+            routes,
+            history,
+            middlewares,
+            metrics,
+            paused,
+            workers,
+            reliable,
+            priority,
+            priority_channels: options.priority_channels().clone(),
+        }
+    }
+
+    pub(crate) async fn dispatch(&self, message: HubMessage) {
+        let message = if is_meta_channel(&message.channel) {
+            message
+        } else {
+            match self.middlewares.process(message) {
+                Some(message) => message,
+                None => return,
+            }
+        };
+        self.metrics.record(&message);
+        let reliable = self.reliable.contains(&message.channel);
+        match self.paused.hold(message, reliable) {
+            Hold::Dispatch(message) => self.route(message, reliable).await,
+            Hold::Buffered => {}
+            Hold::Dropped(message) => self.metrics.record_drop(&message.channel),
+        }
+    }
+
+    /// Resumes `channel`, routing the messages held while it was paused in order. Held
+    /// messages already went through the middlewares and were counted when received
+    pub(crate) async fn resume(&self, channel: &HubChannelName) {
+        let reliable = self.reliable.contains(channel);
+        while let Some(messages) = self.paused.drain(channel) {
+            for message in messages {
+                self.route(message, reliable).await;
+            }
+        }
+    }
+
+    // Sends message to the priority task, to the worker of its channel, or delivers it inline
+    async fn route(&self, message: HubMessage, reliable: bool) {
+        if let Some(priority) = &self.priority {
+            if self.priority_channels.contains(&message.channel) {
+                if let Err(e) = priority.send(message) {
+                    error!("Priority dispatch error : {:?}", e);
+                }
+                return;
+            }
+        }
+        if self.workers.is_empty() {
+            return deliver(&self.routes, &self.history, message).await;
+        }
+        let worker = &self.workers[self.worker_idx(&message.channel)];
+        let channel = message.channel.clone();
+        let result = if reliable {
+            worker.send(message).await.map_err(|e| e.to_string())
+        } else {
+            worker.try_send(message).map_err(|e| e.to_string())
+        };
+        if let Err(e) = result {
+            self.metrics.record_drop(&channel);
+            error!("Dispatch worker error : {:?}", e);
+        }
+    }
+
+    fn worker_idx(&self, channel: &HubChannelName) -> usize {
+        let mut hasher = DefaultHasher::new();
+        channel.hash(&mut hasher);
+        (hasher.finish() % self.workers.len() as u64) as usize
+    }
+}
+
+// retrieve channel from data and broadcast to all registered clients. Messages for full
+// queues of blocking channels are sent once there is room, after the history lock is released.
+async fn deliver(routes: &ArcSwap<HubRoutes>, history: &HubHistory, data: HubMessage) {
+    let mut deferred = Deferred::new();
+    history.record(data, |data| {
+        if let Some(sender) = routes.load().get(&data.channel) {
+            info!("Received data: {:?}", data);
+            match sender.send(data) {
+                Ok(pending) => deferred = pending,
+                Err(e) => error!("Error : {:?}", e),
+            }
+        }
+    });
+    for (queue, message) in deferred {
+        // subscriber may be gone already
+        let _ = queue.send(message).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::HubOptionsBuilder;
+    use crate::config::SupervisionPolicy;
+    use crate::services::hub::channel::HubChannels;
+    use tokio::sync::broadcast;
+
+    fn supervisor() -> Supervisor {
+        Supervisor::new(broadcast::channel(10).0, SupervisionPolicy::Report)
+    }
+
+    #[tokio::test]
+    async fn test_inline_dispatch() {
+        let mut channels = HubChannels::new();
+        let channel = HubChannelName::try_from("channel").unwrap();
+        let mut receiver = channels.subscribe_user(&channel);
+
+        let dispatcher = Dispatcher::spawn(
+            channels.routes(),
+            Arc::new(HubHistory::new(0)),
+            MiddlewarePipeline::default(),
+            Arc::new(ChannelMetrics::default()),
+            Arc::default(),
+            &HubOptions::default(),
+            &supervisor(),
+        );
+        assert!(dispatcher.workers.is_empty());
+        dispatcher
+            .dispatch(HubMessage::try_from_str("channel", "1").unwrap())
+            .await;
+        assert_eq!(receiver.recv().await.unwrap().data.as_str(), "1");
+    }
+
+    #[tokio::test]
+    async fn test_worker_dispatch_keeps_channel_order() {
+        let mut channels = HubChannels::new();
+        let channel1 = HubChannelName::try_from("channel1").unwrap();
+        let channel2 = HubChannelName::try_from("channel2").unwrap();
+        let mut receiver1 = channels.subscribe_user(&channel1);
+        let mut receiver2 = channels.subscribe_user(&channel2);
+
+        let options = HubOptionsBuilder::new()
+            .dispatch_workers(4)
+            .build()
+            .unwrap();
+        let dispatcher = Dispatcher::spawn(
+            channels.routes(),
+            Arc::new(HubHistory::new(0)),
+            MiddlewarePipeline::default(),
+            Arc::new(ChannelMetrics::default()),
+            Arc::default(),
+            &options,
+            &supervisor(),
+        );
+        assert_eq!(dispatcher.workers.len(), 4);
+        assert_eq!(
+            dispatcher.worker_idx(&channel1),
+            dispatcher.worker_idx(&channel1)
+        );
+
+        for i in 0..10 {
+            let data = i.to_string();
+            dispatcher
+                .dispatch(HubMessage::try_from_str("channel1", &data).unwrap())
+                .await;
+            dispatcher
+                .dispatch(HubMessage::try_from_str("channel2", &data).unwrap())
+                .await;
+        }
+        for i in 0..10 {
+            assert_eq!(receiver1.recv().await.unwrap().data.as_str(), i.to_string());
+            assert_eq!(receiver2.recv().await.unwrap().data.as_str(), i.to_string());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_workers_stop_with_supervisor() {
+        let channels = HubChannels::new();
+        let options = HubOptionsBuilder::new()
+            .dispatch_workers(2)
+            .priority_channel("estop")
+            .build()
+            .unwrap();
+        let supervisor = supervisor();
+        let dispatcher = Dispatcher::spawn(
+            channels.routes(),
+            Arc::new(HubHistory::new(0)),
+            MiddlewarePipeline::default(),
+            Arc::new(ChannelMetrics::default()),
+            Arc::default(),
+            &options,
+            &supervisor,
+        );
+        assert!(!dispatcher.workers.iter().any(mpsc::Sender::is_closed));
+
+        supervisor.shutdown().await;
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert!(dispatcher.workers.iter().all(mpsc::Sender::is_closed));
+        assert!(dispatcher.priority.as_ref().unwrap().is_closed());
+    }
+
+    #[tokio::test]
+    async fn test_reliable_channel_waits_for_worker() {
+        use crate::config::{ChannelQosBuilder, Reliability};
+
+        let reliable = ChannelQosBuilder::new()
+            .reliability(Reliability::Reliable)
+            .build()
+            .unwrap();
+        let options = HubOptionsBuilder::new()
+            .dispatch_workers(2)
+            .dispatch_queue_capacity(1)
+            .qos("cmd", reliable)
+            .build()
+            .unwrap();
+        let mut channels = HubChannels::with_qos(options.qos().clone());
+        let channel = HubChannelName::try_from("cmd").unwrap();
+        let mut receiver = channels.subscribe_user(&channel);
+
+        let dispatcher = Dispatcher::spawn(
+            channels.routes(),
+            Arc::new(HubHistory::new(0)),
+            MiddlewarePipeline::default(),
+            Arc::new(ChannelMetrics::default()),
+            Arc::default(),
+            &options,
+            &supervisor(),
+        );
+        for i in 0..50 {
+            dispatcher
+                .dispatch(HubMessage::try_from_str("cmd", &i.to_string()).unwrap())
+                .await;
+        }
+        for i in 0..50 {
+            assert_eq!(receiver.recv().await.unwrap().data.as_str(), i.to_string());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_priority_channel_skips_queued_telemetry() {
+        use crate::config::{Backpressure, ChannelQosBuilder};
+        use std::time::Duration;
+
+        let blocking = ChannelQosBuilder::new()
+            .backpressure(Backpressure::Block)
+            .depth(1)
+            .build()
+            .unwrap();
+        let options = HubOptionsBuilder::new()
+            .dispatch_workers(2)
+            .qos("telemetry", blocking)
+            .priority_channel("estop")
+            .build()
+            .unwrap();
+        let mut channels = HubChannels::with_qos(options.qos().clone());
+        let mut telemetry =
+            channels.subscribe_user(&HubChannelName::try_from("telemetry").unwrap());
+        let mut estop = channels.subscribe_user(&HubChannelName::try_from("estop").unwrap());
+
+        let dispatcher = Dispatcher::spawn(
+            channels.routes(),
+            Arc::new(HubHistory::new(0)),
+            MiddlewarePipeline::default(),
+            Arc::new(ChannelMetrics::default()),
+            Arc::default(),
+            &options,
+            &supervisor(),
+        );
+        // telemetry worker blocks on the full subscriber queue
+        for i in 0..3 {
+            dispatcher
+                .dispatch(HubMessage::try_from_str("telemetry", &i.to_string()).unwrap())
+                .await;
+        }
+        dispatcher
+            .dispatch(HubMessage::try_from_str("estop", "stop").unwrap())
+            .await;
+        let message = tokio::time::timeout(Duration::from_secs(1), estop.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(message.data.as_str(), "stop");
+        for i in 0..3 {
+            assert_eq!(telemetry.recv().await.unwrap().data.as_str(), i.to_string());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resumed_messages_are_routed_and_counted_once() {
+        use crate::config::{ChannelQosBuilder, Reliability};
+        use std::time::Duration;
+
+        let reliable = ChannelQosBuilder::new()
+            .reliability(Reliability::Reliable)
+            .build()
+            .unwrap();
+        let options = HubOptionsBuilder::new()
+            .dispatch_workers(2)
+            .dispatch_queue_capacity(1)
+            .qos("cmd", reliable)
+            .build()
+            .unwrap();
+        let mut channels = HubChannels::with_qos(options.qos().clone());
+        let channel = HubChannelName::try_from("cmd").unwrap();
+        let mut receiver = channels.subscribe_user(&channel);
+        let metrics = Arc::new(ChannelMetrics::default());
+        let paused = Arc::new(PausedChannels::default());
+
+        let dispatcher = Dispatcher::spawn(
+            channels.routes(),
+            Arc::new(HubHistory::new(0)),
+            MiddlewarePipeline::default(),
+            metrics.clone(),
+            paused.clone(),
+            &options,
+            &supervisor(),
+        );
+        paused.pause(channel.clone(), 10);
+        for i in 0..10 {
+            dispatcher
+                .dispatch(HubMessage::try_from_str("cmd", &i.to_string()).unwrap())
+                .await;
+        }
+        assert!(
+            tokio::time::timeout(Duration::from_millis(50), receiver.recv())
+                .await
+                .is_err()
+        );
+
+        // held messages wait for room in the single slot worker queue
+        dispatcher.resume(&channel).await;
+        for i in 0..10 {
+            assert_eq!(receiver.recv().await.unwrap().data.as_str(), i.to_string());
+        }
+        assert!(paused.paused().is_empty());
+        let stats = metrics.snapshot(&channels.routes().load());
+        assert_eq!(stats[0].messages, 10);
+        assert_eq!(stats[0].dropped, 0);
+    }
+}
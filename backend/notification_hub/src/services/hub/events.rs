@@ -0,0 +1,301 @@
+use futures_util::stream::{self, BoxStream};
+use futures_util::StreamExt;
+use log::error;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::time::MissedTickBehavior;
+use tokio_stream::wrappers::BroadcastStream;
+
+use super::health::HUB_HEALTH;
+use super::routing::HubNode;
+use super::schema::HUB_DEAD_LETTER;
+use super::stats::HUB_STATS;
+use super::supervisor::HUB_ERRORS;
+use crate::models::hub::{HubChannelName, HubMessage};
+
+/// Reserved meta-channel where the hub publishes channel lifecycle events
+pub const CHANNEL_EVENTS: &str = "hub_channels";
+
+const CHANNEL_CAPACITY: usize = 100;
+const ADDED_TAG: &str = "added:";
+const REMOVED_TAG: &str = "removed:";
+
+/// Returns true if channel is a meta-channel fed by the hub itself rather than by hub nodes
+pub(crate) fn is_meta_channel(channel: &HubChannelName) -> bool {
+    matches!(
+        channel.as_str(),
+        CHANNEL_EVENTS | HUB_DEAD_LETTER | HUB_ERRORS | HUB_HEALTH | HUB_STATS
+    )
+}
+
+/// Lifecycle event of a topic channel in the Hub network.
+///
+/// Events are published on the `CHANNEL_EVENTS` meta-channel with data `added:<channel>`
+/// or `removed:<channel>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChannelEvent {
+    ChannelAdded(HubChannelName),
+    ChannelRemoved(HubChannelName),
+}
+
+impl ChannelEvent {
+    pub fn channel(&self) -> &HubChannelName {
+        match self {
+            ChannelEvent::ChannelAdded(channel) | ChannelEvent::ChannelRemoved(channel) => channel,
+        }
+    }
+
+    fn to_message(&self) -> HubMessage {
+        let data = match self {
+            ChannelEvent::ChannelAdded(channel) => format!("{}{}", ADDED_TAG, channel.as_str()),
+            ChannelEvent::ChannelRemoved(channel) => format!("{}{}", REMOVED_TAG, channel.as_str()),
+        };
+        HubMessage::try_from_str(CHANNEL_EVENTS, &data).unwrap()
+    }
+}
+
+impl TryFrom<&HubMessage> for ChannelEvent {
+    type Error = String;
+
+    fn try_from(message: &HubMessage) -> Result<Self, Self::Error> {
+        if message.channel.as_str() != CHANNEL_EVENTS {
+            return Err(format!(
+                "Message from channel {} is not a channel event",
+                message.channel.as_str()
+            ));
+        }
+        let data = message.data.as_str();
+        if let Some(channel) = data.strip_prefix(ADDED_TAG) {
+            return Ok(ChannelEvent::ChannelAdded(HubChannelName::try_from(
+                channel,
+            )?));
+        }
+        if let Some(channel) = data.strip_prefix(REMOVED_TAG) {
+            return Ok(ChannelEvent::ChannelRemoved(HubChannelName::try_from(
+                channel,
+            )?));
+        }
+        Err(format!("Invalid channel event {}", data))
+    }
+}
+
+/// Channels known by a `ChannelWatcher`.
+///
+/// # Fields
+/// - `channels`: Channels listed by the hub nodes, or seen in received messages.
+/// - `listed`: Channels listed by the hub nodes in the last refresh.
+#[derive(Debug, Default)]
+struct KnownChannels {
+    channels: HashSet<HubChannelName>,
+    listed: HashSet<HubChannelName>,
+}
+
+/// `ChannelWatcher` tracks the channels available in the hub nodes, and emits a
+/// `ChannelEvent` every time a channel appears or disappears.
+///
+/// Channels are added as soon as the hub receives their first message (e.g. a new sensor
+/// parsed from the serial port, or a new topic published by a WebSocket peer). Hub nodes
+/// don't notify removed channels, so the watcher refreshes the channel list periodically as
+/// well. Channels are removed once they are no longer listed by the hub nodes, so channels
+/// of nodes that don't list their channels are never removed.
+///
+/// Events are sent to `watch` streams and published on the `CHANNEL_EVENTS` meta-channel.
+#[derive(Debug, Clone)]
+pub(crate) struct ChannelWatcher {
+    known: Arc<Mutex<KnownChannels>>,
+    events: broadcast::Sender<ChannelEvent>,
+}
+
+impl ChannelWatcher {
+    pub(crate) fn new() -> Self {
+        let (events, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self {
+            known: Arc::new(Mutex::new(KnownChannels::default())),
+            events,
+        }
+    }
+
+    fn known(&self) -> MutexGuard<'_, KnownChannels> {
+        self.known.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Sends event to `watch` streams and to `hub_sender`
+    fn emit(&self, event: ChannelEvent, hub_sender: &broadcast::Sender<HubMessage>) {
+        // nobody may be listening
+        let _ = hub_sender.send(event.to_message());
+        let _ = self.events.send(event);
+    }
+
+    /// Adds the channel of a message received by the hub, emitting a `ChannelAdded` event
+    /// if the channel was unknown
+    pub(crate) fn observe(
+        &self,
+        channel: &HubChannelName,
+        hub_sender: &broadcast::Sender<HubMessage>,
+    ) {
+        if is_meta_channel(channel) || !self.known().channels.insert(channel.clone()) {
+            return;
+        }
+        self.emit(ChannelEvent::ChannelAdded(channel.clone()), hub_sender);
+    }
+
+    /// Returns a stream starting with a `ChannelAdded` event for every known channel,
+    /// followed by live events
+    pub(crate) fn watch(&self) -> BoxStream<'static, ChannelEvent> {
+        let live = BroadcastStream::new(self.events.subscribe())
+            .filter_map(|event| std::future::ready(event.ok()));
+        let known: Vec<_> = self
+            .known()
+            .channels
+            .iter()
+            .cloned()
+            .map(ChannelEvent::ChannelAdded)
+            .collect();
+        stream::iter(known).chain(live).boxed()
+    }
+
+    /// Updates the known channels with the `channels` listed by the hub nodes, and returns
+    /// the events of channels not known before, and of channels no longer listed
+    pub(crate) fn update(&self, channels: HashSet<HubChannelName>) -> Vec<ChannelEvent> {
+        let mut known = self.known();
+        let mut events: Vec<_> = channels
+            .difference(&known.channels)
+            .cloned()
+            .map(ChannelEvent::ChannelAdded)
+            .collect();
+        let removed: Vec<_> = known.listed.difference(&channels).cloned().collect();
+        for channel in &removed {
+            known.channels.remove(channel);
+        }
+        events.extend(removed.into_iter().map(ChannelEvent::ChannelRemoved));
+        known.channels.extend(channels.iter().cloned());
+        known.listed = channels;
+        events
+    }
+
+    /// Refreshes the channels of `hub_nodes` every `interval`. Events are also sent to
+    /// `hub_sender` so that subscribers of the meta-channel receive them.
+    pub(crate) async fn run(
+        self,
+        hub_nodes: Vec<HubNode>,
+        hub_sender: broadcast::Sender<HubMessage>,
+        interval: Duration,
+    ) {
+        let mut interval = tokio::time::interval(interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            let mut channels = HashSet::new();
+            let mut failed = false;
+            for (_, node) in &hub_nodes {
+                match node.list_channels().await {
+                    Ok(node_channels) => channels.extend(node_channels),
+                    Err(e) => {
+                        error!("Failed to list channels of {:?}: {:?}", node, e);
+                        failed = true;
+                    }
+                }
+            }
+            // a partial list would report channels of the failing node as removed
+            if failed {
+                continue;
+            }
+            for event in self.update(channels) {
+                self.emit(event, &hub_sender);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(name: &str) -> HubChannelName {
+        HubChannelName::try_from(name).unwrap()
+    }
+
+    #[test]
+    fn test_update_emits_differences() {
+        let watcher = ChannelWatcher::new();
+        let events = watcher.update(HashSet::from([channel("topic1")]));
+        assert_eq!(events, vec![ChannelEvent::ChannelAdded(channel("topic1"))]);
+
+        assert!(watcher
+            .update(HashSet::from([channel("topic1")]))
+            .is_empty());
+
+        let events = watcher.update(HashSet::from([channel("topic2")]));
+        assert_eq!(
+            events,
+            vec![
+                ChannelEvent::ChannelAdded(channel("topic2")),
+                ChannelEvent::ChannelRemoved(channel("topic1"))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_event_message_conversion() {
+        for event in [
+            ChannelEvent::ChannelAdded(channel("topic1")),
+            ChannelEvent::ChannelRemoved(channel("topic1")),
+        ] {
+            let message = event.to_message();
+            assert!(is_meta_channel(&message.channel));
+            assert_eq!(ChannelEvent::try_from(&message).unwrap(), event);
+        }
+        let message = HubMessage::try_from_str("topic1", "added:topic2").unwrap();
+        assert!(ChannelEvent::try_from(&message).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_observed_channels_are_added() {
+        let watcher = ChannelWatcher::new();
+        let (hub_sender, mut hub_receiver) = broadcast::channel(CHANNEL_CAPACITY);
+        let mut events = watcher.watch();
+
+        watcher.observe(&channel("sensor/7"), &hub_sender);
+        watcher.observe(&channel("sensor/7"), &hub_sender);
+        watcher.observe(&channel(CHANNEL_EVENTS), &hub_sender);
+        let added = ChannelEvent::ChannelAdded(channel("sensor/7"));
+        assert_eq!(events.next().await.unwrap(), added);
+        assert_eq!(
+            ChannelEvent::try_from(&hub_receiver.recv().await.unwrap()).unwrap(),
+            added
+        );
+        assert!(hub_receiver.try_recv().is_err());
+
+        // channels not listed by hub nodes are kept
+        assert!(watcher.update(HashSet::new()).is_empty());
+        assert!(watcher
+            .update(HashSet::from([channel("sensor/7")]))
+            .is_empty());
+        assert_eq!(
+            watcher.update(HashSet::new()),
+            vec![ChannelEvent::ChannelRemoved(channel("sensor/7"))]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_watch_starts_with_known_channels() {
+        let watcher = ChannelWatcher::new();
+        watcher.update(HashSet::from([channel("topic1")]));
+
+        let mut events = watcher.watch();
+        assert_eq!(
+            events.next().await.unwrap(),
+            ChannelEvent::ChannelAdded(channel("topic1"))
+        );
+
+        for event in watcher.update(HashSet::new()) {
+            watcher.events.send(event).unwrap();
+        }
+        assert_eq!(
+            events.next().await.unwrap(),
+            ChannelEvent::ChannelRemoved(channel("topic1"))
+        );
+    }
+}
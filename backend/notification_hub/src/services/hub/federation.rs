@@ -0,0 +1,404 @@
+use async_trait::async_trait;
+use log::{error, warn};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, RwLock};
+use tokio::task::JoinHandle;
+
+use super::handle::HubHandle;
+use crate::models::hub::{HubChannelName, HubMessage};
+use crate::ports::{NodeTasks, NotificationHub};
+
+/// Link channel where the exporting hub advertises its channels, as a comma separated list
+pub const FEDERATION_ADVERTISE: &str = "hub_federation/advertise";
+/// Link channel where the importing hub requests the messages of a channel
+pub const FEDERATION_SUBSCRIBE: &str = "hub_federation/subscribe";
+/// Link channel where the importing hub stops the messages of a channel
+pub const FEDERATION_UNSUBSCRIBE: &str = "hub_federation/unsubscribe";
+
+const CHANNEL_CAPACITY: usize = 100;
+
+fn control_channel(name: &str) -> HubChannelName {
+    HubChannelName::try_from(name).unwrap()
+}
+
+fn control_message(channel: &str, data: &str) -> Result<HubMessage, std::io::Error> {
+    HubMessage::try_from_str(channel, data)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+// Channels listed in an advertisement. Invalid names are skipped
+fn advertised_channels(message: &HubMessage) -> HashSet<HubChannelName> {
+    message
+        .data
+        .as_str()
+        .split(',')
+        .filter(|name| !name.is_empty())
+        .filter_map(|name| HubChannelName::try_from(name).ok())
+        .collect()
+}
+
+/// `FederationExport` exports the channels of a hub to a remote hub over a link node (e.g. a
+/// WebSocket or serial client connected to the base station).
+///
+/// The channels of the hub are advertised periodically on `FEDERATION_ADVERTISE`. A channel is
+/// only subscribed in the hub, and its messages sent over the link, once the remote hub
+/// requests it on `FEDERATION_SUBSCRIBE`, until it is released on `FEDERATION_UNSUBSCRIBE`.
+/// Any other message received from the link is published in the hub.
+///
+/// # Fields
+/// - `hub`: Handle of the exported hub.
+/// - `link`: Node connected to the remote hub.
+/// - `advertise_interval`: Period at which channels are advertised.
+#[derive(Debug)]
+pub struct FederationExport {
+    hub: HubHandle,
+    link: Arc<dyn NotificationHub>,
+    advertise_interval: Duration,
+}
+
+impl FederationExport {
+    pub fn new(
+        hub: HubHandle,
+        link: Box<dyn NotificationHub>,
+        advertise_interval: Duration,
+    ) -> Self {
+        Self {
+            hub,
+            link: Arc::from(link),
+            advertise_interval,
+        }
+    }
+
+    /// Starts the link, and the task serving the requests of the remote hub. Returns the
+    /// handles of the tasks spawned
+    pub async fn start(&self) -> Result<NodeTasks, std::io::Error> {
+        let (link_sender, mut link_receiver) = broadcast::channel(CHANNEL_CAPACITY);
+        let mut tasks = self.link.start(Some(link_sender)).await?;
+        for channel in [FEDERATION_SUBSCRIBE, FEDERATION_UNSUBSCRIBE] {
+            self.link.subscribe(control_channel(channel)).await?;
+        }
+
+        let hub = self.hub.clone();
+        let link = Arc::clone(&self.link);
+        let advertise_interval = self.advertise_interval;
+        tasks.push(tokio::spawn(async move {
+            let mut exports = HashMap::new();
+            let mut advertise = tokio::time::interval(advertise_interval);
+            loop {
+                tokio::select! {
+                    _ = advertise.tick() => advertise_channels(&hub, &link).await,
+                    received = link_receiver.recv() => match received {
+                        Ok(message) => serve(&hub, &link, &mut exports, message).await,
+                        Err(RecvError::Lagged(skipped)) => {
+                            warn!("Federation link lagged. {} messages dropped", skipped);
+                        }
+                        Err(RecvError::Closed) => break,
+                    }
+                }
+            }
+            // dropped receivers unsubscribe from the hub
+            for forwarder in exports.into_values() {
+                forwarder.abort();
+            }
+        }));
+        Ok(tasks)
+    }
+}
+
+async fn advertise_channels(hub: &HubHandle, link: &Arc<dyn NotificationHub>) {
+    let channels = match hub.list_channels().await {
+        Ok(channels) => channels,
+        Err(e) => {
+            error!("Failed to list federated channels: {:?}", e);
+            return;
+        }
+    };
+    let mut channels: Vec<_> = channels.iter().map(HubChannelName::as_str).collect();
+    channels.sort();
+    let result = match control_message(FEDERATION_ADVERTISE, &channels.join(",")) {
+        Ok(message) => link.send(message).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        error!("Failed to advertise federated channels: {:?}", e);
+    }
+}
+
+// Serves message received from the remote hub. `exports` holds the task forwarding the
+// messages of every exported channel over the link.
+async fn serve(
+    hub: &HubHandle,
+    link: &Arc<dyn NotificationHub>,
+    exports: &mut HashMap<HubChannelName, JoinHandle<()>>,
+    message: HubMessage,
+) {
+    match message.channel.as_str() {
+        FEDERATION_SUBSCRIBE => {
+            let Ok(channel) = HubChannelName::try_from(message.data.as_str()) else {
+                warn!("Invalid federated channel {}", message.data.as_str());
+                return;
+            };
+            if exports.contains_key(&channel) {
+                return;
+            }
+            let mut receiver = match hub.subscribe(channel.clone()).await {
+                Ok(receiver) => receiver,
+                Err(e) => {
+                    error!("Failed to export channel {}: {:?}", channel.as_str(), e);
+                    return;
+                }
+            };
+            let link = Arc::clone(link);
+            let forwarder = tokio::spawn(async move {
+                loop {
+                    match receiver.recv().await {
+                        Ok(message) => {
+                            if let Err(e) = link.send(message).await {
+                                error!("Failed to send federated message: {:?}", e);
+                            }
+                        }
+                        Err(RecvError::Lagged(skipped)) => {
+                            warn!("Federated channel lagged. {} messages dropped", skipped);
+                        }
+                        Err(RecvError::Closed) => break,
+                    }
+                }
+            });
+            exports.insert(channel, forwarder);
+        }
+        FEDERATION_UNSUBSCRIBE => {
+            let Ok(channel) = HubChannelName::try_from(message.data.as_str()) else {
+                warn!("Invalid federated channel {}", message.data.as_str());
+                return;
+            };
+            // dropping the receiver unsubscribes from the hub
+            if let Some(forwarder) = exports.remove(&channel) {
+                forwarder.abort();
+            }
+        }
+        FEDERATION_ADVERTISE => {}
+        _ => {
+            if let Err(e) = hub.publish(message).await {
+                error!("Failed to publish federated message: {:?}", e);
+            }
+        }
+    }
+}
+
+/// `FederatedNode` is the hub node importing the channels of a remote hub exported with
+/// `FederationExport`, over a link node.
+///
+/// The node lists the channels advertised by the remote hub. Subscribing to a channel requests
+/// it on `FEDERATION_SUBSCRIBE`, so only channels consumed by this hub cross the link.
+/// Subscriptions are requested again with every advertisement, so they survive restarts of
+/// the remote hub. Messages sent to the node are published in the remote hub.
+///
+/// # Fields
+/// - `link`: Node connected to the remote hub.
+/// - `advertised`: Channels last advertised by the remote hub.
+/// - `subscriptions`: Channels requested to the remote hub.
+#[derive(Debug)]
+pub struct FederatedNode {
+    link: Arc<dyn NotificationHub>,
+    advertised: Arc<RwLock<HashSet<HubChannelName>>>,
+    subscriptions: Arc<RwLock<HashSet<HubChannelName>>>,
+}
+
+impl FederatedNode {
+    pub fn new(link: Box<dyn NotificationHub>) -> Self {
+        Self {
+            link: Arc::from(link),
+            advertised: Arc::new(RwLock::new(HashSet::new())),
+            subscriptions: Arc::new(RwLock::new(HashSet::new())),
+        }
+    }
+}
+
+#[async_trait]
+impl NotificationHub for FederatedNode {
+    async fn send(&self, message: HubMessage) -> Result<(), std::io::Error> {
+        self.link.send(message).await
+    }
+
+    async fn start(
+        &self,
+        sender: Option<broadcast::Sender<HubMessage>>,
+    ) -> Result<NodeTasks, std::io::Error> {
+        let Some(hub_sender) = sender else {
+            return self.link.start(None).await;
+        };
+        let (link_sender, mut link_receiver) = broadcast::channel(CHANNEL_CAPACITY);
+        let mut tasks = self.link.start(Some(link_sender)).await?;
+        self.link
+            .subscribe(control_channel(FEDERATION_ADVERTISE))
+            .await?;
+
+        let link = Arc::clone(&self.link);
+        let advertised = Arc::clone(&self.advertised);
+        let subscriptions = Arc::clone(&self.subscriptions);
+        tasks.push(tokio::spawn(async move {
+            loop {
+                match link_receiver.recv().await {
+                    Ok(message) => match message.channel.as_str() {
+                        FEDERATION_ADVERTISE => {
+                            let channels = advertised_channels(&message);
+                            for channel in subscriptions.read().await.iter() {
+                                if !channels.contains(channel) {
+                                    continue;
+                                }
+                                let result =
+                                    match control_message(FEDERATION_SUBSCRIBE, channel.as_str()) {
+                                        Ok(message) => link.send(message).await,
+                                        Err(e) => Err(e),
+                                    };
+                                if let Err(e) = result {
+                                    error!("Failed to request federated channel: {:?}", e);
+                                }
+                            }
+                            *advertised.write().await = channels;
+                        }
+                        FEDERATION_SUBSCRIBE | FEDERATION_UNSUBSCRIBE => {}
+                        _ => {
+                            // hub may not be listening yet
+                            let _ = hub_sender.send(message);
+                        }
+                    },
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Federation link lagged. {} messages dropped", skipped);
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        }));
+        Ok(tasks)
+    }
+
+    async fn list_channels(&self) -> Result<Vec<HubChannelName>, std::io::Error> {
+        Ok(self.advertised.read().await.iter().cloned().collect())
+    }
+
+    async fn subscribe(&self, channel: HubChannelName) -> Result<(), std::io::Error> {
+        self.link.subscribe(channel.clone()).await?;
+        self.link
+            .send(control_message(FEDERATION_SUBSCRIBE, channel.as_str())?)
+            .await?;
+        self.subscriptions.write().await.insert(channel);
+        Ok(())
+    }
+
+    async fn unsubscribe(&self, channel: HubChannelName) -> Result<(), std::io::Error> {
+        self.subscriptions.write().await.remove(&channel);
+        self.link
+            .send(control_message(FEDERATION_UNSUBSCRIBE, channel.as_str())?)
+            .await?;
+        self.link.unsubscribe(channel).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::hub::mock::MockNode;
+    use crate::services::hub::HubManager;
+
+    #[derive(Debug, Default)]
+    struct LinkEnd {
+        sender: std::sync::Mutex<Option<broadcast::Sender<HubMessage>>>,
+    }
+
+    /// One side of an in-memory link, delivering sent messages to the other side
+    #[derive(Debug)]
+    struct Link {
+        local: Arc<LinkEnd>,
+        remote: Arc<LinkEnd>,
+    }
+
+    fn link_pair() -> (Link, Link) {
+        let (end1, end2) = (Arc::new(LinkEnd::default()), Arc::new(LinkEnd::default()));
+        (
+            Link {
+                local: end1.clone(),
+                remote: end2.clone(),
+            },
+            Link {
+                local: end2,
+                remote: end1,
+            },
+        )
+    }
+
+    #[async_trait]
+    impl NotificationHub for Link {
+        async fn send(&self, message: HubMessage) -> Result<(), std::io::Error> {
+            if let Some(sender) = self.remote.sender.lock().unwrap().as_ref() {
+                let _ = sender.send(message);
+            }
+            Ok(())
+        }
+        async fn start(
+            &self,
+            sender: Option<broadcast::Sender<HubMessage>>,
+        ) -> Result<NodeTasks, std::io::Error> {
+            *self.local.sender.lock().unwrap() = sender;
+            Ok(NodeTasks::new())
+        }
+        async fn list_channels(&self) -> Result<Vec<HubChannelName>, std::io::Error> {
+            Ok(Vec::new())
+        }
+    }
+
+    fn channel(name: &str) -> HubChannelName {
+        HubChannelName::try_from(name).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_channels_cross_link_on_demand() {
+        let (robot_link, base_link) = link_pair();
+
+        let sensor = Arc::new(MockNode::default());
+        sensor.channels.lock().await.push(channel("imu"));
+        let mut robot = HubManager::new();
+        robot.add("sensor", Box::new(sensor.clone())).unwrap();
+        robot.start().await.unwrap();
+        let export = FederationExport::new(
+            robot.spawn(),
+            Box::new(robot_link),
+            Duration::from_millis(10),
+        );
+        export.start().await.unwrap();
+
+        let mut base = HubManager::new();
+        base.add("robot", Box::new(FederatedNode::new(Box::new(base_link))))
+            .unwrap();
+        base.start().await.unwrap();
+        while base.list_channels().await.unwrap().is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(base
+            .list_channels()
+            .await
+            .unwrap()
+            .contains(&channel("imu")));
+        assert!(sensor.subscribed.lock().await.is_empty());
+
+        let mut receiver = base.register_to_channel(channel("imu")).await.unwrap();
+        while sensor.subscribed.lock().await.is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        sensor.receive(HubMessage::try_from_str("imu", "1,2,3").unwrap());
+        assert_eq!(receiver.recv().await.unwrap().data.as_str(), "1,2,3");
+
+        base.publish(HubMessage::try_from_str("cmd_vel", "1").unwrap())
+            .await
+            .unwrap();
+        drop(receiver);
+        while sensor.unsubscribed.lock().await.is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(*sensor.unsubscribed.lock().await, vec![channel("imu")]);
+        assert_eq!(sensor.sent.lock().await[0].channel, channel("cmd_vel"));
+    }
+}
@@ -0,0 +1,136 @@
+use std::fmt;
+use std::sync::Arc;
+
+use crate::models::hub::HubMessage;
+
+type Predicate = dyn Fn(&HubMessage) -> bool + Send + Sync;
+
+/// `MessageFilter` selects the messages delivered to a subscription. Messages are matched
+/// when dispatched, so subscribers are not woken up by messages they are not interested in.
+///
+/// Field filters read the comma separated fields of the message data (e.g. `12.5,0.3,ok`).
+/// Messages whose field is missing or not a number don't match.
+///
+/// - `Contains`: Data contains the text.
+/// - `Above`: Numeric field `field` is greater than `threshold`.
+/// - `Below`: Numeric field `field` is lower than `threshold`.
+/// - `All`: Every filter matches.
+/// - `Any`: At least one filter matches.
+/// - `Predicate`: Arbitrary function, built with `MessageFilter::predicate`.
+#[derive(Clone)]
+pub enum MessageFilter {
+    Contains(String),
+    Above { field: usize, threshold: f64 },
+    Below { field: usize, threshold: f64 },
+    All(Vec<MessageFilter>),
+    Any(Vec<MessageFilter>),
+    Predicate(Arc<Predicate>),
+}
+
+fn field(message: &HubMessage, field: usize) -> Option<f64> {
+    message
+        .data
+        .as_str()
+        .split(',')
+        .nth(field)
+        .and_then(|value| value.trim().parse().ok())
+}
+
+impl MessageFilter {
+    pub fn predicate(predicate: impl Fn(&HubMessage) -> bool + Send + Sync + 'static) -> Self {
+        Self::Predicate(Arc::new(predicate))
+    }
+
+    /// Returns true if message passes the filter
+    pub fn matches(&self, message: &HubMessage) -> bool {
+        match self {
+            Self::Contains(text) => message.data.as_str().contains(text.as_str()),
+            Self::Above {
+                field: idx,
+                threshold,
+            } => field(message, *idx).is_some_and(|value| value > *threshold),
+            Self::Below {
+                field: idx,
+                threshold,
+            } => field(message, *idx).is_some_and(|value| value < *threshold),
+            Self::All(filters) => filters.iter().all(|filter| filter.matches(message)),
+            Self::Any(filters) => filters.iter().any(|filter| filter.matches(message)),
+            Self::Predicate(predicate) => predicate(message),
+        }
+    }
+}
+
+impl fmt::Debug for MessageFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Contains(text) => f.debug_tuple("Contains").field(text).finish(),
+            Self::Above { field, threshold } => f
+                .debug_struct("Above")
+                .field("field", field)
+                .field("threshold", threshold)
+                .finish(),
+            Self::Below { field, threshold } => f
+                .debug_struct("Below")
+                .field("field", field)
+                .field("threshold", threshold)
+                .finish(),
+            Self::All(filters) => f.debug_tuple("All").field(filters).finish(),
+            Self::Any(filters) => f.debug_tuple("Any").field(filters).finish(),
+            Self::Predicate(_) => f.write_str("Predicate"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(data: &str) -> HubMessage {
+        HubMessage::try_from_str("channel", data).unwrap()
+    }
+
+    #[test]
+    fn test_declarative_filters() {
+        let contains = MessageFilter::Contains("error".to_string());
+        assert!(contains.matches(&message("motor error")));
+        assert!(!contains.matches(&message("ok")));
+
+        let above = MessageFilter::Above {
+            field: 1,
+            threshold: 80.0,
+        };
+        assert!(above.matches(&message("1.0, 85.5")));
+        assert!(!above.matches(&message("90.0,20.0")));
+        assert!(!above.matches(&message("90.0")));
+        assert!(!above.matches(&message("1.0,high")));
+
+        let below = MessageFilter::Below {
+            field: 0,
+            threshold: 10.0,
+        };
+        assert!(below.matches(&message("9.5")));
+        assert!(!below.matches(&message("10")));
+    }
+
+    #[test]
+    fn test_combined_filters() {
+        let battery_low = MessageFilter::All(vec![
+            MessageFilter::Below {
+                field: 0,
+                threshold: 11.0,
+            },
+            MessageFilter::predicate(|message| !message.data.as_str().ends_with(",charging")),
+        ]);
+        assert!(battery_low.matches(&message("10.8,discharging")));
+        assert!(!battery_low.matches(&message("10.8,charging")));
+        assert!(!battery_low.matches(&message("12.1,discharging")));
+
+        let any = MessageFilter::Any(vec![
+            MessageFilter::Contains("fault".to_string()),
+            battery_low,
+        ]);
+        assert!(any.matches(&message("12.1,fault")));
+        assert!(!any.matches(&message("12.1,ok")));
+        assert!(format!("{:?}", any).starts_with("Any"));
+    }
+}
@@ -0,0 +1,341 @@
+use log::warn;
+use std::collections::HashSet;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use uuid::Uuid;
+
+use super::controller::HubManager;
+use super::filter::MessageFilter;
+use super::history::Replay;
+use super::receiver::HubReceiver;
+use super::rpc::{self, RpcMessage};
+use super::schema::ChannelSchema;
+use super::stats::HubStats;
+use crate::models::hub::{HubChannelName, HubData, HubMessage};
+
+const CHANNEL_CAPACITY: usize = 100;
+
+type Reply<T> = oneshot::Sender<Result<T, std::io::Error>>;
+
+/// Commands processed by the manager task
+#[derive(Debug)]
+enum HubCommand {
+    Publish(HubMessage, Reply<()>),
+    Subscribe(HubChannelName, Option<Replay>, Reply<HubReceiver>),
+    SubscribeFiltered(HubChannelName, MessageFilter, Reply<HubReceiver>),
+    SubscribeGrouped(HubChannelName, String, Reply<HubReceiver>),
+    SubscribeWithCapacity(HubChannelName, usize, Reply<HubReceiver>),
+    SubscribeUser(Uuid, HubChannelName, Reply<HubReceiver>),
+    Unsubscribe(HubChannelName, Uuid, Reply<()>),
+    DropUser(Uuid, Reply<()>),
+    ListChannels(Reply<HashSet<HubChannelName>>),
+    PauseChannel(HubChannelName, Reply<()>),
+    ResumeChannel(HubChannelName, Reply<()>),
+    DeclareSchema(HubChannelName, ChannelSchema, Reply<()>),
+    ChannelSchema(HubChannelName, Reply<Option<ChannelSchema>>),
+    Stats(Reply<HubStats>),
+}
+
+/// `HubHandle` is a cheap, cloneable handle to a `HubManager` running in its own task.
+/// Requests are sent to the manager task through a command channel, and processed in order,
+/// so services and adapters can share the hub without owning it.
+#[derive(Debug, Clone)]
+pub struct HubHandle {
+    commands: mpsc::Sender<HubCommand>,
+}
+
+impl HubManager {
+    /// Moves the manager into its own task, and returns a handle to it. The manager task stops
+    /// when every handle is dropped.
+    pub fn spawn(mut self) -> HubHandle {
+        let (commands, mut receiver) = mpsc::channel(CHANNEL_CAPACITY);
+        tokio::spawn(async move {
+            while let Some(command) = receiver.recv().await {
+                // requester may have given up waiting. Reply errors are ignored
+                match command {
+                    HubCommand::Publish(message, reply) => {
+                        let _ = reply.send(self.publish(message).await);
+                    }
+                    HubCommand::Subscribe(channel, replay, reply) => {
+                        let receiver = match replay {
+                            Some(replay) => self.register_to_channel_with_replay(channel, replay),
+                            None => self.register_to_channel(channel),
+                        };
+                        let _ = reply.send(receiver.await);
+                    }
+                    HubCommand::SubscribeFiltered(channel, filter, reply) => {
+                        let _ =
+                            reply.send(self.register_to_channel_with_filter(channel, filter).await);
+                    }
+                    HubCommand::SubscribeGrouped(channel, group, reply) => {
+                        let _ =
+                            reply.send(self.register_to_channel_in_group(channel, &group).await);
+                    }
+                    HubCommand::SubscribeWithCapacity(channel, capacity, reply) => {
+                        let _ = reply.send(
+                            self.register_to_channel_with_capacity(channel, capacity)
+                                .await,
+                        );
+                    }
+                    HubCommand::SubscribeUser(user_id, channel, reply) => {
+                        let _ = reply.send(self.register_user_to_channel(user_id, channel).await);
+                    }
+                    HubCommand::Unsubscribe(channel, user_id, reply) => {
+                        let _ = reply.send(self.unregister_from_channel(channel, user_id).await);
+                    }
+                    HubCommand::DropUser(user_id, reply) => {
+                        let _ = reply.send(self.drop_user(user_id).await);
+                    }
+                    HubCommand::ListChannels(reply) => {
+                        let _ = reply.send(self.list_channels().await);
+                    }
+                    HubCommand::PauseChannel(channel, reply) => {
+                        let _ = reply.send(self.pause_channel(channel));
+                    }
+                    HubCommand::ResumeChannel(channel, reply) => {
+                        self.resume_channel(&channel).await;
+                        let _ = reply.send(Ok(()));
+                    }
+                    HubCommand::DeclareSchema(channel, schema, reply) => {
+                        self.declare_schema(channel, schema);
+                        let _ = reply.send(Ok(()));
+                    }
+                    HubCommand::ChannelSchema(channel, reply) => {
+                        let _ = reply.send(Ok(self.channel_schema(&channel)));
+                    }
+                    HubCommand::Stats(reply) => {
+                        let _ = reply.send(Ok(self.stats().await));
+                    }
+                }
+            }
+            warn!("All hub handles dropped. Stopping hub manager task");
+        });
+        HubHandle { commands }
+    }
+}
+
+fn hub_stopped() -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::BrokenPipe, "Hub manager stopped")
+}
+
+impl HubHandle {
+    async fn command<T>(
+        &self,
+        command: impl FnOnce(Reply<T>) -> HubCommand,
+    ) -> Result<T, std::io::Error> {
+        let (reply, response) = oneshot::channel();
+        self.commands
+            .send(command(reply))
+            .await
+            .map_err(|_| hub_stopped())?;
+        response.await.map_err(|_| hub_stopped())?
+    }
+
+    /// Publishes HubMessage to the hub nodes routing its channel
+    pub async fn publish(&self, message: HubMessage) -> Result<(), std::io::Error> {
+        self.command(|reply| HubCommand::Publish(message, reply))
+            .await
+    }
+
+    /// Publishes request `data` in `channel`, and waits up to `timeout` for the response
+    /// published in `<channel>/reply`. Returns the response data
+    pub async fn request(
+        &self,
+        channel: HubChannelName,
+        data: HubData,
+        timeout: Duration,
+    ) -> Result<HubData, std::io::Error> {
+        let invalid_input = |e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e);
+        let request = RpcMessage::new(data);
+        let reply_channel = rpc::reply_channel(&channel).map_err(invalid_input)?;
+        let message = request.to_message(channel).map_err(invalid_input)?;
+        let mut receiver = self
+            .subscribe_with_filter(reply_channel, rpc::correlated(request.correlation_id))
+            .await?;
+        self.publish(message).await?;
+        rpc::await_reply(&mut receiver, timeout).await
+    }
+
+    /// Subscribes to channel. Returns the receiver where channel messages are delivered
+    pub async fn subscribe(&self, channel: HubChannelName) -> Result<HubReceiver, std::io::Error> {
+        self.command(|reply| HubCommand::Subscribe(channel, None, reply))
+            .await
+    }
+
+    /// Subscribes to channel. Returns the receiver where up to the last `replay` messages of the
+    /// channel history are delivered before live messages
+    pub async fn subscribe_with_history(
+        &self,
+        channel: HubChannelName,
+        replay: usize,
+    ) -> Result<HubReceiver, std::io::Error> {
+        self.subscribe_with_replay(channel, Replay::Last(replay))
+            .await
+    }
+
+    /// Subscribes to channel. Returns the receiver where the messages of the channel history
+    /// selected by `replay` are delivered before live messages
+    pub async fn subscribe_with_replay(
+        &self,
+        channel: HubChannelName,
+        replay: Replay,
+    ) -> Result<HubReceiver, std::io::Error> {
+        self.command(|reply| HubCommand::Subscribe(channel, Some(replay), reply))
+            .await
+    }
+
+    /// Subscribes to channel. Returns the receiver where channel messages passing `filter` are
+    /// delivered
+    pub async fn subscribe_with_filter(
+        &self,
+        channel: HubChannelName,
+        filter: MessageFilter,
+    ) -> Result<HubReceiver, std::io::Error> {
+        self.command(|reply| HubCommand::SubscribeFiltered(channel, filter, reply))
+            .await
+    }
+
+    /// Subscribes to channel as a member of consumer group `group`. Returns the receiver where
+    /// the share of the channel messages assigned to this member is delivered
+    pub async fn subscribe_in_group(
+        &self,
+        channel: HubChannelName,
+        group: &str,
+    ) -> Result<HubReceiver, std::io::Error> {
+        let group = group.to_string();
+        self.command(|reply| HubCommand::SubscribeGrouped(channel, group, reply))
+            .await
+    }
+
+    /// Subscribes to channel with a receiver buffering up to `capacity` messages
+    pub async fn subscribe_with_capacity(
+        &self,
+        channel: HubChannelName,
+        capacity: usize,
+    ) -> Result<HubReceiver, std::io::Error> {
+        self.command(|reply| HubCommand::SubscribeWithCapacity(channel, capacity, reply))
+            .await
+    }
+
+    /// Subscribes to channel as user `user_id`, so that the user can be unsubscribed from all
+    /// its channels at once with `drop_user`
+    pub async fn subscribe_user(
+        &self,
+        user_id: Uuid,
+        channel: HubChannelName,
+    ) -> Result<HubReceiver, std::io::Error> {
+        self.command(|reply| HubCommand::SubscribeUser(user_id, channel, reply))
+            .await
+    }
+
+    /// Unsubscribes user from every channel it is subscribed to
+    pub async fn drop_user(&self, user_id: Uuid) -> Result<(), std::io::Error> {
+        self.command(|reply| HubCommand::DropUser(user_id, reply))
+            .await
+    }
+
+    /// Unsubscribes user from channel
+    pub async fn unsubscribe(
+        &self,
+        channel: HubChannelName,
+        user_id: Uuid,
+    ) -> Result<(), std::io::Error> {
+        self.command(|reply| HubCommand::Unsubscribe(channel, user_id, reply))
+            .await
+    }
+
+    /// Lists available topic channels in the Hub network
+    pub async fn list_channels(&self) -> Result<HashSet<HubChannelName>, std::io::Error> {
+        self.command(HubCommand::ListChannels).await
+    }
+
+    /// Pauses dispatch of `channel`, keeping its subscriptions
+    pub async fn pause_channel(&self, channel: HubChannelName) -> Result<(), std::io::Error> {
+        self.command(|reply| HubCommand::PauseChannel(channel, reply))
+            .await
+    }
+
+    /// Resumes dispatch of `channel`, delivering the messages buffered while it was paused
+    pub async fn resume_channel(&self, channel: HubChannelName) -> Result<(), std::io::Error> {
+        self.command(|reply| HubCommand::ResumeChannel(channel, reply))
+            .await
+    }
+
+    /// Declares the expected data format of `channel`
+    pub async fn declare_schema(
+        &self,
+        channel: HubChannelName,
+        schema: ChannelSchema,
+    ) -> Result<(), std::io::Error> {
+        self.command(|reply| HubCommand::DeclareSchema(channel, schema, reply))
+            .await
+    }
+
+    /// Returns the schema declared for `channel`, if any
+    pub async fn channel_schema(
+        &self,
+        channel: HubChannelName,
+    ) -> Result<Option<ChannelSchema>, std::io::Error> {
+        self.command(|reply| HubCommand::ChannelSchema(channel, reply))
+            .await
+    }
+
+    /// Returns a summary of the hub state
+    pub async fn stats(&self) -> Result<HubStats, std::io::Error> {
+        self.command(HubCommand::Stats).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::hub::mock::MockNode;
+    use crate::services::hub::stats::ChannelStats;
+
+    #[tokio::test]
+    async fn test_handle_is_shared_across_tasks() {
+        let mut hub = HubManager::new();
+        hub.add("node", Box::new(MockNode::default())).unwrap();
+        hub.start().await.unwrap();
+        let handle = hub.spawn();
+
+        let channel = HubChannelName::try_from("topic1").unwrap();
+        let subscriber = handle.clone();
+        let subscribed_channel = channel.clone();
+        let receiver =
+            tokio::spawn(async move { subscriber.subscribe(subscribed_channel).await.unwrap() })
+                .await
+                .unwrap();
+
+        let stats = handle.stats().await.unwrap();
+        assert_eq!(
+            stats,
+            HubStats {
+                hub_nodes: 1,
+                channels: 1,
+                subscriptions: 1,
+                channel_stats: vec![ChannelStats::new(channel.clone())],
+            }
+        );
+
+        handle
+            .publish(HubMessage::try_from_str("topic1", "1").unwrap())
+            .await
+            .unwrap();
+        assert!(handle.list_channels().await.unwrap().is_empty());
+
+        handle
+            .unsubscribe(channel, receiver.user_id())
+            .await
+            .unwrap();
+        assert_eq!(handle.stats().await.unwrap().subscriptions, 0);
+    }
+
+    #[tokio::test]
+    async fn test_handle_fails_when_hub_is_stopped() {
+        let (commands, receiver) = mpsc::channel(1);
+        drop(receiver);
+        let handle = HubHandle { commands };
+        assert!(handle.stats().await.is_err());
+    }
+}
@@ -0,0 +1,629 @@
+use async_trait::async_trait;
+use futures_util::future::select_all;
+use log::{error, warn};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::AbortHandle;
+
+use super::routing::NodeId;
+use crate::config::RetryPolicy;
+use crate::models::hub::{HubChannelName, HubMessage};
+use crate::ports::{NodeTasks, NotificationHub};
+
+/// Reserved meta-channel where the hub publishes the health of its nodes
+pub const HUB_HEALTH: &str = "hub_health";
+
+const CHANNEL_CAPACITY: usize = 100;
+const UP: &str = "up";
+const DOWN: &str = "down";
+
+/// Liveness of a hub node.
+///
+/// Health is published periodically on the `HUB_HEALTH` meta-channel with data
+/// `<node>,<up|down>,<ms since last read>,<ms since last write>,<write errors>,<restarts>`.
+/// Times are left empty if the node didn't read or write any message yet.
+///
+/// # Fields
+/// - `node`: Name of the hub node.
+/// - `alive`: False once the tasks of the node exited (e.g. serial port unplugged,
+///   WebSocket disconnected), until it is restarted.
+/// - `since_last_read`: Time since the node received its last message.
+/// - `since_last_write`: Time since the node last sent a message successfully.
+/// - `write_errors`: Number of messages the node failed to send.
+/// - `restarts`: Number of times the node was restarted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodeHealth {
+    pub node: NodeId,
+    pub alive: bool,
+    pub since_last_read: Option<Duration>,
+    pub since_last_write: Option<Duration>,
+    pub write_errors: u64,
+    pub restarts: u64,
+}
+
+fn millis(elapsed: Option<Duration>) -> String {
+    elapsed
+        .map(|elapsed| elapsed.as_millis().to_string())
+        .unwrap_or_default()
+}
+
+impl NodeHealth {
+    pub(crate) fn to_message(&self) -> HubMessage {
+        let data = format!(
+            "{},{},{},{},{},{}",
+            self.node,
+            if self.alive { UP } else { DOWN },
+            millis(self.since_last_read),
+            millis(self.since_last_write),
+            self.write_errors,
+            self.restarts
+        );
+        HubMessage::try_from_str(HUB_HEALTH, &data).unwrap()
+    }
+}
+
+impl TryFrom<&HubMessage> for NodeHealth {
+    type Error = String;
+
+    fn try_from(message: &HubMessage) -> Result<Self, Self::Error> {
+        let data = message.data.as_str();
+        if message.channel.as_str() != HUB_HEALTH {
+            return Err(format!(
+                "Message from channel {} is not a node health report",
+                message.channel.as_str()
+            ));
+        }
+        let invalid = || format!("Invalid node health {}", data);
+        let fields: Vec<_> = data.split(',').collect();
+        let [node, alive, last_read, last_write, write_errors, restarts] = fields[..] else {
+            return Err(invalid());
+        };
+        let elapsed = |millis: &str| -> Result<Option<Duration>, String> {
+            if millis.is_empty() {
+                return Ok(None);
+            }
+            let millis = millis.parse().map_err(|_| invalid())?;
+            Ok(Some(Duration::from_millis(millis)))
+        };
+        Ok(Self {
+            node: NodeId::try_from(node)?,
+            alive: match alive {
+                UP => true,
+                DOWN => false,
+                _ => return Err(invalid()),
+            },
+            since_last_read: elapsed(last_read)?,
+            since_last_write: elapsed(last_write)?,
+            write_errors: write_errors.parse().map_err(|_| invalid())?,
+            restarts: restarts.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+struct TrackerState {
+    alive: bool,
+    last_read: Option<Instant>,
+    last_write: Option<Instant>,
+    write_errors: u64,
+    restarts: u64,
+}
+
+/// `HealthTracker` records the activity of a hub node
+#[derive(Debug, Default)]
+pub(crate) struct HealthTracker {
+    state: Mutex<TrackerState>,
+}
+
+impl HealthTracker {
+    fn state(&self) -> MutexGuard<'_, TrackerState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn read(&self) {
+        self.state().last_read = Some(Instant::now());
+    }
+
+    fn write(&self, succeeded: bool) {
+        let mut state = self.state();
+        if succeeded {
+            state.last_write = Some(Instant::now());
+        } else {
+            state.write_errors += 1;
+        }
+    }
+
+    pub(crate) fn set_alive(&self, alive: bool) {
+        self.state().alive = alive;
+    }
+
+    fn restarted(&self) {
+        let mut state = self.state();
+        state.alive = true;
+        state.restarts += 1;
+    }
+
+    /// Returns the current health of `node`
+    pub(crate) fn health(&self, node: &NodeId) -> NodeHealth {
+        let state = self.state();
+        NodeHealth {
+            node: node.clone(),
+            alive: state.alive,
+            since_last_read: state.last_read.map(|instant| instant.elapsed()),
+            since_last_write: state.last_write.map(|instant| instant.elapsed()),
+            write_errors: state.write_errors,
+            restarts: state.restarts,
+        }
+    }
+}
+
+/// `MonitoredNode` wraps a hub node and records its activity in a `HealthTracker`.
+///
+/// Messages received by the node are forwarded to the hub by an additional task, which records
+/// the read and tags the messages with the node as their origin. Another task waits for the
+/// tasks of the node, and marks the node as down once one of them exits. With a restart delay,
+/// the node is then started again, and subscribed again to the channels it was subscribed to.
+/// Nodes are restarted by calling `start` again once their tasks are stopped. Tasks left by a
+/// previous start (e.g. before the hub was stopped) are stopped as well before the node is
+/// started. A node failing to
+/// start with `AlreadyExists` can't be started twice, and stays down without further attempts.
+/// With a retry policy, `send`, `subscribe` and `list_channels` calls to the node time out and
+/// are retried, so a hung node fails its callers instead of stalling them.
+#[derive(Debug)]
+pub(crate) struct MonitoredNode {
+    id: NodeId,
+    node: Arc<dyn NotificationHub>,
+    health: Arc<HealthTracker>,
+    subscriptions: Arc<Mutex<HashSet<HubChannelName>>>,
+    node_tasks: Arc<Mutex<Vec<AbortHandle>>>,
+    restart_delay: Option<Duration>,
+    retry: Option<RetryPolicy>,
+}
+
+impl MonitoredNode {
+    pub(crate) fn new(
+        id: NodeId,
+        node: Arc<dyn NotificationHub>,
+        health: Arc<HealthTracker>,
+        restart_delay: Option<Duration>,
+        retry: Option<RetryPolicy>,
+    ) -> Self {
+        Self {
+            id,
+            node,
+            health,
+            subscriptions: Arc::new(Mutex::new(HashSet::new())),
+            node_tasks: Arc::new(Mutex::new(Vec::new())),
+            restart_delay,
+            retry,
+        }
+    }
+
+    fn subscriptions(&self) -> MutexGuard<'_, HashSet<HubChannelName>> {
+        self.subscriptions.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Aborts the node tasks of a previous start, and waits until they are stopped
+    async fn stop_node_tasks(&self) {
+        let tasks: Vec<_> = lock(&self.node_tasks).drain(..).collect();
+        for task in &tasks {
+            task.abort();
+        }
+        while !tasks.iter().all(|task| task.is_finished()) {
+            tokio::task::yield_now().await;
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// Records the tasks of the started node
+fn record(node_tasks: &Mutex<Vec<AbortHandle>>, tasks: &NodeTasks) {
+    *lock(node_tasks) = tasks.iter().map(|task| task.abort_handle()).collect();
+}
+
+#[async_trait]
+impl NotificationHub for MonitoredNode {
+    async fn send(&self, message: HubMessage) -> Result<(), std::io::Error> {
+        let result = match self.retry {
+            Some(retry) => retry.run(|| self.node.send(message.clone())).await,
+            None => self.node.send(message).await,
+        };
+        self.health.write(result.is_ok());
+        result
+    }
+
+    async fn start(
+        &self,
+        sender: Option<broadcast::Sender<HubMessage>>,
+    ) -> Result<NodeTasks, std::io::Error> {
+        let Some(hub_sender) = sender else {
+            return self.node.start(None).await;
+        };
+        self.stop_node_tasks().await;
+        let (node_sender, mut node_receiver) = broadcast::channel(CHANNEL_CAPACITY);
+        let node_tasks = self.node.start(Some(node_sender.clone())).await?;
+        record(&self.node_tasks, &node_tasks);
+        self.health.set_alive(true);
+
+        let health = Arc::clone(&self.health);
+        let origin = self.id.clone();
+        let forwarder = tokio::spawn(async move {
+            loop {
+                match node_receiver.recv().await {
+                    Ok(mut message) => {
+                        health.read();
+                        message.origin = Some(origin.clone());
+                        // hub may not be listening yet
+                        let _ = hub_sender.send(message);
+                    }
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Monitored node lagged. {} messages dropped", skipped);
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+        let keeper = tokio::spawn(keep_alive(
+            Arc::clone(&self.node),
+            Arc::clone(&self.health),
+            Arc::clone(&self.subscriptions),
+            Arc::clone(&self.node_tasks),
+            self.restart_delay,
+            node_sender,
+            node_tasks,
+        ));
+        Ok(vec![forwarder, keeper])
+    }
+
+    async fn list_channels(&self) -> Result<Vec<HubChannelName>, std::io::Error> {
+        match self.retry {
+            Some(retry) => retry.run(|| self.node.list_channels()).await,
+            None => self.node.list_channels().await,
+        }
+    }
+
+    async fn subscribe(&self, channel: HubChannelName) -> Result<(), std::io::Error> {
+        match self.retry {
+            Some(retry) => retry.run(|| self.node.subscribe(channel.clone())).await?,
+            None => self.node.subscribe(channel.clone()).await?,
+        }
+        self.subscriptions().insert(channel);
+        Ok(())
+    }
+
+    async fn unsubscribe(&self, channel: HubChannelName) -> Result<(), std::io::Error> {
+        self.subscriptions().remove(&channel);
+        self.node.unsubscribe(channel).await
+    }
+}
+
+// Tasks of a node, aborted when dropped so that stopping the hub stops the node as well
+struct AbortOnDrop(NodeTasks);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        for task in &self.0 {
+            task.abort();
+        }
+    }
+}
+
+// Waits for the tasks of the node, and restarts the node after `restart_delay` once one of
+// them exits. The other tasks are stopped first, so the node releases its resources (e.g. the
+// read half of a port) before being started again. Returns when the node exits and can't be
+// restarted, or has no tasks to wait for.
+async fn keep_alive(
+    node: Arc<dyn NotificationHub>,
+    health: Arc<HealthTracker>,
+    subscriptions: Arc<Mutex<HashSet<HubChannelName>>>,
+    node_tasks: Arc<Mutex<Vec<AbortHandle>>>,
+    restart_delay: Option<Duration>,
+    node_sender: broadcast::Sender<HubMessage>,
+    tasks: NodeTasks,
+) {
+    let mut tasks = AbortOnDrop(tasks);
+    while !tasks.0.is_empty() {
+        select_all(tasks.0.iter_mut()).await;
+        for task in tasks.0.drain(..) {
+            // the task that exited was already awaited
+            if !task.is_finished() {
+                task.abort();
+                let _ = task.await;
+            }
+        }
+        health.set_alive(false);
+        let Some(restart_delay) = restart_delay else {
+            return;
+        };
+        tasks.0 = loop {
+            warn!("Restarting hub node {:?}", node);
+            tokio::time::sleep(restart_delay).await;
+            match node.start(Some(node_sender.clone())).await {
+                Ok(tasks) => {
+                    record(&node_tasks, &tasks);
+                    break tasks;
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    error!("Hub node {:?} can't be restarted: {:?}", node, e);
+                    return;
+                }
+                Err(e) => error!("Failed to restart hub node {:?}: {:?}", node, e),
+            }
+        };
+        health.restarted();
+        let channels: Vec<_> = lock(&subscriptions).iter().cloned().collect();
+        for channel in channels {
+            if let Err(e) = node.subscribe(channel.clone()).await {
+                error!(
+                    "Failed to subscribe restarted node to {:?}: {:?}",
+                    channel, e
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::hub::mock::MockNode;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn channel(name: &str) -> HubChannelName {
+        HubChannelName::try_from(name).unwrap()
+    }
+
+    fn node_id(name: &str) -> NodeId {
+        NodeId::try_from(name).unwrap()
+    }
+
+    // Node whose read loop exits right away the first time it is started
+    #[derive(Debug, Default)]
+    struct FlakyNode {
+        inner: MockNode,
+        starts: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl NotificationHub for Arc<FlakyNode> {
+        async fn send(&self, message: HubMessage) -> Result<(), std::io::Error> {
+            self.inner.send(message).await
+        }
+        async fn start(
+            &self,
+            sender: Option<broadcast::Sender<HubMessage>>,
+        ) -> Result<NodeTasks, std::io::Error> {
+            self.inner.start(sender).await?;
+            if self.starts.fetch_add(1, Ordering::SeqCst) == 0 {
+                Ok(vec![tokio::spawn(async {})])
+            } else {
+                Ok(vec![tokio::spawn(std::future::pending::<()>())])
+            }
+        }
+        async fn list_channels(&self) -> Result<Vec<HubChannelName>, std::io::Error> {
+            self.inner.list_channels().await
+        }
+        async fn subscribe(&self, channel: HubChannelName) -> Result<(), std::io::Error> {
+            self.inner.subscribe(channel).await
+        }
+    }
+
+    // Node whose read loop exits right away, and that can only be started once
+    #[derive(Debug, Default)]
+    struct OneShotNode {
+        starts: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl NotificationHub for Arc<OneShotNode> {
+        async fn send(&self, _message: HubMessage) -> Result<(), std::io::Error> {
+            Ok(())
+        }
+        async fn start(
+            &self,
+            _sender: Option<broadcast::Sender<HubMessage>>,
+        ) -> Result<NodeTasks, std::io::Error> {
+            if self.starts.fetch_add(1, Ordering::SeqCst) > 0 {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::AlreadyExists,
+                    "Node already started",
+                ));
+            }
+            Ok(vec![tokio::spawn(async {})])
+        }
+        async fn list_channels(&self) -> Result<Vec<HubChannelName>, std::io::Error> {
+            Ok(Vec::new())
+        }
+        async fn subscribe(&self, _channel: HubChannelName) -> Result<(), std::io::Error> {
+            Ok(())
+        }
+    }
+
+    // Node never completing its operations, as a hung serial port
+    #[derive(Debug)]
+    struct HungNode;
+
+    #[async_trait]
+    impl NotificationHub for HungNode {
+        async fn send(&self, _message: HubMessage) -> Result<(), std::io::Error> {
+            std::future::pending().await
+        }
+        async fn start(
+            &self,
+            _sender: Option<broadcast::Sender<HubMessage>>,
+        ) -> Result<NodeTasks, std::io::Error> {
+            Ok(NodeTasks::new())
+        }
+        async fn list_channels(&self) -> Result<Vec<HubChannelName>, std::io::Error> {
+            std::future::pending().await
+        }
+        async fn subscribe(&self, _channel: HubChannelName) -> Result<(), std::io::Error> {
+            std::future::pending().await
+        }
+    }
+
+    #[test]
+    fn test_health_message_conversion() {
+        let health = NodeHealth {
+            node: node_id("motors"),
+            alive: false,
+            since_last_read: Some(Duration::from_millis(250)),
+            since_last_write: None,
+            write_errors: 3,
+            restarts: 1,
+        };
+        let message = health.to_message();
+        assert_eq!(message.data.as_str(), "motors,down,250,,3,1");
+        assert_eq!(NodeHealth::try_from(&message).unwrap(), health);
+
+        let message = HubMessage::try_from_str(HUB_HEALTH, "motors,down,250").unwrap();
+        assert!(NodeHealth::try_from(&message).is_err());
+        let message = HubMessage::try_from_str("status", "motors,down,250,,3,1").unwrap();
+        assert!(NodeHealth::try_from(&message).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_monitored_node_records_activity() {
+        let node = Arc::new(MockNode::default());
+        let health = Arc::new(HealthTracker::default());
+        let monitored = MonitoredNode::new(
+            node_id("imu"),
+            Arc::new(node.clone()),
+            health.clone(),
+            None,
+            None,
+        );
+
+        let (hub_sender, mut hub_receiver) = broadcast::channel(10);
+        monitored.start(Some(hub_sender)).await.unwrap();
+        assert!(health.health(&node_id("imu")).since_last_read.is_none());
+
+        node.receive(HubMessage::try_from_str("imu", "1").unwrap());
+        assert_eq!(
+            hub_receiver.recv().await.unwrap().origin,
+            Some(node_id("imu"))
+        );
+        monitored
+            .send(HubMessage::try_from_str("cmd", "1").unwrap())
+            .await
+            .unwrap();
+
+        let report = health.health(&node_id("imu"));
+        assert!(report.alive);
+        assert!(report.since_last_read.is_some());
+        assert!(report.since_last_write.is_some());
+        assert_eq!(report.write_errors, 0);
+    }
+
+    #[tokio::test]
+    async fn test_exited_node_is_restarted_and_resubscribed() {
+        let node = Arc::new(FlakyNode::default());
+        let health = Arc::new(HealthTracker::default());
+        let monitored = MonitoredNode::new(
+            node_id("imu"),
+            Arc::new(node.clone()),
+            health.clone(),
+            Some(Duration::from_millis(10)),
+            None,
+        );
+        monitored.subscribe(channel("imu")).await.unwrap();
+
+        let (hub_sender, _hub_receiver) = broadcast::channel(10);
+        monitored.start(Some(hub_sender)).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        assert_eq!(node.starts.load(Ordering::SeqCst), 2);
+        let report = health.health(&node_id("imu"));
+        assert!(report.alive);
+        assert_eq!(report.restarts, 1);
+        assert_eq!(
+            *node.inner.subscribed.lock().await,
+            vec![channel("imu"), channel("imu")]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_exited_node_without_restart_is_down() {
+        let node = Arc::new(FlakyNode::default());
+        let health = Arc::new(HealthTracker::default());
+        let monitored = MonitoredNode::new(
+            node_id("imu"),
+            Arc::new(node.clone()),
+            health.clone(),
+            None,
+            None,
+        );
+
+        let (hub_sender, _hub_receiver) = broadcast::channel(10);
+        let tasks = monitored.start(Some(hub_sender)).await.unwrap();
+        for task in tasks.into_iter().skip(1) {
+            task.await.unwrap();
+        }
+        assert!(!health.health(&node_id("imu")).alive);
+        assert_eq!(node.starts.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_node_started_once_is_not_restarted() {
+        let node = Arc::new(OneShotNode::default());
+        let health = Arc::new(HealthTracker::default());
+        let monitored = MonitoredNode::new(
+            node_id("imu"),
+            Arc::new(node.clone()),
+            health.clone(),
+            Some(Duration::from_millis(10)),
+            None,
+        );
+
+        let (hub_sender, _hub_receiver) = broadcast::channel(10);
+        let tasks = monitored.start(Some(hub_sender)).await.unwrap();
+        for task in tasks.into_iter().skip(1) {
+            tokio::time::timeout(Duration::from_secs(1), task)
+                .await
+                .unwrap()
+                .unwrap();
+        }
+        assert_eq!(node.starts.load(Ordering::SeqCst), 2);
+        let report = health.health(&node_id("imu"));
+        assert!(!report.alive);
+        assert_eq!(report.restarts, 0);
+    }
+
+    #[tokio::test]
+    async fn test_hung_node_operations_time_out() {
+        use crate::config::RetryPolicyBuilder;
+
+        let health = Arc::new(HealthTracker::default());
+        let retry = RetryPolicyBuilder::new()
+            .timeout(Duration::from_millis(10))
+            .retries(1)
+            .backoff(Duration::from_millis(5))
+            .build()
+            .unwrap();
+        let monitored = MonitoredNode::new(
+            node_id("imu"),
+            Arc::new(HungNode),
+            health.clone(),
+            None,
+            Some(retry),
+        );
+
+        let error = monitored
+            .send(HubMessage::try_from_str("cmd", "1").unwrap())
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
+        assert_eq!(health.health(&node_id("imu")).write_errors, 1);
+
+        let error = monitored.subscribe(channel("imu")).await.unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::TimedOut);
+        assert!(monitored.subscriptions().is_empty());
+        assert!(monitored.list_channels().await.is_err());
+    }
+}
@@ -0,0 +1,256 @@
+use dashmap::DashMap;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::config::HubOptions;
+use crate::models::hub::{HubChannelName, HubMessage, HubTimestamp};
+
+type HistoryBuffer = Arc<Mutex<VecDeque<HubMessage>>>;
+
+/// Messages of the channel history replayed to a new subscriber
+/// - `Replay::Last` -> Last messages, up to the given number.
+/// - `Replay::Since` -> Messages created within the given time window, based on their
+///   timestamp. The window is measured with the monotonic clock for messages timestamped by
+///   this hub, so wall clock jumps don't change the replayed messages.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Replay {
+    Last(usize),
+    Since(Duration),
+}
+
+impl Replay {
+    // Selects the replayed messages of a channel history, oldest first
+    fn select(&self, buffer: &VecDeque<HubMessage>) -> VecDeque<HubMessage> {
+        match self {
+            Replay::Last(n) => {
+                let skip = buffer.len().saturating_sub(*n);
+                buffer.iter().skip(skip).cloned().collect()
+            }
+            Replay::Since(window) => {
+                let now = HubTimestamp::now();
+                let window = window.as_secs_f64();
+                buffer
+                    .iter()
+                    .filter(|message| now.elapsed_since(&message.timestamp) <= window)
+                    .cloned()
+                    .collect()
+            }
+        }
+    }
+}
+
+/// `HubHistory` keeps the last `depth` messages delivered in every channel, so that
+/// late subscribers can be replayed recent data before live messages. Channels in `depths`
+/// keep their own number of messages.
+///
+/// With `retain_last`, the last message of every channel is kept and replayed to new
+/// subscribers not requesting a replay. Requested replays are honoured, even if they select
+/// no message.
+///
+/// Messages are recorded and delivered while holding the channel buffer lock, and
+/// subscriptions with replay take the same lock. A message is therefore either part of the
+/// replayed history or delivered live to the new subscriber, but never both.
+/// In channels with depth 0, history is disabled and messages are delivered without locking.
+#[derive(Debug)]
+pub(crate) struct HubHistory {
+    depth: usize,
+    depths: HashMap<HubChannelName, usize>,
+    retain_last: bool,
+    buffers: DashMap<HubChannelName, HistoryBuffer>,
+}
+
+fn lock(buffer: &HistoryBuffer) -> MutexGuard<'_, VecDeque<HubMessage>> {
+    buffer.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl HubHistory {
+    pub(crate) fn new(depth: usize) -> Self {
+        Self {
+            depth,
+            depths: HashMap::new(),
+            retain_last: false,
+            buffers: DashMap::new(),
+        }
+    }
+
+    pub(crate) fn from_options(options: &HubOptions) -> Self {
+        Self {
+            depth: options.history_depth(),
+            depths: options.channel_history_depths().clone(),
+            retain_last: options.retain_last_message(),
+            buffers: DashMap::new(),
+        }
+    }
+
+    // Number of messages kept in channel
+    fn depth(&self, channel: &HubChannelName) -> usize {
+        let depth = self.depths.get(channel).copied().unwrap_or(self.depth);
+        depth.max(self.retain_last as usize)
+    }
+
+    fn buffer(&self, channel: &HubChannelName) -> HistoryBuffer {
+        if let Some(buffer) = self.buffers.get(channel) {
+            return Arc::clone(buffer.value());
+        }
+        Arc::clone(self.buffers.entry(channel.clone()).or_default().value())
+    }
+
+    /// Delivers message with `deliver`, and records it in the history of its channel
+    pub(crate) fn record(&self, message: HubMessage, deliver: impl FnOnce(HubMessage)) {
+        let depth = self.depth(&message.channel);
+        if depth == 0 {
+            return deliver(message);
+        }
+        let buffer = self.buffer(&message.channel);
+        let mut buffer = lock(&buffer);
+        deliver(message.clone());
+        if buffer.len() >= depth {
+            buffer.pop_front();
+        }
+        buffer.push_back(message);
+    }
+
+    /// Subscribes to channel with `subscribe`, and returns the subscription together with
+    /// the messages of the channel history selected by `replay`, oldest first. Without
+    /// `replay`, the retained message of the channel is returned, if kept
+    pub(crate) fn replay<T>(
+        &self,
+        channel: &HubChannelName,
+        replay: Option<Replay>,
+        subscribe: impl FnOnce() -> T,
+    ) -> (T, VecDeque<HubMessage>) {
+        let replays = match replay {
+            None => self.retain_last,
+            Some(replay) => replay != Replay::Last(0),
+        };
+        if self.depth(channel) == 0 || !replays {
+            return (subscribe(), VecDeque::new());
+        }
+        let buffer = self.buffer(channel);
+        let buffer = lock(&buffer);
+        let subscription = subscribe();
+        let replayed = match replay {
+            Some(replay) => replay.select(&buffer),
+            None => buffer.back().cloned().into_iter().collect(),
+        };
+        (subscription, replayed)
+    }
+
+    /// Removes the history of channel
+    pub(crate) fn remove(&self, channel: &HubChannelName) {
+        self.buffers.remove(channel);
+    }
+
+    /// Returns the last message recorded in the channel, if any
+    pub(crate) fn last(&self, channel: &HubChannelName) -> Option<HubMessage> {
+        let buffer = self.buffers.get(channel)?;
+        let buffer = lock(buffer.value());
+        buffer.back().cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(history: &HubHistory, data: &str) -> usize {
+        let mut delivered = 0;
+        history.record(HubMessage::try_from_str("channel", data).unwrap(), |_| {
+            delivered += 1
+        });
+        delivered
+    }
+
+    #[test]
+    fn test_history_is_bounded() {
+        let history = HubHistory::new(3);
+        for i in 0..5 {
+            assert_eq!(record(&history, &i.to_string()), 1);
+        }
+
+        let channel = HubChannelName::try_from("channel").unwrap();
+        let (_, replayed) = history.replay(&channel, Some(Replay::Last(10)), || ());
+        let data: Vec<_> = replayed.iter().map(|m| m.data.as_str()).collect();
+        assert_eq!(data, vec!["2", "3", "4"]);
+
+        let (_, replayed) = history.replay(&channel, Some(Replay::Last(2)), || ());
+        let data: Vec<_> = replayed.iter().map(|m| m.data.as_str()).collect();
+        assert_eq!(data, vec!["3", "4"]);
+        assert_eq!(history.last(&channel).unwrap().data.as_str(), "4");
+    }
+
+    #[test]
+    fn test_disabled_history() {
+        let history = HubHistory::new(0);
+        assert_eq!(record(&history, "1"), 1);
+
+        let channel = HubChannelName::try_from("channel").unwrap();
+        let (_, replayed) = history.replay(&channel, Some(Replay::Last(10)), || ());
+        assert!(replayed.is_empty());
+        assert!(history.buffers.is_empty());
+        assert!(history.last(&channel).is_none());
+    }
+
+    #[test]
+    fn test_channel_depth() {
+        let options = crate::config::HubOptionsBuilder::new()
+            .history_depth(1)
+            .channel_history_depth("pose", 3)
+            .channel_history_depth("channel", 0)
+            .build()
+            .unwrap();
+        let history = HubHistory::from_options(&options);
+        for i in 0..5 {
+            history.record(
+                HubMessage::try_from_str("pose", &i.to_string()).unwrap(),
+                |_| (),
+            );
+            record(&history, &i.to_string());
+        }
+        assert!(history
+            .buffers
+            .get(&HubChannelName::try_from("channel").unwrap())
+            .is_none());
+        let pose = HubChannelName::try_from("pose").unwrap();
+        let (_, replayed) = history.replay(&pose, Some(Replay::Last(10)), || ());
+        assert_eq!(replayed.len(), 3);
+    }
+
+    #[test]
+    fn test_replay_since() {
+        let history = HubHistory::new(10);
+        let mut old = HubMessage::try_from_str("channel", "old").unwrap();
+        old.timestamp -= 60.0;
+        history.record(old, |_| ());
+        record(&history, "new");
+
+        let channel = HubChannelName::try_from("channel").unwrap();
+        let (_, replayed) =
+            history.replay(&channel, Some(Replay::Since(Duration::from_secs(5))), || ());
+        let data: Vec<_> = replayed.iter().map(|m| m.data.as_str()).collect();
+        assert_eq!(data, vec!["new"]);
+    }
+
+    #[test]
+    fn test_retain_last() {
+        let options = crate::config::HubOptionsBuilder::new()
+            .retain_last_message(true)
+            .build()
+            .unwrap();
+        let history = HubHistory::from_options(&options);
+        record(&history, "1");
+        record(&history, "2");
+
+        let channel = HubChannelName::try_from("channel").unwrap();
+        let (_, replayed) = history.replay(&channel, None, || ());
+        let data: Vec<_> = replayed.iter().map(|m| m.data.as_str()).collect();
+        assert_eq!(data, vec!["2"]);
+
+        // requested replays are honoured
+        let (_, replayed) = history.replay(&channel, Some(Replay::Last(0)), || ());
+        assert!(replayed.is_empty());
+        let (_, replayed) = history.replay(&channel, Some(Replay::Last(5)), || ());
+        assert_eq!(replayed.len(), 1);
+    }
+}
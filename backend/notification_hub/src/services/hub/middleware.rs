@@ -0,0 +1,275 @@
+use log::warn;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::RemapRules;
+use crate::models::hub::{HubChannelName, HubMessage};
+use crate::ports::MessageMiddleware;
+
+/// `MiddlewarePipeline` is the ordered list of middlewares applied by the hub before delivering
+/// messages. Global middlewares are applied first, followed by the middlewares of the message
+/// channel, as named after the global middlewares.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct MiddlewarePipeline {
+    global: Vec<Arc<dyn MessageMiddleware>>,
+    channels: HashMap<HubChannelName, Vec<Arc<dyn MessageMiddleware>>>,
+}
+
+impl MiddlewarePipeline {
+    pub(crate) fn add(&mut self, middleware: Arc<dyn MessageMiddleware>) {
+        self.global.push(middleware);
+    }
+
+    pub(crate) fn add_to_channel(
+        &mut self,
+        channel: HubChannelName,
+        middleware: Arc<dyn MessageMiddleware>,
+    ) {
+        self.channels.entry(channel).or_default().push(middleware);
+    }
+
+    /// Applies middlewares to message. Returns `None` if the message is blocked
+    pub(crate) fn process(&self, message: HubMessage) -> Option<HubMessage> {
+        let message = self
+            .global
+            .iter()
+            .try_fold(message, |message, middleware| middleware.process(message))?;
+        match self.channels.get(&message.channel) {
+            Some(middlewares) => middlewares
+                .iter()
+                .try_fold(message, |message, middleware| middleware.process(message)),
+            None => Some(message),
+        }
+    }
+}
+
+/// Drops messages of a channel arriving less than `min_interval` after the last message
+/// let through in that channel
+#[derive(Debug)]
+pub struct RateLimit {
+    min_interval: Duration,
+    last: Mutex<HashMap<HubChannelName, Instant>>,
+}
+
+impl RateLimit {
+    pub fn new(min_interval: Duration) -> Self {
+        Self {
+            min_interval,
+            last: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl MessageMiddleware for RateLimit {
+    fn process(&self, message: HubMessage) -> Option<HubMessage> {
+        let now = Instant::now();
+        let mut last = self.last.lock().unwrap_or_else(|e| e.into_inner());
+        match last.get(&message.channel) {
+            Some(previous) if now.duration_since(*previous) < self.min_interval => None,
+            _ => {
+                last.insert(message.channel.clone(), now);
+                Some(message)
+            }
+        }
+    }
+}
+
+/// Drops messages of a channel already let through within the last `window`, so that
+/// subscribers don't receive the same sample twice when several bridged hub nodes (e.g.
+/// serial and WebSocket) carry the same channel.
+///
+/// Messages are identified by a hash of their data, or of the comma separated field `field`
+/// holding a sequence id. Messages without the field are let through.
+#[derive(Debug)]
+pub struct Deduplicate {
+    window: Duration,
+    field: Option<usize>,
+    seen: Mutex<HashMap<HubChannelName, VecDeque<(Instant, u64)>>>,
+}
+
+impl Deduplicate {
+    /// Identifies messages by their data
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            field: None,
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Identifies messages by the sequence id in data field `field`
+    pub fn by_field(window: Duration, field: usize) -> Self {
+        Self {
+            field: Some(field),
+            ..Self::new(window)
+        }
+    }
+
+    fn key(&self, message: &HubMessage) -> Option<u64> {
+        let data = message.data.as_str();
+        let key = match self.field {
+            Some(field) => data.split(',').nth(field)?.trim(),
+            None => data,
+        };
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        Some(hasher.finish())
+    }
+}
+
+impl MessageMiddleware for Deduplicate {
+    fn process(&self, message: HubMessage) -> Option<HubMessage> {
+        let Some(key) = self.key(&message) else {
+            return Some(message);
+        };
+        let now = Instant::now();
+        let mut seen = self.seen.lock().unwrap_or_else(|e| e.into_inner());
+        let keys = seen.entry(message.channel.clone()).or_default();
+        while keys
+            .front()
+            .is_some_and(|(instant, _)| now.duration_since(*instant) >= self.window)
+        {
+            keys.pop_front();
+        }
+        if keys.iter().any(|(_, seen_key)| *seen_key == key) {
+            return None;
+        }
+        keys.push_back((now, key));
+        Some(message)
+    }
+}
+
+type Validator = dyn Fn(&HubMessage) -> Result<(), String> + Send + Sync;
+
+/// Blocks messages rejected by a validation function
+pub struct Validate {
+    validator: Box<Validator>,
+}
+
+impl Validate {
+    pub fn new(
+        validator: impl Fn(&HubMessage) -> Result<(), String> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            validator: Box::new(validator),
+        }
+    }
+}
+
+impl std::fmt::Debug for Validate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Validate").finish_non_exhaustive()
+    }
+}
+
+impl MessageMiddleware for Validate {
+    fn process(&self, message: HubMessage) -> Option<HubMessage> {
+        match (self.validator)(&message) {
+            Ok(()) => Some(message),
+            Err(e) => {
+                warn!(
+                    "Invalid message blocked in channel {}: {}",
+                    message.channel.as_str(),
+                    e
+                );
+                None
+            }
+        }
+    }
+}
+
+/// Renames the channel of messages with the ingest direction of `RemapRules`
+#[derive(Debug)]
+pub struct Remap {
+    rules: RemapRules,
+}
+
+impl Remap {
+    pub fn new(rules: RemapRules) -> Self {
+        Self { rules }
+    }
+}
+
+impl MessageMiddleware for Remap {
+    fn process(&self, mut message: HubMessage) -> Option<HubMessage> {
+        message.channel = self.rules.ingest(&message.channel);
+        Some(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RemapRulesBuilder;
+
+    fn message(channel: &str, data: &str) -> HubMessage {
+        HubMessage::try_from_str(channel, data).unwrap()
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let rate_limit = RateLimit::new(Duration::from_secs(3600));
+        assert!(rate_limit.process(message("imu", "1")).is_some());
+        assert!(rate_limit.process(message("imu", "2")).is_none());
+        assert!(rate_limit.process(message("odometry", "1")).is_some());
+    }
+
+    #[test]
+    fn test_deduplicate() {
+        let deduplicate = Deduplicate::new(Duration::from_secs(3600));
+        assert!(deduplicate.process(message("imu", "1,2,3")).is_some());
+        assert!(deduplicate.process(message("imu", "1,2,3")).is_none());
+        assert!(deduplicate.process(message("imu", "1,2,4")).is_some());
+        assert!(deduplicate.process(message("odometry", "1,2,3")).is_some());
+
+        let expired = Deduplicate::new(Duration::ZERO);
+        assert!(expired.process(message("imu", "1")).is_some());
+        assert!(expired.process(message("imu", "1")).is_some());
+    }
+
+    #[test]
+    fn test_deduplicate_by_sequence_id() {
+        let deduplicate = Deduplicate::by_field(Duration::from_secs(3600), 0);
+        assert!(deduplicate.process(message("gps", "17,47.39")).is_some());
+        assert!(deduplicate.process(message("gps", "17, 47.38")).is_none());
+        assert!(deduplicate.process(message("gps", "18,47.38")).is_some());
+
+        let missing_field = Deduplicate::by_field(Duration::from_secs(3600), 2);
+        assert!(missing_field.process(message("gps", "17,47.39")).is_some());
+        assert!(missing_field.process(message("gps", "17,47.39")).is_some());
+    }
+
+    #[test]
+    fn test_validate() {
+        let validate = Validate::new(|message| {
+            if message.data.as_str().split(',').count() == 3 {
+                Ok(())
+            } else {
+                Err("expected 3 axes".to_string())
+            }
+        });
+        assert!(validate.process(message("imu", "1,2,3")).is_some());
+        assert!(validate.process(message("imu", "1,2")).is_none());
+    }
+
+    #[test]
+    fn test_pipeline_order() {
+        let rules = RemapRulesBuilder::new()
+            .alias("imu_raw", "sensors/imu")
+            .build()
+            .unwrap();
+        let mut pipeline = MiddlewarePipeline::default();
+        pipeline.add(Arc::new(Remap::new(rules)));
+        pipeline.add_to_channel(
+            HubChannelName::try_from("sensors/imu").unwrap(),
+            Arc::new(Validate::new(|_| Err("blocked".to_string()))),
+        );
+
+        assert!(pipeline.process(message("imu_raw", "1")).is_none());
+        let processed = pipeline.process(message("odometry", "1")).unwrap();
+        assert_eq!(processed.channel.as_str(), "odometry");
+    }
+}
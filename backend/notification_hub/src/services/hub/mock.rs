@@ -0,0 +1,87 @@
+use async_trait::async_trait;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::{broadcast, Mutex};
+
+use crate::models::hub::{HubChannelName, HubMessage};
+use crate::ports::{NodeTasks, NotificationHub};
+
+/// Hub node recording every call, used to test hub services without transports
+#[derive(Debug, Default)]
+pub(crate) struct MockNode {
+    pub(crate) sent: Mutex<Vec<HubMessage>>,
+    pub(crate) subscribed: Mutex<Vec<HubChannelName>>,
+    pub(crate) unsubscribed: Mutex<Vec<HubChannelName>>,
+    pub(crate) channels: Mutex<Vec<HubChannelName>>,
+    // subscriptions and unsubscriptions wait while the gate is locked. Subscriptions fail if
+    // `fail_subscribe` is set
+    pub(crate) gate: Mutex<()>,
+    pub(crate) fail_subscribe: AtomicBool,
+    sender: std::sync::Mutex<Option<broadcast::Sender<HubMessage>>>,
+}
+
+impl MockNode {
+    /// Emulates a message received by the node, forwarding it to the hub once started
+    pub(crate) fn receive(&self, message: HubMessage) {
+        if let Some(sender) = self.sender.lock().unwrap().as_ref() {
+            let _ = sender.send(message);
+        }
+    }
+}
+
+#[async_trait]
+impl NotificationHub for MockNode {
+    async fn send(&self, message: HubMessage) -> Result<(), std::io::Error> {
+        self.sent.lock().await.push(message);
+        Ok(())
+    }
+    async fn list_channels(&self) -> Result<Vec<HubChannelName>, std::io::Error> {
+        Ok(self.channels.lock().await.clone())
+    }
+    async fn start(
+        &self,
+        sender: Option<broadcast::Sender<HubMessage>>,
+    ) -> Result<NodeTasks, std::io::Error> {
+        *self.sender.lock().unwrap() = sender;
+        Ok(NodeTasks::new())
+    }
+    async fn subscribe(&self, channel: HubChannelName) -> Result<(), std::io::Error> {
+        let _gate = self.gate.lock().await;
+        if self.fail_subscribe.load(Ordering::Relaxed) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::BrokenPipe,
+                "Subscription failed",
+            ));
+        }
+        self.subscribed.lock().await.push(channel);
+        Ok(())
+    }
+    async fn unsubscribe(&self, channel: HubChannelName) -> Result<(), std::io::Error> {
+        let _gate = self.gate.lock().await;
+        self.unsubscribed.lock().await.push(channel);
+        Ok(())
+    }
+}
+
+// Shared mock, so that tests can inspect a node owned by the hub
+#[async_trait]
+impl NotificationHub for Arc<MockNode> {
+    async fn send(&self, message: HubMessage) -> Result<(), std::io::Error> {
+        self.as_ref().send(message).await
+    }
+    async fn list_channels(&self) -> Result<Vec<HubChannelName>, std::io::Error> {
+        self.as_ref().list_channels().await
+    }
+    async fn start(
+        &self,
+        sender: Option<broadcast::Sender<HubMessage>>,
+    ) -> Result<NodeTasks, std::io::Error> {
+        self.as_ref().start(sender).await
+    }
+    async fn subscribe(&self, channel: HubChannelName) -> Result<(), std::io::Error> {
+        self.as_ref().subscribe(channel).await
+    }
+    async fn unsubscribe(&self, channel: HubChannelName) -> Result<(), std::io::Error> {
+        self.as_ref().unsubscribe(channel).await
+    }
+}
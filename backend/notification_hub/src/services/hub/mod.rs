@@ -1,5 +1,40 @@
+pub mod builder;
 pub(crate) mod channel;
 pub mod controller;
+pub(crate) mod dispatch;
+pub mod events;
+pub mod federation;
+pub mod filter;
+pub mod handle;
+pub mod health;
+pub(crate) mod history;
+pub mod middleware;
+#[cfg(test)]
+pub(crate) mod mock;
+pub(crate) mod pause;
+pub mod receiver;
+pub(crate) mod remap;
+pub mod routing;
+pub mod rpc;
+pub mod schema;
+pub mod sender;
+pub mod stats;
+pub(crate) mod supervisor;
 pub(crate) mod user;
 
+pub use builder::HubManagerBuilder;
 pub use controller::HubManager;
+pub use events::{ChannelEvent, CHANNEL_EVENTS};
+pub use federation::{FederatedNode, FederationExport};
+pub use filter::MessageFilter;
+pub use handle::HubHandle;
+pub use health::{NodeHealth, HUB_HEALTH};
+pub use history::Replay;
+pub use middleware::{Deduplicate, RateLimit, Remap, Validate};
+pub use receiver::{HubReceiver, TypedReceiver};
+pub use routing::{HubRouting, NodeId, RoutePattern};
+pub use rpc::RpcMessage;
+pub use schema::{ChannelSchema, FieldType, HUB_DEAD_LETTER};
+pub use sender::HubSender;
+pub use stats::{ChannelStats, HubStats, HUB_STATS};
+pub use supervisor::HUB_ERRORS;
@@ -0,0 +1,145 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::models::hub::{HubChannelName, HubMessage};
+
+/// Outcome of dispatching a message while channels may be paused
+#[derive(Debug)]
+pub(crate) enum Hold {
+    /// Channel is not paused, and the message is dispatched
+    Dispatch(HubMessage),
+    /// Message is buffered until its channel is resumed
+    Buffered,
+    /// Message of a paused best effort channel, or oldest message of a full buffer when the
+    /// new message is buffered, returned to be counted as dropped
+    Dropped(HubMessage),
+}
+
+#[derive(Debug)]
+struct PausedChannel {
+    resuming: bool,
+    depth: usize,
+    buffered: VecDeque<HubMessage>,
+}
+
+/// `PausedChannels` holds the channels whose dispatch is paused with
+/// `HubManager::pause_channel`. Subscriptions of paused channels are kept.
+///
+/// Messages of paused reliable channels are buffered until the channel is resumed, while
+/// messages of best effort channels are dropped. Buffers keep up to `depth` messages, dropping
+/// the oldest ones. Buffered messages are drained in order once the channel is resumed, and
+/// new messages keep being buffered until the buffer is empty, so they aren't delivered ahead
+/// of older messages.
+#[derive(Debug, Default)]
+pub(crate) struct PausedChannels {
+    channels: Mutex<HashMap<HubChannelName, PausedChannel>>,
+}
+
+impl PausedChannels {
+    fn channels(&self) -> MutexGuard<'_, HashMap<HubChannelName, PausedChannel>> {
+        self.channels.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Pauses dispatch of `channel`, buffering up to `depth` messages
+    pub(crate) fn pause(&self, channel: HubChannelName, depth: usize) {
+        let mut channels = self.channels();
+        let paused = channels.entry(channel).or_insert_with(|| PausedChannel {
+            resuming: false,
+            depth,
+            buffered: VecDeque::new(),
+        });
+        paused.resuming = false;
+        paused.depth = depth;
+    }
+
+    /// Returns the paused channels, including channels still draining their buffer
+    pub(crate) fn paused(&self) -> Vec<HubChannelName> {
+        self.channels().keys().cloned().collect()
+    }
+
+    /// Holds `message` if its channel is paused. `reliable` channels buffer the message
+    pub(crate) fn hold(&self, message: HubMessage, reliable: bool) -> Hold {
+        let mut channels = self.channels();
+        match channels.get_mut(&message.channel) {
+            None => Hold::Dispatch(message),
+            Some(paused) if reliable || paused.resuming => {
+                paused.buffered.push_back(message);
+                if paused.buffered.len() > paused.depth {
+                    if let Some(oldest) = paused.buffered.pop_front() {
+                        return Hold::Dropped(oldest);
+                    }
+                }
+                Hold::Buffered
+            }
+            Some(_) => Hold::Dropped(message),
+        }
+    }
+
+    /// Resumes `channel`, taking the messages buffered so far. The channel is unpaused once a
+    /// call finds its buffer empty, so callers drain until `None` is returned.
+    pub(crate) fn drain(&self, channel: &HubChannelName) -> Option<Vec<HubMessage>> {
+        let mut channels = self.channels();
+        let paused = channels.get_mut(channel)?;
+        if paused.buffered.is_empty() {
+            channels.remove(channel);
+            return None;
+        }
+        paused.resuming = true;
+        Some(paused.buffered.drain(..).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(data: &str) -> HubMessage {
+        HubMessage::try_from_str("camera", data).unwrap()
+    }
+
+    #[test]
+    fn test_paused_channels() {
+        let paused = PausedChannels::default();
+        let channel = HubChannelName::try_from("camera").unwrap();
+        assert!(matches!(
+            paused.hold(message("1"), false),
+            Hold::Dispatch(_)
+        ));
+
+        paused.pause(channel.clone(), 10);
+        assert_eq!(paused.paused(), vec![channel.clone()]);
+        assert!(matches!(paused.hold(message("1"), false), Hold::Dropped(_)));
+        assert!(matches!(paused.hold(message("2"), true), Hold::Buffered));
+
+        let drained = paused.drain(&channel).unwrap();
+        assert_eq!(drained.len(), 1);
+        assert_eq!(drained[0].data.as_str(), "2");
+        // messages received while draining are buffered, whatever the QoS
+        assert!(matches!(paused.hold(message("3"), false), Hold::Buffered));
+        assert_eq!(paused.drain(&channel).unwrap()[0].data.as_str(), "3");
+        assert!(paused.drain(&channel).is_none());
+
+        assert!(paused.paused().is_empty());
+        assert!(matches!(
+            paused.hold(message("4"), false),
+            Hold::Dispatch(_)
+        ));
+    }
+
+    #[test]
+    fn test_full_buffer_drops_oldest() {
+        let paused = PausedChannels::default();
+        let channel = HubChannelName::try_from("camera").unwrap();
+        paused.pause(channel.clone(), 2);
+        assert!(matches!(paused.hold(message("1"), true), Hold::Buffered));
+        assert!(matches!(paused.hold(message("2"), true), Hold::Buffered));
+        match paused.hold(message("3"), true) {
+            Hold::Dropped(message) => assert_eq!(message.data.as_str(), "1"),
+            hold => panic!("Unexpected {:?}", hold),
+        }
+
+        let drained = paused.drain(&channel).unwrap();
+        let data: Vec<_> = drained.iter().map(|m| m.data.as_str()).collect();
+        assert_eq!(data, vec!["2", "3"]);
+    }
+}
@@ -0,0 +1,437 @@
+use futures_util::{Stream, StreamExt};
+use log::warn;
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::BroadcastStream;
+use uuid::Uuid;
+
+use crate::models::hub::{HubChannelName, HubMessage, HubPayload};
+
+/// Slot where the hub leaves a broadcast receiver when a direct channel is promoted
+/// to a broadcast channel.
+pub(crate) type PromotionSlot = Arc<Mutex<Option<broadcast::Receiver<HubMessage>>>>;
+
+/// Number of messages lost by a receiver, shared with the sender dropping messages for it
+pub(crate) type DropCounter = Arc<AtomicU64>;
+
+/// Channel used by dropped receivers to request the hub to unsubscribe them
+pub(crate) type Unsubscriber = mpsc::UnboundedSender<(HubChannelName, Uuid)>;
+
+/// Unsubscribes the user from the channel when dropped
+#[derive(Debug)]
+struct SubscriptionGuard {
+    channel: HubChannelName,
+    user_id: Uuid,
+    unsubscriber: Unsubscriber,
+}
+
+impl Drop for SubscriptionGuard {
+    fn drop(&mut self) {
+        // hub may be gone already
+        let _ = self.unsubscriber.send((self.channel.clone(), self.user_id));
+    }
+}
+
+#[derive(Debug)]
+enum Delivery {
+    // Channel has a single subscriber. Messages are delivered through a dedicated mpsc.
+    Direct {
+        receiver: mpsc::Receiver<HubMessage>,
+        promotion: PromotionSlot,
+    },
+    // Channel has several subscribers sharing a broadcast channel.
+    Broadcast(BroadcastStream<HubMessage>),
+    // Channel is reliable. Messages are queued for the subscriber until received.
+    Reliable(mpsc::UnboundedReceiver<HubMessage>),
+}
+
+/// `HubReceiver` is handed to a user subscribed to a topic channel, and consists of the user id
+/// (Uuid) and the channel receiver.
+///
+/// Channels with a single subscriber deliver messages through a direct mpsc channel. When a
+/// second subscriber registers, the hub promotes the channel to broadcast: the direct sender is
+/// dropped and a broadcast receiver is left in the promotion slot. The receiver drains the pending
+/// direct messages before switching, so messages are received in order.
+///
+/// `HubReceiver` implements `Stream<Item = HubMessage>`. The stream ends when the user is
+/// unsubscribed from the channel, and skips messages lost by lagging receivers. Receivers of
+/// reliable channels never lag. The number of messages lost by the receiver, either because it
+/// lagged or because the hub dropped them for a full queue, is available from `dropped`.
+///
+/// Receivers returned by the `HubManager` unsubscribe the user from the channel when dropped.
+/// Receivers subscribed with history deliver the replayed messages before live messages.
+#[derive(Debug)]
+pub struct HubReceiver {
+    user_id: Uuid,
+    history: VecDeque<HubMessage>,
+    delivery: Delivery,
+    dropped: DropCounter,
+    guard: Option<SubscriptionGuard>,
+}
+
+impl HubReceiver {
+    pub(crate) fn direct(
+        user_id: Uuid,
+        receiver: mpsc::Receiver<HubMessage>,
+        promotion: PromotionSlot,
+    ) -> Self {
+        Self {
+            user_id,
+            history: VecDeque::new(),
+            delivery: Delivery::Direct {
+                receiver,
+                promotion,
+            },
+            dropped: DropCounter::default(),
+            guard: None,
+        }
+    }
+
+    pub(crate) fn broadcast(user_id: Uuid, receiver: broadcast::Receiver<HubMessage>) -> Self {
+        Self {
+            user_id,
+            history: VecDeque::new(),
+            delivery: Delivery::Broadcast(BroadcastStream::new(receiver)),
+            dropped: DropCounter::default(),
+            guard: None,
+        }
+    }
+
+    pub(crate) fn reliable(user_id: Uuid, receiver: mpsc::UnboundedReceiver<HubMessage>) -> Self {
+        Self {
+            user_id,
+            history: VecDeque::new(),
+            delivery: Delivery::Reliable(receiver),
+            dropped: DropCounter::default(),
+            guard: None,
+        }
+    }
+
+    /// Attaches a guard requesting `unsubscriber` to unsubscribe the user from `channel`
+    /// when the receiver is dropped
+    pub(crate) fn with_guard(
+        mut self,
+        channel: HubChannelName,
+        unsubscriber: Unsubscriber,
+    ) -> Self {
+        self.guard = Some(SubscriptionGuard {
+            channel,
+            user_id: self.user_id,
+            unsubscriber,
+        });
+        self
+    }
+
+    /// Queues `history` messages to be received before live messages
+    pub(crate) fn with_history(mut self, history: VecDeque<HubMessage>) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// Returns the counter of messages lost by the receiver, to be shared with the sender
+    pub(crate) fn drop_counter(&self) -> DropCounter {
+        Arc::clone(&self.dropped)
+    }
+
+    pub fn user_id(&self) -> Uuid {
+        self.user_id
+    }
+
+    /// Returns the number of messages of the channel lost by the receiver because it didn't
+    /// keep up
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Returns true if messages are delivered through a direct channel
+    pub fn is_direct(&self) -> bool {
+        matches!(self.delivery, Delivery::Direct { .. })
+    }
+
+    /// Receives next message published in the channel.
+    /// Returns `RecvError::Closed` once the user is unsubscribed from the channel, and
+    /// `RecvError::Lagged` if the receiver fell behind a broadcast channel.
+    pub async fn recv(&mut self) -> Result<HubMessage, RecvError> {
+        std::future::poll_fn(|cx| self.poll_recv(cx)).await
+    }
+
+    fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Result<HubMessage, RecvError>> {
+        if let Some(message) = self.history.pop_front() {
+            return Poll::Ready(Ok(message));
+        }
+        loop {
+            match &mut self.delivery {
+                Delivery::Direct {
+                    receiver,
+                    promotion,
+                } => {
+                    if let Some(message) = std::task::ready!(receiver.poll_recv(cx)) {
+                        return Poll::Ready(Ok(message));
+                    }
+                    // direct sender is gone. Switch to broadcast if the channel was promoted.
+                    let promoted = promotion.lock().ok().and_then(|mut slot| slot.take());
+                    match promoted {
+                        Some(receiver) => {
+                            self.delivery = Delivery::Broadcast(BroadcastStream::new(receiver))
+                        }
+                        None => return Poll::Ready(Err(RecvError::Closed)),
+                    }
+                }
+                Delivery::Broadcast(receiver) => {
+                    return match std::task::ready!(receiver.poll_next_unpin(cx)) {
+                        Some(Ok(message)) => Poll::Ready(Ok(message)),
+                        Some(Err(BroadcastStreamRecvError::Lagged(n))) => {
+                            self.dropped.fetch_add(n, Ordering::Relaxed);
+                            Poll::Ready(Err(RecvError::Lagged(n)))
+                        }
+                        None => Poll::Ready(Err(RecvError::Closed)),
+                    }
+                }
+                Delivery::Reliable(receiver) => {
+                    return match std::task::ready!(receiver.poll_recv(cx)) {
+                        Some(message) => Poll::Ready(Ok(message)),
+                        None => Poll::Ready(Err(RecvError::Closed)),
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Stream for HubReceiver {
+    type Item = HubMessage;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            match std::task::ready!(this.poll_recv(cx)) {
+                Ok(message) => return Poll::Ready(Some(message)),
+                Err(RecvError::Lagged(n)) => {
+                    warn!(
+                        "Receiver {} lagged behind. {} messages lost",
+                        this.user_id, n
+                    )
+                }
+                Err(RecvError::Closed) => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+/// `TypedReceiver` wraps a `HubReceiver`, and decodes the data of every message into a `T`.
+///
+/// Messages whose data can't be decoded into a `T` are skipped, logged, and counted in
+/// `decode_errors`, so a malformed sample doesn't end the subscription.
+///
+/// `TypedReceiver` implements `Stream<Item = T>`.
+#[derive(Debug)]
+pub struct TypedReceiver<T> {
+    receiver: HubReceiver,
+    decode_errors: u64,
+    payload: PhantomData<fn() -> T>,
+}
+
+impl<T: HubPayload> TypedReceiver<T> {
+    pub(crate) fn new(receiver: HubReceiver) -> Self {
+        Self {
+            receiver,
+            decode_errors: 0,
+            payload: PhantomData,
+        }
+    }
+
+    pub fn user_id(&self) -> Uuid {
+        self.receiver.user_id()
+    }
+
+    /// Returns the number of messages of the channel lost by the receiver because it didn't
+    /// keep up
+    pub fn dropped(&self) -> u64 {
+        self.receiver.dropped()
+    }
+
+    /// Returns the number of messages skipped because their data couldn't be decoded
+    pub fn decode_errors(&self) -> u64 {
+        self.decode_errors
+    }
+
+    /// Receives next message published in the channel that decodes into a `T`.
+    /// Returns the same errors as `HubReceiver::recv`.
+    pub async fn recv(&mut self) -> Result<T, RecvError> {
+        loop {
+            let message = self.receiver.recv().await?;
+            if let Some(payload) = self.decode(&message) {
+                return Ok(payload);
+            }
+        }
+    }
+
+    fn decode(&mut self, message: &HubMessage) -> Option<T> {
+        match message.payload::<T>() {
+            Ok(payload) => Some(payload),
+            Err(e) => {
+                warn!(
+                    "Receiver {} skipped message of channel {}: {}",
+                    self.receiver.user_id(),
+                    message.channel.as_str(),
+                    e
+                );
+                self.decode_errors += 1;
+                None
+            }
+        }
+    }
+}
+
+impl<T: HubPayload> Stream for TypedReceiver<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            match std::task::ready!(this.receiver.poll_next_unpin(cx)) {
+                Some(message) => {
+                    if let Some(payload) = this.decode(&message) {
+                        return Poll::Ready(Some(payload));
+                    }
+                }
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_direct_receiver() {
+        let (sender, receiver) = mpsc::channel(10);
+        let mut receiver = HubReceiver::direct(Uuid::new_v4(), receiver, PromotionSlot::default());
+        assert!(receiver.is_direct());
+
+        sender
+            .send(HubMessage::try_from_str("channel", "1").unwrap())
+            .await
+            .unwrap();
+        assert_eq!(receiver.recv().await.unwrap().data.as_str(), "1");
+
+        drop(sender);
+        assert!(matches!(receiver.recv().await, Err(RecvError::Closed)));
+    }
+
+    #[tokio::test]
+    async fn test_promotion_preserves_order() {
+        let (sender, receiver) = mpsc::channel(10);
+        let promotion = PromotionSlot::default();
+        let mut receiver = HubReceiver::direct(Uuid::new_v4(), receiver, promotion.clone());
+
+        sender
+            .send(HubMessage::try_from_str("channel", "1").unwrap())
+            .await
+            .unwrap();
+
+        // promote channel
+        let (broadcast_sender, _) = broadcast::channel(10);
+        *promotion.lock().unwrap() = Some(broadcast_sender.subscribe());
+        drop(sender);
+        broadcast_sender
+            .send(HubMessage::try_from_str("channel", "2").unwrap())
+            .unwrap();
+
+        assert_eq!(receiver.recv().await.unwrap().data.as_str(), "1");
+        assert_eq!(receiver.recv().await.unwrap().data.as_str(), "2");
+        assert!(!receiver.is_direct());
+    }
+
+    #[tokio::test]
+    async fn test_lagged_messages_are_counted() {
+        let (sender, receiver) = broadcast::channel(2);
+        let mut receiver = HubReceiver::broadcast(Uuid::new_v4(), receiver);
+        for data in ["1", "2", "3", "4", "5"] {
+            sender
+                .send(HubMessage::try_from_str("channel", data).unwrap())
+                .unwrap();
+        }
+        assert!(matches!(receiver.recv().await, Err(RecvError::Lagged(3))));
+        assert_eq!(receiver.recv().await.unwrap().data.as_str(), "4");
+        assert_eq!(receiver.dropped(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_history_is_received_first() {
+        let (sender, receiver) = broadcast::channel(10);
+        let history = VecDeque::from([HubMessage::try_from_str("channel", "1").unwrap()]);
+        let mut receiver = HubReceiver::broadcast(Uuid::new_v4(), receiver).with_history(history);
+        sender
+            .send(HubMessage::try_from_str("channel", "2").unwrap())
+            .unwrap();
+
+        assert_eq!(receiver.recv().await.unwrap().data.as_str(), "1");
+        assert_eq!(receiver.recv().await.unwrap().data.as_str(), "2");
+    }
+
+    #[test]
+    fn test_guard_unsubscribes_on_drop() {
+        let (unsubscriber, mut requests) = mpsc::unbounded_channel();
+        let channel = HubChannelName::try_from("channel").unwrap();
+        let (_, receiver) = broadcast::channel(10);
+        let receiver = HubReceiver::broadcast(Uuid::new_v4(), receiver)
+            .with_guard(channel.clone(), unsubscriber);
+        let user_id = receiver.user_id();
+
+        assert!(requests.try_recv().is_err());
+        drop(receiver);
+        assert_eq!(requests.try_recv().unwrap(), (channel, user_id));
+    }
+
+    #[tokio::test]
+    async fn test_receiver_stream() {
+        let (sender, receiver) = broadcast::channel(10);
+        let receiver = HubReceiver::broadcast(Uuid::new_v4(), receiver);
+        for data in ["1", "2", "3"] {
+            sender
+                .send(HubMessage::try_from_str("channel", data).unwrap())
+                .unwrap();
+        }
+        drop(sender);
+
+        let data: Vec<_> = receiver
+            .filter(|message| std::future::ready(message.data.as_str() != "2"))
+            .map(|message| message.data.as_str().to_string())
+            .collect()
+            .await;
+        assert_eq!(data, vec!["1", "3"]);
+    }
+
+    #[tokio::test]
+    async fn test_typed_receiver() {
+        let (sender, receiver) = broadcast::channel(10);
+        let mut receiver =
+            TypedReceiver::<Vec<f64>>::new(HubReceiver::broadcast(Uuid::new_v4(), receiver));
+        for data in ["[1.0,2.0]", "1.0,high", "[3.0]"] {
+            sender
+                .send(HubMessage::try_from_str("channel", data).unwrap())
+                .unwrap();
+        }
+
+        assert_eq!(receiver.recv().await.unwrap(), vec![1.0, 2.0]);
+        assert_eq!(receiver.recv().await.unwrap(), vec![3.0]);
+        assert_eq!(receiver.decode_errors(), 1);
+
+        sender
+            .send(HubMessage::try_from_str("channel", "[4.0]").unwrap())
+            .unwrap();
+        drop(sender);
+        let samples: Vec<_> = receiver.collect().await;
+        assert_eq!(samples, vec![vec![4.0]]);
+    }
+}
@@ -0,0 +1,154 @@
+use async_trait::async_trait;
+use log::warn;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::config::RemapRules;
+use crate::models::hub::{HubChannelName, HubMessage};
+use crate::ports::{NodeTasks, NotificationHub};
+
+const CHANNEL_CAPACITY: usize = 100;
+
+/// `RemappedNode` wraps a hub node and translates channel names with `RemapRules`.
+/// Messages received by the node are forwarded to the hub by an additional task, which renames
+/// their channel into the hub namespace.
+#[derive(Debug)]
+pub(crate) struct RemappedNode {
+    node: Box<dyn NotificationHub>,
+    rules: RemapRules,
+}
+
+impl RemappedNode {
+    pub(crate) fn new(node: Box<dyn NotificationHub>, rules: RemapRules) -> Self {
+        Self { node, rules }
+    }
+}
+
+#[async_trait]
+impl NotificationHub for RemappedNode {
+    async fn send(&self, mut message: HubMessage) -> Result<(), std::io::Error> {
+        message.channel = self.rules.egress(&message.channel);
+        self.node.send(message).await
+    }
+
+    async fn start(
+        &self,
+        sender: Option<broadcast::Sender<HubMessage>>,
+    ) -> Result<NodeTasks, std::io::Error> {
+        let Some(hub_sender) = sender else {
+            return self.node.start(None).await;
+        };
+        let (node_sender, mut node_receiver) = broadcast::channel(CHANNEL_CAPACITY);
+        let mut tasks = self.node.start(Some(node_sender)).await?;
+        let rules = self.rules.clone();
+        tasks.push(tokio::spawn(async move {
+            loop {
+                match node_receiver.recv().await {
+                    Ok(mut message) => {
+                        message.channel = rules.ingest(&message.channel);
+                        // hub may not be listening yet
+                        let _ = hub_sender.send(message);
+                    }
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Remapped node lagged. {} messages dropped", skipped);
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        }));
+        Ok(tasks)
+    }
+
+    async fn list_channels(&self) -> Result<Vec<HubChannelName>, std::io::Error> {
+        let channels = self.node.list_channels().await?;
+        Ok(channels
+            .iter()
+            .map(|channel| self.rules.ingest(channel))
+            .collect())
+    }
+
+    async fn subscribe(&self, channel: HubChannelName) -> Result<(), std::io::Error> {
+        self.node.subscribe(self.rules.egress(&channel)).await
+    }
+
+    async fn unsubscribe(&self, channel: HubChannelName) -> Result<(), std::io::Error> {
+        self.node.unsubscribe(self.rules.egress(&channel)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RemapRulesBuilder;
+    use crate::services::hub::mock::MockNode;
+    use std::sync::Arc;
+
+    fn channel(name: &str) -> HubChannelName {
+        HubChannelName::try_from(name).unwrap()
+    }
+
+    #[derive(Debug, Default)]
+    struct EchoNode {
+        inner: MockNode,
+        sender: std::sync::Mutex<Option<broadcast::Sender<HubMessage>>>,
+    }
+
+    #[async_trait]
+    impl NotificationHub for Arc<EchoNode> {
+        async fn send(&self, message: HubMessage) -> Result<(), std::io::Error> {
+            if let Some(sender) = self.sender.lock().unwrap().as_ref() {
+                let _ = sender.send(message.clone());
+            }
+            self.inner.send(message).await
+        }
+        async fn start(
+            &self,
+            sender: Option<broadcast::Sender<HubMessage>>,
+        ) -> Result<NodeTasks, std::io::Error> {
+            *self.sender.lock().unwrap() = sender;
+            Ok(NodeTasks::new())
+        }
+        async fn list_channels(&self) -> Result<Vec<HubChannelName>, std::io::Error> {
+            self.inner.list_channels().await
+        }
+        async fn subscribe(&self, channel: HubChannelName) -> Result<(), std::io::Error> {
+            self.inner.subscribe(channel).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_remapped_node() {
+        let rules = RemapRulesBuilder::new()
+            .alias("imu_raw", "sensors/imu")
+            .prefix("robot2")
+            .build()
+            .unwrap();
+        let node = Arc::new(EchoNode::default());
+        node.inner.channels.lock().await.push(channel("imu_raw"));
+        node.inner.channels.lock().await.push(channel("odometry"));
+        let remapped = RemappedNode::new(Box::new(node.clone()), rules);
+
+        let channels = remapped.list_channels().await.unwrap();
+        assert_eq!(
+            channels,
+            vec![channel("sensors/imu"), channel("robot2/odometry")]
+        );
+
+        remapped.subscribe(channel("sensors/imu")).await.unwrap();
+        assert_eq!(
+            *node.inner.subscribed.lock().await,
+            vec![channel("imu_raw")]
+        );
+
+        // node echoes sent messages back to the hub
+        let (hub_sender, mut hub_receiver) = broadcast::channel(10);
+        remapped.start(Some(hub_sender)).await.unwrap();
+        remapped
+            .send(HubMessage::try_from_str("robot2/cmd", "1").unwrap())
+            .await
+            .unwrap();
+        assert_eq!(node.inner.sent.lock().await[0].channel, channel("cmd"));
+        let received = hub_receiver.recv().await.unwrap();
+        assert_eq!(received.channel, channel("robot2/cmd"));
+    }
+}
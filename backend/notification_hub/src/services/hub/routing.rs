@@ -0,0 +1,382 @@
+use log::error;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::models::hub::hub_channel_name::NAMESPACE_SEPARATOR;
+use crate::models::hub::{HubChannelName, HubMessage};
+use crate::ports::NotificationHub;
+
+pub use crate::models::hub::NodeId;
+
+/// Hub node added to a `HubManager`, together with its identifier
+pub(crate) type HubNode = (NodeId, Arc<dyn NotificationHub>);
+
+/// Wildcard segment matching every channel of a namespace in a `RoutePattern`
+pub const ROUTE_WILDCARD: &str = "*";
+
+/// Channels a routing rule applies to
+///
+/// - `Channel`: A single channel (e.g. `cmd_motor`).
+/// - `Namespace`: Every channel nested in a namespace, written with a trailing wildcard
+///   (e.g. `telemetry/*`).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum RoutePattern {
+    Channel(HubChannelName),
+    Namespace(HubChannelName),
+}
+
+impl RoutePattern {
+    /// Whether `channel` is matched by the pattern
+    pub fn matches(&self, channel: &HubChannelName) -> bool {
+        match self {
+            RoutePattern::Channel(pattern) => pattern == channel,
+            RoutePattern::Namespace(namespace) => channel.is_in_namespace(namespace),
+        }
+    }
+}
+
+impl TryFrom<&str> for RoutePattern {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        let value = value.trim();
+        match value
+            .strip_suffix(ROUTE_WILDCARD)
+            .and_then(|namespace| namespace.strip_suffix(NAMESPACE_SEPARATOR))
+        {
+            Some(namespace) => Ok(RoutePattern::Namespace(HubChannelName::try_from(
+                namespace,
+            )?)),
+            None => Ok(RoutePattern::Channel(HubChannelName::try_from(value)?)),
+        }
+    }
+}
+
+impl From<HubChannelName> for RoutePattern {
+    fn from(channel: HubChannelName) -> Self {
+        RoutePattern::Channel(channel)
+    }
+}
+
+/// `HubRouting` decides which hub nodes a published message is sent to.
+/// Channels with routing rules are sent only to the nodes owning or bridging them. Rules of a
+/// channel take precedence over rules of its namespaces, and rules of nested namespaces over
+/// rules of their parents.
+/// Channels in a namespace owned by a node (e.g. `robot2/cmd_vel` when `robot2` is the
+/// namespace of a bridged robot) are sent only to that node.
+/// Messages from any other channel are broadcast to all hub nodes.
+#[derive(Debug, Clone, Default)]
+pub struct HubRouting {
+    rules: HashMap<HubChannelName, Vec<NodeId>>,
+    namespace_rules: HashMap<HubChannelName, Vec<NodeId>>,
+    namespaces: HashMap<HubChannelName, NodeId>,
+}
+
+impl HubRouting {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Routes messages from `channel` to `node`
+    pub fn add_route(&mut self, channel: HubChannelName, node: NodeId) {
+        self.add_pattern_route(RoutePattern::Channel(channel), node);
+    }
+
+    /// Routes messages from the channels matched by `pattern` to `node`
+    pub fn add_pattern_route(&mut self, pattern: RoutePattern, node: NodeId) {
+        let nodes = match pattern {
+            RoutePattern::Channel(channel) => self.rules.entry(channel).or_default(),
+            RoutePattern::Namespace(namespace) => {
+                self.namespace_rules.entry(namespace).or_default()
+            }
+        };
+        if !nodes.contains(&node) {
+            nodes.push(node);
+        }
+    }
+
+    /// Removes routing rules of `channel`. Messages from channel are broadcast again, unless
+    /// routed by the rules of a namespace.
+    pub fn remove_routes(&mut self, channel: &HubChannelName) {
+        self.rules.remove(channel);
+    }
+
+    /// Removes routing rules of `pattern`
+    pub fn remove_pattern_routes(&mut self, pattern: &RoutePattern) {
+        match pattern {
+            RoutePattern::Channel(channel) => self.rules.remove(channel),
+            RoutePattern::Namespace(namespace) => self.namespace_rules.remove(namespace),
+        };
+    }
+
+    // Returns nodes of the rules of the channel, or of its innermost namespace with rules
+    fn rule_nodes(&self, channel: &HubChannelName) -> Option<&Vec<NodeId>> {
+        if let Some(nodes) = self.rules.get(channel) {
+            return Some(nodes);
+        }
+        let mut namespace = channel.namespace();
+        while let Some(current) = namespace {
+            if let Some(nodes) = self.namespace_rules.get(&current) {
+                return Some(nodes);
+            }
+            namespace = current.namespace();
+        }
+        None
+    }
+
+    /// Routes messages from every channel under top-level `namespace` to `node`
+    pub fn add_namespace_route(&mut self, namespace: HubChannelName, node: NodeId) {
+        self.namespaces.insert(namespace, node);
+    }
+
+    /// Removes every routing rule of `node`. Channels and namespaces left without nodes are
+    /// broadcast again.
+    pub fn remove_node(&mut self, node: &NodeId) {
+        for rules in [&mut self.rules, &mut self.namespace_rules] {
+            rules.retain(|_, nodes| {
+                nodes.retain(|rule_node| rule_node != node);
+                !nodes.is_empty()
+            });
+        }
+        self.namespaces.retain(|_, owner| owner != node);
+    }
+
+    // Returns node owning the top-level namespace of channel, if any
+    fn namespace_owner(&self, channel: &HubChannelName) -> Option<&NodeId> {
+        let namespace = channel.root_namespace()?;
+        self.namespaces.get(&namespace)
+    }
+
+    // Returns nodes a message from channel is sent to, with their identifiers
+    fn route_nodes<'a>(
+        &self,
+        channel: &HubChannelName,
+        hub_nodes: &'a [HubNode],
+    ) -> Vec<&'a HubNode> {
+        match self.rule_nodes(channel) {
+            Some(nodes) => hub_nodes
+                .iter()
+                .filter(|(id, _)| nodes.contains(id))
+                .collect(),
+            None => self.subscription_nodes(channel, hub_nodes),
+        }
+    }
+
+    // Returns nodes subscribed to channel, with their identifiers
+    fn subscription_nodes<'a>(
+        &self,
+        channel: &HubChannelName,
+        hub_nodes: &'a [HubNode],
+    ) -> Vec<&'a HubNode> {
+        match self.namespace_owner(channel) {
+            Some(owner) => hub_nodes.iter().filter(|(id, _)| id == owner).collect(),
+            None => hub_nodes.iter().collect(),
+        }
+    }
+
+    // Returns nodes a message from channel is sent to
+    pub(crate) fn route<'a>(
+        &self,
+        channel: &HubChannelName,
+        hub_nodes: &'a [HubNode],
+    ) -> Vec<&'a Arc<dyn NotificationHub>> {
+        self.route_nodes(channel, hub_nodes)
+            .into_iter()
+            .map(|(_, node)| node)
+            .collect()
+    }
+
+    // Returns nodes subscribed to channel. Only namespaces restrict subscriptions, as channels
+    // with routing rules may also be fed by other nodes.
+    pub(crate) fn route_subscription<'a>(
+        &self,
+        channel: &HubChannelName,
+        hub_nodes: &'a [HubNode],
+    ) -> Vec<&'a Arc<dyn NotificationHub>> {
+        self.subscription_nodes(channel, hub_nodes)
+            .into_iter()
+            .map(|(_, node)| node)
+            .collect()
+    }
+
+    /// Sends message to the nodes selected by the routing rules. Message is sent to every node
+    /// even if some of them fail, and the first error is returned. Messages received from a
+    /// hub node and relayed back to the hub are not sent to their origin node, so nodes
+    /// bridging the same channel don't echo messages to each other forever.
+    pub(crate) async fn publish(
+        &self,
+        hub_nodes: &[HubNode],
+        message: HubMessage,
+    ) -> Result<(), std::io::Error> {
+        let mut result = Ok(());
+        for (id, node) in self.route_nodes(&message.channel, hub_nodes) {
+            if message.origin.as_ref() == Some(id) {
+                continue;
+            }
+            if let Err(e) = node.send(message.clone()).await {
+                error!("Failed to publish message to {:?}: {:?}", node, e);
+                if result.is_ok() {
+                    result = Err(e);
+                }
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::hub::mock::MockNode;
+
+    fn node_id(idx: usize) -> NodeId {
+        NodeId::try_from(format!("node{}", idx).as_str()).unwrap()
+    }
+
+    fn nodes(n: usize) -> (Vec<Arc<MockNode>>, Vec<HubNode>) {
+        let mocks: Vec<_> = (0..n).map(|_| Arc::new(MockNode::default())).collect();
+        let hub_nodes = mocks
+            .iter()
+            .enumerate()
+            .map(|(idx, node)| (node_id(idx), node.clone() as Arc<dyn NotificationHub>))
+            .collect();
+        (mocks, hub_nodes)
+    }
+
+    #[tokio::test]
+    async fn test_publish_broadcasts_by_default() {
+        let (mocks, hub_nodes) = nodes(2);
+        let routing = HubRouting::new();
+        let message = HubMessage::try_from_str("channel", "1").unwrap();
+        routing.publish(&hub_nodes, message).await.unwrap();
+
+        for mock in mocks {
+            assert_eq!(mock.sent.lock().await.len(), 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_publish_skips_origin_node() {
+        let (mocks, hub_nodes) = nodes(3);
+        let routing = HubRouting::new();
+        let mut message = HubMessage::try_from_str("channel", "1").unwrap();
+        message.origin = Some(node_id(1));
+        routing.publish(&hub_nodes, message).await.unwrap();
+
+        assert_eq!(mocks[0].sent.lock().await.len(), 1);
+        assert!(mocks[1].sent.lock().await.is_empty());
+        assert_eq!(mocks[2].sent.lock().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_publish_follows_routes() {
+        let (mocks, hub_nodes) = nodes(3);
+        let channel = HubChannelName::try_from("cmd_vel").unwrap();
+        let mut routing = HubRouting::new();
+        routing.add_route(channel.clone(), node_id(1));
+        routing.add_route(channel.clone(), node_id(1));
+
+        let message = HubMessage::try_from_str("cmd_vel", "1").unwrap();
+        routing.publish(&hub_nodes, message).await.unwrap();
+        assert!(mocks[0].sent.lock().await.is_empty());
+        assert_eq!(mocks[1].sent.lock().await.len(), 1);
+        assert!(mocks[2].sent.lock().await.is_empty());
+
+        routing.remove_routes(&channel);
+        assert_eq!(routing.route(&channel, &hub_nodes).len(), 3);
+    }
+
+    #[test]
+    fn test_route_pattern() {
+        let pattern = RoutePattern::try_from("telemetry/*").unwrap();
+        assert_eq!(
+            pattern,
+            RoutePattern::Namespace(HubChannelName::try_from("telemetry").unwrap())
+        );
+        assert!(pattern.matches(&HubChannelName::try_from("telemetry/battery").unwrap()));
+        assert!(!pattern.matches(&HubChannelName::try_from("telemetry").unwrap()));
+        assert!(!pattern.matches(&HubChannelName::try_from("telemetry2/battery").unwrap()));
+
+        let pattern = RoutePattern::try_from("cmd_motor").unwrap();
+        assert!(pattern.matches(&HubChannelName::try_from("cmd_motor").unwrap()));
+        assert!(RoutePattern::try_from("*").is_err());
+        assert!(RoutePattern::try_from("telemetry/*/battery").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_pattern_routes() {
+        let (_, hub_nodes) = nodes(3);
+        let mut routing = HubRouting::new();
+        routing.add_pattern_route(RoutePattern::try_from("telemetry/*").unwrap(), node_id(1));
+        routing.add_pattern_route(
+            RoutePattern::try_from("telemetry/motors/*").unwrap(),
+            node_id(2),
+        );
+        routing.add_route(
+            HubChannelName::try_from("telemetry/battery").unwrap(),
+            node_id(0),
+        );
+
+        let route = |routing: &HubRouting, name: &str| {
+            let channel = HubChannelName::try_from(name).unwrap();
+            routing
+                .route(&channel, &hub_nodes)
+                .into_iter()
+                .map(|node| {
+                    hub_nodes
+                        .iter()
+                        .position(|(_, n)| Arc::ptr_eq(n, node))
+                        .unwrap()
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(route(&routing, "telemetry/imu"), vec![1]);
+        assert_eq!(route(&routing, "telemetry/motors/left"), vec![2]);
+        assert_eq!(route(&routing, "telemetry/battery"), vec![0]);
+        assert_eq!(route(&routing, "telemetry"), vec![0, 1, 2]);
+
+        routing.remove_pattern_routes(&RoutePattern::try_from("telemetry/*").unwrap());
+        assert_eq!(route(&routing, "telemetry/imu"), vec![0, 1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_namespace_routes() {
+        let (_, hub_nodes) = nodes(3);
+        let mut routing = HubRouting::new();
+        routing.add_namespace_route(HubChannelName::try_from("robot2").unwrap(), node_id(2));
+
+        let channel = HubChannelName::try_from("robot2/cmd_vel").unwrap();
+        assert!(Arc::ptr_eq(
+            routing.route(&channel, &hub_nodes)[0],
+            &hub_nodes[2].1
+        ));
+        assert_eq!(routing.route(&channel, &hub_nodes).len(), 1);
+        assert_eq!(routing.route_subscription(&channel, &hub_nodes).len(), 1);
+
+        for name in ["robot2", "robot1/cmd_vel", "robot22/cmd_vel"] {
+            let channel = HubChannelName::try_from(name).unwrap();
+            assert_eq!(routing.route(&channel, &hub_nodes).len(), 3);
+        }
+    }
+
+    #[test]
+    fn test_remove_node() {
+        let (_, hub_nodes) = nodes(3);
+        let mut routing = HubRouting::new();
+        let cmd_vel = HubChannelName::try_from("cmd_vel").unwrap();
+        routing.add_route(cmd_vel.clone(), node_id(1));
+        routing.add_route(cmd_vel.clone(), node_id(2));
+        routing.add_pattern_route(RoutePattern::try_from("telemetry/*").unwrap(), node_id(1));
+        routing.add_namespace_route(HubChannelName::try_from("robot2").unwrap(), node_id(1));
+
+        routing.remove_node(&node_id(1));
+        assert!(Arc::ptr_eq(
+            routing.route(&cmd_vel, &hub_nodes)[0],
+            &hub_nodes[2].1
+        ));
+        for name in ["telemetry/imu", "robot2/cmd_vel"] {
+            let channel = HubChannelName::try_from(name).unwrap();
+            assert_eq!(routing.route(&channel, &hub_nodes).len(), 3);
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use std::time::Duration;
+use uuid::Uuid;
+
+use super::filter::MessageFilter;
+use super::receiver::HubReceiver;
+use crate::models::hub::hub_message::CORRELATION_ID_HEADER;
+use crate::models::hub::{HubChannelName, HubData, HubMessage};
+
+/// Name of the channel nested in a request channel where responses are published
+pub const REPLY_CHANNEL: &str = "reply";
+
+/// Returns the channel where responses to requests published in `channel` are published,
+/// i.e. `<channel>/reply`
+pub fn reply_channel(channel: &HubChannelName) -> Result<HubChannelName, String> {
+    channel.join(&HubChannelName::try_from(REPLY_CHANNEL)?)
+}
+
+/// `RpcMessage` is a request or response exchanged over the hub with `HubManager::request`.
+///
+/// The correlation id matching a response with its request is carried in the
+/// `CORRELATION_ID_HEADER` header, so the data is left untouched. Requests and responses go
+/// through any hub node carrying headers (i.e. not serial nodes). Responders subscribe to the
+/// request channel, and publish their response with `RpcMessage::reply`.
+///
+/// # Fields
+/// - `correlation_id`: Identifier shared by a request and its response.
+/// - `data`: Request or response data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RpcMessage {
+    pub correlation_id: Uuid,
+    pub data: HubData,
+}
+
+impl RpcMessage {
+    /// New request with a unique correlation id
+    pub fn new(data: HubData) -> Self {
+        Self {
+            correlation_id: Uuid::new_v4(),
+            data,
+        }
+    }
+
+    /// Encodes message as a `HubMessage` published in `channel`
+    pub fn to_message(&self, channel: HubChannelName) -> Result<HubMessage, String> {
+        Ok(HubMessage::new(channel, self.data.clone()).with_header(
+            CORRELATION_ID_HEADER,
+            &self.correlation_id.simple().to_string(),
+        ))
+    }
+
+    /// Builds the response to this request, received in `channel`, with `data`
+    pub fn reply(&self, channel: &HubChannelName, data: HubData) -> Result<HubMessage, String> {
+        let response = Self {
+            correlation_id: self.correlation_id,
+            data,
+        };
+        response.to_message(reply_channel(channel)?)
+    }
+}
+
+impl TryFrom<&HubMessage> for RpcMessage {
+    type Error = String;
+
+    fn try_from(message: &HubMessage) -> Result<Self, Self::Error> {
+        let correlation_id = message
+            .header(CORRELATION_ID_HEADER)
+            .ok_or_else(|| format!("RPC message without {} header", CORRELATION_ID_HEADER))?;
+        Ok(Self {
+            correlation_id: Uuid::parse_str(correlation_id)
+                .map_err(|_| format!("Invalid RPC correlation id: {}", correlation_id))?,
+            data: message.data.clone(),
+        })
+    }
+}
+
+/// Filter matching the responses to request `correlation_id`
+pub(crate) fn correlated(correlation_id: Uuid) -> MessageFilter {
+    MessageFilter::predicate(move |message| {
+        RpcMessage::try_from(message)
+            .is_ok_and(|response| response.correlation_id == correlation_id)
+    })
+}
+
+/// Waits up to `timeout` for the response delivered in `receiver`
+pub(crate) async fn await_reply(
+    receiver: &mut HubReceiver,
+    timeout: Duration,
+) -> Result<HubData, std::io::Error> {
+    let message = tokio::time::timeout(timeout, receiver.recv())
+        .await
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "No response to request"))?
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::BrokenPipe, e.to_string()))?;
+    RpcMessage::try_from(&message)
+        .map(|response| response.data)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rpc_round_trip() {
+        let channel = HubChannelName::try_from("battery").unwrap();
+        let request = RpcMessage::new("query".parse().unwrap());
+        let message = request.to_message(channel.clone()).unwrap();
+        assert_eq!(message.data.as_str(), "query");
+        assert_eq!(
+            message.header(CORRELATION_ID_HEADER),
+            Some(request.correlation_id.simple().to_string().as_str())
+        );
+        assert_eq!(RpcMessage::try_from(&message).unwrap(), request);
+
+        let reply = request.reply(&channel, "12.4".parse().unwrap()).unwrap();
+        assert_eq!(reply.channel.as_str(), "battery/reply");
+        let response = RpcMessage::try_from(&reply).unwrap();
+        assert_eq!(response.correlation_id, request.correlation_id);
+        assert_eq!(response.data.as_str(), "12.4");
+        assert!(correlated(request.correlation_id).matches(&reply));
+        assert!(!correlated(Uuid::new_v4()).matches(&reply));
+    }
+
+    #[test]
+    fn test_invalid_rpc_message() {
+        let message = HubMessage::try_from_str("battery", "12.4").unwrap();
+        assert!(RpcMessage::try_from(&message).is_err());
+        let message = message.with_header(CORRELATION_ID_HEADER, "not_an_id");
+        assert!(RpcMessage::try_from(&message).is_err());
+    }
+}
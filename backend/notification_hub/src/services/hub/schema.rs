@@ -0,0 +1,364 @@
+use log::warn;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+use tokio::sync::broadcast;
+
+use crate::config::SchemaViolation;
+use crate::models::hub::{HubChannelName, HubData, HubMessage};
+use crate::ports::MessageMiddleware;
+
+/// Reserved meta-channel where published messages not following the schema of their channel
+/// are delivered with `SchemaViolation::DeadLetter`
+pub const HUB_DEAD_LETTER: &str = "hub_dead_letter";
+/// Header of dead-letter messages with the channel the message was published in
+pub const DEAD_LETTER_CHANNEL_HEADER: &str = "dead_letter_channel";
+/// Header of dead-letter messages with the reason the message was rejected
+pub const DEAD_LETTER_ERROR_HEADER: &str = "dead_letter_error";
+
+/// Type of a field of the comma separated data of a channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldType {
+    Float,
+    Integer,
+    Bool,
+    Text,
+}
+
+impl FieldType {
+    fn name(&self) -> &'static str {
+        match self {
+            FieldType::Float => "float",
+            FieldType::Integer => "integer",
+            FieldType::Bool => "bool",
+            FieldType::Text => "text",
+        }
+    }
+
+    fn matches(&self, value: &str) -> bool {
+        match self {
+            FieldType::Float => value.parse::<f64>().is_ok(),
+            FieldType::Integer => value.parse::<i64>().is_ok(),
+            FieldType::Bool => value.parse::<bool>().is_ok(),
+            FieldType::Text => true,
+        }
+    }
+}
+
+/// `ChannelSchema` is the expected format of the data of a channel.
+///
+/// - `Fields`: Comma separated fields (e.g. `x,y,z` floats), as read by `MessageFilter`.
+/// - `Json`: JSON data described by a JSON schema. Validation supports the `type`,
+///   `properties`, `required` and `items` keywords.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChannelSchema {
+    Fields(Vec<(String, FieldType)>),
+    Json(Value),
+}
+
+impl ChannelSchema {
+    /// Schema of comma separated float fields named `names`
+    pub fn floats(names: &[&str]) -> Self {
+        Self::Fields(
+            names
+                .iter()
+                .map(|name| (name.to_string(), FieldType::Float))
+                .collect(),
+        )
+    }
+
+    /// Returns an error describing why `data` doesn't follow the schema
+    pub fn validate(&self, data: &HubData) -> Result<(), String> {
+        match self {
+            ChannelSchema::Fields(fields) => {
+                let values: Vec<_> = data.as_str().split(',').map(str::trim).collect();
+                if values.len() != fields.len() {
+                    return Err(format!(
+                        "Expected {} fields, found {}",
+                        fields.len(),
+                        values.len()
+                    ));
+                }
+                for ((name, field_type), value) in fields.iter().zip(values) {
+                    if !field_type.matches(value) {
+                        return Err(format!(
+                            "Field {} is not a {}: {}",
+                            name,
+                            field_type.name(),
+                            value
+                        ));
+                    }
+                }
+                Ok(())
+            }
+            ChannelSchema::Json(schema) => {
+                let value: Value = serde_json::from_str(data.as_str())
+                    .map_err(|e| format!("Invalid JSON data: {}", e))?;
+                validate_json(schema, &value, "$")
+            }
+        }
+    }
+}
+
+impl fmt::Display for ChannelSchema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChannelSchema::Fields(fields) => {
+                let fields: Vec<_> = fields
+                    .iter()
+                    .map(|(name, field_type)| format!("{}:{}", name, field_type.name()))
+                    .collect();
+                write!(f, "{}", fields.join(","))
+            }
+            ChannelSchema::Json(schema) => write!(f, "{}", schema),
+        }
+    }
+}
+
+fn json_type_matches(expected: &str, value: &Value) -> bool {
+    match expected {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => false,
+    }
+}
+
+// Validates `value` at `path` against the supported subset of JSON schema
+fn validate_json(schema: &Value, value: &Value, path: &str) -> Result<(), String> {
+    if let Some(expected) = schema.get("type").and_then(Value::as_str) {
+        if !json_type_matches(expected, value) {
+            return Err(format!("{} is not of type {}", path, expected));
+        }
+    }
+    if let (Some(required), Some(object)) = (
+        schema.get("required").and_then(Value::as_array),
+        value.as_object(),
+    ) {
+        for key in required.iter().filter_map(Value::as_str) {
+            if !object.contains_key(key) {
+                return Err(format!("{}.{} is required", path, key));
+            }
+        }
+    }
+    if let (Some(properties), Some(object)) = (
+        schema.get("properties").and_then(Value::as_object),
+        value.as_object(),
+    ) {
+        for (key, property) in properties {
+            if let Some(value) = object.get(key) {
+                validate_json(property, value, &format!("{}.{}", path, key))?;
+            }
+        }
+    }
+    if let (Some(items), Some(array)) = (schema.get("items"), value.as_array()) {
+        for (idx, item) in array.iter().enumerate() {
+            validate_json(items, item, &format!("{}[{}]", path, idx))?;
+        }
+    }
+    Ok(())
+}
+
+/// `SchemaRegistry` holds the schemas declared for hub channels. It is shared between the hub
+/// and the dispatch path, so schemas can be declared once the hub is started.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SchemaRegistry {
+    schemas: Arc<RwLock<HashMap<HubChannelName, ChannelSchema>>>,
+}
+
+impl SchemaRegistry {
+    pub(crate) fn declare(&self, channel: HubChannelName, schema: ChannelSchema) {
+        self.schemas
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(channel, schema);
+    }
+
+    pub(crate) fn get(&self, channel: &HubChannelName) -> Option<ChannelSchema> {
+        self.schemas
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(channel)
+            .cloned()
+    }
+
+    /// Validates message against the schema of its channel, if any
+    pub(crate) fn validate(&self, message: &HubMessage) -> Result<(), String> {
+        let schemas = self.schemas.read().unwrap_or_else(|e| e.into_inner());
+        match schemas.get(&message.channel) {
+            Some(schema) => schema.validate(&message.data),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Validates the messages published by the hub against the schema of their channel, and
+/// applies the configured `SchemaViolation` to those not following it
+#[derive(Debug, Clone)]
+pub(crate) struct PublishValidation {
+    registry: SchemaRegistry,
+    violation: SchemaViolation,
+    dead_letter: broadcast::Sender<HubMessage>,
+}
+
+impl PublishValidation {
+    pub(crate) fn new(
+        registry: SchemaRegistry,
+        violation: SchemaViolation,
+        dead_letter: broadcast::Sender<HubMessage>,
+    ) -> Self {
+        Self {
+            registry,
+            violation,
+            dead_letter,
+        }
+    }
+
+    /// Returns an `ErrorKind::InvalidData` error if `message` is to be rejected
+    pub(crate) fn check(&self, message: &HubMessage) -> Result<(), std::io::Error> {
+        if self.violation == SchemaViolation::Publish {
+            return Ok(());
+        }
+        let Err(e) = self.registry.validate(message) else {
+            return Ok(());
+        };
+        let error = format!(
+            "Message not following the schema of channel {}: {}",
+            message.channel.as_str(),
+            e
+        );
+        if self.violation == SchemaViolation::DeadLetter {
+            let mut dead_letter = message.clone();
+            dead_letter.channel = HubChannelName::try_from(HUB_DEAD_LETTER).unwrap();
+            let dead_letter = dead_letter
+                .with_header(DEAD_LETTER_CHANNEL_HEADER, message.channel.as_str())
+                .with_header(DEAD_LETTER_ERROR_HEADER, &e);
+            // nobody may be listening
+            let _ = self.dead_letter.send(dead_letter);
+        }
+        Err(std::io::Error::new(std::io::ErrorKind::InvalidData, error))
+    }
+}
+
+/// Blocks messages not following the schema declared for their channel
+#[derive(Debug)]
+pub(crate) struct SchemaValidation {
+    registry: SchemaRegistry,
+}
+
+impl SchemaValidation {
+    pub(crate) fn new(registry: SchemaRegistry) -> Self {
+        Self { registry }
+    }
+}
+
+impl MessageMiddleware for SchemaValidation {
+    fn process(&self, message: HubMessage) -> Option<HubMessage> {
+        match self.registry.validate(&message) {
+            Ok(()) => Some(message),
+            Err(e) => {
+                warn!(
+                    "Message not following the schema of channel {} blocked: {}",
+                    message.channel.as_str(),
+                    e
+                );
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(data: &str) -> HubData {
+        data.parse().unwrap()
+    }
+
+    #[test]
+    fn test_fields_schema() {
+        let schema = ChannelSchema::floats(&["x", "y", "z"]);
+        assert_eq!(schema.to_string(), "x:float,y:float,z:float");
+        assert!(schema.validate(&data("1.0, 2, -3.5")).is_ok());
+        assert!(schema.validate(&data("1.0,2.0")).is_err());
+        assert!(schema.validate(&data("1.0,2.0,high")).is_err());
+
+        let schema = ChannelSchema::Fields(vec![
+            ("count".to_string(), FieldType::Integer),
+            ("ok".to_string(), FieldType::Bool),
+            ("label".to_string(), FieldType::Text),
+        ]);
+        assert!(schema.validate(&data("3,true,motor")).is_ok());
+        assert!(schema.validate(&data("3.5,true,motor")).is_err());
+    }
+
+    #[test]
+    fn test_json_schema() {
+        let schema = ChannelSchema::Json(serde_json::json!({
+            "type": "object",
+            "required": ["pose"],
+            "properties": {
+                "pose": {"type": "array", "items": {"type": "number"}},
+                "frame": {"type": "string"}
+            }
+        }));
+        assert!(schema
+            .validate(&data(r#"{"pose": [1.0, 2.5], "frame": "map"}"#))
+            .is_ok());
+        assert!(schema.validate(&data(r#"{"frame": "map"}"#)).is_err());
+        assert!(schema.validate(&data(r#"{"pose": [1.0, "x"]}"#)).is_err());
+        assert!(schema.validate(&data("1.0,2.5")).is_err());
+    }
+
+    #[test]
+    fn test_publish_validation() {
+        let registry = SchemaRegistry::default();
+        let channel = HubChannelName::try_from("imu").unwrap();
+        registry.declare(channel, ChannelSchema::floats(&["x", "y"]));
+        let valid = HubMessage::try_from_str("imu", "1.0,2.0").unwrap();
+        let invalid = HubMessage::try_from_str("imu", "1.0,high").unwrap();
+        let (sender, mut receiver) = broadcast::channel(10);
+
+        let validation =
+            PublishValidation::new(registry.clone(), SchemaViolation::Publish, sender.clone());
+        assert!(validation.check(&invalid).is_ok());
+
+        let validation =
+            PublishValidation::new(registry.clone(), SchemaViolation::Reject, sender.clone());
+        assert!(validation.check(&valid).is_ok());
+        let error = validation.check(&invalid).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert!(receiver.try_recv().is_err());
+
+        let validation = PublishValidation::new(registry, SchemaViolation::DeadLetter, sender);
+        assert!(validation.check(&invalid).is_err());
+        let dead_letter = receiver.try_recv().unwrap();
+        assert_eq!(dead_letter.channel.as_str(), HUB_DEAD_LETTER);
+        assert_eq!(dead_letter.data.as_str(), "1.0,high");
+        assert_eq!(dead_letter.header(DEAD_LETTER_CHANNEL_HEADER), Some("imu"));
+        assert!(dead_letter.header(DEAD_LETTER_ERROR_HEADER).is_some());
+    }
+
+    #[test]
+    fn test_schema_validation_middleware() {
+        let registry = SchemaRegistry::default();
+        let validation = SchemaValidation::new(registry.clone());
+        let message = HubMessage::try_from_str("imu", "1.0,2.0").unwrap();
+        assert!(validation.process(message.clone()).is_some());
+
+        registry.declare(
+            message.channel.clone(),
+            ChannelSchema::floats(&["x", "y", "z"]),
+        );
+        assert!(validation.process(message).is_none());
+        assert!(validation
+            .process(HubMessage::try_from_str("imu", "1.0,2.0,3.0").unwrap())
+            .is_some());
+    }
+}
@@ -0,0 +1,111 @@
+use futures_channel::mpsc;
+use futures_util::{Sink, StreamExt};
+use log::error;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use super::routing::{HubNode, HubRouting};
+use super::schema::PublishValidation;
+use crate::models::hub::HubMessage;
+
+const CHANNEL_CAPACITY: usize = 100;
+
+/// `HubSender` publishes `HubMessage`s to the hub nodes selected by the hub routing rules,
+/// and implements `Sink<HubMessage>`
+/// so that streams can be forwarded into the hub (e.g. `stream.map(Ok).forward(sender)`).
+///
+/// Messages are queued and sent by a background task, so the sink applies backpressure
+/// when the queue is full. `HubSender` is cheap to clone.
+#[derive(Debug, Clone)]
+pub struct HubSender(mpsc::Sender<HubMessage>);
+
+impl HubSender {
+    /// Spawns the publish task sending every message to `hub_nodes` according to `routing`.
+    /// Messages rejected by `validation` are logged and dropped
+    pub(crate) fn spawn(
+        hub_nodes: Vec<HubNode>,
+        routing: HubRouting,
+        validation: PublishValidation,
+    ) -> Self {
+        let (sender, mut receiver) = mpsc::channel::<HubMessage>(CHANNEL_CAPACITY);
+        tokio::spawn(async move {
+            while let Some(message) = receiver.next().await {
+                if let Err(e) = validation.check(&message) {
+                    error!("Failed to publish message: {:?}", e);
+                    continue;
+                }
+                if let Err(e) = routing.publish(&hub_nodes, message).await {
+                    error!("Failed to publish message: {:?}", e);
+                }
+            }
+        });
+        Self(sender)
+    }
+}
+
+fn closed(e: mpsc::SendError) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::BrokenPipe, e)
+}
+
+impl Sink<HubMessage> for HubSender {
+    type Error = std::io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().0.poll_ready(cx).map_err(closed)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: HubMessage) -> Result<(), Self::Error> {
+        self.get_mut().0.start_send(item).map_err(closed)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.get_mut().0)
+            .poll_flush(cx)
+            .map_err(closed)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Pin::new(&mut self.get_mut().0)
+            .poll_close(cx)
+            .map_err(closed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::{stream, SinkExt};
+
+    use crate::config::SchemaViolation;
+    use crate::models::hub::NodeId;
+    use crate::ports::NotificationHub;
+    use crate::services::hub::mock::MockNode;
+    use crate::services::hub::schema::SchemaRegistry;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_forward_stream_to_sender() {
+        let node = Arc::new(MockNode::default());
+        let hub_nodes = vec![(
+            NodeId::try_from("node").unwrap(),
+            node.clone() as Arc<dyn NotificationHub>,
+        )];
+        let (hub_sender, _) = tokio::sync::broadcast::channel(10);
+        let validation = PublishValidation::new(
+            SchemaRegistry::default(),
+            SchemaViolation::default(),
+            hub_sender,
+        );
+        let mut sender = HubSender::spawn(hub_nodes, HubRouting::new(), validation);
+
+        let messages = stream::iter(["1", "2", "3"])
+            .map(|data| Ok(HubMessage::try_from_str("channel", data).unwrap()));
+        sender.send_all(&mut Box::pin(messages)).await.unwrap();
+        sender.close().await.unwrap();
+
+        tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
+        let received = node.sent.lock().await;
+        assert_eq!(received.len(), 3);
+        assert_eq!(received[2].data.as_str(), "3");
+    }
+}
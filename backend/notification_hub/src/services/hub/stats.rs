@@ -0,0 +1,338 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use super::channel::HubRoutes;
+use super::events::is_meta_channel;
+use crate::models::hub::{HubChannelName, HubMessage};
+
+/// Reserved meta-channel where the hub publishes the statistics of its channels
+pub const HUB_STATS: &str = "hub_stats";
+
+/// Minimum period over which message and byte rates are measured
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// `HubStats` summarizes the state of a `HubManager`
+///
+/// # Fields
+/// - `hub_nodes`: Number of hub nodes.
+/// - `channels`: Number of channels with subscribers.
+/// - `subscriptions`: Number of active subscriptions across all channels.
+/// - `channel_stats`: Statistics of the channels with subscribers or traffic, sorted by
+///   channel name.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HubStats {
+    pub hub_nodes: usize,
+    pub channels: usize,
+    pub subscriptions: usize,
+    pub channel_stats: Vec<ChannelStats>,
+}
+
+/// Throughput and lag of a hub channel.
+///
+/// Statistics are published periodically on the `HUB_STATS` meta-channel with data
+/// `<channel>,<messages>,<bytes>,<messages/sec>,<bytes/sec>,<lag>,<dropped>`.
+///
+/// # Fields
+/// - `channel`: Name of the channel.
+/// - `messages`: Number of messages received by the hub in the channel.
+/// - `bytes`: Number of data bytes received by the hub in the channel.
+/// - `messages_per_sec`: Message rate over the last measurement window.
+/// - `bytes_per_sec`: Data rate over the last measurement window.
+/// - `lag`: Number of messages waiting in the fullest subscriber queue of the channel.
+///   Expanding queues don't report their length.
+/// - `dropped`: Number of messages dropped by the dispatcher, or by full subscriber queues
+///   dropping new messages. Subscribers lagging behind broadcast channels report their
+///   drops in `HubReceiver::dropped`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelStats {
+    pub channel: HubChannelName,
+    pub messages: u64,
+    pub bytes: u64,
+    pub messages_per_sec: f64,
+    pub bytes_per_sec: f64,
+    pub lag: usize,
+    pub dropped: u64,
+}
+
+impl ChannelStats {
+    pub(crate) fn new(channel: HubChannelName) -> Self {
+        Self {
+            channel,
+            messages: 0,
+            bytes: 0,
+            messages_per_sec: 0.0,
+            bytes_per_sec: 0.0,
+            lag: 0,
+            dropped: 0,
+        }
+    }
+
+    pub(crate) fn to_message(&self) -> HubMessage {
+        let data = format!(
+            "{},{},{},{:.3},{:.3},{},{}",
+            self.channel.as_str(),
+            self.messages,
+            self.bytes,
+            self.messages_per_sec,
+            self.bytes_per_sec,
+            self.lag,
+            self.dropped
+        );
+        HubMessage::try_from_str(HUB_STATS, &data).unwrap()
+    }
+}
+
+impl TryFrom<&HubMessage> for ChannelStats {
+    type Error = String;
+
+    fn try_from(message: &HubMessage) -> Result<Self, Self::Error> {
+        let data = message.data.as_str();
+        if message.channel.as_str() != HUB_STATS {
+            return Err(format!(
+                "Message from channel {} is not a channel statistics report",
+                message.channel.as_str()
+            ));
+        }
+        let invalid = || format!("Invalid channel statistics {}", data);
+        let fields: Vec<_> = data.split(',').collect();
+        let [channel, messages, bytes, messages_per_sec, bytes_per_sec, lag, dropped] = fields[..]
+        else {
+            return Err(invalid());
+        };
+        Ok(Self {
+            channel: HubChannelName::try_from(channel)?,
+            messages: messages.parse().map_err(|_| invalid())?,
+            bytes: bytes.parse().map_err(|_| invalid())?,
+            messages_per_sec: messages_per_sec.parse().map_err(|_| invalid())?,
+            bytes_per_sec: bytes_per_sec.parse().map_err(|_| invalid())?,
+            lag: lag.parse().map_err(|_| invalid())?,
+            dropped: dropped.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+#[derive(Debug)]
+struct ChannelCounters {
+    messages: u64,
+    bytes: u64,
+    dropped: u64,
+    last_active: Instant,
+    window_start: Instant,
+    window_messages: u64,
+    window_bytes: u64,
+    messages_per_sec: f64,
+    bytes_per_sec: f64,
+}
+
+impl ChannelCounters {
+    fn new(now: Instant) -> Self {
+        Self {
+            messages: 0,
+            bytes: 0,
+            dropped: 0,
+            last_active: now,
+            window_start: now,
+            window_messages: 0,
+            window_bytes: 0,
+            messages_per_sec: 0.0,
+            bytes_per_sec: 0.0,
+        }
+    }
+
+    // Updates the rates once the measurement window is over, and starts a new window
+    fn roll(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.window_start);
+        if elapsed < RATE_WINDOW {
+            return;
+        }
+        self.messages_per_sec = self.window_messages as f64 / elapsed.as_secs_f64();
+        self.bytes_per_sec = self.window_bytes as f64 / elapsed.as_secs_f64();
+        self.window_start = now;
+        self.window_messages = 0;
+        self.window_bytes = 0;
+    }
+}
+
+/// `ChannelMetrics` counts the messages, bytes and drops of every channel in the dispatch
+/// path. Meta-channels published by the hub itself are not counted.
+#[derive(Debug, Default)]
+pub(crate) struct ChannelMetrics {
+    counters: Mutex<HashMap<HubChannelName, ChannelCounters>>,
+}
+
+impl ChannelMetrics {
+    fn counters(&self) -> MutexGuard<'_, HashMap<HubChannelName, ChannelCounters>> {
+        self.counters.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Records message received by the hub
+    pub(crate) fn record(&self, message: &HubMessage) {
+        if is_meta_channel(&message.channel) {
+            return;
+        }
+        let now = Instant::now();
+        let bytes = message.data.as_str().len() as u64;
+        let mut counters = self.counters();
+        let counters = counters
+            .entry(message.channel.clone())
+            .or_insert_with(|| ChannelCounters::new(now));
+        counters.roll(now);
+        counters.last_active = now;
+        counters.messages += 1;
+        counters.bytes += bytes;
+        counters.window_messages += 1;
+        counters.window_bytes += bytes;
+    }
+
+    /// Records message of `channel` dropped by the dispatcher
+    pub(crate) fn record_drop(&self, channel: &HubChannelName) {
+        if is_meta_channel(channel) {
+            return;
+        }
+        let now = Instant::now();
+        let mut counters = self.counters();
+        let counters = counters
+            .entry(channel.clone())
+            .or_insert_with(|| ChannelCounters::new(now));
+        counters.last_active = now;
+        counters.dropped += 1;
+    }
+
+    /// Removes the counters of channels without subscribers in `routes`, and without traffic
+    /// for `ttl`. Channels with subscribers are considered active. Returns the removed channels
+    pub(crate) fn expire(&self, routes: &HubRoutes, ttl: Duration) -> Vec<HubChannelName> {
+        let now = Instant::now();
+        let mut expired = Vec::new();
+        self.counters().retain(|channel, counters| {
+            if routes.contains_key(channel) {
+                counters.last_active = now;
+            }
+            let active = now.duration_since(counters.last_active) < ttl;
+            if !active {
+                expired.push(channel.clone());
+            }
+            active
+        });
+        expired
+    }
+
+    /// Returns statistics of the channels with traffic or with subscribers in `routes`,
+    /// sorted by channel name
+    pub(crate) fn snapshot(&self, routes: &HubRoutes) -> Vec<ChannelStats> {
+        let now = Instant::now();
+        let mut counters = self.counters();
+        let mut stats: HashMap<HubChannelName, ChannelStats> = HashMap::new();
+        for (channel, counters) in counters.iter_mut() {
+            counters.roll(now);
+            stats.insert(
+                channel.clone(),
+                ChannelStats {
+                    channel: channel.clone(),
+                    messages: counters.messages,
+                    bytes: counters.bytes,
+                    messages_per_sec: counters.messages_per_sec,
+                    bytes_per_sec: counters.bytes_per_sec,
+                    lag: 0,
+                    dropped: counters.dropped,
+                },
+            );
+        }
+        for (channel, sender) in routes {
+            if is_meta_channel(channel) {
+                continue;
+            }
+            let channel_stats = stats
+                .entry(channel.clone())
+                .or_insert_with(|| ChannelStats::new(channel.clone()));
+            channel_stats.lag = sender.backlog();
+            channel_stats.dropped += sender.dropped();
+        }
+        let mut stats: Vec<_> = stats.into_values().collect();
+        stats.sort_by(|a, b| a.channel.as_str().cmp(b.channel.as_str()));
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::hub::channel::HubChannels;
+
+    fn channel(name: &str) -> HubChannelName {
+        HubChannelName::try_from(name).unwrap()
+    }
+
+    #[test]
+    fn test_channel_stats_message() {
+        let stats = ChannelStats {
+            channel: channel("imu"),
+            messages: 10,
+            bytes: 120,
+            messages_per_sec: 2.5,
+            bytes_per_sec: 30.0,
+            lag: 3,
+            dropped: 1,
+        };
+        let message = stats.to_message();
+        assert_eq!(message.data.as_str(), "imu,10,120,2.500,30.000,3,1");
+        assert_eq!(ChannelStats::try_from(&message).unwrap(), stats);
+
+        let message = HubMessage::try_from_str(HUB_STATS, "imu,10,120").unwrap();
+        assert!(ChannelStats::try_from(&message).is_err());
+        let message = HubMessage::try_from_str("status", "imu,10,120,2.5,30,3,1").unwrap();
+        assert!(ChannelStats::try_from(&message).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_channel_metrics() {
+        let mut channels = HubChannels::new();
+        let _receiver = channels.subscribe_user(&channel("imu"));
+        let _idle = channels.subscribe_user(&channel("idle"));
+        let routes = channels.routes();
+
+        let metrics = ChannelMetrics::default();
+        for _ in 0..3 {
+            let message = HubMessage::try_from_str("imu", "1.0,2.0").unwrap();
+            metrics.record(&message);
+            routes
+                .load()
+                .get(&message.channel)
+                .unwrap()
+                .send(message)
+                .unwrap();
+        }
+        metrics.record(&HubMessage::try_from_str(HUB_STATS, "imu,0,0,0,0,0,0").unwrap());
+        metrics.record_drop(&channel("imu"));
+
+        let stats = metrics.snapshot(&routes.load());
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0], ChannelStats::new(channel("idle")));
+        assert_eq!(stats[1].messages, 3);
+        assert_eq!(stats[1].bytes, 21);
+        assert_eq!(stats[1].lag, 3);
+        assert_eq!(stats[1].dropped, 1);
+    }
+
+    #[tokio::test]
+    async fn test_expire_idle_channels() {
+        let mut channels = HubChannels::new();
+        let _receiver = channels.subscribe_user(&channel("imu"));
+        let routes = channels.routes();
+
+        let metrics = ChannelMetrics::default();
+        metrics.record(&HubMessage::try_from_str("imu", "1").unwrap());
+        metrics.record(&HubMessage::try_from_str("sensor/1", "1").unwrap());
+        let ttl = Duration::from_millis(20);
+        assert!(metrics.expire(&routes.load(), ttl).is_empty());
+
+        std::thread::sleep(ttl);
+        assert_eq!(
+            metrics.expire(&routes.load(), ttl),
+            vec![channel("sensor/1")]
+        );
+        let stats = metrics.snapshot(&routes.load());
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].channel, channel("imu"));
+    }
+}
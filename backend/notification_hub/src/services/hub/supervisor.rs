@@ -0,0 +1,219 @@
+use log::{error, warn};
+use std::future::Future;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::broadcast;
+use tokio::task::{AbortHandle, JoinError, JoinHandle};
+
+use crate::config::SupervisionPolicy;
+use crate::models::hub::HubMessage;
+
+/// Reserved meta-channel where the hub publishes background task failures
+pub const HUB_ERRORS: &str = "hub_errors";
+
+/// `Supervisor` watches the background tasks of the hub and its nodes, so that they don't
+/// die silently. Failures are logged and published on the `HUB_ERRORS` meta-channel.
+///
+/// Hub tasks are spawned by the supervisor, and restarted according to the
+/// `SupervisionPolicy` if they panic. A hub task returning normally is stopping because
+/// the hub is shutting down, and is not reported.
+/// Hub node tasks are spawned by the nodes themselves. They can only be monitored, and any
+/// exit is reported as a failure.
+/// Every task spawned or monitored is aborted by `shutdown` when the hub is stopped. Aborted
+/// tasks are not reported.
+#[derive(Debug, Clone)]
+pub(crate) struct Supervisor {
+    hub_sender: broadcast::Sender<HubMessage>,
+    policy: SupervisionPolicy,
+    tasks: Arc<Mutex<Vec<AbortHandle>>>,
+}
+
+impl Supervisor {
+    pub(crate) fn new(
+        hub_sender: broadcast::Sender<HubMessage>,
+        policy: SupervisionPolicy,
+    ) -> Self {
+        Self {
+            hub_sender,
+            policy,
+            tasks: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    fn tasks(&self) -> MutexGuard<'_, Vec<AbortHandle>> {
+        self.tasks.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Records task, so it is aborted on shutdown
+    fn track<T>(&self, handle: &JoinHandle<T>) {
+        let mut tasks = self.tasks();
+        tasks.retain(|task| !task.is_finished());
+        tasks.push(handle.abort_handle());
+    }
+
+    /// Aborts every supervised and monitored task, and waits until they are stopped
+    pub(crate) async fn shutdown(&self) {
+        let tasks: Vec<_> = self.tasks().drain(..).collect();
+        for task in &tasks {
+            task.abort();
+        }
+        while !tasks.iter().all(|task| task.is_finished()) {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    fn report(&self, task: &str, reason: &str) {
+        error!("Hub task {} {}", task, reason);
+        let data = format!("{} {}", task, reason);
+        if let Ok(message) = HubMessage::try_from_str(HUB_ERRORS, &data) {
+            // nobody may be listening
+            let _ = self.hub_sender.send(message);
+        }
+    }
+
+    /// Reports `task` when it exits
+    pub(crate) fn monitor(&self, task: String, handle: JoinHandle<()>) {
+        self.track(&handle);
+        let supervisor = self.clone();
+        let monitor = tokio::spawn(async move {
+            match handle.await {
+                Ok(()) => supervisor.report(&task, "exited"),
+                Err(e) if e.is_cancelled() => (),
+                Err(e) => supervisor.report(&task, &failure(e)),
+            }
+        });
+        self.track(&monitor);
+    }
+
+    /// Spawns the task built by `spawn`, and rebuilds it after a failure as long as the
+    /// supervision policy allows it
+    pub(crate) fn supervise<F, Fut>(&self, task: &'static str, spawn: F)
+    where
+        F: Fn() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let supervisor = self.clone();
+        let supervision = tokio::spawn(async move {
+            let mut restarts = 0;
+            loop {
+                let handle = tokio::spawn(spawn());
+                supervisor.track(&handle);
+                let Err(e) = handle.await else {
+                    break;
+                };
+                if e.is_cancelled() {
+                    break;
+                }
+                supervisor.report(task, &failure(e));
+                match supervisor.policy {
+                    SupervisionPolicy::Restart { max_restarts } if restarts < max_restarts => {
+                        restarts += 1;
+                        warn!(
+                            "Restarting hub task {} ({}/{})",
+                            task, restarts, max_restarts
+                        );
+                    }
+                    _ => break,
+                }
+            }
+        });
+        self.track(&supervision);
+    }
+}
+
+fn failure(e: JoinError) -> String {
+    if e.is_panic() {
+        "panicked".to_string()
+    } else {
+        format!("failed: {}", e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_failed_task_is_restarted() {
+        let (hub_sender, mut hub_receiver) = broadcast::channel(10);
+        let supervisor =
+            Supervisor::new(hub_sender, SupervisionPolicy::Restart { max_restarts: 2 });
+
+        let runs = Arc::new(AtomicUsize::new(0));
+        let task_runs = runs.clone();
+        supervisor.supervise("test", move || {
+            let runs = task_runs.clone();
+            async move {
+                if runs.fetch_add(1, Ordering::SeqCst) == 0 {
+                    panic!("test task failure");
+                }
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+        let message = hub_receiver.recv().await.unwrap();
+        assert_eq!(message.channel.as_str(), HUB_ERRORS);
+        assert_eq!(message.data.as_str(), "test panicked");
+        assert!(hub_receiver.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_report_policy_does_not_restart() {
+        let (hub_sender, _hub_receiver) = broadcast::channel(10);
+        let supervisor = Supervisor::new(hub_sender, SupervisionPolicy::Report);
+
+        let runs = Arc::new(AtomicUsize::new(0));
+        let task_runs = runs.clone();
+        supervisor.supervise("test", move || {
+            let runs = task_runs.clone();
+            async move {
+                runs.fetch_add(1, Ordering::SeqCst);
+                panic!("test task failure");
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_monitored_task_exit_is_reported() {
+        let (hub_sender, mut hub_receiver) = broadcast::channel(10);
+        let supervisor = Supervisor::new(hub_sender, SupervisionPolicy::Report);
+        supervisor.monitor("node 0".to_string(), tokio::spawn(async {}));
+
+        let message = hub_receiver.recv().await.unwrap();
+        assert_eq!(message.data.as_str(), "node 0 exited");
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_aborts_tasks() {
+        let (hub_sender, mut hub_receiver) = broadcast::channel(10);
+        let supervisor = Supervisor::new(hub_sender, SupervisionPolicy::Report);
+        let node_task = tokio::spawn(std::future::pending::<()>());
+        let node_abort = node_task.abort_handle();
+        supervisor.monitor("node 0".to_string(), node_task);
+
+        let runs = Arc::new(AtomicUsize::new(0));
+        let task_runs = runs.clone();
+        supervisor.supervise("test", move || {
+            let runs = task_runs.clone();
+            async move {
+                loop {
+                    runs.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                }
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        supervisor.shutdown().await;
+        let stopped_runs = runs.load(Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), stopped_runs);
+        assert!(node_abort.is_finished());
+        assert!(hub_receiver.try_recv().is_err());
+    }
+}
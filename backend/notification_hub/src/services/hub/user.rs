@@ -1,16 +1,13 @@
-use std::collections::HashMap;
-use tokio::sync::broadcast;
+use std::collections::{HashMap, HashSet};
 use uuid::Uuid;
 
-use super::controller::HubReceiver;
-use crate::models::hub::{HubChannelName, HubMessage};
+use crate::models::hub::HubChannelName;
 
-/// `HubSubscriptionInfo` holds information about a subscription in the hub, including
-/// channel name and associated channel receiver.
-type HubSubscriptionInfo = HashMap<HubChannelName, broadcast::Receiver<HubMessage>>;
+/// `HubSubscriptionInfo` holds the set of channels a user is subscribed to.
+type HubSubscriptionInfo = HashSet<HubChannelName>;
 
 /// `HubUsers` manages a collection of users in the hub, identified by their UUIDs.
-/// Each UUID identifies a collection of channels/receivers a user is subscribed to.
+/// Each UUID identifies a collection of channels a user is subscribed to.
 #[derive(Debug, Default)]
 pub(crate) struct HubUsers(HashMap<Uuid, HubSubscriptionInfo>);
 
@@ -21,11 +18,10 @@ impl HubUsers {
     }
 
     /// Subscribes a user to a specific channel.
-    pub(crate) fn subscribe_user(&mut self, channel: &HubChannelName, hub_receiver: &HubReceiver) {
-        let HubReceiver(user_id, receiver) = hub_receiver.resubscribe();
+    pub(crate) fn subscribe_user(&mut self, channel: &HubChannelName, user_id: Uuid) {
         let subscription_info = self.0.entry(user_id).or_default();
 
-        subscription_info.insert(channel.clone(), receiver);
+        subscription_info.insert(channel.clone());
     }
 
     /// Unsubscribes a user from a specific channel.
@@ -39,57 +35,82 @@ impl HubUsers {
             }
         }
     }
+
+    /// Returns whether a user is subscribed to a specific channel.
+    pub(crate) fn is_subscribed(&self, channel: &HubChannelName, user_id: Uuid) -> bool {
+        self.0
+            .get(&user_id)
+            .is_some_and(|subscription_info| subscription_info.contains(channel))
+    }
+
+    /// Returns the channels a user is subscribed to.
+    pub(crate) fn channels(&self, user_id: Uuid) -> Vec<HubChannelName> {
+        self.0
+            .get(&user_id)
+            .map(|subscription_info| subscription_info.iter().cloned().collect())
+            .unwrap_or_default()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    #[tokio::test]
-    async fn test_subscribe_user() {
+    #[test]
+    fn test_subscribe_user() {
         let mut hub_users = HubUsers::new();
         let channel = HubChannelName::try_from("test_channel").unwrap();
-        let (_, receiver) = broadcast::channel(10);
         let user_id = Uuid::new_v4();
-        let hub_receiver = HubReceiver(user_id, receiver);
 
-        hub_users.subscribe_user(&channel, &hub_receiver);
+        hub_users.subscribe_user(&channel, user_id);
 
         assert!(hub_users.0.contains_key(&user_id));
-        assert!(hub_users.0.get(&user_id).unwrap().contains_key(&channel));
+        assert!(hub_users.0.get(&user_id).unwrap().contains(&channel));
     }
 
-    #[tokio::test]
-    async fn test_unsubscribe_user() {
+    #[test]
+    fn test_unsubscribe_user() {
         let mut hub_users = HubUsers::new();
         let channel = HubChannelName::try_from("test_channel").unwrap();
-        let (_, receiver) = broadcast::channel(10);
         let user_id = Uuid::new_v4();
-        let hub_receiver = HubReceiver(user_id, receiver);
 
-        hub_users.subscribe_user(&channel, &hub_receiver);
+        hub_users.subscribe_user(&channel, user_id);
         hub_users.unsubscribe_user(&channel, user_id);
 
         assert!(!hub_users.0.contains_key(&user_id));
     }
 
-    #[tokio::test]
-    async fn test_unsubscribe_user_from_multiple_channels() {
+    #[test]
+    fn test_unsubscribe_user_from_multiple_channels() {
         let mut hub_users = HubUsers::new();
         let channel1 = HubChannelName::try_from("test_channel1").unwrap();
         let channel2 = HubChannelName::try_from("test_channel2").unwrap();
-        let (_, receiver1) = broadcast::channel(10);
-        let (_, receiver2) = broadcast::channel(10);
         let user_id = Uuid::new_v4();
-        let hub_receiver1 = HubReceiver(user_id, receiver1);
-        let hub_receiver2 = HubReceiver(user_id, receiver2);
 
-        hub_users.subscribe_user(&channel1, &hub_receiver1);
-        hub_users.subscribe_user(&channel2, &hub_receiver2);
+        hub_users.subscribe_user(&channel1, user_id);
+        hub_users.subscribe_user(&channel2, user_id);
         hub_users.unsubscribe_user(&channel1, user_id);
 
         assert!(hub_users.0.contains_key(&user_id));
-        assert!(!hub_users.0.get(&user_id).unwrap().contains_key(&channel1));
-        assert!(hub_users.0.get(&user_id).unwrap().contains_key(&channel2));
+        assert!(!hub_users.0.get(&user_id).unwrap().contains(&channel1));
+        assert!(hub_users.0.get(&user_id).unwrap().contains(&channel2));
+    }
+
+    #[test]
+    fn test_user_channels() {
+        let mut hub_users = HubUsers::new();
+        let channel1 = HubChannelName::try_from("test_channel1").unwrap();
+        let channel2 = HubChannelName::try_from("test_channel2").unwrap();
+        let user_id = Uuid::new_v4();
+
+        hub_users.subscribe_user(&channel1, user_id);
+        hub_users.subscribe_user(&channel2, user_id);
+
+        assert!(hub_users.is_subscribed(&channel1, user_id));
+        assert!(!hub_users.is_subscribed(&channel1, Uuid::new_v4()));
+        let mut channels = hub_users.channels(user_id);
+        channels.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        assert_eq!(channels, vec![channel1, channel2]);
+        assert!(hub_users.channels(Uuid::new_v4()).is_empty());
     }
 }
@@ -1,7 +1,6 @@
 use imu_common::types::untimed::{Scalar, UnitQuaternion, XYZ};
 use log::info;
 use notification_hub::models::hub::{HubChannelName, HubMessage};
-use serde_json;
 use std::io::{Error, ErrorKind};
 use tokio::time::Duration;
 
@@ -40,18 +39,18 @@ async fn main() -> std::io::Result<()> {
     hub::wait_for_channels(&hub, &channels).await;
 
     // register to channels
-    let hub_receivers = hub::register_to_channels(&mut hub, &channels).await;
+    let mut hub_receivers = hub::register_to_channels(&mut hub, &channels).await;
 
     // process channels
-    hub::listen_to_channel("odometry", &hub_receivers, Box::new(odometry_processor)).await;
+    hub::listen_to_channel("odometry", &mut hub_receivers, Box::new(odometry_processor)).await;
     hub::listen_to_channel(
         "orientation",
-        &hub_receivers,
+        &mut hub_receivers,
         Box::new(orientation_processor),
     )
     .await;
-    hub::listen_to_channel("distance", &hub_receivers, Box::new(distance_processor)).await;
-    hub::listen_to_channel("joystick", &hub_receivers, Box::new(joystick_processor)).await;
+    hub::listen_to_channel("distance", &mut hub_receivers, Box::new(distance_processor)).await;
+    hub::listen_to_channel("joystick", &mut hub_receivers, Box::new(joystick_processor)).await;
 
     tokio::time::sleep(Duration::from_secs(50)).await;
 
@@ -59,8 +58,7 @@ async fn main() -> std::io::Result<()> {
 }
 
 fn odometry_processor(channel: HubChannelName, message: HubMessage) {
-    let data = format!(r#""{}""#, message.data.as_str());
-    if let Ok(sample) = serde_json::from_str::<XYZ>(data.as_str()) {
+    if let Ok(sample) = message.payload::<XYZ>() {
         println!(
             "Odometry processor received message {:?} from channel {:?}",
             sample, channel
@@ -69,8 +67,7 @@ fn odometry_processor(channel: HubChannelName, message: HubMessage) {
 }
 
 fn orientation_processor(channel: HubChannelName, message: HubMessage) {
-    let data = format!(r#""{}""#, message.data.as_str());
-    if let Ok(sample) = serde_json::from_str::<UnitQuaternion>(data.as_str()) {
+    if let Ok(sample) = message.payload::<UnitQuaternion>() {
         println!(
             "Orientation processor received message {:?} from channel {:?}",
             sample, channel
@@ -79,8 +76,7 @@ fn orientation_processor(channel: HubChannelName, message: HubMessage) {
 }
 
 fn distance_processor(channel: HubChannelName, message: HubMessage) {
-    let data = message.data.as_str();
-    if let Ok(sample) = serde_json::from_str::<Scalar>(data) {
+    if let Ok(sample) = message.payload::<Scalar>() {
         println!(
             "Distance processor received message {:?} from channel {:?}",
             sample, channel
@@ -89,8 +85,7 @@ fn distance_processor(channel: HubChannelName, message: HubMessage) {
 }
 
 fn joystick_processor(channel: HubChannelName, message: HubMessage) {
-    let data = format!(r#""{}""#, message.data.as_str());
-    if let Ok(sample) = serde_json::from_str::<XYZ>(data.as_str()) {
+    if let Ok(sample) = message.payload::<XYZ>() {
         println!(
             "Joystick processor received message {:?} from channel {:?}",
             sample, channel
@@ -2,22 +2,27 @@ use async_trait::async_trait;
 use log::{debug, error, info, warn};
 use std::sync::Arc;
 use tokio::fs::{File, OpenOptions};
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::io::AsyncWriteExt;
 use tokio::sync::{broadcast, Mutex, RwLock};
 
+use bytes::Bytes;
+use notification_hub::adapters::serial::buffer::LineBuffer;
 use notification_hub::adapters::serial::channels::{SerialChannelName, SerialPubChannels};
+use notification_hub::adapters::serial::format::LineFormat;
 use notification_hub::adapters::serial::message::SerialRawMessage;
 use notification_hub::models::hub::{HubChannelName, HubMessage};
-use notification_hub::ports::NotificationHub;
+use notification_hub::ports::{NodeTasks, NotificationHub};
 
 /// The `ClientPipe` struct represents a client that communicates via files, mimicking a serial port,  that can subscribe
 /// to specific topic channels. It allows to send and receive messages on specific topics.
+/// Lines are quoted `##CHANNEL## DATA` strings, unless a `LineFormat` is set.
 
 #[derive(Debug)]
 pub struct PipeClient {
     write_pipe: Arc<Mutex<Option<File>>>,
     read_pipes: Option<Vec<Arc<Mutex<File>>>>,
     channels: Arc<RwLock<SerialPubChannels>>,
+    line_format: Option<LineFormat>,
 }
 
 impl PipeClient {
@@ -61,64 +66,61 @@ impl PipeClient {
             write_pipe: Arc::new(Mutex::new(write_pipe)),
             read_pipes: read_pipes,
             channels: Arc::new(RwLock::new(SerialPubChannels::new())),
+            line_format: None,
         };
         info!("Pipe opened");
         Ok(pipe_client)
     }
+
+    /// Reads and writes lines with the delimiters of `line_format`, emulating devices with
+    /// custom firmware
+    pub fn with_line_format(mut self, line_format: LineFormat) -> Self {
+        self.line_format = Some(line_format);
+        self
+    }
+
     /// Start client
     async fn start_read_pipe(
         &self,
         sender: Option<broadcast::Sender<HubMessage>>,
         read_pipe: Arc<Mutex<File>>,
-    ) -> Result<(), std::io::Error> {
+    ) -> Result<NodeTasks, std::io::Error> {
+        let mut tasks = NodeTasks::new();
         if let Some(sender) = sender {
             let read_pipe = Arc::clone(&read_pipe);
             let channels = Arc::clone(&self.channels);
+            let line_format = self.line_format.clone();
             info!("Starting pipe...");
 
-            tokio::spawn(async move {
+            let task = tokio::spawn(async move {
                 let read_pipe = Arc::clone(&read_pipe);
+                let mut lines = LineBuffer::new();
+                let terminator = line_format.as_ref().map_or(b'\n', LineFormat::terminator);
                 loop {
-                    let line = {
+                    let read = {
                         let mut read_pipe_lock = read_pipe.lock().await;
-                        let reader = BufReader::new(&mut *read_pipe_lock);
-                        reader.lines().next_line().await // Read one line
+                        lines.read_from(&mut *read_pipe_lock).await
                     };
-                    match line {
-                        Ok(Some(line)) => {
-                            let line = line
-                                .strip_prefix('"')
-                                .and_then(|s| s.strip_suffix('"'))
-                                .unwrap_or(&line)
-                                .to_string();
-                            if line.starts_with("##") {
-                                let raw_serial_message = SerialRawMessage::from_str(line.as_str());
-                                // serial client learns available channels by inspecting received data
-                                match HubMessage::try_from(raw_serial_message) {
-                                    Ok(message) => {
-                                        let mut serial_channels = channels.write().await;
-                                        serial_channels
-                                            .add(SerialChannelName::from(message.channel.clone()));
-                                        debug!(
-                                            "New message from channel {:?} received by Pipe client",
-                                            message.channel.clone()
-                                        );
-                                        if let Err(e) = sender.send(message) {
-                                            error!("Pipe send error {:?}", e);
-                                        }
-                                    }
-                                    Err(e) => error!("Pipe receive error {:?}", e),
+                    match read {
+                        Ok(n) => {
+                            while let Some(line) = lines.next_delimited(terminator) {
+                                handle_pipe_line(line, line_format.as_ref(), &channels, &sender)
+                                    .await;
+                            }
+                            // lines written to the pipe may not be terminated
+                            if n == 0 {
+                                if let Some(line) = lines.take_remaining() {
+                                    handle_pipe_line(
+                                        line,
+                                        line_format.as_ref(),
+                                        &channels,
+                                        &sender,
+                                    )
+                                    .await;
                                 }
-                            } else {
-                                warn!(
-                                    "Invalid pipe data. Waiting for valid channel prefix: {:?}",
-                                    line
-                                );
+                                tokio::time::sleep(tokio::time::Duration::from_millis(100)).await
                             }
                         }
-                        Ok(None) => {
-                            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await
-                        }
                         Err(e) => {
                             error!("Pipe error {:?}", e);
                             break;
@@ -126,11 +128,72 @@ impl PipeClient {
                     }
                 }
             });
+            tasks.push(task);
         }
-        Ok(())
+        Ok(tasks)
+    }
+}
+
+// Parses a line read from the pipe and forwards it to the hub
+async fn handle_pipe_line(
+    line: Bytes,
+    line_format: Option<&LineFormat>,
+    channels: &RwLock<SerialPubChannels>,
+    sender: &broadcast::Sender<HubMessage>,
+) {
+    let (line, is_message) = match line_format {
+        Some(line_format) => {
+            let line = line_format.trim(line);
+            let is_message = line_format.is_message(&line);
+            (line, is_message)
+        }
+        None => {
+            let line = trim_line(line);
+            let is_message = line.starts_with(b"##");
+            (line, is_message)
+        }
+    };
+    if is_message {
+        let message = match line_format {
+            Some(line_format) => line_format.parse(&line),
+            None => HubMessage::try_from(SerialRawMessage::from_bytes(line)),
+        };
+        // serial client learns available channels by inspecting received data
+        match message {
+            Ok(message) => {
+                let mut serial_channels = channels.write().await;
+                serial_channels.add(SerialChannelName::from(message.channel.clone()));
+                debug!(
+                    "New message from channel {:?} received by Pipe client",
+                    message.channel.clone()
+                );
+                if let Err(e) = sender.send(message) {
+                    error!("Pipe send error {:?}", e);
+                }
+            }
+            Err(e) => error!("Pipe receive error {:?}", e),
+        }
+    } else if !line.is_empty() {
+        warn!(
+            "Invalid pipe data. Waiting for valid channel prefix: {:?}",
+            String::from_utf8_lossy(&line)
+        );
     }
 }
 
+// Removes line terminator and JSON string quotes without copying
+fn trim_line(line: Bytes) -> Bytes {
+    let mut end = line.len();
+    while end > 0 && (line[end - 1] == b'\n' || line[end - 1] == b'\r') {
+        end -= 1;
+    }
+    let line = line.slice(..end);
+    if line.len() >= 2 && line.starts_with(b"\"") && line.ends_with(b"\"") {
+        return line.slice(1..line.len() - 1);
+    }
+    line
+}
+
 #[async_trait]
 impl NotificationHub for PipeClient {
     /// Send a message through channel
@@ -145,8 +208,10 @@ impl NotificationHub for PipeClient {
                 ))
             }
         };
-        let serial_message = SerialRawMessage::from(message);
-        let raw_bytes = serial_message.to_bytes()?;
+        let raw_bytes = match &self.line_format {
+            Some(line_format) => line_format.to_line(&message),
+            None => SerialRawMessage::from(message).to_bytes()?,
+        };
         write_pipe.write_all(&raw_bytes).await?;
         Ok(())
     }
@@ -161,12 +226,13 @@ impl NotificationHub for PipeClient {
     async fn start(
         &self,
         sender: Option<broadcast::Sender<HubMessage>>,
-    ) -> Result<(), std::io::Error> {
+    ) -> Result<NodeTasks, std::io::Error> {
+        let mut tasks = NodeTasks::new();
         match &self.read_pipes {
             Some(read_pipes) => {
                 for read_pipe in read_pipes {
                     let pipe = Arc::clone(read_pipe);
-                    self.start_read_pipe(sender.clone(), pipe).await?;
+                    tasks.extend(self.start_read_pipe(sender.clone(), pipe).await?);
                 }
             }
             None => {
@@ -176,16 +242,25 @@ impl NotificationHub for PipeClient {
                 ))
             }
         };
-        Ok(())
+        Ok(tasks)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use notification_hub::adapters::serial::format::LineFormatBuilder;
     use notification_hub::models::hub::HubData;
     use tokio::time::Duration;
 
+    #[test]
+    fn test_trim_line() {
+        let line = trim_line(Bytes::from_static(b"\"##test_channel## 1,2,3\"\r\n"));
+        assert_eq!(&line[..], b"##test_channel## 1,2,3");
+        let line = trim_line(Bytes::from_static(b"##test_channel## 1,2,3"));
+        assert_eq!(&line[..], b"##test_channel## 1,2,3");
+    }
+
     #[tokio::test]
     async fn test_pipe_client_new() {
         let write_path = "/tmp/test_pipe";
@@ -203,6 +278,36 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_line_format() {
+        let path = "/tmp/test_pipe_line_format";
+        let (sender, mut receiver) = broadcast::channel(16);
+        let line_format = LineFormatBuilder::new()
+            .prefix("$")
+            .separator(",")
+            .terminator(b';')
+            .build()
+            .unwrap();
+
+        let client_sender = PipeClient::new(Some(path), None)
+            .await
+            .unwrap()
+            .with_line_format(line_format.clone());
+        let client_receiver = PipeClient::new(None, Some(vec![path]))
+            .await
+            .unwrap()
+            .with_line_format(line_format);
+        client_receiver.start(Some(sender)).await.unwrap();
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let message = HubMessage::try_from_str("gpgga", "123519,4807.038,N").unwrap();
+        client_sender.send(message).await.unwrap();
+
+        let message = receiver.recv().await.unwrap();
+        assert_eq!(message.channel.as_str(), "gpgga");
+        assert_eq!(message.data.as_str(), "123519,4807.038,N");
+    }
+
     #[tokio::test]
     async fn test_list_channels() {
         let path = "/tmp/test_pipe_list";
@@ -1,6 +1,5 @@
-use imu_common::types::clock::Clock;
 use log::{error, info};
-use notification_hub::models::hub::{HubChannelName, HubData, HubMessage};
+use notification_hub::models::hub::{HubChannelName, HubData, HubMessage, HubTimestamp};
 use notification_hub::ports::NotificationHub;
 use rand::Rng;
 use tokio::sync::broadcast;
@@ -58,17 +57,16 @@ async fn generate_data_process(
     n_dims: usize,
     period_millis: u64,
 ) {
-    let now = Clock::now().as_secs();
-    let start = time::Instant::now();
-
     tokio::spawn(async move {
         loop {
-            let timestamp = start.elapsed().as_secs_f64() + now;
+            let timestamp = HubTimestamp::now();
             let data = generate_random_data(n_dims);
             let message = HubMessage {
                 channel: channel.clone(),
                 timestamp,
                 data,
+                headers: Default::default(),
+                origin: None,
             };
             // Send the generated message to the sender
             if let Err(e) = sender.send(message) {
@@ -86,12 +84,5 @@ fn generate_random_data(n_dims: usize) -> HubData {
     let values: Vec<f64> = (0..n_dims)
         .map(|_| rng.gen_range(-100.0..100.0)) // Random f64 between -100 and 100
         .collect();
-
-    // Join the values into a comma-separated string
-    let data = values
-        .iter()
-        .map(|v| v.to_string())
-        .collect::<Vec<String>>()
-        .join(",");
-    data.parse::<HubData>().unwrap()
+    HubData::from_f64_slice(&values).unwrap()
 }
@@ -0,0 +1,237 @@
+use log::info;
+use notification_hub::adapters::websocket::{WebSocketClient, WebSocketServer};
+use notification_hub::models::hub::HubMessage;
+use notification_hub::ports::NotificationHub;
+use notification_hub::services::hub::{HubManager, NodeId};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use crate::PipeClient;
+
+const LOCALHOST: &str = "127.0.0.1:0";
+
+/// `TestHub` runs a complete hub in process for integration tests:
+/// - an embedded WebSocket server listening on a free port chosen by the OS,
+/// - a hub with a WebSocket node connected to the embedded server, and an emulated serial
+///   node (a `PipeClient` over two temporary files),
+/// - the emulated serial device at the other end of the serial node.
+///
+/// Tests don't depend on fixed ports or files, so they can run concurrently. Tests end with
+/// `shutdown`, which stops the hub and its nodes before the server. Dropping `TestHub` can't
+/// wait for the hub tasks, so it only stops the server, which closes its listener and every
+/// open connection, and removes the temporary files.
+#[derive(Debug)]
+pub struct TestHub {
+    hub: HubManager,
+    server: WebSocketServer,
+    ws_url: String,
+    ws_node: NodeId,
+    serial_node: NodeId,
+    device: PipeClient,
+    serial_paths: [PathBuf; 2],
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("test_hub_{}_{}", name, Uuid::new_v4()))
+}
+
+impl TestHub {
+    /// Starts the embedded server and the hub
+    pub async fn start() -> Result<Self, std::io::Error> {
+        let server = WebSocketServer::new(LOCALHOST);
+        let ws_url = server.start().await?.to_string();
+        info!("Test hub WS server listening on {}", ws_url);
+
+        let device_to_hub = temp_path("serial_rx");
+        let hub_to_device = temp_path("serial_tx");
+        let device = PipeClient::new(Some(path_str(&device_to_hub)?), None).await?;
+        let serial = PipeClient::new(
+            Some(path_str(&hub_to_device)?),
+            Some(vec![path_str(&device_to_hub)?]),
+        )
+        .await?;
+
+        let mut hub = HubManager::new();
+        let invalid_input = |e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e);
+        let ws_node = hub
+            .add("ws", Box::new(WebSocketClient::new(&ws_url).await?))
+            .map_err(invalid_input)?;
+        let serial_node = hub.add("serial", Box::new(serial)).map_err(invalid_input)?;
+        let test_hub = Self {
+            hub,
+            server,
+            ws_url,
+            ws_node,
+            serial_node,
+            device,
+            serial_paths: [device_to_hub, hub_to_device],
+        };
+        test_hub.hub.start().await?;
+        Ok(test_hub)
+    }
+
+    pub fn hub(&mut self) -> &mut HubManager {
+        &mut self.hub
+    }
+
+    /// Address of the embedded WebSocket server
+    pub fn ws_url(&self) -> &str {
+        &self.ws_url
+    }
+
+    /// Identifier of the WebSocket node in the hub
+    pub fn ws_node(&self) -> NodeId {
+        self.ws_node.clone()
+    }
+
+    /// Identifier of the emulated serial node in the hub
+    pub fn serial_node(&self) -> NodeId {
+        self.serial_node.clone()
+    }
+
+    /// Connects a new peer to the embedded WebSocket server
+    pub async fn ws_peer(&self) -> Result<WebSocketClient, std::io::Error> {
+        WebSocketClient::new(&self.ws_url).await
+    }
+
+    /// Writes message to the hub from the emulated serial device
+    pub async fn serial_write(&self, message: HubMessage) -> Result<(), std::io::Error> {
+        self.device.send(message).await
+    }
+
+    /// Stops the hub and its nodes, then the embedded server, and removes the temporary files
+    pub async fn shutdown(self) {
+        self.hub.stop().await;
+        self.server.stop();
+    }
+}
+
+fn path_str(path: &Path) -> Result<&str, std::io::Error> {
+    path.to_str().ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Invalid path {:?}", path),
+        )
+    })
+}
+
+impl Drop for TestHub {
+    fn drop(&mut self) {
+        self.server.stop();
+        for path in &self.serial_paths {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hub::wait_for_channels;
+    use notification_hub::models::hub::HubChannelName;
+    use notification_hub::services::hub::HubReceiver;
+    use tokio::sync::broadcast::error::RecvError;
+    use tokio::time::{timeout, Duration};
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+    const RETRY: Duration = Duration::from_millis(20);
+
+    // Server processes subscriptions asynchronously, so message is published from peer
+    // until the receiver gets it
+    async fn publish_until_received(
+        peer: &WebSocketClient,
+        receiver: &mut HubReceiver,
+        message: HubMessage,
+    ) -> HubMessage {
+        timeout(TIMEOUT, async {
+            loop {
+                peer.send(message.clone()).await.unwrap();
+                if let Ok(Ok(received)) = timeout(RETRY, receiver.recv()).await {
+                    return received;
+                }
+            }
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_wsocket() {
+        let _ = env_logger::builder().is_test(true).try_init();
+        let mut test_hub = TestHub::start().await.unwrap();
+        let peer = test_hub.ws_peer().await.unwrap();
+        let channel = HubChannelName::try_from("topic1").unwrap();
+
+        // check channels are empty
+        assert!(test_hub.hub().list_channels().await.unwrap().is_empty());
+
+        // Send message to topic1. This will create a new channel
+        let message = HubMessage::try_from_str("topic1", "test topic1").unwrap();
+        peer.send(message).await.unwrap();
+        timeout(
+            TIMEOUT,
+            wait_for_channels(test_hub.hub(), &[channel.clone()]),
+        )
+        .await
+        .unwrap();
+        let channels: Vec<_> = test_hub
+            .hub()
+            .list_channels()
+            .await
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(channels, vec![channel.clone()]);
+
+        // subscribe to channel topic 1 and send message
+        let mut receiver = test_hub
+            .hub()
+            .register_to_channel(channel.clone())
+            .await
+            .unwrap();
+        let message = HubMessage::try_from_str("topic1", "new message test topic1").unwrap();
+        let received = publish_until_received(&peer, &mut receiver, message).await;
+        assert_eq!(received.channel, channel);
+        assert_eq!(received.data.as_str(), "new message test topic1");
+
+        // unsubscribe from channel topic 1
+        test_hub
+            .hub()
+            .unregister_from_channel(channel, receiver.user_id())
+            .await
+            .unwrap();
+        assert!(matches!(receiver.recv().await, Err(RecvError::Closed)));
+
+        // server no longer accepts connections
+        test_hub.shutdown().await;
+        assert!(peer.list_channels().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_emulated_serial() {
+        let mut test_hub = TestHub::start().await.unwrap();
+        let channel = HubChannelName::try_from("acceleration").unwrap();
+
+        // serial node learns channels from received data
+        let message = HubMessage::try_from_str("acceleration", "1,2,3").unwrap();
+        test_hub.serial_write(message).await.unwrap();
+        timeout(
+            TIMEOUT,
+            wait_for_channels(test_hub.hub(), &[channel.clone()]),
+        )
+        .await
+        .unwrap();
+
+        let mut receiver = test_hub
+            .hub()
+            .register_to_channel(channel.clone())
+            .await
+            .unwrap();
+        let message = HubMessage::try_from_str("acceleration", "4,5,6").unwrap();
+        test_hub.serial_write(message).await.unwrap();
+        let received = timeout(TIMEOUT, receiver.recv()).await.unwrap().unwrap();
+        assert_eq!(received.channel, channel);
+        assert_eq!(received.data.as_str(), "4,5,6");
+        test_hub.shutdown().await;
+    }
+}
@@ -1,10 +1,11 @@
+use futures_util::StreamExt;
 use log::info;
-use notification_hub::adapters::serial::SerialClient;
-use notification_hub::adapters::websocket::WebSocketClient;
+use notification_hub::config::NodeConfigBuilder;
 use notification_hub::models::hub::{HubChannelName, HubMessage};
 use notification_hub::services::hub::controller::HubReceiver;
-use notification_hub::services::hub::HubManager;
-use std::collections::HashMap;
+use notification_hub::services::hub::{ChannelEvent, HubManager, HubManagerBuilder};
+use std::collections::{HashMap, HashSet};
+use tokio::sync::broadcast::error::RecvError;
 use tokio::time::Duration;
 
 use crate::ClientPipeOptions;
@@ -16,16 +17,17 @@ pub type ProcessorFunction = Box<dyn Fn(HubChannelName, HubMessage) + Send + Syn
 
 pub async fn listen_to_channel(
     channel_str: &str,
-    receivers: &RegisteredReceivers,
+    receivers: &mut RegisteredReceivers,
     processor: ProcessorFunction,
 ) {
     let channel = HubChannelName::try_from(channel_str).unwrap();
-    let hub_receiver = receivers.get(&channel).unwrap();
-    let mut receiver = hub_receiver.receiver();
+    let mut receiver = receivers.remove(&channel).unwrap();
     tokio::spawn(async move {
         loop {
-            if let Ok(data) = receiver.recv().await {
-                processor(channel.clone(), data);
+            match receiver.recv().await {
+                Ok(data) => processor(channel.clone(), data),
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
             }
         }
     });
@@ -47,20 +49,20 @@ pub async fn register_to_channels(
 }
 
 pub async fn wait_for_channels(hub: &HubManager, channels: &[HubChannelName]) {
-    loop {
-        let available_channels: Vec<_> = hub.list_channels().await.unwrap().into_iter().collect();
-        info!(
-            "Available channels: {:?}, Requested channels: {:?}",
-            available_channels, channels
-        );
-        let all_available = channels
-            .iter()
-            .all(|item| available_channels.contains(item));
-
-        if all_available {
-            break;
+    let mut pending: HashSet<_> = channels.iter().cloned().collect();
+    let mut events = hub.watch_channels();
+    while !pending.is_empty() {
+        match events.next().await {
+            Some(ChannelEvent::ChannelAdded(channel)) => {
+                pending.remove(&channel);
+                info!(
+                    "Channel {:?} available, Pending channels: {:?}",
+                    channel, pending
+                );
+            }
+            Some(ChannelEvent::ChannelRemoved(_)) => continue,
+            None => break,
         }
-        tokio::time::sleep(Duration::from_millis(900)).await;
     }
 }
 
@@ -69,30 +71,37 @@ pub async fn start_hub(
     ws_url: Option<&str>,
     serial_port_options: Option<(&str, u32)>,
 ) -> Result<HubManager, std::io::Error> {
-    let mut hub = HubManager::new();
+    let invalid_input = |e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e);
+    let mut builder = HubManagerBuilder::new();
     if let Some(pipe_options) = pipe_options {
         let pipe_read_path: Vec<_> = pipe_options
             .into_iter()
             .filter_map(|o| o.read_path())
             .collect();
-        let pipe_read_path: Vec<_> = pipe_read_path.iter().map(|s| s.as_str()).collect();
-        if let Ok(pipe_client) = PipeClient::new(None, Some(pipe_read_path)).await {
-            hub.add(Box::new(pipe_client));
-        }
+        let pipe = NodeConfigBuilder::new().name("pipe").custom(move || {
+            let pipe_read_path = pipe_read_path.clone();
+            async move {
+                let pipe_read_path: Vec<_> = pipe_read_path.iter().map(|s| s.as_str()).collect();
+                PipeClient::new(None, Some(pipe_read_path)).await
+            }
+        });
+        builder = builder.node(pipe.optional(true).build().map_err(invalid_input)?);
     }
     if let Some(ws_url) = ws_url {
-        if let Ok(ws_client) = WebSocketClient::new(ws_url).await {
-            hub.add(Box::new(ws_client));
-        }
+        let ws = NodeConfigBuilder::new()
+            .name("ws")
+            .websocket(ws_url)
+            .optional(true);
+        builder = builder.node(ws.build().map_err(invalid_input)?);
     }
-    if let Some(serial_port_options) = serial_port_options {
-        if let Ok(serial_client) = SerialClient::new(serial_port_options.0, serial_port_options.1) {
-            hub.add(Box::new(serial_client));
-        }
+    if let Some((port, baud_rate)) = serial_port_options {
+        let serial = NodeConfigBuilder::new()
+            .name("serial")
+            .serial(port, baud_rate)
+            .optional(true);
+        builder = builder.node(serial.build().map_err(invalid_input)?);
     }
-
-    hub.start().await?;
-    Ok(hub)
+    builder.build().await
 }
 
 pub async fn start_pipe_data_sources(options: Vec<ClientPipeOptions>) -> Vec<HubChannelName> {
@@ -1,8 +1,10 @@
 pub mod client_pipe;
 pub mod client_pipe_options;
 pub mod data_source;
+pub mod harness;
 pub mod hub;
 
 pub use client_pipe::PipeClient;
 pub use client_pipe_options::{ClientPipeOptions, ClientPipeOptionsBuilder};
 pub use data_source::DataSource;
+pub use harness::TestHub;